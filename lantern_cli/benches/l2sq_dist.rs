@@ -0,0 +1,64 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use lantern_cli::pq::distance::{
+    l2sq_dist, l2sq_dist_impl_name, l2sq_dist_portable, l2sq_dist_scalar,
+};
+use rand::Rng;
+
+fn random_vec(dim: usize) -> Vec<f32> {
+    let mut rng = rand::thread_rng();
+    (0..dim).map(|_| rng.gen_range(-1.0..1.0)).collect()
+}
+
+fn bench_l2sq_dist(c: &mut Criterion) {
+    let mut group = c.benchmark_group(format!("l2sq_dist ({})", l2sq_dist_impl_name()));
+    for dim in [8, 128, 768, 1536] {
+        let a = random_vec(dim);
+        let b = random_vec(dim);
+        group.bench_with_input(BenchmarkId::new("scalar", dim), &dim, |bencher, _| {
+            bencher.iter(|| l2sq_dist_scalar(black_box(&a), black_box(&b)))
+        });
+        group.bench_with_input(BenchmarkId::new("portable", dim), &dim, |bencher, _| {
+            bencher.iter(|| l2sq_dist_portable(black_box(&a), black_box(&b)))
+        });
+        group.bench_with_input(BenchmarkId::new("dispatched", dim), &dim, |bencher, _| {
+            bencher.iter(|| l2sq_dist(black_box(&a), black_box(&b)))
+        });
+    }
+    group.finish();
+}
+
+// Closest centroid search over 256 centroids, as done for each subvector during compression
+fn bench_closest_centroid(c: &mut Criterion) {
+    let mut group = c.benchmark_group("closest_centroid");
+    for subvector_dim in [8, 48] {
+        let centroids: Vec<Vec<f32>> = (0..256).map(|_| random_vec(subvector_dim)).collect();
+        let vec = random_vec(subvector_dim);
+        let distance_fns: [(&str, fn(&[f32], &[f32]) -> f32); 2] =
+            [("scalar", l2sq_dist_scalar), ("dispatched", l2sq_dist)];
+        for (name, distance_fn) in distance_fns {
+            group.bench_with_input(
+                BenchmarkId::new(name, subvector_dim),
+                &subvector_dim,
+                |bencher, _| {
+                    bencher.iter(|| {
+                        centroids
+                            .iter()
+                            .map(|centroid| distance_fn(centroid, black_box(&vec)))
+                            .enumerate()
+                            .fold((0, f32::MAX), |closest, (idx, distance)| {
+                                if distance < closest.1 {
+                                    (idx, distance)
+                                } else {
+                                    closest
+                                }
+                            })
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_l2sq_dist, bench_closest_centroid);
+criterion_main!(benches);
@@ -0,0 +1,14 @@
+use std::process;
+
+use clap::Parser;
+use lantern_cli::logger::{LogLevel, Logger};
+use lantern_cli::mock_provider::{self, cli::MockProviderArgs};
+
+fn main() {
+    let args = MockProviderArgs::parse();
+    let logger = Logger::new("Lantern Mock Provider", LogLevel::Debug);
+    if let Err(e) = mock_provider::start(args, Some(logger.clone())) {
+        logger.error(&e.to_string());
+        process::exit(1);
+    }
+}
@@ -1,9 +1,21 @@
+use super::config::cli::ConfigArgs;
 use super::daemon::cli::DaemonArgs;
-use super::embeddings::cli::{EmbeddingArgs, MeasureModelSpeedArgs, ShowModelsArgs};
+use super::embeddings::cli::{
+    BenchmarkArgs, EmbedTextArgs, EmbeddingArgs, FanOutEmbeddingArgs, MeasureModelSpeedArgs,
+    ModelsArgs, RerankArgs, SearchArgs, ShowModelsArgs, ShowRuntimesArgs, SyncEmbeddingArgs,
+};
 use super::external_index::cli::CreateIndexArgs;
 use super::http_server::cli::HttpServerArgs;
 use super::index_autotune::cli::IndexAutotuneArgs;
-use super::pq::cli::PQArgs;
+use super::jobs::cli::JobsArgs;
+use super::metrics::cli::MetricsArgs;
+use super::pq::cli::{PQArgs, PQCommandArgs};
+use super::support_bundle::cli::SupportBundleArgs;
+use super::telemetry;
+use super::vector_jobs::cli::{
+    AnomalyArgs, ClassifyArgs, ConvertColumnArgs, SemanticJoinArgs, StratifiedSampleArgs,
+    VectorOpsArgs,
+};
 use clap::{Parser, Subcommand};
 
 #[derive(Subcommand, Debug)]
@@ -12,20 +24,57 @@ pub enum Commands {
     CreateIndex(CreateIndexArgs),
     /// Create embeddings
     CreateEmbeddings(EmbeddingArgs),
-    /// Show embedding models
-    ShowRuntimes,
+    /// Create embeddings in multiple databases with the same schema
+    CreateEmbeddingsFanOut(FanOutEmbeddingArgs),
+    /// Continuously generate embeddings for inserted and updated rows
+    SyncEmbeddings(SyncEmbeddingArgs),
+    /// Show embedding runtimes
+    ShowRuntimes(ShowRuntimesArgs),
     /// Show embedding models
     ShowModels(ShowModelsArgs),
+    /// Print embedding of the given text
+    EmbedText(EmbedTextArgs),
+    /// Search table rows nearest to the embedding of a query text
+    Search(SearchArgs),
+    /// Score candidate texts against a query with a reranker model
+    Rerank(RerankArgs),
+    /// Manage downloaded models of the local runtime
+    Models(ModelsArgs),
     /// Measure embedding geneartion speed
     MeasureModelSpeed(MeasureModelSpeedArgs),
+    /// Compare throughput, dimensions and cost of models on a sample of table rows
+    Benchmark(BenchmarkArgs),
     /// Autotune index
     AutotuneIndex(IndexAutotuneArgs),
     /// Quantize table
     PQTable(PQArgs),
+    /// Product quantization tools
+    Pq(PQCommandArgs),
     /// Start in daemon mode
     StartDaemon(DaemonArgs),
     /// Start in http mode
+    #[command(alias = "http-server")]
     StartServer(HttpServerArgs),
+    /// Find nearest neighbours of table rows in another table
+    SemanticJoin(SemanticJoinArgs),
+    /// Assign labels to table rows by nearest prototype vector
+    Classify(ClassifyArgs),
+    /// Compute anomaly scores for table rows
+    ScoreAnomalies(AnomalyArgs),
+    /// Export stratified sample of table rows for labeling
+    SampleRows(StratifiedSampleArgs),
+    /// Average, add and subtract embeddings and store named query vectors
+    VectorOps(VectorOpsArgs),
+    /// Convert embedding column between REAL[], vector, halfvec and bytea formats
+    ConvertColumn(ConvertColumnArgs),
+    /// Manage embedding jobs stored in database
+    Jobs(JobsArgs),
+    /// Collect sanitized diagnostics into a tarball for bug reports
+    SupportBundle(SupportBundleArgs),
+    /// List metrics and export Grafana dashboard
+    Metrics(MetricsArgs),
+    /// Validate config file
+    Config(ConfigArgs),
 }
 
 #[derive(Parser, Debug)]
@@ -33,4 +82,12 @@ pub enum Commands {
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
+
+    /// OpenTelemetry collector endpoint to export pipeline traces to over OTLP gRPC (e.g. http://localhost:4317)
+    #[arg(long, global = true)]
+    pub otlp_endpoint: Option<String>,
+
+    /// Service name reported in exported traces
+    #[arg(long, global = true, default_value = telemetry::DEFAULT_SERVICE_NAME)]
+    pub otlp_service_name: String,
 }
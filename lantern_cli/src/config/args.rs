@@ -0,0 +1,273 @@
+use super::{resolve_secret, resolve_secrets};
+use clap::{Arg, Command};
+use std::collections::HashSet;
+use toml::{Table, Value};
+
+// Options which are not passed on the command line are read from LANTERN_<OPTION> environment
+// variables, e.g. LANTERN_URI or LANTERN_BATCH_SIZE, and then from the [args] section of the config
+pub static ENV_PREFIX: &str = "LANTERN_";
+// Config file used when --config is not passed
+pub static CONFIG_ENV: &str = "LANTERN_CONFIG";
+static CONFIG_FLAG: &str = "--config";
+
+fn get_env_name(long: &str) -> String {
+    format!("{ENV_PREFIX}{}", long.to_uppercase().replace('-', "_"))
+}
+
+fn find_long<'a>(path: &[&'a Command], long: &str) -> Option<&'a Arg> {
+    path.iter()
+        .flat_map(|command| command.get_arguments())
+        .find(|arg| arg.get_long() == Some(long))
+}
+
+fn find_short<'a>(path: &[&'a Command], short: char) -> Option<&'a Arg> {
+    path.iter()
+        .flat_map(|command| command.get_arguments())
+        .find(|arg| arg.get_short() == Some(short))
+}
+
+fn takes_value(arg: Option<&Arg>) -> bool {
+    arg.map(|arg| arg.get_action().takes_values())
+        .unwrap_or(false)
+}
+
+// Returns the command and its subcommands given in argv, e.g. [lantern-cli, jobs, run]
+fn get_subcommand_path<'a>(command: &'a Command, args: &[String]) -> Vec<&'a Command> {
+    let mut path = vec![command];
+    let mut idx = 1;
+    while idx < args.len() {
+        let arg = &args[idx];
+        if arg == "--" {
+            break;
+        }
+        if let Some(long) = arg.strip_prefix("--") {
+            if !long.contains('=') && (arg == CONFIG_FLAG || takes_value(find_long(&path, long))) {
+                idx += 1;
+            }
+        } else if let Some(short) = arg.strip_prefix('-') {
+            let mut chars = short.chars();
+            if let (Some(short), None) = (chars.next(), chars.next()) {
+                if takes_value(find_short(&path, short)) {
+                    idx += 1;
+                }
+            }
+        } else {
+            let current: &'a Command = path[path.len() - 1];
+            match current.find_subcommand(arg) {
+                Some(subcommand) => path.push(subcommand),
+                // Positional argument of the command
+                None => break,
+            }
+        }
+        idx += 1;
+    }
+    path
+}
+
+// Removes --config from argv and returns its value
+fn take_config_flag(args: &mut Vec<String>) -> Result<Option<String>, anyhow::Error> {
+    let mut config = None;
+    let mut idx = 1;
+    while idx < args.len() && args[idx] != "--" {
+        if let Some(path) = args[idx].strip_prefix("--config=") {
+            config = Some(path.to_owned());
+            args.remove(idx);
+        } else if args[idx] == CONFIG_FLAG {
+            if idx + 1 >= args.len() {
+                anyhow::bail!("{CONFIG_FLAG} requires a path");
+            }
+            config = Some(args.remove(idx + 1));
+            args.remove(idx);
+        } else {
+            idx += 1;
+        }
+    }
+    Ok(config)
+}
+
+// Long names of the options which are given on the command line
+fn get_present_options(path: &[&Command], args: &[String]) -> HashSet<String> {
+    let mut present = HashSet::new();
+    for arg in args.iter().skip(1).take_while(|arg| *arg != "--") {
+        if let Some(long) = arg.strip_prefix("--") {
+            present.insert(long.split('=').next().unwrap().to_owned());
+        } else if let Some(short) = arg.strip_prefix('-').and_then(|s| s.chars().next()) {
+            if let Some(long) = find_short(path, short).and_then(|arg| arg.get_long()) {
+                present.insert(long.to_owned());
+            }
+        }
+    }
+    present
+}
+
+// Returns [args] and the sections of the subcommands, e.g. [args.jobs.run], most specific first
+fn get_scopes<'a>(config: &'a Table, path: &[&Command]) -> Result<Vec<&'a Table>, anyhow::Error> {
+    let mut scopes = Vec::new();
+    let mut scope = match config.get("args") {
+        Some(Value::Table(args)) => args,
+        Some(value) => anyhow::bail!("args: expected table, got {}", value.type_str()),
+        None => return Ok(scopes),
+    };
+    scopes.push(scope);
+    for command in path.iter().skip(1) {
+        scope = match scope.get(command.get_name()) {
+            Some(Value::Table(table)) => table,
+            _ => break,
+        };
+        scopes.push(scope);
+    }
+    scopes.reverse();
+    Ok(scopes)
+}
+
+fn get_config_value<'a>(scopes: &[&'a Table], long: &str) -> Option<&'a Value> {
+    let key = long.replace('-', "_");
+    scopes
+        .iter()
+        .find_map(|scope| scope.get(long).or(scope.get(&key)))
+}
+
+// Tables are passed as JSON, e.g. runtime_params = { api_token = "env:OPENAI_API_KEY" }
+fn to_arg_values(value: &Value, long: &str) -> Result<Vec<String>, anyhow::Error> {
+    Ok(match value {
+        Value::String(value) => {
+            vec![resolve_secret(value).map_err(|e| anyhow::anyhow!("args.{long}: {e}"))?]
+        }
+        Value::Integer(value) => vec![value.to_string()],
+        Value::Float(value) => vec![value.to_string()],
+        Value::Boolean(value) => vec![value.to_string()],
+        Value::Datetime(value) => vec![value.to_string()],
+        Value::Array(values) => {
+            let mut arg_values = Vec::with_capacity(values.len());
+            for value in values {
+                if matches!(value, Value::Array(_)) {
+                    anyhow::bail!("args.{long}: nested arrays are not supported");
+                }
+                arg_values.extend(to_arg_values(value, long)?);
+            }
+            arg_values
+        }
+        Value::Table(_) => {
+            let value = resolve_secrets(&serde_json::to_value(value)?)
+                .map_err(|e| anyhow::anyhow!("args.{long}: {e}"))?;
+            vec![value.to_string()]
+        }
+    })
+}
+
+fn push_env_option(
+    options: &mut Vec<String>,
+    arg: &Arg,
+    long: &str,
+    value: String,
+) -> Result<(), anyhow::Error> {
+    if arg.get_action().takes_values() {
+        options.push(format!("--{long}={value}"));
+        return Ok(());
+    }
+    match value.to_lowercase().as_str() {
+        "true" | "1" => options.push(format!("--{long}")),
+        "false" | "0" | "" => {}
+        _ => anyhow::bail!(
+            "{} should be true or false, got '{value}'",
+            get_env_name(long)
+        ),
+    }
+    Ok(())
+}
+
+fn push_config_option(
+    options: &mut Vec<String>,
+    arg: &Arg,
+    long: &str,
+    value: &Value,
+) -> Result<(), anyhow::Error> {
+    if arg.get_action().takes_values() {
+        for value in to_arg_values(value, long)? {
+            options.push(format!("--{long}={value}"));
+        }
+        return Ok(());
+    }
+    match value {
+        Value::Boolean(true) => options.push(format!("--{long}")),
+        Value::Boolean(false) => {}
+        value => anyhow::bail!("args.{long}: expected boolean, got {}", value.type_str()),
+    }
+    Ok(())
+}
+
+// Adds the options which are missing in argv from LANTERN_* environment variables and the config file
+// passed with --config or LANTERN_CONFIG. Options given on the command line take precedence,
+// then environment variables, then the section of the subcommand and then the [args] section
+pub fn apply_config_args(
+    command: &Command,
+    args: Vec<String>,
+) -> Result<Vec<String>, anyhow::Error> {
+    let path = get_subcommand_path(command, &args);
+    // Config subcommands take the path of the config in their own --config option
+    if path.len() < 2 || path[1].get_name() == "config" {
+        return Ok(args);
+    }
+
+    let mut args = args;
+    let config_path = match take_config_flag(&mut args)? {
+        Some(path) => Some(path),
+        None => std::env::var(CONFIG_ENV)
+            .ok()
+            .filter(|path| !path.is_empty()),
+    };
+    let config: Table = match &config_path {
+        Some(config_path) => {
+            let config = match std::fs::read_to_string(config_path) {
+                Ok(config) => config,
+                Err(e) => anyhow::bail!("Can not read config {config_path}: {e}"),
+            };
+            match toml::from_str(&config) {
+                Ok(config) => config,
+                Err(e) => anyhow::bail!("Invalid config {config_path}: {}", e.message()),
+            }
+        }
+        None => Table::new(),
+    };
+    let scopes = get_scopes(&config, &path).map_err(|e| {
+        anyhow::anyhow!(
+            "Invalid config {}: {e}",
+            config_path.as_deref().unwrap_or_default()
+        )
+    })?;
+
+    let present = get_present_options(&path, &args);
+    let leaf = path.last().unwrap();
+    let arguments = leaf
+        .get_arguments()
+        .chain(command.get_arguments().filter(|arg| arg.is_global_set()));
+    let mut options = Vec::new();
+    for arg in arguments {
+        let long = match arg.get_long() {
+            Some(long) if long != "help" && long != "version" => long,
+            _ => continue,
+        };
+        if present.contains(long) {
+            continue;
+        }
+
+        if let Ok(value) = std::env::var(get_env_name(long)) {
+            push_env_option(&mut options, arg, long, value)?;
+        } else if let Some(value) = get_config_value(&scopes, long) {
+            push_config_option(&mut options, arg, long, value).map_err(|e| {
+                anyhow::anyhow!(
+                    "Invalid config {}: {e}",
+                    config_path.as_deref().unwrap_or_default()
+                )
+            })?;
+        }
+    }
+
+    // Options are inserted before "--", so they are not taken as positional arguments
+    let end = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    args.splice(end..end, options);
+    Ok(args)
+}
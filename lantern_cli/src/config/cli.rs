@@ -0,0 +1,26 @@
+use clap::{Parser, Subcommand};
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Check config file and report all errors
+    Validate(ValidateConfigArgs),
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct ConfigArgs {
+    #[command(subcommand)]
+    pub command: ConfigCommands,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct ValidateConfigArgs {
+    /// Path of the config file
+    #[arg(short, long, default_value = "lantern.toml")]
+    pub config: String,
+
+    /// Also connect to job databases and initialize runtimes
+    #[arg(long, default_value_t = false)]
+    pub deep: bool,
+}
@@ -0,0 +1,92 @@
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use std::fmt;
+
+pub mod args;
+pub mod cli;
+pub mod schedule;
+mod validate;
+
+// Values starting with these prefixes are read from environment variable or file
+// so secrets do not need to be stored in the config
+pub static SECRET_PREFIXES: [&str; 2] = ["env:", "file:"];
+
+#[derive(Debug, Clone)]
+pub struct ConfigError {
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.path.is_empty() {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "{}: {}", self.path, self.message)
+        }
+    }
+}
+
+// Resolve env:VAR and file:/path references, other values are returned as is
+pub fn resolve_secret(value: &str) -> Result<String, anyhow::Error> {
+    if let Some(var) = value.strip_prefix("env:") {
+        return match std::env::var(var) {
+            Ok(value) if !value.is_empty() => Ok(value),
+            _ => anyhow::bail!("environment variable {var} is not set"),
+        };
+    }
+
+    if let Some(path) = value.strip_prefix("file:") {
+        return match std::fs::read_to_string(path) {
+            Ok(value) => Ok(value.trim_end().to_owned()),
+            Err(e) => anyhow::bail!("can not read {path}: {e}"),
+        };
+    }
+
+    Ok(value.to_owned())
+}
+
+// Resolve references in all string values of the JSON
+pub fn resolve_secrets(value: &serde_json::Value) -> Result<serde_json::Value, anyhow::Error> {
+    Ok(match value {
+        serde_json::Value::String(value) => serde_json::Value::String(resolve_secret(value)?),
+        serde_json::Value::Object(map) => {
+            let mut resolved = serde_json::Map::new();
+            for (key, value) in map {
+                resolved.insert(key.clone(), resolve_secrets(value)?);
+            }
+            serde_json::Value::Object(resolved)
+        }
+        serde_json::Value::Array(values) => serde_json::Value::Array(
+            values
+                .iter()
+                .map(resolve_secrets)
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        value => value.clone(),
+    })
+}
+
+// Returns all errors of the config, the config is valid if the list is empty
+pub fn get_config_errors(config: &str, deep: bool) -> Vec<ConfigError> {
+    validate::Validator::new().validate(config, deep)
+}
+
+pub fn validate_config(args: &cli::ValidateConfigArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Config", LogLevel::Debug));
+    let config = match std::fs::read_to_string(&args.config) {
+        Ok(config) => config,
+        Err(e) => anyhow::bail!("Can not read config {}: {e}", args.config),
+    };
+
+    let errors = get_config_errors(&config, args.deep);
+    if errors.is_empty() {
+        logger.info(&format!("Config {} is valid", args.config));
+        return Ok(());
+    }
+
+    for error in &errors {
+        logger.error(&error.to_string());
+    }
+    anyhow::bail!("Config {} has {} errors", args.config, errors.len());
+}
@@ -0,0 +1,138 @@
+static MACROS: [&str; 7] = [
+    "@yearly",
+    "@annually",
+    "@monthly",
+    "@weekly",
+    "@daily",
+    "@midnight",
+    "@hourly",
+];
+static MONTHS: [&str; 12] = [
+    "jan", "feb", "mar", "apr", "may", "jun", "jul", "aug", "sep", "oct", "nov", "dec",
+];
+static DAYS: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+
+struct Field {
+    name: &'static str,
+    min: u32,
+    max: u32,
+    names: &'static [&'static str],
+}
+
+static FIELDS: [Field; 5] = [
+    Field {
+        name: "minute",
+        min: 0,
+        max: 59,
+        names: &[],
+    },
+    Field {
+        name: "hour",
+        min: 0,
+        max: 23,
+        names: &[],
+    },
+    Field {
+        name: "day of month",
+        min: 1,
+        max: 31,
+        names: &[],
+    },
+    Field {
+        name: "month",
+        min: 1,
+        max: 12,
+        names: &MONTHS,
+    },
+    Field {
+        name: "day of week",
+        min: 0,
+        max: 7,
+        names: &DAYS,
+    },
+];
+
+fn parse_value(field: &Field, value: &str) -> Result<u32, String> {
+    if let Some(idx) = field
+        .names
+        .iter()
+        .position(|name| name.eq_ignore_ascii_case(value))
+    {
+        return Ok(idx as u32 + field.min);
+    }
+
+    let parsed: u32 = value
+        .parse()
+        .map_err(|_| format!("invalid {} value '{value}'", field.name))?;
+
+    if parsed < field.min || parsed > field.max {
+        return Err(format!(
+            "{} value {parsed} is out of range {}-{}",
+            field.name, field.min, field.max
+        ));
+    }
+
+    Ok(parsed)
+}
+
+// Validate one comma separated item: *, */n, a, a-b or a-b/n
+fn validate_item(field: &Field, item: &str) -> Result<(), String> {
+    let (range, step) = match item.split_once('/') {
+        Some((range, step)) => (range, Some(step)),
+        None => (item, None),
+    };
+
+    if let Some(step) = step {
+        match step.parse::<u32>() {
+            Ok(step) if step > 0 => {}
+            _ => return Err(format!("invalid {} step '{step}'", field.name)),
+        }
+    }
+
+    if range == "*" {
+        return Ok(());
+    }
+
+    match range.split_once('-') {
+        Some((start, end)) => {
+            let start = parse_value(field, start)?;
+            let end = parse_value(field, end)?;
+            if start > end {
+                return Err(format!("invalid {} range '{range}'", field.name));
+            }
+        }
+        None => {
+            parse_value(field, range)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Check 5 field cron expression (minute hour day-of-month month day-of-week) or macro like @daily
+pub fn validate_schedule(schedule: &str) -> Result<(), String> {
+    let schedule = schedule.trim();
+    if schedule.starts_with('@') {
+        if MACROS.contains(&schedule) {
+            return Ok(());
+        }
+        return Err(format!("unknown schedule macro '{schedule}'"));
+    }
+
+    let parts: Vec<&str> = schedule.split_whitespace().collect();
+    if parts.len() != FIELDS.len() {
+        return Err(format!(
+            "expected {} fields (minute hour day-of-month month day-of-week), got {}",
+            FIELDS.len(),
+            parts.len()
+        ));
+    }
+
+    for (field, part) in FIELDS.iter().zip(parts) {
+        for item in part.split(',') {
+            validate_item(field, item)?;
+        }
+    }
+
+    Ok(())
+}
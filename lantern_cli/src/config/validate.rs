@@ -0,0 +1,377 @@
+use super::schedule::validate_schedule;
+use super::{resolve_secret, resolve_secrets, ConfigError, SECRET_PREFIXES};
+use crate::embeddings::core::{get_runtime, Runtime};
+use crate::utils::{append_params_to_uri, redact_secrets};
+use postgres::{Client, NoTls};
+use std::collections::HashMap;
+use std::str::FromStr;
+use toml::{Table, Value};
+
+static CONNECTION_PARAMS: &str = "connect_timeout=10";
+static TOP_LEVEL_KEYS: [&str; 3] = ["runtimes", "jobs", "args"];
+static RUNTIME_KEYS: [&str; 2] = ["runtime", "params"];
+static JOB_KEYS: [&str; 11] = [
+    "name",
+    "uri",
+    "schema",
+    "table",
+    "column",
+    "out_column",
+    "pk",
+    "model",
+    "runtime",
+    "batch_size",
+    "schedule",
+];
+
+struct RuntimeConfig {
+    runtime: Runtime,
+    params: serde_json::Value,
+}
+
+struct JobConfig {
+    path: String,
+    uri: String,
+    schema: String,
+    table: String,
+    column: String,
+}
+
+// Collects all errors of the config instead of stopping on the first one
+pub struct Validator {
+    errors: Vec<ConfigError>,
+}
+
+impl Validator {
+    pub fn new() -> Validator {
+        Validator { errors: Vec::new() }
+    }
+
+    fn error(&mut self, path: &str, message: &str) {
+        self.errors.push(ConfigError {
+            path: path.to_owned(),
+            message: message.to_owned(),
+        });
+    }
+
+    fn check_keys(&mut self, table: &Table, allowed: &[&str], path: &str) {
+        for key in table.keys() {
+            if !allowed.contains(&key.as_str()) {
+                self.error(&join_path(path, key), "unknown field");
+            }
+        }
+    }
+
+    fn get_str<'a>(
+        &mut self,
+        table: &'a Table,
+        key: &str,
+        path: &str,
+        required: bool,
+    ) -> Option<&'a str> {
+        match table.get(key) {
+            Some(Value::String(value)) if value.trim().is_empty() => {
+                self.error(&join_path(path, key), "should not be empty");
+                None
+            }
+            Some(Value::String(value)) => Some(value),
+            Some(value) => {
+                self.error(
+                    &join_path(path, key),
+                    &format!("expected string, got {}", value.type_str()),
+                );
+                None
+            }
+            None if required => {
+                self.error(&join_path(path, key), "missing required field");
+                None
+            }
+            None => None,
+        }
+    }
+
+    // Check that env: and file: references in the value can be resolved
+    fn check_secrets(&mut self, value: &Value, path: &str) {
+        match value {
+            Value::String(value) if SECRET_PREFIXES.iter().any(|p| value.starts_with(p)) => {
+                if let Err(e) = resolve_secret(value) {
+                    self.error(path, &e.to_string());
+                }
+            }
+            Value::Table(table) => {
+                for (key, value) in table {
+                    self.check_secrets(value, &join_path(path, key));
+                }
+            }
+            Value::Array(values) => {
+                for (idx, value) in values.iter().enumerate() {
+                    self.check_secrets(value, &format!("{path}[{idx}]"));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn validate_runtime(&mut self, value: &Value, path: &str) -> Option<RuntimeConfig> {
+        let table = match value {
+            Value::Table(table) => table,
+            value => {
+                self.error(path, &format!("expected table, got {}", value.type_str()));
+                return None;
+            }
+        };
+        self.check_keys(table, &RUNTIME_KEYS, path);
+
+        let runtime = self
+            .get_str(table, "runtime", path, true)
+            .and_then(|runtime| match Runtime::from_str(runtime) {
+                Ok(runtime) => Some(runtime),
+                Err(e) => {
+                    self.error(&join_path(path, "runtime"), &e.to_string());
+                    None
+                }
+            });
+
+        let params = match table.get("params") {
+            Some(params @ Value::Table(_)) => {
+                self.check_secrets(params, &join_path(path, "params"));
+                serde_json::to_value(params).ok()
+            }
+            Some(value) => {
+                self.error(
+                    &join_path(path, "params"),
+                    &format!("expected table, got {}", value.type_str()),
+                );
+                None
+            }
+            None => Some(serde_json::json!({})),
+        };
+
+        Some(RuntimeConfig {
+            runtime: runtime?,
+            params: params?,
+        })
+    }
+
+    fn validate_job(
+        &mut self,
+        value: &Value,
+        path: &str,
+        runtimes: &HashMap<String, Option<RuntimeConfig>>,
+        job_names: &mut Vec<String>,
+    ) -> Option<JobConfig> {
+        let table = match value {
+            Value::Table(table) => table,
+            value => {
+                self.error(path, &format!("expected table, got {}", value.type_str()));
+                return None;
+            }
+        };
+        self.check_keys(table, &JOB_KEYS, path);
+
+        if let Some(name) = self.get_str(table, "name", path, true) {
+            if job_names.iter().any(|n| n == name) {
+                self.error(
+                    &join_path(path, "name"),
+                    &format!("duplicate job name '{name}'"),
+                );
+            }
+            job_names.push(name.to_owned());
+        }
+
+        let uri = self.get_str(table, "uri", path, true);
+        if let Some(uri) = table.get("uri") {
+            self.check_secrets(uri, &join_path(path, "uri"));
+        }
+        let schema = self.get_str(table, "schema", path, false);
+        let db_table = self.get_str(table, "table", path, true);
+        let column = self.get_str(table, "column", path, true);
+        self.get_str(table, "out_column", path, true);
+        self.get_str(table, "pk", path, false);
+        self.get_str(table, "model", path, true);
+
+        if let Some(runtime) = self.get_str(table, "runtime", path, false) {
+            if !runtimes.contains_key(runtime) && Runtime::from_str(runtime).is_err() {
+                self.error(
+                    &join_path(path, "runtime"),
+                    &format!("'{runtime}' is neither defined in [runtimes] nor a runtime name"),
+                );
+            }
+        }
+
+        match table.get("batch_size") {
+            Some(Value::Integer(batch_size)) if *batch_size > 0 => {}
+            Some(Value::Integer(_)) => {
+                self.error(&join_path(path, "batch_size"), "should be greater than 0")
+            }
+            Some(value) => self.error(
+                &join_path(path, "batch_size"),
+                &format!("expected integer, got {}", value.type_str()),
+            ),
+            None => {}
+        }
+
+        if let Some(schedule) = self.get_str(table, "schedule", path, false) {
+            if let Err(e) = validate_schedule(schedule) {
+                self.error(&join_path(path, "schedule"), &e);
+            }
+        }
+
+        Some(JobConfig {
+            path: path.to_owned(),
+            uri: uri?.to_owned(),
+            schema: schema.unwrap_or("public").to_owned(),
+            table: db_table?.to_owned(),
+            column: column?.to_owned(),
+        })
+    }
+
+    fn check_job_database(&mut self, job: &JobConfig) {
+        let uri = match resolve_secret(&job.uri) {
+            Ok(uri) => uri,
+            // Error is already reported for unresolved reference
+            Err(_) => return,
+        };
+
+        let mut client =
+            match Client::connect(&append_params_to_uri(&uri, CONNECTION_PARAMS), NoTls) {
+                Ok(client) => client,
+                Err(e) => {
+                    self.error(
+                        &join_path(&job.path, "uri"),
+                        &format!("connection failed: {}", redact_secrets(&e.to_string())),
+                    );
+                    return;
+                }
+            };
+
+        let columns = client.query(
+            "SELECT column_name::TEXT FROM information_schema.columns WHERE table_schema=$1 AND table_name=$2",
+            &[&job.schema, &job.table],
+        );
+
+        match columns {
+            Ok(columns) if columns.is_empty() => self.error(
+                &join_path(&job.path, "table"),
+                &format!("table {}.{} does not exist", job.schema, job.table),
+            ),
+            Ok(columns) => {
+                if !columns
+                    .iter()
+                    .any(|row| row.get::<usize, String>(0) == job.column)
+                {
+                    self.error(
+                        &join_path(&job.path, "column"),
+                        &format!("column {} does not exist in {}", job.column, job.table),
+                    );
+                }
+            }
+            Err(e) => self.error(&join_path(&job.path, "table"), &e.to_string()),
+        }
+    }
+
+    fn check_runtime(&mut self, runtime: &RuntimeConfig, path: &str) {
+        let params = match resolve_secrets(&runtime.params) {
+            Ok(params) => params.to_string(),
+            // Error is already reported for unresolved reference
+            Err(_) => return,
+        };
+
+        let result = get_runtime(&runtime.runtime, None, &params).map(|_| ());
+        if let Err(e) = result {
+            self.error(
+                path,
+                &format!(
+                    "runtime initialization failed: {}",
+                    redact_secrets(&e.to_string())
+                ),
+            );
+        }
+    }
+
+    pub fn validate(mut self, config: &str, deep: bool) -> Vec<ConfigError> {
+        let config: Table = match toml::from_str(config) {
+            Ok(config) => config,
+            Err(e) => {
+                let message = e.message().replace('\n', ", ");
+                if message.is_empty() {
+                    self.error("", "invalid TOML");
+                } else {
+                    self.error("", &message);
+                }
+                if let Some(span) = e.span() {
+                    let line = config[..span.start].matches('\n').count() + 1;
+                    self.errors[0].path = format!("line {line}");
+                }
+                return self.errors;
+            }
+        };
+
+        self.check_keys(&config, &TOP_LEVEL_KEYS, "");
+
+        let mut runtimes = HashMap::new();
+        match config.get("runtimes") {
+            Some(Value::Table(table)) => {
+                for (name, value) in table {
+                    let runtime = self.validate_runtime(value, &format!("runtimes.{name}"));
+                    runtimes.insert(name.clone(), runtime);
+                }
+            }
+            Some(value) => self.error(
+                "runtimes",
+                &format!("expected table, got {}", value.type_str()),
+            ),
+            None => {}
+        }
+
+        // Option names depend on the command, so only the values are checked
+        match config.get("args") {
+            Some(args @ Value::Table(_)) => self.check_secrets(args, "args"),
+            Some(value) => self.error("args", &format!("expected table, got {}", value.type_str())),
+            None => {}
+        }
+
+        let mut jobs = Vec::new();
+        let mut job_names = Vec::new();
+        match config.get("jobs") {
+            Some(Value::Array(values)) => {
+                for (idx, value) in values.iter().enumerate() {
+                    if let Some(job) =
+                        self.validate_job(value, &format!("jobs[{idx}]"), &runtimes, &mut job_names)
+                    {
+                        jobs.push(job);
+                    }
+                }
+            }
+            Some(value) => self.error(
+                "jobs",
+                &format!("expected array of tables, got {}", value.type_str()),
+            ),
+            None if !config.contains_key("args") => {
+                self.error("jobs", "at least one job should be defined")
+            }
+            None => {}
+        }
+
+        if deep {
+            for job in &jobs {
+                self.check_job_database(job);
+            }
+            for (name, runtime) in &runtimes {
+                if let Some(runtime) = runtime {
+                    self.check_runtime(runtime, &format!("runtimes.{name}"));
+                }
+            }
+        }
+
+        self.errors
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{path}.{key}")
+    }
+}
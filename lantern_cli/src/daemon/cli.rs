@@ -62,4 +62,8 @@ pub struct DaemonArgs {
     /// Log level
     #[arg(long, value_enum, default_value_t = LogLevel::Info)] // arg_enum here
     pub log_level: LogLevel,
+
+    /// Serve Prometheus metrics on this port at /metrics
+    #[arg(long)]
+    pub metrics_port: Option<u16>,
 }
@@ -34,6 +34,7 @@ use super::types::{
 };
 use crate::embeddings::cli::EmbeddingArgs;
 use crate::logger::Logger;
+use crate::metrics::{self, Subsystem};
 use crate::utils::{get_full_table_name, quote_ident};
 use crate::{embeddings, types::*};
 use futures::future;
@@ -43,7 +44,7 @@ use std::path::Path;
 use std::process;
 use std::sync::Arc;
 use std::time::Duration;
-use std::time::SystemTime;
+use std::time::{Instant, SystemTime};
 use tokio::fs;
 use tokio::sync::mpsc::{Receiver, Sender, UnboundedReceiver, UnboundedSender};
 use tokio::sync::{mpsc, Mutex, RwLock};
@@ -51,6 +52,7 @@ use tokio_postgres::types::ToSql;
 use tokio_postgres::{Client, NoTls};
 
 const EMB_LOCK_TABLE_NAME: &'static str = "_lantern_emb_job_locks";
+const METRICS_JOB_TYPE: &'static str = "embedding";
 
 lazy_static! {
     static ref JOBS: JobCancellationHandlersMap = RwLock::new(HashMap::new());
@@ -351,6 +353,8 @@ async fn embedding_worker(
             );
             let job_clone = job.clone();
 
+            metrics::add_gauge(Subsystem::Daemon, "jobs_running", &[METRICS_JOB_TYPE], 1.0);
+            let job_start = Instant::now();
             let result = crate::embeddings::create_embeddings_from_db(
                 EmbeddingArgs {
                     model: job_clone.model.clone(),
@@ -364,12 +368,10 @@ async fn embedding_worker(
                     batch_size: job_clone.batch_size,
                     runtime: job_clone.runtime.clone(),
                     runtime_params: job_clone.runtime_params.clone(),
-                    visual: false,
                     stream: true,
                     create_column: false,
-                    out_csv: None,
                     filter: job_clone.filter.clone(),
-                    limit: None,
+                    ..Default::default()
                 },
                 false,
                 None,
@@ -377,8 +379,27 @@ async fn embedding_worker(
                 Some(task_logger),
             );
 
+            metrics::add_gauge(Subsystem::Daemon, "jobs_running", &[METRICS_JOB_TYPE], -1.0);
+            metrics::observe(
+                Subsystem::Daemon,
+                "job_duration_seconds",
+                &[METRICS_JOB_TYPE],
+                job_start.elapsed().as_secs_f64(),
+            );
+            metrics::inc_counter(
+                Subsystem::Daemon,
+                "jobs_finished_total",
+                &[
+                    METRICS_JOB_TYPE,
+                    if result.is_ok() { "success" } else { "failure" },
+                ],
+                1.0,
+            );
+
             match result {
-                Ok((processed_rows, processed_tokens)) => {
+                Ok(report) => {
+                    let (processed_rows, processed_tokens) =
+                        (report.processed_rows, report.processed_tokens);
                     if processed_tokens > 0 {
                         let fn_name = get_full_table_name(
                             &schema_ref,
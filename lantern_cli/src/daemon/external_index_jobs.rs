@@ -137,6 +137,8 @@ async fn external_index_worker(
                         out: index_path,
                         remote_database: true,
                         pq: false,
+                        threads: None,
+                        copy: false,
                     }, progress_callback, Some(is_canceled_clone), Some(task_logger));
                     futures::executor::block_on(cancel_tx_clone.send(String::new()))?;
                     result
@@ -12,6 +12,7 @@ use std::sync::{
 };
 
 use crate::logger::Logger;
+use crate::metrics;
 use crate::types::AnyhowVoidResult;
 
 pub fn start(
@@ -37,6 +38,10 @@ pub fn start(
 
     logger.info("Starting Daemon");
 
+    if let Some(port) = args.metrics_port {
+        metrics::start_metrics_server(port, logger.clone())?;
+    }
+
     if args.embedding_table.is_some() {
         let embedding_error_sender = error_sender.clone();
         std::thread::spawn(move || {
@@ -0,0 +1,71 @@
+// Cores are added to cpu_set_t, which holds CPU_SETSIZE cores
+#[cfg(target_os = "linux")]
+const MAX_CORES: usize = libc::CPU_SETSIZE as usize;
+#[cfg(not(target_os = "linux"))]
+const MAX_CORES: usize = 1024;
+
+fn check_core_id(core: usize) -> Result<usize, anyhow::Error> {
+    if core >= MAX_CORES {
+        anyhow::bail!("Core id {core} should be less than {MAX_CORES}");
+    }
+    Ok(core)
+}
+
+// Parse core list in the format used by taskset, e.g. "0-3,6"
+pub fn parse_core_list(cores: &str) -> Result<Vec<usize>, anyhow::Error> {
+    let mut result = Vec::new();
+
+    for part in cores.split(',').map(|p| p.trim()).filter(|p| !p.is_empty()) {
+        match part.split_once('-') {
+            Some((start, end)) => {
+                let start: usize = start.trim().parse()?;
+                let end = check_core_id(end.trim().parse()?)?;
+                if start > end {
+                    anyhow::bail!("Invalid core range {part}");
+                }
+                result.extend(start..=end);
+            }
+            None => result.push(check_core_id(part.parse()?)?),
+        }
+    }
+
+    if result.is_empty() {
+        anyhow::bail!("Core list should not be empty");
+    }
+
+    Ok(result)
+}
+
+// Pin the calling thread to the provided cores
+// Threads spawned afterwards from this thread (e.g. onnxruntime thread pool) will inherit the affinity
+#[cfg(target_os = "linux")]
+pub fn pin_current_thread(cores: &[usize]) -> Result<(), anyhow::Error> {
+    unsafe {
+        let mut set: libc::cpu_set_t = std::mem::zeroed();
+        for core in cores {
+            libc::CPU_SET(*core, &mut set);
+        }
+
+        if libc::sched_setaffinity(0, std::mem::size_of::<libc::cpu_set_t>(), &set) != 0 {
+            anyhow::bail!(
+                "Failed to set thread affinity: {}",
+                std::io::Error::last_os_error()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn pin_current_thread(_cores: &[usize]) -> Result<(), anyhow::Error> {
+    anyhow::bail!("Thread affinity is supported only on linux");
+}
+
+pub fn pin_current_thread_to(cores: &Option<String>) -> Result<(), anyhow::Error> {
+    if let Some(cores) = cores {
+        pin_current_thread(&parse_core_list(cores)?)?;
+    }
+
+    Ok(())
+}
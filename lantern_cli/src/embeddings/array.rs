@@ -0,0 +1,51 @@
+use super::EmbeddingRecord;
+use std::collections::HashMap;
+
+// Embeddings of array elements are averaged into one embedding per row
+// Elements of one row may be split between batches, so partial sums are kept
+// until embeddings for all elements of the row are received
+pub struct MeanAggregator {
+    pending: HashMap<String, (Vec<f32>, usize, usize)>,
+}
+
+impl MeanAggregator {
+    pub fn new() -> Self {
+        MeanAggregator {
+            pending: HashMap::new(),
+        }
+    }
+
+    pub fn expect(&mut self, id: &str, elements: usize) {
+        self.pending
+            .insert(id.to_owned(), (Vec::new(), 0, elements));
+    }
+
+    // Returns mean embeddings of the rows which are complete
+    pub fn add(&mut self, records: Vec<EmbeddingRecord>) -> Vec<EmbeddingRecord> {
+        let mut completed = Vec::new();
+
+        for (id, embedding) in records {
+            let (sum, received, expected) = match self.pending.get_mut(&id) {
+                Some(entry) => entry,
+                None => continue,
+            };
+
+            if sum.is_empty() {
+                *sum = embedding;
+            } else {
+                for (s, v) in sum.iter_mut().zip(embedding.iter()) {
+                    *s += v;
+                }
+            }
+            *received += 1;
+
+            if received == expected {
+                let (sum, received, _) = self.pending.remove(&id).unwrap();
+                let mean = sum.iter().map(|s| s / received as f32).collect();
+                completed.push((id, mean));
+            }
+        }
+
+        completed
+    }
+}
@@ -0,0 +1,67 @@
+// Token batcher collects inputs until the token budget or the batch size is reached
+// If token budget is not set, inputs will be collected until `take` is called
+pub struct TokenBatcher {
+    max_tokens: Option<usize>,
+    max_rows: usize,
+    ids: Vec<String>,
+    inputs: Vec<String>,
+    token_counts: Vec<usize>,
+    token_count: usize,
+}
+
+// Ids, inputs and token counts of the inputs, so the tokens are not counted again for rate limits
+pub type Batch = (Vec<String>, Vec<String>, Vec<usize>);
+
+impl TokenBatcher {
+    pub fn new(max_tokens: Option<usize>, max_rows: usize) -> Self {
+        TokenBatcher {
+            max_tokens,
+            max_rows,
+            ids: Vec::new(),
+            inputs: Vec::new(),
+            token_counts: Vec::new(),
+            token_count: 0,
+        }
+    }
+
+    pub fn is_token_aware(&self) -> bool {
+        self.max_tokens.is_some()
+    }
+
+    // Add input to the current batch
+    // If the input does not fit into token budget or the batch has max rows, the current batch
+    // will be returned and the input will be added to the new batch
+    // Short texts would otherwise fit tens of thousands of rows into one batch
+    pub fn push(&mut self, id: String, input: String, tokens: usize) -> Option<Batch> {
+        let mut full_batch = None;
+
+        if let Some(max_tokens) = self.max_tokens {
+            if !self.ids.is_empty()
+                && (self.token_count + tokens > max_tokens || self.ids.len() >= self.max_rows)
+            {
+                full_batch = self.take();
+            }
+        }
+
+        self.ids.push(id);
+        self.inputs.push(input);
+        self.token_counts.push(tokens);
+        self.token_count += tokens;
+
+        full_batch
+    }
+
+    // Return the current batch if it is not empty
+    pub fn take(&mut self) -> Option<Batch> {
+        if self.ids.is_empty() {
+            return None;
+        }
+
+        self.token_count = 0;
+        Some((
+            std::mem::take(&mut self.ids),
+            std::mem::take(&mut self.inputs),
+            std::mem::take(&mut self.token_counts),
+        ))
+    }
+}
@@ -0,0 +1,52 @@
+use super::cli::{BinaryType, EmbeddingArgs};
+use crate::utils::quote_ident;
+
+// Positive values are encoded as 1 bits, zero and negative values as 0 bits
+pub fn sign_bits(embedding: &[f32]) -> String {
+    embedding
+        .iter()
+        .map(|v| if *v > 0.0 { '1' } else { '0' })
+        .collect()
+}
+
+// Bits are packed in the same order as in bit(n) type, last byte is padded with 0 bits
+pub fn pack_sign_bits(embedding: &[f32]) -> Vec<u8> {
+    embedding
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .filter(|(_, v)| **v > 0.0)
+                .fold(0u8, |byte, (idx, _)| byte | (0x80 >> idx))
+        })
+        .collect()
+}
+
+pub fn hamming_distance(a: &[u8], b: &[u8]) -> u32 {
+    a.iter().zip(b).map(|(a, b)| (a ^ b).count_ones()).sum()
+}
+
+// Type of the column binary vectors are written to
+// bit(n) is used when dimensions are known, so vectors of different length can not be mixed
+pub fn get_binary_type_sql(args: &EmbeddingArgs, dimensions: Option<u64>) -> String {
+    match (&args.binary_type, dimensions) {
+        (BinaryType::Bytea, _) => "BYTEA".to_owned(),
+        (BinaryType::Bit, Some(dimensions)) => format!("BIT({dimensions})"),
+        (BinaryType::Bit, None) => "BIT VARYING".to_owned(),
+    }
+}
+
+// Hamming distance between binary vectors for prefiltering before re-ranking with float vectors
+// Bytea vectors are compared as bit strings, so both functions return the same distance
+pub fn get_hamming_distance_sql(schema: &str) -> String {
+    let schema = quote_ident(schema);
+    format!(
+        "
+        CREATE OR REPLACE FUNCTION {schema}.hamming_distance(a BIT VARYING, b BIT VARYING) RETURNS INT
+            AS $$ SELECT bit_count(a # b)::INT $$ LANGUAGE SQL IMMUTABLE STRICT PARALLEL SAFE;
+        CREATE OR REPLACE FUNCTION {schema}.hamming_distance(a BYTEA, b BYTEA) RETURNS INT
+            AS $$ SELECT bit_count(('x' || encode(a, 'hex'))::BIT VARYING # ('x' || encode(b, 'hex'))::BIT VARYING)::INT $$ LANGUAGE SQL IMMUTABLE STRICT PARALLEL SAFE;
+    "
+    )
+}
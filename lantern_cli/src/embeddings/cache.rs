@@ -0,0 +1,116 @@
+use super::cli::Runtime;
+use super::CONNECTION_PARAMS;
+use crate::utils::{append_params_to_uri, get_full_table_name};
+use postgres::{Client, NoTls};
+use std::collections::HashMap;
+
+static CACHE_SCHEMA_NAME: &'static str = "_lantern_internal";
+static CACHE_TABLE_NAME: &'static str = "_lantern_emb_cache";
+
+// Embedding cache stores generated embeddings keyed by runtime, model name, hash of the runtime params
+// and md5 hash of the input text
+// So re-running jobs on the same data will not call the runtime again for identical texts
+pub struct EmbeddingCache {
+    client: Client,
+    full_table_name: String,
+    runtime: String,
+    params_hash: String,
+}
+
+pub fn hash_text(text: &str) -> String {
+    format!("{:x}", md5::compute(text))
+}
+
+fn is_secret_param(key: &str) -> bool {
+    let key = key.to_lowercase();
+    ["token", "key", "secret", "password"]
+        .iter()
+        .any(|secret| key.contains(secret))
+}
+
+// Params like dimensions or base url change the embeddings, so they are part of the cache key
+// Secrets are left out, so rotating the API key keeps the cache
+// Keys of serde_json maps are sorted, so the same params always give the same hash
+fn hash_runtime_params(runtime_params: &str) -> Result<String, anyhow::Error> {
+    let mut params: serde_json::Value = serde_json::from_str(runtime_params)?;
+    if let Some(params) = params.as_object_mut() {
+        params.retain(|key, _| !is_secret_param(key));
+    }
+    Ok(hash_text(&params.to_string()))
+}
+
+impl EmbeddingCache {
+    pub fn new(uri: &str, runtime: &Runtime, runtime_params: &str) -> Result<Self, anyhow::Error> {
+        let uri = append_params_to_uri(uri, CONNECTION_PARAMS);
+        let mut client = Client::connect(&uri, NoTls)?;
+        let full_table_name = get_full_table_name(CACHE_SCHEMA_NAME, CACHE_TABLE_NAME);
+
+        client.batch_execute(&format!(
+            "
+            CREATE SCHEMA IF NOT EXISTS {CACHE_SCHEMA_NAME};
+            CREATE TABLE IF NOT EXISTS {full_table_name} (
+                runtime TEXT NOT NULL,
+                model TEXT NOT NULL,
+                params_hash TEXT NOT NULL,
+                text_hash TEXT NOT NULL,
+                embedding REAL[] NOT NULL,
+                PRIMARY KEY (runtime, model, params_hash, text_hash)
+            );
+        "
+        ))?;
+
+        Ok(Self {
+            client,
+            full_table_name,
+            runtime: runtime.to_string(),
+            params_hash: hash_runtime_params(runtime_params)?,
+        })
+    }
+
+    pub fn get(
+        &mut self,
+        model: &str,
+        hashes: &[String],
+    ) -> Result<HashMap<String, Vec<f32>>, anyhow::Error> {
+        let rows = self.client.query(
+            &format!(
+                "SELECT text_hash, embedding FROM {} WHERE runtime = $1 AND model = $2 AND params_hash = $3 AND text_hash = ANY($4)",
+                self.full_table_name
+            ),
+            &[&self.runtime, &model, &self.params_hash, &hashes],
+        )?;
+
+        Ok(rows
+            .iter()
+            .map(|row| (row.get::<usize, String>(0), row.get::<usize, Vec<f32>>(1)))
+            .collect())
+    }
+
+    pub fn set(
+        &mut self,
+        model: &str,
+        hashes: &[String],
+        embeddings: &[Vec<f32>],
+    ) -> Result<(), anyhow::Error> {
+        // Arrays of arrays can not be passed to UNNEST with different lengths
+        // So the rows are inserted one by one in a single transaction
+        let mut transaction = self.client.transaction()?;
+        let statement = transaction.prepare(&format!(
+            "INSERT INTO {} (runtime, model, params_hash, text_hash, embedding) VALUES ($1, $2, $3, $4, $5) ON CONFLICT DO NOTHING",
+            self.full_table_name
+        ))?;
+
+        for (hash, embedding) in hashes.iter().zip(embeddings.iter()) {
+            if embedding.is_empty() {
+                continue;
+            }
+            transaction.execute(
+                &statement,
+                &[&self.runtime, &model, &self.params_hash, hash, embedding],
+            )?;
+        }
+
+        transaction.commit()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,176 @@
+use super::cli::{ChunkUnit, EmbeddingArgs, JsonPathMissing};
+use super::core::runtime::DEFAULT_TOKENIZER;
+use super::{get_source_sql, CONNECTION_PARAMS};
+use crate::logger::Logger;
+use crate::utils::{append_params_to_uri, escape_copy_value, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+use std::io::Write;
+
+static CHUNK_BATCH_SIZE: i32 = 1000;
+
+// Decode tokens into text. If the window ends in the middle of multi-byte character
+// the last tokens will be dropped until the text can be decoded
+fn decode_tokens(tokens: &[usize]) -> String {
+    let mut end = tokens.len();
+    while end > 0 {
+        if let Ok(text) = DEFAULT_TOKENIZER.decode(tokens[..end].to_vec()) {
+            return text;
+        }
+        end -= 1;
+    }
+    String::new()
+}
+
+fn split_windows<T: Clone>(items: &[T], size: usize, overlap: usize) -> Vec<Vec<T>> {
+    let step = size - overlap;
+    let mut windows = Vec::new();
+    let mut start = 0;
+
+    loop {
+        let end = std::cmp::min(start + size, items.len());
+        windows.push(items[start..end].to_vec());
+        if end == items.len() {
+            break;
+        }
+        start += step;
+    }
+
+    windows
+}
+
+pub fn split_text(text: &str, unit: &ChunkUnit, size: usize, overlap: usize) -> Vec<String> {
+    match unit {
+        ChunkUnit::Chars => {
+            let chars: Vec<char> = text.chars().collect();
+            split_windows(&chars, size, overlap)
+                .into_iter()
+                .map(|chunk| chunk.into_iter().collect())
+                .collect()
+        }
+        ChunkUnit::Tokens => {
+            let tokens = DEFAULT_TOKENIZER.encode_ordinary(text);
+            split_windows(&tokens, size, overlap)
+                .iter()
+                .map(|chunk| decode_tokens(chunk))
+                .collect()
+        }
+    }
+}
+
+// This function will split source column text into chunks and write them into chunks table
+// Chunks of the processed rows will be recreated, so the job can be rerun for the same rows
+// Embeddings for the chunks will be generated afterwards using chunks table as source table
+pub fn create_chunks(args: &EmbeddingArgs, logger: &Logger) -> Result<usize, anyhow::Error> {
+    if let Some(chunk_size) = args.chunk_size {
+        if chunk_size == 0 || args.chunk_overlap >= chunk_size {
+            anyhow::bail!("Chunk size should be greater than 0 and chunk overlap");
+        }
+    }
+
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let mut out_client = Client::connect(&uri, NoTls)?;
+
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let chunks_full_table_name =
+        get_full_table_name(&args.schema, args.chunks_table.as_ref().unwrap());
+    let pk = quote_ident(&args.pk);
+    let column = quote_ident(&args.column);
+
+    let source_sql = get_source_sql(args, &column);
+    let mut filter_sql = match &args.filter {
+        Some(filter) => format!("WHERE ({filter})"),
+        None => format!("WHERE {column} IS NOT NULL"),
+    };
+
+    if args.jsonpath.is_some() && matches!(args.jsonpath_missing, JsonPathMissing::Skip) {
+        filter_sql.push_str(&format!(" AND {source_sql} IS NOT NULL"));
+    }
+
+    // Limited rows are ordered, so old chunks are deleted for the same rows which are chunked
+    let limit_sql = match &args.limit {
+        Some(limit) => format!("ORDER BY {pk} LIMIT {limit}"),
+        None => "".to_owned(),
+    };
+
+    let mut out_transaction = out_client.transaction()?;
+    out_transaction.batch_execute(&format!(
+        "
+        CREATE TABLE IF NOT EXISTS {chunks_full_table_name} AS SELECT {pk} AS source_pk, 0 AS chunk_index, ''::TEXT AS chunk_text, NULL::REAL[] AS embedding FROM {full_table_name} LIMIT 0;
+        DELETE FROM {chunks_full_table_name} WHERE source_pk IN (SELECT {pk} FROM {full_table_name} {filter_sql} {limit_sql});
+    "
+    ))?;
+
+    let mut writer = out_transaction.copy_in(&format!(
+        "COPY {chunks_full_table_name} (source_pk, chunk_index, chunk_text) FROM stdin"
+    ))?;
+
+    let mut transaction = client.transaction()?;
+    let portal = transaction.bind(
+        &format!("SELECT {pk}::text, {source_sql} FROM {full_table_name} {filter_sql} {limit_sql}"),
+        &[],
+    )?;
+
+    let mut chunk_cnt = 0;
+    loop {
+        let rows = transaction.query_portal(&portal, CHUNK_BATCH_SIZE)?;
+
+        if rows.is_empty() {
+            break;
+        }
+
+        for row in &rows {
+            // Elements of text[] column are written as separate chunks
+            // If chunk size is specified each element will be split as well
+            let texts: Vec<&str> = if args.array_mode.is_some() {
+                match row.try_get::<usize, Option<Vec<Option<&str>>>>(1) {
+                    Ok(Some(elements)) => elements
+                        .into_iter()
+                        .flatten()
+                        .filter(|element| element.trim() != "")
+                        .collect(),
+                    _ => continue,
+                }
+            } else {
+                vec![match row.try_get::<usize, Option<&str>>(1) {
+                    Ok(Some(text)) if text.trim() != "" => text,
+                    Ok(None) if args.jsonpath.is_some() => {
+                        if let JsonPathMissing::Error = args.jsonpath_missing {
+                            anyhow::bail!(
+                                "JSONPath '{}' did not match row with {} {}",
+                                args.jsonpath.as_ref().unwrap(),
+                                args.pk,
+                                row.get::<usize, &str>(0)
+                            );
+                        }
+                        continue;
+                    }
+                    _ => continue,
+                }]
+            };
+            let source_pk = escape_copy_value(row.get::<usize, &str>(0));
+
+            let chunks = texts.into_iter().flat_map(|text| match args.chunk_size {
+                Some(chunk_size) => {
+                    split_text(text, &args.chunk_unit, chunk_size, args.chunk_overlap)
+                }
+                None => vec![text.to_owned()],
+            });
+
+            for (idx, chunk) in chunks.enumerate() {
+                writer.write_all(
+                    format!("{source_pk}\t{idx}\t{}\n", escape_copy_value(&chunk)).as_bytes(),
+                )?;
+                chunk_cnt += 1;
+            }
+        }
+    }
+
+    writer.finish()?;
+    out_transaction.commit()?;
+
+    logger.info(&format!(
+        "Created {chunk_cnt} chunks in table {chunks_full_table_name}"
+    ));
+    Ok(chunk_cnt)
+}
@@ -1,7 +1,108 @@
 pub use super::core::Runtime;
-use clap::Parser;
+use crate::external_index::cli::UMetricKind;
+use clap::{Parser, Subcommand, ValueEnum};
 
-#[derive(Parser, Debug)]
+#[derive(Debug, Clone, ValueEnum)]
+pub enum ChunkUnit {
+    Chars,
+    Tokens,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum CountMode {
+    /// SELECT COUNT(*) of the source rows
+    Exact,
+    /// Row estimate of the planner, taken from pg_class.reltuples if there is no filter
+    Estimate,
+    /// Do not count the rows, progress is reported without percent and ETA
+    None,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum ColumnType {
+    /// REAL[] column
+    Real,
+    /// vector column with dimensions from runtime params
+    Vector,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Precision {
+    /// 32-bit floats
+    F32,
+    /// 16-bit floats, written as halfvec for vector columns and as SMALLINT[] with packed values for real columns
+    F16,
+    /// 8-bit integers quantized with per-dimension ranges, written as BYTEA for real columns
+    Int8,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Quantize {
+    /// 1 bit per dimension, set for positive values
+    Binary,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum BinaryType {
+    /// bit(n) column, or bit varying if dimensions are not known
+    Bit,
+    /// Bits packed into bytes
+    Bytea,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum ArrayMode {
+    /// Write embedding of each element into chunks table
+    ChildTable,
+    /// Write mean of element embeddings into output column
+    Mean,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum JsonPathMissing {
+    /// Skip rows where JSONPath does not match or the value is null
+    Skip,
+    /// Fail the job if JSONPath does not match or the value is null
+    Error,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum OutMode {
+    /// Update embedding column of the source table rows
+    Update,
+    /// Insert embeddings with source primary keys into --out-table
+    Insert,
+    /// Insert embeddings into --out-table, updating the rows with existing keys
+    Upsert,
+}
+
+#[derive(Debug, Clone, PartialEq, ValueEnum)]
+pub enum ListFormat {
+    /// Human readable listing
+    Text,
+    /// JSON array for scripts and UIs
+    Json,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum VectorFormat {
+    /// JSON array
+    Json,
+    /// pgvector literal, e.g. '[1,2,3]'
+    Pgvector,
+    /// Postgres array literal for REAL[] columns, e.g. '{1,2,3}'
+    Array,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum Fusion {
+    /// Reciprocal rank fusion, sum of 1 / (k + rank) of the row in each result list
+    Rrf,
+    /// Weighted sum of min-max normalized vector similarity and text rank
+    Weighted,
+}
+
+#[derive(Parser, Debug, Clone)]
 #[command(version, about, long_about = None)]
 pub struct EmbeddingArgs {
     /// Model name
@@ -9,11 +110,11 @@ pub struct EmbeddingArgs {
     pub model: String,
 
     /// Fully associated database connection string including db name
-    #[arg(short, long)]
+    #[arg(short, long, required_unless_present_any = ["in_csv", "in_parquet", "stdin"], default_value = "")]
     pub uri: String,
 
-    /// Table name
-    #[arg(short, long)]
+    /// Table name. With --in-csv or --in-parquet embeddings are inserted into this table if no output file is specified
+    #[arg(short, long, required_unless_present_any = ["in_csv", "in_parquet", "stdin"], default_value = "")]
     pub table: String,
 
     /// Schema name
@@ -21,7 +122,7 @@ pub struct EmbeddingArgs {
     pub schema: String,
 
     /// Column name to generate embeddings for
-    #[arg(short, long)]
+    #[arg(short, long, required_unless_present_any = ["in_csv", "in_parquet", "stdin"], default_value = "")]
     pub column: String,
 
     /// Output db uri, fully associated database connection string including db name. Defaults to
@@ -32,14 +133,51 @@ pub struct EmbeddingArgs {
     #[arg(long)]
     pub out_table: Option<String>,
 
-    /// Output column name
+    /// Output schema name. Defaults to schema
+    #[arg(long)]
+    pub out_schema: Option<String>,
+
+    /// How embeddings are written to the output table. In insert and upsert modes the output table
+    /// is a separate table of (key, embedding) rows, which is created if it does not exist
+    #[arg(long, value_enum, default_value_t = OutMode::Update)]
+    pub out_mode: OutMode,
+
+    /// Column of the output table, where primary key of the source row is written in insert and
+    /// upsert modes. Upsert mode needs a unique index on it. Defaults to --pk
     #[arg(long)]
+    pub out_key_column: Option<String>,
+
+    /// Create HNSW index on the output column after the embeddings are written, e.g.
+    /// "hnsw(m=16, ef_construction=64, dist=cos)". lantern_hnsw is used for real columns and
+    /// pgvector hnsw for vector columns
+    #[arg(long)]
+    pub create_index: Option<String>,
+
+    /// Create the index with CREATE INDEX CONCURRENTLY, so writes to the table are not blocked
+    #[arg(long, default_value_t = false, requires = "create_index")]
+    pub create_index_concurrently: bool,
+
+    /// Output column name
+    #[arg(long, required_unless_present = "stdin", default_value = "")]
     pub out_column: String,
 
-    /// Batch size
+    /// Batch size. If it is set, batches have a fixed number of rows unless --max-tokens-per-batch is passed
     #[arg(short, long)]
     pub batch_size: Option<usize>,
 
+    /// Maximum number of tokens in one batch sent to the runtime, batches are also limited to batch size rows. Defaults to model specific value if batch size is not set,
+    /// so by default batches are packed by token budget instead of a fixed number of rows
+    #[arg(long)]
+    pub max_tokens_per_batch: Option<usize>,
+
+    /// Maximum HTTP requests per minute sent to the runtime, shared between all embedding workers
+    #[arg(long)]
+    pub max_requests_per_minute: Option<u32>,
+
+    /// Maximum tokens sent to the runtime per minute shared between all embedding workers
+    #[arg(long)]
+    pub max_tokens_per_minute: Option<u32>,
+
     /// Runtime
     #[arg(long, default_value_t = Runtime::Ort)]
     pub runtime: Runtime,
@@ -64,6 +202,14 @@ pub struct EmbeddingArgs {
     #[arg(short, long)]
     pub limit: Option<u32>,
 
+    /// Embed this many random rows of the table, e.g. to check the output and the job settings before running the full table
+    #[arg(long, conflicts_with_all = ["limit", "sample_percent"])]
+    pub sample: Option<u32>,
+
+    /// Embed a random sample of about this percent of the table rows, read with TABLESAMPLE BERNOULLI
+    #[arg(long)]
+    pub sample_percent: Option<f64>,
+
     /// Stream data to output table while still generating
     #[arg(long, default_value_t = false)]
     pub stream: bool,
@@ -71,9 +217,411 @@ pub struct EmbeddingArgs {
     /// Create destination column if not exists
     #[arg(long, default_value_t = true)]
     pub create_column: bool,
+
+    /// Primary key of the source table. Used to reference source rows from chunks table
+    #[arg(long, default_value = "id")]
+    pub pk: String,
+
+    /// Split source text into chunks of this size before generating embeddings
+    #[arg(long)]
+    pub chunk_size: Option<usize>,
+
+    /// Number of units shared between consecutive chunks
+    #[arg(long, default_value_t = 0)]
+    pub chunk_overlap: usize,
+
+    /// Unit in which chunk size and overlap are measured
+    #[arg(long, value_enum, default_value_t = ChunkUnit::Chars)]
+    pub chunk_unit: ChunkUnit,
+
+    /// Output chunks table name. Chunks will be written to this table with (source_pk, chunk_index, chunk_text, embedding) columns
+    #[arg(long)]
+    pub chunks_table: Option<String>,
+
+    /// Use embedding cache table to skip generating embeddings for already processed texts
+    #[arg(long, default_value_t = false)]
+    pub use_cache: bool,
+
+    /// Generate embeddings only for rows where output column is NULL
+    #[arg(long, default_value_t = false)]
+    pub only_missing: bool,
+
+    /// Column with row update timestamp. Rows updated after their embeddings were generated will be processed again
+    #[arg(long)]
+    pub stale_check: Option<String>,
+
+    /// Column where the embedding generation timestamp is stored when stale check is used
+    #[arg(long, default_value = "embedded_at")]
+    pub embedded_at_column: String,
+
+    /// Number of embedding worker threads
+    #[arg(long, default_value_t = 1)]
+    pub embedding_workers: usize,
+
+    /// CPU cores to pin producer thread to (e.g. "0" or "0-3,6")
+    #[arg(long)]
+    pub producer_cores: Option<String>,
+
+    /// CPU cores to pin embedding worker threads to (e.g. "1-6")
+    #[arg(long)]
+    pub embedding_cores: Option<String>,
+
+    /// CPU cores to pin exporter thread to (e.g. "7")
+    #[arg(long)]
+    pub exporter_cores: Option<String>,
+
+    /// Grow flush thresholds in streaming mode when destination UPDATE latency exceeds the target
+    #[arg(long, default_value_t = false)]
+    pub adaptive_flush: bool,
+
+    /// Target latency of a single flush in milliseconds for adaptive flush
+    #[arg(long, default_value_t = 1000)]
+    pub flush_latency_target_ms: u64,
+
+    /// Seconds after which collected rows are flushed in streaming mode, if there are at least --min-flush-rows
+    #[arg(long, default_value_t = 10)]
+    pub flush_interval: u64,
+
+    /// Minimum number of rows flushed after --flush-interval in streaming mode
+    #[arg(long, default_value_t = 50)]
+    pub min_flush_rows: usize,
+
+    /// Rows are flushed in streaming mode as soon as this many rows are collected
+    #[arg(long, default_value_t = 1000)]
+    pub max_flush_rows: usize,
+
+    /// If set, destination table is updated in separate transactions of this many rows, so one
+    /// large UPDATE does not hold row locks for the whole flush
+    #[arg(long)]
+    pub update_batch_size: Option<usize>,
+
+    /// Write schema changes (e.g. new columns) to this migration file instead of executing them
+    #[arg(long)]
+    pub emit_migration: Option<String>,
+
+    /// Commit rows to destination table in separate transactions of this size
+    #[arg(long)]
+    pub commit_every_rows: Option<usize>,
+
+    /// Type of destination column to create
+    #[arg(long, value_enum, default_value_t = ColumnType::Real)]
+    pub column_type: ColumnType,
+
+    /// Refuse to run if runtime endpoint is not in this region (e.g. "eu")
+    #[arg(long)]
+    pub require_region: Option<String>,
+
+    /// JSONPath to extract text from JSONB source column (e.g. '$.description')
+    #[arg(long)]
+    pub jsonpath: Option<String>,
+
+    /// What to do with rows where JSONPath does not match or the value is null
+    #[arg(long, value_enum, default_value_t = JsonPathMissing::Skip)]
+    pub jsonpath_missing: JsonPathMissing,
+
+    /// Column to store the text extracted with JSONPath
+    #[arg(long)]
+    pub jsonpath_text_column: Option<String>,
+
+    /// Embed each element of text[] source column
+    #[arg(long, value_enum)]
+    pub array_mode: Option<ArrayMode>,
+
+    /// Run the job separately for each distinct value of this column (e.g. tenant_id)
+    #[arg(long, conflicts_with = "iterate_schemas")]
+    pub iterate_by: Option<String>,
+
+    /// Run the job separately for each schema matching this LIKE pattern (e.g. 'tenant_%')
+    #[arg(long)]
+    pub iterate_schemas: Option<String>,
+
+    /// Name of checkpoint to record completed tenants. Completed tenants are skipped when the job is run again with the same checkpoint
+    #[arg(long)]
+    pub tenant_checkpoint: Option<String>,
+
+    /// Read the table in a single repeatable read snapshot, so the row count and all producer scans see the same data
+    #[arg(long, default_value_t = false)]
+    pub consistent_snapshot: bool,
+
+    /// How the source rows are counted for progress. Exact count can take minutes on huge tables before the job starts
+    #[arg(long, value_enum, default_value_t = CountMode::Exact)]
+    pub count_mode: CountMode,
+
+    /// Read the table with short transactions per batch, fetching the rows ordered by this unique not null column (e.g. the primary key) after the last key of the previous batch. Avoids holding one transaction open for the whole job
+    #[arg(long)]
+    pub paginate_by: Option<String>,
+
+    /// --uri is a read replica of --out-uri. Source rows are read from the replica and the embeddings are written to the same table on the primary, matched by --pk
+    #[arg(long, default_value_t = false)]
+    pub read_replica: bool,
+
+    /// Seconds to wait for the replica to replay the changes committed on the primary before the job started
+    #[arg(long, default_value_t = 60)]
+    pub replica_wait_timeout: u64,
+
+    /// Number of parallel producer scans over table page ranges
+    #[arg(long, default_value_t = 1)]
+    pub producer_scans: usize,
+
+    /// Number of connections writing embeddings to the database in parallel. Batches are sharded between writers, each writing to its own temporary table and updating the destination table independently
+    #[arg(long, default_value_t = 1)]
+    pub writer_jobs: usize,
+
+    /// Retry failed flush window this many times on a new connection before failing the job (streaming mode or --commit-every-rows)
+    #[arg(long, default_value_t = 3)]
+    pub flush_retries: usize,
+
+    /// OpenLineage HTTP endpoint to send job start and completion events to (e.g. http://marquez:5000/api/v1/lineage)
+    #[arg(long)]
+    pub lineage_url: Option<String>,
+
+    /// OpenLineage job namespace
+    #[arg(long, default_value = "lantern")]
+    pub lineage_namespace: String,
+
+    /// API key sent as bearer token to the OpenLineage endpoint
+    #[arg(long)]
+    pub lineage_api_key: Option<String>,
+
+    /// Serve Prometheus metrics on this port at /metrics while the job is running
+    #[arg(long)]
+    pub metrics_port: Option<u16>,
+
+    /// Send progress events of the job with pg_notify to the lantern_job_progress channel of the output database
+    #[arg(long, default_value_t = false)]
+    pub notify_progress: bool,
+
+    /// Job id included in the --notify-progress events, so listeners can tell the jobs apart
+    #[arg(long)]
+    pub job_id: Option<String>,
+
+    /// Fail right away if another job is writing to the same output column, instead of waiting for it to finish
+    #[arg(long, default_value_t = false, conflicts_with_all = ["wait_lock", "skip_lock"])]
+    pub fail_if_locked: bool,
+
+    /// Wait at most this many seconds for another job writing to the same output column to finish. Waits until it finishes if not set
+    #[arg(long, conflicts_with = "skip_lock")]
+    pub wait_lock: Option<u64>,
+
+    /// Do not lock the output column, e.g. when jobs with different filters write to the same column in parallel
+    #[arg(long, default_value_t = false)]
+    pub skip_lock: bool,
+
+    /// Price in USD per 1M tokens for the cost report. Defaults to the list price of API models
+    #[arg(long)]
+    pub token_price: Option<f64>,
+
+    /// Count tokens and requests and print the cost report without calling the runtime or writing results
+    #[arg(long, default_value_t = false)]
+    pub dry_run_cost: bool,
+
+    /// Validate the job and print the plan without writing to the database. Checks permissions, embeds one sample batch and compares dimensions with the target column
+    #[arg(long, default_value_t = false)]
+    pub dry_run: bool,
+
+    /// Read texts from this CSV file with a header row instead of the source table
+    #[arg(long, conflicts_with = "in_parquet")]
+    pub in_csv: Option<String>,
+
+    /// Read texts from this Parquet file instead of the source table
+    #[arg(long)]
+    pub in_parquet: Option<String>,
+
+    /// Column of --in-csv or --in-parquet file with row ids
+    #[arg(long, default_value = "id")]
+    pub id_column: String,
+
+    /// Column of --in-csv or --in-parquet file with texts to embed
+    #[arg(long, default_value = "text")]
+    pub text_column: String,
+
+    /// Output Parquet path. If specified result will be written to Parquet file with (id, embedding) columns instead of database
+    #[arg(long, conflicts_with = "out_csv")]
+    pub out_parquet: Option<String>,
+
+    /// Read newline-delimited texts from standard input instead of the source table. Row ids are line numbers
+    #[arg(long, default_value_t = false, conflicts_with_all = ["in_csv", "in_parquet"])]
+    pub stdin: bool,
+
+    /// Output JSON Lines path with {"id", "embedding"} objects. Use "-" to write to standard output, which is the default for --stdin without --table
+    #[arg(long, conflicts_with_all = ["out_csv", "out_parquet"])]
+    pub out_jsonl: Option<String>,
+
+    /// Truncate embeddings to this many dimensions and re-normalize them (for Matryoshka models like text-embedding-3 and nomic-embed-text)
+    #[arg(long)]
+    pub truncate_dim: Option<usize>,
+
+    /// L2-normalize embeddings before export, so dot product is equivalent to cosine similarity
+    #[arg(long, default_value_t = false)]
+    pub normalize: bool,
+
+    /// Precision of exported embeddings
+    #[arg(long, value_enum, default_value_t = Precision::F32)]
+    pub precision: Precision,
+
+    /// Number of threads serializing and compressing rows for --out-csv (compressed with gzip if path ends with .gz)
+    #[arg(long, default_value_t = 2)]
+    pub csv_workers: usize,
+
+    /// Number of embeddings used to train per-dimension ranges for --precision int8
+    #[arg(long, default_value_t = 10000)]
+    pub int8_train_rows: usize,
+
+    /// Write quantized vectors instead of floats, or next to them if --quantize-column is specified
+    #[arg(long, value_enum)]
+    pub quantize: Option<Quantize>,
+
+    /// Column to write quantized vectors to, float vectors will be written to --out-column
+    #[arg(long)]
+    pub quantize_column: Option<String>,
+
+    /// Column type for --quantize binary
+    #[arg(long, value_enum, default_value_t = BinaryType::Bit)]
+    pub binary_type: BinaryType,
+}
+
+// Values match the command line defaults, so the jobs and tests built in code only set the options they use
+impl Default for EmbeddingArgs {
+    fn default() -> Self {
+        EmbeddingArgs {
+            model: String::new(),
+            uri: String::new(),
+            table: String::new(),
+            schema: "public".to_owned(),
+            column: String::new(),
+            out_uri: None,
+            out_table: None,
+            out_schema: None,
+            out_mode: OutMode::Update,
+            out_key_column: None,
+            create_index: None,
+            create_index_concurrently: false,
+            out_column: String::new(),
+            batch_size: None,
+            max_tokens_per_batch: None,
+            max_requests_per_minute: None,
+            max_tokens_per_minute: None,
+            runtime: Runtime::Ort,
+            runtime_params: "{}".to_owned(),
+            visual: false,
+            out_csv: None,
+            filter: None,
+            limit: None,
+            sample: None,
+            sample_percent: None,
+            stream: false,
+            create_column: true,
+            pk: "id".to_owned(),
+            chunk_size: None,
+            chunk_overlap: 0,
+            chunk_unit: ChunkUnit::Chars,
+            chunks_table: None,
+            use_cache: false,
+            only_missing: false,
+            stale_check: None,
+            embedded_at_column: "embedded_at".to_owned(),
+            embedding_workers: 1,
+            producer_cores: None,
+            embedding_cores: None,
+            exporter_cores: None,
+            adaptive_flush: false,
+            flush_latency_target_ms: 1000,
+            flush_interval: 10,
+            min_flush_rows: 50,
+            max_flush_rows: 1000,
+            update_batch_size: None,
+            emit_migration: None,
+            commit_every_rows: None,
+            column_type: ColumnType::Real,
+            require_region: None,
+            jsonpath: None,
+            jsonpath_missing: JsonPathMissing::Skip,
+            jsonpath_text_column: None,
+            array_mode: None,
+            iterate_by: None,
+            iterate_schemas: None,
+            tenant_checkpoint: None,
+            consistent_snapshot: false,
+            count_mode: CountMode::Exact,
+            paginate_by: None,
+            read_replica: false,
+            replica_wait_timeout: 60,
+            producer_scans: 1,
+            writer_jobs: 1,
+            flush_retries: 3,
+            lineage_url: None,
+            lineage_namespace: "lantern".to_owned(),
+            lineage_api_key: None,
+            metrics_port: None,
+            notify_progress: false,
+            job_id: None,
+            fail_if_locked: false,
+            wait_lock: None,
+            skip_lock: false,
+            token_price: None,
+            dry_run_cost: false,
+            dry_run: false,
+            in_csv: None,
+            in_parquet: None,
+            id_column: "id".to_owned(),
+            text_column: "text".to_owned(),
+            out_parquet: None,
+            stdin: false,
+            out_jsonl: None,
+            truncate_dim: None,
+            normalize: false,
+            precision: Precision::F32,
+            csv_workers: 2,
+            int8_train_rows: 10000,
+            quantize: None,
+            quantize_column: None,
+            binary_type: BinaryType::Bit,
+        }
+    }
 }
 
 impl EmbeddingArgs {
+    pub fn has_file_input(&self) -> bool {
+        self.in_csv.is_some() || self.in_parquet.is_some() || self.stdin
+    }
+
+    pub fn has_file_output(&self) -> bool {
+        self.out_csv.is_some() || self.out_parquet.is_some() || self.out_jsonl.is_some()
+    }
+
+    // Rows of the source table are matched by ctid only if the embeddings are written back to them,
+    // rows of other tables and databases are matched by primary key
+    // With a read replica the output table on the primary is the source table
+    pub fn writes_to_source_table(&self) -> bool {
+        (self.read_replica || self.out_uri.as_ref().unwrap_or(&self.uri) == &self.uri)
+            && self.out_schema.as_ref().unwrap_or(&self.schema) == &self.schema
+            && self.out_table.as_ref().unwrap_or(&self.table) == &self.table
+    }
+
+    // Ctid of a row read in an earlier transaction may be reused by another row after vacuum,
+    // so paginated scans and replica reads match the rows by primary key
+    pub fn matches_rows_by_ctid(&self) -> bool {
+        self.writes_to_source_table() && self.paginate_by.is_none() && !self.read_replica
+    }
+
+    // Logs should not be mixed with the embeddings written to standard output
+    pub fn writes_to_stdout(&self) -> bool {
+        [&self.out_csv, &self.out_parquet, &self.out_jsonl]
+            .iter()
+            .any(|path| path.as_deref() == Some("-"))
+    }
+
+    // Embeddings of --stdin texts are written to standard output if no other destination is given
+    pub fn with_stdout_default(self) -> Self {
+        if self.stdin && !self.has_file_output() && self.table.is_empty() {
+            return EmbeddingArgs {
+                out_jsonl: Some("-".to_owned()),
+                ..self
+            };
+        }
+        self
+    }
+
     pub fn with_defaults(self) -> Self {
         EmbeddingArgs {
             out_uri: self.out_uri.or(Some(self.uri.clone())),
@@ -83,6 +631,40 @@ impl EmbeddingArgs {
     }
 }
 
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct SyncEmbeddingArgs {
+    #[command(flatten)]
+    pub embedding_args: EmbeddingArgs,
+
+    /// Notification channel name (defaults to lantern_sync_{table})
+    #[arg(long)]
+    pub channel: Option<String>,
+
+    /// Interval in seconds to collect changed rows before generating embeddings
+    #[arg(long, default_value_t = 5)]
+    pub sync_interval: u64,
+
+    /// Drop created triggers when sync is stopped
+    #[arg(long, default_value_t = false)]
+    pub drop_triggers: bool,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct FanOutEmbeddingArgs {
+    #[command(flatten)]
+    pub embedding_args: EmbeddingArgs,
+
+    /// Additional database connection strings with the same schema. The job is run against --uri and each of these databases
+    #[arg(long = "shard-uri", required = true)]
+    pub shard_uris: Vec<String>,
+
+    /// Number of databases to process in parallel
+    #[arg(long, default_value_t = 1)]
+    pub shard_concurrency: usize,
+}
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct ShowModelsArgs {
@@ -96,6 +678,197 @@ pub struct ShowModelsArgs {
     /// Runtime Params JSON string
     #[arg(long, default_value = "{}")]
     pub runtime_params: String,
+
+    /// Output format. JSON includes dimensions, max tokens, modality and default batch size of the models
+    #[arg(long, value_enum, default_value_t = ListFormat::Text)]
+    pub format: ListFormat,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct ShowRuntimesArgs {
+    /// Output format. JSON includes whether the runtime requires an API key
+    #[arg(long, value_enum, default_value_t = ListFormat::Text)]
+    pub format: ListFormat,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct EmbedTextArgs {
+    /// Text to embed
+    pub text: String,
+
+    /// Model name
+    #[arg(short, long)]
+    pub model: String,
+
+    /// Runtime
+    #[arg(long, default_value_t = Runtime::Ort)]
+    pub runtime: Runtime,
+
+    /// Runtime Params JSON string
+    #[arg(long, default_value = "{}")]
+    pub runtime_params: String,
+
+    /// Truncate the embedding to this many dimensions and re-normalize it
+    #[arg(long)]
+    pub truncate_dim: Option<usize>,
+
+    /// L2-normalize the embedding
+    #[arg(long, default_value_t = false)]
+    pub normalize: bool,
+
+    /// Output format of the vector
+    #[arg(long, value_enum, default_value_t = VectorFormat::Json)]
+    pub format: VectorFormat,
+
+    /// Print dimensions and L2 norm of the embedding to stderr
+    #[arg(long, default_value_t = false)]
+    pub stats: bool,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None)]
+pub struct SearchArgs {
+    /// Fully associated database connection string including db name
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Schema name
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Table name
+    #[arg(short, long)]
+    pub table: String,
+
+    /// Embedding column to search
+    #[arg(short, long)]
+    pub column: String,
+
+    /// Model the embeddings were generated with
+    #[arg(short, long)]
+    pub model: String,
+
+    /// Runtime
+    #[arg(long, default_value_t = Runtime::Ort)]
+    pub runtime: Runtime,
+
+    /// Runtime Params JSON string
+    #[arg(long, default_value = "{}")]
+    pub runtime_params: String,
+
+    /// Query text
+    #[arg(short, long)]
+    pub query: String,
+
+    /// Number of rows to return
+    #[arg(long, default_value_t = 10)]
+    pub top_k: usize,
+
+    /// Distance algorithm
+    #[arg(long, value_enum, default_value_t = UMetricKind::Cos)]
+    pub metric_kind: UMetricKind,
+
+    /// Distance operator, overrides the operator of --metric-kind (e.g. <#> for pgvector inner product)
+    #[arg(long)]
+    pub operator: Option<String>,
+
+    /// Comma separated columns to return
+    #[arg(long, default_value = "*")]
+    pub select: String,
+
+    /// SQL condition to filter rows
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    /// Search candidate list size of lantern and pgvector HNSW indexes
+    #[arg(long)]
+    pub ef: Option<usize>,
+
+    /// Truncate the query embedding to this many dimensions and re-normalize it
+    #[arg(long)]
+    pub truncate_dim: Option<usize>,
+
+    /// L2-normalize the query embedding
+    #[arg(long, default_value_t = false)]
+    pub normalize: bool,
+
+    /// Primary key column used to match rows of vector and full-text results
+    #[arg(long, default_value = "id")]
+    pub pk: String,
+
+    /// tsvector column to run full-text query on and fuse its results with vector search results
+    #[arg(long)]
+    pub hybrid: Option<String>,
+
+    /// Method to fuse vector and full-text results
+    #[arg(long, value_enum, default_value_t = Fusion::Rrf)]
+    pub fusion: Fusion,
+
+    /// Constant k of reciprocal rank fusion
+    #[arg(long, default_value_t = 60)]
+    pub rrf_k: usize,
+
+    /// Weight of vector similarity for --fusion weighted. Text rank weight is 1 - vector weight
+    #[arg(long, default_value_t = 0.5)]
+    pub vector_weight: f64,
+
+    /// Text search configuration of the full-text query (e.g. english). Defaults to default_text_search_config
+    #[arg(long)]
+    pub ts_config: Option<String>,
+
+    /// Number of candidates taken from each of vector and full-text results. Defaults to 4 * --top-k
+    #[arg(long)]
+    pub candidates: Option<usize>,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None)]
+pub struct RerankArgs {
+    /// Query to score the candidates against
+    #[arg(short, long)]
+    pub query: String,
+
+    /// Reranker model name
+    #[arg(short, long)]
+    pub model: String,
+
+    /// Runtime (ort or cohere)
+    #[arg(long, default_value_t = Runtime::Ort)]
+    pub runtime: Runtime,
+
+    /// Runtime Params JSON string
+    #[arg(long, default_value = "{}")]
+    pub runtime_params: String,
+
+    /// Fully associated database connection string including db name
+    #[arg(short, long, required_unless_present = "in_jsonl")]
+    pub uri: Option<String>,
+
+    /// SQL query returning the candidates
+    #[arg(
+        long,
+        required_unless_present = "in_jsonl",
+        conflicts_with = "in_jsonl"
+    )]
+    pub sql: Option<String>,
+
+    /// JSON Lines file with the candidates. Use "-" to read from standard input
+    #[arg(long)]
+    pub in_jsonl: Option<String>,
+
+    /// Column or field of the candidates with texts to score
+    #[arg(long, default_value = "text")]
+    pub text_column: String,
+
+    /// Number of top scored candidates to print. All candidates are printed if not specified
+    #[arg(long)]
+    pub top_k: Option<usize>,
+
+    /// Number of candidates scored in one request
+    #[arg(long, default_value_t = 32)]
+    pub batch_size: usize,
 }
 
 #[derive(Parser, Debug)]
@@ -128,4 +901,106 @@ pub struct MeasureModelSpeedArgs {
     /// Runtime Params JSON string
     #[arg(long, default_value = "{}")]
     pub runtime_params: String,
+
+    /// Price in USD per 1M tokens for the cost report. Defaults to the list price of API models
+    #[arg(long)]
+    pub token_price: Option<f64>,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct BenchmarkArgs {
+    /// Fully associated database connection string including db name
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Table name
+    #[arg(short, long)]
+    pub table: String,
+
+    /// Schema name
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Column name of the texts
+    #[arg(short, long)]
+    pub column: String,
+
+    /// Filter SQL text which will be added into where clause of the sample query
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    /// Models to compare as <runtime>:<model>, e.g. ort:BAAI/bge-small-en or
+    /// openai:openai/text-embedding-3-small. Runtime defaults to ort
+    #[arg(short, long = "model", required = true, value_delimiter = ',')]
+    pub models: Vec<String>,
+
+    /// Runtime params JSON of a runtime as <runtime>=<json>, e.g. openai={"api_key_env": "OPENAI_API_KEY"}
+    #[arg(long)]
+    pub runtime_params: Vec<String>,
+
+    /// Number of random rows embedded by each model
+    #[arg(long, default_value_t = 200)]
+    pub sample_size: usize,
+
+    /// Batch size. Defaults to the batch size of each model
+    #[arg(short, long)]
+    pub batch_size: Option<usize>,
+
+    /// Output format. JSON includes the errors of failed models
+    #[arg(long, value_enum, default_value_t = ListFormat::Text)]
+    pub format: ListFormat,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ModelsCommands {
+    /// Download model files to the data path
+    Pull(PullModelArgs),
+    /// List downloaded models
+    List(ListModelsArgs),
+    /// Remove downloaded model files
+    Rm(RemoveModelArgs),
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct ModelsArgs {
+    #[command(subcommand)]
+    pub command: ModelsCommands,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct PullModelArgs {
+    /// Model name
+    #[arg(short, long)]
+    pub model: String,
+
+    /// Runtime Params JSON string (data_path, quantized, hf_endpoint)
+    #[arg(long, default_value = "{}")]
+    pub runtime_params: String,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct ListModelsArgs {
+    /// Recompute checksums of downloaded files and compare with the stored ones
+    #[arg(long, default_value_t = false)]
+    pub verify: bool,
+
+    /// Runtime Params JSON string (data_path)
+    #[arg(long, default_value = "{}")]
+    pub runtime_params: String,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct RemoveModelArgs {
+    /// Model name
+    #[arg(short, long)]
+    pub model: String,
+
+    /// Runtime Params JSON string (data_path)
+    #[arg(long, default_value = "{}")]
+    pub runtime_params: String,
 }
@@ -0,0 +1,614 @@
+use chrono::{DateTime, Utc};
+use core::time::Duration;
+use isahc::{config::RedirectPolicy, prelude::*, HttpClient};
+use itertools::Itertools;
+use ring::{digest, hmac};
+use std::sync::Arc;
+use std::{collections::HashMap, sync::RwLock};
+use tokio::runtime::Runtime;
+use url::Url;
+
+use super::{
+    cohere_runtime::{INPUT_TYPES, TRUNCATE_OPTIONS},
+    runtime::{self, EmbeddingResult, EmbeddingRuntime, Modality, DEFAULT_TOKENIZER},
+    utils::post_with_retries,
+    LoggerFn,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(PartialEq)]
+enum ModelProvider {
+    Titan,
+    Cohere,
+}
+
+struct ModelInfo {
+    name: String,
+    provider: ModelProvider,
+    sequence_len: usize,
+    dimensions: usize,
+    supported_dimensions: Vec<usize>,
+    max_batch_size: usize,
+}
+
+#[derive(Deserialize)]
+struct TitanResponse {
+    embedding: Vec<f32>,
+    #[serde(rename = "inputTextTokenCount")]
+    input_text_token_count: usize,
+}
+
+#[derive(Deserialize)]
+struct CohereResponse {
+    embeddings: Vec<Vec<f32>>,
+}
+
+impl ModelInfo {
+    pub fn new(model_name: &str) -> Result<Self, anyhow::Error> {
+        let name = model_name.split("/").last().unwrap().to_owned();
+        match model_name {
+            "bedrock/amazon.titan-embed-text-v1" => Ok(Self {
+                name,
+                provider: ModelProvider::Titan,
+                sequence_len: 8192,
+                dimensions: 1536,
+                supported_dimensions: vec![1536],
+                max_batch_size: 1,
+            }),
+            "bedrock/amazon.titan-embed-text-v2:0" => Ok(Self {
+                name,
+                provider: ModelProvider::Titan,
+                sequence_len: 8192,
+                dimensions: 1024,
+                supported_dimensions: vec![256, 512, 1024],
+                max_batch_size: 1,
+            }),
+            "bedrock/cohere.embed-english-v3" => Ok(Self {
+                name,
+                provider: ModelProvider::Cohere,
+                sequence_len: 512,
+                dimensions: 1024,
+                supported_dimensions: vec![1024],
+                max_batch_size: 96,
+            }),
+            "bedrock/cohere.embed-multilingual-v3" => Ok(Self {
+                name,
+                provider: ModelProvider::Cohere,
+                sequence_len: 512,
+                dimensions: 1024,
+                supported_dimensions: vec![1024],
+                max_batch_size: 96,
+            }),
+            _ => anyhow::bail!("Unsupported model {model_name}"),
+        }
+    }
+}
+
+lazy_static! {
+    static ref MODEL_INFO_MAP: RwLock<HashMap<&'static str, ModelInfo>> =
+        RwLock::new(HashMap::from([
+            (
+                "bedrock/amazon.titan-embed-text-v1",
+                ModelInfo::new("bedrock/amazon.titan-embed-text-v1").unwrap()
+            ),
+            (
+                "bedrock/amazon.titan-embed-text-v2:0",
+                ModelInfo::new("bedrock/amazon.titan-embed-text-v2:0").unwrap()
+            ),
+            (
+                "bedrock/cohere.embed-english-v3",
+                ModelInfo::new("bedrock/cohere.embed-english-v3").unwrap()
+            ),
+            (
+                "bedrock/cohere.embed-multilingual-v3",
+                ModelInfo::new("bedrock/cohere.embed-multilingual-v3").unwrap()
+            ),
+        ]));
+}
+
+pub struct AwsCredentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+pub struct BedrockRuntime<'a> {
+    request_timeout: u64,
+    base_url: String,
+    region: String,
+    credentials: AwsCredentials,
+    dimensions: Option<usize>,
+    normalize: bool,
+    input_type: String,
+    truncate: String,
+    #[allow(dead_code)]
+    logger: &'a LoggerFn,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct BedrockRuntimeParams {
+    pub region: Option<String>,
+    pub access_key_id: Option<String>,
+    pub secret_access_key: Option<String>,
+    pub session_token: Option<String>,
+    pub profile: Option<String>,
+    pub dimensions: Option<usize>,
+    pub normalize: Option<bool>,
+    pub input_type: Option<String>,
+    pub truncate: Option<String>,
+    pub base_url: Option<String>,
+}
+
+static SIGNING_SERVICE: &str = "bedrock";
+static SIGNING_ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+pub fn sha256_hex(data: &[u8]) -> String {
+    hex_encode(digest::digest(&digest::SHA256, data).as_ref())
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), data.as_bytes())
+        .as_ref()
+        .to_vec()
+}
+
+// URI encode as described in AWS Signature Version 4 documentation
+pub fn uri_encode(value: &str) -> String {
+    value
+        .bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{b:02X}"),
+        })
+        .collect()
+}
+
+// Build Authorization header value using AWS Signature Version 4
+// `headers` should contain all headers which will be signed including host and x-amz-date
+pub fn sign_request(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    canonical_uri: &str,
+    headers: &[(String, String)],
+    body: &str,
+) -> Result<String, anyhow::Error> {
+    sign_request_with_query(
+        credentials,
+        region,
+        service,
+        method,
+        canonical_uri,
+        "",
+        headers,
+        &sha256_hex(body.as_bytes()),
+    )
+}
+
+// Same as `sign_request` for requests with query parameters and precomputed payload hash
+// `canonical_query` should contain URI encoded parameters sorted by name (e.g. "partNumber=1&uploadId=id")
+pub fn sign_request_with_query(
+    credentials: &AwsCredentials,
+    region: &str,
+    service: &str,
+    method: &str,
+    canonical_uri: &str,
+    canonical_query: &str,
+    headers: &[(String, String)],
+    payload_hash: &str,
+) -> Result<String, anyhow::Error> {
+    let headers: Vec<(String, String)> = headers
+        .iter()
+        .map(|(name, value)| (name.to_lowercase(), value.trim().to_owned()))
+        .sorted()
+        .collect();
+
+    let amz_date = match headers.iter().find(|(name, _)| name == "x-amz-date") {
+        Some((_, amz_date)) if amz_date.len() > 8 => amz_date.clone(),
+        _ => anyhow::bail!("Valid x-amz-date header is required to sign the request"),
+    };
+    let date_stamp = &amz_date[..8];
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{value}\n"))
+        .collect();
+    let signed_headers = headers.iter().map(|(name, _)| name).join(";");
+
+    let canonical_request = format!(
+        "{method}\n{canonical_uri}\n{canonical_query}\n{canonical_headers}\n{signed_headers}\n{payload_hash}"
+    );
+    let scope = format!("{date_stamp}/{region}/{service}/aws4_request");
+    let string_to_sign = format!(
+        "{SIGNING_ALGORITHM}\n{amz_date}\n{scope}\n{}",
+        sha256_hex(canonical_request.as_bytes())
+    );
+
+    let date_key = hmac_sha256(
+        format!("AWS4{}", credentials.secret_access_key).as_bytes(),
+        date_stamp,
+    );
+    let region_key = hmac_sha256(&date_key, region);
+    let service_key = hmac_sha256(&region_key, service);
+    let signing_key = hmac_sha256(&service_key, "aws4_request");
+    let signature = hex_encode(&hmac_sha256(&signing_key, &string_to_sign));
+
+    Ok(format!(
+        "{SIGNING_ALGORITHM} Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+        credentials.access_key_id
+    ))
+}
+
+// Read credentials of the profile from shared credentials file (~/.aws/credentials)
+fn read_profile_credentials(profile: &str) -> Option<AwsCredentials> {
+    let path = std::env::var("AWS_SHARED_CREDENTIALS_FILE")
+        .ok()
+        .or(std::env::var("HOME")
+            .ok()
+            .map(|home| format!("{home}/.aws/credentials")))?;
+    let content = std::fs::read_to_string(path).ok()?;
+
+    let mut values = HashMap::new();
+    let mut in_profile = false;
+    for line in content.lines().map(|l| l.trim()) {
+        if line.starts_with('[') && line.ends_with(']') {
+            in_profile = line[1..line.len() - 1].trim() == profile;
+            continue;
+        }
+
+        if in_profile {
+            if let Some((key, value)) = line.split_once('=') {
+                values.insert(key.trim().to_owned(), value.trim().to_owned());
+            }
+        }
+    }
+
+    Some(AwsCredentials {
+        access_key_id: values.remove("aws_access_key_id")?,
+        secret_access_key: values.remove("aws_secret_access_key")?,
+        session_token: values.remove("aws_session_token"),
+    })
+}
+
+// Credentials from environment variables or shared credentials file, e.g. for object storage access
+pub fn get_default_credentials() -> Result<AwsCredentials, anyhow::Error> {
+    get_credentials(&BedrockRuntimeParams::default())
+}
+
+// Credentials are resolved in the following order
+// 1. Runtime params
+// 2. AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY environment variables
+// 3. Shared credentials file profile (from runtime params, AWS_PROFILE or default)
+fn get_credentials(runtime_params: &BedrockRuntimeParams) -> Result<AwsCredentials, anyhow::Error> {
+    if let (Some(access_key_id), Some(secret_access_key)) = (
+        &runtime_params.access_key_id,
+        &runtime_params.secret_access_key,
+    ) {
+        return Ok(AwsCredentials {
+            access_key_id: access_key_id.clone(),
+            secret_access_key: secret_access_key.clone(),
+            session_token: runtime_params.session_token.clone(),
+        });
+    }
+
+    if runtime_params.access_key_id.is_some() || runtime_params.secret_access_key.is_some() {
+        anyhow::bail!("Both 'access_key_id' and 'secret_access_key' should be specified");
+    }
+
+    if runtime_params.profile.is_none() {
+        if let (Ok(access_key_id), Ok(secret_access_key)) = (
+            std::env::var("AWS_ACCESS_KEY_ID"),
+            std::env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            return Ok(AwsCredentials {
+                access_key_id,
+                secret_access_key,
+                session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            });
+        }
+    }
+
+    let profile = runtime_params
+        .profile
+        .clone()
+        .or(std::env::var("AWS_PROFILE").ok())
+        .unwrap_or("default".to_owned());
+
+    match read_profile_credentials(&profile) {
+        Some(credentials) => Ok(credentials),
+        None => anyhow::bail!(
+            "AWS credentials not found. Specify 'access_key_id' and 'secret_access_key' in runtime params, set AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY environment variables or add profile '{profile}' to shared credentials file"
+        ),
+    }
+}
+
+impl<'a> BedrockRuntime<'a> {
+    pub fn new(logger: &'a LoggerFn, params: &str) -> Result<Self, anyhow::Error> {
+        let runtime_params: BedrockRuntimeParams = serde_json::from_str(params)?;
+
+        let region = match runtime_params
+            .region
+            .clone()
+            .or(std::env::var("AWS_REGION").ok())
+            .or(std::env::var("AWS_DEFAULT_REGION").ok())
+        {
+            Some(region) => region,
+            None => anyhow::bail!(
+                "'region' is required for Bedrock runtime. Specify it in runtime params or set AWS_REGION environment variable"
+            ),
+        };
+
+        let credentials = get_credentials(&runtime_params)?;
+
+        let input_type = runtime_params
+            .input_type
+            .unwrap_or("search_document".to_owned());
+        if !INPUT_TYPES.contains(&input_type.as_str()) {
+            anyhow::bail!(
+                "Invalid input_type '{input_type}'. Supported values: {}",
+                INPUT_TYPES.join(", ")
+            );
+        }
+
+        let truncate = runtime_params.truncate.unwrap_or("END".to_owned());
+        if !TRUNCATE_OPTIONS.contains(&truncate.as_str()) {
+            anyhow::bail!(
+                "Invalid truncate '{truncate}'. Supported values: {}",
+                TRUNCATE_OPTIONS.join(", ")
+            );
+        }
+
+        Ok(Self {
+            base_url: runtime_params
+                .base_url
+                .unwrap_or(format!("https://bedrock-runtime.{region}.amazonaws.com")),
+            logger,
+            request_timeout: 120,
+            region,
+            credentials,
+            dimensions: runtime_params.dimensions,
+            normalize: runtime_params.normalize.unwrap_or(true),
+            input_type,
+            truncate,
+        })
+    }
+
+    fn get_model_info<'b>(
+        model_map: &'b HashMap<&'static str, ModelInfo>,
+        model_name: &str,
+    ) -> Result<&'b ModelInfo, anyhow::Error> {
+        match model_map.get(model_name) {
+            Some(model_info) => Ok(model_info),
+            None => anyhow::bail!(
+                "Unsupported model {model_name}\nAvailable models: {}",
+                model_map.keys().join(", ")
+            ),
+        }
+    }
+
+    fn chunk_inputs(
+        &self,
+        model_info: &ModelInfo,
+        inputs: &Vec<&str>,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        if let Some(dimensions) = self.dimensions {
+            if !model_info.supported_dimensions.contains(&dimensions) {
+                anyhow::bail!(
+                    "Model {} does not support {dimensions} dimensions. Supported dimensions: {}",
+                    model_info.name,
+                    model_info.supported_dimensions.iter().join(", ")
+                );
+            }
+        }
+
+        let batch_tokens: Vec<String> = inputs
+            .chunks(model_info.max_batch_size)
+            .map(|token_group| match model_info.provider {
+                ModelProvider::Titan => {
+                    let mut body = serde_json::json!({ "inputText": token_group[0] });
+                    // Only v2 model accepts dimensions and normalize params
+                    if model_info.supported_dimensions.len() > 1 {
+                        body["dimensions"] =
+                            serde_json::json!(self.dimensions.unwrap_or(model_info.dimensions));
+                        body["normalize"] = serde_json::json!(self.normalize);
+                    }
+                    body.to_string()
+                }
+                ModelProvider::Cohere => serde_json::json!({
+                    "texts": token_group,
+                    "input_type": self.input_type,
+                    "truncate": self.truncate,
+                })
+                .to_string(),
+            })
+            .collect();
+
+        Ok(batch_tokens)
+    }
+
+    fn get_signed_headers(
+        &self,
+        url: &Url,
+        body: &str,
+        datetime: &DateTime<Utc>,
+    ) -> Result<Vec<(String, String)>, anyhow::Error> {
+        let host = match (url.host_str(), url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_owned(),
+            _ => anyhow::bail!("Invalid Bedrock url {url}"),
+        };
+        let amz_date = datetime.format("%Y%m%dT%H%M%SZ").to_string();
+
+        let mut headers = vec![
+            ("content-type".to_owned(), "application/json".to_owned()),
+            ("host".to_owned(), host),
+            ("x-amz-date".to_owned(), amz_date),
+        ];
+        if let Some(session_token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token".to_owned(), session_token.clone()));
+        }
+
+        // Path segments are encoded once in the url and once more in canonical uri
+        let canonical_uri = url.path().split('/').map(uri_encode).join("/");
+        let authorization = sign_request(
+            &self.credentials,
+            &self.region,
+            SIGNING_SERVICE,
+            "POST",
+            &canonical_uri,
+            &headers,
+            body,
+        )?;
+
+        // Host header is set by the http client
+        headers.retain(|(name, _)| name != "host");
+        headers.push(("authorization".to_owned(), authorization));
+        Ok(headers)
+    }
+
+    // Static functions
+    pub fn get_titan_response(body: Vec<u8>) -> Result<EmbeddingResult, anyhow::Error> {
+        let result: Result<TitanResponse, serde_json::Error> = serde_json::from_slice(&body);
+        if let Err(e) = result {
+            anyhow::bail!(
+                "Error: {e}. Bedrock response: {:?}",
+                serde_json::from_slice::<serde_json::Value>(&body)?
+            );
+        }
+
+        let result = result.unwrap();
+        Ok(EmbeddingResult {
+            embeddings: vec![result.embedding],
+            processed_tokens: result.input_text_token_count,
+        })
+    }
+
+    // Cohere models on Bedrock return token count only in response headers
+    // So the tokens are counted using the default tokenizer
+    pub fn get_cohere_response(body: Vec<u8>) -> Result<EmbeddingResult, anyhow::Error> {
+        let result: Result<CohereResponse, serde_json::Error> = serde_json::from_slice(&body);
+        if let Err(e) = result {
+            anyhow::bail!(
+                "Error: {e}. Bedrock response: {:?}",
+                serde_json::from_slice::<serde_json::Value>(&body)?
+            );
+        }
+
+        Ok(EmbeddingResult {
+            embeddings: result.unwrap().embeddings,
+            processed_tokens: 0,
+        })
+    }
+}
+
+impl<'a> EmbeddingRuntime for BedrockRuntime<'a> {
+    // Bedrock runtime does not use HTTPRuntime macro, as each request should be signed separately
+    fn process(
+        &self,
+        model_name: &str,
+        inputs: &Vec<&str>,
+    ) -> Result<EmbeddingResult, anyhow::Error> {
+        let model_map = MODEL_INFO_MAP.read().unwrap();
+        let model_info = Self::get_model_info(&model_map, model_name)?;
+        let request_bodies = self.chunk_inputs(model_info, inputs)?;
+        let url = Url::parse(&format!(
+            "{}/model/{}/invoke",
+            self.base_url.trim_end_matches('/'),
+            uri_encode(&model_info.name)
+        ))?;
+
+        let tokio_runtime = Runtime::new()?;
+        let client = Arc::new(
+            HttpClient::builder()
+                .timeout(Duration::from_secs(self.request_timeout))
+                .redirect_policy(RedirectPolicy::Limit(2))
+                .build()?,
+        );
+
+        let datetime = Utc::now();
+        let mut tasks = Vec::with_capacity(request_bodies.len());
+        for request_body in request_bodies {
+            let headers = self.get_signed_headers(&url, &request_body, &datetime)?;
+            tasks.push(tokio_runtime.spawn(post_with_retries(
+                client.clone(),
+                url.to_string(),
+                request_body,
+                headers,
+                match model_info.provider {
+                    ModelProvider::Titan => Box::new(Self::get_titan_response),
+                    ModelProvider::Cohere => Box::new(Self::get_cohere_response),
+                },
+                5,
+            )));
+        }
+
+        let mut result = tokio_runtime.block_on(async move {
+            let mut processed_tokens = 0;
+            let mut embeddings = Vec::with_capacity(inputs.len());
+            for task in tasks {
+                let embedding_response = task.await??;
+                processed_tokens += embedding_response.processed_tokens;
+                embeddings.extend(embedding_response.embeddings);
+            }
+            Ok::<EmbeddingResult, anyhow::Error>(EmbeddingResult {
+                embeddings,
+                processed_tokens,
+            })
+        })?;
+
+        if model_info.provider == ModelProvider::Cohere {
+            result.processed_tokens = inputs
+                .iter()
+                .map(|input| DEFAULT_TOKENIZER.encode_ordinary(input).len())
+                .sum();
+        }
+
+        Ok(result)
+    }
+
+    fn count_requests(&self, model_name: &str, inputs: &Vec<&str>) -> Result<usize, anyhow::Error> {
+        let model_map = MODEL_INFO_MAP.read().unwrap();
+        let model_info = Self::get_model_info(&model_map, model_name)?;
+        Ok(self.chunk_inputs(model_info, inputs)?.len())
+    }
+
+    fn get_available_models(&self) -> (String, Vec<(String, bool)>) {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        let mut res = String::new();
+        let mut models = Vec::with_capacity(map.len());
+        for (key, value) in &*map {
+            res.push_str(&format!(
+                "{} - sequence_len: {}, dimensions: {}\n",
+                key, value.sequence_len, value.dimensions
+            ));
+            models.push((key.to_string(), false));
+        }
+
+        (res, models)
+    }
+
+    fn get_models_info(&self) -> Vec<runtime::ModelInfo> {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        map.iter()
+            .map(|(key, value)| runtime::ModelInfo {
+                name: key.to_string(),
+                // Requests fail if the model does not support the dimensions from runtime params
+                dims: Some(self.dimensions.unwrap_or(value.dimensions)),
+                max_tokens: Some(value.sequence_len),
+                modality: Modality::Text,
+                provider: "bedrock".to_owned(),
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect()
+    }
+
+    fn get_region(&self) -> Option<String> {
+        Some(self.region.clone())
+    }
+}
@@ -2,7 +2,8 @@ use itertools::Itertools;
 use std::{collections::HashMap, sync::RwLock};
 
 use super::{
-    runtime::{EmbeddingResult, EmbeddingRuntime},
+    runtime::{self, EmbeddingResult, EmbeddingRuntime, Modality, RerankResult, RerankRuntime},
+    utils::post_with_retries_as,
     LoggerFn,
 };
 use crate::HTTPRuntime;
@@ -30,6 +31,24 @@ struct CohereResponse {
     meta: CohereMeta,
 }
 
+#[derive(Deserialize)]
+struct CohereRerankResult {
+    index: usize,
+    relevance_score: f32,
+}
+
+#[derive(Deserialize)]
+struct CohereRerankResponse {
+    results: Vec<CohereRerankResult>,
+}
+
+pub static RERANK_MODELS: [&'static str; 4] = [
+    "cohere/rerank-english-v3.0",
+    "cohere/rerank-multilingual-v3.0",
+    "cohere/rerank-english-v2.0",
+    "cohere/rerank-multilingual-v2.0",
+];
+
 impl ModelInfo {
     pub fn new(model_name: &str) -> Result<Self, anyhow::Error> {
         let name = model_name.split("/").last().unwrap().to_owned();
@@ -114,6 +133,8 @@ pub struct CohereRuntime<'a> {
     base_url: String,
     headers: Vec<(String, String)>,
     input_type: String,
+    truncate: String,
+    region: Option<String>,
     #[allow(dead_code)]
     logger: &'a LoggerFn,
 }
@@ -122,24 +143,66 @@ pub struct CohereRuntime<'a> {
 pub struct CohereRuntimeParams {
     pub api_token: Option<String>,
     pub input_type: Option<String>,
+    pub truncate: Option<String>,
+    pub base_url: Option<String>,
+    pub region: Option<String>,
 }
 
+pub static INPUT_TYPES: [&'static str; 4] = [
+    "search_document",
+    "search_query",
+    "classification",
+    "clustering",
+];
+pub static TRUNCATE_OPTIONS: [&'static str; 3] = ["NONE", "START", "END"];
+
 impl<'a> CohereRuntime<'a> {
-    pub fn new(logger: &'a LoggerFn, params: &'a str) -> Result<Self, anyhow::Error> {
+    pub fn new(logger: &'a LoggerFn, params: &str) -> Result<Self, anyhow::Error> {
         let runtime_params: CohereRuntimeParams = serde_json::from_str(&params)?;
 
         if runtime_params.api_token.is_none() {
-            anyhow::bail!("'api_token' is required for OpenAi runtime");
+            anyhow::bail!("'api_token' is required for Cohere runtime");
+        }
+
+        let input_type = runtime_params
+            .input_type
+            .unwrap_or("search_document".to_owned());
+        if !INPUT_TYPES.contains(&input_type.as_str()) {
+            anyhow::bail!(
+                "Invalid input_type '{input_type}'. Supported values: {}",
+                INPUT_TYPES.join(", ")
+            );
         }
 
+        let truncate = runtime_params.truncate.unwrap_or("END".to_owned());
+        if !TRUNCATE_OPTIONS.contains(&truncate.as_str()) {
+            anyhow::bail!(
+                "Invalid truncate '{truncate}'. Supported values: {}",
+                TRUNCATE_OPTIONS.join(", ")
+            );
+        }
+
+        // Public Cohere API is served from us region
+        // For other regions base_url of the regional deployment should be provided
+        let region = match (&runtime_params.base_url, runtime_params.region) {
+            (None, None) => Some("us".to_owned()),
+            (None, Some(region)) if region == "us" => Some(region),
+            (None, Some(region)) => {
+                anyhow::bail!("Cohere API is not available in region '{region}'. Specify 'base_url' of the regional deployment");
+            }
+            (Some(_), region) => region,
+        };
+
         Ok(Self {
-            base_url: "https://api.cohere.ai".to_owned(),
+            base_url: runtime_params
+                .base_url
+                .unwrap_or("https://api.cohere.ai".to_owned()),
             logger,
             request_timeout: 120,
             max_batch_size: 96,
-            input_type: runtime_params
-                .input_type
-                .unwrap_or("search_document".to_owned()),
+            input_type,
+            truncate,
+            region,
             headers: vec![
                 ("Content-Type".to_owned(), "application/json".to_owned()),
                 (
@@ -172,13 +235,14 @@ impl<'a> CohereRuntime<'a> {
             .map(|token_group| {
                 let json_string = serde_json::to_string(token_group).unwrap();
                 let input_type = &self.input_type;
+                let truncate = &self.truncate;
                 format!(
                     r#"
                  {{
                    "texts": {json_string},
                    "model": "{name}",
                    "input_type": "{input_type}",
-                   "truncate": "END"
+                   "truncate": "{truncate}"
                  }}
                 "#
                 )
@@ -205,6 +269,24 @@ impl<'a> CohereRuntime<'a> {
             processed_tokens: result.meta.billed_units.input_tokens,
         })
     }
+
+    // Results are sorted by relevance, so they are returned as (document index, score) pairs
+    pub fn get_rerank_response(body: Vec<u8>) -> Result<Vec<(usize, f32)>, anyhow::Error> {
+        let result: Result<CohereRerankResponse, serde_json::Error> = serde_json::from_slice(&body);
+        if let Err(e) = result {
+            anyhow::bail!(
+                "Error: {e}. Cohere response: {:?}",
+                serde_json::from_slice::<serde_json::Value>(&body)?
+            );
+        }
+
+        Ok(result
+            .unwrap()
+            .results
+            .into_iter()
+            .map(|result| (result.index, result.relevance_score))
+            .collect())
+    }
 }
 
 impl<'a> EmbeddingRuntime for CohereRuntime<'a> {
@@ -216,6 +298,10 @@ impl<'a> EmbeddingRuntime for CohereRuntime<'a> {
         self.post_request("/v1/embed", model_name, inputs)
     }
 
+    fn count_requests(&self, model_name: &str, inputs: &Vec<&str>) -> Result<usize, anyhow::Error> {
+        Ok(self.chunk_inputs(model_name, inputs)?.len())
+    }
+
     fn get_available_models(&self) -> (String, Vec<(String, bool)>) {
         let map = MODEL_INFO_MAP.read().unwrap();
         let mut res = String::new();
@@ -230,5 +316,67 @@ impl<'a> EmbeddingRuntime for CohereRuntime<'a> {
 
         return (res, models);
     }
+
+    fn get_models_info(&self) -> Vec<runtime::ModelInfo> {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        map.iter()
+            .map(|(key, value)| runtime::ModelInfo {
+                name: key.to_string(),
+                dims: Some(value.dimensions),
+                max_tokens: Some(value.sequence_len),
+                modality: Modality::Text,
+                provider: "cohere".to_owned(),
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect()
+    }
+
+    fn get_region(&self) -> Option<String> {
+        self.region.clone()
+    }
+}
+impl<'a> RerankRuntime for CohereRuntime<'a> {
+    fn rerank(
+        &self,
+        model_name: &str,
+        query: &str,
+        documents: &Vec<&str>,
+    ) -> Result<RerankResult, anyhow::Error> {
+        if !RERANK_MODELS.contains(&model_name) {
+            anyhow::bail!(
+                "Unsupported rerank model {model_name}\nAvailable models: {}",
+                RERANK_MODELS.join(", ")
+            );
+        }
+
+        let body = serde_json::json!({
+            "model": model_name.split("/").last().unwrap(),
+            "query": query,
+            "documents": documents,
+            "top_n": documents.len(),
+            "return_documents": false,
+        })
+        .to_string();
+        let url = Url::parse(&self.base_url)?.join("/v1/rerank")?.to_string();
+        let client = Arc::new(self.get_client()?);
+        let results: Vec<(usize, f32)> = Runtime::new()?.block_on(post_with_retries_as(
+            client,
+            url,
+            body,
+            Vec::new(),
+            Box::new(Self::get_rerank_response),
+            5,
+        ))?;
+
+        let mut scores = vec![0.0; documents.len()];
+        for (index, score) in results {
+            match scores.get_mut(index) {
+                Some(value) => *value = score,
+                None => anyhow::bail!("Cohere returned score of unknown document {index}"),
+            }
+        }
+
+        Ok(RerankResult { scores })
+    }
 }
 HTTPRuntime!(CohereRuntime);
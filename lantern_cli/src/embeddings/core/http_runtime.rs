@@ -55,6 +55,7 @@ macro_rules! HTTPRuntime {
                             client,
                             url,
                             request_body,
+                            Vec::new(),
                             Box::new($a::get_response),
                             5,
                         )
@@ -0,0 +1,250 @@
+use itertools::Itertools;
+use std::{collections::HashMap, sync::RwLock};
+
+use super::{
+    runtime::{self, EmbeddingResult, EmbeddingRuntime, Modality},
+    LoggerFn,
+};
+use crate::HTTPRuntime;
+use serde::{Deserialize, Serialize};
+
+struct ModelInfo {
+    name: String,
+    sequence_len: usize,
+    dimensions: usize,
+}
+
+#[derive(Deserialize)]
+struct JinaEmbedding {
+    embedding: Vec<f32>,
+    index: usize,
+}
+
+#[derive(Deserialize)]
+struct JinaUsage {
+    total_tokens: usize,
+}
+
+#[derive(Deserialize)]
+struct JinaResponse {
+    data: Vec<JinaEmbedding>,
+    usage: JinaUsage,
+}
+
+impl ModelInfo {
+    pub fn new(model_name: &str) -> Result<Self, anyhow::Error> {
+        let name = model_name.split("/").last().unwrap().to_owned();
+        match model_name {
+            "jina/jina-embeddings-v3" => Ok(Self {
+                name,
+                sequence_len: 8192,
+                dimensions: 1024,
+            }),
+            _ => anyhow::bail!("Unsupported model {model_name}"),
+        }
+    }
+}
+
+lazy_static! {
+    static ref MODEL_INFO_MAP: RwLock<HashMap<&'static str, ModelInfo>> =
+        RwLock::new(HashMap::from([(
+            "jina/jina-embeddings-v3",
+            ModelInfo::new("jina/jina-embeddings-v3").unwrap()
+        ),]));
+}
+
+pub struct JinaRuntime<'a> {
+    request_timeout: u64,
+    max_batch_size: usize,
+    base_url: String,
+    headers: Vec<(String, String)>,
+    task: String,
+    dimensions: Option<usize>,
+    late_chunking: bool,
+    region: Option<String>,
+    #[allow(dead_code)]
+    logger: &'a LoggerFn,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct JinaRuntimeParams {
+    pub api_token: Option<String>,
+    pub task: Option<String>,
+    pub dimensions: Option<usize>,
+    pub late_chunking: Option<bool>,
+    pub base_url: Option<String>,
+    pub region: Option<String>,
+}
+
+// jina-embeddings-v3 uses task specific LoRA adapters
+pub static TASKS: [&str; 5] = [
+    "retrieval.passage",
+    "retrieval.query",
+    "separation",
+    "classification",
+    "text-matching",
+];
+
+impl<'a> JinaRuntime<'a> {
+    pub fn new(logger: &'a LoggerFn, params: &str) -> Result<Self, anyhow::Error> {
+        let runtime_params: JinaRuntimeParams = serde_json::from_str(params)?;
+
+        if runtime_params.api_token.is_none() {
+            anyhow::bail!("'api_token' is required for Jina runtime");
+        }
+
+        let task = runtime_params
+            .task
+            .unwrap_or("retrieval.passage".to_owned());
+        if !TASKS.contains(&task.as_str()) {
+            anyhow::bail!(
+                "Invalid task '{task}'. Supported values: {}",
+                TASKS.join(", ")
+            );
+        }
+
+        if let Some(dimensions) = runtime_params.dimensions {
+            if !(32..=1024).contains(&dimensions) {
+                anyhow::bail!("'dimensions' should be between 32 and 1024 for Jina runtime");
+            }
+        }
+
+        // Region of the public Jina API is not declared, so it can be set only for custom deployments
+        let region = match (&runtime_params.base_url, runtime_params.region) {
+            (None, Some(_)) => {
+                anyhow::bail!(
+                    "'region' can be specified only with 'base_url' of the regional deployment"
+                );
+            }
+            (_, region) => region,
+        };
+
+        Ok(Self {
+            base_url: runtime_params
+                .base_url
+                .unwrap_or("https://api.jina.ai".to_owned()),
+            logger,
+            request_timeout: 120,
+            max_batch_size: 128,
+            task,
+            dimensions: runtime_params.dimensions,
+            late_chunking: runtime_params.late_chunking.unwrap_or(false),
+            region,
+            headers: vec![
+                ("Content-Type".to_owned(), "application/json".to_owned()),
+                (
+                    "Authorization".to_owned(),
+                    format!("Bearer {}", runtime_params.api_token.unwrap()),
+                ),
+            ],
+        })
+    }
+
+    fn chunk_inputs(
+        &self,
+        model_name: &str,
+        inputs: &[&str],
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let model_map = MODEL_INFO_MAP.read().unwrap();
+        let model_info = model_map.get(model_name);
+
+        if model_info.is_none() {
+            anyhow::bail!(
+                "Unsupported model {model_name}\nAvailable models: {}",
+                model_map.keys().join(", ")
+            );
+        }
+        let model_info = model_info.unwrap();
+
+        let batch_tokens: Vec<String> = inputs
+            .chunks(self.max_batch_size)
+            .map(|token_group| {
+                let mut body = serde_json::json!({
+                    "input": token_group,
+                    "model": model_info.name,
+                    "task": self.task,
+                    "late_chunking": self.late_chunking,
+                    "embedding_type": "float",
+                });
+                if let Some(dimensions) = self.dimensions {
+                    body["dimensions"] = dimensions.into();
+                }
+                body.to_string()
+            })
+            .collect();
+
+        Ok(batch_tokens)
+    }
+
+    // Static functions
+    pub fn get_response(body: Vec<u8>) -> Result<EmbeddingResult, anyhow::Error> {
+        let result: Result<JinaResponse, serde_json::Error> = serde_json::from_slice(&body);
+        if let Err(e) = result {
+            anyhow::bail!(
+                "Error: {e}. Jina response: {:?}",
+                serde_json::from_slice::<serde_json::Value>(&body)?
+            );
+        }
+
+        let mut result = result.unwrap();
+        result.data.sort_by_key(|embedding| embedding.index);
+
+        Ok(EmbeddingResult {
+            embeddings: result
+                .data
+                .into_iter()
+                .map(|embedding| embedding.embedding)
+                .collect(),
+            processed_tokens: result.usage.total_tokens,
+        })
+    }
+}
+
+impl<'a> EmbeddingRuntime for JinaRuntime<'a> {
+    fn process(
+        &self,
+        model_name: &str,
+        inputs: &Vec<&str>,
+    ) -> Result<EmbeddingResult, anyhow::Error> {
+        self.post_request("/v1/embeddings", model_name, inputs)
+    }
+
+    fn count_requests(&self, model_name: &str, inputs: &Vec<&str>) -> Result<usize, anyhow::Error> {
+        Ok(self.chunk_inputs(model_name, inputs)?.len())
+    }
+
+    fn get_available_models(&self) -> (String, Vec<(String, bool)>) {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        let mut res = String::new();
+        let mut models = Vec::with_capacity(map.len());
+        for (key, value) in &*map {
+            res.push_str(&format!(
+                "{} - sequence_len: {}, dimensions: {}\n",
+                key, value.sequence_len, value.dimensions
+            ));
+            models.push((key.to_string(), false));
+        }
+
+        (res, models)
+    }
+
+    fn get_models_info(&self) -> Vec<runtime::ModelInfo> {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        map.iter()
+            .map(|(key, value)| runtime::ModelInfo {
+                name: key.to_string(),
+                // Requests fail if the model does not support the dimensions from runtime params
+                dims: Some(self.dimensions.unwrap_or(value.dimensions)),
+                max_tokens: Some(value.sequence_len),
+                modality: Modality::Text,
+                provider: "jina".to_owned(),
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect()
+    }
+
+    fn get_region(&self) -> Option<String> {
+        self.region.clone()
+    }
+}
+HTTPRuntime!(JinaRuntime);
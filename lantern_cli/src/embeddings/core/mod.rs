@@ -1,27 +1,58 @@
+pub mod bedrock_runtime;
 pub mod cohere_runtime;
 pub mod http_runtime;
+pub mod jina_runtime;
+pub mod mistral_runtime;
+pub mod nomic_runtime;
+pub mod openai_compat_runtime;
 pub mod openai_runtime;
 pub mod ort_runtime;
 pub mod runtime;
+pub mod tei_runtime;
+pub mod tokenizer_cache;
 pub mod utils;
+pub mod vertex_runtime;
+pub mod voyage_runtime;
 
+use crate::utils::register_secret;
 use std::str::FromStr;
 use strum::{EnumIter, IntoEnumIterator};
 
+use bedrock_runtime::BedrockRuntime;
 use cohere_runtime::CohereRuntime;
+use jina_runtime::JinaRuntime;
+use mistral_runtime::MistralRuntime;
+use nomic_runtime::NomicRuntime;
+use openai_compat_runtime::OpenAiCompatRuntime;
 use openai_runtime::OpenAiRuntime;
 use ort_runtime::OrtRuntime;
-use runtime::EmbeddingRuntime;
+use runtime::{EmbeddingRuntime, RerankRuntime};
+use tei_runtime::TeiRuntime;
+use vertex_runtime::VertexRuntime;
+use voyage_runtime::VoyageRuntime;
 
-fn default_logger(text: &str) {
+pub fn default_logger(text: &str) {
     println!("{}", text);
 }
 
+// Used when embeddings are written to standard output
+pub fn stderr_logger(text: &str) {
+    eprintln!("{}", text);
+}
+
 #[derive(Debug, PartialEq, Clone, EnumIter)]
 pub enum Runtime {
     Ort,
     OpenAi,
     Cohere,
+    Vertex,
+    Bedrock,
+    Voyage,
+    Mistral,
+    Jina,
+    Nomic,
+    OpenAiCompat,
+    Tei,
 }
 
 pub type LoggerFn = fn(&str);
@@ -32,6 +63,14 @@ impl FromStr for Runtime {
             "ort" => Ok(Runtime::Ort),
             "openai" => Ok(Runtime::OpenAi),
             "cohere" => Ok(Runtime::Cohere),
+            "vertex" => Ok(Runtime::Vertex),
+            "bedrock" => Ok(Runtime::Bedrock),
+            "voyage" => Ok(Runtime::Voyage),
+            "mistral" => Ok(Runtime::Mistral),
+            "jina" => Ok(Runtime::Jina),
+            "nomic" => Ok(Runtime::Nomic),
+            "openai-compat" => Ok(Runtime::OpenAiCompat),
+            "tei" => Ok(Runtime::Tei),
             _ => anyhow::bail!("Invalid runtime {input}"),
         }
     }
@@ -50,15 +89,93 @@ impl ToString for Runtime {
             Runtime::Ort => "ort".to_owned(),
             Runtime::OpenAi => "openai".to_owned(),
             Runtime::Cohere => "cohere".to_owned(),
+            Runtime::Vertex => "vertex".to_owned(),
+            Runtime::Bedrock => "bedrock".to_owned(),
+            Runtime::Voyage => "voyage".to_owned(),
+            Runtime::Mistral => "mistral".to_owned(),
+            Runtime::Jina => "jina".to_owned(),
+            Runtime::Nomic => "nomic".to_owned(),
+            Runtime::OpenAiCompat => "openai-compat".to_owned(),
+            Runtime::Tei => "tei".to_owned(),
+        }
+    }
+}
+
+// Runtime param which receives the key from api_key_env or api_key_file
+fn get_api_key_param(
+    runtime: &Runtime,
+    params: &serde_json::Map<String, serde_json::Value>,
+) -> Option<&'static str> {
+    match runtime {
+        Runtime::OpenAi
+            if params.contains_key("azure_endpoint")
+                || params
+                    .get("base_url")
+                    .and_then(|url| url.as_str())
+                    .is_some_and(|url| url.contains(".openai.azure.com")) =>
+        {
+            Some("azure_api_token")
         }
+        Runtime::OpenAi
+        | Runtime::Cohere
+        | Runtime::Voyage
+        | Runtime::Mistral
+        | Runtime::Jina
+        | Runtime::Nomic => Some("api_token"),
+        Runtime::OpenAiCompat | Runtime::Tei => Some("api_key"),
+        Runtime::Vertex => Some("access_token"),
+        Runtime::Ort | Runtime::Bedrock => None,
     }
 }
 
+// Read the API key from the environment variable or file given with api_key_env or api_key_file,
+// so the key is not passed inline in --runtime-params and does not leak into shell history
+// and process listings. The key is masked in all log output
+pub fn resolve_api_key(runtime: &Runtime, params: &str) -> Result<String, anyhow::Error> {
+    if !params.contains("api_key_env") && !params.contains("api_key_file") {
+        return Ok(params.to_owned());
+    }
+
+    let mut params: serde_json::Map<String, serde_json::Value> = serde_json::from_str(params)?;
+    let key = match (params.remove("api_key_env"), params.remove("api_key_file")) {
+        (None, None) => return Ok(serde_json::Value::Object(params).to_string()),
+        (Some(_), Some(_)) => {
+            anyhow::bail!("Only one of 'api_key_env' and 'api_key_file' can be specified")
+        }
+        (Some(serde_json::Value::String(var)), None) => match std::env::var(&var) {
+            Ok(key) if !key.trim().is_empty() => key.trim().to_owned(),
+            _ => anyhow::bail!("Environment variable {var} from 'api_key_env' is not set"),
+        },
+        (None, Some(serde_json::Value::String(path))) => match std::fs::read_to_string(&path) {
+            Ok(key) if !key.trim().is_empty() => key.trim().to_owned(),
+            Ok(_) => anyhow::bail!("File {path} from 'api_key_file' is empty"),
+            Err(e) => anyhow::bail!("Can not read {path} from 'api_key_file': {e}"),
+        },
+        _ => anyhow::bail!("'api_key_env' and 'api_key_file' should be strings"),
+    };
+
+    let key_param = match get_api_key_param(runtime, &params) {
+        Some(key_param) => key_param,
+        None => anyhow::bail!(
+            "Runtime {} does not accept 'api_key_env' or 'api_key_file'",
+            runtime.to_string()
+        ),
+    };
+    if params.contains_key(key_param) {
+        anyhow::bail!("'{key_param}' can not be combined with 'api_key_env' or 'api_key_file'");
+    }
+
+    register_secret(&key);
+    params.insert(key_param.to_owned(), serde_json::Value::String(key));
+    Ok(serde_json::Value::Object(params).to_string())
+}
+
 pub fn get_runtime<'a>(
     runtime: &Runtime,
     logger: Option<&'a LoggerFn>,
-    params: &'a str,
+    params: &str,
 ) -> Result<Box<dyn EmbeddingRuntime + 'a>, anyhow::Error> {
+    let params = &resolve_api_key(runtime, params)?;
     Ok(match runtime {
         Runtime::Ort => Box::new(OrtRuntime::new(
             logger.unwrap_or(&(default_logger as LoggerFn)),
@@ -72,9 +189,84 @@ pub fn get_runtime<'a>(
             logger.unwrap_or(&(default_logger as LoggerFn)),
             params,
         )?),
+        Runtime::Vertex => Box::new(VertexRuntime::new(
+            logger.unwrap_or(&(default_logger as LoggerFn)),
+            params,
+        )?),
+        Runtime::Bedrock => Box::new(BedrockRuntime::new(
+            logger.unwrap_or(&(default_logger as LoggerFn)),
+            params,
+        )?),
+        Runtime::Voyage => Box::new(VoyageRuntime::new(
+            logger.unwrap_or(&(default_logger as LoggerFn)),
+            params,
+        )?),
+        Runtime::Mistral => Box::new(MistralRuntime::new(
+            logger.unwrap_or(&(default_logger as LoggerFn)),
+            params,
+        )?),
+        Runtime::Jina => Box::new(JinaRuntime::new(
+            logger.unwrap_or(&(default_logger as LoggerFn)),
+            params,
+        )?),
+        Runtime::Nomic => Box::new(NomicRuntime::new(
+            logger.unwrap_or(&(default_logger as LoggerFn)),
+            params,
+        )?),
+        Runtime::OpenAiCompat => Box::new(OpenAiCompatRuntime::new(
+            logger.unwrap_or(&(default_logger as LoggerFn)),
+            params,
+        )?),
+        Runtime::Tei => Box::new(TeiRuntime::new(
+            logger.unwrap_or(&(default_logger as LoggerFn)),
+            params,
+        )?),
+    })
+}
+
+// Only runtimes with cross-encoder models support reranking
+pub fn get_rerank_runtime<'a>(
+    runtime: &Runtime,
+    logger: Option<&'a LoggerFn>,
+    params: &str,
+) -> Result<Box<dyn RerankRuntime + 'a>, anyhow::Error> {
+    let params = &resolve_api_key(runtime, params)?;
+    Ok(match runtime {
+        Runtime::Ort => Box::new(OrtRuntime::new(
+            logger.unwrap_or(&(default_logger as LoggerFn)),
+            params,
+        )?),
+        Runtime::Cohere => Box::new(CohereRuntime::new(
+            logger.unwrap_or(&(default_logger as LoggerFn)),
+            params,
+        )?),
+        _ => anyhow::bail!(
+            "Runtime {} does not support reranking. Supported runtimes: ort, cohere",
+            runtime.to_string()
+        ),
     })
 }
 
+impl Runtime {
+    // Vertex and Bedrock can use the default cloud credentials of the environment,
+    // and self-hosted servers are usually called without a key
+    pub fn requires_api_key(&self) -> bool {
+        match self {
+            Runtime::OpenAi
+            | Runtime::Cohere
+            | Runtime::Voyage
+            | Runtime::Mistral
+            | Runtime::Jina
+            | Runtime::Nomic => true,
+            Runtime::Ort
+            | Runtime::Vertex
+            | Runtime::Bedrock
+            | Runtime::OpenAiCompat
+            | Runtime::Tei => false,
+        }
+    }
+}
+
 pub fn get_available_runtimes() -> Vec<String> {
     Runtime::iter().map(|e| e.to_string()).collect()
 }
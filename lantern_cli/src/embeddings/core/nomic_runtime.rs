@@ -0,0 +1,233 @@
+use itertools::Itertools;
+use std::{collections::HashMap, sync::RwLock};
+
+use super::{
+    runtime::{self, EmbeddingResult, EmbeddingRuntime, Modality},
+    LoggerFn,
+};
+use crate::HTTPRuntime;
+use serde::{Deserialize, Serialize};
+
+struct ModelInfo {
+    name: String,
+    sequence_len: usize,
+    dimensions: usize,
+}
+
+#[derive(Deserialize)]
+struct NomicUsage {
+    total_tokens: usize,
+}
+
+#[derive(Deserialize)]
+struct NomicResponse {
+    embeddings: Vec<Vec<f32>>,
+    usage: NomicUsage,
+}
+
+impl ModelInfo {
+    pub fn new(model_name: &str) -> Result<Self, anyhow::Error> {
+        let name = model_name.split("/").last().unwrap().to_owned();
+        match model_name {
+            "nomic/nomic-embed-text-v1.5" => Ok(Self {
+                name,
+                sequence_len: 8192,
+                dimensions: 768,
+            }),
+            _ => anyhow::bail!("Unsupported model {model_name}"),
+        }
+    }
+}
+
+lazy_static! {
+    static ref MODEL_INFO_MAP: RwLock<HashMap<&'static str, ModelInfo>> =
+        RwLock::new(HashMap::from([(
+            "nomic/nomic-embed-text-v1.5",
+            ModelInfo::new("nomic/nomic-embed-text-v1.5").unwrap()
+        ),]));
+}
+
+pub struct NomicRuntime<'a> {
+    request_timeout: u64,
+    max_batch_size: usize,
+    base_url: String,
+    headers: Vec<(String, String)>,
+    task_type: String,
+    dimensionality: Option<usize>,
+    region: Option<String>,
+    #[allow(dead_code)]
+    logger: &'a LoggerFn,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct NomicRuntimeParams {
+    pub api_token: Option<String>,
+    pub task_type: Option<String>,
+    #[serde(alias = "dimensionality")]
+    pub dimensions: Option<usize>,
+    pub base_url: Option<String>,
+    pub region: Option<String>,
+}
+
+// Hosted API adds the task prefix (search_document: ...) expected by the model to each text
+pub static TASK_TYPES: [&str; 4] = [
+    "search_document",
+    "search_query",
+    "clustering",
+    "classification",
+];
+
+impl<'a> NomicRuntime<'a> {
+    pub fn new(logger: &'a LoggerFn, params: &str) -> Result<Self, anyhow::Error> {
+        let runtime_params: NomicRuntimeParams = serde_json::from_str(params)?;
+
+        if runtime_params.api_token.is_none() {
+            anyhow::bail!("'api_token' is required for Nomic runtime");
+        }
+
+        let task_type = runtime_params
+            .task_type
+            .unwrap_or("search_document".to_owned());
+        if !TASK_TYPES.contains(&task_type.as_str()) {
+            anyhow::bail!(
+                "Invalid task_type '{task_type}'. Supported values: {}",
+                TASK_TYPES.join(", ")
+            );
+        }
+
+        if let Some(dimensions) = runtime_params.dimensions {
+            if !(64..=768).contains(&dimensions) {
+                anyhow::bail!("'dimensions' should be between 64 and 768 for Nomic runtime");
+            }
+        }
+
+        // Public Nomic API is served from us region
+        let region = match (&runtime_params.base_url, runtime_params.region) {
+            (None, None) => Some("us".to_owned()),
+            (None, Some(region)) if region == "us" => Some(region),
+            (None, Some(region)) => {
+                anyhow::bail!("Nomic API is not available in region '{region}'. Specify 'base_url' of the regional deployment");
+            }
+            (Some(_), region) => region,
+        };
+
+        Ok(Self {
+            base_url: runtime_params
+                .base_url
+                .unwrap_or("https://api-atlas.nomic.ai".to_owned()),
+            logger,
+            request_timeout: 120,
+            max_batch_size: 128,
+            task_type,
+            dimensionality: runtime_params.dimensions,
+            region,
+            headers: vec![
+                ("Content-Type".to_owned(), "application/json".to_owned()),
+                (
+                    "Authorization".to_owned(),
+                    format!("Bearer {}", runtime_params.api_token.unwrap()),
+                ),
+            ],
+        })
+    }
+
+    fn chunk_inputs(
+        &self,
+        model_name: &str,
+        inputs: &[&str],
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let model_map = MODEL_INFO_MAP.read().unwrap();
+        let model_info = model_map.get(model_name);
+
+        if model_info.is_none() {
+            anyhow::bail!(
+                "Unsupported model {model_name}\nAvailable models: {}",
+                model_map.keys().join(", ")
+            );
+        }
+        let model_info = model_info.unwrap();
+
+        let batch_tokens: Vec<String> = inputs
+            .chunks(self.max_batch_size)
+            .map(|token_group| {
+                let mut body = serde_json::json!({
+                    "texts": token_group,
+                    "model": model_info.name,
+                    "task_type": self.task_type,
+                });
+                if let Some(dimensionality) = self.dimensionality {
+                    body["dimensionality"] = dimensionality.into();
+                }
+                body.to_string()
+            })
+            .collect();
+
+        Ok(batch_tokens)
+    }
+
+    // Static functions
+    pub fn get_response(body: Vec<u8>) -> Result<EmbeddingResult, anyhow::Error> {
+        let result: Result<NomicResponse, serde_json::Error> = serde_json::from_slice(&body);
+        if let Err(e) = result {
+            anyhow::bail!(
+                "Error: {e}. Nomic response: {:?}",
+                serde_json::from_slice::<serde_json::Value>(&body)?
+            );
+        }
+
+        let result = result.unwrap();
+
+        Ok(EmbeddingResult {
+            embeddings: result.embeddings,
+            processed_tokens: result.usage.total_tokens,
+        })
+    }
+}
+
+impl<'a> EmbeddingRuntime for NomicRuntime<'a> {
+    fn process(
+        &self,
+        model_name: &str,
+        inputs: &Vec<&str>,
+    ) -> Result<EmbeddingResult, anyhow::Error> {
+        self.post_request("/v1/embedding/text", model_name, inputs)
+    }
+
+    fn count_requests(&self, model_name: &str, inputs: &Vec<&str>) -> Result<usize, anyhow::Error> {
+        Ok(self.chunk_inputs(model_name, inputs)?.len())
+    }
+
+    fn get_available_models(&self) -> (String, Vec<(String, bool)>) {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        let mut res = String::new();
+        let mut models = Vec::with_capacity(map.len());
+        for (key, value) in &*map {
+            res.push_str(&format!(
+                "{} - sequence_len: {}, dimensions: {}\n",
+                key, value.sequence_len, value.dimensions
+            ));
+            models.push((key.to_string(), false));
+        }
+
+        (res, models)
+    }
+
+    fn get_models_info(&self) -> Vec<runtime::ModelInfo> {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        map.iter()
+            .map(|(key, value)| runtime::ModelInfo {
+                name: key.to_string(),
+                dims: Some(value.dimensions),
+                max_tokens: Some(value.sequence_len),
+                modality: Modality::Text,
+                provider: "nomic".to_owned(),
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect()
+    }
+
+    fn get_region(&self) -> Option<String> {
+        self.region.clone()
+    }
+}
+HTTPRuntime!(NomicRuntime);
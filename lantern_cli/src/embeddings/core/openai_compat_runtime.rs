@@ -0,0 +1,208 @@
+use super::{
+    runtime::{self, EmbeddingResult, EmbeddingRuntime, Modality},
+    LoggerFn,
+};
+use crate::HTTPRuntime;
+use serde::{Deserialize, Serialize};
+
+#[derive(Deserialize)]
+struct OpenAiCompatEmbedding {
+    embedding: Vec<f32>,
+    index: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiCompatUsage {
+    total_tokens: Option<usize>,
+    prompt_tokens: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct OpenAiCompatResponse {
+    data: Vec<OpenAiCompatEmbedding>,
+    usage: Option<OpenAiCompatUsage>,
+}
+
+// Runtime for self-hosted servers implementing OpenAI embeddings API (vLLM, TEI, LocalAI, LM Studio, ...)
+// Models are served by the server, so the model name is passed as is and is not validated
+pub struct OpenAiCompatRuntime<'a> {
+    request_timeout: u64,
+    max_batch_size: usize,
+    base_url: String,
+    headers: Vec<(String, String)>,
+    model: Option<String>,
+    dimensions: Option<usize>,
+    region: Option<String>,
+    #[allow(dead_code)]
+    logger: &'a LoggerFn,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct OpenAiCompatRuntimeParams {
+    pub base_url: Option<String>,
+    pub api_key: Option<String>,
+    pub model: Option<String>,
+    pub dimensions: Option<usize>,
+    pub max_batch_size: Option<usize>,
+    pub request_timeout: Option<u64>,
+    pub region: Option<String>,
+}
+
+impl<'a> OpenAiCompatRuntime<'a> {
+    pub fn new(logger: &'a LoggerFn, params: &str) -> Result<Self, anyhow::Error> {
+        let runtime_params: OpenAiCompatRuntimeParams = serde_json::from_str(params)?;
+
+        let base_url = match &runtime_params.base_url {
+            Some(base_url) => Self::get_embeddings_url(base_url)?,
+            None => anyhow::bail!("'base_url' is required for OpenAI compatible runtime"),
+        };
+
+        if runtime_params.max_batch_size == Some(0) {
+            anyhow::bail!("'max_batch_size' should be greater than 0");
+        }
+
+        let mut headers = vec![("Content-Type".to_owned(), "application/json".to_owned())];
+        // Local servers usually do not require authentication
+        if let Some(api_key) = runtime_params.api_key {
+            headers.push(("Authorization".to_owned(), format!("Bearer {api_key}")));
+        }
+
+        Ok(Self {
+            base_url,
+            logger,
+            request_timeout: runtime_params.request_timeout.unwrap_or(120),
+            max_batch_size: runtime_params.max_batch_size.unwrap_or(64),
+            headers,
+            model: runtime_params.model,
+            dimensions: runtime_params.dimensions,
+            region: runtime_params.region,
+        })
+    }
+
+    // Accept server root, /v1 or full embeddings endpoint url
+    // e.g http://localhost:8000 -> http://localhost:8000/v1/embeddings
+    fn get_embeddings_url(base_url: &str) -> Result<String, anyhow::Error> {
+        let url = url::Url::parse(base_url)?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            anyhow::bail!("Invalid base url for OpenAI compatible runtime: {base_url}");
+        }
+
+        let base_url = base_url.trim_end_matches('/');
+        Ok(if base_url.ends_with("/embeddings") {
+            base_url.to_owned()
+        } else if base_url.ends_with("/v1") {
+            format!("{base_url}/embeddings")
+        } else {
+            format!("{base_url}/v1/embeddings")
+        })
+    }
+
+    fn chunk_inputs(
+        &self,
+        model_name: &str,
+        inputs: &[&str],
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let model = self.model.as_deref().unwrap_or(model_name);
+
+        let batch_tokens: Vec<String> = inputs
+            .chunks(self.max_batch_size)
+            .map(|token_group| {
+                let mut body = serde_json::json!({
+                    "input": token_group,
+                    "model": model,
+                    "encoding_format": "float",
+                });
+                if let Some(dimensions) = self.dimensions {
+                    body["dimensions"] = dimensions.into();
+                }
+                body.to_string()
+            })
+            .collect();
+
+        Ok(batch_tokens)
+    }
+
+    // Static functions
+    pub fn get_response(body: Vec<u8>) -> Result<EmbeddingResult, anyhow::Error> {
+        let result: Result<OpenAiCompatResponse, serde_json::Error> = serde_json::from_slice(&body);
+        if let Err(e) = result {
+            anyhow::bail!(
+                "Error: {e}. Server response: {:?}",
+                serde_json::from_slice::<serde_json::Value>(&body)?
+            );
+        }
+
+        let mut result = result.unwrap();
+        result
+            .data
+            .sort_by_key(|embedding| embedding.index.unwrap_or(0));
+
+        // Some servers do not report usage, in that case processed tokens will be 0
+        let processed_tokens = result
+            .usage
+            .and_then(|usage| usage.total_tokens.or(usage.prompt_tokens))
+            .unwrap_or(0);
+
+        Ok(EmbeddingResult {
+            embeddings: result
+                .data
+                .into_iter()
+                .map(|embedding| embedding.embedding)
+                .collect(),
+            processed_tokens,
+        })
+    }
+}
+
+impl<'a> EmbeddingRuntime for OpenAiCompatRuntime<'a> {
+    fn process(
+        &self,
+        model_name: &str,
+        inputs: &Vec<&str>,
+    ) -> Result<EmbeddingResult, anyhow::Error> {
+        self.post_request("", model_name, inputs)
+    }
+
+    fn count_requests(&self, model_name: &str, inputs: &Vec<&str>) -> Result<usize, anyhow::Error> {
+        Ok(self.chunk_inputs(model_name, inputs)?.len())
+    }
+
+    fn get_available_models(&self) -> (String, Vec<(String, bool)>) {
+        match &self.model {
+            Some(model) => (
+                format!("{model} - served by {}\n", self.base_url),
+                vec![(model.clone(), false)],
+            ),
+            None => (
+                format!(
+                    "Any model served by {} can be used with --model\n",
+                    self.base_url
+                ),
+                Vec::new(),
+            ),
+        }
+    }
+
+    fn get_models_info(&self) -> Vec<runtime::ModelInfo> {
+        self.model
+            .iter()
+            .filter_map(|model| self.get_model_info(model))
+            .collect()
+    }
+
+    // Any model name is passed to the server, dimensions are known only if set in params
+    fn get_model_info(&self, model_name: &str) -> Option<runtime::ModelInfo> {
+        Some(runtime::ModelInfo {
+            name: model_name.to_owned(),
+            dims: self.dimensions,
+            max_tokens: None,
+            modality: Modality::Text,
+            provider: "openai-compat".to_owned(),
+        })
+    }
+
+    fn get_region(&self) -> Option<String> {
+        self.region.clone()
+    }
+}
+HTTPRuntime!(OpenAiCompatRuntime);
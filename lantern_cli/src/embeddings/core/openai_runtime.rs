@@ -3,16 +3,17 @@ use regex::Regex;
 use std::{collections::HashMap, sync::RwLock};
 
 use super::{
-    runtime::{EmbeddingResult, EmbeddingRuntime},
+    runtime::{self, EmbeddingResult, EmbeddingRuntime, Modality},
+    tokenizer_cache::get_cl100k_base,
     LoggerFn,
 };
 use crate::HTTPRuntime;
 use serde::{Deserialize, Serialize};
-use tiktoken_rs::{cl100k_base, CoreBPE};
+use tiktoken_rs::CoreBPE;
 
 struct ModelInfo {
     name: String,
-    tokenizer: CoreBPE,
+    tokenizer: Arc<CoreBPE>,
     sequence_len: usize,
     dimensions: usize,
     var_dimension: bool,
@@ -39,7 +40,14 @@ enum OpenAiDeployment {
     OpenAi,
 }
 
-static AZURE_OPENAI_REGEX: &'static str = r"^https:\/\/[a-zA-Z0-9_\-]+\.openai\.azure\.com\/openai\/deployments\/[a-zA-Z0-9_\-]+\/embeddings\?api-version=2023-05-15$";
+static AZURE_OPENAI_REGEX: &'static str = r"^https:\/\/[a-zA-Z0-9_\-]+\.openai\.azure\.com\/openai\/deployments\/[a-zA-Z0-9_\-]+\/embeddings\?api-version=[0-9]{4}-[0-9]{2}-[0-9]{2}(-preview)?$";
+static AZURE_ENDPOINT_REGEX: &'static str = r"^https:\/\/[a-zA-Z0-9_\-]+\.openai\.azure\.com\/?$";
+static AZURE_DEFAULT_API_VERSION: &'static str = "2023-05-15";
+static AZURE_REGION_REGEX: &'static str = r"^[a-z0-9]+$";
+static OPENAI_REGIONS: [(&'static str, &'static str); 2] = [
+    ("us", "https://api.openai.com/v1/embeddings"),
+    ("eu", "https://eu.api.openai.com/v1/embeddings"),
+];
 
 impl ModelInfo {
     pub fn new(model_name: &str) -> Result<Self, anyhow::Error> {
@@ -47,21 +55,21 @@ impl ModelInfo {
         match model_name {
             "openai/text-embedding-ada-002" => Ok(Self {
                 name,
-                tokenizer: cl100k_base()?,
+                tokenizer: get_cl100k_base()?,
                 sequence_len: 8190,
                 dimensions: 1536,
                 var_dimension: false,
             }),
             "openai/text-embedding-3-small" => Ok(Self {
                 name,
-                tokenizer: cl100k_base()?,
+                tokenizer: get_cl100k_base()?,
                 sequence_len: 8190,
                 dimensions: 1536,
                 var_dimension: true,
             }),
             "openai/text-embedding-3-large" => Ok(Self {
                 name,
-                tokenizer: cl100k_base()?,
+                tokenizer: get_cl100k_base()?,
                 sequence_len: 8190,
                 dimensions: 3072,
                 var_dimension: true,
@@ -94,6 +102,7 @@ pub struct OpenAiRuntime<'a> {
     base_url: String,
     headers: Vec<(String, String)>,
     dimensions: Option<usize>,
+    region: Option<String>,
     #[allow(dead_code)]
     logger: &'a LoggerFn,
 }
@@ -105,13 +114,22 @@ pub struct OpenAiRuntimeParams {
     pub azure_api_token: Option<String>,
     pub azure_entra_token: Option<String>,
     pub dimensions: Option<usize>,
+    pub region: Option<String>,
+    pub azure_endpoint: Option<String>,
+    pub deployment: Option<String>,
+    #[serde(alias = "api-version")]
+    pub api_version: Option<String>,
 }
 
 impl<'a> OpenAiRuntime<'a> {
-    pub fn new(logger: &'a LoggerFn, params: &'a str) -> Result<Self, anyhow::Error> {
+    pub fn new(logger: &'a LoggerFn, params: &str) -> Result<Self, anyhow::Error> {
         let runtime_params: OpenAiRuntimeParams = serde_json::from_str(&params)?;
 
-        let (deployment, base_url) = Self::get_base_url(&runtime_params.base_url)?;
+        let base_url = match Self::get_azure_deployment_url(&runtime_params)? {
+            Some(url) => Some(url),
+            None => runtime_params.base_url.clone(),
+        };
+        let (deployment, base_url, region) = Self::get_base_url(&base_url, &runtime_params.region)?;
 
         let auth_header = match deployment {
             OpenAiDeployment::OpenAi => {
@@ -153,16 +171,69 @@ impl<'a> OpenAiRuntime<'a> {
                 auth_header,
             ],
             dimensions: runtime_params.dimensions,
+            region,
         })
     }
 
+    // Build Azure deployment url from endpoint, deployment and api version params
+    // e.g https://{resource}.openai.azure.com/openai/deployments/{deployment}/embeddings?api-version={api_version}
+    fn get_azure_deployment_url(
+        runtime_params: &OpenAiRuntimeParams,
+    ) -> Result<Option<String>, anyhow::Error> {
+        let endpoint = match &runtime_params.azure_endpoint {
+            Some(endpoint) => endpoint,
+            None => {
+                if runtime_params.deployment.is_some() || runtime_params.api_version.is_some() {
+                    anyhow::bail!("'azure_endpoint' is required when 'deployment' or 'api_version' is specified");
+                }
+                return Ok(None);
+            }
+        };
+
+        if runtime_params.base_url.is_some() {
+            anyhow::bail!("'base_url' and 'azure_endpoint' can not be used together");
+        }
+
+        let azure_endpoint_re = Regex::new(AZURE_ENDPOINT_REGEX).unwrap();
+        if !azure_endpoint_re.is_match(endpoint) {
+            anyhow::bail!("Invalid Azure endpoint {endpoint}. Endpoint should be in format https://{{resource}}.openai.azure.com");
+        }
+
+        let deployment = match &runtime_params.deployment {
+            Some(deployment) => deployment,
+            None => anyhow::bail!("'deployment' is required for Azure OpenAi runtime"),
+        };
+
+        Ok(Some(format!(
+            "{}/openai/deployments/{deployment}/embeddings?api-version={}",
+            endpoint.trim_end_matches('/'),
+            runtime_params
+                .api_version
+                .as_deref()
+                .unwrap_or(AZURE_DEFAULT_API_VERSION)
+        )))
+    }
+
     fn get_base_url(
         base_url: &Option<String>,
-    ) -> Result<(OpenAiDeployment, String), anyhow::Error> {
+        region: &Option<String>,
+    ) -> Result<(OpenAiDeployment, String, Option<String>), anyhow::Error> {
         if base_url.is_none() {
+            // OpenAi API endpoint is selected by region
+            let region = region.as_deref().unwrap_or("us");
+            let base_url = OPENAI_REGIONS.iter().find(|(name, _)| *name == region);
+
+            if base_url.is_none() {
+                anyhow::bail!(
+                    "Invalid region '{region}' for OpenAi runtime. Supported regions: {}",
+                    OPENAI_REGIONS.iter().map(|(name, _)| name).join(", ")
+                );
+            }
+
             return Ok((
                 OpenAiDeployment::OpenAi,
-                "https://api.openai.com/v1/embeddings".to_owned(),
+                base_url.unwrap().1.to_owned(),
+                Some(region.to_owned()),
             ));
         }
 
@@ -170,7 +241,14 @@ impl<'a> OpenAiRuntime<'a> {
         let azure_openai_re = Regex::new(AZURE_OPENAI_REGEX).unwrap();
 
         if azure_openai_re.is_match(base_url) {
-            return Ok((OpenAiDeployment::Azure, base_url.clone()));
+            // Azure deployment url does not contain region, so it should be declared in params
+            if let Some(region) = region {
+                let azure_region_re = Regex::new(AZURE_REGION_REGEX).unwrap();
+                if !azure_region_re.is_match(region) {
+                    anyhow::bail!("Invalid Azure region '{region}'. Region should be in format like 'westeurope'");
+                }
+            }
+            return Ok((OpenAiDeployment::Azure, base_url.clone(), region.clone()));
         }
 
         anyhow::bail!("Invalid base url for OpenAi Runtime: {base_url}");
@@ -236,10 +314,23 @@ impl<'a> OpenAiRuntime<'a> {
             .collect();
 
         // Dimensions for new openai models can be specified
-        let dimensions_input = if model_info.var_dimension && self.dimensions.is_some() {
-            format!(r#", "dimensions": {}"#, self.dimensions.as_ref().unwrap())
-        } else {
-            "".to_owned()
+        let dimensions_input = match self.dimensions {
+            Some(dimensions) if model_info.var_dimension => {
+                if dimensions == 0 || dimensions > model_info.dimensions {
+                    anyhow::bail!(
+                        "Dimensions for model {model_name} should be between 1 and {}",
+                        model_info.dimensions
+                    );
+                }
+                format!(r#", "dimensions": {dimensions}"#)
+            }
+            Some(dimensions) if dimensions != model_info.dimensions => {
+                anyhow::bail!(
+                    "Model {model_name} does not support custom dimensions (dimensions: {})",
+                    model_info.dimensions
+                );
+            }
+            _ => "".to_owned(),
         };
 
         let name = &model_info.name;
@@ -295,6 +386,10 @@ impl<'a> EmbeddingRuntime for OpenAiRuntime<'a> {
         self.post_request("", model_name, inputs)
     }
 
+    fn count_requests(&self, model_name: &str, inputs: &Vec<&str>) -> Result<usize, anyhow::Error> {
+        Ok(self.chunk_inputs(model_name, inputs)?.len())
+    }
+
     fn get_available_models(&self) -> (String, Vec<(String, bool)>) {
         let map = MODEL_INFO_MAP.read().unwrap();
         let mut res = String::new();
@@ -309,5 +404,49 @@ impl<'a> EmbeddingRuntime for OpenAiRuntime<'a> {
 
         return (res, models);
     }
+
+    fn get_models_info(&self) -> Vec<runtime::ModelInfo> {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        map.iter()
+            .map(|(key, value)| runtime::ModelInfo {
+                name: key.to_string(),
+                // Requests fail if the model does not support the dimensions from runtime params
+                dims: Some(self.dimensions.unwrap_or(value.dimensions)),
+                max_tokens: Some(value.sequence_len),
+                modality: Modality::Text,
+                provider: "openai".to_owned(),
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect()
+    }
+
+    fn get_region(&self) -> Option<String> {
+        self.region.clone()
+    }
+
+    fn count_tokens(
+        &self,
+        model_name: &str,
+        inputs: &Vec<&str>,
+    ) -> Result<Vec<usize>, anyhow::Error> {
+        let model_map = MODEL_INFO_MAP.read().unwrap();
+        let model_info = model_map.get(model_name);
+
+        if model_info.is_none() {
+            anyhow::bail!(
+                "Unsupported model {model_name}\nAvailable models: {}",
+                model_map.keys().join(", ")
+            );
+        }
+
+        let model_info = model_info.unwrap();
+        Ok(inputs
+            .iter()
+            .map(|input| {
+                let token_cnt = model_info.tokenizer.encode_with_special_tokens(input).len();
+                std::cmp::min(token_cnt, model_info.sequence_len)
+            })
+            .collect())
+    }
 }
 HTTPRuntime!(OpenAiRuntime);
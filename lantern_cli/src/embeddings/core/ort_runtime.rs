@@ -3,15 +3,20 @@ use image::{imageops::FilterType, io::Reader as ImageReader, GenericImageView};
 use isahc::{config::RedirectPolicy, prelude::*, HttpClient};
 use itertools::Itertools;
 use ndarray::{s, Array2, Array4, ArrayBase, Axis, CowArray, CowRepr, Dim, IxDynImpl};
+use ort::execution_providers::{
+    CUDAExecutionProviderOptions, DirectMLExecutionProviderOptions, ROCmExecutionProviderOptions,
+};
 use ort::session::Session;
 use ort::tensor::ort_owned_tensor::ViewHolder;
 use ort::{Environment, ExecutionProvider, GraphOptimizationLevel, SessionBuilder, Value};
 use serde::Deserialize;
 use std::{
     cmp,
-    collections::HashMap,
+    collections::{HashMap, HashSet},
+    fmt,
     io::Cursor,
     path::{Path, PathBuf},
+    str::FromStr,
     sync::{Arc, Mutex, RwLock},
     time::Duration,
 };
@@ -20,8 +25,14 @@ use tokenizers::{PaddingParams, Tokenizer, TruncationParams};
 use tokio::{fs, runtime};
 use url::Url;
 
-use super::runtime::{EmbeddingResult, EmbeddingRuntime};
-use super::utils::{download_file, get_available_memory, percent_gpu_memory_used};
+use super::runtime::{
+    EmbeddingResult, EmbeddingRuntime, Modality, RerankResult, RerankRuntime, DEFAULT_TOKENIZER,
+};
+use super::tokenizer_cache::HF_TOKENIZER_CACHE;
+use super::utils::{
+    download_file, get_available_memory, get_gpu_count, get_gpu_free_memory,
+    percent_gpu_memory_used,
+};
 use super::LoggerFn;
 
 type SessionInput<'a> = ArrayBase<CowRepr<'a, i64>, Dim<IxDynImpl>>;
@@ -94,6 +105,224 @@ impl PoolingStrategy {
     }
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum Device {
+    // Use the first available of CUDA, OpenVINO and CPU
+    Auto,
+    Cpu,
+    Cuda(u32),
+    Rocm(u32),
+    CoreMl,
+    DirectMl(u32),
+    OpenVino,
+}
+
+impl FromStr for Device {
+    type Err = anyhow::Error;
+    fn from_str(input: &str) -> Result<Device, anyhow::Error> {
+        let (name, index) = match input.split_once(':') {
+            Some((name, index)) => match index.parse::<u32>() {
+                Ok(index) => (name, Some(index)),
+                Err(_) => anyhow::bail!("Invalid device index in \"{input}\""),
+            },
+            None => (input, None),
+        };
+
+        Ok(match (name.to_lowercase().as_str(), index) {
+            ("auto", None) => Device::Auto,
+            ("cpu", None) => Device::Cpu,
+            ("cuda", index) => Device::Cuda(index.unwrap_or(0)),
+            ("rocm", index) => Device::Rocm(index.unwrap_or(0)),
+            ("coreml", None) => Device::CoreMl,
+            ("directml", index) => Device::DirectMl(index.unwrap_or(0)),
+            ("openvino", None) => Device::OpenVino,
+            _ => anyhow::bail!(
+                "Invalid device \"{input}\". Supported devices: auto, cpu, cuda[:index], rocm[:index], coreml, directml[:index], openvino"
+            ),
+        })
+    }
+}
+
+impl fmt::Display for Device {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Device::Auto => write!(f, "auto"),
+            Device::Cpu => write!(f, "cpu"),
+            Device::Cuda(index) => write!(f, "cuda:{index}"),
+            Device::Rocm(index) => write!(f, "rocm:{index}"),
+            Device::CoreMl => write!(f, "coreml"),
+            Device::DirectMl(index) => write!(f, "directml:{index}"),
+            Device::OpenVino => write!(f, "openvino"),
+        }
+    }
+}
+
+impl Device {
+    fn get_execution_provider(&self) -> ExecutionProvider {
+        match self {
+            Device::Auto | Device::Cpu => ExecutionProvider::CPU(Default::default()),
+            Device::Cuda(index) => ExecutionProvider::CUDA(CUDAExecutionProviderOptions {
+                device_id: *index,
+                ..Default::default()
+            }),
+            Device::Rocm(index) => ExecutionProvider::ROCm(ROCmExecutionProviderOptions {
+                device_id: *index as i32,
+                gpu_mem_limit: usize::MAX,
+                do_copy_in_default_stream: true,
+                ..Default::default()
+            }),
+            Device::CoreMl => ExecutionProvider::CoreML(Default::default()),
+            Device::DirectMl(index) => {
+                ExecutionProvider::DirectML(DirectMLExecutionProviderOptions { device_id: *index })
+            }
+            Device::OpenVino => ExecutionProvider::OpenVINO(Default::default()),
+        }
+    }
+
+    // NVML is used to get GPU memory, so it is only available for CUDA devices
+    fn get_gpu_index(&self) -> Option<u32> {
+        match self {
+            Device::Cuda(index) => Some(*index),
+            _ => None,
+        }
+    }
+}
+
+const HF_MODEL_PREFIX: &str = "hf:";
+const HF_ENDPOINT: &str = "https://huggingface.co";
+const HF_DEFAULT_REVISION: &str = "main";
+const HF_DEFAULT_MAX_SEQ_LENGTH: usize = 512;
+// Quantized exports in the order of preference, the first found in repository is used
+const HF_QUANTIZED_MODEL_FILES: [&str; 6] = [
+    "model_quantized.onnx",
+    "model_int8.onnx",
+    "model_uint8.onnx",
+    "model_quint8_avx2.onnx",
+    "model_qint8_avx512.onnx",
+    "model_qint8_arm64.onnx",
+];
+
+// Sentence transformers model from Hugging Face hub passed as hf:org/model-name[@revision]
+#[derive(Debug, Clone, PartialEq)]
+pub struct HuggingFaceModel {
+    pub repo_id: String,
+    pub revision: String,
+}
+
+#[derive(Deserialize)]
+struct HuggingFaceRepoFile {
+    rfilename: String,
+}
+
+#[derive(Deserialize)]
+struct HuggingFaceRepoInfo {
+    siblings: Vec<HuggingFaceRepoFile>,
+}
+
+#[derive(Deserialize)]
+struct SentenceTransformersPooling {
+    #[serde(default)]
+    pooling_mode_cls_token: bool,
+    #[serde(default)]
+    pooling_mode_mean_tokens: bool,
+}
+
+#[derive(Deserialize)]
+struct SentenceTransformersConfig {
+    max_seq_length: Option<usize>,
+}
+
+impl HuggingFaceModel {
+    pub fn is_hf_model(model_name: &str) -> bool {
+        model_name.starts_with(HF_MODEL_PREFIX)
+    }
+
+    pub fn parse(model_name: &str) -> Result<HuggingFaceModel, anyhow::Error> {
+        let Some(name) = model_name.strip_prefix(HF_MODEL_PREFIX) else {
+            anyhow::bail!("Model name should start with \"{HF_MODEL_PREFIX}\"");
+        };
+
+        let (repo_id, revision) = name.split_once('@').unwrap_or((name, HF_DEFAULT_REVISION));
+
+        // Parts are used as folder names in the cache, so only safe characters are allowed
+        let is_valid_part = |part: &str| {
+            !part.is_empty()
+                && part != "."
+                && part != ".."
+                && part
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        };
+
+        let repo_parts: Vec<&str> = repo_id.split('/').collect();
+        if repo_parts.len() != 2 || !repo_parts.iter().all(|part| is_valid_part(part)) {
+            anyhow::bail!(
+                "Invalid Hugging Face model \"{model_name}\". Expected format: hf:org/model-name[@revision]"
+            );
+        }
+
+        if !is_valid_part(revision) {
+            anyhow::bail!("Invalid revision \"{revision}\" for model \"{model_name}\"");
+        }
+
+        Ok(HuggingFaceModel {
+            repo_id: repo_id.to_owned(),
+            revision: revision.to_owned(),
+        })
+    }
+
+    // Files are cached per revision, so different pinned revisions do not overwrite each other
+    pub fn get_folder(&self, data_path: &str) -> PathBuf {
+        Path::new(data_path)
+            .join("hf")
+            .join(&self.repo_id)
+            .join(&self.revision)
+    }
+
+    fn get_base_url(&self, endpoint: &str) -> String {
+        format!("{endpoint}/{}/resolve/{}", self.repo_id, self.revision)
+    }
+
+    fn get_files(&self, endpoint: &str) -> Result<HashSet<String>, anyhow::Error> {
+        let client = HttpClient::builder()
+            .timeout(Duration::from_secs(30))
+            .redirect_policy(RedirectPolicy::Limit(2))
+            .build()?;
+        let url = format!(
+            "{endpoint}/api/models/{}/revision/{}",
+            self.repo_id, self.revision
+        );
+
+        let mut response = match client.get(&url) {
+            Ok(response) => response,
+            Err(e) => anyhow::bail!("Failed to fetch model info from {url}: {e}"),
+        };
+
+        let status = response.status();
+        if status.as_u16() == 404 {
+            anyhow::bail!(
+                "Model \"{}\" with revision \"{}\" not found on Hugging Face hub",
+                self.repo_id,
+                self.revision
+            );
+        }
+
+        if !status.is_success() {
+            anyhow::bail!(
+                "Failed to fetch model info from {url}. Status {status}. Error: {}",
+                response.text()?
+            );
+        }
+
+        let repo_info: HuggingFaceRepoInfo = serde_json::from_str(&response.text()?)?;
+        Ok(repo_info
+            .siblings
+            .into_iter()
+            .map(|file| file.rfilename)
+            .collect())
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ModelParams {
     layer_cnt: Option<usize>,
@@ -104,8 +333,12 @@ pub struct ModelParams {
 
 pub struct EncoderService {
     name: String,
+    device: Device,
+    // Device param the encoder was created for, it differs from the device after the CPU fallback
+    requested_device: Device,
+    quantized: bool,
     model_params: ModelParams,
-    tokenizer: Option<Tokenizer>,
+    tokenizer: Option<Arc<Tokenizer>>,
     vision_size: Option<usize>,
     encoder: Session,
 }
@@ -113,28 +346,36 @@ pub struct EncoderService {
 pub struct EncoderOptions {
     pub visual: bool,
     pub use_tokenizer: bool,
+    pub cross_encoder: bool,
     padding_params: Option<PaddingParams>,
     truncation_params: Option<TruncationParams>,
     pub input_image_size: Option<usize>,
 }
 
 const DATA_PATH: &'static str = ".ldb_extras_data/";
+const QUANTIZED_MODEL_FILE: &str = "model_quantized.onnx";
 const MAX_IMAGE_SIZE: usize = 1024 * 1024 * 20; // 20 MB
 
 struct ModelInfo {
     url: String,
+    quantized_url: Option<String>,
     params: ModelParams,
     tokenizer_url: Option<String>,
     onnx_data_url: Option<String>,
     encoder_args: EncoderOptions,
     encoder: Option<EncoderService>,
+    dimensions: Option<usize>,
+    max_tokens: Option<usize>,
 }
 
 struct ModelInfoBuilder {
-    base_url: &'static str,
+    base_url: String,
+    model_path: Option<String>,
+    quantized_model: Option<String>,
     pooling_strategy: Option<PoolingStrategy>,
     use_tokenizer: Option<bool>,
     visual: Option<bool>,
+    cross_encoder: bool,
     input_image_size: Option<usize>,
     padding_params: Option<PaddingParams>,
     truncation_params: Option<TruncationParams>,
@@ -142,15 +383,20 @@ struct ModelInfoBuilder {
     head_cnt: Option<usize>,
     head_dim: Option<usize>,
     onnx_data: bool,
+    dimensions: Option<usize>,
+    max_tokens: Option<usize>,
 }
 
 impl ModelInfoBuilder {
-    fn new(base_url: &'static str) -> Self {
+    fn new(base_url: &str) -> Self {
         ModelInfoBuilder {
-            base_url,
+            base_url: base_url.to_owned(),
+            model_path: None,
+            quantized_model: None,
             pooling_strategy: None,
             use_tokenizer: None,
             visual: None,
+            cross_encoder: false,
             input_image_size: None,
             padding_params: None,
             truncation_params: None,
@@ -158,6 +404,8 @@ impl ModelInfoBuilder {
             head_cnt: None,
             head_dim: None,
             onnx_data: false,
+            dimensions: None,
+            max_tokens: None,
         }
     }
 
@@ -171,6 +419,12 @@ impl ModelInfoBuilder {
         self
     }
 
+    // Reranker models score query and document pairs instead of returning embeddings
+    fn with_cross_encoder(&mut self, status: bool) -> &mut Self {
+        self.cross_encoder = status;
+        self
+    }
+
     fn with_input_image_size(&mut self, len: usize) -> &mut Self {
         self.input_image_size = Some(len);
         self
@@ -201,8 +455,49 @@ impl ModelInfoBuilder {
         self
     }
 
+    // Folder of the model files relative to base url, tokenizer is always taken from base url
+    fn with_model_path(&mut self, path: &str) -> &mut Self {
+        self.model_path = Some(path.to_owned());
+        self
+    }
+
+    // File name of int8/uint8 quantized variant of the model in the model folder
+    fn with_quantized_model(&mut self, file_name: &str) -> &mut Self {
+        self.quantized_model = Some(file_name.to_owned());
+        self
+    }
+
+    fn with_padding_params(&mut self, params: PaddingParams) -> &mut Self {
+        self.padding_params = Some(params);
+        self
+    }
+
+    fn with_truncation_params(&mut self, params: TruncationParams) -> &mut Self {
+        self.truncation_params = Some(params);
+        self
+    }
+
+    // Output dimensions and input sequence length reported by the model info API
+    fn with_dimensions(&mut self, dimensions: usize) -> &mut Self {
+        self.dimensions = Some(dimensions);
+        self
+    }
+
+    fn with_max_tokens(&mut self, max_tokens: usize) -> &mut Self {
+        self.max_tokens = Some(max_tokens);
+        self
+    }
+
     fn build(&self) -> ModelInfo {
-        let model_url = format!("{}/model.onnx", self.base_url);
+        let model_base_url = match &self.model_path {
+            Some(path) => format!("{}/{path}", self.base_url),
+            None => self.base_url.clone(),
+        };
+        let model_url = format!("{model_base_url}/model.onnx");
+        let quantized_url = self
+            .quantized_model
+            .as_ref()
+            .map(|file_name| format!("{model_base_url}/{file_name}"));
         let mut tokenizer_url = None;
         let mut onnx_data_url = None;
 
@@ -211,12 +506,13 @@ impl ModelInfoBuilder {
         }
 
         if self.onnx_data {
-            onnx_data_url = Some(format!("{}/model.onnx_data", self.base_url));
+            onnx_data_url = Some(format!("{model_base_url}/model.onnx_data"));
         }
 
         let encoder_args = EncoderOptions {
             visual: self.visual.is_some(),
             use_tokenizer: self.use_tokenizer.is_some(),
+            cross_encoder: self.cross_encoder,
             input_image_size: self.input_image_size.clone(),
             padding_params: self.padding_params.clone(),
             truncation_params: self.truncation_params.clone(),
@@ -224,6 +520,7 @@ impl ModelInfoBuilder {
 
         ModelInfo {
             url: model_url,
+            quantized_url,
             tokenizer_url,
             params: ModelParams {
                 layer_cnt: self.layer_cnt.clone(),
@@ -237,39 +534,43 @@ impl ModelInfoBuilder {
             encoder: None,
             encoder_args,
             onnx_data_url,
+            dimensions: self.dimensions,
+            max_tokens: self
+                .max_tokens
+                .or(self.truncation_params.as_ref().map(|p| p.max_length)),
         }
     }
 }
 
 lazy_static! {
-    static ref MODEL_INFO_MAP: RwLock<HashMap<&'static str, ModelInfo>> = RwLock::new(HashMap::from([
-        ("clip/ViT-B-32-textual", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/openai/ViT-B-32/textual").with_tokenizer(true).build()),
-        ("clip/ViT-B-32-visual", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/openai/ViT-B-32/visual").with_visual(true).with_input_image_size(224).build()),
-        ("BAAI/bge-small-en", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/BAAI/bge-small-en-v1.5").with_tokenizer(true).build()),
-        ("BAAI/bge-base-en", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/BAAI/bge-base-en-v1.5").with_tokenizer(true).build()),
-        ("BAAI/bge-large-en", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/BAAI/bge-large-en-v1.5").with_tokenizer(true).build()),
-        ("BAAI/bge-m3", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/BAAI/bge-m3").with_tokenizer(true).with_onnx_data(true).with_layer_cnt(8).with_head_cnt(4).with_head_dim(64).build()),
-        ("intfloat/e5-base-v2", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/intfloat/e5-base-v2").with_tokenizer(true).build()),
-        ("intfloat/e5-large-v2", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/intfloat/e5-large-v2").with_tokenizer(true).build()),
-        ("llmrails/ember-v1", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/llmrails/ember-v1").with_tokenizer(true).build()),
-        ("thenlper/gte-base", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/thenlper/gte-base").with_tokenizer(true).build()),
-        ("thenlper/gte-large", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/thenlper/gte-large").with_tokenizer(true).build()),
-        ("microsoft/all-MiniLM-L12-v2", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/microsoft/all-MiniLM-L12-v2").with_tokenizer(true).build()),
-        ("microsoft/all-mpnet-base-v2", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/microsoft/all-mpnet-base-v2").with_tokenizer(true).build()),
-        ("transformers/multi-qa-mpnet-base-dot-v1", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/transformers/multi-qa-mpnet-base-dot-v1").with_tokenizer(true).build()),
-        ("jinaai/jina-embeddings-v2-small-en", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/jinaai/jina-embeddings-v2-small-en").with_tokenizer(true).with_layer_cnt(4).with_head_cnt(4).with_head_dim(64).with_pooling_strategy(PoolingStrategy::Mean).build()),
-        ("jinaai/jina-embeddings-v2-base-en", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/jinaai/jina-embeddings-v2-base-en").with_tokenizer(true).with_layer_cnt(12).with_head_cnt(12).with_head_dim(64).with_pooling_strategy(PoolingStrategy::Mean).build())
-    ]));
+    // Models from Hugging Face hub are added to the map when they are first requested
+    static ref MODEL_INFO_MAP: RwLock<HashMap<String, ModelInfo>> = RwLock::new([
+        ("clip/ViT-B-32-textual", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/openai/ViT-B-32/textual").with_tokenizer(true).with_dimensions(512).with_max_tokens(77).build()),
+        ("clip/ViT-B-32-visual", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/openai/ViT-B-32/visual").with_visual(true).with_input_image_size(224).with_dimensions(512).build()),
+        ("BAAI/bge-small-en", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/BAAI/bge-small-en-v1.5").with_tokenizer(true).with_dimensions(384).with_max_tokens(512).build()),
+        ("BAAI/bge-base-en", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/BAAI/bge-base-en-v1.5").with_tokenizer(true).with_dimensions(768).with_max_tokens(512).build()),
+        ("BAAI/bge-large-en", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/BAAI/bge-large-en-v1.5").with_tokenizer(true).with_dimensions(1024).with_max_tokens(512).build()),
+        ("BAAI/bge-m3", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/BAAI/bge-m3").with_tokenizer(true).with_onnx_data(true).with_layer_cnt(8).with_head_cnt(4).with_head_dim(64).with_dimensions(1024).with_max_tokens(8192).build()),
+        ("intfloat/e5-base-v2", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/intfloat/e5-base-v2").with_tokenizer(true).with_dimensions(768).with_max_tokens(512).build()),
+        ("intfloat/e5-large-v2", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/intfloat/e5-large-v2").with_tokenizer(true).with_dimensions(1024).with_max_tokens(512).build()),
+        ("llmrails/ember-v1", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/llmrails/ember-v1").with_tokenizer(true).with_dimensions(1024).with_max_tokens(512).build()),
+        ("thenlper/gte-base", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/thenlper/gte-base").with_tokenizer(true).with_dimensions(768).with_max_tokens(512).build()),
+        ("thenlper/gte-large", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/thenlper/gte-large").with_tokenizer(true).with_dimensions(1024).with_max_tokens(512).build()),
+        ("microsoft/all-MiniLM-L12-v2", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/microsoft/all-MiniLM-L12-v2").with_tokenizer(true).with_dimensions(384).with_max_tokens(512).build()),
+        ("microsoft/all-mpnet-base-v2", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/microsoft/all-mpnet-base-v2").with_tokenizer(true).with_dimensions(768).with_max_tokens(512).build()),
+        ("transformers/multi-qa-mpnet-base-dot-v1", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/transformers/multi-qa-mpnet-base-dot-v1").with_tokenizer(true).with_dimensions(768).with_max_tokens(512).build()),
+        ("jinaai/jina-embeddings-v2-small-en", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/jinaai/jina-embeddings-v2-small-en").with_tokenizer(true).with_layer_cnt(4).with_head_cnt(4).with_head_dim(64).with_pooling_strategy(PoolingStrategy::Mean).with_dimensions(512).with_max_tokens(8192).build()),
+        ("jinaai/jina-embeddings-v2-base-en", ModelInfoBuilder::new("https://huggingface.co/varik77/onnx-models/resolve/main/jinaai/jina-embeddings-v2-base-en").with_tokenizer(true).with_layer_cnt(12).with_head_cnt(12).with_head_dim(64).with_pooling_strategy(PoolingStrategy::Mean).with_dimensions(768).with_max_tokens(8192).build()),
+        ("BAAI/bge-reranker-base", ModelInfoBuilder::new("https://huggingface.co/BAAI/bge-reranker-base/resolve/main").with_model_path("onnx").with_tokenizer(true).with_cross_encoder(true).with_padding_params(PaddingParams::default()).with_truncation_params(TruncationParams { max_length: 512, ..Default::default() }).build()),
+        ("BAAI/bge-reranker-large", ModelInfoBuilder::new("https://huggingface.co/BAAI/bge-reranker-large/resolve/main").with_model_path("onnx").with_tokenizer(true).with_cross_encoder(true).with_padding_params(PaddingParams::default()).with_truncation_params(TruncationParams { max_length: 512, ..Default::default() }).build())
+    ].into_iter().map(|(name, info)| (name.to_owned(), info)).collect());
 }
 
 lazy_static! {
+    // Execution providers are selected per session based on the device param
     static ref ONNX_ENV: Arc<Environment> = Environment::builder()
         .with_name("ldb_extras")
-        .with_execution_providers([
-            ExecutionProvider::CUDA(Default::default()),
-            ExecutionProvider::OpenVINO(Default::default()),
-            ExecutionProvider::CPU(Default::default()),
-        ])
+        .with_execution_providers([ExecutionProvider::CPU(Default::default())])
         .build()
         .unwrap()
         .into_arc();
@@ -282,38 +583,54 @@ impl EncoderService {
         environment: &Arc<Environment>,
         model_name: &str,
         model_params: ModelParams,
-        model_folder: &PathBuf,
+        model_path: &PathBuf,
         args: &EncoderOptions,
+        intra_threads: Option<usize>,
+        device: &Device,
+        requested_device: &Device,
+        execution_providers: &[ExecutionProvider],
     ) -> Result<EncoderService, Box<dyn std::error::Error + Send + Sync>> {
         let mut tokenizer = None;
 
+        // Tokenizer is kept in the shared cache, so it is not loaded again
+        // when the encoder is reloaded after being unloaded on memory pressure
         if args.use_tokenizer {
-            let mut tokenizer_instance =
-                Tokenizer::from_file(Path::join(model_folder, "tokenizer.json")).unwrap();
-
-            // In case tokenizer will not contain padding and truncation params
-            // We will specify them manually
-            if args.padding_params.is_some() {
-                tokenizer_instance.with_padding(args.padding_params.clone());
-            }
+            tokenizer = Some(HF_TOKENIZER_CACHE.get_or_load(model_name, || {
+                let mut tokenizer_instance =
+                    Tokenizer::from_file(model_path.with_file_name("tokenizer.json"))
+                        .map_err(|e| anyhow::anyhow!(e))?;
+
+                // In case tokenizer will not contain padding and truncation params
+                // We will specify them manually
+                if args.padding_params.is_some() && tokenizer_instance.get_padding().is_none() {
+                    tokenizer_instance.with_padding(args.padding_params.clone());
+                }
 
-            if args.truncation_params.is_some() {
-                tokenizer_instance.with_truncation(args.truncation_params.clone())?;
-            }
+                if args.truncation_params.is_some() && tokenizer_instance.get_truncation().is_none()
+                {
+                    tokenizer_instance
+                        .with_truncation(args.truncation_params.clone())
+                        .map_err(|e| anyhow::anyhow!(e))?;
+                }
 
-            tokenizer = Some(tokenizer_instance);
+                Ok(tokenizer_instance)
+            })?);
         }
 
-        let num_cpus = num_cpus::get();
+        let num_cpus = intra_threads.unwrap_or(num_cpus::get());
 
         let encoder = SessionBuilder::new(environment)?
+            .with_execution_providers(execution_providers)?
             .with_parallel_execution(true)?
             .with_intra_threads(num_cpus as i16)?
             .with_optimization_level(GraphOptimizationLevel::Level3)?
-            .with_model_from_file(Path::join(model_folder, "model.onnx"))?;
+            .with_model_from_file(model_path)?;
 
         Ok(EncoderService {
             name: model_name.to_string(),
+            device: device.clone(),
+            requested_device: requested_device.clone(),
+            quantized: model_path.ends_with(QUANTIZED_MODEL_FILE),
             tokenizer,
             encoder,
             model_params,
@@ -363,7 +680,7 @@ impl EncoderService {
         let token_cnt = vecs[0].len() / batch_size;
         let input_cnt = vecs.len();
         // Get available memory for GPU or RAM
-        let available_memory = get_available_memory()? as usize;
+        let available_memory = get_available_memory(self.device.get_gpu_index())? as usize;
         // Calculate memory consumption for one item
         // Then devide GPU memory / memory needed
         // Then devide array into chunks of that count
@@ -551,6 +868,72 @@ impl EncoderService {
         })
     }
 
+    // Query and document are encoded as a pair and the model returns one relevance logit for it
+    // Logits are mapped to [0, 1] with sigmoid
+    fn rerank_text(
+        &self,
+        query: &str,
+        documents: &Vec<&str>,
+    ) -> Result<RerankResult, Box<dyn std::error::Error + Send + Sync>> {
+        let session = &self.encoder;
+        if session.outputs[0].dimensions.last() != Some(&Some(1)) {
+            return Err(format!(
+                "Model {} is not a cross-encoder, it should return one score per input",
+                self.name
+            )
+            .into());
+        }
+
+        let tokenizer = match &self.tokenizer {
+            Some(tokenizer) => tokenizer,
+            None => return Err(format!("Model {} does not have tokenizer", self.name).into()),
+        };
+        let pairs: Vec<(&str, &str)> = documents.iter().map(|doc| (query, *doc)).collect();
+        let preprocessed = tokenizer.encode_batch(pairs, true)?;
+        // Pairs are padded to the same length
+        let token_cnt = preprocessed[0].get_ids().len();
+
+        let mut inputs: Vec<SessionInput> = Vec::with_capacity(session.inputs.len());
+        for input in &session.inputs {
+            let values: Vec<i64> = match input.name.as_str() {
+                "input_ids" => preprocessed
+                    .iter()
+                    .map(|i| i.get_ids().iter().map(|b| *b as i64).collect())
+                    .concat(),
+                "attention_mask" => preprocessed
+                    .iter()
+                    .map(|i| i.get_attention_mask().iter().map(|b| *b as i64).collect())
+                    .concat(),
+                "token_type_ids" => preprocessed
+                    .iter()
+                    .map(|i| i.get_type_ids().iter().map(|b| *b as i64).collect())
+                    .concat(),
+                name => return Err(format!("Unsupported model input {name}").into()),
+            };
+            inputs.push(
+                CowArray::from(Array2::from_shape_vec(
+                    (documents.len(), token_cnt),
+                    values,
+                )?)
+                .into_dyn(),
+            );
+        }
+
+        let values = inputs
+            .iter()
+            .map(|v| Value::from_array(session.allocator(), v))
+            .collect::<Result<Vec<Value<'_>>, _>>()?;
+        let outputs = session.run(values)?;
+        let binding = outputs[0].try_extract::<f32>()?;
+        let scores = binding
+            .view()
+            .iter()
+            .map(|logit| 1.0 / (1.0 + (-logit).exp()))
+            .collect();
+
+        Ok(RerankResult { scores })
+    }
+
     fn process_text(
         &self,
         texts: &Vec<&str>,
@@ -633,6 +1016,11 @@ impl EncoderService {
 pub struct OrtRuntime<'a> {
     cache: bool,
     data_path: String,
+    intra_threads: Option<usize>,
+    device: Device,
+    fallback: bool,
+    quantized: bool,
+    hf_endpoint: String,
     logger: &'a LoggerFn,
 }
 
@@ -640,22 +1028,213 @@ pub struct OrtRuntime<'a> {
 pub struct OrtRuntimeParams {
     data_path: Option<String>,
     cache: Option<bool>,
+    intra_threads: Option<usize>,
+    device: Option<String>,
+    fallback: Option<bool>,
+    quantized: Option<bool>,
+    hf_endpoint: Option<String>,
 }
 
 impl<'a> OrtRuntime<'a> {
-    pub fn new(logger: &'a LoggerFn, params: &'a str) -> Result<Self, anyhow::Error> {
+    pub fn new(logger: &'a LoggerFn, params: &str) -> Result<Self, anyhow::Error> {
         let runtime_params: OrtRuntimeParams = serde_json::from_str(&params)?;
 
         Ok(Self {
             logger,
             cache: runtime_params.cache.unwrap_or(false),
             data_path: runtime_params.data_path.unwrap_or(DATA_PATH.to_owned()),
+            intra_threads: runtime_params.intra_threads,
+            device: Device::from_str(runtime_params.device.as_deref().unwrap_or("auto"))?,
+            fallback: runtime_params.fallback.unwrap_or(true),
+            quantized: runtime_params.quantized.unwrap_or(false),
+            hf_endpoint: runtime_params
+                .hf_endpoint
+                .as_deref()
+                .unwrap_or(HF_ENDPOINT)
+                .trim_end_matches('/')
+                .to_owned(),
         })
     }
 
+    fn get_model_folder(&self, model_name: &str) -> PathBuf {
+        if HuggingFaceModel::is_hf_model(model_name) {
+            if let Ok(model) = HuggingFaceModel::parse(model_name) {
+                return model.get_folder(&self.data_path);
+            }
+        }
+
+        Path::new(&self.data_path).join(model_name)
+    }
+
+    // Add model info for hf:org/model-name[@revision] to the model map
+    // The repository should contain ONNX export at onnx/model.onnx and tokenizer.json
+    // Pooling and max sequence length are taken from sentence transformers configs if present
+    // The list of repository files is cached, so cached models can be loaded without network
+    fn register_hf_model(&self, model_name: &str) -> Result<(), anyhow::Error> {
+        let model = HuggingFaceModel::parse(model_name)?;
+        let model_folder = model.get_folder(&self.data_path);
+        let base_url = model.get_base_url(&self.hf_endpoint);
+        let files_path = Path::join(&model_folder, "files.json");
+        let pooling_config_path = Path::join(&model_folder, "1_Pooling/config.json");
+        let model_config_path = Path::join(&model_folder, "sentence_bert_config.json");
+
+        let files: HashSet<String> = if files_path.exists() {
+            serde_json::from_str(&std::fs::read_to_string(&files_path)?)?
+        } else {
+            let files = model.get_files(&self.hf_endpoint)?;
+
+            for required_file in ["onnx/model.onnx", "tokenizer.json"] {
+                if !files.contains(required_file) {
+                    anyhow::bail!(
+                        "Model \"{}\" does not contain {required_file}. Only sentence transformers models with ONNX export are supported",
+                        model.repo_id
+                    );
+                }
+            }
+
+            for (file, path) in [
+                ("1_Pooling/config.json", &pooling_config_path),
+                ("sentence_bert_config.json", &model_config_path),
+            ] {
+                if files.contains(file) {
+                    download_file(&format!("{base_url}/{file}"), path)?;
+                }
+            }
+
+            std::fs::create_dir_all(&model_folder)?;
+            std::fs::write(&files_path, serde_json::to_string(&files)?)?;
+            files
+        };
+
+        let pooling_strategy = if pooling_config_path.exists() {
+            let config: SentenceTransformersPooling =
+                serde_json::from_str(&std::fs::read_to_string(&pooling_config_path)?)?;
+
+            if config.pooling_mode_cls_token {
+                PoolingStrategy::CLS
+            } else if config.pooling_mode_mean_tokens {
+                PoolingStrategy::Mean
+            } else {
+                anyhow::bail!(
+                    "Unsupported pooling mode for model \"{}\". Only CLS and mean pooling are supported",
+                    model.repo_id
+                );
+            }
+        } else {
+            PoolingStrategy::Mean
+        };
+
+        let max_seq_length = if model_config_path.exists() {
+            let config: SentenceTransformersConfig =
+                serde_json::from_str(&std::fs::read_to_string(&model_config_path)?)?;
+            config.max_seq_length
+        } else {
+            None
+        };
+
+        let mut builder = ModelInfoBuilder::new(&base_url);
+        builder
+            .with_model_path("onnx")
+            .with_tokenizer(true)
+            .with_onnx_data(files.contains("onnx/model.onnx_data"))
+            .with_pooling_strategy(pooling_strategy)
+            .with_padding_params(PaddingParams::default())
+            .with_truncation_params(TruncationParams {
+                max_length: max_seq_length.unwrap_or(HF_DEFAULT_MAX_SEQ_LENGTH),
+                ..Default::default()
+            });
+
+        if let Some(file_name) = HF_QUANTIZED_MODEL_FILES
+            .iter()
+            .find(|file_name| files.contains(&format!("onnx/{file_name}")))
+        {
+            builder.with_quantized_model(file_name);
+        }
+
+        let model_info = builder.build();
+
+        MODEL_INFO_MAP
+            .write()
+            .unwrap()
+            .insert(model_name.to_owned(), model_info);
+
+        Ok(())
+    }
+
+    fn fallback_to_cpu(
+        &self,
+        reason: &str,
+    ) -> Result<(Device, Vec<ExecutionProvider>), anyhow::Error> {
+        if !self.fallback {
+            anyhow::bail!("{reason}");
+        }
+
+        (self.logger)(&format!("{reason}, falling back to CPU"));
+        Ok((
+            Device::Cpu,
+            vec![ExecutionProvider::CPU(Default::default())],
+        ))
+    }
+
+    // Resolve the device which will be used for the model and execution providers for the session
+    // CPU provider is always registered last, so ONNX Runtime can place unsupported nodes on it
+    fn get_execution_providers(
+        &self,
+        model_path: &PathBuf,
+    ) -> Result<(Device, Vec<ExecutionProvider>), anyhow::Error> {
+        let cpu_provider = ExecutionProvider::CPU(Default::default());
+
+        if self.device == Device::Cpu {
+            return Ok((Device::Cpu, vec![cpu_provider]));
+        }
+
+        if self.device == Device::Auto {
+            for device in [Device::Cuda(0), Device::OpenVino] {
+                let provider = device.get_execution_provider();
+                if provider.is_available() {
+                    return Ok((device, vec![provider, cpu_provider]));
+                }
+            }
+            return Ok((Device::Cpu, vec![cpu_provider]));
+        }
+
+        let provider = self.device.get_execution_provider();
+        if !provider.is_available() {
+            return self.fallback_to_cpu(&format!(
+                "{} is not available in the installed ONNX Runtime",
+                provider.as_str()
+            ));
+        }
+
+        if let Some(gpu_index) = self.device.get_gpu_index() {
+            if let Some(gpu_count) = get_gpu_count() {
+                if gpu_index >= gpu_count {
+                    return self.fallback_to_cpu(&format!(
+                        "Device {} not found. Available GPU count: {gpu_count}",
+                        self.device
+                    ));
+                }
+            }
+
+            let model_size = std::fs::metadata(model_path)?.len();
+            if let Some(free_memory) = get_gpu_free_memory(gpu_index) {
+                if free_memory < model_size {
+                    return self.fallback_to_cpu(&format!(
+                        "Not enough free memory on device {}. Memory needed: {:.2}MB, Memory available: {:.2}MB",
+                        self.device,
+                        model_size as f64 / 1024.0 / 1024.0,
+                        free_memory as f64 / 1024.0 / 1024.0
+                    ));
+                }
+            }
+        }
+
+        Ok((self.device.clone(), vec![provider, cpu_provider]))
+    }
+
     fn clear_model_cache(
         &self,
-        model_map: &mut HashMap<&'static str, ModelInfo>,
+        model_map: &mut HashMap<String, ModelInfo>,
     ) -> Result<(), anyhow::Error> {
         for (_, model_info) in model_map.iter_mut() {
             model_info.encoder = None;
@@ -667,7 +1246,7 @@ impl<'a> OrtRuntime<'a> {
     fn check_available_memory(
         &self,
         model_path: &PathBuf,
-        model_map: &mut HashMap<&'static str, ModelInfo>,
+        model_map: &mut HashMap<String, ModelInfo>,
     ) -> Result<(), anyhow::Error> {
         let mut sys = System::new_all();
         sys.refresh_all();
@@ -707,7 +1286,15 @@ impl<'a> OrtRuntime<'a> {
             }
         }
 
-        if self.cache && percent_gpu_memory_used()? >= MEM_PERCENT_THRESHOLD {
+        let gpu_index = match self.device {
+            Device::Auto => Some(0),
+            _ => self.device.get_gpu_index(),
+        };
+
+        if self.cache
+            && gpu_index.is_some()
+            && percent_gpu_memory_used(gpu_index.unwrap())? >= MEM_PERCENT_THRESHOLD
+        {
             // The GPU memory will only be checked when the models are cached
             // If not enough GPU RAM and cache is not clearted already
             // try to clear model cache
@@ -723,38 +1310,88 @@ impl<'a> OrtRuntime<'a> {
     }
 
     fn check_and_download_files(&self, model_name: &str) -> Result<(), anyhow::Error> {
+        self.ensure_model_info(model_name)?;
+
         {
             let map = MODEL_INFO_MAP.read().unwrap();
-            let model_info = map.get(model_name);
-
-            if model_info.is_none() {
-                anyhow::bail!(
-                    "Model \"{}\" not found.\nAvailable models: {}",
-                    model_name,
-                    map.keys().join(", ")
-                )
+            let model_info = map.get(model_name).unwrap();
+            let quantized = self.quantized && model_info.quantized_url.is_some();
+
+            if let Some(encoder) = &model_info.encoder {
+                // if encoder exists and was created for the requested device and weights return
+                if (self.device == Device::Auto || encoder.requested_device == self.device)
+                    && encoder.quantized == quantized
+                {
+                    return Ok(());
+                }
             }
+        }
+
+        let mut map_write = MODEL_INFO_MAP.write().unwrap();
+        let model_path = self.download_model_files(model_name, &map_write[model_name])?;
+
+        // Check available memory
+        self.check_available_memory(&model_path, &mut map_write)?;
 
-            let model_info = model_info.unwrap();
+        let (device, execution_providers) = self.get_execution_providers(&model_path)?;
+        (self.logger)(&format!(
+            "Using {} (device: {device}) for model {model_name}",
+            execution_providers[0].as_str()
+        ));
+
+        let model_info = map_write.get_mut(model_name).unwrap();
+        let encoder = EncoderService::new(
+            &ONNX_ENV,
+            model_name,
+            model_info.params.clone(),
+            &model_path,
+            &model_info.encoder_args,
+            self.intra_threads,
+            &device,
+            &self.device,
+            &execution_providers,
+        );
 
-            if model_info.encoder.is_some() {
-                // if encoder exists return
-                return Ok(());
+        match encoder {
+            Ok(enc) => model_info.encoder = Some(enc),
+            Err(err) => {
+                drop(map_write);
+                anyhow::bail!(err)
             }
         }
 
-        let mut map_write = MODEL_INFO_MAP.write().unwrap();
-        let model_info = map_write.get_mut(model_name).unwrap();
+        Ok(())
+    }
 
-        let model_folder = Path::join(&Path::new(&self.data_path), model_name);
-        let model_path = Path::join(&model_folder, "model.onnx");
+    // Downloads missing model files and returns the path of the ONNX model
+    fn download_model_files(
+        &self,
+        model_name: &str,
+        model_info: &ModelInfo,
+    ) -> Result<PathBuf, anyhow::Error> {
+        let quantized = self.quantized && model_info.quantized_url.is_some();
+        if self.quantized && !quantized {
+            (self.logger)(&format!(
+                "Quantized variant is not available for model {model_name}, using full precision model"
+            ));
+        }
+
+        let model_folder = self.get_model_folder(model_name);
+        let (model_url, model_path) = if quantized {
+            (
+                model_info.quantized_url.as_ref().unwrap(),
+                Path::join(&model_folder, QUANTIZED_MODEL_FILE),
+            )
+        } else {
+            (&model_info.url, Path::join(&model_folder, "model.onnx"))
+        };
         let tokenizer_path = Path::join(&model_folder, "tokenizer.json");
 
         // TODO parallel download with tokio
         if !model_path.exists() {
             // model is not downloaded, we should download it
             (self.logger)("Downloading model [this is one time operation]");
-            download_file(&model_info.url, &model_path)?;
+            download_file(model_url, &model_path)?;
         }
 
         if !tokenizer_path.exists() && model_info.tokenizer_url.is_some() {
@@ -763,7 +1400,8 @@ impl<'a> OrtRuntime<'a> {
             download_file(&model_info.tokenizer_url.as_ref().unwrap(), &tokenizer_path)?;
         }
 
-        if let Some(onnx_data_url) = &model_info.onnx_data_url {
+        // External data is only used by full precision models
+        if let Some(onnx_data_url) = model_info.onnx_data_url.as_ref().filter(|_| !quantized) {
             let onnx_data_path = Path::join(&model_folder, "model.onnx_data");
 
             if !onnx_data_path.exists() {
@@ -772,29 +1410,66 @@ impl<'a> OrtRuntime<'a> {
             }
         }
 
-        // Check available memory
-        self.check_available_memory(&model_path, &mut map_write)?;
+        Ok(model_path)
+    }
 
-        let model_info = map_write.get_mut(model_name).unwrap();
-        let encoder = EncoderService::new(
-            &ONNX_ENV,
-            model_name,
-            model_info.params.clone(),
-            &model_folder,
-            &model_info.encoder_args,
-        );
+    // Registers Hugging Face models and validates that the model is known
+    fn ensure_model_info(&self, model_name: &str) -> Result<(), anyhow::Error> {
+        if HuggingFaceModel::is_hf_model(model_name)
+            && !MODEL_INFO_MAP.read().unwrap().contains_key(model_name)
+        {
+            self.register_hf_model(model_name)?;
+        }
 
-        match encoder {
-            Ok(enc) => model_info.encoder = Some(enc),
-            Err(err) => {
-                drop(map_write);
-                anyhow::bail!(err)
-            }
+        let map = MODEL_INFO_MAP.read().unwrap();
+        if !map.contains_key(model_name) {
+            anyhow::bail!(
+                "Model \"{}\" not found.\nAvailable models: {}",
+                model_name,
+                map.keys().join(", ")
+            )
         }
 
         Ok(())
     }
 
+    pub fn get_data_path(&self) -> &str {
+        &self.data_path
+    }
+
+    // Downloads model files to the data path without creating the session
+    pub fn pull_model(&self, model_name: &str) -> Result<PathBuf, anyhow::Error> {
+        self.ensure_model_info(model_name)?;
+        let map = MODEL_INFO_MAP.read().unwrap();
+        self.download_model_files(model_name, map.get(model_name).unwrap())?;
+        Ok(self.get_model_folder(model_name))
+    }
+
+    // Removes downloaded model files and drops the loaded session and tokenizer
+    pub fn remove_model(&self, model_name: &str) -> Result<PathBuf, anyhow::Error> {
+        if !HuggingFaceModel::is_hf_model(model_name)
+            && !MODEL_INFO_MAP.read().unwrap().contains_key(model_name)
+        {
+            anyhow::bail!("Model \"{model_name}\" not found");
+        }
+        // Validates the name before it is used as a path
+        if HuggingFaceModel::is_hf_model(model_name) {
+            HuggingFaceModel::parse(model_name)?;
+        }
+
+        let model_folder = self.get_model_folder(model_name);
+        if !model_folder.exists() {
+            anyhow::bail!("Model \"{model_name}\" is not downloaded");
+        }
+
+        if let Some(model_info) = MODEL_INFO_MAP.write().unwrap().get_mut(model_name) {
+            model_info.encoder = None;
+        }
+        HF_TOKENIZER_CACHE.evict(model_name);
+        std::fs::remove_dir_all(&model_folder)?;
+        Ok(model_folder)
+    }
+
     async fn get_image_buffer(&self, path_or_url: &str) -> Result<Vec<u8>, anyhow::Error> {
         if let Ok(url) = Url::parse(path_or_url) {
             let client = HttpClient::builder()
@@ -900,6 +1575,12 @@ impl<'a> EmbeddingRuntime for OrtRuntime<'a> {
         let map = MODEL_INFO_MAP.read().unwrap();
         let model_info = map.get(model_name).unwrap();
 
+        if model_info.encoder_args.cross_encoder {
+            anyhow::bail!(
+                "Model {model_name} is a reranker and can not be used to generate embeddings"
+            );
+        }
+
         let result;
         if model_info.encoder_args.visual {
             let buffers = self.get_images_parallel(inputs)?;
@@ -997,28 +1678,136 @@ impl<'a> EmbeddingRuntime for OrtRuntime<'a> {
     fn get_available_models(&self) -> (String, Vec<(String, bool)>) {
         let map = MODEL_INFO_MAP.read().unwrap();
         let mut res = String::new();
-        let data_path = &self.data_path;
         let mut models = Vec::with_capacity(map.len());
         for (key, value) in &*map {
-            let model_exists =
-                if Path::join(&Path::new(data_path), format!("{}/model.onnx", key)).exists() {
-                    "true"
-                } else {
-                    "false"
-                };
-            let model_type = if !value.encoder_args.visual {
+            let model_exists = if Path::join(&self.get_model_folder(key), "model.onnx").exists() {
+                "true"
+            } else {
+                "false"
+            };
+            let model_type = if value.encoder_args.cross_encoder {
+                "reranker"
+            } else if !value.encoder_args.visual {
                 "textual"
             } else {
                 "visual"
             };
+            let has_quantized = value.quantized_url.is_some();
 
             res.push_str(&format!(
-                "{} - type: {}, downloaded: {}\n",
-                key, model_type, model_exists
+                "{} - type: {}, downloaded: {}, quantized: {}\n",
+                key, model_type, model_exists, has_quantized
             ));
-            models.push((key.to_string(), value.encoder_args.visual));
+            // Rerankers are listed, but they can not be used to generate embeddings
+            if !value.encoder_args.cross_encoder {
+                models.push((key.to_string(), value.encoder_args.visual));
+            }
         }
 
         return (res, models);
     }
+
+    // Rerankers are not listed, as they do not produce embeddings
+    // Dimensions of Hugging Face hub models are not known until the model is loaded
+    fn get_models_info(&self) -> Vec<super::runtime::ModelInfo> {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        map.iter()
+            .filter(|(_, value)| !value.encoder_args.cross_encoder)
+            .map(|(key, value)| super::runtime::ModelInfo {
+                name: key.to_string(),
+                dims: value.dimensions,
+                max_tokens: value.max_tokens,
+                modality: if value.encoder_args.visual {
+                    Modality::Image
+                } else {
+                    Modality::Text
+                },
+                provider: "ort".to_owned(),
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect()
+    }
+
+    // Tokens are counted with the model tokenizer from the shared cache
+    // Padding tokens are not counted, as they are not part of the input
+    fn count_tokens(
+        &self,
+        model_name: &str,
+        inputs: &Vec<&str>,
+    ) -> Result<Vec<usize>, anyhow::Error> {
+        if let Err(err) = self.check_and_download_files(model_name) {
+            anyhow::bail!("{:?}", err);
+        }
+
+        let tokenizer = MODEL_INFO_MAP
+            .read()
+            .unwrap()
+            .get(model_name)
+            .and_then(|model_info| model_info.encoder.as_ref())
+            .and_then(|encoder| encoder.tokenizer.clone());
+
+        let tokenizer = match tokenizer {
+            Some(tokenizer) => tokenizer,
+            None => {
+                return Ok(inputs
+                    .iter()
+                    .map(|input| DEFAULT_TOKENIZER.encode_ordinary(input).len())
+                    .collect())
+            }
+        };
+
+        inputs
+            .iter()
+            .map(|input| {
+                let encoding = tokenizer
+                    .encode(*input, true)
+                    .map_err(|e| anyhow::anyhow!(e))?;
+                Ok(encoding
+                    .get_attention_mask()
+                    .iter()
+                    .filter(|mask| **mask == 1)
+                    .count())
+            })
+            .collect()
+    }
+}
+
+impl<'a> RerankRuntime for OrtRuntime<'a> {
+    fn rerank(
+        &self,
+        model_name: &str,
+        query: &str,
+        documents: &Vec<&str>,
+    ) -> Result<RerankResult, anyhow::Error> {
+        if documents.is_empty() {
+            return Ok(RerankResult { scores: Vec::new() });
+        }
+
+        if let Err(err) = self.check_and_download_files(model_name) {
+            anyhow::bail!("{:?}", err);
+        }
+
+        let map = MODEL_INFO_MAP.read().unwrap();
+        let result = map
+            .get(model_name)
+            .unwrap()
+            .encoder
+            .as_ref()
+            .unwrap()
+            .rerank_text(query, documents);
+        drop(map);
+
+        if !self.cache {
+            let mut map = MODEL_INFO_MAP.write().unwrap();
+            let model_info = map.get_mut(model_name).unwrap();
+            model_info.encoder = None;
+        }
+
+        match result {
+            Ok(res) => Ok(res),
+            Err(err) => {
+                anyhow::bail!("Error happened while reranking {:?}", err);
+            }
+        }
+    }
 }
@@ -1,3 +1,30 @@
+use super::tokenizer_cache::get_cl100k_base;
+use serde::Serialize;
+use std::sync::Arc;
+use tiktoken_rs::CoreBPE;
+
+lazy_static! {
+    pub static ref DEFAULT_TOKENIZER: Arc<CoreBPE> = get_cl100k_base().unwrap();
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Modality {
+    Text,
+    Image,
+}
+
+// Structured model metadata, so callers do not need to parse the text listing
+// Dimensions and max tokens are None when the runtime does not know them ahead of time
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ModelInfo {
+    pub name: String,
+    pub dims: Option<usize>,
+    pub max_tokens: Option<usize>,
+    pub modality: Modality,
+    pub provider: String,
+}
+
 pub struct EmbeddingResult {
     pub embeddings: Vec<Vec<f32>>,
     pub processed_tokens: usize,
@@ -9,4 +36,57 @@ pub trait EmbeddingRuntime {
         inputs: &Vec<&str>,
     ) -> Result<EmbeddingResult, anyhow::Error>;
     fn get_available_models(&self) -> (String, Vec<(String, bool)>);
+
+    // Returns metadata of the embedding models known to the runtime sorted by name
+    fn get_models_info(&self) -> Vec<ModelInfo>;
+
+    fn get_model_info(&self, model_name: &str) -> Option<ModelInfo> {
+        self.get_models_info()
+            .into_iter()
+            .find(|info| info.name == model_name)
+    }
+
+    // Returns token count for each input. This is used to pack batches by token budget
+    // Runtimes without own tokenizer will approximate the count using cl100k_base tokenizer
+    fn count_tokens(
+        &self,
+        _model_name: &str,
+        inputs: &Vec<&str>,
+    ) -> Result<Vec<usize>, anyhow::Error> {
+        Ok(inputs
+            .iter()
+            .map(|input| DEFAULT_TOKENIZER.encode_ordinary(input).len())
+            .collect())
+    }
+
+    // Returns number of requests the inputs are split into by the runtime
+    // Local runtimes process the inputs in one call
+    fn count_requests(
+        &self,
+        _model_name: &str,
+        _inputs: &Vec<&str>,
+    ) -> Result<usize, anyhow::Error> {
+        Ok(1)
+    }
+
+    // Returns region of the remote endpoint the inputs are sent to
+    // None means the region is unknown
+    fn get_region(&self) -> Option<String> {
+        None
+    }
+}
+
+// Relevance scores of the documents in the input order
+pub struct RerankResult {
+    pub scores: Vec<f32>,
+}
+
+// Runtimes of cross-encoder models, which score each document against the query
+pub trait RerankRuntime {
+    fn rerank(
+        &self,
+        model_name: &str,
+        query: &str,
+        documents: &Vec<&str>,
+    ) -> Result<RerankResult, anyhow::Error>;
 }
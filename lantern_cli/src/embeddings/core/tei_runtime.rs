@@ -0,0 +1,489 @@
+use super::{
+    runtime::{self, EmbeddingResult, EmbeddingRuntime, Modality},
+    LoggerFn,
+};
+use crate::metrics::{self, Subsystem};
+use crate::HTTPRuntime;
+use isahc::config::VersionNegotiation;
+use isahc::{ReadResponseExt, Request, ResponseExt};
+use serde::{Deserialize, Serialize};
+
+static GRPC_EMBED_STREAM_PATH: &str = "/tei.v1.Embed/EmbedStream";
+static GRPC_MAX_RETRIES: usize = 5;
+// gRPC status codes which are worth retrying: RESOURCE_EXHAUSTED, UNAVAILABLE
+static GRPC_RETRY_STATUSES: [&str; 2] = ["8", "14"];
+
+#[derive(Debug, PartialEq)]
+enum TeiProtocol {
+    Http,
+    Grpc,
+}
+
+// Text Embeddings Inference server serves one model, so the model name is not validated
+pub struct TeiRuntime<'a> {
+    request_timeout: u64,
+    max_batch_size: usize,
+    base_url: String,
+    headers: Vec<(String, String)>,
+    protocol: TeiProtocol,
+    truncate: bool,
+    normalize: bool,
+    truncation_direction: String,
+    prompt_name: Option<String>,
+    region: Option<String>,
+    #[allow(dead_code)]
+    logger: &'a LoggerFn,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct TeiRuntimeParams {
+    pub base_url: Option<String>,
+    pub protocol: Option<String>,
+    pub api_key: Option<String>,
+    pub truncate: Option<bool>,
+    pub normalize: Option<bool>,
+    pub truncation_direction: Option<String>,
+    pub prompt_name: Option<String>,
+    pub max_batch_size: Option<usize>,
+    pub request_timeout: Option<u64>,
+    pub region: Option<String>,
+}
+
+pub static TRUNCATION_DIRECTIONS: [&str; 2] = ["right", "left"];
+
+fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+fn decode_varint(buf: &[u8], pos: &mut usize) -> Result<u64, anyhow::Error> {
+    let mut value = 0;
+    for shift in (0..64).step_by(7) {
+        let byte = match buf.get(*pos) {
+            Some(byte) => *byte,
+            None => anyhow::bail!("Unexpected end of protobuf message"),
+        };
+        *pos += 1;
+        value |= ((byte & 0x7F) as u64) << shift;
+        if byte & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+    anyhow::bail!("Invalid protobuf varint")
+}
+
+fn encode_string_field(field: u64, value: &str, buf: &mut Vec<u8>) {
+    encode_varint(field << 3 | 2, buf);
+    encode_varint(value.len() as u64, buf);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn encode_varint_field(field: u64, value: u64, buf: &mut Vec<u8>) {
+    encode_varint(field << 3, buf);
+    encode_varint(value, buf);
+}
+
+// Read length delimited field value and move position after it
+fn read_bytes<'b>(buf: &'b [u8], pos: &mut usize) -> Result<&'b [u8], anyhow::Error> {
+    let len = decode_varint(buf, pos)? as usize;
+    if *pos + len > buf.len() {
+        anyhow::bail!("Unexpected end of protobuf message");
+    }
+    let bytes = &buf[*pos..*pos + len];
+    *pos += len;
+    Ok(bytes)
+}
+
+fn skip_field(wire_type: u64, buf: &[u8], pos: &mut usize) -> Result<(), anyhow::Error> {
+    match wire_type {
+        0 => {
+            decode_varint(buf, pos)?;
+        }
+        1 => *pos += 8,
+        2 => {
+            read_bytes(buf, pos)?;
+        }
+        5 => *pos += 4,
+        _ => anyhow::bail!("Unsupported protobuf wire type {wire_type}"),
+    }
+    Ok(())
+}
+
+fn read_f32(buf: &[u8]) -> f32 {
+    f32::from_le_bytes([buf[0], buf[1], buf[2], buf[3]])
+}
+
+// tei.v1.EmbedRequest { string inputs = 1; bool truncate = 2; bool normalize = 3;
+// TruncationDirection truncation_direction = 4; optional string prompt_name = 5; }
+// The message is wrapped into gRPC frame: compression flag (1 byte), message length (4 bytes big endian)
+pub fn encode_embed_request(
+    input: &str,
+    truncate: bool,
+    normalize: bool,
+    truncation_direction: &str,
+    prompt_name: Option<&str>,
+) -> Vec<u8> {
+    let mut message = Vec::with_capacity(input.len() + 16);
+    encode_string_field(1, input, &mut message);
+    if truncate {
+        encode_varint_field(2, 1, &mut message);
+    }
+    if normalize {
+        encode_varint_field(3, 1, &mut message);
+    }
+    if truncation_direction == "left" {
+        encode_varint_field(4, 1, &mut message);
+    }
+    if let Some(prompt_name) = prompt_name {
+        encode_string_field(5, prompt_name, &mut message);
+    }
+
+    let mut frame = Vec::with_capacity(message.len() + 5);
+    frame.push(0);
+    frame.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&message);
+    frame
+}
+
+// tei.v1.EmbedResponse { repeated float embeddings = 1; Metadata metadata = 2; }
+// Metadata { uint32 compute_chars = 1; uint32 compute_tokens = 2; ... }
+fn decode_embed_response(message: &[u8]) -> Result<(Vec<f32>, usize), anyhow::Error> {
+    let mut pos = 0;
+    let mut embedding = Vec::new();
+    let mut compute_tokens = 0;
+
+    while pos < message.len() {
+        let key = decode_varint(message, &mut pos)?;
+        match (key >> 3, key & 7) {
+            // packed repeated float
+            (1, 2) => {
+                let bytes = read_bytes(message, &mut pos)?;
+                embedding.extend(bytes.chunks_exact(4).map(read_f32));
+            }
+            (1, 5) => {
+                if pos + 4 > message.len() {
+                    anyhow::bail!("Unexpected end of protobuf message");
+                }
+                embedding.push(read_f32(&message[pos..pos + 4]));
+                pos += 4;
+            }
+            (2, 2) => {
+                let metadata = read_bytes(message, &mut pos)?;
+                let mut metadata_pos = 0;
+                while metadata_pos < metadata.len() {
+                    let key = decode_varint(metadata, &mut metadata_pos)?;
+                    if key == (2 << 3) {
+                        compute_tokens = decode_varint(metadata, &mut metadata_pos)? as usize;
+                    } else {
+                        skip_field(key & 7, metadata, &mut metadata_pos)?;
+                    }
+                }
+            }
+            (_, wire_type) => skip_field(wire_type, message, &mut pos)?,
+        }
+    }
+
+    Ok((embedding, compute_tokens))
+}
+
+// Decode stream of gRPC framed EmbedResponse messages
+pub fn decode_embed_responses(body: &[u8]) -> Result<EmbeddingResult, anyhow::Error> {
+    let mut pos = 0;
+    let mut embeddings = Vec::new();
+    let mut processed_tokens = 0;
+
+    while pos < body.len() {
+        if pos + 5 > body.len() {
+            anyhow::bail!("Invalid gRPC frame");
+        }
+        if body[pos] != 0 {
+            anyhow::bail!("Compressed gRPC messages are not supported");
+        }
+        let len = u32::from_be_bytes([body[pos + 1], body[pos + 2], body[pos + 3], body[pos + 4]])
+            as usize;
+        pos += 5;
+        if pos + len > body.len() {
+            anyhow::bail!("Invalid gRPC frame");
+        }
+        let (embedding, tokens) = decode_embed_response(&body[pos..pos + len])?;
+        embeddings.push(embedding);
+        processed_tokens += tokens;
+        pos += len;
+    }
+
+    Ok(EmbeddingResult {
+        embeddings,
+        processed_tokens,
+    })
+}
+
+impl<'a> TeiRuntime<'a> {
+    pub fn new(logger: &'a LoggerFn, params: &str) -> Result<Self, anyhow::Error> {
+        let runtime_params: TeiRuntimeParams = serde_json::from_str(params)?;
+
+        let base_url = match runtime_params.base_url {
+            Some(base_url) => base_url.trim_end_matches('/').to_owned(),
+            None => anyhow::bail!("'base_url' is required for TEI runtime"),
+        };
+        let url = url::Url::parse(&base_url)?;
+        if url.scheme() != "http" && url.scheme() != "https" {
+            anyhow::bail!("Invalid base url for TEI runtime: {base_url}");
+        }
+
+        let protocol = match runtime_params.protocol.as_deref() {
+            None | Some("http") => TeiProtocol::Http,
+            Some("grpc") => TeiProtocol::Grpc,
+            Some(protocol) => {
+                anyhow::bail!("Invalid protocol '{protocol}'. Supported values: http, grpc")
+            }
+        };
+
+        let truncation_direction = runtime_params
+            .truncation_direction
+            .unwrap_or("right".to_owned());
+        if !TRUNCATION_DIRECTIONS.contains(&truncation_direction.as_str()) {
+            anyhow::bail!(
+                "Invalid truncation_direction '{truncation_direction}'. Supported values: {}",
+                TRUNCATION_DIRECTIONS.join(", ")
+            );
+        }
+
+        if runtime_params.max_batch_size == Some(0) {
+            anyhow::bail!("'max_batch_size' should be greater than 0");
+        }
+
+        let mut headers = vec![(
+            "Content-Type".to_owned(),
+            if protocol == TeiProtocol::Grpc {
+                "application/grpc".to_owned()
+            } else {
+                "application/json".to_owned()
+            },
+        )];
+        if let Some(api_key) = runtime_params.api_key {
+            headers.push(("Authorization".to_owned(), format!("Bearer {api_key}")));
+        }
+        if protocol == TeiProtocol::Grpc {
+            headers.push(("TE".to_owned(), "trailers".to_owned()));
+        }
+
+        Ok(Self {
+            base_url,
+            logger,
+            request_timeout: runtime_params.request_timeout.unwrap_or(120),
+            // TEI rejects batches larger than --max-client-batch-size (32 by default)
+            max_batch_size: runtime_params.max_batch_size.unwrap_or(32),
+            headers,
+            protocol,
+            truncate: runtime_params.truncate.unwrap_or(true),
+            normalize: runtime_params.normalize.unwrap_or(true),
+            truncation_direction,
+            prompt_name: runtime_params.prompt_name,
+            region: runtime_params.region,
+        })
+    }
+
+    fn chunk_inputs(
+        &self,
+        _model_name: &str,
+        inputs: &[&str],
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let truncation_direction = if self.truncation_direction == "left" {
+            "Left"
+        } else {
+            "Right"
+        };
+
+        Ok(inputs
+            .chunks(self.max_batch_size)
+            .map(|token_group| {
+                let mut body = serde_json::json!({
+                    "inputs": token_group,
+                    "truncate": self.truncate,
+                    "normalize": self.normalize,
+                    "truncation_direction": truncation_direction,
+                });
+                if let Some(prompt_name) = &self.prompt_name {
+                    body["prompt_name"] = prompt_name.clone().into();
+                }
+                body.to_string()
+            })
+            .collect())
+    }
+
+    // HTTP API does not report token usage, so processed tokens will be 0
+    pub fn get_response(body: Vec<u8>) -> Result<EmbeddingResult, anyhow::Error> {
+        let result: Result<Vec<Vec<f32>>, serde_json::Error> = serde_json::from_slice(&body);
+        if let Err(e) = result {
+            anyhow::bail!(
+                "Error: {e}. TEI response: {:?}",
+                serde_json::from_slice::<serde_json::Value>(&body)?
+            );
+        }
+
+        Ok(EmbeddingResult {
+            embeddings: result.unwrap(),
+            processed_tokens: 0,
+        })
+    }
+
+    // Each batch is sent as one EmbedStream call with a request message per input
+    // and the server responds with embeddings in the same order
+    fn process_grpc(&self, inputs: &[&str]) -> Result<EmbeddingResult, anyhow::Error> {
+        let client = self.get_grpc_client()?;
+        let url = format!("{}{GRPC_EMBED_STREAM_PATH}", self.base_url);
+        let mut embeddings = Vec::with_capacity(inputs.len());
+        let mut processed_tokens = 0;
+
+        for batch in inputs.chunks(self.max_batch_size) {
+            let body: Vec<u8> = batch
+                .iter()
+                .flat_map(|input| {
+                    encode_embed_request(
+                        input,
+                        self.truncate,
+                        self.normalize,
+                        &self.truncation_direction,
+                        self.prompt_name.as_deref(),
+                    )
+                })
+                .collect();
+
+            let result = self.send_grpc_with_retries(&client, &url, body)?;
+            if result.embeddings.len() != batch.len() {
+                anyhow::bail!(
+                    "TEI returned {} embeddings for {} inputs",
+                    result.embeddings.len(),
+                    batch.len()
+                );
+            }
+            processed_tokens += result.processed_tokens;
+            embeddings.extend(result.embeddings);
+        }
+
+        Ok(EmbeddingResult {
+            embeddings,
+            processed_tokens,
+        })
+    }
+
+    fn get_grpc_client(&self) -> Result<isahc::HttpClient, anyhow::Error> {
+        let mut client = isahc::HttpClient::builder()
+            .timeout(Duration::from_secs(self.request_timeout))
+            .version_negotiation(VersionNegotiation::http2());
+
+        for header in &self.headers {
+            client = client.default_header(header.0.clone(), header.1.clone());
+        }
+
+        Ok(client.build()?)
+    }
+
+    fn send_grpc_with_retries(
+        &self,
+        client: &isahc::HttpClient,
+        url: &str,
+        body: Vec<u8>,
+    ) -> Result<EmbeddingResult, anyhow::Error> {
+        let starting_interval = 4000; // ms
+        let mut last_error = String::new();
+
+        for i in 0..GRPC_MAX_RETRIES {
+            if i > 0 {
+                metrics::inc_counter(Subsystem::Embeddings, "request_retries_total", &[], 1.0);
+            }
+            match client.send(Request::post(url).body(body.clone())?) {
+                Err(e) => {
+                    eprintln!("Request error: url: {url}, error: {e}, retry: {i}");
+                    last_error = e.to_string();
+                }
+                Ok(mut response) => {
+                    let mut response_body = Vec::new();
+                    response.copy_to(&mut response_body)?;
+
+                    // Status is sent in headers for trailers-only responses and in trailers otherwise
+                    let headers = if response.headers().contains_key("grpc-status") {
+                        response.headers()
+                    } else {
+                        response.trailer().wait()
+                    };
+                    let status = headers
+                        .get("grpc-status")
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or("");
+                    let message = headers
+                        .get("grpc-message")
+                        .and_then(|value| value.to_str().ok())
+                        .unwrap_or("");
+
+                    if status == "0" {
+                        return decode_embed_responses(&response_body);
+                    }
+
+                    last_error = format!(
+                        "gRPC status: {status}, message: {message}, http status: {}",
+                        response.status()
+                    );
+                    if !GRPC_RETRY_STATUSES.contains(&status) {
+                        anyhow::bail!("TEI request failed. {last_error}");
+                    }
+                    eprintln!("Request error: url: {url}, error: {last_error}, retry: {i}");
+                }
+            }
+
+            std::thread::sleep(Duration::from_millis((starting_interval * (i + 1)) as u64));
+        }
+
+        anyhow::bail!("All {GRPC_MAX_RETRIES} requests failed. Last error was: {last_error}")
+    }
+}
+
+impl<'a> EmbeddingRuntime for TeiRuntime<'a> {
+    fn process(
+        &self,
+        model_name: &str,
+        inputs: &Vec<&str>,
+    ) -> Result<EmbeddingResult, anyhow::Error> {
+        match self.protocol {
+            TeiProtocol::Http => self.post_request("/embed", model_name, inputs),
+            TeiProtocol::Grpc => self.process_grpc(inputs),
+        }
+    }
+
+    fn count_requests(&self, model_name: &str, inputs: &Vec<&str>) -> Result<usize, anyhow::Error> {
+        Ok(self.chunk_inputs(model_name, inputs)?.len())
+    }
+
+    fn get_available_models(&self) -> (String, Vec<(String, bool)>) {
+        (
+            format!(
+                "Model served by TEI at {} can be used with any --model name\n",
+                self.base_url
+            ),
+            Vec::new(),
+        )
+    }
+
+    fn get_models_info(&self) -> Vec<runtime::ModelInfo> {
+        Vec::new()
+    }
+
+    // The model is chosen by the server, so any name is accepted and dimensions are unknown
+    fn get_model_info(&self, model_name: &str) -> Option<runtime::ModelInfo> {
+        Some(runtime::ModelInfo {
+            name: model_name.to_owned(),
+            dims: None,
+            max_tokens: None,
+            modality: Modality::Text,
+            provider: "tei".to_owned(),
+        })
+    }
+
+    fn get_region(&self) -> Option<String> {
+        self.region.clone()
+    }
+}
+HTTPRuntime!(TeiRuntime);
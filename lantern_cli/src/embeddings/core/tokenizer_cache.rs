@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use tiktoken_rs::{cl100k_base, CoreBPE};
+use tokenizers::Tokenizer;
+
+lazy_static! {
+    pub static ref TIKTOKEN_CACHE: TokenizerCache<CoreBPE> =
+        TokenizerCache::new(TOKENIZER_CACHE_CAPACITY);
+    pub static ref HF_TOKENIZER_CACHE: TokenizerCache<Tokenizer> =
+        TokenizerCache::new(TOKENIZER_CACHE_CAPACITY);
+}
+
+static TOKENIZER_CACHE_CAPACITY: usize = 16;
+pub static CL100K_BASE: &str = "cl100k_base";
+
+type CacheSlot<T> = Arc<Mutex<Option<Arc<T>>>>;
+
+struct CacheEntry<T> {
+    slot: CacheSlot<T>,
+    last_used: u64,
+}
+
+struct CacheState<T> {
+    entries: HashMap<String, CacheEntry<T>>,
+    tick: u64,
+    loads: usize,
+}
+
+// Process-wide cache of loaded tokenizers shared by all workers and jobs
+// Tokenizers are loaded lazily on first use. When the capacity is exceeded the least
+// recently used tokenizer is evicted, holders of the evicted tokenizer can still use it
+pub struct TokenizerCache<T> {
+    state: Mutex<CacheState<T>>,
+    capacity: usize,
+}
+
+impl<T> TokenizerCache<T> {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(CacheState {
+                entries: HashMap::new(),
+                tick: 0,
+                loads: 0,
+            }),
+            capacity,
+        }
+    }
+
+    fn get_slot(&self, key: &str) -> CacheSlot<T> {
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+
+        if let Some(entry) = state.entries.get_mut(key) {
+            entry.last_used = tick;
+            return entry.slot.clone();
+        }
+
+        if state.entries.len() >= self.capacity {
+            let lru_key = state
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(lru_key) = lru_key {
+                state.entries.remove(&lru_key);
+            }
+        }
+
+        let slot: CacheSlot<T> = Arc::new(Mutex::new(None));
+        state.entries.insert(
+            key.to_owned(),
+            CacheEntry {
+                slot: slot.clone(),
+                last_used: tick,
+            },
+        );
+        slot
+    }
+
+    // Only the slot of the requested key is locked while loading, so workers waiting
+    // for the same tokenizer will get the loaded instance and other keys are not blocked
+    pub fn get_or_load<F>(&self, key: &str, load: F) -> Result<Arc<T>, anyhow::Error>
+    where
+        F: FnOnce() -> Result<T, anyhow::Error>,
+    {
+        let slot = self.get_slot(key);
+        let mut tokenizer = slot.lock().unwrap();
+        if let Some(tokenizer) = tokenizer.as_ref() {
+            return Ok(tokenizer.clone());
+        }
+
+        let loaded = Arc::new(load()?);
+        *tokenizer = Some(loaded.clone());
+        self.state.lock().unwrap().loads += 1;
+        Ok(loaded)
+    }
+
+    pub fn evict(&self, key: &str) {
+        self.state.lock().unwrap().entries.remove(key);
+    }
+
+    pub fn len(&self) -> usize {
+        self.state.lock().unwrap().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Number of times a tokenizer was loaded
+    pub fn loads(&self) -> usize {
+        self.state.lock().unwrap().loads
+    }
+}
+
+pub fn get_cl100k_base() -> Result<Arc<CoreBPE>, anyhow::Error> {
+    TIKTOKEN_CACHE.get_or_load(CL100K_BASE, cl100k_base)
+}
@@ -1,76 +1,219 @@
 use anyhow::anyhow;
-use isahc::config::RedirectPolicy;
-use isahc::{prelude::*, HttpClient};
+use isahc::config::{Configurable, RedirectPolicy};
+use isahc::http::StatusCode;
+use isahc::{prelude::*, HttpClient, Request};
+use nvml_wrapper::struct_wrappers::device::MemoryInfo;
 use nvml_wrapper::Nvml;
+use ring::digest;
+use std::fs::{create_dir_all, File, OpenOptions};
+use std::io::Read;
 use std::ops::Deref;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::{fs::create_dir_all, time::Duration};
+use std::time::Duration;
 use sysinfo::{System, SystemExt};
 
 use super::runtime::EmbeddingResult;
+use crate::metrics::{self, Subsystem};
 
-type GetResponseFn = Box<dyn Fn(Vec<u8>) -> Result<EmbeddingResult, anyhow::Error> + Send + Sync>;
+type GetResponseFn<T = EmbeddingResult> =
+    Box<dyn Fn(Vec<u8>) -> Result<T, anyhow::Error> + Send + Sync>;
 
-pub fn download_file(url: &str, path: &PathBuf) -> Result<(), anyhow::Error> {
+static DOWNLOAD_RETRIES: usize = 3;
+// Incomplete downloads are kept next to the target file and resumed on the next attempt
+pub static PARTIAL_FILE_SUFFIX: &str = ".part";
+// Checksum of the downloaded file is stored next to it to verify the cache later
+pub static CHECKSUM_FILE_SUFFIX: &str = ".sha256";
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+pub fn get_partial_path(path: &Path) -> PathBuf {
+    with_suffix(path, PARTIAL_FILE_SUFFIX)
+}
+
+pub fn get_checksum_path(path: &Path) -> PathBuf {
+    with_suffix(path, CHECKSUM_FILE_SUFFIX)
+}
+
+fn is_sha256_hex(value: &str) -> bool {
+    value.len() == 64 && value.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, anyhow::Error> {
+    let mut file = File::open(path)?;
+    let mut context = digest::Context::new(&digest::SHA256);
+    let mut buf = vec![0; 1024 * 1024];
+
+    loop {
+        let read = file.read(&mut buf)?;
+        if read == 0 {
+            break;
+        }
+        context.update(&buf[..read]);
+    }
+
+    Ok(context
+        .finish()
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect())
+}
+
+// Hugging Face returns sha256 of LFS files in x-linked-etag header of the redirect response
+// None is returned if the server does not provide the checksum
+fn get_expected_checksum(client: &HttpClient, url: &str) -> Option<String> {
+    let request = Request::head(url)
+        .redirect_policy(RedirectPolicy::None)
+        .body(())
+        .ok()?;
+    let response = client.send(request).ok()?;
+    let checksum = response
+        .headers()
+        .get("x-linked-etag")?
+        .to_str()
+        .ok()?
+        .trim_start_matches("W/")
+        .trim_matches('"')
+        .to_lowercase();
+
+    is_sha256_hex(&checksum).then_some(checksum)
+}
+
+// Appends the response to the partial file
+// If the server ignores the range request the file is downloaded from the start
+fn download_to_partial_file(
+    client: &HttpClient,
+    url: &str,
+    partial_path: &Path,
+) -> Result<(), anyhow::Error> {
+    let offset = std::fs::metadata(partial_path)
+        .map(|meta| meta.len())
+        .unwrap_or(0);
+
+    let mut request = Request::get(url);
+    if offset > 0 {
+        request = request.header("Range", format!("bytes={offset}-"));
+    }
+    let mut response = client.send(request.body(())?)?;
+    let status = response.status();
+
+    let mut file = match status {
+        StatusCode::PARTIAL_CONTENT => OpenOptions::new().append(true).open(partial_path)?,
+        StatusCode::RANGE_NOT_SATISFIABLE => {
+            // Partial file is bigger than the remote file, it should be downloaded again
+            std::fs::remove_file(partial_path)?;
+            anyhow::bail!("Failed to resume download of {url}. Status {status}");
+        }
+        status if status.is_success() => File::create(partial_path)?,
+        _ => anyhow::bail!("Failed to download {url}. Status {status}"),
+    };
+
+    std::io::copy(response.body_mut(), &mut file)?;
+    file.sync_all()?;
+    Ok(())
+}
+
+// Downloads the file to {path}.part and renames it to path after the checksum is verified
+// Interrupted downloads are resumed from the partial file
+// If expected_checksum is None, the checksum provided by the server is used if any
+pub fn download_file_with_checksum(
+    url: &str,
+    path: &PathBuf,
+    expected_checksum: Option<&str>,
+) -> Result<String, anyhow::Error> {
     let client = HttpClient::builder()
         .timeout(Duration::from_secs(600))
         .redirect_policy(RedirectPolicy::Limit(2))
         .build()?;
 
-    let mut response = client.get(url)?;
-    // Copy the response body to the local file
     create_dir_all(path.parent().unwrap())?;
-    let mut file = std::fs::File::create(path)?;
-    std::io::copy(response.body_mut(), &mut file).expect("Failed writing response to file");
-    Ok(())
-}
+    let partial_path = get_partial_path(path);
+    let expected_checksum = match expected_checksum {
+        Some(checksum) => Some(checksum.to_lowercase()),
+        None => get_expected_checksum(&client, url),
+    };
 
-pub fn get_available_memory() -> Result<u64, anyhow::Error> {
-    let mut _nvml_instance = None;
-    let mut gpu_device = None;
-
-    if let Ok(nvml) = Nvml::init() {
-        _nvml_instance = Some(nvml);
-        let _nvml_insteance = _nvml_instance.as_mut().unwrap();
-        let nvml_device = _nvml_insteance.device_by_index(0);
-        if let Ok(device) = nvml_device {
-            gpu_device = Some(device);
+    let mut last_error = None;
+    for i in 0..DOWNLOAD_RETRIES {
+        match download_to_partial_file(&client, url, &partial_path) {
+            Ok(()) => {
+                last_error = None;
+                break;
+            }
+            Err(e) => {
+                // TODO:: use logger
+                eprintln!("Download error: url: {url}, error: {e}, retry: {i}");
+                last_error = Some(e);
+                std::thread::sleep(Duration::from_millis(500 * (i as u64 + 1)));
+            }
         }
     }
 
-    if gpu_device.is_none() {
-        let mut sys = System::new_all();
-        sys.refresh_all();
-        return Ok(sys.total_memory() - sys.used_memory());
+    if let Some(e) = last_error {
+        anyhow::bail!(
+            "All {DOWNLOAD_RETRIES} attempts to download {url} failed. Last error was: {e}"
+        );
     }
 
-    let gpu_device = gpu_device.as_ref().unwrap();
+    let checksum = sha256_file(&partial_path)?;
+    if let Some(expected_checksum) = expected_checksum {
+        if checksum != expected_checksum {
+            // Corrupted file can not be resumed, so it is removed
+            std::fs::remove_file(&partial_path)?;
+            anyhow::bail!(
+                "Checksum mismatch for {url}: expected sha256 {expected_checksum}, got {checksum}"
+            );
+        }
+    }
 
-    let mem_info = gpu_device.memory_info()?;
-    Ok(mem_info.free)
+    std::fs::write(get_checksum_path(path), &checksum)?;
+    std::fs::rename(&partial_path, path)?;
+    Ok(checksum)
 }
 
-pub fn percent_gpu_memory_used() -> Result<f64, anyhow::Error> {
-    let mut _nvml_instance = None;
-    let mut gpu_device = None;
+pub fn download_file(url: &str, path: &PathBuf) -> Result<(), anyhow::Error> {
+    download_file_with_checksum(url, path, None)?;
+    Ok(())
+}
 
-    if let Ok(nvml) = Nvml::init() {
-        _nvml_instance = Some(nvml);
-        let _nvml_insteance = _nvml_instance.as_mut().unwrap();
-        let nvml_device = _nvml_insteance.device_by_index(0);
-        if let Ok(device) = nvml_device {
-            gpu_device = Some(device);
-        }
-    }
+// Returns None if NVML is not available or there is no GPU with the given index
+fn get_gpu_memory_info(device_index: u32) -> Option<MemoryInfo> {
+    let nvml = Nvml::init().ok()?;
+    let device = nvml.device_by_index(device_index).ok()?;
+    device.memory_info().ok()
+}
+
+// Returns number of NVIDIA GPUs or None if NVML is not available
+pub fn get_gpu_count() -> Option<u32> {
+    Nvml::init().ok()?.device_count().ok()
+}
 
-    if gpu_device.is_none() {
-        return Ok(0.0);
+pub fn get_gpu_free_memory(device_index: u32) -> Option<u64> {
+    get_gpu_memory_info(device_index).map(|mem_info| mem_info.free)
+}
+
+// Free memory of the GPU if index is passed and GPU is found, otherwise free RAM
+pub fn get_available_memory(gpu_index: Option<u32>) -> Result<u64, anyhow::Error> {
+    if let Some(free) = gpu_index.and_then(get_gpu_free_memory) {
+        return Ok(free);
     }
 
-    let gpu_device = gpu_device.as_ref().unwrap();
+    let mut sys = System::new_all();
+    sys.refresh_all();
+    Ok(sys.total_memory() - sys.used_memory())
+}
+
+pub fn percent_gpu_memory_used(gpu_index: u32) -> Result<f64, anyhow::Error> {
+    let mem_info = match get_gpu_memory_info(gpu_index) {
+        Some(mem_info) => mem_info,
+        None => return Ok(0.0),
+    };
 
-    let mem_info = gpu_device.memory_info()?;
     // Sometimes models consume much more memory based on input size.
     // In my experiments the 200mb model was consuming 3GB memory.
     // So comparing model size with free GPU memory will not be correct here.
@@ -80,18 +223,44 @@ pub fn percent_gpu_memory_used() -> Result<f64, anyhow::Error> {
     Ok((mem_info.used as f64 / mem_info.total as f64) * 100.0)
 }
 
+// Headers are added to the client default headers
+// This is used for the headers which differ per request (e.g request signature)
 pub async fn post_with_retries(
     client: Arc<HttpClient>,
     url: String,
     body: String,
+    headers: Vec<(String, String)>,
     get_response_fn: GetResponseFn,
     max_retries: usize,
 ) -> Result<EmbeddingResult, anyhow::Error> {
+    post_with_retries_as(client, url, body, headers, get_response_fn, max_retries).await
+}
+
+// Same as post_with_retries for responses which are not embeddings
+pub async fn post_with_retries_as<T>(
+    client: Arc<HttpClient>,
+    url: String,
+    body: String,
+    headers: Vec<(String, String)>,
+    get_response_fn: GetResponseFn<T>,
+    max_retries: usize,
+) -> Result<T, anyhow::Error> {
     let starting_interval = 4000; // ms
     let mut last_error = "".to_string();
 
     for i in 0..max_retries {
-        match client.post_async(&url, body.deref()).await {
+        if i > 0 {
+            metrics::inc_counter(Subsystem::Embeddings, "request_retries_total", &[], 1.0);
+        }
+        let mut request = Request::post(&url);
+        for (name, value) in &headers {
+            request = request.header(name, value);
+        }
+
+        match client
+            .send_async(request.body(body.deref().to_owned())?)
+            .await
+        {
             Err(e) => {
                 // TODO:: use logger
                 eprintln!("Request error: url: {url}, error: {e}, retry: {i}");
@@ -100,6 +269,22 @@ pub async fn post_with_retries(
                 tokio::time::sleep(Duration::from_millis((starting_interval * (i + 1)) as u64))
                     .await;
             }
+            Ok(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => {
+                // Rate limited requests are retried after the interval requested by provider
+                let retry_after = response
+                    .headers()
+                    .get("retry-after")
+                    .and_then(|value| value.to_str().ok())
+                    .and_then(|value| value.trim().parse::<f64>().ok())
+                    .map(|seconds| Duration::from_millis((seconds * 1000.0) as u64))
+                    .unwrap_or(Duration::from_millis((starting_interval * (i + 1)) as u64));
+                eprintln!(
+                    "Rate limited: url: {url}, retry after: {}ms, retry: {i}",
+                    retry_after.as_millis()
+                );
+                last_error = "Rate limit exceeded".to_string();
+                tokio::time::sleep(retry_after).await;
+            }
             Ok(mut response) => {
                 let mut body: Vec<u8> = Vec::with_capacity(body.capacity());
                 response.copy_to(&mut body).await?;
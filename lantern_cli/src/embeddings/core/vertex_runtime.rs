@@ -0,0 +1,452 @@
+use core::time::Duration;
+use gcp_auth::{AuthenticationManager, CustomServiceAccount};
+use isahc::{config::RedirectPolicy, prelude::*, HttpClient};
+use itertools::Itertools;
+use std::sync::{Arc, Mutex};
+use std::{collections::HashMap, sync::RwLock};
+use tokio::runtime::Runtime;
+
+use super::{
+    runtime::{self, EmbeddingResult, EmbeddingRuntime, Modality},
+    utils::post_with_retries,
+    LoggerFn,
+};
+use serde::{Deserialize, Serialize};
+
+struct ModelInfo {
+    name: String,
+    sequence_len: usize,
+    dimensions: usize,
+    var_dimension: bool,
+    task_type: bool,
+    max_batch_size: usize,
+}
+
+#[derive(Deserialize)]
+struct VertexStatistics {
+    token_count: f64,
+}
+
+#[derive(Deserialize)]
+struct VertexEmbedding {
+    values: Vec<f32>,
+    statistics: VertexStatistics,
+}
+
+#[derive(Deserialize)]
+struct VertexPrediction {
+    embeddings: VertexEmbedding,
+}
+
+#[derive(Deserialize)]
+struct VertexResponse {
+    predictions: Vec<VertexPrediction>,
+}
+
+impl ModelInfo {
+    pub fn new(model_name: &str) -> Result<Self, anyhow::Error> {
+        let name = model_name.split("/").last().unwrap().to_owned();
+        match model_name {
+            "vertex/textembedding-gecko@001" => Ok(Self {
+                name,
+                sequence_len: 3072,
+                dimensions: 768,
+                var_dimension: false,
+                task_type: false,
+                max_batch_size: 5,
+            }),
+            "vertex/textembedding-gecko@003" => Ok(Self {
+                name,
+                sequence_len: 3072,
+                dimensions: 768,
+                var_dimension: false,
+                task_type: true,
+                max_batch_size: 250,
+            }),
+            "vertex/textembedding-gecko-multilingual@001" => Ok(Self {
+                name,
+                sequence_len: 2048,
+                dimensions: 768,
+                var_dimension: false,
+                task_type: true,
+                max_batch_size: 5,
+            }),
+            "vertex/text-embedding-004" => Ok(Self {
+                name,
+                sequence_len: 2048,
+                dimensions: 768,
+                var_dimension: true,
+                task_type: true,
+                max_batch_size: 250,
+            }),
+            "vertex/text-multilingual-embedding-002" => Ok(Self {
+                name,
+                sequence_len: 2048,
+                dimensions: 768,
+                var_dimension: true,
+                task_type: true,
+                max_batch_size: 250,
+            }),
+            _ => anyhow::bail!("Unsupported model {model_name}"),
+        }
+    }
+}
+
+lazy_static! {
+    static ref MODEL_INFO_MAP: RwLock<HashMap<&'static str, ModelInfo>> =
+        RwLock::new(HashMap::from([
+            (
+                "vertex/textembedding-gecko@001",
+                ModelInfo::new("vertex/textembedding-gecko@001").unwrap()
+            ),
+            (
+                "vertex/textembedding-gecko@003",
+                ModelInfo::new("vertex/textembedding-gecko@003").unwrap()
+            ),
+            (
+                "vertex/textembedding-gecko-multilingual@001",
+                ModelInfo::new("vertex/textembedding-gecko-multilingual@001").unwrap()
+            ),
+            (
+                "vertex/text-embedding-004",
+                ModelInfo::new("vertex/text-embedding-004").unwrap()
+            ),
+            (
+                "vertex/text-multilingual-embedding-002",
+                ModelInfo::new("vertex/text-multilingual-embedding-002").unwrap()
+            ),
+        ]));
+}
+
+enum VertexAuth {
+    // Access token passed in runtime params
+    Token(String),
+    // Service account key passed in runtime params
+    ServiceAccount(String),
+    // Application Default Credentials resolved on first request
+    Default,
+}
+
+pub struct VertexRuntime<'a> {
+    request_timeout: u64,
+    base_url: String,
+    project_id: String,
+    location: String,
+    task_type: String,
+    dimensions: Option<usize>,
+    auth: VertexAuth,
+    authentication_manager: Mutex<Option<Arc<AuthenticationManager>>>,
+    #[allow(dead_code)]
+    logger: &'a LoggerFn,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VertexRuntimeParams {
+    pub project_id: Option<String>,
+    pub location: Option<String>,
+    pub access_token: Option<String>,
+    pub service_account_json: Option<String>,
+    pub service_account_file: Option<String>,
+    pub task_type: Option<String>,
+    pub dimensions: Option<usize>,
+    pub base_url: Option<String>,
+}
+
+static TASK_TYPES: [&str; 7] = [
+    "RETRIEVAL_DOCUMENT",
+    "RETRIEVAL_QUERY",
+    "SEMANTIC_SIMILARITY",
+    "CLASSIFICATION",
+    "CLUSTERING",
+    "QUESTION_ANSWERING",
+    "FACT_VERIFICATION",
+];
+static DEFAULT_LOCATION: &str = "us-central1";
+static AUTH_SCOPES: [&str; 1] = ["https://www.googleapis.com/auth/cloud-platform"];
+
+impl<'a> VertexRuntime<'a> {
+    pub fn new(logger: &'a LoggerFn, params: &str) -> Result<Self, anyhow::Error> {
+        let runtime_params: VertexRuntimeParams = serde_json::from_str(params)?;
+
+        let service_account_json = match (
+            runtime_params.service_account_json,
+            runtime_params.service_account_file,
+        ) {
+            (Some(_), Some(_)) => {
+                anyhow::bail!("Only one of 'service_account_json' and 'service_account_file' can be specified")
+            }
+            (Some(json), None) => Some(json),
+            (None, Some(path)) => Some(std::fs::read_to_string(path)?),
+            (None, None) => None,
+        };
+
+        // Project id can be taken from service account key if not specified
+        let service_account_project_id = match &service_account_json {
+            Some(json) => serde_json::from_str::<serde_json::Value>(json)?
+                .get("project_id")
+                .and_then(|p| p.as_str())
+                .map(|p| p.to_owned()),
+            None => None,
+        };
+
+        let project_id = match runtime_params.project_id.or(service_account_project_id) {
+            Some(project_id) => project_id,
+            None => anyhow::bail!("'project_id' is required for Vertex runtime"),
+        };
+
+        let auth = match (runtime_params.access_token, service_account_json) {
+            (Some(_), Some(_)) => {
+                anyhow::bail!("Only one of 'access_token' and service account can be specified")
+            }
+            (Some(token), None) => VertexAuth::Token(token),
+            (None, Some(json)) => VertexAuth::ServiceAccount(json),
+            (None, None) => VertexAuth::Default,
+        };
+
+        let task_type = runtime_params
+            .task_type
+            .unwrap_or("RETRIEVAL_DOCUMENT".to_owned());
+        if !TASK_TYPES.contains(&task_type.as_str()) {
+            anyhow::bail!(
+                "Invalid task_type '{task_type}'. Supported values: {}",
+                TASK_TYPES.join(", ")
+            );
+        }
+
+        let location = runtime_params
+            .location
+            .unwrap_or(DEFAULT_LOCATION.to_owned());
+
+        Ok(Self {
+            base_url: runtime_params
+                .base_url
+                .unwrap_or(format!("https://{location}-aiplatform.googleapis.com")),
+            logger,
+            request_timeout: 120,
+            project_id,
+            location,
+            task_type,
+            dimensions: runtime_params.dimensions,
+            auth,
+            authentication_manager: Mutex::new(None),
+        })
+    }
+
+    // Tokens are cached and refreshed by authentication manager
+    // So long running jobs will not fail when the token expires
+    async fn get_token(&self) -> Result<String, anyhow::Error> {
+        let service_account_json = match &self.auth {
+            VertexAuth::Token(token) => return Ok(token.clone()),
+            VertexAuth::ServiceAccount(json) => Some(json),
+            VertexAuth::Default => None,
+        };
+
+        let existing_manager = self.authentication_manager.lock().unwrap().clone();
+        let manager = match existing_manager {
+            Some(manager) => manager,
+            None => {
+                let manager = Arc::new(match service_account_json {
+                    Some(json) => {
+                        AuthenticationManager::from(CustomServiceAccount::from_json(json)?)
+                    }
+                    None => AuthenticationManager::new().await?,
+                });
+                *self.authentication_manager.lock().unwrap() = Some(manager.clone());
+                manager
+            }
+        };
+
+        Ok(manager.get_token(&AUTH_SCOPES).await?.as_str().to_owned())
+    }
+
+    fn get_model_info<'b>(
+        model_map: &'b HashMap<&'static str, ModelInfo>,
+        model_name: &str,
+    ) -> Result<&'b ModelInfo, anyhow::Error> {
+        match model_map.get(model_name) {
+            Some(model_info) => Ok(model_info),
+            None => anyhow::bail!(
+                "Unsupported model {model_name}\nAvailable models: {}",
+                model_map.keys().join(", ")
+            ),
+        }
+    }
+
+    fn chunk_inputs(
+        &self,
+        model_info: &ModelInfo,
+        inputs: &Vec<&str>,
+    ) -> Result<Vec<String>, anyhow::Error> {
+        if let Some(dimensions) = self.dimensions {
+            if !model_info.var_dimension && dimensions != model_info.dimensions {
+                anyhow::bail!(
+                    "Model {} does not support custom dimensions. Supported dimensions: {}",
+                    model_info.name,
+                    model_info.dimensions
+                );
+            }
+            if dimensions == 0 || dimensions > model_info.dimensions {
+                anyhow::bail!(
+                    "Dimensions for model {} should be between 1 and {}",
+                    model_info.name,
+                    model_info.dimensions
+                );
+            }
+        }
+
+        let mut parameters = serde_json::json!({ "autoTruncate": true });
+        if let Some(dimensions) = self.dimensions {
+            if model_info.var_dimension {
+                parameters["outputDimensionality"] = serde_json::json!(dimensions);
+            }
+        }
+
+        let batch_tokens: Vec<String> = inputs
+            .chunks(model_info.max_batch_size)
+            .map(|token_group| {
+                let instances: Vec<serde_json::Value> = token_group
+                    .iter()
+                    .map(|input| {
+                        if model_info.task_type {
+                            serde_json::json!({ "content": input, "task_type": self.task_type })
+                        } else {
+                            serde_json::json!({ "content": input })
+                        }
+                    })
+                    .collect();
+                serde_json::json!({ "instances": instances, "parameters": parameters }).to_string()
+            })
+            .collect();
+
+        Ok(batch_tokens)
+    }
+
+    fn get_url(&self, model_info: &ModelInfo) -> String {
+        format!(
+            "{}/v1/projects/{}/locations/{}/publishers/google/models/{}:predict",
+            self.base_url.trim_end_matches('/'),
+            self.project_id,
+            self.location,
+            model_info.name
+        )
+    }
+
+    // Static functions
+    pub fn get_response(body: Vec<u8>) -> Result<EmbeddingResult, anyhow::Error> {
+        let result: Result<VertexResponse, serde_json::Error> = serde_json::from_slice(&body);
+        if let Err(e) = result {
+            anyhow::bail!(
+                "Error: {e}. Vertex response: {:?}",
+                serde_json::from_slice::<serde_json::Value>(&body)?
+            );
+        }
+
+        let result = result.unwrap();
+        let processed_tokens = result
+            .predictions
+            .iter()
+            .map(|p| p.embeddings.statistics.token_count as usize)
+            .sum();
+
+        Ok(EmbeddingResult {
+            embeddings: result
+                .predictions
+                .into_iter()
+                .map(|p| p.embeddings.values)
+                .collect(),
+            processed_tokens,
+        })
+    }
+}
+
+impl<'a> EmbeddingRuntime for VertexRuntime<'a> {
+    // Vertex runtime does not use HTTPRuntime macro, as the access token should be refreshed
+    // during the job and can not be set in default headers of the runtime
+    fn process(
+        &self,
+        model_name: &str,
+        inputs: &Vec<&str>,
+    ) -> Result<EmbeddingResult, anyhow::Error> {
+        let model_map = MODEL_INFO_MAP.read().unwrap();
+        let model_info = Self::get_model_info(&model_map, model_name)?;
+        let request_bodies = self.chunk_inputs(model_info, inputs)?;
+        let url = self.get_url(model_info);
+
+        let tokio_runtime = Runtime::new()?;
+        let token = tokio_runtime.block_on(self.get_token())?;
+        let client = Arc::new(
+            HttpClient::builder()
+                .timeout(Duration::from_secs(self.request_timeout))
+                .redirect_policy(RedirectPolicy::Limit(2))
+                .default_header("Content-Type", "application/json")
+                .default_header("Authorization", format!("Bearer {token}"))
+                .build()?,
+        );
+
+        let mut tasks = Vec::with_capacity(request_bodies.len());
+        for request_body in request_bodies {
+            tasks.push(tokio_runtime.spawn(post_with_retries(
+                client.clone(),
+                url.clone(),
+                request_body,
+                Vec::new(),
+                Box::new(Self::get_response),
+                5,
+            )));
+        }
+
+        tokio_runtime.block_on(async move {
+            let mut processed_tokens = 0;
+            let mut embeddings = Vec::with_capacity(inputs.len());
+            for task in tasks {
+                let embedding_response = task.await??;
+                processed_tokens += embedding_response.processed_tokens;
+                embeddings.extend(embedding_response.embeddings);
+            }
+            Ok(EmbeddingResult {
+                embeddings,
+                processed_tokens,
+            })
+        })
+    }
+
+    fn count_requests(&self, model_name: &str, inputs: &Vec<&str>) -> Result<usize, anyhow::Error> {
+        let model_map = MODEL_INFO_MAP.read().unwrap();
+        let model_info = Self::get_model_info(&model_map, model_name)?;
+        Ok(self.chunk_inputs(model_info, inputs)?.len())
+    }
+
+    fn get_available_models(&self) -> (String, Vec<(String, bool)>) {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        let mut res = String::new();
+        let mut models = Vec::with_capacity(map.len());
+        for (key, value) in &*map {
+            res.push_str(&format!(
+                "{} - sequence_len: {}, dimensions: {}\n",
+                key, value.sequence_len, value.dimensions
+            ));
+            models.push((key.to_string(), false));
+        }
+
+        (res, models)
+    }
+
+    fn get_models_info(&self) -> Vec<runtime::ModelInfo> {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        map.iter()
+            .map(|(key, value)| runtime::ModelInfo {
+                name: key.to_string(),
+                // Requests fail if the model does not support the dimensions from runtime params
+                dims: Some(self.dimensions.unwrap_or(value.dimensions)),
+                max_tokens: Some(value.sequence_len),
+                modality: Modality::Text,
+                provider: "vertex".to_owned(),
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect()
+    }
+
+    fn get_region(&self) -> Option<String> {
+        Some(self.location.clone())
+    }
+}
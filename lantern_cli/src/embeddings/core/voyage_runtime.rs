@@ -0,0 +1,239 @@
+use itertools::Itertools;
+use std::{collections::HashMap, sync::RwLock};
+
+use super::{
+    runtime::{self, EmbeddingResult, EmbeddingRuntime, Modality},
+    LoggerFn,
+};
+use crate::HTTPRuntime;
+use serde::{Deserialize, Serialize};
+
+struct ModelInfo {
+    name: String,
+    sequence_len: usize,
+    dimensions: usize,
+}
+
+#[derive(Deserialize)]
+struct VoyageEmbedding {
+    embedding: Vec<f32>,
+    index: usize,
+}
+
+#[derive(Deserialize)]
+struct VoyageUsage {
+    total_tokens: usize,
+}
+
+#[derive(Deserialize)]
+struct VoyageResponse {
+    data: Vec<VoyageEmbedding>,
+    usage: VoyageUsage,
+}
+
+impl ModelInfo {
+    pub fn new(model_name: &str) -> Result<Self, anyhow::Error> {
+        let name = model_name.split("/").last().unwrap().to_owned();
+        match model_name {
+            "voyage/voyage-large-2" => Ok(Self {
+                name,
+                sequence_len: 16000,
+                dimensions: 1536,
+            }),
+            "voyage/voyage-code-2" => Ok(Self {
+                name,
+                sequence_len: 16000,
+                dimensions: 1536,
+            }),
+            _ => anyhow::bail!("Unsupported model {model_name}"),
+        }
+    }
+}
+
+lazy_static! {
+    static ref MODEL_INFO_MAP: RwLock<HashMap<&'static str, ModelInfo>> =
+        RwLock::new(HashMap::from([
+            (
+                "voyage/voyage-large-2",
+                ModelInfo::new("voyage/voyage-large-2").unwrap()
+            ),
+            (
+                "voyage/voyage-code-2",
+                ModelInfo::new("voyage/voyage-code-2").unwrap()
+            ),
+        ]));
+}
+
+pub struct VoyageRuntime<'a> {
+    request_timeout: u64,
+    max_batch_size: usize,
+    base_url: String,
+    headers: Vec<(String, String)>,
+    input_type: Option<String>,
+    truncation: bool,
+    region: Option<String>,
+    #[allow(dead_code)]
+    logger: &'a LoggerFn,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct VoyageRuntimeParams {
+    pub api_token: Option<String>,
+    pub input_type: Option<String>,
+    pub truncation: Option<bool>,
+    pub base_url: Option<String>,
+    pub region: Option<String>,
+}
+
+pub static INPUT_TYPES: [&str; 2] = ["query", "document"];
+
+impl<'a> VoyageRuntime<'a> {
+    pub fn new(logger: &'a LoggerFn, params: &str) -> Result<Self, anyhow::Error> {
+        let runtime_params: VoyageRuntimeParams = serde_json::from_str(params)?;
+
+        if runtime_params.api_token.is_none() {
+            anyhow::bail!("'api_token' is required for Voyage runtime");
+        }
+
+        if let Some(input_type) = &runtime_params.input_type {
+            if !INPUT_TYPES.contains(&input_type.as_str()) {
+                anyhow::bail!(
+                    "Invalid input_type '{input_type}'. Supported values: {}",
+                    INPUT_TYPES.join(", ")
+                );
+            }
+        }
+
+        // Public Voyage API is served from us region
+        let region = match (&runtime_params.base_url, runtime_params.region) {
+            (None, None) => Some("us".to_owned()),
+            (None, Some(region)) if region == "us" => Some(region),
+            (None, Some(region)) => {
+                anyhow::bail!("Voyage API is not available in region '{region}'. Specify 'base_url' of the regional deployment");
+            }
+            (Some(_), region) => region,
+        };
+
+        Ok(Self {
+            base_url: runtime_params
+                .base_url
+                .unwrap_or("https://api.voyageai.com".to_owned()),
+            logger,
+            request_timeout: 120,
+            max_batch_size: 128,
+            input_type: runtime_params.input_type,
+            truncation: runtime_params.truncation.unwrap_or(true),
+            region,
+            headers: vec![
+                ("Content-Type".to_owned(), "application/json".to_owned()),
+                (
+                    "Authorization".to_owned(),
+                    format!("Bearer {}", runtime_params.api_token.unwrap()),
+                ),
+            ],
+        })
+    }
+
+    fn chunk_inputs(
+        &self,
+        model_name: &str,
+        inputs: &[&str],
+    ) -> Result<Vec<String>, anyhow::Error> {
+        let model_map = MODEL_INFO_MAP.read().unwrap();
+        let model_info = model_map.get(model_name);
+
+        if model_info.is_none() {
+            anyhow::bail!(
+                "Unsupported model {model_name}\nAvailable models: {}",
+                model_map.keys().join(", ")
+            );
+        }
+        let model_info = model_info.unwrap();
+
+        let batch_tokens: Vec<String> = inputs
+            .chunks(self.max_batch_size)
+            .map(|token_group| {
+                serde_json::json!({
+                    "input": token_group,
+                    "model": model_info.name,
+                    "input_type": self.input_type,
+                    "truncation": self.truncation,
+                })
+                .to_string()
+            })
+            .collect();
+
+        Ok(batch_tokens)
+    }
+
+    // Static functions
+    pub fn get_response(body: Vec<u8>) -> Result<EmbeddingResult, anyhow::Error> {
+        let result: Result<VoyageResponse, serde_json::Error> = serde_json::from_slice(&body);
+        if let Err(e) = result {
+            anyhow::bail!(
+                "Error: {e}. Voyage response: {:?}",
+                serde_json::from_slice::<serde_json::Value>(&body)?
+            );
+        }
+
+        let mut result = result.unwrap();
+        result.data.sort_by_key(|embedding| embedding.index);
+
+        Ok(EmbeddingResult {
+            embeddings: result
+                .data
+                .into_iter()
+                .map(|embedding| embedding.embedding)
+                .collect(),
+            processed_tokens: result.usage.total_tokens,
+        })
+    }
+}
+
+impl<'a> EmbeddingRuntime for VoyageRuntime<'a> {
+    fn process(
+        &self,
+        model_name: &str,
+        inputs: &Vec<&str>,
+    ) -> Result<EmbeddingResult, anyhow::Error> {
+        self.post_request("/v1/embeddings", model_name, inputs)
+    }
+
+    fn count_requests(&self, model_name: &str, inputs: &Vec<&str>) -> Result<usize, anyhow::Error> {
+        Ok(self.chunk_inputs(model_name, inputs)?.len())
+    }
+
+    fn get_available_models(&self) -> (String, Vec<(String, bool)>) {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        let mut res = String::new();
+        let mut models = Vec::with_capacity(map.len());
+        for (key, value) in &*map {
+            res.push_str(&format!(
+                "{} - sequence_len: {}, dimensions: {}\n",
+                key, value.sequence_len, value.dimensions
+            ));
+            models.push((key.to_string(), false));
+        }
+
+        (res, models)
+    }
+
+    fn get_models_info(&self) -> Vec<runtime::ModelInfo> {
+        let map = MODEL_INFO_MAP.read().unwrap();
+        map.iter()
+            .map(|(key, value)| runtime::ModelInfo {
+                name: key.to_string(),
+                dims: Some(value.dimensions),
+                max_tokens: Some(value.sequence_len),
+                modality: Modality::Text,
+                provider: "voyage".to_owned(),
+            })
+            .sorted_by(|a, b| a.name.cmp(&b.name))
+            .collect()
+    }
+
+    fn get_region(&self) -> Option<String> {
+        self.region.clone()
+    }
+}
+HTTPRuntime!(VoyageRuntime);
@@ -0,0 +1,165 @@
+use super::batcher::{Batch, TokenBatcher};
+use super::core::{get_runtime, Runtime};
+use super::{cli, get_filter_sql, get_max_tokens_per_batch, get_source_sql, CONNECTION_PARAMS};
+use crate::logger::Logger;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+use std::fmt;
+
+// List prices in USD per 1M input tokens
+// Runtimes billed by characters (e.g. Vertex AI) or self-hosted models are not listed
+static MODEL_PRICES: [(&str, f64); 17] = [
+    ("openai/text-embedding-ada-002", 0.10),
+    ("openai/text-embedding-3-small", 0.02),
+    ("openai/text-embedding-3-large", 0.13),
+    ("cohere/embed-english-v3.0", 0.10),
+    ("cohere/embed-multilingual-v3.0", 0.10),
+    ("cohere/embed-english-light-v3.0", 0.10),
+    ("cohere/embed-multilingual-light-v3.0", 0.10),
+    ("cohere/embed-english-v2.0", 0.10),
+    ("cohere/embed-english-light-v2.0", 0.10),
+    ("cohere/embed-multilingual-v2.0", 0.10),
+    ("voyage/voyage-large-2", 0.12),
+    ("voyage/voyage-code-2", 0.12),
+    ("mistral/mistral-embed", 0.10),
+    ("jina/jina-embeddings-v3", 0.02),
+    ("bedrock/amazon.titan-embed-text-v1", 0.10),
+    ("bedrock/cohere.embed-english-v3", 0.10),
+    ("bedrock/cohere.embed-multilingual-v3", 0.10),
+];
+
+// Price passed with --token-price takes precedence over the built-in price
+pub fn get_token_price(model: &str, price_override: Option<f64>) -> Option<f64> {
+    price_override.or_else(|| {
+        MODEL_PRICES
+            .iter()
+            .find(|(name, _)| *name == model)
+            .map(|(_, price)| *price)
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostReport {
+    pub model: String,
+    pub runtime: String,
+    pub rows: usize,
+    pub tokens: usize,
+    pub requests: usize,
+    // USD per 1M tokens, None if the price of the model is unknown
+    pub token_price: Option<f64>,
+}
+
+impl CostReport {
+    pub fn new(args: &cli::EmbeddingArgs, rows: usize, tokens: usize, requests: usize) -> Self {
+        CostReport {
+            model: args.model.clone(),
+            runtime: args.runtime.to_string(),
+            rows,
+            tokens,
+            requests,
+            token_price: get_token_price(&args.model, args.token_price),
+        }
+    }
+
+    pub fn estimated_cost(&self) -> Option<f64> {
+        self.token_price
+            .map(|price| self.tokens as f64 * price / 1_000_000.0)
+    }
+}
+
+impl fmt::Display for CostReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Cost report for {} ({}): {} rows, {} tokens, {} requests, ",
+            self.model, self.runtime, self.rows, self.tokens, self.requests
+        )?;
+        match self.estimated_cost() {
+            Some(cost) => write!(
+                f,
+                "estimated cost ${cost:.4} (${} per 1M tokens)",
+                self.token_price.unwrap()
+            ),
+            None => write!(f, "estimated cost unknown (pass --token-price)"),
+        }
+    }
+}
+
+// Local models do not have a per token cost
+pub fn is_billed_runtime(runtime: &Runtime) -> bool {
+    !matches!(runtime, Runtime::Ort)
+}
+
+// Read the source texts and count tokens with the runtime tokenizer without calling the API
+// Requests are counted by packing the texts into batches the same way as the embedding worker
+pub fn estimate_cost(
+    args: &cli::EmbeddingArgs,
+    batch_size: usize,
+    logger: &Logger,
+) -> Result<CostReport, anyhow::Error> {
+    if args.array_mode.is_some() || args.chunks_table.is_some() {
+        anyhow::bail!("--dry-run-cost can not be used with --array-mode or --chunks-table");
+    }
+
+    let runtime = get_runtime(&args.runtime, None, &args.runtime_params)?;
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let mut transaction = client.transaction()?;
+
+    let limit_sql = match args.limit {
+        Some(limit) => format!("LIMIT {limit}"),
+        None => "".to_owned(),
+    };
+    let select_sql = format!(
+        "SELECT {source_sql} FROM {full_table_name} {filter_sql} {limit_sql}",
+        source_sql = get_source_sql(args, &quote_ident(&args.column)),
+        full_table_name = get_full_table_name(&args.schema, &args.table),
+        filter_sql = get_filter_sql(args),
+    );
+    let portal = transaction.bind(&select_sql, &[])?;
+
+    let mut batcher = TokenBatcher::new(get_max_tokens_per_batch(args), batch_size);
+    let (mut rows, mut tokens, mut requests) = (0, 0, 0);
+    // Runtimes may split a batch into several HTTP requests
+    let count_requests = |(_, inputs, _): Batch| {
+        runtime.count_requests(&args.model, &inputs.iter().map(String::as_str).collect())
+    };
+    loop {
+        let batch = transaction.query_portal(&portal, batch_size as i32)?;
+        if batch.is_empty() {
+            break;
+        }
+
+        let inputs: Vec<&str> = batch
+            .iter()
+            .filter_map(|row| row.get::<usize, Option<&str>>(0))
+            .filter(|input| input.trim() != "")
+            .collect();
+        if inputs.is_empty() {
+            continue;
+        }
+
+        let token_counts = runtime.count_tokens(&args.model, &inputs)?;
+        rows += inputs.len();
+        tokens += token_counts.iter().sum::<usize>();
+
+        for (input, input_tokens) in inputs.into_iter().zip(token_counts) {
+            if let Some(batch) = batcher.push(String::new(), input.to_owned(), input_tokens) {
+                requests += count_requests(batch)?;
+            }
+        }
+        // Without token budget each fetched batch is sent as one batch
+        if !batcher.is_token_aware() {
+            if let Some(batch) = batcher.take() {
+                requests += count_requests(batch)?;
+            }
+        }
+    }
+    if let Some(batch) = batcher.take() {
+        requests += count_requests(batch)?;
+    }
+
+    let report = CostReport::new(args, rows, tokens, requests);
+    logger.info(&format!("[dry run] {report}"));
+    Ok(report)
+}
@@ -0,0 +1,196 @@
+use super::affinity::pin_current_thread_to;
+use super::cli::EmbeddingArgs;
+use super::exporter::Exporter;
+use super::object_store::OutputWriter;
+use super::precision::ValueFormat;
+use super::EmbeddingRecord;
+use crate::logger::Logger;
+use crate::types::*;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use std::collections::BTreeMap;
+use std::io::Write;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+// Serialized batch with the number of rows in it
+type Chunk = Result<(Vec<u8>, usize), anyhow::Error>;
+
+pub fn is_compressed(path: &str) -> bool {
+    path.ends_with(".gz")
+}
+
+// Each batch is compressed as a separate gzip member
+// Concatenated gzip members form a valid gzip file, so chunks can be written as is
+fn serialize_batch(
+    rows: &[EmbeddingRecord],
+    value_formats: &[ValueFormat],
+    compress: bool,
+) -> Chunk {
+    let mut wtr = csv::Writer::from_writer(Vec::new());
+    for (id, embedding) in rows {
+        let mut record = vec![id.clone()];
+        record.extend(value_formats.iter().map(|f| f.format_embedding(embedding)));
+        wtr.write_record(record)?;
+    }
+    let data = wtr.into_inner().map_err(|e| anyhow::anyhow!("{e}"))?;
+
+    if !compress {
+        return Ok((data, rows.len()));
+    }
+
+    let mut encoder = GzEncoder::new(Vec::with_capacity(data.len() / 2), Compression::default());
+    encoder.write_all(&data)?;
+    Ok((encoder.finish()?, rows.len()))
+}
+
+// Batches are serialized and compressed by `--csv-workers` threads
+// and written to the file by the calling thread in the received order
+pub fn write_csv(
+    args: Arc<EmbeddingArgs>,
+    rx: Receiver<Vec<EmbeddingRecord>>,
+) -> Result<usize, anyhow::Error> {
+    let path = args.out_csv.as_ref().unwrap();
+    let compress = is_compressed(path);
+    // Binary vectors are written as an additional field if --quantize-column is specified
+    let mut value_formats = vec![ValueFormat::for_csv(&args)];
+    value_formats.extend(ValueFormat::quantized_for_csv(&args));
+    let value_formats = Arc::new(value_formats);
+    let workers = args.csv_workers;
+    let mut file = OutputWriter::create(path)?;
+
+    // Bounded channels limit the number of batches kept in memory
+    let (job_tx, job_rx) = mpsc::sync_channel::<(usize, Vec<EmbeddingRecord>)>(workers * 2);
+    let (chunk_tx, chunk_rx) = mpsc::sync_channel::<(usize, Chunk)>(workers * 2);
+    let job_rx = Arc::new(Mutex::new(job_rx));
+
+    let dispatcher = std::thread::spawn(move || {
+        for (seq, rows) in rx.iter().enumerate() {
+            // Workers are stopped if writing has failed
+            if job_tx.send((seq, rows)).is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut handles = Vec::with_capacity(workers);
+    for _ in 0..workers {
+        let job_rx = job_rx.clone();
+        let chunk_tx = chunk_tx.clone();
+        let args = args.clone();
+        let value_formats = value_formats.clone();
+        handles.push(std::thread::spawn(move || -> Result<(), anyhow::Error> {
+            pin_current_thread_to(&args.exporter_cores)?;
+            loop {
+                // Lock is released right after receiving, so batches are serialized in parallel
+                let job = job_rx.lock().unwrap().recv();
+                let (seq, rows) = match job {
+                    Ok(job) => job,
+                    Err(_) => return Ok(()),
+                };
+                if chunk_tx
+                    .send((seq, serialize_batch(&rows, &value_formats, compress)))
+                    .is_err()
+                {
+                    return Ok(());
+                }
+            }
+        }));
+    }
+    drop(chunk_tx);
+
+    let mut pending = BTreeMap::new();
+    let mut next_seq = 0;
+    let mut processed_row_cnt = 0;
+    for (seq, chunk) in chunk_rx.iter() {
+        pending.insert(seq, chunk?);
+        while let Some((data, row_cnt)) = pending.remove(&next_seq) {
+            file.write_all(&data)?;
+            processed_row_cnt += row_cnt;
+            next_seq += 1;
+        }
+    }
+    file.finish()?;
+
+    if dispatcher.join().is_err() {
+        anyhow::bail!("CSV dispatcher thread panicked");
+    }
+    for handle in handles {
+        match handle.join() {
+            Ok(res) => res?,
+            Err(e) => anyhow::bail!("{:?}", e),
+        }
+    }
+
+    if !pending.is_empty() {
+        anyhow::bail!("{} batches were not written to {path}", pending.len());
+    }
+
+    Ok(processed_row_cnt)
+}
+
+// Batches are sent to the CSV writer running on its own thread
+pub struct CsvExporter {
+    args: Arc<EmbeddingArgs>,
+    logger: Arc<Logger>,
+    tx: Option<Sender<Vec<EmbeddingRecord>>>,
+    handle: Option<JoinHandle<AnyhowUsizeResult>>,
+}
+
+impl CsvExporter {
+    pub fn new(args: Arc<EmbeddingArgs>, logger: Arc<Logger>) -> Self {
+        CsvExporter {
+            args,
+            logger,
+            tx: None,
+            handle: None,
+        }
+    }
+
+    fn join_writer(&mut self) -> AnyhowUsizeResult {
+        drop(self.tx.take());
+        match self.handle.take() {
+            Some(handle) => match handle.join() {
+                Ok(res) => res,
+                Err(e) => anyhow::bail!("{:?}", e),
+            },
+            None => Ok(0),
+        }
+    }
+}
+
+impl Exporter for CsvExporter {
+    fn begin(&mut self) -> AnyhowVoidResult {
+        let (tx, rx) = mpsc::channel();
+        let args = self.args.clone();
+        self.tx = Some(tx);
+        self.handle = Some(std::thread::spawn(move || {
+            pin_current_thread_to(&args.exporter_cores)?;
+            write_csv(args, rx)
+        }));
+        Ok(())
+    }
+
+    fn write_batch(&mut self, rows: Vec<EmbeddingRecord>) -> AnyhowVoidResult {
+        let sent = match &self.tx {
+            Some(tx) => tx.send(rows).is_ok(),
+            None => false,
+        };
+        // Writer stops receiving only if it has failed, so its error is returned
+        if !sent {
+            self.join_writer()?;
+            anyhow::bail!("CSV writer has stopped");
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> AnyhowVoidResult {
+        self.join_writer()?;
+        self.logger.info(&format!(
+            "Embeddings exported to {}",
+            self.args.out_csv.as_ref().unwrap()
+        ));
+        Ok(())
+    }
+}
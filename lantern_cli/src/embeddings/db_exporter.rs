@@ -0,0 +1,764 @@
+use super::cli::{EmbeddingArgs, OutMode};
+use super::exporter::Exporter;
+use super::flush::FlushPolicy;
+use super::precision::ValueFormat;
+use super::progress::PipelineStats;
+use super::{
+    binary, get_column_type_sql, get_quantize_column_type_sql, get_source_sql, int8,
+    EmbeddingRecord, CONNECTION_PARAMS,
+};
+use crate::logger::Logger;
+use crate::metrics::{self, Subsystem};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+use rand::Rng;
+use std::io::Write;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Instant;
+
+// Write embedding records to the temporary table (or the columns of the table) in COPY text format
+fn copy_records(
+    client: &mut Client,
+    target: &str,
+    rows: &[EmbeddingRecord],
+    value_formats: &[ValueFormat],
+) -> AnyhowVoidResult {
+    let mut writer = client.copy_in(&format!("COPY {target} FROM stdin WITH NULL AS 'NULL'"))?;
+
+    for row in rows {
+        writer.write_all(row.0.as_bytes())?;
+        // Each format is written to a separate column
+        for value_format in value_formats {
+            writer.write_all("\t".as_bytes())?;
+            if !row.1.is_empty() {
+                writer.write_all(value_format.format_embedding(&row.1).as_bytes())?;
+            } else {
+                writer.write_all("NULL".as_bytes())?;
+            }
+        }
+        writer.write_all("\n".as_bytes())?;
+    }
+
+    writer.finish()?;
+    Ok(())
+}
+
+// Update destination table from the temporary table and clear it in one transaction
+// If batch update is passed, the rows are written in separate transactions instead
+fn commit_window(
+    client: &mut Client,
+    temp_table_name: &str,
+    update_sql: &str,
+    batch_update: Option<&(String, usize)>,
+) -> AnyhowVoidResult {
+    let _span = tracing::info_span!("commit_window").entered();
+    if let Some((batch_update_sql, batch_size)) = batch_update {
+        // Pages of the temporary table are split into ranges of about batch_size rows, so each
+        // batch is read with ctid range scan instead of scanning the whole table
+        let row = client.query_one(
+            &format!("SELECT COUNT(*), pg_relation_size('{temp_table_name}'::regclass) / current_setting('block_size')::BIGINT FROM {temp_table_name}"),
+            &[],
+        )?;
+        let (row_cnt, page_cnt) = (row.get::<usize, i64>(0), row.get::<usize, i64>(1));
+        let pages_per_batch = (*batch_size as i64 * page_cnt / row_cnt.max(1)).max(1);
+
+        let mut start_page = 0;
+        while start_page < page_cnt {
+            let end_page = start_page + pages_per_batch;
+            client.execute(
+                batch_update_sql.as_str(),
+                &[&format!("({start_page},0)"), &format!("({end_page},0)")],
+            )?;
+            start_page = end_page;
+        }
+        client.batch_execute(&format!("TRUNCATE TABLE {temp_table_name}"))?;
+        return Ok(());
+    }
+
+    let mut transaction = client.transaction()?;
+    transaction.batch_execute(&format!(
+        "
+        {update_sql};
+        TRUNCATE TABLE {temp_table_name};
+    "
+    ))?;
+    transaction.commit()?;
+    Ok(())
+}
+
+// DB exporter will create temp table with name _lantern_tmp_${rand(0,1000)}
+// Then it will COPY the received embeddings mapped with row ids to that table
+// At the end (or on each flush window) it will UPDATE destination table
+// Using our TEMP table data
+pub struct DbExporter {
+    args: Arc<EmbeddingArgs>,
+    embedded_at: Option<String>,
+    stats: Arc<PipelineStats>,
+    logger: Arc<Logger>,
+    uri: String,
+    schema: String,
+    table: String,
+    full_table_name: String,
+    columns: Vec<(String, ValueFormat)>,
+    value_formats: Vec<ValueFormat>,
+    temp_table_name: String,
+    create_temp_table_sql: String,
+    update_sql: String,
+    // Statement updating the rows of a page range of the temporary table and the number of
+    // rows in a batch, set if --update-batch-size is specified
+    batch_update: Option<(String, usize)>,
+    client: Option<Client>,
+    // If `--commit-every-rows` is specified or the job is run in streaming mode
+    // the rows are committed in separate flush windows. Rows of the current window
+    // are kept until the commit is confirmed, so the window can be replayed if it fails
+    is_windowed: bool,
+    window_rows: Vec<EmbeddingRecord>,
+    window_error: Option<anyhow::Error>,
+    window_start: Instant,
+    flush_policy: FlushPolicy,
+    collected_row_cnt: usize,
+    processed_row_cnt: usize,
+    dimensions: Option<usize>,
+}
+
+impl DbExporter {
+    pub fn new(
+        args: Arc<EmbeddingArgs>,
+        embedded_at: Option<String>,
+        stats: Arc<PipelineStats>,
+        logger: Arc<Logger>,
+    ) -> Self {
+        let uri = append_params_to_uri(
+            args.out_uri.as_ref().unwrap_or(&args.uri),
+            CONNECTION_PARAMS,
+        );
+        let table = args.out_table.clone().unwrap_or(args.table.clone());
+        let schema = args.out_schema.clone().unwrap_or(args.schema.clone());
+        let full_table_name = get_full_table_name(&schema, &table);
+
+        let value_format = ValueFormat::for_table(&args);
+        // Binary vectors are written next to the float vectors if --quantize-column is specified
+        let mut columns = vec![(args.out_column.clone(), value_format)];
+        columns.extend(
+            args.quantize_column
+                .clone()
+                .zip(ValueFormat::quantized_for_table(&args)),
+        );
+        let value_formats: Vec<ValueFormat> = columns.iter().map(|(_, f)| *f).collect();
+
+        let mut rng = rand::thread_rng();
+        let temp_table_name = format!("_lantern_tmp_{}", rng.gen_range(0..1000));
+        let temp_columns_sql = columns
+            .iter()
+            .map(|(column, value_format)| {
+                format!(
+                    "NULL::{array_type} AS {column}",
+                    column = quote_ident(column),
+                    array_type = value_format.array_type_sql()
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        // Rows of other tables are matched by key column, which has the same type in the temporary table
+        let (id_sql, match_sql) = if args.matches_rows_by_ctid() {
+            (
+                "ctid::TEXT".to_owned(),
+                "src.id::tid = dest.ctid".to_owned(),
+            )
+        } else {
+            let key = quote_ident(args.out_key_column.as_ref().unwrap_or(&args.pk));
+            (key.clone(), format!("src.id = dest.{key}"))
+        };
+        // Temporary tables are not WAL-logged, so COPY to them is as cheap as to UNLOGGED tables
+        let create_temp_table_sql = format!(
+            "CREATE TEMPORARY TABLE {temp_table_name} AS SELECT {id_sql} as id, {temp_columns_sql} FROM {full_table_name} LIMIT 0"
+        );
+
+        let embedded_at_sql = match &embedded_at {
+            Some(embedded_at) => format!(
+                ", {} = '{embedded_at}'::TIMESTAMPTZ",
+                quote_ident(&args.embedded_at_column)
+            ),
+            None => "".to_owned(),
+        };
+        // Extracted text is stored from the same row, as rows are matched by ctid
+        let text_column_sql = match &args.jsonpath_text_column {
+            Some(text_column) => format!(
+                ", {} = {}",
+                quote_ident(text_column),
+                get_source_sql(&args, &format!("dest.{}", quote_ident(&args.column)))
+            ),
+            None => "".to_owned(),
+        };
+        let set_columns_sql = columns
+            .iter()
+            .map(|(column, _)| format!("{column} = src.{column}", column = quote_ident(column)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let get_update_sql = |source: &str| {
+            format!("UPDATE {full_table_name} dest SET {set_columns_sql}{embedded_at_sql}{text_column_sql} FROM {source} src WHERE {match_sql}")
+        };
+        let update_sql = get_update_sql(&quote_ident(&temp_table_name));
+        // Batches are written in separate transactions, so the row locks of the destination table
+        // are held only for one batch
+        let batch_update = args.update_batch_size.map(|batch_size| {
+            (
+                get_update_sql(&format!("(SELECT * FROM {temp_table_name} WHERE ctid >= $1::text::tid AND ctid < $2::text::tid)")),
+                batch_size,
+            )
+        });
+
+        DbExporter {
+            is_windowed: args.stream || args.commit_every_rows.is_some(),
+            flush_policy: FlushPolicy::new(&args),
+            args,
+            embedded_at,
+            stats,
+            logger,
+            uri,
+            schema,
+            table,
+            full_table_name,
+            columns,
+            value_formats,
+            temp_table_name,
+            create_temp_table_sql,
+            update_sql,
+            batch_update,
+            client: None,
+            window_rows: Vec::new(),
+            window_error: None,
+            window_start: Instant::now(),
+            collected_row_cnt: 0,
+            processed_row_cnt: 0,
+            dimensions: None,
+        }
+    }
+
+    fn client(&mut self) -> &mut Client {
+        self.client
+            .as_mut()
+            .expect("DbExporter::begin should be called before writing")
+    }
+
+    // Temporary table is lost with the connection, so it is created again on reconnect
+    fn reconnect(&self) -> Result<Client, anyhow::Error> {
+        let mut client = Client::connect(&self.uri, NoTls)?;
+        client.batch_execute(&self.create_temp_table_sql)?;
+        Ok(client)
+    }
+
+    // Rows are matched by ctid and written with the same values, so replaying a window
+    // which was committed before the connection was lost is idempotent
+    fn commit_window_with_retries(&mut self) -> AnyhowVoidResult {
+        let mut result = match self.window_error.take() {
+            Some(e) => Err(e),
+            None => {
+                let (temp_table_name, update_sql, batch_update) = (
+                    self.temp_table_name.clone(),
+                    self.update_sql.clone(),
+                    self.batch_update.clone(),
+                );
+                commit_window(
+                    self.client(),
+                    &temp_table_name,
+                    &update_sql,
+                    batch_update.as_ref(),
+                )
+            }
+        };
+
+        let mut attempt = 0;
+        while let Err(e) = result {
+            if attempt >= self.args.flush_retries {
+                anyhow::bail!("Flush failed after {attempt} retries: {e:#}");
+            }
+            attempt += 1;
+            metrics::inc_counter(
+                Subsystem::Embeddings,
+                "flush_retries_total",
+                &[&self.table],
+                1.0,
+            );
+            self.logger.warn(&format!(
+                "Flush of {} rows failed: {e:#}. Retrying ({attempt}/{})",
+                self.window_rows.len(),
+                self.args.flush_retries
+            ));
+            std::thread::sleep(std::time::Duration::from_millis(500 * attempt as u64));
+
+            result = self
+                .reconnect()
+                .and_then(|mut client| {
+                    copy_records(
+                        &mut client,
+                        &self.temp_table_name,
+                        &self.window_rows,
+                        &self.value_formats,
+                    )?;
+                    commit_window(
+                        &mut client,
+                        &self.temp_table_name,
+                        &self.update_sql,
+                        self.batch_update.as_ref(),
+                    )?;
+                    Ok(client)
+                })
+                .map(|client| {
+                    self.client = Some(client);
+                });
+        }
+
+        Ok(())
+    }
+}
+
+impl Exporter for DbExporter {
+    fn begin(&mut self) -> AnyhowVoidResult {
+        let args = self.args.clone();
+        let full_table_name = &self.full_table_name;
+        let column = &args.out_column;
+        let mut client = Client::connect(&self.uri, NoTls)?;
+        let mut transaction = client.transaction()?;
+
+        if args.create_column && args.emit_migration.is_none() {
+            transaction.execute(
+                &format!(
+                    "ALTER TABLE {full_table_name} ADD COLUMN IF NOT EXISTS {column} {column_type}",
+                    column = quote_ident(column),
+                    column_type = get_column_type_sql(&args)?
+                ),
+                &[],
+            )?;
+
+            if let Some(quantize_column) = &args.quantize_column {
+                transaction.execute(
+                    &format!(
+                        "ALTER TABLE {full_table_name} ADD COLUMN IF NOT EXISTS {column} {column_type}",
+                        column = quote_ident(quantize_column),
+                        column_type = get_quantize_column_type_sql(&args)?
+                    ),
+                    &[],
+                )?;
+            }
+
+            if self.embedded_at.is_some() {
+                transaction.execute(
+                    &format!(
+                        "ALTER TABLE {full_table_name} ADD COLUMN IF NOT EXISTS {embedded_at_column} TIMESTAMPTZ",
+                        embedded_at_column = quote_ident(&args.embedded_at_column)
+                    ),
+                    &[],
+                )?;
+            }
+
+            if let Some(text_column) = &args.jsonpath_text_column {
+                transaction.execute(
+                    &format!(
+                        "ALTER TABLE {full_table_name} ADD COLUMN IF NOT EXISTS {text_column} TEXT",
+                        text_column = quote_ident(text_column)
+                    ),
+                    &[],
+                )?;
+            }
+        }
+
+        // Try to check if user has write permissions to table
+        let res = transaction.query("SELECT 1 FROM information_schema.column_privileges WHERE table_schema=$1 AND table_name=$2 AND column_name=$3 AND privilege_type='UPDATE' AND grantee=current_user", &[&self.schema, &self.table, column])?;
+
+        if res.get(0).is_none() {
+            anyhow::bail!("User does not have write permissions to target table");
+        }
+
+        transaction.execute(&self.create_temp_table_sql, &[])?;
+        transaction.commit()?;
+
+        // Helper function is replaced on each run, so failure to create it does not fail the job
+        // With --emit-migration it is created by the migration
+        if args.quantize.is_some() && args.create_column && args.emit_migration.is_none() {
+            if let Err(e) = client.batch_execute(&binary::get_hamming_distance_sql(&self.schema)) {
+                self.logger
+                    .warn(&format!("Failed to create hamming_distance function: {e}"));
+            }
+        }
+
+        // Reconnects replace the client, so the exporter holds one connection at a time
+        self.stats
+            .active_connections
+            .fetch_add(1, Ordering::Relaxed);
+        self.client = Some(client);
+        self.window_start = Instant::now();
+        Ok(())
+    }
+
+    fn write_batch(&mut self, rows: Vec<EmbeddingRecord>) -> AnyhowVoidResult {
+        // SMALLINT[] and BYTEA columns do not have dimensions in type, so they are checked here
+        if matches!(
+            self.columns[0].1,
+            ValueFormat::PackedF16 | ValueFormat::Int8Bytea
+        ) {
+            for (_, embedding) in rows.iter().filter(|(_, e)| !e.is_empty()) {
+                match self.dimensions {
+                    None => self.dimensions = Some(embedding.len()),
+                    Some(dims) if dims != embedding.len() => anyhow::bail!(
+                        "Embeddings have different dimensions: {dims} and {}",
+                        embedding.len()
+                    ),
+                    _ => {}
+                }
+            }
+        }
+
+        // After failure the connection may be broken, so the rest of the window
+        // is only buffered and written when the window is replayed
+        if self.window_error.is_none() {
+            let (temp_table_name, value_formats) =
+                (self.temp_table_name.clone(), self.value_formats.clone());
+            let copy_result = tracing::info_span!("copy_batch", rows = rows.len())
+                .in_scope(|| copy_records(self.client(), &temp_table_name, &rows, &value_formats));
+            if let Err(e) = copy_result {
+                if !self.is_windowed {
+                    return Err(e);
+                }
+                self.window_error = Some(e);
+            }
+        }
+
+        self.collected_row_cnt += rows.len();
+        self.processed_row_cnt += rows.len();
+        if self.is_windowed {
+            self.window_rows.extend(rows);
+        } else {
+            drop(rows);
+        }
+        // Rows waiting in the channel and rows of the current window are not committed yet
+        metrics::set_gauge(
+            Subsystem::Embeddings,
+            "export_lag_rows",
+            &[&self.table],
+            (self.stats.exporter_buffered_rows.load(Ordering::Relaxed) + self.collected_row_cnt)
+                as f64,
+        );
+
+        let commit_rows_reached = self
+            .args
+            .commit_every_rows
+            .is_some_and(|commit_rows| self.collected_row_cnt >= commit_rows);
+
+        if commit_rows_reached
+            || (self.args.stream
+                && self
+                    .flush_policy
+                    .should_flush(self.collected_row_cnt, self.window_start.elapsed()))
+        {
+            // if job is run in streaming mode
+            // it will write results to target table each --flush-interval seconds (if collected
+            // rows are more than --min-flush-rows) or if collected row count is more than
+            // --max-flush-rows
+            // with adaptive flush these thresholds will grow while the database is under load
+            let flush_start = Instant::now();
+            self.commit_window_with_retries()?;
+            metrics::observe(
+                Subsystem::Embeddings,
+                "flush_duration_seconds",
+                &[&self.table],
+                flush_start.elapsed().as_secs_f64(),
+            );
+
+            if self.flush_policy.record_latency(flush_start.elapsed()) {
+                self.logger.debug(&format!(
+                    "Flush took {}ms, flushing every {}s or {} rows",
+                    flush_start.elapsed().as_millis(),
+                    self.flush_policy.flush_interval(),
+                    self.flush_policy.max_flush_rows()
+                ));
+            }
+
+            self.window_rows.clear();
+            self.collected_row_cnt = 0;
+            self.window_start = Instant::now();
+        }
+
+        Ok(())
+    }
+
+    fn finish(&mut self) -> AnyhowVoidResult {
+        if self.processed_row_cnt == 0 {
+            return Ok(());
+        }
+
+        let flush_start = Instant::now();
+        if self.is_windowed {
+            self.commit_window_with_retries()?;
+        } else {
+            let (temp_table_name, update_sql, batch_update) = (
+                self.temp_table_name.clone(),
+                self.update_sql.clone(),
+                self.batch_update.clone(),
+            );
+            commit_window(
+                self.client(),
+                &temp_table_name,
+                &update_sql,
+                batch_update.as_ref(),
+            )?;
+        }
+        metrics::observe(
+            Subsystem::Embeddings,
+            "flush_duration_seconds",
+            &[&self.table],
+            flush_start.elapsed().as_secs_f64(),
+        );
+        metrics::set_gauge(
+            Subsystem::Embeddings,
+            "export_lag_rows",
+            &[&self.table],
+            0.0,
+        );
+
+        let column = &self.args.out_column;
+        // Store how to decode the packed values in column comment
+        if let (Some(dimensions), true) = (self.dimensions, self.args.create_column) {
+            let encoding = match self.columns[0].1 {
+                ValueFormat::Int8Bytea => format!(
+                    "precision=int8, packed=bytea, ranges={}.{}",
+                    int8::METADATA_SCHEMA_NAME,
+                    int8::METADATA_TABLE_NAME
+                ),
+                _ => "precision=f16, packed=smallint".to_owned(),
+            };
+            let comment_sql = format!(
+                "COMMENT ON COLUMN {full_table_name}.{column} IS 'lantern: {encoding}, dimensions={dimensions}'",
+                full_table_name = self.full_table_name,
+                column = quote_ident(column)
+            );
+            if let Err(e) = self.client().batch_execute(&comment_sql) {
+                self.logger
+                    .warn(&format!("Failed to set column comment: {e}"));
+            }
+        }
+
+        self.logger.info(&format!(
+            "Embeddings exported to table {} under column {}",
+            &self.table, column
+        ));
+        Ok(())
+    }
+}
+
+impl Drop for DbExporter {
+    fn drop(&mut self) {
+        if self.client.take().is_some() {
+            self.stats
+                .active_connections
+                .fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// Destination table of TableExporter, which is also written to the migration with --emit-migration
+// Upsert needs a unique index on the key column
+pub(super) fn get_create_table_sql(
+    args: &EmbeddingArgs,
+    full_table_name: &str,
+    key_column: &str,
+    is_upsert: bool,
+) -> Result<String, anyhow::Error> {
+    let key_constraint = if is_upsert { " PRIMARY KEY" } else { "" };
+    let mut columns_sql = vec![format!("{} TEXT{key_constraint}", quote_ident(key_column))];
+    columns_sql.push(format!(
+        "{} {}",
+        quote_ident(&args.out_column),
+        get_column_type_sql(args)?
+    ));
+    if let Some(quantize_column) = &args.quantize_column {
+        columns_sql.push(format!(
+            "{} {}",
+            quote_ident(quantize_column),
+            get_quantize_column_type_sql(args)?
+        ));
+    }
+
+    Ok(format!(
+        "CREATE TABLE IF NOT EXISTS {full_table_name} ({})",
+        columns_sql.join(", ")
+    ))
+}
+
+// Records of file producers and rows of the source table in --out-mode insert or upsert
+// are not rows of the destination table, so they are inserted into it with the ids
+// written to the key column (--out-key-column or --pk)
+pub struct TableExporter {
+    args: Arc<EmbeddingArgs>,
+    stats: Arc<PipelineStats>,
+    logger: Arc<Logger>,
+    uri: String,
+    table: String,
+    full_table_name: String,
+    key_column: String,
+    columns: Vec<(String, ValueFormat)>,
+    value_formats: Vec<ValueFormat>,
+    // In upsert mode the batches are copied to the temporary table first,
+    // and then inserted with ON CONFLICT on the key column
+    upsert_sql: Option<String>,
+    temp_table_name: String,
+    client: Option<Client>,
+    processed_row_cnt: usize,
+}
+
+impl TableExporter {
+    pub fn new(args: Arc<EmbeddingArgs>, stats: Arc<PipelineStats>, logger: Arc<Logger>) -> Self {
+        let uri = append_params_to_uri(
+            args.out_uri.as_ref().unwrap_or(&args.uri),
+            CONNECTION_PARAMS,
+        );
+        let table = args.out_table.clone().unwrap_or(args.table.clone());
+        let schema = args.out_schema.as_ref().unwrap_or(&args.schema);
+        let full_table_name = get_full_table_name(schema, &table);
+
+        let mut columns = vec![(args.out_column.clone(), ValueFormat::for_table(&args))];
+        columns.extend(
+            args.quantize_column
+                .clone()
+                .zip(ValueFormat::quantized_for_table(&args)),
+        );
+        let value_formats: Vec<ValueFormat> = columns.iter().map(|(_, f)| *f).collect();
+        let key_column = args.out_key_column.clone().unwrap_or(args.pk.clone());
+
+        let mut rng = rand::thread_rng();
+        let temp_table_name = format!("_lantern_tmp_{}", rng.gen_range(0..1000));
+        let upsert_sql = matches!(args.out_mode, OutMode::Upsert).then(|| {
+            let columns_sql = columns
+                .iter()
+                .map(|(column, _)| quote_ident(column))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let set_columns_sql = columns
+                .iter()
+                .map(|(column, _)| format!("{column} = EXCLUDED.{column}", column = quote_ident(column)))
+                .collect::<Vec<_>>()
+                .join(", ");
+            format!(
+                "INSERT INTO {full_table_name} ({key}, {columns_sql}) SELECT {key}, {columns_sql} FROM {temp_table_name} ON CONFLICT ({key}) DO UPDATE SET {set_columns_sql}",
+                key = quote_ident(&key_column)
+            )
+        });
+
+        TableExporter {
+            args,
+            stats,
+            logger,
+            uri,
+            table,
+            full_table_name,
+            key_column,
+            columns,
+            value_formats,
+            upsert_sql,
+            temp_table_name,
+            client: None,
+            processed_row_cnt: 0,
+        }
+    }
+
+    fn client(&mut self) -> &mut Client {
+        self.client
+            .as_mut()
+            .expect("TableExporter::begin should be called before writing")
+    }
+}
+
+impl Exporter for TableExporter {
+    fn begin(&mut self) -> AnyhowVoidResult {
+        let args = self.args.clone();
+        let mut client = Client::connect(&self.uri, NoTls)?;
+
+        if args.create_column && args.emit_migration.is_none() {
+            client.batch_execute(&get_create_table_sql(
+                &args,
+                &self.full_table_name,
+                &self.key_column,
+                self.upsert_sql.is_some(),
+            )?)?;
+        }
+
+        // Temporary table has the same column types as the destination table
+        if self.upsert_sql.is_some() {
+            client.batch_execute(&format!(
+                "CREATE TEMPORARY TABLE {temp_table_name} AS SELECT {key}, {columns} FROM {full_table_name} LIMIT 0",
+                temp_table_name = self.temp_table_name,
+                key = quote_ident(&self.key_column),
+                columns = self
+                    .columns
+                    .iter()
+                    .map(|(column, _)| quote_ident(column))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                full_table_name = self.full_table_name
+            ))?;
+        }
+
+        self.stats
+            .active_connections
+            .fetch_add(1, Ordering::Relaxed);
+        self.client = Some(client);
+        Ok(())
+    }
+
+    fn write_batch(&mut self, rows: Vec<EmbeddingRecord>) -> AnyhowVoidResult {
+        if let Some(upsert_sql) = self.upsert_sql.clone() {
+            let (temp_table_name, value_formats) =
+                (self.temp_table_name.clone(), self.value_formats.clone());
+            tracing::info_span!("copy_batch", rows = rows.len()).in_scope(|| {
+                copy_records(self.client(), &temp_table_name, &rows, &value_formats)
+            })?;
+            commit_window(self.client(), &temp_table_name, &upsert_sql, None)?;
+            self.processed_row_cnt += rows.len();
+            return Ok(());
+        }
+
+        let copy_target = format!(
+            "{full_table_name} ({key}, {columns})",
+            full_table_name = self.full_table_name,
+            key = quote_ident(&self.key_column),
+            columns = self
+                .columns
+                .iter()
+                .map(|(column, _)| quote_ident(column))
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        let value_formats = self.value_formats.clone();
+        tracing::info_span!("copy_batch", rows = rows.len())
+            .in_scope(|| copy_records(self.client(), &copy_target, &rows, &value_formats))?;
+        self.processed_row_cnt += rows.len();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> AnyhowVoidResult {
+        self.logger.info(&format!(
+            "Embeddings of {} rows {} into table {} under column {}",
+            self.processed_row_cnt,
+            if self.upsert_sql.is_some() {
+                "upserted"
+            } else {
+                "inserted"
+            },
+            &self.table,
+            &self.args.out_column
+        ));
+        Ok(())
+    }
+}
+
+impl Drop for TableExporter {
+    fn drop(&mut self) {
+        if self.client.take().is_some() {
+            self.stats
+                .active_connections
+                .fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,153 @@
+use super::cli::{EmbeddingArgs, Precision};
+use super::core::get_runtime;
+use super::CONNECTION_PARAMS;
+use crate::logger::Logger;
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+
+// Input embedded to find out the dimensions of the model output
+static PROBE_INPUT: &str = "Hello world!";
+
+// Dimensions from typmod of types like vector(1536), halfvec(768) or bit(1024)
+pub fn get_type_dimensions(column_type: &str) -> Option<usize> {
+    let (_, typmod) = column_type.split_once('(')?;
+    typmod.trim_end_matches(')').parse::<usize>().ok()
+}
+
+pub fn get_column_type(
+    client: &mut Client,
+    full_table_name: &str,
+    column: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let row = client.query_opt(
+        "SELECT format_type(atttypid, atttypmod) FROM pg_attribute WHERE attrelid=$1::text::regclass AND attname=$2 AND NOT attisdropped",
+        &[&full_table_name, &column],
+    )?;
+    Ok(row.map(|row| row.get::<usize, String>(0)))
+}
+
+// Dimensions of an embedding already written to a column without typmod
+// Packed f16 and int8 values do not have one value per dimension, so they are not checked
+fn get_row_dimensions(
+    client: &mut Client,
+    full_table_name: &str,
+    column: &str,
+    column_type: &str,
+    precision: &Precision,
+) -> Result<Option<usize>, anyhow::Error> {
+    let column_sql = quote_ident(column);
+    let dimensions_sql = if column_type.starts_with("vector") || column_type.starts_with("halfvec")
+    {
+        format!("vector_dims({column_sql})")
+    } else if column_type.starts_with("bit") {
+        format!("length({column_sql})")
+    } else if column_type == "real[]" && matches!(precision, Precision::F32) {
+        format!("array_length({column_sql}, 1)")
+    } else {
+        return Ok(None);
+    };
+
+    let row = client.query_opt(
+        &format!(
+            "SELECT {dimensions_sql} FROM {full_table_name} WHERE {column_sql} IS NOT NULL LIMIT 1"
+        ),
+        &[],
+    )?;
+    Ok(row
+        .and_then(|row| row.get::<usize, Option<i32>>(0))
+        .map(|dimensions| dimensions as usize))
+}
+
+// Dimensions expected by the column from its typmod, e.g. vector(768), or from the existing embeddings
+// Returns the description of the column for the error message
+fn get_expected_dimensions(
+    client: &mut Client,
+    full_table_name: &str,
+    column: &str,
+    precision: &Precision,
+) -> Result<Option<(usize, String)>, anyhow::Error> {
+    // Missing columns are created with the dimensions of the model
+    let column_type = match get_column_type(client, full_table_name, column)? {
+        Some(column_type) => column_type,
+        None => return Ok(None),
+    };
+
+    if let Some(dimensions) = get_type_dimensions(&column_type) {
+        return Ok(Some((dimensions, format!("is {column_type}"))));
+    }
+
+    let dimensions = get_row_dimensions(client, full_table_name, column, &column_type, precision)?;
+    Ok(dimensions.map(|dimensions| {
+        (
+            dimensions,
+            format!("has existing embeddings with {dimensions} dimensions"),
+        )
+    }))
+}
+
+// Compare the output columns with the model dimensions before the job starts,
+// so a mismatch fails the job before any embeddings are generated instead of failing the export
+// The model is called only if the columns have known dimensions and the runtime does not know
+// the model dimensions
+pub fn check_output_dimensions(args: &EmbeddingArgs, logger: &Logger) -> AnyhowVoidResult {
+    let out_full_table_name = get_full_table_name(
+        args.out_schema.as_ref().unwrap_or(&args.schema),
+        args.out_table.as_ref().unwrap_or(&args.table),
+    );
+    let out_uri = append_params_to_uri(
+        args.out_uri.as_ref().unwrap_or(&args.uri),
+        CONNECTION_PARAMS,
+    );
+    let mut client = Client::connect(&out_uri, NoTls)?;
+
+    let mut columns = vec![&args.out_column];
+    columns.extend(args.quantize_column.as_ref());
+    let mut expected_dimensions = Vec::with_capacity(columns.len());
+    for column in columns {
+        if let Some((dimensions, description)) =
+            get_expected_dimensions(&mut client, &out_full_table_name, column, &args.precision)?
+        {
+            expected_dimensions.push((column, dimensions, description));
+        }
+    }
+    if expected_dimensions.is_empty() {
+        return Ok(());
+    }
+
+    let dimensions = match args.truncate_dim {
+        Some(dimensions) => dimensions,
+        None => {
+            let runtime = get_runtime(&args.runtime, None, &args.runtime_params)?;
+            // The model is called only if the runtime does not know its dimensions
+            match runtime
+                .get_model_info(&args.model)
+                .and_then(|info| info.dims)
+            {
+                Some(dimensions) => dimensions,
+                // Image models can not embed the text probe
+                None if args.visual => return Ok(()),
+                None => {
+                    let response = runtime.process(&args.model, &vec![PROBE_INPUT])?;
+                    match response.embeddings.first() {
+                        Some(embedding) => embedding.len(),
+                        None => return Ok(()),
+                    }
+                }
+            }
+        }
+    };
+
+    for (column, expected, description) in expected_dimensions {
+        if expected != dimensions {
+            anyhow::bail!(
+                "Model {} produces {dimensions} dimensions, but column {column} of {out_full_table_name} {description}",
+                args.model
+            );
+        }
+    }
+    logger.debug(&format!(
+        "Output columns of {out_full_table_name} match the model dimensions ({dimensions})"
+    ));
+    Ok(())
+}
@@ -0,0 +1,243 @@
+use super::cli::EmbeddingArgs;
+use super::core::get_runtime;
+use super::cost::{self, CostReport};
+use super::dimensions::{get_column_type, get_type_dimensions};
+use super::object_store;
+use super::{get_filter_sql, get_source_sql, CONNECTION_PARAMS};
+use crate::logger::Logger;
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+use std::fmt;
+use std::path::Path;
+
+#[derive(Debug, Clone)]
+pub struct DryRunPlan {
+    pub rows: usize,
+    pub batch_size: usize,
+    pub batches: usize,
+    // None when there are no rows to embed
+    pub dimensions: Option<usize>,
+    pub destination: String,
+    pub cost: Option<CostReport>,
+}
+
+impl fmt::Display for DryRunPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Plan: embed {} rows in {} batches of {} rows into {}",
+            self.rows, self.batches, self.batch_size, self.destination
+        )?;
+        if let Some(dimensions) = self.dimensions {
+            write!(f, ", {dimensions} dimensions")?;
+        }
+        if let Some(cost) = &self.cost {
+            write!(f, "\n{cost}")?;
+        }
+        Ok(())
+    }
+}
+
+fn check_column_privilege(
+    client: &mut Client,
+    full_table_name: &str,
+    column: &str,
+    privilege: &str,
+) -> AnyhowVoidResult {
+    let has_privilege: bool = client
+        .query_one(
+            "SELECT has_column_privilege($1, $2, $3)",
+            &[&full_table_name, &column, &privilege],
+        )?
+        .get(0);
+    if !has_privilege {
+        anyhow::bail!(
+            "User does not have {privilege} permission on column {column} of {full_table_name}"
+        );
+    }
+    Ok(())
+}
+
+// Adding columns requires to be the owner of the table
+fn check_table_owner(client: &mut Client, full_table_name: &str) -> AnyhowVoidResult {
+    let is_owner: bool = client
+        .query_one(
+            "SELECT pg_has_role(relowner, 'USAGE') FROM pg_class WHERE oid=$1::text::regclass",
+            &[&full_table_name],
+        )?
+        .get(0);
+    if !is_owner {
+        anyhow::bail!("User must be the owner of {full_table_name} to create missing columns");
+    }
+    Ok(())
+}
+
+// Check that the column can be written and its typmod matches the embedding dimensions
+// Missing columns are only allowed if they will be created by the job
+fn check_target_column(
+    client: &mut Client,
+    full_table_name: &str,
+    column: &str,
+    create_column: bool,
+    dimensions: Option<usize>,
+    logger: &Logger,
+) -> AnyhowVoidResult {
+    let column_type = match get_column_type(client, full_table_name, column)? {
+        Some(column_type) => column_type,
+        None if create_column => {
+            check_table_owner(client, full_table_name)?;
+            logger.info(&format!(
+                "[dry run] Column {column} will be created in {full_table_name}"
+            ));
+            return Ok(());
+        }
+        None => anyhow::bail!("Column {column} does not exist in {full_table_name}"),
+    };
+
+    check_column_privilege(client, full_table_name, column, "UPDATE")?;
+    match (get_type_dimensions(&column_type), dimensions) {
+        (Some(expected), Some(dimensions)) if expected != dimensions => anyhow::bail!(
+            "Model returns {dimensions} dimensions, but column {column} has type {column_type}"
+        ),
+        _ => Ok(()),
+    }
+}
+
+// Validate the job without writing anything to the database
+// 1. Check the source table can be read and count the rows which will be embedded
+// 2. Embed one sample batch, which also resolves (and downloads) the model
+// 3. Check the target columns can be written and their dimensions match the model
+pub fn dry_run(
+    args: &EmbeddingArgs,
+    batch_size: usize,
+    logger: &Logger,
+) -> Result<DryRunPlan, anyhow::Error> {
+    if args.array_mode.is_some() || args.chunks_table.is_some() {
+        anyhow::bail!("--dry-run can not be used with --array-mode or --chunks-table");
+    }
+
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    check_column_privilege(&mut client, &full_table_name, &args.column, "SELECT")?;
+
+    let filter_sql = get_filter_sql(args);
+    let count: i64 = client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {full_table_name} {filter_sql}"),
+            &[],
+        )?
+        .get(0);
+    let mut rows = count as usize;
+    if let Some(limit) = args.limit {
+        rows = rows.min(limit as usize);
+    }
+    let batches = (rows + batch_size - 1) / batch_size;
+    logger.info(&format!(
+        "[dry run] Source table {full_table_name} has {rows} rows to embed"
+    ));
+
+    let sample_rows = client.query(
+        &format!(
+            "SELECT {source_sql} FROM {full_table_name} {filter_sql} LIMIT {limit}",
+            source_sql = get_source_sql(args, &quote_ident(&args.column)),
+            limit = batch_size.min(rows)
+        ),
+        &[],
+    )?;
+    let inputs: Vec<&str> = sample_rows
+        .iter()
+        .filter_map(|row| row.get::<usize, Option<&str>>(0))
+        .filter(|input| input.trim() != "")
+        .collect();
+
+    let mut dimensions = None;
+    let mut tokens_per_row = 0.0;
+    if !inputs.is_empty() {
+        let runtime = get_runtime(&args.runtime, None, &args.runtime_params)?;
+        let response = runtime.process(&args.model, &inputs)?;
+        let model_dimensions = response.embeddings.first().map(|e| e.len()).unwrap_or(0);
+        if let Some(truncate_dim) = args.truncate_dim {
+            if model_dimensions < truncate_dim {
+                anyhow::bail!("Can not truncate embedding with {model_dimensions} dimensions to {truncate_dim} dimensions");
+            }
+        }
+        dimensions = Some(args.truncate_dim.unwrap_or(model_dimensions));
+        tokens_per_row = response.processed_tokens as f64 / inputs.len() as f64;
+        logger.info(&format!(
+            "[dry run] Embedded sample batch of {} rows with model {}, {model_dimensions} dimensions",
+            inputs.len(),
+            args.model
+        ));
+    }
+
+    let destination = if let Some(out_file) = args
+        .out_csv
+        .as_ref()
+        .or(args.out_parquet.as_ref())
+        .or(args.out_jsonl.as_ref())
+    {
+        let parent = Path::new(out_file).parent().unwrap_or(Path::new("."));
+        if !object_store::is_object_uri(out_file)
+            && !parent.as_os_str().is_empty()
+            && !parent.is_dir()
+        {
+            anyhow::bail!("Directory of output file {out_file} does not exist");
+        }
+        out_file.clone()
+    } else {
+        let out_table = args.out_table.as_ref().unwrap_or(&args.table);
+        let out_schema = args.out_schema.as_ref().unwrap_or(&args.schema);
+        let out_full_table_name = get_full_table_name(out_schema, out_table);
+        let mut out_client = match &args.out_uri {
+            Some(out_uri) => {
+                Client::connect(&append_params_to_uri(out_uri, CONNECTION_PARAMS), NoTls)?
+            }
+            None => client,
+        };
+
+        // Binary vector types (bit(n)) have the same typmod as float vectors
+        check_target_column(
+            &mut out_client,
+            &out_full_table_name,
+            &args.out_column,
+            args.create_column,
+            dimensions,
+            logger,
+        )?;
+        if let Some(quantize_column) = &args.quantize_column {
+            check_target_column(
+                &mut out_client,
+                &out_full_table_name,
+                quantize_column,
+                args.create_column,
+                dimensions,
+                logger,
+            )?;
+        }
+        format!("{out_full_table_name}.{}", quote_ident(&args.out_column))
+    };
+
+    let cost = if cost::is_billed_runtime(&args.runtime) {
+        Some(CostReport::new(
+            args,
+            rows,
+            (tokens_per_row * rows as f64).round() as usize,
+            batches,
+        ))
+    } else {
+        None
+    };
+
+    let plan = DryRunPlan {
+        rows,
+        batch_size,
+        batches,
+        dimensions,
+        destination,
+        cost,
+    };
+    logger.info(&format!("[dry run] {plan}"));
+    Ok(plan)
+}
@@ -0,0 +1,75 @@
+use super::cli::{EmbedTextArgs, Runtime, VectorFormat};
+use super::core::{get_runtime, stderr_logger, LoggerFn};
+use super::truncate_embeddings;
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::vector_jobs::vector_ops::normalize_vector;
+
+// Embedding is post-processed the same way as in create-embeddings,
+// so the vector can be compared with the stored ones
+pub fn embed_query(
+    runtime: &Runtime,
+    runtime_params: &str,
+    model: &str,
+    text: &str,
+    truncate_dim: Option<usize>,
+    normalize: bool,
+) -> Result<Vec<f32>, anyhow::Error> {
+    if truncate_dim == Some(0) {
+        anyhow::bail!("--truncate-dim should be greater than 0");
+    }
+
+    // Runtime logs are written to stderr, so stdout contains only the results
+    let runtime_logger: LoggerFn = stderr_logger;
+    let runtime = get_runtime(runtime, Some(&runtime_logger), runtime_params)?;
+    let mut embeddings = runtime.process(model, &vec![text])?.embeddings;
+
+    if let Some(dimensions) = truncate_dim {
+        truncate_embeddings(&mut embeddings, dimensions)?;
+    }
+
+    let mut embedding = match embeddings.pop() {
+        Some(embedding) => embedding,
+        None => anyhow::bail!("Runtime returned no embedding"),
+    };
+    if normalize {
+        normalize_vector(&mut embedding);
+    }
+    Ok(embedding)
+}
+
+pub fn embed_text(args: &EmbedTextArgs) -> Result<Vec<f32>, anyhow::Error> {
+    embed_query(
+        &args.runtime,
+        &args.runtime_params,
+        &args.model,
+        &args.text,
+        args.truncate_dim,
+        args.normalize,
+    )
+}
+
+pub fn format_vector(embedding: &[f32], format: &VectorFormat) -> String {
+    let values = embedding
+        .iter()
+        .map(|v| v.to_string())
+        .collect::<Vec<_>>()
+        .join(",");
+    match format {
+        VectorFormat::Json | VectorFormat::Pgvector => format!("[{values}]"),
+        VectorFormat::Array => format!("{{{values}}}"),
+    }
+}
+
+pub fn print_embedding(args: &EmbedTextArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Embeddings", LogLevel::Error));
+    let embedding = embed_text(args)?;
+
+    if args.stats {
+        let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+        eprintln!("dimensions: {}, norm: {norm}", embedding.len());
+    }
+
+    logger.print_raw(&format_vector(&embedding, &args.format));
+    Ok(())
+}
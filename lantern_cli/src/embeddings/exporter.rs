@@ -0,0 +1,178 @@
+use super::affinity::pin_current_thread_to;
+use super::cli::EmbeddingArgs;
+use super::progress::{PipelineStats, ProgressNotifier, ProgressTracker};
+use super::{BatchHookFn, EmbeddingRecord};
+use crate::logger::Logger;
+use crate::metrics::{self, Subsystem};
+use crate::types::*;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Receiver;
+use std::sync::{Arc, RwLock};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+// Without the row count there is no percent, so progress is logged periodically
+static UNCOUNTED_PROGRESS_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+// Sink for embedded batches, implemented by the database and CSV exporters
+// Downstream crates can implement it to write the embeddings to other stores
+// and pass it to the pipeline with `EmbeddingPipeline::exporter`
+pub trait Exporter: Send {
+    // Called once before the first batch, e.g. to create the destination or open connections
+    fn begin(&mut self) -> AnyhowVoidResult {
+        Ok(())
+    }
+
+    // Batches are passed in the order they are received from the embedding workers
+    fn write_batch(&mut self, rows: Vec<EmbeddingRecord>) -> AnyhowVoidResult;
+
+    // Called once after the last batch to flush buffered rows
+    // It is not called if the job has failed
+    fn finish(&mut self) -> AnyhowVoidResult {
+        Ok(())
+    }
+}
+
+// Pass embedded batches to the hooks registered in EmbeddingPipeline
+// Each hook receives its own copy of the batch
+pub struct HookExporter {
+    hooks: Vec<BatchHookFn>,
+}
+
+impl HookExporter {
+    pub fn new(hooks: Vec<BatchHookFn>) -> Self {
+        HookExporter { hooks }
+    }
+}
+
+impl Exporter for HookExporter {
+    fn write_batch(&mut self, rows: Vec<EmbeddingRecord>) -> AnyhowVoidResult {
+        if let Some((last_hook, hooks)) = self.hooks.split_last_mut() {
+            for hook in hooks {
+                hook(rows.clone())?;
+            }
+            last_hook(rows)?;
+        }
+        Ok(())
+    }
+}
+
+// Exporter thread passes the received batches to the exporter and reports the progress
+pub(crate) fn exporter_worker(
+    args: Arc<EmbeddingArgs>,
+    rx: Receiver<Vec<EmbeddingRecord>>,
+    item_count: i64,
+    mut exporter: Box<dyn Exporter>,
+    progress_cb: Option<ProgressEventCbFn>,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    stats: Arc<PipelineStats>,
+    logger: Arc<Logger>,
+) -> Result<JoinHandle<AnyhowUsizeResult>, anyhow::Error> {
+    let parent_span = tracing::Span::current();
+    let handle = std::thread::spawn(move || {
+        let _span = tracing::info_span!(parent: &parent_span, "exporter").entered();
+        pin_current_thread_to(&args.exporter_cores)?;
+        let table = args.out_table.as_ref().unwrap_or(&args.table);
+        let column = &args.out_column;
+        let mut progress_tracker = ProgressTracker::new(item_count, stats.clone());
+        let mut notifier = if args.notify_progress {
+            let mut notifier = ProgressNotifier::connect(&args)?;
+            notifier.notify("started", &progress_tracker.add_rows(0), None)?;
+            Some(notifier)
+        } else {
+            None
+        };
+        // Events are not sent after the first failed notification, as the connection is likely lost
+        let mut notify = |stage: &str, progress: &ProgressEvent, error: Option<&anyhow::Error>| {
+            if let Some(active_notifier) = &mut notifier {
+                if let Err(e) = active_notifier.notify(stage, progress, error) {
+                    logger.warn(&format!("Failed to send progress notification: {e}"));
+                    notifier = None;
+                }
+            }
+        };
+
+        let result = (|| -> AnyhowUsizeResult {
+            exporter.begin()?;
+
+            let mut processed_row_cnt = 0;
+            let mut old_progress = 0;
+            let mut last_progress_log = Instant::now();
+
+            while let Ok(rows) = rx.recv() {
+                stats
+                    .exporter_buffered_rows
+                    .fetch_sub(rows.len(), Ordering::Relaxed);
+                let row_cnt = rows.len();
+                let export_start = Instant::now();
+                exporter.write_batch(rows)?;
+                stats.latencies.record_export(export_start.elapsed());
+
+                processed_row_cnt += row_cnt;
+                let progress = progress_tracker.add_rows(row_cnt);
+                metrics::set_gauge(
+                    Subsystem::Embeddings,
+                    "progress_ratio",
+                    &[table, column],
+                    progress.percent as f64 / 100.0,
+                );
+
+                if progress.percent > old_progress {
+                    old_progress = progress.percent;
+                    logger.debug(&format!("Progress {progress}"));
+                    notify("embedding", &progress, None);
+                } else if progress.total_rows.is_none()
+                    && last_progress_log.elapsed() >= UNCOUNTED_PROGRESS_LOG_INTERVAL
+                {
+                    last_progress_log = Instant::now();
+                    logger.debug(&format!("Progress {progress}"));
+                    notify("embedding", &progress, None);
+                }
+                if let Some(cb) = &progress_cb {
+                    cb(&progress);
+                }
+            }
+
+            // Without streaming or --commit-every-rows all rows are written in one transaction on finish
+            // so a cancelled job is stopped without committing the partial result
+            let is_windowed = args.stream || args.commit_every_rows.is_some();
+            if !is_windowed
+                && is_canceled
+                    .as_ref()
+                    .is_some_and(|is_canceled| *is_canceled.read().unwrap())
+            {
+                anyhow::bail!(JOB_CANCELLED_MESSAGE);
+            }
+
+            // There might be a case when filter is provided manually
+            // And `{column} IS NOT NULL` will be missing from the table
+            // So we will check if the column is null in rust code before generating embedding
+            // Thus the processed rows may be less than the actual estimated row count
+            // And progress will not be 100
+            if old_progress != 100 {
+                let progress = progress_tracker.finish();
+                metrics::set_gauge(
+                    Subsystem::Embeddings,
+                    "progress_ratio",
+                    &[table, column],
+                    1.0,
+                );
+                logger.debug(&format!("Progress {progress}"));
+                if let Some(cb) = &progress_cb {
+                    cb(&progress);
+                }
+            }
+
+            exporter.finish()?;
+            Ok(processed_row_cnt)
+        })();
+
+        match &result {
+            Ok(_) => notify("finished", &progress_tracker.finish(), None),
+            Err(e) => notify("failed", &progress_tracker.add_rows(0), Some(e)),
+        }
+        result
+    });
+
+    return Ok(handle);
+}
@@ -0,0 +1,183 @@
+use super::cli::{EmbeddingArgs, FanOutEmbeddingArgs};
+use super::limiter::RequestLimiter;
+use super::lineage::run_with_lineage;
+use super::run_embedding_pipeline;
+use crate::logger::{LogLevel, Logger};
+use crate::types::percent_progress_cb;
+use crate::utils::redact_secrets;
+use std::sync::{Arc, Mutex};
+
+#[derive(Debug, Clone)]
+pub struct ShardSummary {
+    pub uri: String,
+    pub processed_rows: usize,
+    pub processed_tokens: usize,
+    pub error: Option<String>,
+}
+
+// Log overall progress as the average of shard progresses
+fn report_progress(progress: &Mutex<Vec<u8>>, shard_idx: usize, value: u8, logger: &Logger) {
+    let mut progress = progress.lock().unwrap();
+    progress[shard_idx] = value;
+    let total: usize = progress.iter().map(|p| *p as usize).sum();
+    let shards = progress
+        .iter()
+        .enumerate()
+        .map(|(idx, p)| format!("#{idx} {p}%"))
+        .collect::<Vec<String>>()
+        .join(", ");
+    logger.info(&format!(
+        "Overall progress {}% ({shards})",
+        total / progress.len()
+    ));
+}
+
+fn log_summary(summaries: &[ShardSummary], logger: &Logger) {
+    let mut lines = vec![format!(
+        "{:<6} {:<8} {:>14} {:>16}  {}",
+        "shard", "status", "processed rows", "processed tokens", "database"
+    )];
+    for (idx, summary) in summaries.iter().enumerate() {
+        lines.push(format!(
+            "{:<6} {:<8} {:>14} {:>16}  {}",
+            format!("#{idx}"),
+            if summary.error.is_some() {
+                "failed"
+            } else {
+                "ok"
+            },
+            summary.processed_rows,
+            summary.processed_tokens,
+            summary.uri
+        ));
+    }
+    for (idx, summary) in summaries.iter().enumerate() {
+        if let Some(error) = &summary.error {
+            lines.push(format!("#{idx}: {error}"));
+        }
+    }
+
+    logger.info("Fan-out summary\n");
+    logger.print_raw(&lines.join("\n"));
+}
+
+// Run the same embedding job against each database
+// Failure of one database does not stop the others, errors are reported in the summary
+pub fn create_embeddings_fan_out(
+    args: &FanOutEmbeddingArgs,
+    logger: Option<Logger>,
+) -> Result<Vec<ShardSummary>, anyhow::Error> {
+    let logger =
+        Arc::new(logger.unwrap_or(Logger::new("Lantern Embeddings Fan-out", LogLevel::Debug)));
+
+    if args.shard_concurrency == 0 {
+        anyhow::bail!("Shard concurrency should be greater than 0");
+    }
+
+    let embedding_args = &args.embedding_args;
+    if embedding_args.out_uri.is_some()
+        || embedding_args.has_file_output()
+        || embedding_args.has_file_input()
+    {
+        anyhow::bail!("Fan-out jobs read and write embeddings in each source database, --out-uri, input and output files can not be used");
+    }
+
+    let mut uris = vec![args.embedding_args.uri.clone()];
+    for uri in &args.shard_uris {
+        if !uris.contains(uri) {
+            uris.push(uri.clone());
+        }
+    }
+
+    // Rate limits are shared between all databases
+    let limiter = RequestLimiter::from_args(&args.embedding_args)?;
+
+    logger.info(&format!(
+        "Running job against {} databases, {} in parallel",
+        uris.len(),
+        args.shard_concurrency
+    ));
+
+    let summaries: Arc<Mutex<Vec<ShardSummary>>> = Arc::new(Mutex::new(
+        uris.iter()
+            .map(|uri| ShardSummary {
+                uri: redact_secrets(uri),
+                processed_rows: 0,
+                processed_tokens: 0,
+                error: None,
+            })
+            .collect(),
+    ));
+    let progress = Arc::new(Mutex::new(vec![0_u8; uris.len()]));
+    let queue = Arc::new(Mutex::new((0..uris.len()).rev().collect::<Vec<usize>>()));
+    let uris = Arc::new(uris);
+
+    let mut handles = Vec::with_capacity(args.shard_concurrency);
+    for _ in 0..args.shard_concurrency.min(uris.len()) {
+        let queue = queue.clone();
+        let uris = uris.clone();
+        let summaries = summaries.clone();
+        let progress = progress.clone();
+        let limiter = limiter.clone();
+        let logger = logger.clone();
+        let embedding_args = args.embedding_args.clone();
+
+        handles.push(std::thread::spawn(move || loop {
+            let shard_idx = match queue.lock().unwrap().pop() {
+                Some(idx) => idx,
+                None => break,
+            };
+
+            let shard_args = EmbeddingArgs {
+                uri: uris[shard_idx].clone(),
+                ..embedding_args.clone()
+            };
+            let shard_logger = Logger::new(
+                &format!("{} #{shard_idx}", logger.label),
+                logger.level.clone(),
+            );
+            let progress = progress.clone();
+            let progress_logger = logger.clone();
+            let progress_cb = percent_progress_cb(Box::new(move |value: u8| {
+                report_progress(&progress, shard_idx, value, &progress_logger)
+            }));
+
+            // Each database is a separate lineage run, as input and output datasets differ
+            let lineage_args = shard_args.clone();
+            let result = run_with_lineage(&lineage_args, &shard_logger, || {
+                run_embedding_pipeline(
+                    shard_args,
+                    true,
+                    Some(progress_cb),
+                    None,
+                    limiter.clone(),
+                    Some(shard_logger.clone()),
+                    None,
+                    None,
+                )
+            });
+
+            let mut summaries = summaries.lock().unwrap();
+            match result {
+                Ok(report) => {
+                    summaries[shard_idx].processed_rows = report.processed_rows;
+                    summaries[shard_idx].processed_tokens = report.processed_tokens;
+                }
+                Err(e) => {
+                    logger.error(&format!("Database #{shard_idx} failed: {e}"));
+                    summaries[shard_idx].error = Some(redact_secrets(&e.to_string()));
+                }
+            }
+        }));
+    }
+
+    for handle in handles {
+        if let Err(e) = handle.join() {
+            anyhow::bail!("{:?}", e);
+        }
+    }
+
+    let summaries = summaries.lock().unwrap().clone();
+    log_summary(&summaries, &logger);
+    Ok(summaries)
+}
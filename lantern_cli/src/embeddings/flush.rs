@@ -0,0 +1,68 @@
+use super::cli::EmbeddingArgs;
+use std::cmp;
+use std::time::Duration;
+
+// Thresholds will not grow more than this factor from their initial values
+static MAX_BACKOFF_FACTOR: u64 = 32;
+
+// Decides when collected rows should be flushed to the destination table in streaming mode
+// If adaptive mode is enabled, the thresholds will grow when UPDATE latency exceeds the target
+// So the rows will be written with larger and less frequent batches while database is under load
+pub struct FlushPolicy {
+    adaptive: bool,
+    latency_target: Duration,
+    backoff_factor: u64,
+    // Initial thresholds from --flush-interval, --min-flush-rows and --max-flush-rows
+    flush_interval: u64,
+    min_flush_rows: usize,
+    max_flush_rows: usize,
+}
+
+impl FlushPolicy {
+    pub fn new(args: &EmbeddingArgs) -> FlushPolicy {
+        FlushPolicy {
+            adaptive: args.adaptive_flush,
+            latency_target: Duration::from_millis(args.flush_latency_target_ms),
+            backoff_factor: 1,
+            flush_interval: args.flush_interval,
+            min_flush_rows: args.min_flush_rows,
+            max_flush_rows: args.max_flush_rows,
+        }
+    }
+
+    pub fn flush_interval(&self) -> u64 {
+        self.flush_interval * self.backoff_factor
+    }
+
+    pub fn min_flush_rows(&self) -> usize {
+        self.min_flush_rows * self.backoff_factor as usize
+    }
+
+    pub fn max_flush_rows(&self) -> usize {
+        self.max_flush_rows * self.backoff_factor as usize
+    }
+
+    pub fn should_flush(&self, collected_rows: usize, elapsed: Duration) -> bool {
+        collected_rows >= self.max_flush_rows()
+            || (self.flush_interval() <= elapsed.as_secs()
+                && collected_rows >= self.min_flush_rows())
+    }
+
+    // Record the latency of the last flush and adjust the thresholds
+    // Returns true if the thresholds were changed
+    pub fn record_latency(&mut self, latency: Duration) -> bool {
+        if !self.adaptive {
+            return false;
+        }
+
+        let old_factor = self.backoff_factor;
+
+        if latency > self.latency_target {
+            self.backoff_factor = cmp::min(self.backoff_factor * 2, MAX_BACKOFF_FACTOR);
+        } else if latency < self.latency_target / 2 {
+            self.backoff_factor = cmp::max(self.backoff_factor / 2, 1);
+        }
+
+        old_factor != self.backoff_factor
+    }
+}
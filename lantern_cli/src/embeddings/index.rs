@@ -0,0 +1,159 @@
+use super::cli::{ColumnType, EmbeddingArgs, Precision};
+use super::CONNECTION_PARAMS;
+use crate::external_index::cli::UMetricKind;
+use crate::logger::Logger;
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+use std::time::Instant;
+
+// Parsed --create-index value, e.g. "hnsw(m=16, ef_construction=64, dist=cos)"
+// Parameters which are not specified have the default values of lantern_hnsw
+#[derive(Debug, Clone)]
+pub struct IndexSpec {
+    pub m: usize,
+    pub ef_construction: usize,
+    // Used only by lantern_hnsw, pgvector takes ef_search from the session settings
+    pub ef: usize,
+    pub metric: UMetricKind,
+}
+
+impl IndexSpec {
+    pub fn parse(spec: &str) -> Result<IndexSpec, anyhow::Error> {
+        let invalid_spec = || {
+            anyhow::anyhow!(
+                "Invalid index '{spec}', expected e.g. 'hnsw(m=16, ef_construction=64, dist=cos)'"
+            )
+        };
+        let params = spec
+            .trim()
+            .strip_prefix("hnsw")
+            .ok_or_else(invalid_spec)?
+            .trim();
+        let params = if params.is_empty() {
+            params
+        } else {
+            params
+                .strip_prefix('(')
+                .and_then(|params| params.strip_suffix(')'))
+                .ok_or_else(invalid_spec)?
+        };
+
+        let mut index_spec = IndexSpec {
+            m: 16,
+            ef_construction: 128,
+            ef: 64,
+            metric: UMetricKind::Cos,
+        };
+        for param in params.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = param.split_once('=').ok_or_else(invalid_spec)?;
+            let (key, value) = (key.trim(), value.trim());
+            let parse_value = || match value.parse::<usize>() {
+                Ok(value) if value > 0 => Ok(value),
+                _ => Err(anyhow::anyhow!(
+                    "Index parameter {key} should be a positive integer, got '{value}'"
+                )),
+            };
+            match key {
+                "m" => index_spec.m = parse_value()?,
+                "ef_construction" => index_spec.ef_construction = parse_value()?,
+                "ef" => index_spec.ef = parse_value()?,
+                "dist" => index_spec.metric = UMetricKind::from(value)?,
+                _ => anyhow::bail!("Unknown index parameter '{key}'"),
+            }
+        }
+        Ok(index_spec)
+    }
+
+    // Returns the access method and operator class for the output column
+    // REAL[] columns are indexed with lantern_hnsw and vector columns with pgvector hnsw
+    pub fn get_method(
+        &self,
+        args: &EmbeddingArgs,
+    ) -> Result<(&'static str, String), anyhow::Error> {
+        if args.quantize.is_some() && args.quantize_column.is_none() {
+            anyhow::bail!(
+                "--create-index can not be used with --quantize without --quantize-column"
+            );
+        }
+
+        match args.column_type {
+            ColumnType::Real => {
+                if !matches!(args.precision, Precision::F32) {
+                    anyhow::bail!(
+                        "--create-index can be used with real columns only with --precision f32"
+                    );
+                }
+                Ok(("lantern_hnsw", self.metric.to_ops()))
+            }
+            ColumnType::Vector => {
+                let vector_type = match args.precision {
+                    Precision::F16 => "halfvec",
+                    _ => "vector",
+                };
+                let ops = match self.metric {
+                    UMetricKind::L2sq => "l2_ops",
+                    UMetricKind::Cos => "cosine_ops",
+                    UMetricKind::Hamming => anyhow::bail!(
+                        "Index with hamming distance can be created only for real columns"
+                    ),
+                };
+                Ok(("hnsw", format!("{vector_type}_{ops}")))
+            }
+        }
+    }
+
+    pub fn get_create_index_sql(
+        &self,
+        args: &EmbeddingArgs,
+        full_table_name: &str,
+        index_name: &str,
+    ) -> Result<String, anyhow::Error> {
+        let (method, op_class) = self.get_method(args)?;
+        let params = match method {
+            "lantern_hnsw" => format!(
+                "m={}, ef_construction={}, ef={}",
+                self.m, self.ef_construction, self.ef
+            ),
+            _ => format!("m={}, ef_construction={}", self.m, self.ef_construction),
+        };
+        Ok(format!(
+            "CREATE INDEX {concurrently}IF NOT EXISTS {index_name} ON {full_table_name} USING {method} ({column} {op_class}) WITH ({params})",
+            concurrently = if args.create_index_concurrently {
+                "CONCURRENTLY "
+            } else {
+                ""
+            },
+            index_name = quote_ident(index_name),
+            column = quote_ident(&args.out_column),
+        ))
+    }
+}
+
+pub fn get_index_name(args: &EmbeddingArgs) -> String {
+    let table = args.out_table.as_ref().unwrap_or(&args.table);
+    format!("{table}_{}_hnsw_idx", args.out_column)
+}
+
+// Index is created on the output table after the exporter has committed the embeddings
+pub fn create_index(args: &EmbeddingArgs, spec: &IndexSpec, logger: &Logger) -> AnyhowVoidResult {
+    let table = args.out_table.as_ref().unwrap_or(&args.table);
+    let full_table_name =
+        get_full_table_name(args.out_schema.as_ref().unwrap_or(&args.schema), table);
+    let index_name = get_index_name(args);
+    let sql = spec.get_create_index_sql(args, &full_table_name, &index_name)?;
+
+    let uri = append_params_to_uri(
+        args.out_uri.as_ref().unwrap_or(&args.uri),
+        CONNECTION_PARAMS,
+    );
+    let mut client = Client::connect(&uri, NoTls)?;
+    logger.info(&format!("Creating index {index_name} on {full_table_name}"));
+    let start = Instant::now();
+    client.batch_execute(&sql)?;
+    logger.info(&format!(
+        "Index {index_name} created in {:.1}s",
+        start.elapsed().as_secs_f64()
+    ));
+    Ok(())
+}
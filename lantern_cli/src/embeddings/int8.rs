@@ -0,0 +1,280 @@
+use super::cli::EmbeddingArgs;
+use super::object_store;
+use super::{EmbeddingRecord, CONNECTION_PARAMS};
+use crate::logger::Logger;
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name};
+use postgres::{Client, NoTls};
+use serde::{Deserialize, Serialize};
+use std::sync::mpsc::{self, Receiver};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+pub(crate) static METADATA_SCHEMA_NAME: &str = "_lantern_internal";
+pub(crate) static METADATA_TABLE_NAME: &str = "int8_quantization";
+
+// Per-dimension ranges mapped to -128..127
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Int8Quantizer {
+    pub mins: Vec<f32>,
+    pub maxs: Vec<f32>,
+}
+
+impl Int8Quantizer {
+    pub fn train(embeddings: &[&[f32]]) -> Result<Int8Quantizer, anyhow::Error> {
+        let dimensions = match embeddings.first() {
+            Some(embedding) => embedding.len(),
+            None => anyhow::bail!("Can not train int8 ranges without embeddings"),
+        };
+
+        let mut mins = vec![f32::MAX; dimensions];
+        let mut maxs = vec![f32::MIN; dimensions];
+        for embedding in embeddings {
+            if embedding.len() != dimensions {
+                anyhow::bail!(
+                    "Embeddings have different dimensions: {dimensions} and {}",
+                    embedding.len()
+                );
+            }
+            for (idx, value) in embedding.iter().enumerate() {
+                mins[idx] = mins[idx].min(*value);
+                maxs[idx] = maxs[idx].max(*value);
+            }
+        }
+
+        Ok(Int8Quantizer { mins, maxs })
+    }
+
+    pub fn dimensions(&self) -> usize {
+        self.mins.len()
+    }
+
+    fn get_scale(&self, idx: usize) -> f32 {
+        (self.maxs[idx] - self.mins[idx]) / 255.0
+    }
+
+    // Values outside of the trained range are clamped
+    pub fn quantize(&self, embedding: &[f32]) -> Result<Vec<i8>, anyhow::Error> {
+        if embedding.len() != self.dimensions() {
+            anyhow::bail!(
+                "Embedding has {} dimensions, int8 ranges are trained for {}",
+                embedding.len(),
+                self.dimensions()
+            );
+        }
+
+        Ok(embedding
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| {
+                let scale = self.get_scale(idx);
+                if scale == 0.0 {
+                    return i8::MIN;
+                }
+                let code = ((value - self.mins[idx]) / scale).round() - 128.0;
+                code.clamp(-128.0, 127.0) as i8
+            })
+            .collect())
+    }
+
+    pub fn dequantize(&self, values: &[i8]) -> Vec<f32> {
+        values
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| (*value as f32 + 128.0) * self.get_scale(idx) + self.mins[idx])
+            .collect()
+    }
+}
+
+// Ranges are stored per destination column, so later runs quantize new rows with the same ranges
+// For CSV output they are stored in {out_csv}.int8.json file
+enum MetadataStore {
+    Table {
+        client: Box<Client>,
+        key: (String, String),
+    },
+    File(String),
+}
+
+impl MetadataStore {
+    fn new(args: &EmbeddingArgs) -> Result<MetadataStore, anyhow::Error> {
+        if let Some(out_csv) = &args.out_csv {
+            return Ok(MetadataStore::File(format!("{out_csv}.int8.json")));
+        }
+
+        let uri = append_params_to_uri(
+            args.out_uri.as_ref().unwrap_or(&args.uri),
+            CONNECTION_PARAMS,
+        );
+        let mut client = Client::connect(&uri, NoTls)?;
+        client.batch_execute(&format!(
+            "
+            CREATE SCHEMA IF NOT EXISTS {METADATA_SCHEMA_NAME};
+            CREATE TABLE IF NOT EXISTS {full_table_name} (
+                table_name TEXT NOT NULL,
+                column_name TEXT NOT NULL,
+                dimensions INT NOT NULL,
+                mins REAL[] NOT NULL,
+                maxs REAL[] NOT NULL,
+                trained_rows INT NOT NULL,
+                created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                PRIMARY KEY (table_name, column_name)
+            );
+        ",
+            full_table_name = get_metadata_table_name()
+        ))?;
+
+        let table = get_full_table_name(
+            args.out_schema.as_ref().unwrap_or(&args.schema),
+            args.out_table.as_ref().unwrap_or(&args.table),
+        );
+        Ok(MetadataStore::Table {
+            client: Box::new(client),
+            key: (table, args.out_column.clone()),
+        })
+    }
+
+    fn load(&mut self) -> Result<Option<Int8Quantizer>, anyhow::Error> {
+        match self {
+            MetadataStore::Table { client, key } => {
+                let row = client.query_opt(
+                    &format!(
+                        "SELECT mins, maxs FROM {} WHERE table_name=$1 AND column_name=$2",
+                        get_metadata_table_name()
+                    ),
+                    &[&key.0, &key.1],
+                )?;
+                Ok(row.map(|row| Int8Quantizer {
+                    mins: row.get(0),
+                    maxs: row.get(1),
+                }))
+            }
+            MetadataStore::File(path) => match object_store::read_to_string(path)? {
+                Some(data) => Ok(Some(serde_json::from_str(&data)?)),
+                None => Ok(None),
+            },
+        }
+    }
+
+    // Returns ranges stored by a concurrent job if it has trained them first
+    fn save(
+        &mut self,
+        quantizer: Int8Quantizer,
+        trained_rows: usize,
+    ) -> Result<Int8Quantizer, anyhow::Error> {
+        match self {
+            MetadataStore::Table { client, key } => {
+                client.execute(
+                    &format!(
+                        "INSERT INTO {} (table_name, column_name, dimensions, mins, maxs, trained_rows) VALUES ($1, $2, $3, $4, $5, $6) ON CONFLICT DO NOTHING",
+                        get_metadata_table_name()
+                    ),
+                    &[
+                        &key.0,
+                        &key.1,
+                        &(quantizer.dimensions() as i32),
+                        &quantizer.mins,
+                        &quantizer.maxs,
+                        &(trained_rows as i32),
+                    ],
+                )?;
+                Ok(self.load()?.unwrap_or(quantizer))
+            }
+            MetadataStore::File(path) => {
+                object_store::write(path, serde_json::to_string(&quantizer)?.as_bytes())?;
+                Ok(quantizer)
+            }
+        }
+    }
+}
+
+pub fn get_metadata_table_name() -> String {
+    get_full_table_name(METADATA_SCHEMA_NAME, METADATA_TABLE_NAME)
+}
+
+// Quantized values are sent to the exporter as f32, which represents all i8 values exactly
+fn quantize_records(
+    quantizer: &Int8Quantizer,
+    records: Vec<EmbeddingRecord>,
+) -> Result<Vec<EmbeddingRecord>, anyhow::Error> {
+    records
+        .into_iter()
+        .map(|(id, embedding)| {
+            if embedding.is_empty() {
+                return Ok((id, embedding));
+            }
+            let codes = quantizer.quantize(&embedding)?;
+            Ok((id, codes.into_iter().map(|v| v as f32).collect()))
+        })
+        .collect()
+}
+
+// Stage between embedding workers and exporter
+// The first --int8-train-rows embeddings are buffered to train the ranges if they are not stored yet
+pub fn int8_quantizer_worker(
+    args: Arc<EmbeddingArgs>,
+    rx: Receiver<Vec<EmbeddingRecord>>,
+    logger: Arc<Logger>,
+) -> Result<(JoinHandle<AnyhowVoidResult>, Receiver<Vec<EmbeddingRecord>>), anyhow::Error> {
+    let (tx, exporter_rx) = mpsc::channel();
+    let mut store = MetadataStore::new(&args)?;
+
+    let handle = std::thread::spawn(move || {
+        let mut quantizer = store.load()?;
+        if quantizer.is_some() {
+            logger.info("Using stored int8 quantization ranges");
+        }
+
+        let mut buffer: Vec<EmbeddingRecord> = Vec::new();
+        let mut train =
+            |buffer: &mut Vec<EmbeddingRecord>| -> Result<Int8Quantizer, anyhow::Error> {
+                let embeddings: Vec<&[f32]> = buffer
+                    .iter()
+                    .filter(|(_, e)| !e.is_empty())
+                    .map(|(_, e)| e.as_slice())
+                    .collect();
+                let trained = Int8Quantizer::train(&embeddings)?;
+                logger.info(&format!(
+                    "Trained int8 quantization ranges on {} embeddings",
+                    embeddings.len()
+                ));
+                store.save(trained, embeddings.len())
+            };
+
+        while let Ok(records) = rx.recv() {
+            let quantizer = match &quantizer {
+                Some(quantizer) => quantizer,
+                None => {
+                    buffer.extend(records);
+                    if buffer.iter().filter(|(_, e)| !e.is_empty()).count() < args.int8_train_rows {
+                        continue;
+                    }
+                    quantizer = Some(train(&mut buffer)?);
+                    let quantizer = quantizer.as_ref().unwrap();
+                    if tx
+                        .send(quantize_records(quantizer, std::mem::take(&mut buffer))?)
+                        .is_err()
+                    {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+
+            // Error will be returned if exporter worker failed and channel has been closed
+            if tx.send(quantize_records(quantizer, records)?).is_err() {
+                return Ok(());
+            }
+        }
+
+        // Less embeddings than --int8-train-rows were generated
+        if quantizer.is_none() && buffer.iter().any(|(_, e)| !e.is_empty()) {
+            let quantizer = train(&mut buffer)?;
+            let _ = tx.send(quantize_records(&quantizer, buffer)?);
+        }
+
+        Ok(())
+    });
+
+    Ok((handle, exporter_rx))
+}
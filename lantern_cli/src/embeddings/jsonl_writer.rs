@@ -0,0 +1,67 @@
+use super::exporter::Exporter;
+use super::object_store::OutputWriter;
+use super::EmbeddingRecord;
+use crate::logger::Logger;
+use crate::types::*;
+use serde::Serialize;
+use std::io::Write;
+use std::sync::Arc;
+
+#[derive(Serialize)]
+struct JsonlRecord<'a> {
+    id: &'a str,
+    embedding: &'a [f32],
+}
+
+pub struct JsonlExporter {
+    path: String,
+    logger: Arc<Logger>,
+    writer: Option<OutputWriter>,
+    processed_row_cnt: usize,
+}
+
+impl JsonlExporter {
+    pub fn new(path: &str, logger: Arc<Logger>) -> Self {
+        JsonlExporter {
+            path: path.to_owned(),
+            logger,
+            writer: None,
+            processed_row_cnt: 0,
+        }
+    }
+}
+
+impl Exporter for JsonlExporter {
+    fn begin(&mut self) -> AnyhowVoidResult {
+        self.writer = Some(OutputWriter::create(&self.path)?);
+        Ok(())
+    }
+
+    fn write_batch(&mut self, rows: Vec<EmbeddingRecord>) -> AnyhowVoidResult {
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("JsonlExporter::begin should be called before writing");
+        for (id, embedding) in &rows {
+            serde_json::to_writer(&mut *writer, &JsonlRecord { id, embedding })?;
+            writer.write_all(b"\n")?;
+        }
+        // Lines written to standard output are flushed per batch for shell pipelines
+        if self.path == "-" {
+            writer.flush()?;
+        }
+        self.processed_row_cnt += rows.len();
+        Ok(())
+    }
+
+    fn finish(&mut self) -> AnyhowVoidResult {
+        if let Some(writer) = self.writer.take() {
+            writer.finish()?;
+        }
+        self.logger.info(&format!(
+            "Embeddings of {} rows exported to {}",
+            self.processed_row_cnt, self.path
+        ));
+        Ok(())
+    }
+}
@@ -0,0 +1,108 @@
+use crate::embeddings::cli::EmbeddingArgs;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Token bucket kept as the time when the bucket refills enough for the next request
+// Each request moves the time forward by its cost, so requests are spaced evenly
+struct Bucket {
+    interval: Duration,
+    next_slot: Instant,
+}
+
+impl Bucket {
+    fn new(per_minute: u32, name: &str) -> Result<Bucket, anyhow::Error> {
+        if per_minute == 0 {
+            anyhow::bail!("{name} per minute should be greater than 0");
+        }
+
+        Ok(Bucket {
+            interval: Duration::from_secs(60) / per_minute,
+            next_slot: Instant::now(),
+        })
+    }
+
+    // Returns the time when the request can be sent, which is taken from the bucket
+    fn reserve(&mut self, now: Instant, cost: u32) -> Instant {
+        let slot = if self.next_slot > now {
+            self.next_slot
+        } else {
+            now
+        };
+        self.next_slot = slot + self.interval * cost;
+        slot
+    }
+}
+
+// Throttles runtime requests to stay under the provider request and token limits
+// The limiter is shared between all embedding workers of the jobs using the same provider
+pub struct RequestLimiter {
+    requests: Option<Mutex<Bucket>>,
+    tokens: Option<Mutex<Bucket>>,
+}
+
+impl RequestLimiter {
+    pub fn new(
+        requests_per_minute: Option<u32>,
+        tokens_per_minute: Option<u32>,
+    ) -> Result<RequestLimiter, anyhow::Error> {
+        let requests = match requests_per_minute {
+            Some(requests) => Some(Mutex::new(Bucket::new(requests, "Requests")?)),
+            None => None,
+        };
+        let tokens = match tokens_per_minute {
+            Some(tokens) => Some(Mutex::new(Bucket::new(tokens, "Tokens")?)),
+            None => None,
+        };
+
+        Ok(RequestLimiter { requests, tokens })
+    }
+
+    // Returns None if the job has no rate limits
+    pub fn from_args(args: &EmbeddingArgs) -> Result<Option<Arc<RequestLimiter>>, anyhow::Error> {
+        if args.max_requests_per_minute.is_none() && args.max_tokens_per_minute.is_none() {
+            return Ok(None);
+        }
+
+        Ok(Some(Arc::new(RequestLimiter::new(
+            args.max_requests_per_minute,
+            args.max_tokens_per_minute,
+        )?)))
+    }
+
+    // Token count of the inputs is needed only if tokens are limited
+    pub fn limits_tokens(&self) -> bool {
+        self.tokens.is_some()
+    }
+
+    // Reserve the next free slot for the requests of a batch with the given token count and sleep until it comes
+    // The batch waits for both buckets, and the later slot is taken from both of them
+    pub fn wait(&self, request_count: usize, tokens: usize) {
+        let slot = {
+            let now = Instant::now();
+            let mut requests = self.requests.as_ref().map(|b| b.lock().unwrap());
+            let mut token_bucket = self.tokens.as_ref().map(|b| b.lock().unwrap());
+            let start = [
+                requests.as_ref().map(|b| b.next_slot),
+                token_bucket.as_ref().map(|b| b.next_slot),
+            ]
+            .into_iter()
+            .flatten()
+            .fold(now, |start, slot| start.max(slot));
+
+            let request_count = u32::try_from(request_count).unwrap_or(u32::MAX);
+            let tokens = u32::try_from(tokens).unwrap_or(u32::MAX);
+            if let Some(bucket) = requests.as_mut() {
+                bucket.reserve(start, request_count);
+            }
+            if let Some(bucket) = token_bucket.as_mut() {
+                bucket.reserve(start, tokens);
+            }
+            start
+        };
+
+        let now = Instant::now();
+        if slot > now {
+            std::thread::sleep(slot - now);
+        }
+    }
+}
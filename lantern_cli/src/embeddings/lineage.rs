@@ -0,0 +1,259 @@
+use super::cli::EmbeddingArgs;
+use super::report::JobReport;
+use crate::logger::Logger;
+use crate::utils::redact_secrets;
+use chrono::{SecondsFormat, Utc};
+use isahc::{config::Configurable, prelude::*, Request};
+use serde_json::{json, Value};
+use std::time::Duration;
+use url::Url;
+
+static PRODUCER: &str = "https://github.com/lanterndata/lantern_extras";
+static RUN_EVENT_SCHEMA_URL: &str =
+    "https://openlineage.io/spec/2-0-2/OpenLineage.json#/definitions/RunEvent";
+static SCHEMA_FACET_URL: &str =
+    "https://openlineage.io/spec/facets/1-1-1/SchemaDatasetFacet.json#/$defs/SchemaDatasetFacet";
+static COLUMN_LINEAGE_FACET_URL: &str = "https://openlineage.io/spec/facets/1-2-0/ColumnLineageDatasetFacet.json#/$defs/ColumnLineageDatasetFacet";
+static ERROR_FACET_URL: &str = "https://openlineage.io/spec/facets/1-0-1/ErrorMessageRunFacet.json#/$defs/ErrorMessageRunFacet";
+static EMBEDDINGS_FACET_URL: &str =
+    "https://github.com/lanterndata/lantern_extras#lantern_embeddings";
+
+pub struct Dataset {
+    pub namespace: String,
+    pub name: String,
+}
+
+// Dataset naming follows OpenLineage conventions
+// postgres://host:port namespace and database.schema.table name
+// Credentials are not included in the namespace
+pub fn get_table_dataset(uri: &str, schema: &str, table: &str) -> Dataset {
+    let (host, port, database) = match Url::parse(uri) {
+        Ok(url) => (
+            url.host_str().unwrap_or("localhost").to_owned(),
+            url.port().unwrap_or(5432),
+            url.path().trim_start_matches('/').to_owned(),
+        ),
+        // Key-value connection string like "host=localhost dbname=postgres"
+        Err(_) => {
+            let mut host = "localhost".to_owned();
+            let mut port = 5432;
+            let mut database = String::new();
+            for param in uri.split_whitespace() {
+                match param.split_once('=') {
+                    Some(("host", value)) => host = value.to_owned(),
+                    Some(("port", value)) => port = value.parse().unwrap_or(port),
+                    Some(("dbname", value)) => database = value.to_owned(),
+                    _ => {}
+                }
+            }
+            (host, port, database)
+        }
+    };
+
+    Dataset {
+        namespace: format!("postgres://{host}:{port}"),
+        name: format!("{database}.{schema}.{table}"),
+    }
+}
+
+fn get_output_dataset(args: &EmbeddingArgs) -> (Dataset, &str) {
+    if let Some(out_csv) = &args.out_csv {
+        return (
+            Dataset {
+                namespace: "file".to_owned(),
+                name: out_csv.clone(),
+            },
+            &args.out_column,
+        );
+    }
+
+    if let Some(chunks_table) = &args.chunks_table {
+        return (
+            get_table_dataset(&args.uri, &args.schema, chunks_table),
+            "embedding",
+        );
+    }
+
+    (
+        get_table_dataset(
+            args.out_uri.as_ref().unwrap_or(&args.uri),
+            args.out_schema.as_ref().unwrap_or(&args.schema),
+            args.out_table.as_ref().unwrap_or(&args.table),
+        ),
+        &args.out_column,
+    )
+}
+
+// Random UUID v4
+fn generate_run_id() -> String {
+    let bits =
+        (rand::random::<u128>() & !(0xf000 << 64) & !(0xc << 60)) | (0x4000 << 64) | (0x8 << 60);
+    let hex = format!("{bits:032x}");
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[0..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..32]
+    )
+}
+
+pub struct LineageEmitter<'a> {
+    url: &'a str,
+    args: &'a EmbeddingArgs,
+    run_id: String,
+}
+
+impl<'a> LineageEmitter<'a> {
+    pub fn new(url: &'a str, args: &'a EmbeddingArgs) -> LineageEmitter<'a> {
+        LineageEmitter {
+            url,
+            args,
+            run_id: generate_run_id(),
+        }
+    }
+
+    fn get_facet(schema_url: &str, facet: Value) -> Value {
+        let mut facet = facet;
+        facet["_producer"] = json!(PRODUCER);
+        facet["_schemaURL"] = json!(schema_url);
+        facet
+    }
+
+    // Run event with input column, output column and model identity
+    // Processed rows and tokens are added to the complete event and error message to the fail event
+    pub fn get_event(
+        &self,
+        event_type: &str,
+        result: Option<&Result<(usize, usize), String>>,
+    ) -> Value {
+        let args = self.args;
+        let input = get_table_dataset(&args.uri, &args.schema, &args.table);
+        let (output, output_column) = get_output_dataset(args);
+
+        let mut run_facets = json!({
+            "lantern_embeddings": LineageEmitter::get_facet(EMBEDDINGS_FACET_URL, json!({
+                "model": args.model,
+                "runtime": args.runtime.to_string(),
+            })),
+        });
+
+        match result {
+            Some(Ok((processed_rows, processed_tokens))) => {
+                run_facets["lantern_embeddings"]["processedRows"] = json!(processed_rows);
+                run_facets["lantern_embeddings"]["processedTokens"] = json!(processed_tokens);
+            }
+            Some(Err(message)) => {
+                run_facets["errorMessage"] = LineageEmitter::get_facet(
+                    ERROR_FACET_URL,
+                    json!({ "message": message, "programmingLanguage": "rust" }),
+                );
+            }
+            None => {}
+        }
+
+        json!({
+            "eventType": event_type,
+            "eventTime": Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true),
+            "run": { "runId": self.run_id, "facets": run_facets },
+            "job": {
+                "namespace": args.lineage_namespace,
+                "name": format!("create_embeddings.{}.{output_column}", output.name),
+            },
+            "inputs": [{
+                "namespace": input.namespace,
+                "name": input.name,
+                "facets": {
+                    "schema": LineageEmitter::get_facet(SCHEMA_FACET_URL, json!({
+                        "fields": [{ "name": args.column }],
+                    })),
+                },
+            }],
+            "outputs": [{
+                "namespace": output.namespace,
+                "name": output.name,
+                "facets": {
+                    "schema": LineageEmitter::get_facet(SCHEMA_FACET_URL, json!({
+                        "fields": [{ "name": output_column }],
+                    })),
+                    "columnLineage": LineageEmitter::get_facet(COLUMN_LINEAGE_FACET_URL, json!({
+                        "fields": {
+                            output_column: {
+                                "inputFields": [{
+                                    "namespace": input.namespace,
+                                    "name": input.name,
+                                    "field": args.column,
+                                }],
+                                "transformationDescription": format!("Embeddings generated with {} model", args.model),
+                            },
+                        },
+                    })),
+                },
+            }],
+            "producer": PRODUCER,
+            "schemaURL": RUN_EVENT_SCHEMA_URL,
+        })
+    }
+
+    fn send_event(&self, event: &Value) -> Result<(), anyhow::Error> {
+        let mut request = Request::post(self.url)
+            .header("Content-Type", "application/json")
+            .timeout(Duration::from_secs(10));
+
+        if let Some(api_key) = &self.args.lineage_api_key {
+            request = request.header("Authorization", format!("Bearer {api_key}"));
+        }
+
+        let mut response = request.body(serde_json::to_vec(event)?)?.send()?;
+        let status = response.status();
+
+        if !status.is_success() {
+            anyhow::bail!("Status {status}. Error: {}", response.text()?);
+        }
+
+        Ok(())
+    }
+
+    // Lineage is best effort, failing to send the event does not fail the job
+    pub fn emit(
+        &self,
+        event_type: &str,
+        result: Option<&Result<(usize, usize), String>>,
+        logger: &Logger,
+    ) {
+        if let Err(e) = self.send_event(&self.get_event(event_type, result)) {
+            logger.warn(&format!("Failed to send {event_type} lineage event: {e}"));
+        }
+    }
+}
+
+// Run the embedding job and emit OpenLineage START and COMPLETE or FAIL events if --lineage-url is set
+pub fn run_with_lineage(
+    args: &EmbeddingArgs,
+    logger: &Logger,
+    run: impl FnOnce() -> Result<JobReport, anyhow::Error>,
+) -> Result<JobReport, anyhow::Error> {
+    let Some(url) = &args.lineage_url else {
+        return run();
+    };
+    // Dry runs do not read or write any datasets for lineage purposes
+    if args.dry_run || args.dry_run_cost {
+        return run();
+    }
+
+    let emitter = LineageEmitter::new(url, args);
+    emitter.emit("START", None, logger);
+
+    let result = run();
+    match &result {
+        Ok(report) => emitter.emit(
+            "COMPLETE",
+            Some(&Ok((report.processed_rows, report.processed_tokens))),
+            logger,
+        ),
+        Err(e) => emitter.emit("FAIL", Some(&Err(redact_secrets(&e.to_string()))), logger),
+    }
+
+    result
+}
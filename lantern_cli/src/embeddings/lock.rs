@@ -0,0 +1,88 @@
+use super::cli::EmbeddingArgs;
+use super::CONNECTION_PARAMS;
+use crate::logger::Logger;
+use crate::types::JOB_CANCELLED_MESSAGE;
+use crate::utils::append_params_to_uri;
+use postgres::{Client, NoTls};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+// First key of the advisory locks taken by embedding jobs, "lant" in ASCII
+// The second key is hashtext of the target column returned from get_lock_target
+pub static JOB_LOCK_CLASS: i32 = 0x6c61_6e74;
+static LOCK_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+// Column the job writes the embeddings to, e.g. public.articles.embedding
+// Chunking jobs write to the embedding column of the chunks table
+pub fn get_lock_target(args: &EmbeddingArgs) -> String {
+    match &args.chunks_table {
+        Some(chunks_table) => format!("{}.{chunks_table}.embedding", args.schema),
+        None => format!(
+            "{}.{}.{}",
+            args.out_schema.as_ref().unwrap_or(&args.schema),
+            args.out_table.as_ref().unwrap_or(&args.table),
+            args.out_column
+        ),
+    }
+}
+
+// Session level advisory lock on the target column in the output database
+// The lock is released when the connection is closed, so it does not outlive a crashed job
+pub struct JobLock {
+    _client: Client,
+}
+
+impl JobLock {
+    // Waits until the job holding the lock finishes, at most --wait-lock seconds if it is set
+    // With --fail-if-locked the job fails right away
+    pub fn acquire(
+        args: &EmbeddingArgs,
+        is_canceled: &Option<Arc<RwLock<bool>>>,
+        logger: &Logger,
+    ) -> Result<JobLock, anyhow::Error> {
+        let uri = append_params_to_uri(
+            args.out_uri.as_ref().unwrap_or(&args.uri),
+            CONNECTION_PARAMS,
+        );
+        let mut client = Client::connect(&uri, NoTls)?;
+        let target = get_lock_target(args);
+        let try_lock = |client: &mut Client| -> Result<bool, anyhow::Error> {
+            Ok(client
+                .query_one(
+                    "SELECT pg_try_advisory_lock($1, hashtext($2))",
+                    &[&JOB_LOCK_CLASS, &target],
+                )?
+                .get::<usize, bool>(0))
+        };
+
+        if try_lock(&mut client)? {
+            return Ok(JobLock { _client: client });
+        }
+        if args.fail_if_locked {
+            anyhow::bail!("Another job is writing embeddings to {target}");
+        }
+
+        logger.info(&format!(
+            "Another job is writing embeddings to {target}, waiting for it to finish"
+        ));
+        let timeout = args.wait_lock.map(Duration::from_secs);
+        let start = Instant::now();
+        loop {
+            if let Some(is_canceled) = is_canceled {
+                if *is_canceled.read().unwrap() {
+                    anyhow::bail!(JOB_CANCELLED_MESSAGE);
+                }
+            }
+            if timeout.is_some_and(|timeout| start.elapsed() >= timeout) {
+                anyhow::bail!(
+                    "Another job is still writing embeddings to {target} after waiting {}s",
+                    start.elapsed().as_secs()
+                );
+            }
+            std::thread::sleep(LOCK_POLL_INTERVAL);
+            if try_lock(&mut client)? {
+                return Ok(JobLock { _client: client });
+            }
+        }
+    }
+}
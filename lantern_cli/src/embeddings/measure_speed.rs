@@ -1,10 +1,15 @@
+use std::collections::HashMap;
 use std::{cmp, time::Instant};
 
 use super::core::{get_runtime, Runtime};
+use super::producer::get_estimated_count;
+use super::{cost, get_default_batch_size, print_json};
 use crate::logger::{LogLevel, Logger};
+use crate::utils::{get_full_table_name, quote_ident};
 use postgres::{Client, NoTls};
+use serde::Serialize;
 
-use super::cli::MeasureModelSpeedArgs;
+use super::cli::{BenchmarkArgs, ListFormat, MeasureModelSpeedArgs};
 use crate::types::*;
 
 static TABLE_NAME: &'static str = "_lantern_emb_test";
@@ -22,34 +27,30 @@ fn measure_model_speed(
     table_name: &str,
     initial_limit: u32,
     batch_size: Option<usize>,
-) -> AnyhowUsizeResult {
+) -> Result<(usize, f64), anyhow::Error> {
     let mut limit = initial_limit;
     let speed: usize;
+    let tokens_per_embedding: f64;
     let mut i = 0;
     loop {
         let logger = Logger::new("Lantern Embeddings", LogLevel::Error);
         let args = super::cli::EmbeddingArgs {
             uri: db_uri.to_owned(),
             create_column: false,
-            stream: false,
             model: model_name.to_owned(),
             column: COLUMN_NAME.to_owned(),
             out_column: OUT_COLUMN_NAME.to_owned(),
             schema: SCHEMA_NAME.to_owned(),
             table: table_name.to_owned(),
-            out_uri: None,
-            out_csv: None,
-            out_table: None,
             runtime: runtime.clone(),
             runtime_params: runtime_params.to_owned(),
             batch_size: batch_size.clone(),
-            visual: false,
             limit: Some(limit.clone()),
-            filter: None,
+            ..Default::default()
         };
         let start = Instant::now();
-        let (processed, _) =
-            super::create_embeddings_from_db(args, false, None, None, Some(logger))?;
+        let report = super::create_embeddings_from_db(args, false, None, None, Some(logger))?;
+        let (processed, tokens) = (report.processed_rows, report.processed_tokens);
         let elapsed = start.elapsed();
 
         if i == 0 {
@@ -60,12 +61,13 @@ fn measure_model_speed(
 
         if elapsed.as_millis() >= 1500 {
             speed = processed / elapsed.as_secs() as usize;
+            tokens_per_embedding = tokens as f64 / cmp::max(processed, 1) as f64;
             break;
         }
 
         limit = limit * 2;
     }
-    return Ok(speed.try_into()?);
+    return Ok((speed, tokens_per_embedding));
 }
 
 pub fn start_speed_test(args: &MeasureModelSpeedArgs, logger: Option<Logger>) -> AnyhowVoidResult {
@@ -122,7 +124,7 @@ pub fn start_speed_test(args: &MeasureModelSpeedArgs, logger: Option<Logger>) ->
 
     let logger = logger.unwrap_or(Logger::new("Lantern Embeddings", LogLevel::Info));
     for model_name in models {
-        let speed_max = measure_model_speed(
+        let (speed_max, tokens_min) = measure_model_speed(
             &args.runtime,
             &args.runtime_params,
             &model_name,
@@ -131,7 +133,7 @@ pub fn start_speed_test(args: &MeasureModelSpeedArgs, logger: Option<Logger>) ->
             args.initial_limit,
             args.batch_size,
         )?;
-        let speed_min = measure_model_speed(
+        let (speed_min, tokens_max) = measure_model_speed(
             &args.runtime,
             &args.runtime_params,
             &model_name,
@@ -145,7 +147,317 @@ pub fn start_speed_test(args: &MeasureModelSpeedArgs, logger: Option<Logger>) ->
         logger.info(&format!("{model_name} max speed - {speed_max} emb/s"));
         logger.info(&format!("{model_name} min speed - {speed_min} emb/s"));
         logger.info(&format!("{model_name} avg speed - {speed_avg} emb/s"));
+
+        if !cost::is_billed_runtime(&args.runtime) {
+            continue;
+        }
+        logger.info(&format!(
+            "{model_name} tokens per embedding - {tokens_min:.1} (small text), {tokens_max:.1} (large text)"
+        ));
+        match cost::get_token_price(&model_name, args.token_price) {
+            Some(price) => {
+                // Price is per 1M tokens, so cost of 1K embeddings is tokens * price / 1000
+                let cost_min = tokens_min * price / 1000.0;
+                let cost_max = tokens_max * price / 1000.0;
+                logger.info(&format!(
+                    "{model_name} cost per 1K embeddings - ${cost_min:.4} (small text), ${cost_max:.4} (large text)"
+                ));
+            }
+            None => logger.info(&format!(
+                "{model_name} cost per 1K embeddings - unknown (pass --token-price)"
+            )),
+        }
     }
     client.execute(&format!("DROP SCHEMA {SCHEMA_NAME} CASCADE"), &[])?;
     Ok(())
 }
+
+// Result of one model in the benchmark
+// Failed models have only the runtime, model and error set, so the other models are still compared
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct BenchmarkResult {
+    pub runtime: String,
+    pub model: String,
+    pub rows: usize,
+    pub tokens: usize,
+    pub rows_per_sec: f64,
+    pub dims: usize,
+    // USD, None for local models and models without known price
+    pub cost_per_1k_rows: Option<f64>,
+    pub estimated_table_cost: Option<f64>,
+    // Mean cosine similarity of the embeddings of neighbouring sample rows with different texts
+    // Values close to 1 mean that the model does not tell the texts apart
+    pub mean_similarity: Option<f64>,
+    pub error: Option<String>,
+}
+
+// Model is given as <runtime>:<model>, the runtime defaults to ort
+// Model names may contain ':' themselves, so the prefix is taken only if it is a runtime name
+fn parse_benchmark_model(spec: &str) -> Result<(Runtime, String), anyhow::Error> {
+    let (runtime, model) = match spec.split_once(':') {
+        Some((runtime, model)) => match Runtime::try_from(runtime) {
+            Ok(runtime) => (runtime, model),
+            Err(_) => (Runtime::Ort, spec),
+        },
+        None => (Runtime::Ort, spec),
+    };
+    if model.is_empty() {
+        anyhow::bail!("Model name is missing in '{spec}'");
+    }
+    Ok((runtime, model.to_owned()))
+}
+
+// Runtime params are given as <runtime>=<json>, runtimes without params get '{}'
+fn parse_benchmark_runtime_params(
+    params: &[String],
+) -> Result<HashMap<String, String>, anyhow::Error> {
+    let mut runtime_params = HashMap::new();
+    for param in params {
+        let Some((runtime, json)) = param.split_once('=') else {
+            anyhow::bail!("Runtime params should be given as <runtime>=<json>, got '{param}'");
+        };
+        let runtime = Runtime::try_from(runtime)?;
+        serde_json::from_str::<serde_json::Value>(json).map_err(|e| {
+            anyhow::anyhow!("Invalid runtime params of {}: {e}", runtime.to_string())
+        })?;
+        runtime_params.insert(runtime.to_string(), json.to_owned());
+    }
+    Ok(runtime_params)
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f64 {
+    let dot: f64 = a.iter().zip(b).map(|(x, y)| *x as f64 * *y as f64).sum();
+    let norm_a = a.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    let norm_b = b.iter().map(|x| (*x as f64).powi(2)).sum::<f64>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 0.0;
+    }
+    dot / (norm_a * norm_b)
+}
+
+fn get_mean_similarity(texts: &[String], embeddings: &[Vec<f32>]) -> Option<f64> {
+    let similarities: Vec<f64> = (1..embeddings.len())
+        .filter(|idx| texts[idx - 1] != texts[*idx])
+        .map(|idx| cosine_similarity(&embeddings[idx - 1], &embeddings[idx]))
+        .collect();
+    if similarities.is_empty() {
+        return None;
+    }
+    Some(similarities.iter().sum::<f64>() / similarities.len() as f64)
+}
+
+fn benchmark_model(
+    runtime: &Runtime,
+    runtime_params: &str,
+    model: &str,
+    texts: &[String],
+    batch_size: usize,
+    table_rows: i64,
+) -> Result<BenchmarkResult, anyhow::Error> {
+    let embedding_runtime = get_runtime(runtime, None, runtime_params)?;
+    let inputs: Vec<&str> = texts.iter().map(|text| text.as_str()).collect();
+
+    // First text is embedded before the timer is started, so model loading is not counted
+    embedding_runtime.process(model, &vec![inputs[0]])?;
+
+    let start = Instant::now();
+    let mut embeddings = Vec::with_capacity(inputs.len());
+    let mut tokens = 0;
+    for batch in inputs.chunks(batch_size) {
+        let result = embedding_runtime.process(model, &batch.to_vec())?;
+        if result.embeddings.len() != batch.len() {
+            anyhow::bail!(
+                "Runtime returned {} embeddings for {} texts",
+                result.embeddings.len(),
+                batch.len()
+            );
+        }
+        tokens += result.processed_tokens;
+        embeddings.extend(result.embeddings);
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+
+    let dims = embeddings[0].len();
+    if embeddings.iter().any(|embedding| embedding.len() != dims) {
+        anyhow::bail!("Runtime returned embeddings with different dimensions");
+    }
+    if embeddings.iter().flatten().any(|value| !value.is_finite()) {
+        anyhow::bail!("Runtime returned embeddings with NaN or infinite values");
+    }
+
+    let tokens_per_row = tokens as f64 / texts.len() as f64;
+    let token_price = if cost::is_billed_runtime(runtime) {
+        cost::get_token_price(model, None)
+    } else {
+        None
+    };
+    Ok(BenchmarkResult {
+        runtime: runtime.to_string(),
+        model: model.to_owned(),
+        rows: texts.len(),
+        tokens,
+        rows_per_sec: if elapsed > 0.0 {
+            texts.len() as f64 / elapsed
+        } else {
+            0.0
+        },
+        dims,
+        // Price is per 1M tokens
+        cost_per_1k_rows: token_price.map(|price| tokens_per_row * price / 1000.0),
+        estimated_table_cost: token_price
+            .map(|price| table_rows as f64 * tokens_per_row * price / 1_000_000.0),
+        mean_similarity: get_mean_similarity(texts, &embeddings),
+        error: None,
+    })
+}
+
+// The same random sample of the table is embedded with each model
+pub fn run_benchmark(
+    args: &BenchmarkArgs,
+    logger: &Logger,
+) -> Result<Vec<BenchmarkResult>, anyhow::Error> {
+    if args.sample_size == 0 {
+        anyhow::bail!("--sample-size should be greater than 0");
+    }
+    if args.batch_size == Some(0) {
+        anyhow::bail!("Batch size should be greater than 0");
+    }
+    let models = args
+        .models
+        .iter()
+        .map(|spec| parse_benchmark_model(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    let runtime_params = parse_benchmark_runtime_params(&args.runtime_params)?;
+
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let column = quote_ident(&args.column);
+    let filter_sql = match &args.filter {
+        Some(filter) => format!("WHERE {column} IS NOT NULL AND ({filter})"),
+        None => format!("WHERE {column} IS NOT NULL"),
+    };
+    let mut client = Client::connect(&args.uri, NoTls)?;
+    let mut transaction = client.transaction()?;
+    let texts: Vec<String> = transaction
+        .query(
+            &format!(
+                "SELECT {column}::text FROM {full_table_name} {filter_sql} AND trim({column}::text) <> '' ORDER BY random() LIMIT {}",
+                args.sample_size
+            ),
+            &[],
+        )?
+        .iter()
+        .map(|row| row.get(0))
+        .collect();
+    if texts.is_empty() {
+        anyhow::bail!("No rows with text found in {full_table_name}");
+    }
+    let table_rows = get_estimated_count(&mut transaction, &full_table_name, &filter_sql)?;
+    transaction.commit()?;
+    logger.info(&format!(
+        "Embedding {} sample rows of {full_table_name} with {} models",
+        texts.len(),
+        models.len()
+    ));
+
+    let mut results = Vec::with_capacity(models.len());
+    for (runtime, model) in models {
+        let params = runtime_params
+            .get(&runtime.to_string())
+            .map(|params| params.as_str())
+            .unwrap_or("{}");
+        let batch_size = args.batch_size.unwrap_or(get_default_batch_size(&model));
+        let result = match benchmark_model(&runtime, params, &model, &texts, batch_size, table_rows)
+        {
+            Ok(result) => result,
+            Err(e) => {
+                logger.error(&format!("{}:{model} failed: {e}", runtime.to_string()));
+                BenchmarkResult {
+                    runtime: runtime.to_string(),
+                    model,
+                    error: Some(e.to_string()),
+                    ..Default::default()
+                }
+            }
+        };
+        results.push(result);
+    }
+    Ok(results)
+}
+
+pub fn format_benchmark_table(results: &[BenchmarkResult]) -> String {
+    let format_cost = |cost: Option<f64>| match cost {
+        Some(cost) => format!("${cost:.4}"),
+        None => "-".to_owned(),
+    };
+    let header = [
+        "Model",
+        "Runtime",
+        "Rows/s",
+        "Dims",
+        "Tokens/row",
+        "Cost/1K rows",
+        "Table cost",
+        "Mean sim",
+    ]
+    .map(|title| title.to_owned())
+    .to_vec();
+    let rows: Vec<Vec<String>> = results
+        .iter()
+        .map(|result| match &result.error {
+            Some(error) => vec![
+                result.model.clone(),
+                result.runtime.clone(),
+                format!("failed: {error}"),
+            ],
+            None => vec![
+                result.model.clone(),
+                result.runtime.clone(),
+                format!("{:.1}", result.rows_per_sec),
+                result.dims.to_string(),
+                format!("{:.1}", result.tokens as f64 / result.rows.max(1) as f64),
+                format_cost(result.cost_per_1k_rows),
+                format_cost(result.estimated_table_cost),
+                match result.mean_similarity {
+                    Some(similarity) => format!("{similarity:.3}"),
+                    None => "-".to_owned(),
+                },
+            ],
+        })
+        .collect();
+
+    // Error of a failed model is not aligned, so it does not widen the throughput column
+    let mut widths: Vec<usize> = header.iter().map(|title| title.len()).collect();
+    for row in &rows {
+        let aligned = if row.len() == header.len() {
+            row.len()
+        } else {
+            row.len() - 1
+        };
+        for (idx, value) in row.iter().take(aligned).enumerate() {
+            widths[idx] = widths[idx].max(value.len());
+        }
+    }
+
+    let mut table = String::new();
+    for row in std::iter::once(&header).chain(&rows) {
+        let line = row
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| format!("{value:<width$}", width = widths[idx]))
+            .collect::<Vec<_>>()
+            .join("  ");
+        table.push_str(line.trim_end());
+        table.push('\n');
+    }
+    table
+}
+
+pub fn print_benchmark(args: &BenchmarkArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Embeddings", LogLevel::Info));
+    let results = run_benchmark(args, &logger)?;
+    if args.format == ListFormat::Json {
+        return print_json(&logger, &results);
+    }
+    logger.print_raw(&format_benchmark_table(&results));
+    Ok(())
+}
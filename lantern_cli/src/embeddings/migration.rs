@@ -0,0 +1,201 @@
+use super::binary;
+use super::cli::{EmbeddingArgs, OutMode};
+use super::db_exporter::get_create_table_sql;
+use super::index;
+use super::{get_column_type_sql, get_quantize_column_type_sql, CONNECTION_PARAMS};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+use std::fs;
+
+fn column_exists(
+    client: &mut Client,
+    schema: &str,
+    table: &str,
+    column: &str,
+) -> Result<bool, anyhow::Error> {
+    let res = client.query(
+        "SELECT 1 FROM information_schema.columns WHERE table_schema=$1 AND table_name=$2 AND column_name=$3",
+        &[&schema, &table, &column],
+    )?;
+
+    Ok(!res.is_empty())
+}
+
+fn index_exists(client: &mut Client, schema: &str, index: &str) -> Result<bool, anyhow::Error> {
+    let res = client.query(
+        "SELECT 1 FROM pg_indexes WHERE schemaname=$1 AND indexname=$2",
+        &[&schema, &index],
+    )?;
+
+    Ok(!res.is_empty())
+}
+
+fn function_exists(
+    client: &mut Client,
+    schema: &str,
+    function: &str,
+) -> Result<bool, anyhow::Error> {
+    let res = client.query(
+        "SELECT 1 FROM pg_proc p JOIN pg_namespace n ON n.oid=p.pronamespace WHERE n.nspname=$1 AND p.proname=$2",
+        &[&schema, &function],
+    )?;
+
+    Ok(!res.is_empty())
+}
+
+fn table_exists(client: &mut Client, schema: &str, table: &str) -> Result<bool, anyhow::Error> {
+    let res = client.query(
+        "SELECT 1 FROM information_schema.tables WHERE table_schema=$1 AND table_name=$2",
+        &[&schema, &table],
+    )?;
+
+    Ok(!res.is_empty())
+}
+
+// Collect DDL statements which would be executed by the job to prepare destination schema
+pub fn get_schema_changes(args: &EmbeddingArgs) -> Result<Vec<String>, anyhow::Error> {
+    let mut statements = Vec::new();
+    let schema = &args.schema;
+
+    if let Some(chunks_table) = &args.chunks_table {
+        let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+        let mut client = Client::connect(&uri, NoTls)?;
+
+        if !table_exists(&mut client, schema, chunks_table)? {
+            let pk_type = client
+                .query_one(
+                    "SELECT format_type(atttypid, atttypmod) FROM pg_attribute WHERE attrelid=$1::text::regclass AND attname=$2",
+                    &[&get_full_table_name(schema, &args.table), &args.pk],
+                )?
+                .get::<usize, String>(0);
+
+            statements.push(format!(
+                "CREATE TABLE {} (source_pk {pk_type}, chunk_index INT, chunk_text TEXT, embedding REAL[]);",
+                get_full_table_name(schema, chunks_table)
+            ));
+        }
+
+        return Ok(statements);
+    }
+
+    if args.out_csv.is_some() || (!args.create_column && args.create_index.is_none()) {
+        return Ok(statements);
+    }
+
+    let uri = args.out_uri.as_ref().unwrap_or(&args.uri);
+    let schema = args.out_schema.as_ref().unwrap_or(&args.schema);
+    let table = args.out_table.as_ref().unwrap_or(&args.table);
+    let full_table_name = get_full_table_name(schema, table);
+    let uri = append_params_to_uri(uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+
+    statements.append(&mut get_column_changes(args, &mut client, schema, table)?);
+
+    // Index on the output column is created by the migration instead of after the job
+    if let Some(spec) = &args.create_index {
+        let index_name = index::get_index_name(args);
+        if !index_exists(&mut client, schema, &index_name)? {
+            let spec = index::IndexSpec::parse(spec)?;
+            statements.push(format!(
+                "{};",
+                spec.get_create_index_sql(args, &full_table_name, &index_name)?
+            ));
+        }
+    }
+
+    Ok(statements)
+}
+
+fn get_column_changes(
+    args: &EmbeddingArgs,
+    client: &mut Client,
+    schema: &str,
+    table: &str,
+) -> Result<Vec<String>, anyhow::Error> {
+    let mut statements = Vec::new();
+    if !args.create_column {
+        return Ok(statements);
+    }
+    let full_table_name = get_full_table_name(schema, table);
+
+    // Missing output table is created by the exporter with the embedding columns
+    if (args.has_file_input() || !args.writes_to_source_table())
+        && !table_exists(client, schema, table)?
+    {
+        // Without the migration the job would switch to insert mode, but after the migration is applied
+        // the table exists and rows of the empty table would be updated
+        if matches!(args.out_mode, OutMode::Update) && !args.has_file_input() {
+            anyhow::bail!("Output table {full_table_name} does not exist, pass --out-mode insert or upsert to create it with --emit-migration");
+        }
+        let key_column = args.out_key_column.as_ref().unwrap_or(&args.pk);
+        statements.push(format!(
+            "{};",
+            get_create_table_sql(
+                args,
+                &full_table_name,
+                key_column,
+                matches!(args.out_mode, OutMode::Upsert)
+            )?
+        ));
+        return Ok(statements);
+    }
+
+    if !column_exists(client, schema, table, &args.out_column)? {
+        statements.push(format!(
+            "ALTER TABLE {full_table_name} ADD COLUMN {} {};",
+            quote_ident(&args.out_column),
+            get_column_type_sql(args)?
+        ));
+    }
+
+    if let Some(quantize_column) = &args.quantize_column {
+        if !column_exists(client, schema, table, quantize_column)? {
+            statements.push(format!(
+                "ALTER TABLE {full_table_name} ADD COLUMN {} {};",
+                quote_ident(quantize_column),
+                get_quantize_column_type_sql(args)?
+            ));
+        }
+    }
+
+    if args.stale_check.is_some()
+        && !column_exists(client, schema, table, &args.embedded_at_column)?
+    {
+        statements.push(format!(
+            "ALTER TABLE {full_table_name} ADD COLUMN {} TIMESTAMPTZ;",
+            quote_ident(&args.embedded_at_column)
+        ));
+    }
+
+    if let Some(text_column) = &args.jsonpath_text_column {
+        if !column_exists(client, schema, table, text_column)? {
+            statements.push(format!(
+                "ALTER TABLE {full_table_name} ADD COLUMN {} TEXT;",
+                quote_ident(text_column)
+            ));
+        }
+    }
+
+    // Helper function for binary vectors is created by the exporter of update mode
+    if args.quantize.is_some()
+        && matches!(args.out_mode, OutMode::Update)
+        && !args.has_file_input()
+        && !function_exists(client, schema, "hamming_distance")?
+    {
+        statements.push(binary::get_hamming_distance_sql(schema).trim().to_owned());
+    }
+
+    Ok(statements)
+}
+
+pub fn write_migration(path: &str, statements: &[String]) -> AnyhowVoidResult {
+    let mut content = String::from("-- Generated by lantern-cli\n");
+    for statement in statements {
+        content.push_str(statement);
+        content.push('\n');
+    }
+
+    fs::write(path, content)?;
+    Ok(())
+}
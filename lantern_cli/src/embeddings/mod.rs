@@ -1,140 +1,312 @@
 use crate::logger::{LogLevel, Logger};
+use crate::metrics::{self, Subsystem};
 use crate::types::*;
 use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
-use core::{get_available_runtimes, get_runtime};
-use csv::Writer;
-use rand::Rng;
-use std::io::Write;
+use crate::vector_jobs::vector_ops::normalize_vector;
+use affinity::{parse_core_list, pin_current_thread_to};
+use array::MeanAggregator;
+use batcher::{Batch, TokenBatcher};
+use cache::{hash_text, EmbeddingCache};
+use core::{
+    default_logger, get_available_runtimes, get_runtime, runtime::ModelInfo, stderr_logger,
+    LoggerFn, Runtime,
+};
+use csv_writer::CsvExporter;
+use db_exporter::{DbExporter, TableExporter};
+use exporter::{Exporter, HookExporter};
+use jsonl_writer::JsonlExporter;
+use limiter::RequestLimiter;
+use parquet_writer::ParquetExporter;
+use precision::ValueFormat;
+use producer::{BatchSink, PostgresProducer, Producer, SourceRecord};
+use progress::{GaugeGuard, PipelineStats};
+use report::JobReport;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::Ordering;
 use std::sync::mpsc::{Receiver, Sender};
-use std::sync::{mpsc, Arc, RwLock};
+use std::sync::{mpsc, Arc, Mutex, RwLock};
 use std::thread::JoinHandle;
 use std::time::Instant;
+use strum::IntoEnumIterator;
+use writer_pool::WriterPool;
 
-use postgres::{Client, NoTls, Row};
+use postgres::{Client, NoTls};
 
+pub mod affinity;
+mod array;
+mod batcher;
+pub mod binary;
+mod cache;
+pub mod chunking;
 pub mod cli;
 pub mod core;
+pub mod cost;
+mod csv_writer;
+mod db_exporter;
+mod dimensions;
+pub mod dry_run;
+pub mod embed_text;
+pub mod exporter;
+pub mod fan_out;
+mod flush;
+pub mod index;
+pub mod int8;
+mod jsonl_writer;
+mod limiter;
+pub mod lineage;
+pub mod lock;
 pub mod measure_speed;
-
-type EmbeddingRecord = (String, Vec<f32>);
+mod migration;
+pub mod models;
+pub mod object_store;
+mod parquet_writer;
+pub mod precision;
+pub mod producer;
+mod progress;
+mod replica;
+pub mod report;
+pub mod rerank;
+mod scan;
+pub mod search;
+mod sync;
+mod tenants;
+mod writer_pool;
+
+pub use sync::sync_embeddings;
+
+// Row id and its embedding
+// Rows are identified by ctid, except in pipelines with custom exporters or --out-mode insert and
+// upsert which use the primary key
+pub type EmbeddingRecord = (String, Vec<f32>);
+pub type BatchHookFn = Box<dyn FnMut(Vec<EmbeddingRecord>) -> AnyhowVoidResult + Send>;
 
 static CONNECTION_PARAMS: &'static str = "connect_timeout=10";
 
-// Helper function to calculate progress using total and processed row count
-fn calculate_progress(total: i64, processed: usize) -> u8 {
-    if total <= 0 {
-        return 0;
+// Build WHERE clause for source rows
+// In incremental mode only rows with missing or stale embeddings will be selected
+fn get_filter_sql(args: &cli::EmbeddingArgs) -> String {
+    let mut conditions = vec![match &args.filter {
+        Some(filter) => format!("({filter})"),
+        None => format!("{} IS NOT NULL", quote_ident(&args.column)),
+    }];
+
+    if args.jsonpath.is_some() && matches!(args.jsonpath_missing, cli::JsonPathMissing::Skip) {
+        conditions.push(format!(
+            "{} IS NOT NULL",
+            get_source_sql(args, &quote_ident(&args.column))
+        ));
     }
 
-    return ((processed as f64 / total as f64) * 100.0) as u8;
+    let out_column = quote_ident(&args.out_column);
+    if let Some(updated_at_column) = &args.stale_check {
+        conditions.push(format!(
+            "({out_column} IS NULL OR {embedded_at} IS NULL OR {updated_at} > {embedded_at})",
+            embedded_at = quote_ident(&args.embedded_at_column),
+            updated_at = quote_ident(updated_at_column),
+        ));
+    } else if args.only_missing {
+        conditions.push(format!("{out_column} IS NULL"));
+    }
+
+    format!("WHERE {}", conditions.join(" AND "))
 }
-// This function will do the following
-// 1. Get approximate number of rows from pg_class (this is just for info logging)
-// 2. Create transaction portal which will poll data from database of batch size provided via args
-// 3. Send the rows over the channel
-fn producer_worker(
-    args: Arc<cli::EmbeddingArgs>,
-    batch_size: usize,
-    tx: Sender<Vec<Row>>,
-    estimate_count: bool,
-    logger: Arc<Logger>,
-) -> Result<(JoinHandle<AnyhowVoidResult>, i64), anyhow::Error> {
-    let mut item_count = 0;
-    let (count_tx, count_rx): (Sender<i64>, Receiver<i64>) = mpsc::channel();
 
-    let handle = std::thread::spawn(move || {
-        let column = &args.column;
-        let schema = &args.schema;
-        let table = &args.table;
-        let full_table_name = get_full_table_name(schema, table);
+// Get SQL expression of the source text for the given column expression
+// If JSONPath is specified the text is extracted from JSONB value, objects and arrays are kept as JSON text
+pub(crate) fn get_source_sql(args: &cli::EmbeddingArgs, column: &str) -> String {
+    if args.array_mode.is_some() {
+        return format!("{column}::text[]");
+    }
 
-        let filter_sql = if args.filter.is_some() {
-            format!("WHERE {}", args.filter.as_ref().unwrap())
-        } else {
-            format!("WHERE {column} IS NOT NULL", column = quote_ident(column))
-        };
+    match &args.jsonpath {
+        Some(jsonpath) => format!(
+            "jsonb_path_query_first({column}::jsonb, '{}'::jsonpath) #>> '{{}}'",
+            jsonpath.replace('\'', "''")
+        ),
+        None => format!("{column}::text"),
+    }
+}
 
-        let limit_sql = if args.limit.is_some() {
-            format!("LIMIT {}", args.limit.as_ref().unwrap())
-        } else {
-            "".to_owned()
-        };
+fn check_jsonpath_args(args: &cli::EmbeddingArgs) -> AnyhowVoidResult {
+    let jsonpath = match &args.jsonpath {
+        Some(jsonpath) => jsonpath,
+        None => {
+            if args.jsonpath_text_column.is_some() {
+                anyhow::bail!("--jsonpath is required when --jsonpath-text-column is specified");
+            }
+            return Ok(());
+        }
+    };
 
-        let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
-        let client = Client::connect(&uri, NoTls);
+    if args.jsonpath_text_column.is_some() {
+        if args.out_csv.is_some() || args.chunks_table.is_some() || !args.writes_to_source_table() {
+            anyhow::bail!(
+                "--jsonpath-text-column can be used only when embeddings are written to the source table"
+            );
+        }
+    }
 
-        // we are excplicity checking for error here
-        // because the item_count atomic should be update
-        // as we have a while loop waiting for it
-        // if before updating the variable there will be error the while
-        // loop will never exit
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    if let Err(e) = client.query_one("SELECT $1::text::jsonpath", &[jsonpath]) {
+        anyhow::bail!("Invalid JSONPath '{jsonpath}': {e}");
+    }
 
-        if let Err(e) = client {
-            count_tx.send(0)?;
-            anyhow::bail!("{e}");
-        }
-        let mut client = client.unwrap();
+    Ok(())
+}
 
-        let mut transaction = client.transaction()?;
+// Output database is checked before the embeddings are generated, so connection errors are
+// reported up front. Rows of other tables are matched by primary key, so if the output table
+// does not exist, it is created and the embeddings are inserted into it
+fn check_output_table(
+    args: cli::EmbeddingArgs,
+    logger: &Logger,
+) -> Result<cli::EmbeddingArgs, anyhow::Error> {
+    if args.has_file_output() || args.has_file_input() || args.writes_to_source_table() {
+        return Ok(args);
+    }
 
-        if estimate_count {
-            let rows = transaction.query(
-                &format!("SELECT COUNT(*) FROM {full_table_name} {filter_sql} {limit_sql};"),
-                &[],
-            );
+    let out_uri = append_params_to_uri(
+        args.out_uri.as_ref().unwrap_or(&args.uri),
+        CONNECTION_PARAMS,
+    );
+    let mut client = match Client::connect(&out_uri, NoTls) {
+        Ok(client) => client,
+        Err(e) => anyhow::bail!("Could not connect to output database: {e}"),
+    };
+    let out_full_table_name = get_full_table_name(
+        args.out_schema.as_ref().unwrap_or(&args.schema),
+        args.out_table.as_ref().unwrap_or(&args.table),
+    );
+    let table_exists = client
+        .query_one(
+            "SELECT to_regclass($1::text) IS NOT NULL",
+            &[&out_full_table_name],
+        )?
+        .get::<usize, bool>(0);
 
-            if let Err(e) = rows {
-                count_tx.send(0)?;
-                anyhow::bail!("{e}");
-            }
+    if table_exists || !matches!(args.out_mode, cli::OutMode::Update) {
+        return Ok(args);
+    }
+    if !args.create_column {
+        anyhow::bail!("Output table {out_full_table_name} does not exist");
+    }
 
-            let rows = rows.unwrap();
+    logger.info(&format!(
+        "Output table {out_full_table_name} does not exist, embeddings will be inserted into a new table"
+    ));
+    Ok(cli::EmbeddingArgs {
+        out_mode: cli::OutMode::Insert,
+        ..args
+    })
+}
 
-            let count: i64 = rows[0].get(0);
-            count_tx.send(count)?;
-            if count > 0 {
-                logger.info(&format!(
-                    "Found approximately {} items in table \"{}\"",
-                    count, table,
-                ));
-            }
-        } else {
-            count_tx.send(0)?;
-        }
+// Refuse to send the data to endpoints outside of the required region
+fn check_required_region(args: &cli::EmbeddingArgs) -> AnyhowVoidResult {
+    let required_region = match &args.require_region {
+        Some(region) => region,
+        None => return Ok(()),
+    };
 
-        // With portal we can execute a query and poll values from it in chunks
-        let portal = transaction.bind(
-            &format!(
-                "SELECT ctid::text, {column}::text FROM {full_table_name} {filter_sql} {limit_sql};",
-                column = quote_ident(column),
-            ),
-            &[],
-        )?;
+    // Local runtime does not send the data anywhere
+    if args.runtime == cli::Runtime::Ort {
+        return Ok(());
+    }
 
-        loop {
-            // poll batch_size rows from portal and send it to embedding thread via channel
-            let rows = transaction.query_portal(&portal, batch_size as i32)?;
+    let runtime = get_runtime(&args.runtime, None, &args.runtime_params)?;
+    match runtime.get_region() {
+        Some(region) if region.eq_ignore_ascii_case(required_region) => Ok(()),
+        Some(region) => anyhow::bail!(
+            "Runtime endpoint region '{region}' does not match required region '{required_region}'"
+        ),
+        None => anyhow::bail!(
+            "Runtime endpoint region is unknown. Specify 'region' in runtime params to use --require-region"
+        ),
+    }
+}
 
-            if rows.len() == 0 {
-                break;
+// Get SQL type of the destination column
+// For vector columns the dimensions from runtime params will be used as typmod
+fn get_column_type_sql(args: &cli::EmbeddingArgs) -> Result<String, anyhow::Error> {
+    if args.quantize.is_some() && args.quantize_column.is_none() {
+        return get_quantize_column_type_sql(args);
+    }
+    match args.column_type {
+        cli::ColumnType::Real => Ok(ValueFormat::for_table(args).array_type_sql().to_owned()),
+        cli::ColumnType::Vector => {
+            let vector_type = match args.precision {
+                cli::Precision::F32 => "vector",
+                cli::Precision::F16 => "halfvec",
+                cli::Precision::Int8 => {
+                    anyhow::bail!("--precision int8 can only be used with --column-type real")
+                }
+            };
+            match get_output_dimensions(args)? {
+                Some(dimensions) => Ok(format!("{vector_type}({dimensions})")),
+                None => Ok(vector_type.to_owned()),
             }
+        }
+    }
+}
 
-            if tx.send(rows).is_err() {
-                break;
-            }
+// Get SQL type of the column binary vectors are written to
+fn get_quantize_column_type_sql(args: &cli::EmbeddingArgs) -> Result<String, anyhow::Error> {
+    Ok(binary::get_binary_type_sql(
+        args,
+        get_output_dimensions(args)?,
+    ))
+}
+
+// Dimensions of the exported embeddings if they are known before the job is started
+fn get_output_dimensions(args: &cli::EmbeddingArgs) -> Result<Option<u64>, anyhow::Error> {
+    if let Some(dimensions) = args.truncate_dim {
+        return Ok(Some(dimensions as u64));
+    }
+    let runtime_params: serde_json::Value = serde_json::from_str(&args.runtime_params)?;
+    Ok(runtime_params.get("dimensions").and_then(|d| d.as_u64()))
+}
+
+// Keep first dimensions of Matryoshka embeddings and scale them back to unit length
+fn truncate_embeddings(embeddings: &mut [Vec<f32>], dimensions: usize) -> AnyhowVoidResult {
+    for embedding in embeddings.iter_mut() {
+        if embedding.len() < dimensions {
+            anyhow::bail!(
+                "Can not truncate embedding with {} dimensions to {dimensions} dimensions",
+                embedding.len()
+            );
         }
-        drop(tx);
-        Ok(())
+        embedding.truncate(dimensions);
+        normalize_vector(embedding);
+    }
+    Ok(())
+}
+
+// Run the producer on its own thread and wait until it reports the row count
+// The count is used by the exporter to track the progress
+fn producer_worker(
+    args: Arc<cli::EmbeddingArgs>,
+    mut producer: Box<dyn Producer>,
+    batch_size: usize,
+    tx: Sender<Vec<SourceRecord>>,
+    stats: Arc<PipelineStats>,
+) -> Result<(JoinHandle<AnyhowVoidResult>, i64), anyhow::Error> {
+    let (count_tx, count_rx): (Sender<i64>, Receiver<i64>) = mpsc::channel();
+    let parent_span = tracing::Span::current();
+
+    let handle = std::thread::spawn(move || {
+        let _span = tracing::info_span!(parent: &parent_span, "producer").entered();
+        let mut sink = BatchSink::new(tx, count_tx, stats);
+        let result = pin_current_thread_to(&args.producer_cores)
+            .and_then(|_| producer.produce(batch_size, &mut sink));
+
+        // The count should be sent even if the producer failed,
+        // otherwise the pipeline would wait for it forever
+        sink.report_count(0);
+        result
     });
 
-    // If we have filter or limit we are not going to fetch
-    // the item count and progress anyway, so we won't lock the process waiting
-    // for thread
-    // Wait for the other thread to release the lock
-    while let Ok(count) = count_rx.recv() {
-        item_count = count;
-        break;
-    }
+    let item_count = count_rx.recv().unwrap_or(0);
 
     return Ok((handle, item_count));
 }
@@ -142,283 +314,337 @@ fn producer_worker(
 // Embedding worker will listen to the producer channel
 // and execute embeddings_core's corresponding function to generate embeddings
 // we will here map each vector to it's row ctid before sending the results over channel
-// So we will get Vec<Row<String, String> and output Vec<(String, Vec<f32>)> the output will
+// So we will get Vec<(String, String)> and output Vec<(String, Vec<f32>)> the output will
 // contain generated embeddings for the text. If text will be null we will skip that row
 fn embedding_worker(
     args: Arc<cli::EmbeddingArgs>,
-    rx: Receiver<Vec<Row>>,
+    rx: Arc<Mutex<Receiver<Vec<SourceRecord>>>>,
     tx: Sender<Vec<EmbeddingRecord>>,
     is_canceled: Option<Arc<RwLock<bool>>>,
+    limiter: Option<Arc<RequestLimiter>>,
+    stats: Arc<PipelineStats>,
     logger: Arc<Logger>,
 ) -> Result<JoinHandle<AnyhowUsizeResult>, anyhow::Error> {
+    let parent_span = tracing::Span::current();
     let handle = std::thread::spawn(move || {
+        let _span = tracing::info_span!(parent: &parent_span, "embedding_worker").entered();
+        pin_current_thread_to(&args.embedding_cores)?;
         let mut count: usize = 0;
         let mut processed_tokens: usize = 0;
         let model = &args.model;
-        let mut start = Instant::now();
-        let runtime = get_runtime(&args.runtime, None, &args.runtime_params)?;
-
-        while let Ok(rows) = rx.recv() {
-            if is_canceled.is_some() && *is_canceled.as_ref().unwrap().read().unwrap() {
-                // This variable will be changed from outside to gracefully
-                // exit job on next chunk
-                anyhow::bail!(JOB_CANCELLED_MESSAGE);
-            }
-
-            if count == 0 {
-                // mark exact start time
-                start = Instant::now();
-            }
-
-            let mut input_vectors: Vec<&str> = Vec::with_capacity(rows.len());
-            let mut input_ids: Vec<String> = Vec::with_capacity(rows.len());
+        let runtime_name = args.runtime.to_string();
+        let runtime_logger: LoggerFn = if args.writes_to_stdout() {
+            stderr_logger
+        } else {
+            default_logger
+        };
+        let runtime = get_runtime(&args.runtime, Some(&runtime_logger), &args.runtime_params)?;
+        let mut batcher = TokenBatcher::new(
+            get_max_tokens_per_batch(&args),
+            args.batch_size
+                .unwrap_or(get_default_batch_size(&args.model)),
+        );
+        let mut cache = if args.use_cache {
+            // Cache is written, so with a read replica it is kept on the primary
+            let cache_uri = match (args.read_replica, &args.out_uri) {
+                (true, Some(out_uri)) => out_uri,
+                _ => &args.uri,
+            };
+            Some(EmbeddingCache::new(
+                cache_uri,
+                &args.runtime,
+                &args.runtime_params,
+            )?)
+        } else {
+            None
+        };
+        let _connection = cache
+            .as_ref()
+            .map(|_| GaugeGuard::new(&stats.active_connections));
+
+        // In array mean mode embeddings of the elements are aggregated before sending to exporter
+        let mut aggregator = match args.array_mode {
+            Some(cli::ArrayMode::Mean) => Some(MeanAggregator::new()),
+            _ => None,
+        };
 
-            for row in &rows {
-                if let Ok(Some(src_data)) = row.try_get::<usize, Option<&str>>(1) {
-                    if src_data.trim() != "" {
-                        input_vectors.push(src_data);
-                        input_ids.push(row.get::<usize, String>(0));
+        let mut process_batch =
+            |(mut input_ids, input_vectors, token_counts): Batch,
+             aggregator: &mut Option<MeanAggregator>| {
+                let input_hashes: Vec<String> = if cache.is_some() {
+                    input_vectors.iter().map(|s| hash_text(s)).collect()
+                } else {
+                    Vec::new()
+                };
+
+                let cached_embeddings = match cache.as_mut() {
+                    Some(cache) => cache.get(model, &input_hashes)?,
+                    None => HashMap::new(),
+                };
+
+                // Only texts missing from cache are sent to the runtime
+                let missing_inputs: Vec<(usize, &str)> = input_vectors
+                    .iter()
+                    .enumerate()
+                    .filter(|(idx, _)| {
+                        input_hashes.is_empty()
+                            || !cached_embeddings.contains_key(&input_hashes[*idx])
+                    })
+                    .map(|(idx, s)| (idx, s.as_str()))
+                    .collect();
+
+                let mut generated_embeddings = if missing_inputs.is_empty() {
+                    Vec::new()
+                } else {
+                    let inputs: Vec<&str> = missing_inputs.iter().map(|(_, s)| *s).collect();
+                    // Runtimes may split the batch into several HTTP requests
+                    let request_count = runtime.count_requests(model, &inputs)?;
+                    if let Some(limiter) = &limiter {
+                        // Tokens are counted before batching when they are limited
+                        let tokens = missing_inputs
+                            .iter()
+                            .map(|(idx, _)| token_counts[*idx])
+                            .sum();
+                        limiter.wait(request_count, tokens);
+                    }
+                    stats.batch_size.store(inputs.len(), Ordering::Relaxed);
+                    stats.requests.fetch_add(request_count, Ordering::Relaxed);
+                    let request = GaugeGuard::new(&stats.in_flight_requests);
+                    let request_start = Instant::now();
+                    let embedding_response =
+                        tracing::info_span!("embed_batch", rows = inputs.len())
+                            .in_scope(|| runtime.process(model, &inputs));
+                    drop(request);
+
+                    if let Err(e) = embedding_response {
+                        metrics::inc_counter(
+                            Subsystem::Embeddings,
+                            "runtime_errors_total",
+                            &[model, &runtime_name],
+                            1.0,
+                        );
+                        anyhow::bail!("{}", e);
+                    }
+                    let request_duration = request_start.elapsed();
+                    stats.latencies.record_embed(request_duration);
+                    metrics::observe(
+                        Subsystem::Embeddings,
+                        "batch_duration_seconds",
+                        &[model, &runtime_name],
+                        request_duration.as_secs_f64(),
+                    );
+
+                    let embedding_response = embedding_response.unwrap();
+                    processed_tokens += embedding_response.processed_tokens;
+                    stats
+                        .tokens
+                        .fetch_add(embedding_response.processed_tokens, Ordering::Relaxed);
+                    metrics::inc_counter(
+                        Subsystem::Embeddings,
+                        "tokens_processed_total",
+                        &[model, &runtime_name, &args.table],
+                        embedding_response.processed_tokens as f64,
+                    );
+                    embedding_response.embeddings
+                };
+
+                if let Some(cache) = cache.as_mut() {
+                    let hashes: Vec<String> = missing_inputs
+                        .iter()
+                        .map(|(idx, _)| input_hashes[*idx].clone())
+                        .collect();
+                    cache.set(model, &hashes, &generated_embeddings)?;
+
+                    if !cached_embeddings.is_empty() {
+                        logger.debug(&format!(
+                            "Found {} embeddings in cache",
+                            cached_embeddings.len()
+                        ));
                     }
                 }
-            }
-
-            if input_vectors.len() == 0 {
-                continue;
-            }
 
-            let embedding_response = runtime.process(&model, &input_vectors);
+                let mut embeddings = if cached_embeddings.is_empty() {
+                    generated_embeddings
+                } else {
+                    // Merge cached and generated embeddings in the input order
+                    generated_embeddings.reverse();
+                    input_hashes
+                        .iter()
+                        .map(|hash| match cached_embeddings.get(hash) {
+                            Some(embedding) => embedding.clone(),
+                            None => generated_embeddings.pop().unwrap(),
+                        })
+                        .collect()
+                };
+
+                // Cache keeps full embeddings, so they are truncated after merging
+                if let Some(dimensions) = args.truncate_dim {
+                    truncate_embeddings(&mut embeddings, dimensions)?;
+                }
 
-            if let Err(e) = embedding_response {
-                anyhow::bail!("{}", e);
-            }
+                count += embeddings.len();
+                metrics::inc_counter(
+                    Subsystem::Embeddings,
+                    "rows_processed_total",
+                    &[model, &runtime_name, &args.table],
+                    embeddings.len() as f64,
+                );
 
-            let embedding_response = embedding_response.unwrap();
+                let mut response_data = Vec::with_capacity(embeddings.len());
 
-            processed_tokens += embedding_response.processed_tokens;
-            let mut embeddings = embedding_response.embeddings;
+                for _ in 0..embeddings.len() {
+                    response_data.push((input_ids.pop().unwrap(), embeddings.pop().unwrap()));
+                }
 
-            count += embeddings.len();
+                let mut response_data = match aggregator {
+                    Some(aggregator) => aggregator.add(response_data),
+                    None => response_data,
+                };
 
-            let duration = start.elapsed().as_secs();
-            // avoid division by zero error
-            let duration = if duration > 0 { duration } else { 1 };
-            logger.debug(&format!(
-                "Generated {} embeddings - speed {} emb/s",
-                count,
-                count / duration as usize
-            ));
+                // Normalized after aggregation, as mean of unit vectors is not unit length
+                if args.normalize {
+                    for (_, embedding) in response_data.iter_mut() {
+                        normalize_vector(embedding);
+                    }
+                }
 
-            let mut response_data = Vec::with_capacity(rows.len());
+                if response_data.is_empty() {
+                    return Ok(true);
+                }
 
-            for _ in 0..embeddings.len() {
-                response_data.push((input_ids.pop().unwrap(), embeddings.pop().unwrap()));
-            }
+                // Error will be returned if exporter worker failed and channel has been closed
+                let row_cnt = response_data.len();
+                stats
+                    .exporter_buffered_rows
+                    .fetch_add(row_cnt, Ordering::Relaxed);
+                if tx.send(response_data).is_err() {
+                    stats
+                        .exporter_buffered_rows
+                        .fetch_sub(row_cnt, Ordering::Relaxed);
+                    return Ok(false);
+                }
+                Ok(true)
+            };
+
+        // Receiver is shared between embedding workers, so the lock is released right after receiving
+        'recv: loop {
+            let rows = rx.lock().unwrap().recv();
+            let rows = match rows {
+                Ok(rows) => rows,
+                Err(_) => break,
+            };
+            stats
+                .producer_buffered_rows
+                .fetch_sub(rows.len(), Ordering::Relaxed);
 
-            if tx.send(response_data).is_err() {
-                // Error occured in exporter worker and channel has been closed
-                break;
+            if is_canceled.is_some() && *is_canceled.as_ref().unwrap().read().unwrap() {
+                // This variable will be changed from outside to gracefully
+                // exit job on next chunk
+                anyhow::bail!(JOB_CANCELLED_MESSAGE);
             }
-        }
-
-        if count > 0 {
-            logger.info("Embedding generation finished, waiting to export results...");
-        } else {
-            logger.warn("No data to generate embeddings");
-        }
-        drop(tx);
-        Ok(processed_tokens)
-    });
-
-    return Ok(handle);
-}
 
-// DB exporter worker will create temp table with name _lantern_tmp_${rand(0,1000)}
-// Then it will create writer stream which will COPY bytes from stdin to that table
-// After that it will receiver the output embeddings mapped with row ids over the channel
-// And write them using writer instance
-// At the end we will flush the writer commit the transaction and UPDATE destination table
-// Using our TEMP table data
-fn db_exporter_worker(
-    args: Arc<cli::EmbeddingArgs>,
-    rx: Receiver<Vec<EmbeddingRecord>>,
-    item_count: i64,
-    progress_cb: Option<ProgressCbFn>,
-    logger: Arc<Logger>,
-) -> Result<JoinHandle<AnyhowUsizeResult>, anyhow::Error> {
-    let handle = std::thread::spawn(move || {
-        let uri = args.out_uri.as_ref().unwrap_or(&args.uri);
-        let column = &args.out_column;
-        let table = args.out_table.as_ref().unwrap_or(&args.table);
-        let schema = &args.schema;
-        let full_table_name = get_full_table_name(schema, table);
-
-        let uri = append_params_to_uri(uri, CONNECTION_PARAMS);
+            let mut input_vectors: Vec<&str> = Vec::with_capacity(rows.len());
+            let mut input_ids: Vec<String> = Vec::with_capacity(rows.len());
 
-        let mut client = Client::connect(&uri, NoTls)?;
-        let mut transaction = client.transaction()?;
-        let mut rng = rand::thread_rng();
-        let temp_table_name = format!("_lantern_tmp_{}", rng.gen_range(0..1000));
-
-        if args.create_column {
-            transaction.execute(
-                &format!(
-                    "ALTER TABLE {full_table_name} ADD COLUMN IF NOT EXISTS {column} REAL[]",
-                    column = quote_ident(column)
-                ),
-                &[],
-            )?;
-        }
-
-        // Try to check if user has write permissions to table
-        let res = transaction.query("SELECT 1 FROM information_schema.column_privileges WHERE table_schema=$1 AND table_name=$2 AND column_name=$3 AND privilege_type='UPDATE' AND grantee=current_user", &[schema, table, column])?;
-
-        if res.get(0).is_none() {
-            anyhow::bail!("User does not have write permissions to target table");
-        }
-
-        transaction
-            .execute(
-                &format!(
-                    "CREATE TEMPORARY TABLE {temp_table_name} AS SELECT ctid::TEXT as id, '{{}}'::REAL[] AS {column} FROM {full_table_name} LIMIT 0",
-                    column=quote_ident(column)
-                ),
-                &[],
-            )?;
-        transaction.commit()?;
-
-        let mut transaction = client.transaction()?;
-        let mut writer = transaction.copy_in(&format!(
-            "COPY {temp_table_name} FROM stdin WITH NULL AS 'NULL'"
-        ))?;
-        let update_sql = &format!("UPDATE {full_table_name} dest SET {column} = src.{column} FROM {temp_table_name} src WHERE src.id::tid = dest.ctid", column=quote_ident(column), temp_table_name=quote_ident(&temp_table_name));
-
-        let flush_interval = 10;
-        let min_flush_rows = 50;
-        let max_flush_rows = 1000;
-        let mut start = Instant::now();
-        let mut collected_row_cnt = 0;
-        let mut processed_row_cnt = 0;
-        let mut old_progress = 0;
-
-        while let Ok(rows) = rx.recv() {
-            for row in &rows {
-                writer.write(row.0.as_bytes())?;
-                writer.write("\t".as_bytes())?;
-                if row.1.len() > 0 {
-                    writer.write("{".as_bytes())?;
-                    let row_str: String = row.1.iter().map(|&x| x.to_string() + ",").collect();
-                    writer.write(row_str[0..row_str.len() - 1].as_bytes())?;
-                    drop(row_str);
-                    writer.write("}".as_bytes())?;
-                } else {
-                    writer.write("NULL".as_bytes())?;
+            for (id, text) in &rows {
+                if text.trim() == "" {
+                    continue;
                 }
-                writer.write("\n".as_bytes())?;
-                collected_row_cnt += 1;
+                input_vectors.push(text.as_str());
+                input_ids.push(id.clone());
             }
 
-            processed_row_cnt += rows.len();
-            let progress = calculate_progress(item_count, processed_row_cnt);
-
-            if progress > old_progress {
-                old_progress = progress;
-                logger.debug(&format!("Progress {progress}%",));
-                if progress_cb.is_some() {
-                    let cb = progress_cb.as_ref().unwrap();
-                    cb(progress);
+            // Array elements of a row are sent together, so they are counted by consecutive ids
+            if let Some(aggregator) = aggregator.as_mut() {
+                let mut idx = 0;
+                while idx < input_ids.len() {
+                    let id = &input_ids[idx];
+                    let element_cnt = input_ids[idx..].iter().take_while(|i| *i == id).count();
+                    aggregator.expect(id, element_cnt);
+                    idx += element_cnt;
                 }
             }
 
-            drop(rows);
-
-            if !args.stream {
+            if input_vectors.len() == 0 {
                 continue;
             }
 
-            if collected_row_cnt >= max_flush_rows
-                || (flush_interval <= start.elapsed().as_secs()
-                    && collected_row_cnt >= min_flush_rows)
+            // Token counts are kept with the batch and reused by the limiter
+            let limits_tokens = limiter
+                .as_ref()
+                .is_some_and(|limiter| limiter.limits_tokens());
+            let token_counts = if batcher.is_token_aware() || limits_tokens {
+                runtime.count_tokens(model, &input_vectors)?
+            } else {
+                vec![0; input_vectors.len()]
+            };
+
+            let mut batches = Vec::new();
+            for ((id, input), tokens) in input_ids.into_iter().zip(input_vectors).zip(token_counts)
             {
-                // if job is run in streaming mode
-                // it will write results to target table each 10 seconds (if collected rows are
-                // more than 50) or if collected row count is more than 1000 rows
-                writer.flush()?;
-                writer.finish()?;
-                transaction.batch_execute(&format!(
-                    "
-                    {update_sql};
-                    TRUNCATE TABLE {temp_table_name};
-                "
-                ))?;
-                transaction.commit()?;
-                transaction = client.transaction()?;
-                writer = transaction.copy_in(&format!("COPY {temp_table_name} FROM stdin"))?;
-                collected_row_cnt = 0;
-                start = Instant::now();
+                if let Some(batch) = batcher.push(id, input.to_owned(), tokens) {
+                    batches.push(batch);
+                }
+            }
+
+            // Without token budget each received chunk is processed as is
+            if !batcher.is_token_aware() {
+                batches.extend(batcher.take());
             }
-        }
 
-        // There might be a case when filter is provided manually
-        // And `{column} IS NOT NULL` will be missing from the table
-        // So we will check if the column is null in rust code before generating embedding
-        // Thus the processed rows may be less than the actual estimated row count
-        // And progress will not be 100
-        if old_progress != 100 {
-            logger.debug("Progress 100%");
-            if progress_cb.is_some() {
-                let cb = progress_cb.as_ref().unwrap();
-                cb(100);
+            for batch in batches {
+                if !process_batch(batch, &mut aggregator)? {
+                    break 'recv;
+                }
             }
         }
 
-        if processed_row_cnt == 0 {
-            return Ok(processed_row_cnt);
+        if let Some(batch) = batcher.take() {
+            process_batch(batch, &mut aggregator)?;
         }
 
-        writer.flush()?;
-        writer.finish()?;
-        transaction.execute(update_sql, &[])?;
-        transaction.commit()?;
-        logger.info(&format!(
-            "Embeddings exported to table {} under column {}",
-            &table, &column
-        ));
-        Ok(processed_row_cnt)
+        if count > 0 {
+            logger.info("Embedding generation finished, waiting to export results...");
+        } else {
+            logger.warn("No data to generate embeddings");
+        }
+        drop(tx);
+        Ok(processed_tokens)
     });
 
     return Ok(handle);
 }
 
-fn csv_exporter_worker(
+// Exporter is selected by the output args
+fn get_exporter(
     args: Arc<cli::EmbeddingArgs>,
-    rx: Receiver<Vec<EmbeddingRecord>>,
+    embedded_at: Option<String>,
+    stats: Arc<PipelineStats>,
     logger: Arc<Logger>,
-) -> Result<JoinHandle<AnyhowUsizeResult>, anyhow::Error> {
-    let handle = std::thread::spawn(move || {
-        let csv_path = args.out_csv.as_ref().unwrap();
-        let mut wtr = Writer::from_path(&csv_path).unwrap();
-        let mut processed_row_cnt = 0;
-        while let Ok(rows) = rx.recv() {
-            for row in &rows {
-                let vector_string = &format!(
-                    "{{{}}}",
-                    row.1
-                        .iter()
-                        .map(|f| f.to_string())
-                        .collect::<Vec<String>>()
-                        .join(",")
-                );
-                wtr.write_record(&[&row.0.to_string(), vector_string])
-                    .unwrap();
-                processed_row_cnt += rows.len();
-            }
-        }
-        wtr.flush().unwrap();
-        logger.info(&format!("Embeddings exported to {}", &csv_path));
-        Ok(processed_row_cnt)
-    });
-
-    return Ok(handle);
+) -> Box<dyn Exporter> {
+    if let Some(out_parquet) = &args.out_parquet {
+        Box::new(ParquetExporter::new(out_parquet, logger))
+    } else if let Some(out_jsonl) = &args.out_jsonl {
+        Box::new(JsonlExporter::new(out_jsonl, logger))
+    } else if args.out_csv.is_some() {
+        Box::new(CsvExporter::new(args, logger))
+    } else if args.has_file_input() || !matches!(args.out_mode, cli::OutMode::Update) {
+        Box::new(TableExporter::new(args, stats, logger))
+    } else if args.writer_jobs > 1 {
+        let exporters = (0..args.writer_jobs)
+            .map(|_| {
+                Box::new(DbExporter::new(
+                    args.clone(),
+                    embedded_at.clone(),
+                    stats.clone(),
+                    logger.clone(),
+                )) as Box<dyn Exporter>
+            })
+            .collect();
+        Box::new(WriterPool::new(exporters))
+    } else {
+        Box::new(DbExporter::new(args, embedded_at, stats, logger))
+    }
 }
 
 pub fn get_default_batch_size(model: &str) -> usize {
@@ -448,67 +674,680 @@ pub fn get_default_batch_size(model: &str) -> usize {
         | "cohere/embed-english-v2.0"
         | "cohere/embed-english-light-v2.0"
         | "cohere/embed-multilingual-v2.0" => 5000,
+        "voyage/voyage-large-2" | "voyage/voyage-code-2" => 1000,
+        "mistral/mistral-embed" => 500,
+        "jina/jina-embeddings-v3" | "nomic/nomic-embed-text-v1.5" => 1000,
         _ => 100,
     }
 }
 
+// Default token budget for one batch sent to the runtime
+// Models not listed here will be batched by row count only
+pub fn get_default_max_tokens_per_batch(model: &str) -> Option<usize> {
+    match model {
+        "clip/ViT-B-32-textual" => Some(64000),
+        "BAAI/bge-small-en"
+        | "jinaai/jina-embeddings-v2-small-en"
+        | "intfloat/e5-base-v2"
+        | "thenlper/gte-base"
+        | "microsoft/all-MiniLM-L12-v2" => Some(64000),
+        "BAAI/bge-base-en"
+        | "jinaai/jina-embeddings-v2-base-en"
+        | "llmrails/ember-v1"
+        | "microsoft/all-mpnet-base-v2"
+        | "transformers/multi-qa-mpnet-base-dot-v1" => Some(32000),
+        "BAAI/bge-large-en" | "intfloat/e5-large-v2" | "thenlper/gte-large" => Some(16000),
+        "openai/text-embedding-ada-002"
+        | "openai/text-embedding-3-small"
+        | "openai/text-embedding-3-large" => Some(250000),
+        "cohere/embed-english-v3.0"
+        | "cohere/embed-multilingual-v3.0"
+        | "cohere/embed-english-light-v3.0"
+        | "cohere/embed-multilingual-light-v3.0"
+        | "cohere/embed-english-v2.0"
+        | "cohere/embed-english-light-v2.0"
+        | "cohere/embed-multilingual-v2.0" => Some(250000),
+        "voyage/voyage-large-2" | "voyage/voyage-code-2" => Some(250000),
+        "mistral/mistral-embed" => Some(250000),
+        "jina/jina-embeddings-v3" | "nomic/nomic-embed-text-v1.5" => Some(250000),
+        _ => None,
+    }
+}
+
+// Token budget is used only for text models
+// If batch size is set explicitly, the model default token budget will not be applied
+fn get_max_tokens_per_batch(args: &cli::EmbeddingArgs) -> Option<usize> {
+    if args.visual {
+        return None;
+    }
+
+    if args.max_tokens_per_batch.is_some() || args.batch_size.is_some() {
+        return args.max_tokens_per_batch;
+    }
+
+    get_default_max_tokens_per_batch(&args.model)
+}
+
+// Progress callback receives only the percent, use create_embeddings_with_progress to get the progress events
 pub fn create_embeddings_from_db(
     args: cli::EmbeddingArgs,
     track_progress: bool,
     progress_cb: Option<ProgressCbFn>,
     is_canceled: Option<Arc<RwLock<bool>>>,
     logger: Option<Logger>,
-) -> Result<(usize, usize), anyhow::Error> {
+) -> Result<JobReport, anyhow::Error> {
+    create_embeddings_with_progress(
+        args,
+        track_progress,
+        progress_cb.map(percent_progress_cb),
+        is_canceled,
+        logger,
+    )
+}
+
+pub fn create_embeddings_with_progress(
+    args: cli::EmbeddingArgs,
+    track_progress: bool,
+    progress_cb: Option<ProgressEventCbFn>,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    logger: Option<Logger>,
+) -> Result<JobReport, anyhow::Error> {
+    EmbeddingPipeline {
+        args,
+        track_progress,
+        progress_cb,
+        is_canceled,
+        logger,
+        exporter: None,
+        producer: None,
+        hooks: Vec::new(),
+    }
+    .run()
+}
+
+// Builder for running the embedding pipeline from other Rust services
+// When a custom exporter or batch hooks are registered the embeddings are passed to them
+// instead of being written to the database or CSV file, and rows are identified by the --pk column
+pub struct EmbeddingPipeline {
+    args: cli::EmbeddingArgs,
+    track_progress: bool,
+    progress_cb: Option<ProgressEventCbFn>,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    logger: Option<Logger>,
+    exporter: Option<Box<dyn Exporter>>,
+    producer: Option<Box<dyn Producer>>,
+    hooks: Vec<BatchHookFn>,
+}
+
+impl EmbeddingPipeline {
+    pub fn new(args: cli::EmbeddingArgs) -> Self {
+        EmbeddingPipeline {
+            args,
+            track_progress: false,
+            progress_cb: None,
+            is_canceled: None,
+            logger: None,
+            exporter: None,
+            producer: None,
+            hooks: Vec::new(),
+        }
+    }
+
+    pub fn track_progress(mut self, track_progress: bool) -> Self {
+        self.track_progress = track_progress;
+        self
+    }
+
+    pub fn on_progress(mut self, progress_cb: ProgressEventCbFn) -> Self {
+        self.progress_cb = Some(progress_cb);
+        self
+    }
+
+    pub fn cancel_flag(mut self, is_canceled: Arc<RwLock<bool>>) -> Self {
+        self.is_canceled = Some(is_canceled);
+        self
+    }
+
+    pub fn logger(mut self, logger: Logger) -> Self {
+        self.logger = Some(logger);
+        self
+    }
+
+    pub fn exporter(mut self, exporter: impl Exporter + 'static) -> Self {
+        self.exporter = Some(Box::new(exporter));
+        self
+    }
+
+    // Texts are read from the producer instead of the source table
+    pub fn producer(mut self, producer: impl Producer + 'static) -> Self {
+        self.producer = Some(Box::new(producer));
+        self
+    }
+
+    // Hooks are called from the exporter thread in the order they are registered
+    // An error returned from a hook stops the pipeline
+    pub fn on_batch_embedded(
+        mut self,
+        hook: impl FnMut(Vec<EmbeddingRecord>) -> AnyhowVoidResult + Send + 'static,
+    ) -> Self {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+
+    // Returns processed rows and tokens with the batch latencies of the job
+    pub fn run(self) -> Result<JobReport, anyhow::Error> {
+        let logger = self
+            .logger
+            .unwrap_or(Logger::new("Lantern Embeddings", LogLevel::Debug));
+        let exporter: Option<Box<dyn Exporter>> = match (self.exporter, self.hooks.is_empty()) {
+            (Some(_), false) => anyhow::bail!("Batch hooks can not be used with a custom exporter"),
+            (Some(exporter), true) => Some(exporter),
+            (None, false) => Some(Box::new(HookExporter::new(self.hooks))),
+            (None, true) => None,
+        };
+        let lineage_args = self.args.clone();
+        lineage::run_with_lineage(&lineage_args, &logger, || {
+            run_embedding_pipeline(
+                self.args,
+                self.track_progress,
+                self.progress_cb,
+                self.is_canceled,
+                None,
+                Some(logger.clone()),
+                exporter,
+                self.producer,
+            )
+        })
+    }
+}
+
+// Runtime requests of all embedding workers are throttled with the limiter
+// Fan-out jobs share one limiter between the pipelines of all databases, otherwise it is created from args
+fn run_embedding_pipeline(
+    args: cli::EmbeddingArgs,
+    track_progress: bool,
+    progress_cb: Option<ProgressEventCbFn>,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    limiter: Option<Arc<RequestLimiter>>,
+    logger: Option<Logger>,
+    exporter: Option<Box<dyn Exporter>>,
+    producer: Option<Box<dyn Producer>>,
+) -> Result<JobReport, anyhow::Error> {
+    let job_start = Instant::now();
     let logger = Arc::new(logger.unwrap_or(Logger::new("Lantern Embeddings", LogLevel::Debug)));
+    let args = if exporter.is_none() {
+        args.with_stdout_default()
+    } else {
+        args
+    };
+    // Worker spans are children of the job span, so a trace shows the whole job
+    let job_span = tracing::info_span!(
+        "embedding_job",
+        table = %args.table,
+        column = %args.column,
+        model = %args.model,
+        runtime = %args.runtime.to_string()
+    );
+    let _job_span = job_span.enter();
     logger.info("Lantern CLI - Create Embeddings");
+    if let Some(port) = args.metrics_port {
+        metrics::start_metrics_server(port, logger.clone())?;
+    }
+    check_required_region(&args)?;
+    check_jsonpath_args(&args)?;
+    let limiter = match limiter {
+        Some(limiter) => Some(limiter),
+        None => RequestLimiter::from_args(&args)?,
+    };
+
+    if args.read_replica {
+        if args.out_uri.is_none() || args.out_uri.as_ref() == Some(&args.uri) {
+            anyhow::bail!("--read-replica requires --out-uri of the primary database");
+        }
+        if args.tenant_checkpoint.is_some() {
+            anyhow::bail!("--read-replica can not be used with --tenant-checkpoint");
+        }
+    }
+
+    if exporter.is_some()
+        && (args.chunks_table.is_some()
+            || args.has_file_output()
+            || args.emit_migration.is_some()
+            || args.iterate_by.is_some()
+            || args.iterate_schemas.is_some())
+    {
+        anyhow::bail!("Custom exporters can not be used with --chunks-table, --out-csv, --out-parquet, --out-jsonl, --emit-migration, --iterate-by or --iterate-schemas");
+    }
+
+    if args.sample == Some(0) {
+        anyhow::bail!("--sample should be greater than 0");
+    }
+
+    if let Some(percent) = args.sample_percent {
+        if !(percent > 0.0 && percent <= 100.0) {
+            anyhow::bail!("--sample-percent should be greater than 0 and at most 100");
+        }
+    }
+
+    if args.sample.is_some() && (args.sample_percent.is_some() || args.limit.is_some()) {
+        anyhow::bail!("--sample can not be used with --sample-percent or --limit");
+    }
+
+    if args.sample.is_some() && args.producer_scans > 1 {
+        anyhow::bail!("--producer-scans can not be used with --sample");
+    }
+
+    if (args.sample.is_some() || args.sample_percent.is_some())
+        && (args.paginate_by.is_some() || args.chunks_table.is_some())
+    {
+        anyhow::bail!(
+            "--sample and --sample-percent can not be used with --paginate-by or --chunks-table"
+        );
+    }
+
+    // Progress events are sent to the output database
+    if args.notify_progress && args.out_uri.as_ref().unwrap_or(&args.uri).is_empty() {
+        anyhow::bail!("--notify-progress requires --uri or --out-uri");
+    }
+
+    // Local datasets are read with the file producers instead of the source table
+    let producer = match producer {
+        Some(producer) => Some(producer),
+        None => producer::get_file_producer(&args)?,
+    };
+
+    // Ids of custom producers can not be matched to table rows by ctid
+    // Records of file producers are inserted into the table instead
+    if producer.is_some() {
+        if exporter.is_none() && !args.has_file_output() && !args.has_file_input() {
+            anyhow::bail!(
+                "Custom producers can be used only with --out-csv, --out-parquet, --out-jsonl or a custom exporter"
+            );
+        }
+        let out_table = args.out_table.as_ref().unwrap_or(&args.table);
+        if exporter.is_none()
+            && !args.has_file_output()
+            && (out_table.is_empty() || args.uri.is_empty())
+        {
+            anyhow::bail!("--uri and --table are required to insert embeddings of --in-csv, --in-parquet or --stdin into database");
+        }
+        if args.chunks_table.is_some()
+            || args.array_mode.is_some()
+            || args.emit_migration.is_some()
+            || args.iterate_by.is_some()
+            || args.iterate_schemas.is_some()
+            || args.dry_run
+            || args.dry_run_cost
+            || args.only_missing
+            || args.stale_check.is_some()
+            || args.producer_scans > 1
+            || args.sample.is_some()
+            || args.sample_percent.is_some()
+        {
+            anyhow::bail!("Custom producers can not be used with --chunks-table, --array-mode, --emit-migration, --iterate-by, --iterate-schemas, --dry-run, --dry-run-cost, --only-missing, --stale-check, --producer-scans, --sample or --sample-percent");
+        }
+    }
+
+    if args.iterate_by.is_some() || args.iterate_schemas.is_some() {
+        return tenants::create_embeddings_per_tenant(
+            args,
+            track_progress,
+            progress_cb,
+            is_canceled,
+            logger,
+        );
+    }
+
+    // Tokens are only counted, nothing is written and the embedding API is not called
+    if args.dry_run_cost {
+        let batch_size = args
+            .batch_size
+            .unwrap_or(get_default_batch_size(&args.model));
+        let report = cost::estimate_cost(&args, batch_size, &logger)?;
+        return Ok(JobReport::new(0, report.tokens));
+    }
+
+    // The sample batch is embedded, but the results are not written
+    if args.dry_run {
+        let batch_size = args
+            .batch_size
+            .unwrap_or(get_default_batch_size(&args.model));
+        dry_run::dry_run(&args, batch_size, &logger)?;
+        return Ok(JobReport::default());
+    }
+
+    // Schema changes are written to migration file instead of being executed
+    // The job will write the data only after the migration is applied
+    if let Some(migration_path) = &args.emit_migration {
+        let statements = migration::get_schema_changes(&args)?;
+        if !statements.is_empty() {
+            migration::write_migration(migration_path, &statements)?;
+            logger.info(&format!(
+                "Schema changes written to {migration_path}. Apply the migration and run the job again"
+            ));
+            return Ok(JobReport::default());
+        }
+    }
+
+    // Jobs writing to the same column would interleave the updates, so they are run one at a time
+    // The lock is held until the job returns
+    let _job_lock = if exporter.is_none() && !args.has_file_output() && !args.skip_lock {
+        Some(lock::JobLock::acquire(&args, &is_canceled, &logger)?)
+    } else {
+        None
+    };
+
+    let is_child_table = matches!(args.array_mode, Some(cli::ArrayMode::ChildTable));
+    if args.array_mode.is_some() && args.jsonpath.is_some() {
+        anyhow::bail!("--array-mode can not be used with --jsonpath");
+    }
+    if is_child_table && args.chunks_table.is_none() {
+        anyhow::bail!("--chunks-table is required for --array-mode child-table");
+    }
+
+    // In chunking mode the source text is split into chunks table first
+    // And then embeddings are generated for the chunks table rows
+    // In array child table mode each array element is written as a chunk
+    let args = if args.chunks_table.is_some() {
+        if args.chunk_size.is_none() && !is_child_table {
+            anyhow::bail!("--chunk-size is required when --chunks-table is specified");
+        }
+        if args.has_file_output() || args.out_uri.is_some() {
+            anyhow::bail!(
+                "Chunks table can not be used with --out-csv, --out-parquet, --out-jsonl or --out-uri"
+            );
+        }
+
+        chunking::create_chunks(&args, &logger)?;
+        let chunks_table = args.chunks_table.clone().unwrap();
+        cli::EmbeddingArgs {
+            table: chunks_table,
+            column: "chunk_text".to_owned(),
+            out_table: None,
+            out_schema: None,
+            out_column: "embedding".to_owned(),
+            filter: Some("chunk_text IS NOT NULL AND embedding IS NULL".to_owned()),
+            limit: None,
+            create_column: false,
+            only_missing: false,
+            stale_check: None,
+            jsonpath: None,
+            jsonpath_text_column: None,
+            array_mode: None,
+            ..args
+        }
+    } else {
+        if args.chunk_size.is_some() {
+            anyhow::bail!("--chunks-table is required when --chunk-size is specified");
+        }
+        args
+    };
+
+    let args = if exporter.is_none() {
+        check_output_table(args, &logger)?
+    } else {
+        args
+    };
+
+    if exporter.is_none() && !args.has_file_output() {
+        dimensions::check_output_dimensions(&args, &logger)?;
+    }
+
     let args = Arc::new(args);
     let batch_size = args
         .batch_size
         .unwrap_or(get_default_batch_size(&args.model));
 
     logger.debug(&format!(
-        "Model - {}, Visual - {}, Batch Size - {}",
-        args.model, args.visual, batch_size
+        "Model - {}, Visual - {}, Batch Size - {}, Max Tokens Per Batch - {}",
+        args.model,
+        args.visual,
+        batch_size,
+        get_max_tokens_per_batch(&args)
+            .map(|tokens| tokens.to_string())
+            .unwrap_or("-".to_owned())
     ));
 
-    // Create channel that will send the database rows to embedding worker
-    let (producer_tx, producer_rx): (Sender<Vec<Row>>, Receiver<Vec<Row>>) = mpsc::channel();
+    if args.only_missing || args.stale_check.is_some() {
+        if args.has_file_output() || !args.writes_to_source_table() {
+            anyhow::bail!(
+                "Incremental mode can be used only when embeddings are written to the source table"
+            );
+        }
+    }
+
+    // Rows changed after this moment will be considered stale on the next run
+    let embedded_at = if args.stale_check.is_some() {
+        let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+        let mut client = Client::connect(&uri, NoTls)?;
+        Some(
+            client
+                .query_one("SELECT now()::text", &[])?
+                .get::<usize, String>(0),
+        )
+    } else {
+        None
+    };
+
+    if args.commit_every_rows == Some(0) {
+        anyhow::bail!("--commit-every-rows should be greater than 0");
+    }
+
+    if args.embedding_workers == 0 {
+        anyhow::bail!("Embedding workers count should be greater than 0");
+    }
+
+    if args.producer_scans == 0 {
+        anyhow::bail!("Producer scans count should be greater than 0");
+    }
+
+    if args.producer_scans > 1 && args.limit.is_some() {
+        anyhow::bail!("--producer-scans can not be used with --limit");
+    }
+
+    if args.paginate_by.is_some()
+        && (args.producer_scans > 1
+            || args.consistent_snapshot
+            || args.chunks_table.is_some()
+            || args.array_mode.is_some())
+    {
+        anyhow::bail!("--paginate-by can not be used with --producer-scans, --consistent-snapshot, --chunks-table or --array-mode");
+    }
+
+    if args.writer_jobs == 0 {
+        anyhow::bail!("Writer jobs count should be greater than 0");
+    }
+
+    if args.writer_jobs > 1
+        && (exporter.is_some() || args.has_file_output() || args.has_file_input())
+    {
+        anyhow::bail!(
+            "--writer-jobs can be used only when embeddings are written to the source table rows"
+        );
+    }
+
+    if !matches!(args.out_mode, cli::OutMode::Update) {
+        if exporter.is_some() || args.has_file_output() {
+            anyhow::bail!("--out-mode insert and upsert can be used only when embeddings are written to database");
+        }
+        if !args.has_file_input() && args.writes_to_source_table() {
+            anyhow::bail!("--out-mode insert and upsert require --out-table or --out-uri different from the source table");
+        }
+        if args.chunks_table.is_some()
+            || args.array_mode.is_some()
+            || args.only_missing
+            || args.stale_check.is_some()
+            || args.jsonpath_text_column.is_some()
+            || args.writer_jobs > 1
+        {
+            anyhow::bail!("--out-mode insert and upsert can not be used with --chunks-table, --array-mode, --only-missing, --stale-check, --jsonpath-text-column or --writer-jobs");
+        }
+    }
+
+    let index_spec = match &args.create_index {
+        Some(spec) => {
+            if exporter.is_some() || args.has_file_output() {
+                anyhow::bail!(
+                    "--create-index can be used only when embeddings are written to database"
+                );
+            }
+            let index_spec = index::IndexSpec::parse(spec)?;
+            index_spec.get_method(&args)?;
+            Some(index_spec)
+        }
+        None => None,
+    };
+
+    if args.update_batch_size == Some(0) {
+        anyhow::bail!("Update batch size should be greater than 0");
+    }
+
+    if args.min_flush_rows > args.max_flush_rows {
+        anyhow::bail!("--min-flush-rows can not be greater than --max-flush-rows");
+    }
+
+    if !matches!(args.precision, cli::Precision::F32) && args.chunks_table.is_some() {
+        anyhow::bail!("--precision f16 and int8 can not be used with --chunks-table");
+    }
+
+    if args.quantize.is_some() {
+        if args.chunks_table.is_some() {
+            anyhow::bail!("--quantize can not be used with --chunks-table");
+        }
+        if matches!(args.precision, cli::Precision::Int8) {
+            anyhow::bail!("--quantize can not be used with --precision int8");
+        }
+        if !matches!(args.precision, cli::Precision::F32) && args.quantize_column.is_none() {
+            anyhow::bail!(
+                "--precision can be used with --quantize only when --quantize-column is specified"
+            );
+        }
+        if args.quantize_column.as_ref() == Some(&args.out_column) {
+            anyhow::bail!("--quantize-column should be different from --out-column");
+        }
+    } else if args.quantize_column.is_some() {
+        anyhow::bail!("--quantize is required when --quantize-column is specified");
+    }
+
+    if matches!(args.precision, cli::Precision::Int8) {
+        if matches!(args.column_type, cli::ColumnType::Vector) {
+            anyhow::bail!("--precision int8 can only be used with --column-type real");
+        }
+        if args.int8_train_rows == 0 {
+            anyhow::bail!("--int8-train-rows should be greater than 0");
+        }
+    }
+
+    if (args.out_parquet.is_some() || args.out_jsonl.is_some())
+        && (!matches!(args.precision, cli::Precision::F32) || args.quantize.is_some())
+    {
+        anyhow::bail!("--out-parquet and --out-jsonl can be used only with --precision f32 and without --quantize");
+    }
+
+    if args.csv_workers == 0 {
+        anyhow::bail!("CSV workers count should be greater than 0");
+    }
+
+    if args.truncate_dim == Some(0) {
+        anyhow::bail!("--truncate-dim should be greater than 0");
+    }
+
+    // Validate core lists before starting the workers
+    for cores in [
+        &args.producer_cores,
+        &args.embedding_cores,
+        &args.exporter_cores,
+    ]
+    .into_iter()
+    .flatten()
+    {
+        parse_core_list(cores)?;
+    }
+
+    // Create channel that will send the source records to embedding worker
+    let (producer_tx, producer_rx): (Sender<Vec<SourceRecord>>, Receiver<Vec<SourceRecord>>) =
+        mpsc::channel();
     let (embedding_tx, embedding_rx): (
         Sender<Vec<EmbeddingRecord>>,
         Receiver<Vec<EmbeddingRecord>>,
     ) = mpsc::channel();
 
+    // Workers update the shared stats to report them in progress events
+    let stats = Arc::new(PipelineStats::default());
+
+    // Custom exporters and other tables get the primary key, as ctid is only meaningful for writing back to the same table
+    let id_sql = if exporter.is_none()
+        && matches!(args.out_mode, cli::OutMode::Update)
+        && args.matches_rows_by_ctid()
+    {
+        "ctid::text".to_owned()
+    } else {
+        format!("{}::text", quote_ident(&args.pk))
+    };
+    if args.read_replica && producer.is_none() {
+        replica::wait_for_replica(&args, &logger)?;
+    }
+    let producer = producer.unwrap_or_else(|| {
+        Box::new(PostgresProducer::new(
+            args.clone(),
+            track_progress,
+            id_sql,
+            logger.clone(),
+        ))
+    });
     let (producer_handle, item_cnt) = producer_worker(
         args.clone(),
+        producer,
         batch_size,
         producer_tx,
-        track_progress,
-        logger.clone(),
+        stats.clone(),
     )?;
 
-    // Create exporter based on provided args
-    // For now we only have csv and db exporters
-    let exporter_handle = if args.out_csv.is_some() {
-        csv_exporter_worker(args.clone(), embedding_rx, logger.clone())?
-    } else {
-        db_exporter_worker(
-            args.clone(),
-            embedding_rx,
-            item_cnt,
-            progress_cb,
-            logger.clone(),
-        )?
+    // Embeddings are quantized before export, so exporters only format the values
+    let (quantizer_handle, embedding_rx) = match args.precision {
+        cli::Precision::Int8 => {
+            let (handle, rx) =
+                int8::int8_quantizer_worker(args.clone(), embedding_rx, logger.clone())?;
+            (Some(handle), rx)
+        }
+        _ => (None, embedding_rx),
     };
 
-    let embedding_handle = embedding_worker(
+    // Create exporter based on provided args
+    // Custom exporter passed with EmbeddingPipeline takes precedence
+    let exporter = exporter
+        .unwrap_or_else(|| get_exporter(args.clone(), embedded_at, stats.clone(), logger.clone()));
+    let exporter_handle = exporter::exporter_worker(
         args.clone(),
-        producer_rx,
-        embedding_tx,
-        is_canceled,
+        embedding_rx,
+        item_cnt,
+        exporter,
+        progress_cb,
+        is_canceled.clone(),
+        stats.clone(),
         logger.clone(),
     )?;
+
+    let producer_rx = Arc::new(Mutex::new(producer_rx));
+    let mut embedding_handles = Vec::with_capacity(args.embedding_workers);
+    for _ in 0..args.embedding_workers {
+        embedding_handles.push(embedding_worker(
+            args.clone(),
+            producer_rx.clone(),
+            embedding_tx.clone(),
+            is_canceled.clone(),
+            limiter.clone(),
+            stats.clone(),
+            logger.clone(),
+        )?);
+    }
+    // Exporter will stop when all embedding workers drop their senders
+    drop(embedding_tx);
+    // Producer will stop when all embedding workers exit, e.g. when the job is cancelled
+    drop(producer_rx);
     // Collect the thread handles in a vector to wait them
-    let handles = vec![producer_handle];
+    let mut handles = vec![producer_handle];
+    handles.extend(quantizer_handle);
 
     for handle in handles {
         match handle.join() {
@@ -525,23 +1364,113 @@ pub fn create_embeddings_from_db(
         }
     }
 
-    let processed_tokens = match embedding_handle.join() {
-        Err(e) => {
-            logger.error(&format!("{:?}", e));
-            anyhow::bail!("{:?}", e);
-        }
-        Ok(res) => res?,
-    };
+    // Workers of a cancelled job stop after their current batch, and the exporter is still
+    // joined, so in streaming mode embeddings which were already generated are committed before returning
+    let mut processed_tokens = 0;
+    let mut job_cancelled = false;
+    for embedding_handle in embedding_handles {
+        processed_tokens += match embedding_handle.join() {
+            Err(e) => {
+                logger.error(&format!("{:?}", e));
+                anyhow::bail!("{:?}", e);
+            }
+            Ok(Err(e)) if e.to_string() == JOB_CANCELLED_MESSAGE => {
+                job_cancelled = true;
+                0
+            }
+            Ok(res) => res?,
+        };
+    }
 
     let processed_rows = match exporter_handle.join() {
         Err(e) => {
             logger.error(&format!("{:?}", e));
             anyhow::bail!("{:?}", e);
         }
+        Ok(Err(e)) if e.to_string() == JOB_CANCELLED_MESSAGE => {
+            logger.warn("Job cancelled, generated embeddings were not committed");
+            anyhow::bail!(JOB_CANCELLED_MESSAGE);
+        }
         Ok(res) => res?,
     };
 
-    Ok((processed_rows, processed_tokens))
+    if job_cancelled {
+        logger.warn(&format!(
+            "Job cancelled, embeddings of {processed_rows} rows were written before stopping"
+        ));
+        anyhow::bail!(JOB_CANCELLED_MESSAGE);
+    }
+
+    let is_canceled = is_canceled.is_some_and(|is_canceled| *is_canceled.read().unwrap());
+    // With --emit-migration the index is created by the migration
+    let index_in_migration = args.emit_migration.is_some() && args.chunks_table.is_none();
+    if let (Some(index_spec), false) = (&index_spec, is_canceled || index_in_migration) {
+        index::create_index(&args, index_spec, &logger)?;
+    }
+
+    if cost::is_billed_runtime(&args.runtime) {
+        let report = cost::CostReport::new(
+            &args,
+            processed_rows,
+            processed_tokens,
+            stats.requests.load(Ordering::Relaxed),
+        );
+        logger.info(&report.to_string());
+    }
+
+    Ok(JobReport {
+        processed_rows,
+        processed_tokens,
+        wall_time: job_start.elapsed(),
+        latencies: stats.latencies.take(),
+    })
+}
+
+#[derive(Serialize)]
+pub struct ModelListing {
+    #[serde(flatten)]
+    pub info: ModelInfo,
+    pub default_batch_size: usize,
+    pub requires_api_key: bool,
+}
+
+#[derive(Serialize)]
+pub struct RuntimeListing {
+    pub name: String,
+    pub requires_api_key: bool,
+}
+
+pub fn get_model_listings(
+    runtime: &Runtime,
+    runtime_params: &str,
+) -> Result<Vec<ModelListing>, anyhow::Error> {
+    let embedding_runtime = get_runtime(runtime, None, runtime_params)?;
+    Ok(embedding_runtime
+        .get_models_info()
+        .into_iter()
+        .map(|info| ModelListing {
+            default_batch_size: get_default_batch_size(&info.name),
+            requires_api_key: runtime.requires_api_key(),
+            info,
+        })
+        .collect())
+}
+
+pub fn get_runtime_listings() -> Vec<RuntimeListing> {
+    Runtime::iter()
+        .map(|runtime| RuntimeListing {
+            name: runtime.to_string(),
+            requires_api_key: runtime.requires_api_key(),
+        })
+        .collect()
+}
+
+// JSON listings are printed without log prefixes, so they can be parsed by scripts
+pub(crate) fn print_json<T: Serialize>(logger: &Logger, value: &T) -> AnyhowVoidResult {
+    let mut json = serde_json::to_string_pretty(value)?;
+    json.push('\n');
+    logger.print_raw(&json);
+    Ok(())
 }
 
 pub fn show_available_models(
@@ -549,14 +1478,26 @@ pub fn show_available_models(
     logger: Option<Logger>,
 ) -> AnyhowVoidResult {
     let logger = logger.unwrap_or(Logger::new("Lantern Embeddings", LogLevel::Info));
-    logger.info("Available Models\n");
+    if args.format == cli::ListFormat::Json {
+        return print_json(
+            &logger,
+            &get_model_listings(&args.runtime, &args.runtime_params)?,
+        );
+    }
     let runtime = get_runtime(&args.runtime, None, &args.runtime_params)?;
+    logger.info("Available Models\n");
     logger.print_raw(&runtime.get_available_models().0);
     Ok(())
 }
 
-pub fn show_available_runtimes(logger: Option<Logger>) -> AnyhowVoidResult {
+pub fn show_available_runtimes(
+    args: &cli::ShowRuntimesArgs,
+    logger: Option<Logger>,
+) -> AnyhowVoidResult {
     let logger = logger.unwrap_or(Logger::new("Lantern Embeddings", LogLevel::Info));
+    if args.format == cli::ListFormat::Json {
+        return print_json(&logger, &get_runtime_listings());
+    }
     let mut runtimes_str = get_available_runtimes().join("\n");
     runtimes_str.push_str("\n");
     logger.info("Available Runtimes\n");
@@ -0,0 +1,208 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::cli::{ListModelsArgs, PullModelArgs, RemoveModelArgs};
+use super::core::ort_runtime::OrtRuntime;
+use super::core::utils::{get_checksum_path, sha256_file, PARTIAL_FILE_SUFFIX};
+use super::core::{default_logger, LoggerFn};
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+
+// Files written by the local runtime, a folder with any of them is listed as a model
+static MODEL_FILES: [&str; 3] = ["model.onnx", "model_quantized.onnx", "model.onnx_data"];
+static HF_FOLDER: &str = "hf";
+
+pub struct LocalModel {
+    pub name: String,
+    pub path: PathBuf,
+    pub size: u64,
+    pub partial_files: Vec<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub enum ChecksumStatus {
+    Ok,
+    Mismatch(Vec<String>),
+    // None of the files has a stored checksum
+    Unknown,
+}
+
+fn get_folder_size(path: &Path) -> Result<u64, anyhow::Error> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        size += if meta.is_dir() {
+            get_folder_size(&entry.path())?
+        } else {
+            meta.len()
+        };
+    }
+    Ok(size)
+}
+
+fn is_model_folder(path: &Path) -> Result<bool, anyhow::Error> {
+    for entry in fs::read_dir(path)? {
+        let file_name = entry?.file_name();
+        let file_name = file_name.to_string_lossy();
+        let file_name = file_name
+            .strip_suffix(PARTIAL_FILE_SUFFIX)
+            .unwrap_or(&file_name);
+        if MODEL_FILES.contains(&file_name) {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+// Hugging Face models are stored under hf/org/model/revision
+fn get_model_name(data_path: &Path, path: &Path) -> String {
+    let parts: Vec<String> = path
+        .strip_prefix(data_path)
+        .unwrap_or(path)
+        .components()
+        .map(|c| c.as_os_str().to_string_lossy().to_string())
+        .collect();
+
+    match parts.as_slice() {
+        [hf, org, model, revision] if hf == HF_FOLDER => format!("hf:{org}/{model}@{revision}"),
+        _ => parts.join("/"),
+    }
+}
+
+fn collect_model_folders(path: &Path, folders: &mut Vec<PathBuf>) -> Result<(), anyhow::Error> {
+    if is_model_folder(path)? {
+        folders.push(path.to_owned());
+        return Ok(());
+    }
+
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            collect_model_folders(&entry.path(), folders)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn get_local_models(data_path: &str) -> Result<Vec<LocalModel>, anyhow::Error> {
+    let data_path = Path::new(data_path);
+    let mut folders = Vec::new();
+    if data_path.exists() {
+        collect_model_folders(data_path, &mut folders)?;
+    }
+
+    let mut models = Vec::with_capacity(folders.len());
+    for path in folders {
+        let mut partial_files = Vec::new();
+        for entry in fs::read_dir(&path)? {
+            let file_name = entry?.file_name().to_string_lossy().to_string();
+            if file_name.ends_with(PARTIAL_FILE_SUFFIX) {
+                partial_files.push(file_name);
+            }
+        }
+        partial_files.sort();
+
+        models.push(LocalModel {
+            name: get_model_name(data_path, &path),
+            size: get_folder_size(&path)?,
+            path,
+            partial_files,
+        });
+    }
+    models.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(models)
+}
+
+// Compares checksums of downloaded files with the ones stored on download
+pub fn verify_local_model(model: &LocalModel) -> Result<ChecksumStatus, anyhow::Error> {
+    let mut checked = 0;
+    let mut mismatched = Vec::new();
+
+    for entry in fs::read_dir(&model.path)? {
+        let path = entry?.path();
+        let checksum_path = get_checksum_path(&path);
+        if !path.is_file() || !checksum_path.exists() {
+            continue;
+        }
+
+        checked += 1;
+        if sha256_file(&path)? != fs::read_to_string(&checksum_path)?.trim() {
+            mismatched.push(path.file_name().unwrap().to_string_lossy().to_string());
+        }
+    }
+    mismatched.sort();
+
+    Ok(match (checked, mismatched.is_empty()) {
+        (0, _) => ChecksumStatus::Unknown,
+        (_, true) => ChecksumStatus::Ok,
+        (_, false) => ChecksumStatus::Mismatch(mismatched),
+    })
+}
+
+pub fn pull_model(args: &PullModelArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Models", LogLevel::Info));
+    let runtime = OrtRuntime::new(&(default_logger as LoggerFn), &args.runtime_params)?;
+    let path = runtime.pull_model(&args.model)?;
+    logger.info(&format!(
+        "Model {} is downloaded to {}",
+        args.model,
+        path.display()
+    ));
+    Ok(())
+}
+
+pub fn list_models(args: &ListModelsArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Models", LogLevel::Info));
+    let runtime = OrtRuntime::new(&(default_logger as LoggerFn), &args.runtime_params)?;
+    let models = get_local_models(runtime.get_data_path())?;
+
+    logger.info(&format!(
+        "Downloaded Models ({})\n",
+        runtime.get_data_path()
+    ));
+    let mut corrupted_models = 0;
+    let mut output = String::new();
+    for model in &models {
+        let mut status = if model.partial_files.is_empty() {
+            "complete".to_owned()
+        } else {
+            format!("partial download: {}", model.partial_files.join(", "))
+        };
+
+        if args.verify {
+            match verify_local_model(model)? {
+                ChecksumStatus::Ok => status.push_str(", checksum ok"),
+                ChecksumStatus::Unknown => status.push_str(", checksum unknown"),
+                ChecksumStatus::Mismatch(files) => {
+                    corrupted_models += 1;
+                    status.push_str(&format!(", checksum mismatch: {}", files.join(", ")));
+                }
+            }
+        }
+
+        output.push_str(&format!(
+            "{}\t{:.1} MB\t{status}\n",
+            model.name,
+            model.size as f64 / 1024.0 / 1024.0
+        ));
+    }
+    logger.print_raw(&output);
+
+    if corrupted_models > 0 {
+        anyhow::bail!("{corrupted_models} models have files with checksum mismatch, remove and pull them again");
+    }
+    Ok(())
+}
+
+pub fn remove_model(args: &RemoveModelArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Models", LogLevel::Info));
+    let runtime = OrtRuntime::new(&(default_logger as LoggerFn), &args.runtime_params)?;
+    let path = runtime.remove_model(&args.model)?;
+    logger.info(&format!(
+        "Model {} is removed from {}",
+        args.model,
+        path.display()
+    ));
+    Ok(())
+}
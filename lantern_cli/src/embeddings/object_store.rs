@@ -0,0 +1,585 @@
+use super::core::bedrock_runtime::{self, sha256_hex, uri_encode, AwsCredentials};
+use crate::types::*;
+use chrono::Utc;
+use gcp_auth::AuthenticationManager;
+use isahc::{prelude::*, Body, Request, Response};
+use itertools::Itertools;
+use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use tokio::runtime::Runtime;
+use url::Url;
+
+// Objects are uploaded in parts of this size, so exports are not buffered on local disk
+// S3 requires parts of at least 5MB and GCS requires multiples of 256KB
+static PART_SIZE: usize = 8 * 1024 * 1024;
+static GCS_SCOPES: [&str; 1] = ["https://www.googleapis.com/auth/devstorage.read_write"];
+static GCS_BASE_URL: &str = "https://storage.googleapis.com";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ObjectUri {
+    S3 { bucket: String, key: String },
+    Gcs { bucket: String, key: String },
+}
+
+impl ObjectUri {
+    // Returns None for local paths
+    pub fn parse(path: &str) -> Result<Option<ObjectUri>, anyhow::Error> {
+        let Some((scheme, rest)) = path.split_once("://") else {
+            return Ok(None);
+        };
+        let (bucket, key) = match rest.split_once('/') {
+            Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => {
+                (bucket.to_owned(), key.to_owned())
+            }
+            _ => anyhow::bail!("Object URI should be in {scheme}://bucket/key format: {path}"),
+        };
+
+        match scheme {
+            "s3" => Ok(Some(ObjectUri::S3 { bucket, key })),
+            "gs" => Ok(Some(ObjectUri::Gcs { bucket, key })),
+            _ => anyhow::bail!(
+                "Unsupported object storage scheme '{scheme}'. Supported schemes: s3, gs"
+            ),
+        }
+    }
+}
+
+pub fn is_object_uri(path: &str) -> bool {
+    path.starts_with("s3://") || path.starts_with("gs://")
+}
+
+fn check_status(response: &mut Response<Body>, description: &str) -> AnyhowVoidResult {
+    let status = response.status();
+    if !status.is_success() {
+        anyhow::bail!(
+            "{description} failed with status {status}: {}",
+            response.text().unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+// Credentials are taken from AWS_ACCESS_KEY_ID/AWS_SECRET_ACCESS_KEY or the shared credentials file
+// AWS_ENDPOINT_URL_S3 (or AWS_ENDPOINT_URL) can point to S3 compatible storage like MinIO
+struct S3Client {
+    credentials: AwsCredentials,
+    region: String,
+    endpoint: Option<String>,
+    bucket: String,
+    key: String,
+}
+
+impl S3Client {
+    fn new(bucket: &str, key: &str) -> Result<Self, anyhow::Error> {
+        Ok(S3Client {
+            credentials: bedrock_runtime::get_default_credentials()?,
+            region: std::env::var("AWS_REGION")
+                .or(std::env::var("AWS_DEFAULT_REGION"))
+                .unwrap_or("us-east-1".to_owned()),
+            endpoint: std::env::var("AWS_ENDPOINT_URL_S3")
+                .or(std::env::var("AWS_ENDPOINT_URL"))
+                .ok(),
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+        })
+    }
+
+    // Custom endpoints are addressed in path style, AWS endpoints in virtual-hosted style
+    // Returns object url and canonical uri for signing
+    fn object_url(&self) -> (String, String) {
+        let key_path = format!("/{}", self.key.split('/').map(uri_encode).join("/"));
+        match &self.endpoint {
+            Some(endpoint) => {
+                let canonical_uri = format!("/{}{key_path}", uri_encode(&self.bucket));
+                (
+                    format!("{}{canonical_uri}", endpoint.trim_end_matches('/')),
+                    canonical_uri,
+                )
+            }
+            None => (
+                format!(
+                    "https://{}.s3.{}.amazonaws.com{key_path}",
+                    self.bucket, self.region
+                ),
+                key_path,
+            ),
+        }
+    }
+
+    fn send(
+        &self,
+        method: &str,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<Response<Body>, anyhow::Error> {
+        let (object_url, canonical_uri) = self.object_url();
+        let canonical_query = query
+            .iter()
+            .map(|(name, value)| format!("{}={}", uri_encode(name), uri_encode(value)))
+            .sorted()
+            .join("&");
+        let url = if canonical_query.is_empty() {
+            object_url
+        } else {
+            format!("{object_url}?{canonical_query}")
+        };
+
+        let parsed_url = Url::parse(&url)?;
+        let host = match (parsed_url.host_str(), parsed_url.port()) {
+            (Some(host), Some(port)) => format!("{host}:{port}"),
+            (Some(host), None) => host.to_owned(),
+            _ => anyhow::bail!("Invalid S3 url {url}"),
+        };
+        let payload_hash = sha256_hex(&body);
+        let mut headers = vec![
+            ("host".to_owned(), host),
+            (
+                "x-amz-date".to_owned(),
+                Utc::now().format("%Y%m%dT%H%M%SZ").to_string(),
+            ),
+            ("x-amz-content-sha256".to_owned(), payload_hash.clone()),
+        ];
+        if let Some(session_token) = &self.credentials.session_token {
+            headers.push(("x-amz-security-token".to_owned(), session_token.clone()));
+        }
+        let authorization = bedrock_runtime::sign_request_with_query(
+            &self.credentials,
+            &self.region,
+            "s3",
+            method,
+            &canonical_uri,
+            &canonical_query,
+            &headers,
+            &payload_hash,
+        )?;
+
+        // Host header is set by the http client
+        let mut request = Request::builder().method(method).uri(&url);
+        for (name, value) in headers.iter().filter(|(name, _)| name != "host") {
+            request = request.header(name, value);
+        }
+        request = request.header("authorization", authorization);
+        Ok(request.body(body)?.send()?)
+    }
+
+    fn send_checked(
+        &self,
+        method: &str,
+        query: &[(&str, &str)],
+        body: Vec<u8>,
+    ) -> Result<Response<Body>, anyhow::Error> {
+        let mut response = self.send(method, query, body)?;
+        check_status(
+            &mut response,
+            &format!("S3 {method} s3://{}/{}", self.bucket, self.key),
+        )?;
+        Ok(response)
+    }
+}
+
+// Access tokens are taken from the default credentials chain
+// (GOOGLE_APPLICATION_CREDENTIALS, gcloud credentials or the metadata server)
+// STORAGE_EMULATOR_HOST can point to a local emulator, which is used without authentication
+struct GcsClient {
+    base_url: String,
+    bucket: String,
+    key: String,
+    auth: Option<(Runtime, AuthenticationManager)>,
+}
+
+impl GcsClient {
+    fn new(bucket: &str, key: &str) -> Result<Self, anyhow::Error> {
+        let (base_url, auth) = match std::env::var("STORAGE_EMULATOR_HOST") {
+            Ok(emulator_host) => (emulator_host.trim_end_matches('/').to_owned(), None),
+            Err(_) => {
+                let runtime = Runtime::new()?;
+                let manager = runtime.block_on(AuthenticationManager::new())?;
+                (GCS_BASE_URL.to_owned(), Some((runtime, manager)))
+            }
+        };
+
+        Ok(GcsClient {
+            base_url,
+            bucket: bucket.to_owned(),
+            key: key.to_owned(),
+            auth,
+        })
+    }
+
+    // Tokens are cached and refreshed by authentication manager, so long uploads do not fail on expiry
+    fn send(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &[(&str, String)],
+        body: Vec<u8>,
+    ) -> Result<Response<Body>, anyhow::Error> {
+        let mut request = Request::builder().method(method).uri(url);
+        if let Some((runtime, manager)) = &self.auth {
+            let token = runtime.block_on(manager.get_token(&GCS_SCOPES))?;
+            request = request.header("Authorization", format!("Bearer {}", token.as_str()));
+        }
+        for (name, value) in headers {
+            request = request.header(*name, value);
+        }
+        Ok(request.body(body)?.send()?)
+    }
+
+    fn description(&self) -> String {
+        format!("gs://{}/{}", self.bucket, self.key)
+    }
+
+    fn download_url(&self) -> String {
+        format!(
+            "{}/storage/v1/b/{}/o/{}?alt=media",
+            self.base_url,
+            uri_encode(&self.bucket),
+            uri_encode(&self.key)
+        )
+    }
+
+    // Returns resumable upload session url
+    fn start_upload(&self) -> Result<String, anyhow::Error> {
+        let url = format!(
+            "{}/upload/storage/v1/b/{}/o?uploadType=resumable&name={}",
+            self.base_url,
+            uri_encode(&self.bucket),
+            uri_encode(&self.key)
+        );
+        let mut response = self.send("POST", &url, &[], Vec::new())?;
+        check_status(
+            &mut response,
+            &format!("GCS upload of {}", self.description()),
+        )?;
+        match response.headers().get("location") {
+            Some(location) => Ok(location.to_str()?.to_owned()),
+            None => anyhow::bail!(
+                "GCS did not return upload session for {}",
+                self.description()
+            ),
+        }
+    }
+}
+
+fn get_tag_value<'a>(xml: &'a str, tag: &str) -> Option<&'a str> {
+    let start = xml.find(&format!("<{tag}>"))? + tag.len() + 2;
+    let end = xml[start..].find(&format!("</{tag}>"))? + start;
+    Some(&xml[start..end])
+}
+
+enum Upload {
+    // Multipart upload is started when the first part is full, smaller objects are uploaded with one request
+    S3 {
+        client: S3Client,
+        upload_id: Option<String>,
+        etags: Vec<String>,
+    },
+    Gcs {
+        client: GcsClient,
+        session_url: Option<String>,
+        offset: usize,
+    },
+}
+
+// Buffers written data and uploads it in parts
+// The object is created only when `finish` is called, uploads which were not finished are aborted
+pub struct ObjectWriter {
+    upload: Upload,
+    buffer: Vec<u8>,
+    finished: bool,
+}
+
+impl ObjectWriter {
+    pub fn new(uri: &ObjectUri) -> Result<Self, anyhow::Error> {
+        let upload = match uri {
+            ObjectUri::S3 { bucket, key } => Upload::S3 {
+                client: S3Client::new(bucket, key)?,
+                upload_id: None,
+                etags: Vec::new(),
+            },
+            ObjectUri::Gcs { bucket, key } => Upload::Gcs {
+                client: GcsClient::new(bucket, key)?,
+                session_url: None,
+                offset: 0,
+            },
+        };
+        Ok(ObjectWriter {
+            upload,
+            buffer: Vec::with_capacity(PART_SIZE),
+            finished: false,
+        })
+    }
+
+    fn upload_part(&mut self, part: Vec<u8>, is_last: bool) -> AnyhowVoidResult {
+        match &mut self.upload {
+            Upload::S3 {
+                client,
+                upload_id,
+                etags,
+            } => {
+                let id = match upload_id.clone() {
+                    Some(id) => id,
+                    None => {
+                        let response_text = client
+                            .send_checked("POST", &[("uploads", "")], Vec::new())?
+                            .text()?;
+                        let id = match get_tag_value(&response_text, "UploadId") {
+                            Some(id) => id.to_owned(),
+                            None => anyhow::bail!("S3 did not return upload id: {response_text}"),
+                        };
+                        *upload_id = Some(id.clone());
+                        id
+                    }
+                };
+                let part_number = (etags.len() + 1).to_string();
+                let response = client.send_checked(
+                    "PUT",
+                    &[
+                        ("partNumber", part_number.as_str()),
+                        ("uploadId", id.as_str()),
+                    ],
+                    part,
+                )?;
+                match response.headers().get("etag") {
+                    Some(etag) => etags.push(etag.to_str()?.to_owned()),
+                    None => anyhow::bail!("S3 did not return ETag of part {part_number}"),
+                }
+            }
+            Upload::Gcs {
+                client,
+                session_url,
+                offset,
+            } => {
+                let url = match session_url.clone() {
+                    Some(url) => url,
+                    None => {
+                        let url = client.start_upload()?;
+                        *session_url = Some(url.clone());
+                        url
+                    }
+                };
+                // Total size is sent with the last part, which completes the upload
+                let total = if is_last {
+                    (*offset + part.len()).to_string()
+                } else {
+                    "*".to_owned()
+                };
+                let range = if part.is_empty() {
+                    format!("bytes */{total}")
+                } else {
+                    format!("bytes {}-{}/{total}", *offset, *offset + part.len() - 1)
+                };
+                let part_len = part.len();
+                let mut response = client.send("PUT", &url, &[("Content-Range", range)], part)?;
+                // 308 means that the part was received and the upload is not complete
+                if response.status().as_u16() != 308 {
+                    check_status(
+                        &mut response,
+                        &format!("GCS upload of {}", client.description()),
+                    )?;
+                }
+                *offset += part_len;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(mut self) -> AnyhowVoidResult {
+        let data = std::mem::take(&mut self.buffer);
+        match &self.upload {
+            Upload::S3 {
+                client,
+                upload_id: None,
+                ..
+            } => {
+                client.send_checked("PUT", &[], data)?;
+            }
+            Upload::S3 { .. } => {
+                if !data.is_empty() {
+                    self.upload_part(data, true)?;
+                }
+                if let Upload::S3 {
+                    client,
+                    upload_id: Some(upload_id),
+                    etags,
+                } = &self.upload
+                {
+                    let parts: String = etags
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, etag)| {
+                            format!(
+                                "<Part><PartNumber>{}</PartNumber><ETag>{etag}</ETag></Part>",
+                                idx + 1
+                            )
+                        })
+                        .collect();
+                    let body =
+                        format!("<CompleteMultipartUpload>{parts}</CompleteMultipartUpload>");
+                    let response_text = client
+                        .send_checked(
+                            "POST",
+                            &[("uploadId", upload_id.as_str())],
+                            body.into_bytes(),
+                        )?
+                        .text()?;
+                    // Complete request can fail after the response status was sent
+                    if response_text.contains("<Error>") {
+                        anyhow::bail!("S3 multipart upload failed: {response_text}");
+                    }
+                }
+            }
+            Upload::Gcs { .. } => {
+                self.upload_part(data, true)?;
+            }
+        }
+        self.finished = true;
+        Ok(())
+    }
+
+    fn abort(&mut self) -> AnyhowVoidResult {
+        match &self.upload {
+            Upload::S3 {
+                client,
+                upload_id: Some(upload_id),
+                ..
+            } => {
+                client.send_checked("DELETE", &[("uploadId", upload_id.as_str())], Vec::new())?;
+            }
+            Upload::Gcs {
+                client,
+                session_url: Some(url),
+                ..
+            } => {
+                client.send("DELETE", url, &[], Vec::new())?;
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+impl Write for ObjectWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        while self.buffer.len() >= PART_SIZE {
+            let rest = self.buffer.split_off(PART_SIZE);
+            let part = std::mem::replace(&mut self.buffer, rest);
+            self.upload_part(part, false)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::Other, e.to_string()))?;
+        }
+        Ok(buf.len())
+    }
+
+    // Buffered data is uploaded when the part is full or the writer is finished
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for ObjectWriter {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.abort();
+        }
+    }
+}
+
+// Writer for local file or object storage uri
+pub enum OutputWriter {
+    File(BufWriter<File>),
+    Object(ObjectWriter),
+    Stdout(BufWriter<std::io::Stdout>),
+}
+
+impl OutputWriter {
+    // "-" path writes to standard output
+    pub fn create(path: &str) -> Result<Self, anyhow::Error> {
+        if path == "-" {
+            return Ok(OutputWriter::Stdout(BufWriter::new(std::io::stdout())));
+        }
+        match ObjectUri::parse(path)? {
+            Some(uri) => Ok(OutputWriter::Object(ObjectWriter::new(&uri)?)),
+            None => Ok(OutputWriter::File(BufWriter::new(File::create(path)?))),
+        }
+    }
+
+    // Objects are created only after finish, so it should be called after the last write
+    pub fn finish(self) -> AnyhowVoidResult {
+        match self {
+            OutputWriter::File(mut file) => file.flush()?,
+            OutputWriter::Object(object) => object.finish()?,
+            OutputWriter::Stdout(mut stdout) => stdout.flush()?,
+        }
+        Ok(())
+    }
+}
+
+impl Write for OutputWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            OutputWriter::File(file) => file.write(buf),
+            OutputWriter::Object(object) => object.write(buf),
+            OutputWriter::Stdout(stdout) => stdout.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            OutputWriter::File(file) => file.flush(),
+            OutputWriter::Object(object) => object.flush(),
+            OutputWriter::Stdout(stdout) => stdout.flush(),
+        }
+    }
+}
+
+// Returns None if the file or object does not exist
+fn open_optional(path: &str) -> Result<Option<Box<dyn Read + Send>>, anyhow::Error> {
+    let mut response = match ObjectUri::parse(path)? {
+        None => {
+            return match File::open(path) {
+                Ok(file) => Ok(Some(Box::new(file))),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+                Err(e) => Err(e.into()),
+            }
+        }
+        Some(ObjectUri::S3 { bucket, key }) => {
+            S3Client::new(&bucket, &key)?.send("GET", &[], Vec::new())?
+        }
+        Some(ObjectUri::Gcs { bucket, key }) => {
+            let client = GcsClient::new(&bucket, &key)?;
+            client.send("GET", &client.download_url(), &[], Vec::new())?
+        }
+    };
+
+    if response.status().as_u16() == 404 {
+        return Ok(None);
+    }
+    check_status(&mut response, &format!("Download of {path}"))?;
+    Ok(Some(Box::new(response.into_body())))
+}
+
+// Objects are streamed, so they are not stored on local disk
+pub fn open_reader(path: &str) -> Result<Box<dyn Read + Send>, anyhow::Error> {
+    match open_optional(path)? {
+        Some(reader) => Ok(reader),
+        None => anyhow::bail!("File {path} not found"),
+    }
+}
+
+pub fn read_to_string(path: &str) -> Result<Option<String>, anyhow::Error> {
+    match open_optional(path)? {
+        Some(mut reader) => {
+            let mut data = String::new();
+            reader.read_to_string(&mut data)?;
+            Ok(Some(data))
+        }
+        None => Ok(None),
+    }
+}
+
+pub fn write(path: &str, data: &[u8]) -> AnyhowVoidResult {
+    let mut writer = OutputWriter::create(path)?;
+    writer.write_all(data)?;
+    writer.finish()
+}
@@ -0,0 +1,134 @@
+use super::exporter::Exporter;
+use super::object_store::OutputWriter;
+use super::EmbeddingRecord;
+use crate::logger::Logger;
+use crate::types::*;
+use parquet::basic::Compression;
+use parquet::data_type::{ByteArray, ByteArrayType, FloatType};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+use std::sync::Arc;
+
+// Embeddings are written as repeated float field, which is read as list<float> by Arrow readers
+static SCHEMA: &'static str = "
+message embeddings {
+    REQUIRED BYTE_ARRAY id (UTF8);
+    REPEATED FLOAT embedding;
+}
+";
+
+// Received batches are buffered to write row groups of this size
+static ROW_GROUP_SIZE: usize = 10000;
+
+pub struct ParquetExporter {
+    path: String,
+    logger: Arc<Logger>,
+    writer: Option<SerializedFileWriter<OutputWriter>>,
+    rows: Vec<EmbeddingRecord>,
+    processed_row_cnt: usize,
+}
+
+impl ParquetExporter {
+    pub fn new(path: &str, logger: Arc<Logger>) -> Self {
+        ParquetExporter {
+            path: path.to_owned(),
+            logger,
+            writer: None,
+            rows: Vec::new(),
+            processed_row_cnt: 0,
+        }
+    }
+
+    fn write_row_group(&mut self) -> AnyhowVoidResult {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+
+        let rows = std::mem::take(&mut self.rows);
+        let writer = self
+            .writer
+            .as_mut()
+            .expect("ParquetExporter::begin should be called before writing");
+        let mut row_group = writer.next_row_group()?;
+
+        if let Some(mut column) = row_group.next_column()? {
+            let ids: Vec<ByteArray> = rows
+                .iter()
+                .map(|(id, _)| ByteArray::from(id.as_str()))
+                .collect();
+            column
+                .typed::<ByteArrayType>()
+                .write_batch(&ids, None, None)?;
+            column.close()?;
+        }
+
+        // Repetition level 0 starts the embedding of the next row
+        // Definition level 0 marks a row without embedding
+        if let Some(mut column) = row_group.next_column()? {
+            let mut values = Vec::new();
+            let mut def_levels = Vec::new();
+            let mut rep_levels = Vec::new();
+            for (_, embedding) in &rows {
+                if embedding.is_empty() {
+                    def_levels.push(0);
+                    rep_levels.push(0);
+                    continue;
+                }
+                for (idx, value) in embedding.iter().enumerate() {
+                    values.push(*value);
+                    def_levels.push(1);
+                    rep_levels.push(if idx == 0 { 0 } else { 1 });
+                }
+            }
+            column.typed::<FloatType>().write_batch(
+                &values,
+                Some(&def_levels),
+                Some(&rep_levels),
+            )?;
+            column.close()?;
+        }
+
+        row_group.close()?;
+        self.processed_row_cnt += rows.len();
+        Ok(())
+    }
+}
+
+impl Exporter for ParquetExporter {
+    fn begin(&mut self) -> AnyhowVoidResult {
+        let schema = Arc::new(parse_message_type(SCHEMA)?);
+        let properties = Arc::new(
+            WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build(),
+        );
+        self.writer = Some(SerializedFileWriter::new(
+            OutputWriter::create(&self.path)?,
+            schema,
+            properties,
+        )?);
+        Ok(())
+    }
+
+    fn write_batch(&mut self, rows: Vec<EmbeddingRecord>) -> AnyhowVoidResult {
+        self.rows.extend(rows);
+        if self.rows.len() >= ROW_GROUP_SIZE {
+            self.write_row_group()?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> AnyhowVoidResult {
+        self.write_row_group()?;
+        // Objects are uploaded only after the file footer is written
+        if let Some(writer) = self.writer.take() {
+            writer.into_inner()?.finish()?;
+        }
+        self.logger.info(&format!(
+            "Embeddings of {} rows exported to {}",
+            self.processed_row_cnt, self.path
+        ));
+        Ok(())
+    }
+}
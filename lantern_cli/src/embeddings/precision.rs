@@ -0,0 +1,138 @@
+use super::binary;
+use super::cli::{BinaryType, ColumnType, EmbeddingArgs, Precision};
+use half::f16;
+
+// Shortest decimal which is rounded back to the same f16 value
+// f16 has 11 bit mantissa, so 5 significant digits are always enough
+pub fn format_f16(value: f16) -> String {
+    let value_f32 = value.to_f32();
+    if !value_f32.is_finite() {
+        return value_f32.to_string();
+    }
+
+    for digits in 0..4 {
+        let decimal: f32 = format!("{value_f32:.digits$e}").parse().unwrap();
+        if f16::from_f32(decimal) == value {
+            return decimal.to_string();
+        }
+    }
+
+    value_f32.to_string()
+}
+
+// Decode BYTEA column written with --precision int8
+pub fn unpack_int8(values: &[u8]) -> Vec<i8> {
+    values.iter().map(|v| *v as i8).collect()
+}
+
+// Decode SMALLINT[] column written with --precision f16 and --column-type real
+pub fn unpack_f16(values: &[i16]) -> Vec<f32> {
+    values
+        .iter()
+        .map(|v| f16::from_bits(*v as u16).to_f32())
+        .collect()
+}
+
+// Text format of embedding values written to the destination
+// All f16 formats use the same round to nearest even conversion
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ValueFormat {
+    F32,
+    F16,
+    // Bits of f16 value stored as SMALLINT, as Postgres does not have half precision float type
+    PackedF16,
+    // Quantized values, integral floats in -128..127 range are expected
+    Int8,
+    // Quantized values stored as one byte per dimension, as Postgres does not have 8-bit integer type
+    Int8Bytea,
+    // Sign bits of the whole embedding as bit string
+    BinaryBit,
+    // Sign bits of the whole embedding packed into bytes
+    BinaryBytea,
+}
+
+impl ValueFormat {
+    pub fn for_table(args: &EmbeddingArgs) -> ValueFormat {
+        if args.quantize.is_some() && args.quantize_column.is_none() {
+            return ValueFormat::binary_for_table(args);
+        }
+        match (&args.precision, &args.column_type) {
+            (Precision::F32, _) => ValueFormat::F32,
+            (Precision::F16, ColumnType::Vector) => ValueFormat::F16,
+            (Precision::F16, ColumnType::Real) => ValueFormat::PackedF16,
+            (Precision::Int8, _) => ValueFormat::Int8Bytea,
+        }
+    }
+
+    pub fn for_csv(args: &EmbeddingArgs) -> ValueFormat {
+        if args.quantize.is_some() && args.quantize_column.is_none() {
+            return ValueFormat::BinaryBit;
+        }
+        match args.precision {
+            Precision::F32 => ValueFormat::F32,
+            Precision::F16 => ValueFormat::F16,
+            Precision::Int8 => ValueFormat::Int8,
+        }
+    }
+
+    fn binary_for_table(args: &EmbeddingArgs) -> ValueFormat {
+        match args.binary_type {
+            BinaryType::Bit => ValueFormat::BinaryBit,
+            BinaryType::Bytea => ValueFormat::BinaryBytea,
+        }
+    }
+
+    // Format of --quantize-column values written next to the float values
+    pub fn quantized_for_table(args: &EmbeddingArgs) -> Option<ValueFormat> {
+        match (&args.quantize, &args.quantize_column) {
+            (Some(_), Some(_)) => Some(ValueFormat::binary_for_table(args)),
+            _ => None,
+        }
+    }
+
+    pub fn quantized_for_csv(args: &EmbeddingArgs) -> Option<ValueFormat> {
+        match (&args.quantize, &args.quantize_column) {
+            (Some(_), Some(_)) => Some(ValueFormat::BinaryBit),
+            _ => None,
+        }
+    }
+
+    // Binary formats encode the whole embedding and are formatted with format_embedding only
+    pub fn format(&self, value: f32) -> String {
+        match self {
+            ValueFormat::F32 => value.to_string(),
+            ValueFormat::F16 => format_f16(f16::from_f32(value)),
+            ValueFormat::PackedF16 => (f16::from_f32(value).to_bits() as i16).to_string(),
+            ValueFormat::Int8 => (value as i8).to_string(),
+            ValueFormat::Int8Bytea => format!("{:02x}", value as i8 as u8),
+            ValueFormat::BinaryBit | ValueFormat::BinaryBytea => binary::sign_bits(&[value]),
+        }
+    }
+
+    // Text of the whole embedding as array literal or as escaped bytea for COPY text format
+    pub fn format_embedding(&self, embedding: &[f32]) -> String {
+        let values = embedding.iter().map(|v| self.format(*v));
+        match self {
+            ValueFormat::BinaryBit => binary::sign_bits(embedding),
+            ValueFormat::BinaryBytea => format!(
+                "\\\\x{}",
+                binary::pack_sign_bits(embedding)
+                    .iter()
+                    .map(|b| format!("{b:02x}"))
+                    .collect::<String>()
+            ),
+            ValueFormat::Int8Bytea => format!("\\\\x{}", values.collect::<String>()),
+            _ => format!("{{{}}}", values.collect::<Vec<_>>().join(",")),
+        }
+    }
+
+    // Type of the temporary table column the values are copied to
+    pub fn array_type_sql(&self) -> &str {
+        match self {
+            ValueFormat::PackedF16 => "SMALLINT[]",
+            ValueFormat::Int8Bytea | ValueFormat::BinaryBytea => "BYTEA",
+            ValueFormat::BinaryBit => "BIT VARYING",
+            _ => "REAL[]",
+        }
+    }
+}
@@ -0,0 +1,543 @@
+use super::cli::{self, EmbeddingArgs};
+use super::object_store::{self, ObjectUri};
+use super::progress::{GaugeGuard, PipelineStats};
+use super::{get_filter_sql, get_source_sql, scan, CONNECTION_PARAMS};
+use crate::logger::Logger;
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use bytes::Bytes;
+use parquet::file::reader::{ChunkReader, FileReader, SerializedFileReader};
+use parquet::record::Field;
+use parquet::schema::types::Type;
+use postgres::{Client, NoTls, Row, Transaction};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Stdin};
+use std::sync::atomic::Ordering;
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::time::Instant;
+
+// Source row id and its text
+pub type SourceRecord = (String, String);
+
+// Source of the texts for the embedding pipeline
+// Downstream crates can implement it to embed texts from other stores
+// and pass it to the pipeline with `EmbeddingPipeline::producer`
+pub trait Producer: Send {
+    // Send batches of at most batch_size records to the sink until the source is exhausted
+    // If the row count is known, it should be reported before the first batch for progress tracking
+    fn produce(&mut self, batch_size: usize, sink: &mut BatchSink) -> AnyhowVoidResult;
+}
+
+// Sends the produced batches to the embedding workers
+pub struct BatchSink {
+    tx: Sender<Vec<SourceRecord>>,
+    count_tx: Option<Sender<i64>>,
+    stats: Arc<PipelineStats>,
+    // Fetch latency of a batch is the time since the previous batch was sent
+    last_send: Instant,
+}
+
+impl BatchSink {
+    pub(crate) fn new(
+        tx: Sender<Vec<SourceRecord>>,
+        count_tx: Sender<i64>,
+        stats: Arc<PipelineStats>,
+    ) -> Self {
+        BatchSink {
+            tx,
+            count_tx: Some(count_tx),
+            stats,
+            last_send: Instant::now(),
+        }
+    }
+
+    pub(crate) fn stats(&self) -> Arc<PipelineStats> {
+        self.stats.clone()
+    }
+
+    // Exporter is started after the count is reported, so only the first call has effect
+    pub fn report_count(&mut self, count: i64) {
+        if let Some(count_tx) = self.count_tx.take() {
+            let _ = count_tx.send(count);
+        }
+    }
+
+    // Returns false if the pipeline has stopped, so no more batches should be produced
+    pub fn send(&mut self, records: Vec<SourceRecord>) -> bool {
+        // Row count is unknown if it was not reported before the first batch
+        self.report_count(0);
+        if records.is_empty() {
+            return true;
+        }
+
+        let row_cnt = records.len();
+        self.stats.latencies.record_fetch(self.last_send.elapsed());
+        self.stats
+            .producer_buffered_rows
+            .fetch_add(row_cnt, Ordering::Relaxed);
+        if self.tx.send(records).is_err() {
+            self.stats
+                .producer_buffered_rows
+                .fetch_sub(row_cnt, Ordering::Relaxed);
+            return false;
+        }
+        self.last_send = Instant::now();
+        true
+    }
+}
+
+// Sinks of parallel scans only send batches, the count is reported by the producer thread
+impl Clone for BatchSink {
+    fn clone(&self) -> Self {
+        BatchSink {
+            tx: self.tx.clone(),
+            count_tx: None,
+            stats: self.stats.clone(),
+            last_send: Instant::now(),
+        }
+    }
+}
+
+// Rows with NULL or empty text are skipped
+// In array mode each element is a separate record with the id of the row
+pub(crate) fn rows_to_records(
+    args: &EmbeddingArgs,
+    rows: Vec<Row>,
+) -> Result<Vec<SourceRecord>, anyhow::Error> {
+    let mut records = Vec::with_capacity(rows.len());
+
+    for row in &rows {
+        if args.array_mode.is_some() {
+            let elements = match row.try_get::<usize, Option<Vec<Option<String>>>>(1) {
+                Ok(Some(elements)) => elements,
+                _ => continue,
+            };
+            let id = row.get::<usize, String>(0);
+            records.extend(
+                elements
+                    .into_iter()
+                    .flatten()
+                    .filter(|element| element.trim() != "")
+                    .map(|element| (id.clone(), element)),
+            );
+            continue;
+        }
+
+        match row.try_get::<usize, Option<String>>(1) {
+            Ok(Some(src_data)) if src_data.trim() != "" => {
+                records.push((row.get::<usize, String>(0), src_data));
+            }
+            Ok(None)
+                if args.jsonpath.is_some()
+                    && matches!(args.jsonpath_missing, cli::JsonPathMissing::Error) =>
+            {
+                anyhow::bail!(
+                    "JSONPath '{}' did not match row {}",
+                    args.jsonpath.as_ref().unwrap(),
+                    row.get::<usize, String>(0)
+                );
+            }
+            _ => {}
+        }
+    }
+
+    Ok(records)
+}
+
+// Rows returned by the top plan node of the query, e.g. "Seq Scan on t  (cost=0.00..35.50 rows=2550 width=4)"
+fn get_plan_rows(transaction: &mut Transaction, sql: &str) -> Result<f64, anyhow::Error> {
+    let plan: String = transaction
+        .query_one(&format!("EXPLAIN {sql}"), &[])?
+        .get(0);
+    plan.split_once(" rows=")
+        .and_then(|(_, rest)| rest.split(' ').next())
+        .and_then(|rows| rows.parse::<f64>().ok())
+        .ok_or_else(|| anyhow::anyhow!("Could not parse row estimate from plan '{plan}'"))
+}
+
+// Row count from pg_class.reltuples adjusted by the filter selectivity of the planner
+// Tables which were never vacuumed or analyzed have no reltuples, then the planner estimate is used
+pub(crate) fn get_estimated_count(
+    transaction: &mut Transaction,
+    full_table_name: &str,
+    filter_sql: &str,
+) -> Result<i64, anyhow::Error> {
+    let reltuples: f32 = transaction
+        .query_one(
+            "SELECT reltuples FROM pg_class WHERE oid = $1::text::regclass",
+            &[&full_table_name],
+        )?
+        .get(0);
+    let filtered_rows = get_plan_rows(
+        transaction,
+        &format!("SELECT 1 FROM {full_table_name} {filter_sql}"),
+    )?;
+    if reltuples < 0.0 {
+        return Ok(filtered_rows.round() as i64);
+    }
+
+    let total_rows = get_plan_rows(transaction, &format!("SELECT 1 FROM {full_table_name}"))?;
+    let selectivity = if total_rows > 0.0 {
+        (filtered_rows / total_rows).min(1.0)
+    } else {
+        1.0
+    };
+    Ok((reltuples as f64 * selectivity).round() as i64)
+}
+
+// 1. Get approximate number of rows from pg_class (this is just for info logging)
+// 2. Create transaction portal which will poll data from database of batch size provided via args
+// 3. Send the rows to the sink
+pub(crate) struct PostgresProducer {
+    args: Arc<EmbeddingArgs>,
+    estimate_count: bool,
+    id_sql: String,
+    logger: Arc<Logger>,
+}
+
+impl PostgresProducer {
+    pub fn new(
+        args: Arc<EmbeddingArgs>,
+        estimate_count: bool,
+        id_sql: String,
+        logger: Arc<Logger>,
+    ) -> Self {
+        PostgresProducer {
+            args,
+            estimate_count,
+            id_sql,
+            logger,
+        }
+    }
+}
+
+impl Producer for PostgresProducer {
+    fn produce(&mut self, batch_size: usize, sink: &mut BatchSink) -> AnyhowVoidResult {
+        let args = &self.args;
+        let column = &args.column;
+        let table = &args.table;
+        let full_table_name = get_full_table_name(&args.schema, table);
+
+        let filter_sql = get_filter_sql(args);
+
+        // Random rows are sampled from the rows matching the filter
+        let limit_sql = match (args.limit, args.sample) {
+            (_, Some(sample)) => format!("ORDER BY random() LIMIT {sample}"),
+            (Some(limit), None) => format!("LIMIT {limit}"),
+            (None, None) => "".to_owned(),
+        };
+        // Sampled table is placed in FROM clause, so the filter is applied to the sampled rows
+        let source_table_sql = match args.sample_percent {
+            Some(percent) => format!("{full_table_name} TABLESAMPLE BERNOULLI ({percent})"),
+            None => full_table_name.clone(),
+        };
+
+        let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+        let mut client = Client::connect(&uri, NoTls)?;
+        let stats = sink.stats();
+        let _connection = GaugeGuard::new(&stats.active_connections);
+
+        let mut transaction = scan::start_transaction(&mut client, args.consistent_snapshot)?;
+
+        let estimate_count = self.estimate_count;
+        let logger = &self.logger;
+        let send_count = |transaction: &mut Transaction,
+                          sink: &mut BatchSink|
+         -> AnyhowVoidResult {
+            let count: i64 = match (estimate_count, &args.count_mode) {
+                (false, _) | (true, cli::CountMode::None) => 0,
+                // Rows are sampled again when they are read, so the count of a sample is approximate
+                (true, cli::CountMode::Exact) => transaction
+                    .query_one(
+                        &format!("SELECT COUNT(*) FROM {source_table_sql} {filter_sql};"),
+                        &[],
+                    )?
+                    .get(0),
+                (true, cli::CountMode::Estimate) => {
+                    let count = get_estimated_count(transaction, &full_table_name, &filter_sql)?;
+                    match args.sample_percent {
+                        Some(percent) => (count as f64 * percent / 100.0).round() as i64,
+                        None => count,
+                    }
+                }
+            };
+            let count = match args.limit.or(args.sample) {
+                Some(limit) => count.min(limit as i64),
+                None => count,
+            };
+            sink.report_count(count);
+            if count > 0 {
+                logger.info(&format!(
+                    "Found approximately {} items in table \"{}\"",
+                    count, table,
+                ));
+            }
+            Ok(())
+        };
+
+        let select_sql = format!(
+            "SELECT {id_sql}, {source_sql} FROM {source_table_sql} {filter_sql}",
+            id_sql = self.id_sql,
+            source_sql = get_source_sql(args, &quote_ident(column)),
+        );
+
+        if let Some(key) = &args.paginate_by {
+            // Only the count is read in the first transaction, it is not kept open during the scan
+            send_count(&mut transaction, sink)?;
+            transaction.commit()?;
+            let page_sql = format!(
+                "SELECT {id_sql}, {source_sql}, {key_sql}::text FROM {full_table_name} {filter_sql}",
+                id_sql = self.id_sql,
+                source_sql = get_source_sql(args, &quote_ident(column)),
+                key_sql = quote_ident(key),
+            );
+            scan::send_pages(&mut client, &page_sql, key, batch_size, args, sink)?;
+        } else if args.producer_scans > 1 {
+            // Exported snapshot can be imported by other sessions while this transaction is open
+            let snapshot_id = if args.consistent_snapshot {
+                Some(
+                    transaction
+                        .query_one("SELECT pg_export_snapshot()", &[])?
+                        .get::<usize, String>(0),
+                )
+            } else {
+                None
+            };
+            let block_count = scan::get_block_count(&mut transaction, &full_table_name)?;
+
+            // Count is sent after the scans are ready, as the exporter is started after it
+            let scan_sink = sink.clone();
+            scan::scan_block_ranges(
+                args,
+                &scan::RangeScan {
+                    uri: &uri,
+                    select_sql: &select_sql,
+                    snapshot_id: snapshot_id.as_deref(),
+                    block_count,
+                    batch_size,
+                },
+                &scan_sink,
+                || send_count(&mut transaction, sink),
+            )?;
+        } else {
+            send_count(&mut transaction, sink)?;
+            scan::send_rows(
+                &mut transaction,
+                &format!("{select_sql} {limit_sql};"),
+                batch_size,
+                args,
+                sink,
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// CSV file with a header row, texts are read from the text column and ids from the id column
+pub struct CsvProducer {
+    path: String,
+    id_column: String,
+    text_column: String,
+}
+
+impl CsvProducer {
+    pub fn new(path: &str, id_column: &str, text_column: &str) -> Self {
+        CsvProducer {
+            path: path.to_owned(),
+            id_column: id_column.to_owned(),
+            text_column: text_column.to_owned(),
+        }
+    }
+}
+
+impl Producer for CsvProducer {
+    fn produce(&mut self, batch_size: usize, sink: &mut BatchSink) -> AnyhowVoidResult {
+        let mut reader =
+            csv::Reader::from_reader(BufReader::new(object_store::open_reader(&self.path)?));
+        let headers = reader.headers()?.clone();
+        let column_idx = |name: &str| -> Result<usize, anyhow::Error> {
+            headers
+                .iter()
+                .position(|header| header == name)
+                .ok_or_else(|| anyhow::anyhow!("Column '{name}' not found in {}", self.path))
+        };
+        let id_idx = column_idx(&self.id_column)?;
+        let text_idx = column_idx(&self.text_column)?;
+
+        let mut batch = Vec::with_capacity(batch_size);
+        for record in reader.records() {
+            let record = record?;
+            let text = record.get(text_idx).unwrap_or("");
+            if text.trim() == "" {
+                continue;
+            }
+            batch.push((record.get(id_idx).unwrap_or("").to_owned(), text.to_owned()));
+
+            if batch.len() >= batch_size && !sink.send(std::mem::take(&mut batch)) {
+                return Ok(());
+            }
+        }
+        sink.send(batch);
+
+        Ok(())
+    }
+}
+
+// Parquet file, only the id and text columns are read
+// Values of non-string id columns are converted to text
+// Objects are read into memory, as Parquet footer is at the end of the file
+pub struct ParquetProducer {
+    path: String,
+    id_column: String,
+    text_column: String,
+}
+
+impl ParquetProducer {
+    pub fn new(path: &str, id_column: &str, text_column: &str) -> Self {
+        ParquetProducer {
+            path: path.to_owned(),
+            id_column: id_column.to_owned(),
+            text_column: text_column.to_owned(),
+        }
+    }
+}
+
+fn field_to_string(field: &Field) -> Option<String> {
+    match field {
+        Field::Null => None,
+        Field::Str(value) => Some(value.clone()),
+        field => Some(field.to_string()),
+    }
+}
+
+impl Producer for ParquetProducer {
+    fn produce(&mut self, batch_size: usize, sink: &mut BatchSink) -> AnyhowVoidResult {
+        if ObjectUri::parse(&self.path)?.is_some() {
+            let mut data = Vec::new();
+            object_store::open_reader(&self.path)?.read_to_end(&mut data)?;
+            self.read_rows(
+                SerializedFileReader::new(Bytes::from(data))?,
+                batch_size,
+                sink,
+            )
+        } else {
+            self.read_rows(
+                SerializedFileReader::new(File::open(&self.path)?)?,
+                batch_size,
+                sink,
+            )
+        }
+    }
+}
+
+impl ParquetProducer {
+    fn read_rows<R: ChunkReader + 'static>(
+        &self,
+        reader: SerializedFileReader<R>,
+        batch_size: usize,
+        sink: &mut BatchSink,
+    ) -> AnyhowVoidResult {
+        let file_metadata = reader.metadata().file_metadata();
+        sink.report_count(file_metadata.num_rows());
+
+        let fields = file_metadata.schema_descr().root_schema().get_fields();
+        let mut projection = Vec::with_capacity(2);
+        for name in [&self.id_column, &self.text_column] {
+            match fields.iter().find(|field| field.name() == name) {
+                Some(field) => projection.push(field.clone()),
+                None => anyhow::bail!("Column '{name}' not found in {}", self.path),
+            }
+        }
+        // Text column can be the same as the id column
+        projection.dedup_by(|a, b| a.name() == b.name());
+        let projection = Type::group_type_builder("schema")
+            .with_fields(projection)
+            .build()?;
+
+        let mut batch = Vec::with_capacity(batch_size);
+        for row in reader.get_row_iter(Some(projection))? {
+            let row = row?;
+            let mut id = None;
+            let mut text = None;
+            for (name, field) in row.get_column_iter() {
+                if name == &self.id_column {
+                    id = field_to_string(field);
+                }
+                if name == &self.text_column {
+                    text = field_to_string(field);
+                }
+            }
+
+            let text = match text {
+                Some(text) if text.trim() != "" => text,
+                _ => continue,
+            };
+            batch.push((id.unwrap_or_default(), text));
+
+            if batch.len() >= batch_size && !sink.send(std::mem::take(&mut batch)) {
+                return Ok(());
+            }
+        }
+        sink.send(batch);
+
+        Ok(())
+    }
+}
+
+// Newline-delimited texts, rows are identified by line number starting from 1
+pub struct LineProducer<R> {
+    reader: R,
+}
+
+impl<R: BufRead + Send> LineProducer<R> {
+    pub fn new(reader: R) -> Self {
+        LineProducer { reader }
+    }
+}
+
+impl LineProducer<BufReader<Stdin>> {
+    pub fn stdin() -> Self {
+        LineProducer::new(BufReader::new(std::io::stdin()))
+    }
+}
+
+impl<R: BufRead + Send> Producer for LineProducer<R> {
+    fn produce(&mut self, batch_size: usize, sink: &mut BatchSink) -> AnyhowVoidResult {
+        let mut batch = Vec::with_capacity(batch_size);
+        for (idx, line) in (&mut self.reader).lines().enumerate() {
+            let line = line?;
+            if line.trim() == "" {
+                continue;
+            }
+            batch.push(((idx + 1).to_string(), line));
+
+            if batch.len() >= batch_size && !sink.send(std::mem::take(&mut batch)) {
+                return Ok(());
+            }
+        }
+        sink.send(batch);
+
+        Ok(())
+    }
+}
+
+// Producer for --in-csv or --in-parquet, None if the texts are read from the source table
+pub fn get_file_producer(args: &EmbeddingArgs) -> Result<Option<Box<dyn Producer>>, anyhow::Error> {
+    match (&args.in_csv, &args.in_parquet) {
+        (Some(_), Some(_)) => anyhow::bail!("--in-csv can not be used with --in-parquet"),
+        (Some(path), None) => Ok(Some(Box::new(CsvProducer::new(
+            path,
+            &args.id_column,
+            &args.text_column,
+        )))),
+        (None, Some(path)) => Ok(Some(Box::new(ParquetProducer::new(
+            path,
+            &args.id_column,
+            &args.text_column,
+        )))),
+        (None, None) if args.stdin => Ok(Some(Box::new(LineProducer::stdin()))),
+        (None, None) => Ok(None),
+    }
+}
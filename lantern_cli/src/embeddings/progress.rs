@@ -0,0 +1,185 @@
+use super::cli::EmbeddingArgs;
+use super::report::LatencyRecorder;
+use super::CONNECTION_PARAMS;
+use crate::types::{AnyhowVoidResult, ProgressEvent, StageStats};
+use crate::utils::{append_params_to_uri, redact_secrets};
+use postgres::{Client, NoTls};
+use serde_json::json;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+// Counters shared by the pipeline workers, a snapshot of them is attached to progress events
+#[derive(Default)]
+pub struct PipelineStats {
+    pub tokens: AtomicUsize,
+    pub requests: AtomicUsize,
+    pub producer_buffered_rows: AtomicUsize,
+    pub exporter_buffered_rows: AtomicUsize,
+    pub in_flight_requests: AtomicUsize,
+    pub batch_size: AtomicUsize,
+    pub active_connections: AtomicUsize,
+    pub latencies: LatencyRecorder,
+}
+
+// Increments the gauge while the guard is alive
+pub struct GaugeGuard<'a>(&'a AtomicUsize);
+
+impl<'a> GaugeGuard<'a> {
+    pub fn new(gauge: &'a AtomicUsize) -> Self {
+        gauge.fetch_add(1, Ordering::Relaxed);
+        Self(gauge)
+    }
+}
+
+impl Drop for GaugeGuard<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl PipelineStats {
+    pub fn snapshot(&self) -> StageStats {
+        StageStats {
+            producer_buffered_rows: self.producer_buffered_rows.load(Ordering::Relaxed),
+            exporter_buffered_rows: self.exporter_buffered_rows.load(Ordering::Relaxed),
+            in_flight_requests: self.in_flight_requests.load(Ordering::Relaxed),
+            batch_size: self.batch_size.load(Ordering::Relaxed),
+            active_connections: self.active_connections.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// Builds progress events from exported rows and tokens counted by embedding workers
+// Throughput is measured from the start of the export, so ETA includes model loading time
+pub struct ProgressTracker {
+    start: Instant,
+    total_rows: Option<usize>,
+    processed_rows: usize,
+    stats: Arc<PipelineStats>,
+}
+
+impl ProgressTracker {
+    pub fn new(total_rows: i64, stats: Arc<PipelineStats>) -> Self {
+        Self {
+            start: Instant::now(),
+            total_rows: if total_rows > 0 {
+                Some(total_rows as usize)
+            } else {
+                None
+            },
+            processed_rows: 0,
+            stats,
+        }
+    }
+
+    pub fn add_rows(&mut self, rows: usize) -> ProgressEvent {
+        self.processed_rows += rows;
+        let elapsed = self.start.elapsed().as_secs_f64();
+        let emb_per_sec = if elapsed > 0.0 {
+            self.processed_rows as f64 / elapsed
+        } else {
+            0.0
+        };
+
+        let (percent, eta) = match self.total_rows {
+            Some(total_rows) => {
+                let percent = std::cmp::min(self.processed_rows * 100 / total_rows, 100) as u8;
+                let remaining_rows = total_rows.saturating_sub(self.processed_rows);
+                let eta = if emb_per_sec > 0.0 {
+                    Some(Duration::from_secs_f64(remaining_rows as f64 / emb_per_sec))
+                } else {
+                    None
+                };
+                (percent, eta)
+            }
+            None => (0, None),
+        };
+
+        ProgressEvent {
+            processed_rows: self.processed_rows,
+            total_rows: self.total_rows,
+            percent,
+            tokens: self.stats.tokens.load(Ordering::Relaxed),
+            emb_per_sec,
+            eta,
+            stages: self.stats.snapshot(),
+        }
+    }
+
+    // Processed rows may be less than the estimated row count, if some rows were skipped
+    pub fn finish(&mut self) -> ProgressEvent {
+        ProgressEvent {
+            percent: 100,
+            eta: Some(Duration::ZERO),
+            ..self.add_rows(0)
+        }
+    }
+}
+
+// Channel of the events sent with --notify-progress
+pub static PROGRESS_CHANNEL: &str = "lantern_job_progress";
+// Payload of a notification should be shorter than 8000 bytes
+static MAX_ERROR_LENGTH: usize = 1000;
+
+// Sends progress events of the exporter with pg_notify, so orchestrators can LISTEN to the channel
+// Notifications are sent on their own connection outside of the export transactions,
+// so they are delivered immediately and not only when the rows are committed
+pub struct ProgressNotifier {
+    client: Client,
+    job_id: Option<String>,
+    table: String,
+    column: String,
+}
+
+impl ProgressNotifier {
+    pub fn connect(args: &EmbeddingArgs) -> Result<Self, anyhow::Error> {
+        let uri = append_params_to_uri(
+            args.out_uri.as_ref().unwrap_or(&args.uri),
+            CONNECTION_PARAMS,
+        );
+        Ok(Self {
+            client: Client::connect(&uri, NoTls)?,
+            job_id: args.job_id.clone(),
+            table: format!(
+                "{}.{}",
+                args.out_schema.as_ref().unwrap_or(&args.schema),
+                args.out_table.as_ref().unwrap_or(&args.table)
+            ),
+            column: args.out_column.clone(),
+        })
+    }
+
+    // Stage is "started", "embedding", "finished" or "failed"
+    pub fn notify(
+        &mut self,
+        stage: &str,
+        progress: &ProgressEvent,
+        error: Option<&anyhow::Error>,
+    ) -> AnyhowVoidResult {
+        let mut payload = json!({
+            "job_id": self.job_id,
+            "stage": stage,
+            "table": self.table,
+            "column": self.column,
+            "percent": progress.total_rows.map(|_| progress.percent),
+            "processed_rows": progress.processed_rows,
+            "total_rows": progress.total_rows,
+            "tokens": progress.tokens,
+            "emb_per_sec": progress.emb_per_sec,
+            "eta_secs": progress.eta.map(|eta| eta.as_secs()),
+        });
+        if let Some(error) = error {
+            let error: String = redact_secrets(&error.to_string())
+                .chars()
+                .take(MAX_ERROR_LENGTH)
+                .collect();
+            payload["error"] = json!(error);
+        }
+        self.client.execute(
+            "SELECT pg_notify($1, $2)",
+            &[&PROGRESS_CHANNEL, &payload.to_string()],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+use super::cli::EmbeddingArgs;
+use super::CONNECTION_PARAMS;
+use crate::logger::Logger;
+use crate::types::AnyhowVoidResult;
+use crate::utils::append_params_to_uri;
+use postgres::{Client, NoTls};
+use std::time::{Duration, Instant};
+
+static REPLICA_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Wait until the replica has replayed the WAL written on the primary before the job started,
+// so the replica sees e.g. the embeddings written by the previous incremental run
+// Only physical replicas report the replayed position, logical replicas are not waited for
+pub fn wait_for_replica(args: &EmbeddingArgs, logger: &Logger) -> AnyhowVoidResult {
+    let replica_uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut replica = Client::connect(&replica_uri, NoTls)?;
+    let in_recovery = replica
+        .query_one("SELECT pg_is_in_recovery()", &[])?
+        .get::<usize, bool>(0);
+    if !in_recovery {
+        logger.warn("--uri is not a physical replica, replication lag will not be checked");
+        return Ok(());
+    }
+
+    let primary_uri = append_params_to_uri(args.out_uri.as_ref().unwrap(), CONNECTION_PARAMS);
+    let mut primary = Client::connect(&primary_uri, NoTls)?;
+    let primary_lsn = primary
+        .query_one("SELECT pg_current_wal_lsn()::text", &[])?
+        .get::<usize, String>(0);
+
+    let timeout = Duration::from_secs(args.replica_wait_timeout);
+    let start = Instant::now();
+    loop {
+        let lag_bytes = replica
+            .query_one(
+                "SELECT COALESCE(pg_wal_lsn_diff($1::text::pg_lsn, pg_last_wal_replay_lsn()), 0)::bigint",
+                &[&primary_lsn],
+            )?
+            .get::<usize, i64>(0);
+        if lag_bytes <= 0 {
+            break;
+        }
+        if start.elapsed() >= timeout {
+            anyhow::bail!(
+                "Replica is still {lag_bytes} bytes behind the primary after {}s",
+                args.replica_wait_timeout
+            );
+        }
+        logger.debug(&format!("Waiting for the replica to replay {lag_bytes} bytes of WAL"));
+        std::thread::sleep(REPLICA_POLL_INTERVAL);
+    }
+
+    logger.info(&format!("Replica has replayed the primary WAL up to {primary_lsn}"));
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::Duration;
+
+// Latencies of the batches recorded by the pipeline workers
+// Fetch is the time the producer spent reading a batch from the source,
+// embed is the runtime request time and export is the time the exporter spent writing a batch
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BatchLatencies {
+    pub fetch: Vec<Duration>,
+    pub embed: Vec<Duration>,
+    pub export: Vec<Duration>,
+}
+
+// Shared between the workers of one pipeline
+#[derive(Default)]
+pub struct LatencyRecorder(Mutex<BatchLatencies>);
+
+impl LatencyRecorder {
+    pub fn record_fetch(&self, latency: Duration) {
+        self.0.lock().unwrap().fetch.push(latency);
+    }
+
+    pub fn record_embed(&self, latency: Duration) {
+        self.0.lock().unwrap().embed.push(latency);
+    }
+
+    pub fn record_export(&self, latency: Duration) {
+        self.0.lock().unwrap().export.push(latency);
+    }
+
+    pub fn take(&self) -> BatchLatencies {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencySummary {
+    pub batches: usize,
+    pub avg: Duration,
+    pub median: Duration,
+    pub p95: Duration,
+}
+
+impl LatencySummary {
+    pub fn new(latencies: &[Duration]) -> Self {
+        if latencies.is_empty() {
+            return LatencySummary::default();
+        }
+
+        let mut sorted = latencies.to_vec();
+        sorted.sort();
+        let percentile = |p: usize| sorted[(sorted.len() * p).div_ceil(100).max(1) - 1];
+        LatencySummary {
+            batches: sorted.len(),
+            avg: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            median: percentile(50),
+            p95: percentile(95),
+        }
+    }
+}
+
+impl fmt::Display for LatencySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let ms = |duration: Duration| duration.as_secs_f64() * 1000.0;
+        write!(
+            f,
+            "{:.1}/{:.1}/{:.1} ms",
+            ms(self.avg),
+            ms(self.median),
+            ms(self.p95)
+        )
+    }
+}
+
+// Summary of a finished embedding job
+// Jobs which do not run the pipeline, e.g. dry runs, have only the counts set
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct JobReport {
+    pub processed_rows: usize,
+    pub processed_tokens: usize,
+    pub wall_time: Duration,
+    pub latencies: BatchLatencies,
+}
+
+impl JobReport {
+    pub fn new(processed_rows: usize, processed_tokens: usize) -> Self {
+        JobReport {
+            processed_rows,
+            processed_tokens,
+            ..Default::default()
+        }
+    }
+
+    pub fn rows_per_sec(&self) -> f64 {
+        if self.wall_time.is_zero() {
+            return 0.0;
+        }
+        self.processed_rows as f64 / self.wall_time.as_secs_f64()
+    }
+
+    pub fn fetch(&self) -> LatencySummary {
+        LatencySummary::new(&self.latencies.fetch)
+    }
+
+    pub fn embed(&self) -> LatencySummary {
+        LatencySummary::new(&self.latencies.embed)
+    }
+
+    pub fn export(&self) -> LatencySummary {
+        LatencySummary::new(&self.latencies.export)
+    }
+
+    // Combines reports of the jobs run one after another, e.g. per tenant
+    pub fn merge(&mut self, other: JobReport) {
+        self.processed_rows += other.processed_rows;
+        self.processed_tokens += other.processed_tokens;
+        self.wall_time += other.wall_time;
+        self.latencies.fetch.extend(other.latencies.fetch);
+        self.latencies.embed.extend(other.latencies.embed);
+        self.latencies.export.extend(other.latencies.export);
+    }
+}
+
+impl fmt::Display for JobReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Job report: {} rows, {} tokens in {:.1}s ({:.1} rows/s)",
+            self.processed_rows,
+            self.processed_tokens,
+            self.wall_time.as_secs_f64(),
+            self.rows_per_sec()
+        )?;
+        for (stage, summary) in [
+            ("fetch", self.fetch()),
+            ("embed", self.embed()),
+            ("export", self.export()),
+        ] {
+            if summary.batches > 0 {
+                write!(
+                    f,
+                    ", {stage} avg/median/p95 {summary} ({} batches)",
+                    summary.batches
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
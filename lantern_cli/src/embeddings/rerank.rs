@@ -0,0 +1,115 @@
+use super::cli::RerankArgs;
+use super::core::{get_rerank_runtime, stderr_logger, LoggerFn};
+use super::object_store;
+use super::CONNECTION_PARAMS;
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::utils::append_params_to_uri;
+use postgres::{Client, NoTls};
+use std::io::{BufRead, BufReader, Read};
+
+// Candidates are returned as JSON objects, so rows of any query can be reranked
+fn get_sql_candidates(uri: &str, sql: &str) -> Result<Vec<serde_json::Value>, anyhow::Error> {
+    let uri = append_params_to_uri(uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    client
+        .query(&format!("SELECT row_to_json(q)::text FROM ({sql}) q"), &[])?
+        .iter()
+        .map(|row| Ok(serde_json::from_str(row.get::<usize, &str>(0))?))
+        .collect()
+}
+
+fn get_jsonl_candidates(path: &str) -> Result<Vec<serde_json::Value>, anyhow::Error> {
+    let reader: Box<dyn Read + Send> = if path == "-" {
+        Box::new(std::io::stdin())
+    } else {
+        object_store::open_reader(path)?
+    };
+
+    let mut candidates = Vec::new();
+    for (idx, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(candidate @ serde_json::Value::Object(_)) => candidates.push(candidate),
+            Ok(_) => anyhow::bail!("Line {} of {path} is not a JSON object", idx + 1),
+            Err(e) => anyhow::bail!("Failed to parse line {} of {path}: {e}", idx + 1),
+        }
+    }
+    Ok(candidates)
+}
+
+// Candidates are returned with the relevance score, sorted from the most relevant
+pub fn rerank(
+    args: &RerankArgs,
+    logger: Option<Logger>,
+) -> Result<Vec<serde_json::Value>, anyhow::Error> {
+    let logger = logger.unwrap_or(Logger::new("Lantern Rerank", LogLevel::Debug));
+
+    if args.batch_size == 0 {
+        anyhow::bail!("--batch-size should be greater than 0");
+    }
+
+    let mut candidates = match (&args.sql, &args.in_jsonl, &args.uri) {
+        (Some(sql), None, Some(uri)) => get_sql_candidates(uri, sql)?,
+        (Some(_), None, None) => anyhow::bail!("--uri is required for --sql"),
+        (None, Some(path), _) => get_jsonl_candidates(path)?,
+        _ => anyhow::bail!("One of --sql or --in-jsonl should be specified"),
+    };
+    logger.debug(&format!("Reranking {} candidates", candidates.len()));
+
+    let texts = candidates
+        .iter()
+        .enumerate()
+        .map(|(idx, candidate)| match &candidate[&args.text_column] {
+            serde_json::Value::String(text) => Ok(text.clone()),
+            serde_json::Value::Null => anyhow::bail!(
+                "Candidate {} does not have '{}' text",
+                idx + 1,
+                args.text_column
+            ),
+            value => Ok(value.to_string()),
+        })
+        .collect::<Result<Vec<String>, anyhow::Error>>()?;
+
+    // Runtime logs are written to stderr, so stdout contains only the results
+    let runtime_logger: LoggerFn = stderr_logger;
+    let runtime = get_rerank_runtime(&args.runtime, Some(&runtime_logger), &args.runtime_params)?;
+    let mut scores = Vec::with_capacity(texts.len());
+    for batch in texts.chunks(args.batch_size) {
+        let documents: Vec<&str> = batch.iter().map(|text| text.as_str()).collect();
+        let result = runtime.rerank(&args.model, &args.query, &documents)?;
+        if result.scores.len() != documents.len() {
+            anyhow::bail!(
+                "Runtime returned {} scores for {} candidates",
+                result.scores.len(),
+                documents.len()
+            );
+        }
+        scores.extend(result.scores);
+    }
+
+    for (candidate, score) in candidates.iter_mut().zip(&scores) {
+        if let Some(candidate) = candidate.as_object_mut() {
+            candidate.insert("score".to_owned(), serde_json::json!(score));
+        }
+    }
+
+    let mut ranked: Vec<(f32, serde_json::Value)> = scores.into_iter().zip(candidates).collect();
+    ranked.sort_by(|(a, _), (b, _)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    Ok(ranked
+        .into_iter()
+        .take(args.top_k.unwrap_or(usize::MAX))
+        .map(|(_, candidate)| candidate)
+        .collect())
+}
+
+pub fn print_rerank_results(args: &RerankArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Rerank", LogLevel::Error));
+    for candidate in rerank(args, Some(logger.clone()))? {
+        logger.print_raw(&candidate.to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,234 @@
+use super::affinity::pin_current_thread_to;
+use super::cli;
+use super::producer::{rows_to_records, BatchSink};
+use super::progress::GaugeGuard;
+use crate::types::AnyhowVoidResult;
+use crate::utils::{get_full_table_name, quote_ident};
+use postgres::{Client, IsolationLevel, NoTls, Portal, Transaction};
+use std::sync::mpsc;
+
+// In consistent snapshot mode all statements of the transaction see the same snapshot
+// So the row count and the scanned rows are not affected by concurrent writes
+pub fn start_transaction(
+    client: &mut Client,
+    consistent_snapshot: bool,
+) -> Result<Transaction<'_>, anyhow::Error> {
+    let mut builder = client.build_transaction();
+    if consistent_snapshot {
+        builder = builder
+            .isolation_level(IsolationLevel::RepeatableRead)
+            .read_only(true);
+    }
+    Ok(builder.start()?)
+}
+
+// With portal we can execute a query and poll values from it in chunks
+// Rows are sent to embedding workers until the query is exhausted or the receiver is dropped
+pub fn send_rows(
+    transaction: &mut Transaction,
+    query: &str,
+    batch_size: usize,
+    args: &cli::EmbeddingArgs,
+    sink: &mut BatchSink,
+) -> AnyhowVoidResult {
+    let portal = transaction.bind(query, &[])?;
+    send_portal_rows(transaction, &portal, batch_size, args, sink)
+}
+
+// The snapshot of the query is taken when the portal is bound
+pub fn send_portal_rows(
+    transaction: &mut Transaction,
+    portal: &Portal,
+    batch_size: usize,
+    args: &cli::EmbeddingArgs,
+    sink: &mut BatchSink,
+) -> AnyhowVoidResult {
+    loop {
+        let rows = tracing::info_span!("db_fetch", batch_size)
+            .in_scope(|| transaction.query_portal(portal, batch_size as i32))?;
+        if rows.is_empty() {
+            break;
+        }
+
+        if !sink.send(rows_to_records(args, rows)?) {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// Keyset pagination, each page is fetched with its own short transaction
+// Pages are ordered by the key column and start after the last key of the previous page,
+// so rows are not skipped or repeated if other rows are inserted or updated between pages
+// The key should be selected as text in the third column of select_sql
+pub fn send_pages(
+    client: &mut Client,
+    select_sql: &str,
+    key: &str,
+    batch_size: usize,
+    args: &cli::EmbeddingArgs,
+    sink: &mut BatchSink,
+) -> AnyhowVoidResult {
+    let key = quote_ident(key);
+    let mut remaining_rows = args.limit.map(|limit| limit as usize);
+    let mut last_key: Option<String> = None;
+
+    loop {
+        let page_size = remaining_rows.map_or(batch_size, |rows| rows.min(batch_size));
+        if page_size == 0 {
+            break;
+        }
+
+        // Key is compared as an untyped literal, so it is coerced to the type of the column
+        let after_key_sql = match &last_key {
+            Some(last_key) => format!("AND {key} > '{}'", last_key.replace('\'', "''")),
+            None => "".to_owned(),
+        };
+        let rows = tracing::info_span!("db_fetch", batch_size = page_size).in_scope(|| {
+            client.query(
+                &format!("{select_sql} {after_key_sql} ORDER BY {key} LIMIT {page_size}"),
+                &[],
+            )
+        })?;
+        if rows.is_empty() {
+            break;
+        }
+
+        let last_page = rows.len() < page_size;
+        remaining_rows = remaining_rows.map(|rows_left| rows_left - rows.len());
+        last_key = rows.last().unwrap().get::<usize, Option<String>>(2);
+        if last_key.is_none() {
+            anyhow::bail!("--paginate-by column {key} should not contain null values");
+        }
+
+        if !sink.send(rows_to_records(args, rows)?) || last_page {
+            break;
+        }
+    }
+
+    Ok(())
+}
+
+// Split table pages into contiguous ranges for parallel scans
+// The last range has no upper bound, so pages added after the size check are not missed
+pub fn get_block_ranges(block_count: u64, scans: usize) -> Vec<(u64, Option<u64>)> {
+    let scans = scans.max(1) as u64;
+    let range_size = block_count.div_ceil(scans).max(1);
+
+    (0..scans)
+        .map(|i| {
+            let end = if i == scans - 1 {
+                None
+            } else {
+                Some((i + 1) * range_size)
+            };
+            (i * range_size, end)
+        })
+        .collect()
+}
+
+pub fn get_block_count(
+    transaction: &mut Transaction,
+    full_table_name: &str,
+) -> Result<u64, anyhow::Error> {
+    let block_count: i64 = transaction
+        .query_one(
+            "SELECT pg_relation_size($1::text::regclass) / current_setting('block_size')::bigint",
+            &[&full_table_name],
+        )?
+        .get(0);
+
+    Ok(block_count as u64)
+}
+
+pub struct RangeScan<'a> {
+    pub uri: &'a str,
+    pub select_sql: &'a str,
+    // Snapshot exported by the producer transaction, which should be kept open until the scans are finished
+    pub snapshot_id: Option<&'a str>,
+    pub block_count: u64,
+    pub batch_size: usize,
+}
+
+// Scan ctid ranges of the table in parallel, each scan uses its own connection
+// on_ready is called after all scans have started their transactions and locked the table
+// Exporter may alter the table after that, so the scans will not be queued behind its lock
+pub fn scan_block_ranges(
+    args: &cli::EmbeddingArgs,
+    scan: &RangeScan,
+    sink: &BatchSink,
+    on_ready: impl FnOnce() -> AnyhowVoidResult,
+) -> AnyhowVoidResult {
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let ranges = get_block_ranges(scan.block_count, args.producer_scans);
+    let scan_count = ranges.len();
+    let (ready_tx, ready_rx) = mpsc::channel::<()>();
+    let parent_span = tracing::Span::current();
+
+    std::thread::scope(|s| {
+        let handles: Vec<_> = ranges
+            .into_iter()
+            .map(|(start, end)| {
+                let ready_tx = ready_tx.clone();
+                let mut sink = sink.clone();
+                let full_table_name = &full_table_name;
+                let parent_span = &parent_span;
+                s.spawn(move || -> AnyhowVoidResult {
+                    let _span =
+                        tracing::info_span!(parent: parent_span, "range_scan", start, end = ?end)
+                            .entered();
+                    pin_current_thread_to(&args.producer_cores)?;
+                    let mut client = Client::connect(scan.uri, NoTls)?;
+                    let stats = sink.stats();
+                    let _connection = GaugeGuard::new(&stats.active_connections);
+                    let mut transaction =
+                        start_transaction(&mut client, scan.snapshot_id.is_some())?;
+
+                    if let Some(snapshot_id) = scan.snapshot_id {
+                        transaction.batch_execute(&format!(
+                            "SET TRANSACTION SNAPSHOT '{}'",
+                            snapshot_id.replace('\'', "''")
+                        ))?;
+                    }
+                    transaction.batch_execute(&format!(
+                        "LOCK TABLE {full_table_name} IN ACCESS SHARE MODE"
+                    ))?;
+
+                    let range_sql = match end {
+                        Some(end) => {
+                            format!("ctid >= '({start},0)'::tid AND ctid < '({end},0)'::tid")
+                        }
+                        None => format!("ctid >= '({start},0)'::tid"),
+                    };
+
+                    // Without a shared snapshot the scan sees the rows committed before its
+                    // portal is bound, so the exporter is started only after all scans are bound
+                    let portal =
+                        transaction.bind(&format!("{} AND {range_sql};", scan.select_sql), &[])?;
+                    ready_tx.send(())?;
+                    drop(ready_tx);
+
+                    send_portal_rows(&mut transaction, &portal, scan.batch_size, args, &mut sink)
+                })
+            })
+            .collect();
+
+        // Senders are dropped by the scans after they are ready or failed
+        drop(ready_tx);
+        let ready_result = if ready_rx.iter().count() == scan_count {
+            on_ready()
+        } else {
+            Ok(())
+        };
+
+        for handle in handles {
+            match handle.join() {
+                Err(e) => anyhow::bail!("{:?}", e),
+                Ok(res) => res?,
+            }
+        }
+
+        ready_result
+    })
+}
@@ -0,0 +1,346 @@
+use super::cli::{Fusion, SearchArgs};
+use super::embed_text::embed_query;
+use super::CONNECTION_PARAMS;
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::types::ToSql;
+use postgres::{Client, NoTls, Transaction};
+use std::collections::HashMap;
+
+// Operator is interpolated into the query, so only operator characters are allowed
+fn get_operator(args: &SearchArgs) -> Result<String, anyhow::Error> {
+    match &args.operator {
+        Some(operator)
+            if operator.is_empty()
+                || !operator.chars().all(|c| "+-*/<>=~!@#%^&|`?".contains(c)) =>
+        {
+            anyhow::bail!("Invalid operator {operator}")
+        }
+        Some(operator) => Ok(operator.clone()),
+        None => Ok(args.metric_kind.sql_operator()),
+    }
+}
+
+fn get_column_type(
+    client: &mut Client,
+    full_table_name: &str,
+    column: &str,
+) -> Result<String, anyhow::Error> {
+    let row = client.query_opt(
+        "SELECT format_type(atttypid, atttypmod) FROM pg_attribute WHERE attrelid=$1::text::regclass AND attname=$2 AND NOT attisdropped",
+        &[&full_table_name, &column],
+    )?;
+    match row {
+        Some(row) => Ok(row.get::<usize, String>(0)),
+        None => anyhow::bail!("Column {column} not found in {full_table_name}"),
+    }
+}
+
+// Dimensions from typmod of types like vector(1536) or halfvec(768)
+fn get_type_dimensions(column_type: &str) -> Option<usize> {
+    let (_, typmod) = column_type.split_once('(')?;
+    typmod.trim_end_matches(')').parse::<usize>().ok()
+}
+
+// Key of the primary key value in rows of hybrid search, it is removed after fusion
+static PK_KEY: &'static str = "_lantern_pk";
+
+struct SearchContext<'a> {
+    args: &'a SearchArgs,
+    full_table_name: &'a str,
+    filter: &'a str,
+    pk: &'a str,
+}
+
+fn query_json_rows(
+    transaction: &mut Transaction,
+    sql: &str,
+    params: &[&(dyn ToSql + Sync)],
+) -> Result<Vec<serde_json::Value>, anyhow::Error> {
+    transaction
+        .query(
+            &format!("SELECT row_to_json(q)::text FROM ({sql}) q"),
+            params,
+        )?
+        .iter()
+        .map(|row| Ok(serde_json::from_str(row.get::<usize, &str>(0))?))
+        .collect()
+}
+
+// Query vector is passed as REAL[] and cast to the column type, so vector and halfvec columns are supported
+fn vector_search(
+    transaction: &mut Transaction,
+    ctx: &SearchContext,
+    operator: &str,
+    column_type: &str,
+    embedding: &Vec<f32>,
+    limit: usize,
+) -> Result<Vec<serde_json::Value>, anyhow::Error> {
+    let query_vector = format!("$1::real[]::{column_type}");
+    query_json_rows(
+        transaction,
+        &format!(
+            "SELECT {select}{pk}, {column} {operator} {query_vector} AS distance
+             FROM {full_table_name}
+             WHERE {column} IS NOT NULL {filter}
+             ORDER BY {column} {operator} {query_vector}
+             LIMIT {limit}",
+            select = ctx.args.select,
+            pk = ctx.pk,
+            column = quote_ident(&ctx.args.column),
+            full_table_name = ctx.full_table_name,
+            filter = ctx.filter,
+        ),
+        &[embedding],
+    )
+}
+
+fn text_search(
+    transaction: &mut Transaction,
+    ctx: &SearchContext,
+    tsvector_column: &str,
+    limit: usize,
+) -> Result<Vec<serde_json::Value>, anyhow::Error> {
+    let tsquery = match &ctx.args.ts_config {
+        Some(_) => "websearch_to_tsquery($2::regconfig, $1)",
+        None => "websearch_to_tsquery($1)",
+    };
+    let sql = format!(
+        "SELECT {select}{pk}, ts_rank({column}, {tsquery}) AS text_rank
+         FROM {full_table_name}
+         WHERE {column} @@ {tsquery} {filter}
+         ORDER BY text_rank DESC
+         LIMIT {limit}",
+        select = ctx.args.select,
+        pk = ctx.pk,
+        column = quote_ident(tsvector_column),
+        full_table_name = ctx.full_table_name,
+        filter = ctx.filter,
+    );
+    match &ctx.args.ts_config {
+        Some(ts_config) => query_json_rows(transaction, &sql, &[&ctx.args.query, ts_config]),
+        None => query_json_rows(transaction, &sql, &[&ctx.args.query]),
+    }
+}
+
+// Scale values to [0, 1], all values are scaled to 1 if they are equal
+fn min_max_normalize(values: &[f64]) -> Vec<f64> {
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|v| {
+            if max > min {
+                (v - min) / (max - min)
+            } else {
+                1.0
+            }
+        })
+        .collect()
+}
+
+// Rows of both lists are matched by primary key and sorted by fused score
+// Vector rows are sorted by distance ascending and text rows by rank descending
+pub fn fuse_results(
+    vector_rows: Vec<serde_json::Value>,
+    text_rows: Vec<serde_json::Value>,
+    fusion: &Fusion,
+    rrf_k: usize,
+    vector_weight: f64,
+    top_k: usize,
+) -> Vec<serde_json::Value> {
+    let vector_scores: Vec<f64> = match fusion {
+        Fusion::Rrf => (0..vector_rows.len())
+            .map(|rank| 1.0 / (rrf_k + rank + 1) as f64)
+            .collect(),
+        // Smaller distance means higher similarity
+        Fusion::Weighted => min_max_normalize(
+            &vector_rows
+                .iter()
+                .map(|row| -row["distance"].as_f64().unwrap_or(0.0))
+                .collect::<Vec<_>>(),
+        )
+        .iter()
+        .map(|score| score * vector_weight)
+        .collect(),
+    };
+    let text_scores: Vec<f64> = match fusion {
+        Fusion::Rrf => (0..text_rows.len())
+            .map(|rank| 1.0 / (rrf_k + rank + 1) as f64)
+            .collect(),
+        Fusion::Weighted => min_max_normalize(
+            &text_rows
+                .iter()
+                .map(|row| row["text_rank"].as_f64().unwrap_or(0.0))
+                .collect::<Vec<_>>(),
+        )
+        .iter()
+        .map(|score| score * (1.0 - vector_weight))
+        .collect(),
+    };
+
+    // Rows are kept in the order they are first seen, so ties are resolved by vector results
+    let mut fused: Vec<(String, serde_json::Value, f64)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let rows = vector_rows
+        .into_iter()
+        .zip(vector_scores)
+        .chain(text_rows.into_iter().zip(text_scores));
+    for (row, score) in rows {
+        let pk = match &row[PK_KEY] {
+            serde_json::Value::String(pk) => pk.clone(),
+            pk => pk.to_string(),
+        };
+        match positions.get(&pk) {
+            Some(&idx) => {
+                let (_, fused_row, fused_score) = &mut fused[idx];
+                if let (Some(fused_row), serde_json::Value::Object(row)) =
+                    (fused_row.as_object_mut(), row)
+                {
+                    for (key, value) in row {
+                        fused_row.entry(key).or_insert(value);
+                    }
+                }
+                *fused_score += score;
+            }
+            None => {
+                positions.insert(pk.clone(), fused.len());
+                fused.push((pk, row, score));
+            }
+        }
+    }
+
+    fused.sort_by(|(_, _, a), (_, _, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    fused
+        .into_iter()
+        .take(top_k)
+        .map(|(_, mut row, score)| {
+            if let Some(row) = row.as_object_mut() {
+                row.remove(PK_KEY);
+                row.insert("score".to_owned(), serde_json::json!(score));
+            }
+            row
+        })
+        .collect()
+}
+
+// Query is embedded with the same runtime and model as the table rows
+// Rows are returned as JSON objects with the selected columns and the distance
+// In hybrid mode full-text results are fused with vector results and the fused score is added
+pub fn search(
+    args: &SearchArgs,
+    logger: Option<Logger>,
+) -> Result<Vec<serde_json::Value>, anyhow::Error> {
+    let logger = logger.unwrap_or(Logger::new("Lantern Search", LogLevel::Debug));
+
+    if args.top_k == 0 {
+        anyhow::bail!("--top-k should be greater than 0");
+    }
+    if !(0.0..=1.0).contains(&args.vector_weight) {
+        anyhow::bail!("--vector-weight should be between 0 and 1");
+    }
+    let operator = get_operator(args)?;
+    let candidates = args.candidates.unwrap_or(args.top_k * 4).max(args.top_k);
+
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let column_type = get_column_type(&mut client, &full_table_name, &args.column)?;
+    if let Some(tsvector_column) = &args.hybrid {
+        let tsvector_type = get_column_type(&mut client, &full_table_name, tsvector_column)?;
+        if tsvector_type != "tsvector" {
+            anyhow::bail!("Column {tsvector_column} should be tsvector, but it is {tsvector_type}");
+        }
+    }
+
+    let embedding = embed_query(
+        &args.runtime,
+        &args.runtime_params,
+        &args.model,
+        &args.query,
+        args.truncate_dim,
+        args.normalize,
+    )?;
+    logger.debug(&format!(
+        "Query embedded with {} dimensions",
+        embedding.len()
+    ));
+
+    if let Some(dimensions) = get_type_dimensions(&column_type) {
+        if dimensions != embedding.len() {
+            anyhow::bail!(
+                "Query embedding has {} dimensions while column {} is {column_type}",
+                embedding.len(),
+                args.column
+            );
+        }
+    }
+
+    let filter = match &args.filter {
+        Some(filter) => format!("AND ({filter})"),
+        None => String::new(),
+    };
+    let pk = match &args.hybrid {
+        Some(_) => format!(", {}::text AS {PK_KEY}", quote_ident(&args.pk)),
+        None => String::new(),
+    };
+    let ctx = SearchContext {
+        args,
+        full_table_name: &full_table_name,
+        filter: &filter,
+        pk: &pk,
+    };
+    let limit = match &args.hybrid {
+        Some(_) => candidates,
+        None => args.top_k,
+    };
+
+    let mut transaction = client.transaction()?;
+    transaction.batch_execute(&format!("SET LOCAL lantern_hnsw.init_k={limit}"))?;
+    if let Some(ef) = args.ef {
+        transaction.batch_execute(&format!(
+            "SET LOCAL lantern_hnsw.ef={ef}; SET LOCAL hnsw.ef_search={ef}"
+        ))?;
+    }
+
+    let vector_rows = vector_search(
+        &mut transaction,
+        &ctx,
+        &operator,
+        &column_type,
+        &embedding,
+        limit,
+    )?;
+
+    let rows = match &args.hybrid {
+        Some(tsvector_column) => {
+            let text_rows = text_search(&mut transaction, &ctx, tsvector_column, limit)?;
+            logger.debug(&format!(
+                "Fusing {} vector and {} full-text results",
+                vector_rows.len(),
+                text_rows.len()
+            ));
+            fuse_results(
+                vector_rows,
+                text_rows,
+                &args.fusion,
+                args.rrf_k,
+                args.vector_weight,
+                args.top_k,
+            )
+        }
+        None => vector_rows,
+    };
+    transaction.commit()?;
+
+    Ok(rows)
+}
+
+pub fn print_search_results(args: &SearchArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Search", LogLevel::Error));
+    for row in search(args, Some(logger.clone()))? {
+        logger.print_raw(&row.to_string());
+    }
+    Ok(())
+}
@@ -0,0 +1,219 @@
+use super::cli::{EmbeddingArgs, SyncEmbeddingArgs};
+use super::{create_embeddings_from_db, get_column_type_sql, CONNECTION_PARAMS};
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use itertools::Itertools;
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::{Client, NoTls};
+use std::collections::HashSet;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+fn get_channel_name(args: &SyncEmbeddingArgs) -> String {
+    args.channel
+        .clone()
+        .unwrap_or(format!("lantern_sync_{}", args.embedding_args.table))
+}
+
+fn get_function_name(args: &EmbeddingArgs) -> String {
+    get_full_table_name(
+        &args.schema,
+        &format!("_lantern_sync_notify_{}", args.table),
+    )
+}
+
+fn get_trigger_name(args: &EmbeddingArgs, operation: &str) -> String {
+    quote_ident(&format!("_lantern_sync_{operation}_{}", args.table))
+}
+
+fn setup_triggers(client: &mut Client, args: &EmbeddingArgs, channel: &str) -> AnyhowVoidResult {
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let column = quote_ident(&args.column);
+    let function_name = get_function_name(args);
+    let insert_trigger_name = get_trigger_name(args, "insert");
+    let update_trigger_name = get_trigger_name(args, "update");
+    let channel = channel.replace('\'', "''");
+
+    client.batch_execute(&format!(
+        "
+        CREATE OR REPLACE FUNCTION {function_name}() RETURNS TRIGGER AS $$
+          BEGIN
+            IF (NEW.{column} IS NOT NULL)
+            THEN
+                PERFORM pg_notify('{channel}', NEW.ctid::TEXT);
+            END IF;
+            RETURN NULL;
+          END;
+        $$ LANGUAGE plpgsql;
+
+        CREATE OR REPLACE TRIGGER {insert_trigger_name}
+        AFTER INSERT
+        ON {full_table_name}
+        FOR EACH ROW
+        EXECUTE PROCEDURE {function_name}();
+
+        CREATE OR REPLACE TRIGGER {update_trigger_name}
+        AFTER UPDATE OF {column}
+        ON {full_table_name}
+        FOR EACH ROW
+        EXECUTE PROCEDURE {function_name}();
+    "
+    ))?;
+
+    Ok(())
+}
+
+fn remove_triggers(client: &mut Client, args: &EmbeddingArgs) -> AnyhowVoidResult {
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let function_name = get_function_name(args);
+    let insert_trigger_name = get_trigger_name(args, "insert");
+    let update_trigger_name = get_trigger_name(args, "update");
+
+    client.batch_execute(&format!(
+        "
+        DROP TRIGGER IF EXISTS {insert_trigger_name} ON {full_table_name};
+        DROP TRIGGER IF EXISTS {update_trigger_name} ON {full_table_name};
+        DROP FUNCTION IF EXISTS {function_name};
+    "
+    ))?;
+
+    Ok(())
+}
+
+fn sync_rows(
+    args: &EmbeddingArgs,
+    row_ids: &HashSet<String>,
+    is_canceled: &Option<Arc<RwLock<bool>>>,
+    logger: &Logger,
+) -> Result<usize, anyhow::Error> {
+    let row_ctids_str = row_ids
+        .iter()
+        .map(|r| format!("'{}'::tid", r.replace('\'', "''")))
+        .join(",");
+    let ctid_filter = format!("ctid IN ({row_ctids_str})");
+
+    let processed_rows = create_embeddings_from_db(
+        EmbeddingArgs {
+            filter: Some(match &args.filter {
+                Some(filter) => format!("({filter}) AND {ctid_filter}"),
+                None => ctid_filter,
+            }),
+            limit: None,
+            only_missing: false,
+            ..args.clone()
+        },
+        false,
+        None,
+        is_canceled.clone(),
+        Some(Logger::new("Lantern Embeddings", logger.level.clone())),
+    )?
+    .processed_rows;
+
+    Ok(processed_rows)
+}
+
+// This function will do the following
+// 1. LISTEN on the notification channel and install insert/update triggers on the source table
+// 2. Generate embeddings for the rows which do not have them yet
+// 3. Collect ctids of inserted/updated rows from notifications and generate embeddings for them
+//    each `sync_interval` seconds until the job is canceled
+pub fn sync_embeddings(
+    args: &SyncEmbeddingArgs,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    logger: Option<Logger>,
+) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Embeddings Sync", LogLevel::Debug));
+    let embedding_args = &args.embedding_args;
+
+    if embedding_args.has_file_output()
+        || embedding_args.has_file_input()
+        || embedding_args.chunks_table.is_some()
+        || embedding_args
+            .out_uri
+            .as_ref()
+            .is_some_and(|uri| uri != &embedding_args.uri)
+        || embedding_args
+            .out_table
+            .as_ref()
+            .is_some_and(|table| table != &embedding_args.table)
+    {
+        anyhow::bail!("Embedding sync can only write to the source table");
+    }
+
+    let uri = append_params_to_uri(&embedding_args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let channel = get_channel_name(args);
+
+    if embedding_args.create_column {
+        // Column should exist before filtering the rows with missing embeddings
+        client.batch_execute(&format!(
+            "ALTER TABLE {full_table_name} ADD COLUMN IF NOT EXISTS {column} {column_type}",
+            full_table_name = get_full_table_name(&embedding_args.schema, &embedding_args.table),
+            column = quote_ident(&embedding_args.out_column),
+            column_type = get_column_type_sql(embedding_args)?
+        ))?;
+    }
+
+    // Start listening before creating triggers, so no changes will be missed
+    client.batch_execute(&format!("LISTEN {}", quote_ident(&channel)))?;
+    setup_triggers(&mut client, embedding_args, &channel)?;
+    logger.info(&format!("Listening for changes on channel {channel}"));
+
+    let processed_rows = create_embeddings_from_db(
+        EmbeddingArgs {
+            only_missing: embedding_args.stale_check.is_none(),
+            ..embedding_args.clone()
+        },
+        false,
+        None,
+        is_canceled.clone(),
+        Some(Logger::new("Lantern Embeddings", logger.level.clone())),
+    )?
+    .processed_rows;
+    logger.info(&format!(
+        "Generated embeddings for {processed_rows} missing rows"
+    ));
+
+    let sync_interval = Duration::from_secs(args.sync_interval);
+    let mut pending_rows: HashSet<String> = HashSet::new();
+    let mut last_sync = Instant::now();
+
+    loop {
+        if let Some(is_canceled) = &is_canceled {
+            if *is_canceled.read().unwrap() {
+                break;
+            }
+        }
+
+        {
+            let mut notifications = client.notifications();
+            let mut iter = notifications.timeout_iter(Duration::from_secs(1));
+            while let Some(notification) = iter.next()? {
+                pending_rows.insert(notification.payload().to_owned());
+                if last_sync.elapsed() >= sync_interval {
+                    break;
+                }
+            }
+        }
+
+        if pending_rows.is_empty() || last_sync.elapsed() < sync_interval {
+            continue;
+        }
+
+        let processed_rows = sync_rows(embedding_args, &pending_rows, &is_canceled, &logger)?;
+        logger.info(&format!(
+            "Synced {processed_rows} rows from {} notifications",
+            pending_rows.len()
+        ));
+        pending_rows.clear();
+        last_sync = Instant::now();
+    }
+
+    if args.drop_triggers {
+        remove_triggers(&mut client, embedding_args)?;
+    }
+
+    logger.info("Embedding sync stopped");
+    Ok(())
+}
@@ -0,0 +1,256 @@
+use super::cli::EmbeddingArgs;
+use super::report::JobReport;
+use super::{run_embedding_pipeline, CONNECTION_PARAMS};
+use crate::logger::Logger;
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+static CHECKPOINT_SCHEMA_NAME: &str = "_lantern_internal";
+static CHECKPOINT_TABLE_NAME: &str = "embedding_tenant_checkpoints";
+
+fn quote_literal(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+fn get_tenants(client: &mut Client, args: &EmbeddingArgs) -> Result<Vec<String>, anyhow::Error> {
+    let rows = if let Some(pattern) = &args.iterate_schemas {
+        client.query(
+            "SELECT nspname::text FROM pg_namespace WHERE nspname LIKE $1 AND to_regclass(quote_ident(nspname) || '.' || quote_ident($2)) IS NOT NULL ORDER BY 1",
+            &[pattern, &args.table],
+        )?
+    } else {
+        let column = quote_ident(args.iterate_by.as_ref().unwrap());
+        let full_table_name = get_full_table_name(&args.schema, &args.table);
+        client.query(
+            &format!("SELECT DISTINCT {column}::text FROM {full_table_name} WHERE {column} IS NOT NULL ORDER BY 1"),
+            &[],
+        )?
+    };
+
+    Ok(rows.iter().map(|row| row.get::<usize, String>(0)).collect())
+}
+
+fn get_job_key(args: &EmbeddingArgs) -> String {
+    format!(
+        "{}.{}.{}.{}.{}",
+        args.schema, args.table, args.column, args.out_column, args.model
+    )
+}
+
+struct Checkpoint {
+    client: Client,
+    name: String,
+    job_key: String,
+    full_table_name: String,
+}
+
+// Completed tenants are stored by checkpoint name and job, so the same checkpoint name
+// can be used for jobs of different tables and columns
+impl Checkpoint {
+    fn new(uri: &str, name: &str, args: &EmbeddingArgs) -> Result<Self, anyhow::Error> {
+        let mut client = Client::connect(uri, NoTls)?;
+        let full_table_name = get_full_table_name(CHECKPOINT_SCHEMA_NAME, CHECKPOINT_TABLE_NAME);
+
+        client.batch_execute(&format!(
+            "
+            CREATE SCHEMA IF NOT EXISTS {CHECKPOINT_SCHEMA_NAME};
+            CREATE TABLE IF NOT EXISTS {full_table_name} (
+                name TEXT NOT NULL,
+                job_key TEXT NOT NULL,
+                tenant TEXT NOT NULL,
+                processed_rows BIGINT NOT NULL,
+                processed_tokens BIGINT NOT NULL,
+                finished_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+                PRIMARY KEY (name, job_key, tenant)
+            );
+        "
+        ))?;
+
+        Ok(Self {
+            client,
+            name: name.to_owned(),
+            job_key: get_job_key(args),
+            full_table_name,
+        })
+    }
+
+    fn get_completed(&mut self) -> Result<Vec<String>, anyhow::Error> {
+        let rows = self.client.query(
+            &format!(
+                "SELECT tenant FROM {} WHERE name = $1 AND job_key = $2",
+                self.full_table_name
+            ),
+            &[&self.name, &self.job_key],
+        )?;
+
+        Ok(rows.iter().map(|row| row.get::<usize, String>(0)).collect())
+    }
+
+    fn set_completed(
+        &mut self,
+        tenant: &str,
+        processed_rows: usize,
+        processed_tokens: usize,
+    ) -> AnyhowVoidResult {
+        self.client.execute(
+            &format!(
+                "INSERT INTO {} (name, job_key, tenant, processed_rows, processed_tokens) VALUES ($1, $2, $3, $4, $5)
+                 ON CONFLICT (name, job_key, tenant) DO UPDATE SET processed_rows = EXCLUDED.processed_rows, processed_tokens = EXCLUDED.processed_tokens, finished_at = now()",
+                self.full_table_name
+            ),
+            &[
+                &self.name,
+                &self.job_key,
+                &tenant,
+                &(processed_rows as i64),
+                &(processed_tokens as i64),
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+// Get arguments of the job for a single tenant
+fn get_tenant_args(args: &EmbeddingArgs, tenant: &str) -> EmbeddingArgs {
+    let mut tenant_args = EmbeddingArgs {
+        iterate_by: None,
+        iterate_schemas: None,
+        tenant_checkpoint: None,
+        ..args.clone()
+    };
+
+    if args.iterate_schemas.is_some() {
+        tenant_args.schema = tenant.to_owned();
+    } else {
+        let tenant_filter = format!(
+            "{}::text = {}",
+            quote_ident(args.iterate_by.as_ref().unwrap()),
+            quote_literal(tenant)
+        );
+        tenant_args.filter = Some(match &args.filter {
+            Some(filter) => format!("({filter}) AND {tenant_filter}"),
+            None => format!(
+                "{} IS NOT NULL AND {tenant_filter}",
+                quote_ident(&args.column)
+            ),
+        });
+    }
+
+    tenant_args
+}
+
+// Run the pipeline for each tenant one by one
+// Each tenant is committed separately, so a failure in one tenant does not roll back the others
+pub fn create_embeddings_per_tenant(
+    args: EmbeddingArgs,
+    track_progress: bool,
+    progress_cb: Option<ProgressEventCbFn>,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    logger: Arc<Logger>,
+) -> Result<JobReport, anyhow::Error> {
+    if args.chunks_table.is_some()
+        || args.has_file_output()
+        || args.has_file_input()
+        || args.emit_migration.is_some()
+    {
+        anyhow::bail!("--iterate-by and --iterate-schemas can not be used with --chunks-table, input or output files or --emit-migration");
+    }
+
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let tenants = get_tenants(&mut client, &args)?;
+
+    let mut checkpoint = match &args.tenant_checkpoint {
+        Some(name) => Some(Checkpoint::new(&uri, name, &args)?),
+        None => None,
+    };
+    let completed = match checkpoint.as_mut() {
+        Some(checkpoint) => checkpoint.get_completed()?,
+        None => Vec::new(),
+    };
+
+    let tenants: Vec<String> = tenants
+        .into_iter()
+        .filter(|tenant| !completed.contains(tenant))
+        .collect();
+
+    logger.info(&format!(
+        "Found {} tenants to process, {} completed in checkpoint",
+        tenants.len(),
+        completed.len()
+    ));
+
+    // Dry runs do not write the embeddings, so the tenants are not marked as completed
+    let is_dry_run = args.dry_run || args.dry_run_cost;
+    let progress_cb = progress_cb.map(Arc::new);
+    let tenant_count = tenants.len();
+    let start = Instant::now();
+    let mut job_report = JobReport::default();
+    let mut failed_tenants = Vec::new();
+
+    for (idx, tenant) in tenants.iter().enumerate() {
+        if let Some(is_canceled) = &is_canceled {
+            if *is_canceled.read().unwrap() {
+                anyhow::bail!(JOB_CANCELLED_MESSAGE);
+            }
+        }
+
+        let tenant_logger = Logger::new(
+            &format!("{} [{tenant}]", logger.label),
+            logger.level.clone(),
+        );
+
+        // Overall progress is reported as the share of processed tenants
+        // Rows, tokens and ETA are reported for the current tenant
+        let tenant_progress_cb: Option<ProgressEventCbFn> = progress_cb.clone().map(|cb| {
+            Box::new(move |progress: &ProgressEvent| {
+                cb(&ProgressEvent {
+                    percent: ((idx * 100 + progress.percent as usize) / tenant_count) as u8,
+                    ..progress.clone()
+                })
+            }) as ProgressEventCbFn
+        });
+
+        match run_embedding_pipeline(
+            get_tenant_args(&args, tenant),
+            track_progress,
+            tenant_progress_cb,
+            is_canceled.clone(),
+            None,
+            Some(tenant_logger),
+            None,
+            None,
+        ) {
+            Ok(report) => {
+                let (rows, tokens) = (report.processed_rows, report.processed_tokens);
+                job_report.merge(report);
+                logger.info(&format!(
+                    "Tenant {tenant} completed ({}/{tenant_count}), processed rows: {rows}",
+                    idx + 1
+                ));
+                if let (Some(checkpoint), false) = (checkpoint.as_mut(), is_dry_run) {
+                    checkpoint.set_completed(tenant, rows, tokens)?;
+                }
+            }
+            Err(e) => {
+                logger.error(&format!("Tenant {tenant} failed: {e}"));
+                failed_tenants.push(tenant.clone());
+            }
+        }
+    }
+
+    if !failed_tenants.is_empty() {
+        anyhow::bail!(
+            "Job failed for {} of {tenant_count} tenants: {}",
+            failed_tenants.len(),
+            failed_tenants.join(", ")
+        );
+    }
+
+    // Wall time includes the checkpoint updates between the tenants
+    job_report.wall_time = start.elapsed();
+    Ok(job_report)
+}
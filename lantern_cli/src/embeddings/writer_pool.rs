@@ -0,0 +1,92 @@
+use super::exporter::Exporter;
+use super::EmbeddingRecord;
+use crate::types::*;
+use std::sync::mpsc::{self, SyncSender};
+use std::thread::JoinHandle;
+
+// Batches waiting for each writer, so a slow writer does not buffer the whole table
+static WRITER_QUEUE_SIZE: usize = 2;
+
+// None is sent after the last batch, writers which are dropped without it do not commit
+// the remaining rows, as exporters are not finished when the job fails
+struct Writer {
+    tx: SyncSender<Option<Vec<EmbeddingRecord>>>,
+    handle: JoinHandle<AnyhowVoidResult>,
+}
+
+// Shards embedded batches between writers running in separate threads
+// Each writer has its own connection and temporary table, and rows of different batches
+// do not overlap, so writers update the destination table independently
+pub struct WriterPool {
+    exporters: Vec<Box<dyn Exporter>>,
+    writers: Vec<Writer>,
+    next_writer: usize,
+}
+
+impl WriterPool {
+    pub fn new(exporters: Vec<Box<dyn Exporter>>) -> Self {
+        WriterPool {
+            exporters,
+            writers: Vec::new(),
+            next_writer: 0,
+        }
+    }
+
+    // Error of a failed writer is returned from its thread
+    fn join_writer(writer: Writer) -> AnyhowVoidResult {
+        let _ = writer.tx.send(None);
+        drop(writer.tx);
+        match writer.handle.join() {
+            Ok(res) => res,
+            Err(e) => anyhow::bail!("Writer thread panicked: {:?}", e),
+        }
+    }
+}
+
+impl Exporter for WriterPool {
+    fn begin(&mut self) -> AnyhowVoidResult {
+        // Destination columns are created by the first writer, so writers are started one by one
+        for mut exporter in self.exporters.drain(..) {
+            exporter.begin()?;
+            let (tx, rx) = mpsc::sync_channel(WRITER_QUEUE_SIZE);
+            let parent_span = tracing::Span::current();
+            let handle = std::thread::spawn(move || {
+                let _span = tracing::info_span!(parent: &parent_span, "writer").entered();
+                while let Ok(rows) = rx.recv() {
+                    match rows {
+                        Some(rows) => exporter.write_batch(rows)?,
+                        None => return exporter.finish(),
+                    }
+                }
+                Ok(())
+            });
+            self.writers.push(Writer { tx, handle });
+        }
+        Ok(())
+    }
+
+    fn write_batch(&mut self, rows: Vec<EmbeddingRecord>) -> AnyhowVoidResult {
+        let writer_idx = self.next_writer;
+        self.next_writer = (self.next_writer + 1) % self.writers.len();
+        if self.writers[writer_idx].tx.send(Some(rows)).is_err() {
+            // Receiver is dropped only if the writer has failed
+            let writer = self.writers.remove(writer_idx);
+            Self::join_writer(writer)?;
+            anyhow::bail!("Writer {writer_idx} stopped unexpectedly");
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> AnyhowVoidResult {
+        // All writers are joined, so their windows are committed before the error is returned
+        let mut result = Ok(());
+        for writer in self.writers.drain(..) {
+            if let Err(e) = Self::join_writer(writer) {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        result
+    }
+}
@@ -166,4 +166,12 @@ pub struct CreateIndexArgs {
     /// Index name to use when imporrting index to database
     #[arg(long)]
     pub index_name: Option<String>,
+
+    /// Number of threads to build the index with, defaults to the number of CPU cores
+    #[arg(long)]
+    pub threads: Option<usize>,
+
+    /// Stream vectors with binary COPY instead of fetching them from a cursor
+    #[arg(long, default_value_t = false)]
+    pub copy: bool,
 }
@@ -13,7 +13,10 @@ use usearch::Index;
 use crate::logger::{LogLevel, Logger};
 use crate::types::*;
 use crate::utils::{get_full_table_name, quote_ident};
-use postgres::{Client, NoTls, Row};
+use postgres::binary_copy::BinaryCopyOutIter;
+use postgres::fallible_iterator::FallibleIterator;
+use postgres::types::Type;
+use postgres::{Client, NoTls};
 use postgres_large_objects::LargeObject;
 use postgres_types::FromSql;
 
@@ -24,6 +27,9 @@ pub mod utils;
 // Used to control chunk size when copying index file to postgres server
 static COPY_BUFFER_CHUNK_SIZE: usize = 1024 * 1024 * 10; // 10MB
 
+// Number of vectors sent to worker threads at once
+static FETCH_CHUNK_SIZE: usize = 2000;
+
 #[derive(Debug)]
 struct Tid {
     label: u64,
@@ -60,11 +66,9 @@ impl<'a> FromSql<'a> for Tid {
     }
 }
 
-fn index_chunk(rows: Vec<Row>, index: Arc<ThreadSafeIndex>) -> AnyhowVoidResult {
-    for row in rows {
-        let ctid: Tid = row.get(0);
-        let vec: Vec<f32> = row.get(1);
-        index.add(ctid.label, &vec)?;
+fn index_chunk(rows: Vec<(u64, Vec<f32>)>, index: Arc<ThreadSafeIndex>) -> AnyhowVoidResult {
+    for (label, vec) in rows {
+        index.add(label, &vec)?;
     }
     Ok(())
 }
@@ -106,6 +110,11 @@ pub fn create_usearch_index(
     let total_start_time = Instant::now();
     logger.info(&format!("Number of available CPU cores: {}", num_cores));
 
+    let num_threads = args.threads.unwrap_or(num_cores);
+    if num_threads == 0 {
+        anyhow::bail!("--threads should be greater than 0");
+    }
+
     // get all row count
     let mut client = Client::connect(&args.uri, NoTls)?;
     let mut transaction = client.transaction()?;
@@ -210,7 +219,8 @@ pub fn create_usearch_index(
         expansion_add: args.efc,
         expansion_search: args.ef,
 
-        num_threads: 0, // automatic
+        // each worker thread needs its own usearch thread context
+        num_threads,
 
         // note: pq_construction and pq_output distinction is not yet implemented in usearch
         // in the future, if pq_construction is false, we will use full vectors in memory (and
@@ -252,7 +262,10 @@ pub fn create_usearch_index(
     // Create a vector to store thread handles
     let mut handles = vec![];
 
-    let (tx, rx): (SyncSender<Vec<Row>>, Receiver<Vec<Row>>) = mpsc::sync_channel(num_cores);
+    let (tx, rx): (
+        SyncSender<Vec<(u64, Vec<f32>)>>,
+        Receiver<Vec<(u64, Vec<f32>)>>,
+    ) = mpsc::sync_channel(num_threads);
     let rx_arc = Arc::new(Mutex::new(rx));
     let is_canceled = is_canceled.unwrap_or(Arc::new(RwLock::new(false)));
     let (progress_tx, progress_rx): (Sender<u8>, Receiver<u8>) = mpsc::channel();
@@ -276,7 +289,8 @@ pub fn create_usearch_index(
     });
 
     let processed_cnt = Arc::new(AtomicU64::new(0));
-    for _ in 0..num_cores {
+    logger.info(&format!("Building index with {num_threads} threads"));
+    for _ in 0..num_threads {
         // spawn thread
         let index_ref = index_arc.clone();
         let receiver = rx_arc.clone();
@@ -327,28 +341,58 @@ pub fn create_usearch_index(
         handles.push(handle);
     }
 
-    // With portal we can execute a query and poll values from it in chunks
-    let portal = transaction.bind(
-        &format!(
-            "SELECT ctid, {col} FROM {table} WHERE {col} IS NOT NULL;",
-            col = quote_ident(&args.column),
-            table = get_full_table_name(&args.schema, &args.table)
-        ),
-        &[],
-    )?;
+    let select_query = format!(
+        "SELECT ctid, {col} FROM {table} WHERE {col} IS NOT NULL",
+        col = quote_ident(&args.column),
+        table = get_full_table_name(&args.schema, &args.table)
+    );
+
+    if args.copy {
+        // Binary COPY streams all rows in one response without round trips for each chunk
+        let reader =
+            transaction.copy_out(&format!("COPY ({select_query}) TO STDOUT (FORMAT binary)"))?;
+        let mut copy_rows = BinaryCopyOutIter::new(reader, &[Type::TID, Type::FLOAT4_ARRAY]);
+        let mut rows = Vec::with_capacity(FETCH_CHUNK_SIZE);
+
+        while let Some(row) = copy_rows.next()? {
+            let ctid: Tid = row.try_get(0)?;
+            rows.push((ctid.label, row.try_get::<Vec<f32>>(1)?));
+            if rows.len() < FETCH_CHUNK_SIZE {
+                continue;
+            }
+            if *is_canceled.read().unwrap() {
+                anyhow::bail!(JOB_CANCELLED_MESSAGE);
+            }
+            tx.send(std::mem::replace(
+                &mut rows,
+                Vec::with_capacity(FETCH_CHUNK_SIZE),
+            ))?;
+        }
 
-    loop {
-        // poll 2000 rows from portal and send it to worker threads via channel
-        let rows = transaction.query_portal(&portal, 2000)?;
-        if rows.len() == 0 {
-            break;
+        if rows.len() > 0 {
+            tx.send(rows)?;
         }
-        if *is_canceled.read().unwrap() {
-            // This variable will be changed from outside to gracefully
-            // exit job on next chunk
-            anyhow::bail!(JOB_CANCELLED_MESSAGE);
+    } else {
+        // With portal we can execute a query and poll values from it in chunks
+        let portal = transaction.bind(&select_query, &[])?;
+
+        loop {
+            // poll rows from portal and send it to worker threads via channel
+            let rows = transaction.query_portal(&portal, FETCH_CHUNK_SIZE as i32)?;
+            if rows.len() == 0 {
+                break;
+            }
+            if *is_canceled.read().unwrap() {
+                // This variable will be changed from outside to gracefully
+                // exit job on next chunk
+                anyhow::bail!(JOB_CANCELLED_MESSAGE);
+            }
+            let rows = rows
+                .iter()
+                .map(|row| (row.get::<usize, Tid>(0).label, row.get(1)))
+                .collect();
+            tx.send(rows)?;
         }
-        tx.send(rows)?;
     }
 
     // Exit all channels
@@ -374,7 +418,6 @@ pub fn create_usearch_index(
     ));
 
     drop(index_arc);
-    drop(portal);
     drop(rx_arc);
 
     if args.import {
@@ -26,4 +26,24 @@ pub struct HttpServerArgs {
     /// Password for HTTP Auth
     #[arg(short, long)]
     pub password: Option<String>,
+
+    /// Serve /jobs endpoints and create the embedding jobs table if it does not exist
+    #[arg(long, default_value_t = false)]
+    pub enable_jobs: bool,
+
+    /// Schema of the embedding jobs table used by /jobs endpoints
+    #[arg(long, default_value = "public")]
+    pub jobs_schema: String,
+
+    /// Embedding jobs table used by /jobs endpoints
+    #[arg(long, default_value = "embedding_jobs")]
+    pub jobs_table: String,
+
+    /// Number of job runners started with the server. Keep 0 if the jobs are run by separate `jobs run` processes
+    #[arg(long, default_value_t = 0, requires = "enable_jobs")]
+    pub job_runners: usize,
+
+    /// Allow jobs submitted to /jobs to pass `db_uri` of other databases than the server database
+    #[arg(long, default_value_t = false, requires = "enable_jobs")]
+    pub allow_job_db_uri: bool,
 }
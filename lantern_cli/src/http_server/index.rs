@@ -123,6 +123,8 @@ async fn create_index(
                     schema: "public".to_owned(),
                     table: name.clone(),
                     pq,
+                    threads: None,
+                    copy: false,
                     remote_database: data.is_remote_database,
                 },
                 None,
@@ -0,0 +1,326 @@
+use actix_web::{
+    error::{ErrorBadRequest, ErrorConflict, ErrorInternalServerError, ErrorNotFound},
+    get, post, web, Responder, Result,
+};
+use serde::{Deserialize, Serialize};
+use tokio_postgres::Row;
+
+use crate::embeddings::{cli::Runtime, get_model_listings, get_runtime_listings};
+
+use super::AppState;
+
+static JOB_COLUMNS: &str = "id, \"schema\", \"table\", pk, src_column, dst_column, embedding_model, runtime, status, progress, processed_rows, processed_tokens, error, attempts, runner, created_at::text, started_at::text, finished_at::text";
+
+// Connection string of the job is not returned, as it may contain the password
+#[derive(Serialize, Debug, utoipa::ToSchema)]
+pub struct JobInfo {
+    id: i32,
+    schema: String,
+    table: String,
+    pk: String,
+    column: String,
+    out_column: String,
+    model: String,
+    runtime: String,
+    status: String,
+    progress: i16,
+    processed_rows: Option<i64>,
+    processed_tokens: Option<i64>,
+    error: Option<String>,
+    attempts: i32,
+    runner: Option<String>,
+    created_at: String,
+    started_at: Option<String>,
+    finished_at: Option<String>,
+}
+
+impl From<&Row> for JobInfo {
+    fn from(row: &Row) -> Self {
+        JobInfo {
+            id: row.get(0),
+            schema: row.get(1),
+            table: row.get(2),
+            pk: row.get(3),
+            column: row.get(4),
+            out_column: row.get(5),
+            model: row.get(6),
+            runtime: row.get(7),
+            status: row.get(8),
+            progress: row.get(9),
+            processed_rows: row.get(10),
+            processed_tokens: row.get(11),
+            error: row.get(12),
+            attempts: row.get(13),
+            runner: row.get(14),
+            created_at: row.get(15),
+            started_at: row.get(16),
+            finished_at: row.get(17),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, utoipa::ToSchema)]
+pub struct CreateJobInput {
+    table: String,
+    schema: Option<String>,
+    pk: Option<String>,
+    column: String,
+    out_column: String,
+    model: String,
+    runtime: Option<String>,
+    #[schema(value_type = Object)]
+    runtime_params: Option<serde_json::Value>,
+    batch_size: Option<i32>,
+    filter: Option<String>,
+    db_uri: Option<String>,
+}
+
+/// Submit embedding job
+///
+/// The job is added to the jobs queue and run by the job runners of the server or by `lantern-cli jobs run`
+///
+/// If `db_uri` is not passed the embeddings are generated for the table in the server database
+/// Other databases can be used only if the server is started with `--allow-job-db-uri`
+#[utoipa::path(
+    post,
+    path = "/jobs",
+    request_body (
+        content = CreateJobInput,
+        example = json!(r#"{ "table": "articles", "column": "content", "out_column": "embedding", "model": "BAAI/bge-small-en", "runtime": "ort" }"#)
+    ),
+    responses(
+        (status = 200, description = "Returns the queued job", body = JobInfo),
+        (status = 400, description = "Bad request"),
+        (status = 500, description = "Internal Server Error")
+    )
+)]
+#[post("/jobs")]
+pub async fn create(
+    data: web::Data<AppState>,
+    body: web::Json<CreateJobInput>,
+) -> Result<impl Responder> {
+    let runtime = body.runtime.clone().unwrap_or("ort".to_owned());
+    Runtime::try_from(runtime.as_str()).map_err(ErrorBadRequest)?;
+    if body.batch_size.is_some_and(|batch_size| batch_size <= 0) {
+        return Err(ErrorBadRequest("batch_size should be greater than 0"));
+    }
+    // Runners connect to the passed database, so by default jobs are limited to the server database
+    if body
+        .db_uri
+        .as_ref()
+        .is_some_and(|db_uri| db_uri != &data.db_uri && !data.allow_job_db_uri)
+    {
+        return Err(ErrorBadRequest(
+            "db_uri should be the server database, start the server with --allow-job-db-uri to use other databases",
+        ));
+    }
+    let runtime_params = body
+        .runtime_params
+        .clone()
+        .unwrap_or(serde_json::json!({}))
+        .to_string();
+
+    let client = data.pool.get().await?;
+    let row = client
+        .query_one(
+            &format!(
+                "INSERT INTO {jobs_table} (db_connection, \"schema\", \"table\", pk, src_column, dst_column, embedding_model, runtime, runtime_params, batch_size, filter)
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9::text::jsonb, $10, $11) RETURNING {JOB_COLUMNS}",
+                jobs_table = data.jobs_table
+            ),
+            &[
+                &body.db_uri,
+                &body.schema.as_deref().unwrap_or("public"),
+                &body.table,
+                &body.pk.as_deref().unwrap_or("id"),
+                &body.column,
+                &body.out_column,
+                &body.model,
+                &runtime,
+                &runtime_params,
+                &body.batch_size,
+                &body.filter,
+            ],
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    Ok(web::Json(JobInfo::from(&row)))
+}
+
+#[derive(Deserialize, Debug, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListJobsQuery {
+    status: Option<String>,
+}
+
+/// Get all jobs
+///
+/// Pass `status` to get only the jobs with the status, one of queued, running, succeeded, failed or canceled
+#[utoipa::path(
+    get,
+    path = "/jobs",
+    params(ListJobsQuery),
+    responses(
+        (status = 200, description = "Returns the jobs ordered by id", body = Vec<JobInfo>),
+        (status = 500, description = "Internal Server Error")
+    )
+)]
+#[get("/jobs")]
+pub async fn list(
+    data: web::Data<AppState>,
+    query: web::Query<ListJobsQuery>,
+) -> Result<impl Responder> {
+    let client = data.pool.get().await?;
+    let rows = client
+        .query(
+            &format!(
+                "SELECT {JOB_COLUMNS} FROM {jobs_table} WHERE $1::text IS NULL OR status=$1 ORDER BY id",
+                jobs_table = data.jobs_table
+            ),
+            &[&query.status],
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    let jobs: Vec<JobInfo> = rows.iter().map(JobInfo::from).collect();
+    Ok(web::Json(jobs))
+}
+
+/// Get job status and progress by id
+#[utoipa::path(
+    get,
+    path = "/jobs/{id}",
+    responses(
+        (status = 200, description = "Returns the job", body = JobInfo),
+        (status = 404, description = "Job not found"),
+        (status = 500, description = "Internal Server Error")
+    ),
+    params(
+       ("id", description = "Job id")
+    ),
+)]
+#[get("/jobs/{id}")]
+pub async fn get(data: web::Data<AppState>, id: web::Path<i32>) -> Result<impl Responder> {
+    let client = data.pool.get().await?;
+    let row = client
+        .query_opt(
+            &format!(
+                "SELECT {JOB_COLUMNS} FROM {jobs_table} WHERE id=$1",
+                jobs_table = data.jobs_table
+            ),
+            &[&id.into_inner()],
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    match row {
+        Some(row) => Ok(web::Json(JobInfo::from(&row))),
+        None => Err(ErrorNotFound("Job not found")),
+    }
+}
+
+/// Cancel queued or running job
+///
+/// Running job is stopped after the current batch, the embeddings which were already generated are kept
+#[utoipa::path(
+    post,
+    path = "/jobs/{id}/cancel",
+    responses(
+        (status = 200, description = "Returns the canceled job", body = JobInfo),
+        (status = 404, description = "Job not found"),
+        (status = 409, description = "Job is already finished"),
+        (status = 500, description = "Internal Server Error")
+    ),
+    params(
+       ("id", description = "Job id")
+    ),
+)]
+#[post("/jobs/{id}/cancel")]
+pub async fn cancel(data: web::Data<AppState>, id: web::Path<i32>) -> Result<impl Responder> {
+    let id = id.into_inner();
+    let client = data.pool.get().await?;
+    // Runner of the running job sets finished_at when the job stops
+    let row = client
+        .query_opt(
+            &format!(
+                "UPDATE {jobs_table} SET status='canceled', finished_at=CASE WHEN status='queued' THEN now() ELSE finished_at END
+                 WHERE id=$1 AND status IN ('queued', 'running') RETURNING {JOB_COLUMNS}",
+                jobs_table = data.jobs_table
+            ),
+            &[&id],
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    if let Some(row) = row {
+        return Ok(web::Json(JobInfo::from(&row)));
+    }
+
+    let status = client
+        .query_opt(
+            &format!(
+                "SELECT status FROM {jobs_table} WHERE id=$1",
+                jobs_table = data.jobs_table
+            ),
+            &[&id],
+        )
+        .await
+        .map_err(ErrorInternalServerError)?;
+
+    match status {
+        Some(row) => Err(ErrorConflict(format!(
+            "Job is already {}",
+            row.get::<usize, String>(0)
+        ))),
+        None => Err(ErrorNotFound("Job not found")),
+    }
+}
+
+#[derive(Deserialize, Debug, utoipa::IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ListModelsQuery {
+    runtime: Option<String>,
+    runtime_params: Option<String>,
+}
+
+/// Get models of the runtime
+///
+/// `runtime` defaults to ort, `runtime_params` is a JSON object with the runtime options, e.g. the API token
+#[utoipa::path(
+    get,
+    path = "/models",
+    params(ListModelsQuery),
+    responses(
+        (status = 200, description = "Returns the models with their dimensions and default batch size"),
+        (status = 400, description = "Bad request"),
+        (status = 500, description = "Internal Server Error")
+    )
+)]
+#[get("/models")]
+pub async fn list_models(query: web::Query<ListModelsQuery>) -> Result<impl Responder> {
+    let runtime =
+        Runtime::try_from(query.runtime.as_deref().unwrap_or("ort")).map_err(ErrorBadRequest)?;
+    let runtime_params = query.runtime_params.clone().unwrap_or("{}".to_owned());
+
+    // Runtimes may read model files when they are created
+    let models = tokio::task::spawn_blocking(move || get_model_listings(&runtime, &runtime_params))
+        .await
+        .map_err(ErrorInternalServerError)?
+        .map_err(ErrorBadRequest)?;
+
+    Ok(web::Json(models))
+}
+
+/// Get available runtimes
+#[utoipa::path(
+    get,
+    path = "/runtimes",
+    responses(
+        (status = 200, description = "Returns the runtimes and if they require an API key"),
+    )
+)]
+#[get("/runtimes")]
+pub async fn list_runtimes() -> Result<impl Responder> {
+    Ok(web::Json(get_runtime_listings()))
+}
@@ -1,3 +1,5 @@
+use crate::jobs::cli::{JobsRunnerArgs, JobsTableArgs};
+use crate::utils::get_full_table_name;
 use crate::{logger::LogLevel, types::AnyhowVoidResult};
 use actix_web::{
     dev::ServiceRequest,
@@ -15,6 +17,7 @@ use utoipa_swagger_ui::SwaggerUi;
 pub mod cli;
 mod collection;
 mod index;
+mod jobs;
 mod pq;
 mod search;
 mod setup;
@@ -52,6 +55,8 @@ pub struct AppState {
     auth_credentials: Option<AuthCredentials>,
     pool: AppPool,
     is_remote_database: bool,
+    jobs_table: String,
+    allow_job_db_uri: bool,
     #[allow(dead_code)]
     logger: crate::logger::Logger,
 }
@@ -91,6 +96,12 @@ The API endpoints are not SQL injection safe, so it can provide maximum flexibil
         index::create_index,
         index::delete_index,
         pq::quantize_table,
+        jobs::create,
+        jobs::list,
+        jobs::get,
+        jobs::cancel,
+        jobs::list_models,
+        jobs::list_runtimes,
     ),
     components(schemas(
         collection::CollectionInfo,
@@ -99,7 +110,9 @@ The API endpoints are not SQL injection safe, so it can provide maximum flexibil
         search::SearchInput,
         search::SearchResponse,
         index::CreateIndexInput,
-        pq::CreatePQInput
+        pq::CreatePQInput,
+        jobs::CreateJobInput,
+        jobs::JobInfo
     ))
 )]
 pub struct ApiDoc;
@@ -127,6 +140,37 @@ pub async fn start(
 
     setup::setup_tables(&pool).await?;
 
+    // Jobs submitted to /jobs are stored in the jobs table and claimed by the runners
+    // started here or by `jobs run` processes on other hosts
+    if args.enable_jobs {
+        let jobs_table_args = JobsTableArgs {
+            uri: args.db_uri.clone(),
+            schema: args.jobs_schema.clone(),
+            table: args.jobs_table.clone(),
+        };
+        let jobs_logger = logger.clone();
+        tokio::task::spawn_blocking(move || {
+            crate::jobs::create_jobs_table(&jobs_table_args, Some(jobs_logger))
+        })
+        .await??;
+    }
+    for runner_id in 0..args.job_runners {
+        let runner_args = JobsRunnerArgs {
+            uri: args.db_uri.clone(),
+            schema: args.jobs_schema.clone(),
+            table: args.jobs_table.clone(),
+            runner_name: Some(format!("http-server-{}-{runner_id}", std::process::id())),
+            poll_interval: 1,
+            once: false,
+        };
+        let runner_logger = logger.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = crate::jobs::run_jobs(&runner_args, None, Some(runner_logger.clone())) {
+                runner_logger.error(&format!("Job runner stopped: {e}"));
+            }
+        });
+    }
+
     let auth_credentials = if args.username.is_some() && args.password.is_some() {
         Some(AuthCredentials {
             username: args.username.clone().unwrap(),
@@ -140,10 +184,13 @@ pub async fn start(
         auth_credentials,
         db_uri: args.db_uri.clone(),
         is_remote_database: args.remote_database,
+        jobs_table: get_full_table_name(&args.jobs_schema, &args.jobs_table),
+        allow_job_db_uri: args.allow_job_db_uri,
         pool: AppPool::new(pool),
         logger,
     });
 
+    let enable_jobs = args.enable_jobs;
     HttpServer::new(move || {
         App::new()
             .wrap(Logger::new("%r - %s %Dms"))
@@ -167,6 +214,16 @@ pub async fn start(
             .service(index::create_index)
             .service(index::delete_index)
             .service(pq::quantize_table)
+            .service(jobs::list_models)
+            .service(jobs::list_runtimes)
+            .configure(|cfg| {
+                if enable_jobs {
+                    cfg.service(jobs::create)
+                        .service(jobs::list)
+                        .service(jobs::get)
+                        .service(jobs::cancel);
+                }
+            })
     })
     .bind((args.host.clone(), args.port))?
     .run()
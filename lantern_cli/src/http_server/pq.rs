@@ -4,7 +4,7 @@ use actix_web::{
     post, web, HttpResponse, Responder, Result,
 };
 
-use crate::pq::cli::PQArgs;
+use crate::pq::cli::{Accel, KmeansInit, PQArgs};
 
 use serde::Deserialize;
 
@@ -84,6 +84,16 @@ async fn quantize_table(
                 codebook_table_name: None,
                 dataset_limit,
                 subvector_id: None,
+                opq: false,
+                opq_iterations: 4,
+                coarse_clusters: None,
+                coarse_centroids_table: None,
+                import_codebook: None,
+                kmeans_init: KmeansInit::Kmeanspp,
+                kmeans_iters: 20,
+                kmeans_tolerance: 0.1,
+                accel: Accel::Simd,
+                kmeans_batch_size: None,
                 overwrite: true,
                 skip_table_setup: false,
                 skip_vector_quantization: false,
@@ -91,6 +101,7 @@ async fn quantize_table(
                 total_task_count: None,
                 parallel_task_count: None,
                 quantization_task_id: None,
+                watch: false,
                 run_on_gcp: false,
                 gcp_cli_image_tag: None,
                 gcp_project: None,
@@ -446,6 +446,8 @@ pub fn autotune_index(
                     index_name: Some(index_name.clone()),
                     remote_database: true,
                     pq: false,
+                    threads: None,
+                    copy: false,
                 },
                 None,
                 Some(is_canceled.clone()),
@@ -532,6 +534,8 @@ pub fn autotune_index(
                 index_name: None,
                 remote_database: true,
                 pq: false,
+                threads: None,
+                copy: false,
             },
             None,
             Some(is_canceled.clone()),
@@ -0,0 +1,60 @@
+use clap::{Parser, Subcommand};
+
+#[derive(Subcommand, Debug)]
+pub enum JobsCommands {
+    /// Create embedding jobs table
+    Init(JobsTableArgs),
+    /// Claim and run queued embedding jobs
+    Run(JobsRunnerArgs),
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct JobsArgs {
+    #[command(subcommand)]
+    pub command: JobsCommands,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct JobsTableArgs {
+    /// Fully associated database connection string including db name to store jobs
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Schema name of jobs table
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Jobs table name
+    #[arg(short, long, default_value = "embedding_jobs")]
+    pub table: String,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct JobsRunnerArgs {
+    /// Fully associated database connection string including db name to get jobs
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Schema name of jobs table
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Jobs table name
+    #[arg(short, long, default_value = "embedding_jobs")]
+    pub table: String,
+
+    /// Runner name stored in claimed jobs (defaults to hostname and process id)
+    #[arg(long)]
+    pub runner_name: Option<String>,
+
+    /// Interval in seconds to check for new jobs when the queue is empty
+    #[arg(long, default_value_t = 5)]
+    pub poll_interval: u64,
+
+    /// Exit when there are no more queued jobs
+    #[arg(long, default_value_t = false)]
+    pub once: bool,
+}
@@ -0,0 +1,277 @@
+use crate::embeddings;
+use crate::embeddings::cli::{EmbeddingArgs, Runtime};
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name};
+use postgres::{Client, NoTls, Row};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+pub mod cli;
+
+static CONNECTION_PARAMS: &'static str = "connect_timeout=10";
+static CANCEL_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+
+/*
+    Jobs Table has the following structure:
+    CREATE TABLE "public"."embedding_jobs" (
+        "id" SERIAL PRIMARY KEY,
+        "db_connection" text,
+        "schema" text NOT NULL DEFAULT 'public',
+        "table" text NOT NULL,
+        "pk" text NOT NULL DEFAULT 'id',
+        "src_column" text NOT NULL,
+        "dst_column" text NOT NULL,
+        "embedding_model" text NOT NULL,
+        "runtime" text NOT NULL DEFAULT 'ort',
+        "runtime_params" jsonb NOT NULL DEFAULT '{}',
+        "batch_size" int,
+        "filter" text,
+        "status" text NOT NULL DEFAULT 'queued',
+        "progress" int2 NOT NULL DEFAULT 0,
+        "processed_rows" bigint,
+        "processed_tokens" bigint,
+        "error" text,
+        "attempts" int NOT NULL DEFAULT 0,
+        "runner" text,
+        "created_at" timestamptz NOT NULL DEFAULT now(),
+        "started_at" timestamptz,
+        "finished_at" timestamptz
+    );
+    Job status is one of: queued, running, succeeded, failed, canceled
+    If db_connection is NULL the job will be run in the jobs database
+*/
+pub fn create_jobs_table(args: &cli::JobsTableArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Jobs", LogLevel::Debug));
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+
+    client.batch_execute(&format!(
+        "
+        CREATE TABLE IF NOT EXISTS {full_table_name} (
+            \"id\" SERIAL PRIMARY KEY,
+            \"db_connection\" text,
+            \"schema\" text NOT NULL DEFAULT 'public',
+            \"table\" text NOT NULL,
+            \"pk\" text NOT NULL DEFAULT 'id',
+            \"src_column\" text NOT NULL,
+            \"dst_column\" text NOT NULL,
+            \"embedding_model\" text NOT NULL,
+            \"runtime\" text NOT NULL DEFAULT 'ort',
+            \"runtime_params\" jsonb NOT NULL DEFAULT '{{}}',
+            \"batch_size\" int,
+            \"filter\" text,
+            \"status\" text NOT NULL DEFAULT 'queued',
+            \"progress\" int2 NOT NULL DEFAULT 0,
+            \"processed_rows\" bigint,
+            \"processed_tokens\" bigint,
+            \"error\" text,
+            \"attempts\" int NOT NULL DEFAULT 0,
+            \"runner\" text,
+            \"created_at\" timestamptz NOT NULL DEFAULT now(),
+            \"started_at\" timestamptz,
+            \"finished_at\" timestamptz
+        );
+    "
+    ))?;
+
+    logger.info(&format!("Jobs table {full_table_name} is ready"));
+    Ok(())
+}
+
+// Claim the oldest queued job
+// Rows locked by other runners are skipped, so each job will be claimed by one runner
+fn claim_job(
+    client: &mut Client,
+    full_table_name: &str,
+    runner_name: &str,
+) -> Result<Option<Row>, anyhow::Error> {
+    let row = client.query_opt(
+        &format!(
+            "
+            UPDATE {full_table_name} SET status='running', started_at=now(), finished_at=NULL, progress=0, error=NULL, attempts=attempts+1, runner=$1
+            WHERE id = (SELECT id FROM {full_table_name} WHERE status='queued' ORDER BY id FOR UPDATE SKIP LOCKED LIMIT 1)
+            RETURNING id, db_connection, \"schema\", \"table\", pk, src_column, dst_column, embedding_model, runtime, runtime_params::text, batch_size, filter
+        "
+        ),
+        &[&runner_name],
+    )?;
+
+    Ok(row)
+}
+
+fn get_job_args(row: &Row, default_uri: &str) -> Result<EmbeddingArgs, anyhow::Error> {
+    let uri = row
+        .get::<&str, Option<String>>("db_connection")
+        .unwrap_or(default_uri.to_owned());
+
+    Ok(EmbeddingArgs {
+        model: row.get::<&str, String>("embedding_model"),
+        uri: uri.clone(),
+        table: row.get::<&str, String>("table"),
+        schema: row.get::<&str, String>("schema"),
+        column: row.get::<&str, String>("src_column"),
+        out_uri: Some(uri),
+        out_column: row.get::<&str, String>("dst_column"),
+        batch_size: row
+            .get::<&str, Option<i32>>("batch_size")
+            .map(|size| size as usize),
+        runtime: Runtime::try_from(row.get::<&str, &str>("runtime"))?,
+        runtime_params: row.get::<&str, String>("runtime_params"),
+        filter: row.get::<&str, Option<String>>("filter"),
+        stream: true,
+        pk: row.get::<&str, String>("pk"),
+        ..Default::default()
+    })
+}
+
+fn run_job(
+    row: Row,
+    uri: &str,
+    full_table_name: &str,
+    is_canceled: &Option<Arc<RwLock<bool>>>,
+    logger: &Logger,
+) -> AnyhowVoidResult {
+    let job_id = row.get::<&str, i32>("id");
+    let status_client = Arc::new(Mutex::new(Client::connect(
+        &append_params_to_uri(uri, CONNECTION_PARAMS),
+        NoTls,
+    )?));
+    logger.info(&format!("Running job {job_id}"));
+
+    let job_args = match get_job_args(&row, uri) {
+        Ok(args) => args,
+        Err(e) => {
+            status_client.lock().unwrap().execute(
+                &format!("UPDATE {full_table_name} SET status='failed', error=$1, finished_at=now() WHERE id=$2"),
+                &[&e.to_string(), &job_id],
+            )?;
+            return Ok(());
+        }
+    };
+
+    let progress_client = status_client.clone();
+    let progress_logger = logger.clone();
+    let progress_table_name = full_table_name.to_owned();
+    let progress_cb: ProgressCbFn = Box::new(move |progress: u8| {
+        let res = progress_client.lock().unwrap().execute(
+            &format!("UPDATE {progress_table_name} SET progress=$1 WHERE id=$2"),
+            &[&(progress as i16), &job_id],
+        );
+        if let Err(e) = res {
+            progress_logger.error(&format!("Failed to update progress of job {job_id}: {e}"));
+        }
+    });
+
+    // Watch for job cancellation from the jobs table and from the runner
+    let job_canceled = Arc::new(RwLock::new(false));
+    let job_finished = Arc::new(RwLock::new(false));
+    let watcher_client = status_client.clone();
+    let watcher_job_canceled = job_canceled.clone();
+    let watcher_job_finished = job_finished.clone();
+    let watcher_is_canceled = is_canceled.clone();
+    let watcher_table_name = full_table_name.to_owned();
+    let watcher_handle = std::thread::spawn(move || {
+        while !*watcher_job_finished.read().unwrap() {
+            let runner_canceled = watcher_is_canceled
+                .as_ref()
+                .is_some_and(|is_canceled| *is_canceled.read().unwrap());
+            let job_canceled = watcher_client
+                .lock()
+                .unwrap()
+                .query_one(
+                    &format!("SELECT status FROM {watcher_table_name} WHERE id=$1"),
+                    &[&job_id],
+                )
+                .is_ok_and(|row| row.get::<usize, String>(0) == "canceled");
+
+            if runner_canceled || job_canceled {
+                *watcher_job_canceled.write().unwrap() = true;
+                break;
+            }
+            std::thread::sleep(CANCEL_CHECK_INTERVAL);
+        }
+    });
+
+    let result = embeddings::create_embeddings_from_db(
+        job_args,
+        true,
+        Some(progress_cb),
+        Some(job_canceled),
+        Some(Logger::new(
+            &format!("Lantern Job {job_id}"),
+            logger.level.clone(),
+        )),
+    );
+
+    *job_finished.write().unwrap() = true;
+    watcher_handle.join().unwrap();
+    let mut status_client = status_client.lock().unwrap();
+
+    match result {
+        Ok(report) => {
+            let (processed_rows, processed_tokens) =
+                (report.processed_rows, report.processed_tokens);
+            status_client.execute(
+                &format!("UPDATE {full_table_name} SET status='succeeded', progress=100, processed_rows=$1, processed_tokens=$2, finished_at=now() WHERE id=$3"),
+                &[&(processed_rows as i64), &(processed_tokens as i64), &job_id],
+            )?;
+            logger.info(&format!(
+                "Job {job_id} finished. Processed {processed_rows} rows"
+            ));
+            logger.debug(&report.to_string());
+        }
+        Err(e) => {
+            // Keep canceled status if the job was canceled from jobs table
+            status_client.execute(
+                &format!("UPDATE {full_table_name} SET status=CASE WHEN status='canceled' THEN status ELSE 'failed' END, error=$1, finished_at=now() WHERE id=$2"),
+                &[&e.to_string(), &job_id],
+            )?;
+            logger.error(&format!("Job {job_id} failed: {e}"));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn run_jobs(
+    args: &cli::JobsRunnerArgs,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    logger: Option<Logger>,
+) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Jobs", LogLevel::Debug));
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let runner_name = args.runner_name.clone().unwrap_or(format!(
+        "{}-{}",
+        std::env::var("HOSTNAME").unwrap_or("lantern".to_owned()),
+        std::process::id()
+    ));
+
+    logger.info(&format!(
+        "Runner {runner_name} started on {full_table_name}"
+    ));
+
+    loop {
+        if let Some(is_canceled) = &is_canceled {
+            if *is_canceled.read().unwrap() {
+                break;
+            }
+        }
+
+        match claim_job(&mut client, &full_table_name, &runner_name)? {
+            Some(row) => run_job(row, &args.uri, &full_table_name, &is_canceled, &logger)?,
+            None => {
+                if args.once {
+                    break;
+                }
+                std::thread::sleep(Duration::from_secs(args.poll_interval));
+            }
+        }
+    }
+
+    logger.info(&format!("Runner {runner_name} stopped"));
+    Ok(())
+}
@@ -1,3 +1,5 @@
+#[cfg(feature = "config")]
+pub mod config;
 #[cfg(feature = "daemon")]
 pub mod daemon;
 #[cfg(feature = "embeddings")]
@@ -8,11 +10,24 @@ pub mod external_index;
 pub mod http_server;
 #[cfg(feature = "autotune")]
 pub mod index_autotune;
+#[cfg(feature = "jobs")]
+pub mod jobs;
 pub mod logger;
+#[cfg(feature = "metrics")]
+pub mod metrics;
+#[cfg(feature = "mock-provider")]
+pub mod mock_provider;
 #[cfg(feature = "pq")]
 pub mod pq;
+pub mod shutdown;
+#[cfg(feature = "support-bundle")]
+pub mod support_bundle;
+#[cfg(feature = "telemetry")]
+pub mod telemetry;
 pub mod types;
 pub mod utils;
+#[cfg(feature = "vector-jobs")]
+pub mod vector_jobs;
 
 #[macro_use]
 extern crate lazy_static;
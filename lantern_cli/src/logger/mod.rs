@@ -1,3 +1,5 @@
+use crate::utils::redact_secrets;
+
 #[derive(PartialEq, Clone)]
 pub enum LogLevel {
     Error,
@@ -11,6 +13,8 @@ pub struct Logger {
     pub level: LogLevel,
 }
 
+// Log messages are passed through redact_secrets, so keys and passwords are not written to logs
+// Raw output is printed as is, as it contains the command results
 impl Logger {
     pub fn new(label: &str, level: LogLevel) -> Logger {
         Logger {
@@ -28,7 +32,7 @@ impl Logger {
             return;
         }
 
-        println!("[*] [{}] {}", &self.label, msg);
+        println!("[*] [{}] {}", &self.label, redact_secrets(msg));
     }
 
     pub fn debug(&self, msg: &str) {
@@ -36,7 +40,7 @@ impl Logger {
             return;
         }
 
-        println!("[+] [{}] {}", &self.label, msg);
+        println!("[+] [{}] {}", &self.label, redact_secrets(msg));
     }
 
     pub fn warn(&self, msg: &str) {
@@ -44,11 +48,11 @@ impl Logger {
             return;
         }
 
-        println!("[!] [{}] {}", &self.label, msg);
+        println!("[!] [{}] {}", &self.label, redact_secrets(msg));
     }
 
     pub fn error(&self, msg: &str) {
-        eprintln!("[X] [{}] {}", &self.label, msg);
+        eprintln!("[X] [{}] {}", &self.label, redact_secrets(msg));
     }
 }
 
@@ -1,14 +1,34 @@
-use std::process;
+use std::{env, process};
 
 use crate::logger::{LogLevel, Logger};
-use clap::Parser;
+use clap::{CommandFactory, Parser};
 use lantern_cli::*;
 mod cli;
 
 #[cfg(feature = "cli")]
 fn main() {
-    let cli = cli::Cli::parse();
+    // Options missing on the command line are taken from LANTERN_* variables and the config file
+    let args = match config::args::apply_config_args(&cli::Cli::command(), env::args().collect()) {
+        Ok(args) => args,
+        Err(e) => {
+            Logger::new("Lantern Config", LogLevel::Error).error(&e.to_string());
+            process::exit(1);
+        }
+    };
+    let cli = cli::Cli::parse_from(args);
     let mut _main_logger = None;
+    // Traces are exported until the guard is dropped at the end of the command
+    let tracing_guard = match &cli.otlp_endpoint {
+        Some(endpoint) => match telemetry::init_tracing(endpoint, &cli.otlp_service_name) {
+            Ok(guard) => Some(guard),
+            Err(e) => {
+                Logger::new("Lantern Telemetry", LogLevel::Error)
+                    .error(&format!("Failed to initialize tracing: {e}"));
+                process::exit(1);
+            }
+        },
+        None => None,
+    };
     let res = match cli.command {
         cli::Commands::CreateIndex(args) => {
             let logger = Logger::new("Lantern Index", LogLevel::Debug);
@@ -16,31 +36,102 @@ fn main() {
             external_index::create_usearch_index(&args, None, None, Some(logger))
         }
         cli::Commands::CreateEmbeddings(args) => {
-            let logger = Logger::new("Lantern Embeddings", LogLevel::Debug);
+            let args = args.with_stdout_default();
+            // Only errors are logged, as they are written to stderr
+            let log_level = if args.writes_to_stdout() {
+                LogLevel::Error
+            } else {
+                LogLevel::Debug
+            };
+            let logger = Logger::new("Lantern Embeddings", log_level);
             _main_logger = Some(logger.clone());
-            let res = embeddings::create_embeddings_from_db(args, true, None, None, Some(logger));
+            let is_canceled = shutdown::cancel_on_signal(&logger);
+            let res = embeddings::create_embeddings_from_db(
+                args,
+                true,
+                None,
+                Some(is_canceled),
+                Some(logger),
+            );
             // Handle error here as this call does not return void as others
             let logger = _main_logger.as_ref().unwrap();
-            if let Err(e) = res {
-                logger.error(&e.to_string());
+            match res {
+                Ok(report) => logger.info(&report.to_string()),
+                Err(e) => logger.error(&e.to_string()),
             }
             Ok(())
         }
+        cli::Commands::CreateEmbeddingsFanOut(args) => {
+            let logger = Logger::new("Lantern Embeddings Fan-out", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            embeddings::fan_out::create_embeddings_fan_out(&args, Some(logger)).and_then(
+                |summaries| {
+                    let failed = summaries.iter().filter(|s| s.error.is_some()).count();
+                    if failed > 0 {
+                        anyhow::bail!("Job failed for {failed} of {} databases", summaries.len());
+                    }
+                    Ok(())
+                },
+            )
+        }
+        cli::Commands::SyncEmbeddings(args) => {
+            let logger = Logger::new("Lantern Embeddings Sync", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            embeddings::sync_embeddings(&args, None, Some(logger))
+        }
         cli::Commands::ShowModels(args) => {
             let logger = Logger::new("Lantern Embeddings", LogLevel::Debug);
             _main_logger = Some(logger.clone());
             embeddings::show_available_models(&args, Some(logger))
         }
-        cli::Commands::ShowRuntimes => {
+        cli::Commands::EmbedText(args) => {
+            // Only errors are logged, so the vector can be used in scripts
+            let logger = Logger::new("Lantern Embeddings", LogLevel::Error);
+            _main_logger = Some(logger.clone());
+            embeddings::embed_text::print_embedding(&args, Some(logger))
+        }
+        cli::Commands::Search(args) => {
+            // Only errors are logged, so the rows can be used in scripts
+            let logger = Logger::new("Lantern Search", LogLevel::Error);
+            _main_logger = Some(logger.clone());
+            embeddings::search::print_search_results(&args, Some(logger))
+        }
+        cli::Commands::Rerank(args) => {
+            // Only errors are logged, so the results can be used in scripts
+            let logger = Logger::new("Lantern Rerank", LogLevel::Error);
+            _main_logger = Some(logger.clone());
+            embeddings::rerank::print_rerank_results(&args, Some(logger))
+        }
+        cli::Commands::Models(args) => {
+            let logger = Logger::new("Lantern Models", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            match args.command {
+                embeddings::cli::ModelsCommands::Pull(args) => {
+                    embeddings::models::pull_model(&args, Some(logger))
+                }
+                embeddings::cli::ModelsCommands::List(args) => {
+                    embeddings::models::list_models(&args, Some(logger))
+                }
+                embeddings::cli::ModelsCommands::Rm(args) => {
+                    embeddings::models::remove_model(&args, Some(logger))
+                }
+            }
+        }
+        cli::Commands::ShowRuntimes(args) => {
             let logger = Logger::new("Lantern Embeddings", LogLevel::Debug);
             _main_logger = Some(logger.clone());
-            embeddings::show_available_runtimes(Some(logger))
+            embeddings::show_available_runtimes(&args, Some(logger))
         }
         cli::Commands::MeasureModelSpeed(args) => {
             let logger = Logger::new("Lantern Embeddings", LogLevel::Info);
             _main_logger = Some(logger.clone());
             embeddings::measure_speed::start_speed_test(&args, Some(logger))
         }
+        cli::Commands::Benchmark(args) => {
+            let logger = Logger::new("Lantern Embeddings", LogLevel::Info);
+            _main_logger = Some(logger.clone());
+            embeddings::measure_speed::print_benchmark(&args, Some(logger))
+        }
         cli::Commands::AutotuneIndex(args) => {
             let logger = Logger::new("Lantern Index Autotune", LogLevel::Debug);
             _main_logger = Some(logger.clone());
@@ -49,7 +140,23 @@ fn main() {
         cli::Commands::PQTable(args) => {
             let logger = Logger::new("Lantern PQ", LogLevel::Debug);
             _main_logger = Some(logger.clone());
-            pq::quantize_table(args, None, None, Some(logger))
+            let is_canceled = shutdown::cancel_on_signal(&logger);
+            pq::quantize_table(args, None, Some(is_canceled), Some(logger))
+        }
+        cli::Commands::Pq(args) => {
+            let logger = Logger::new("Lantern PQ", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            match args.command {
+                pq::cli::PQCommands::Evaluate(args) => {
+                    pq::evaluate::evaluate_pq(&args, Some(logger)).map(|_| ())
+                }
+                pq::cli::PQCommands::ExportCodebook(args) => {
+                    pq::codebook_file::export_codebook(&args, Some(logger))
+                }
+                pq::cli::PQCommands::TrainCentroids(args) => {
+                    pq::ivf::train_centroids(&args, Some(logger))
+                }
+            }
         }
         cli::Commands::StartDaemon(args) => {
             let logger = Logger::new("Lantern Daemon", args.log_level.value());
@@ -61,8 +168,84 @@ fn main() {
             _main_logger = Some(logger.clone());
             http_server::start(args, Some(logger))
         }
+        cli::Commands::SemanticJoin(args) => {
+            let logger = Logger::new("Lantern Semantic Join", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            vector_jobs::semantic_join(&args, None, None, Some(logger))
+        }
+        cli::Commands::Classify(args) => {
+            let logger = Logger::new("Lantern Classify", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            vector_jobs::classify_by_centroid(&args, None, None, Some(logger))
+        }
+        cli::Commands::ScoreAnomalies(args) => {
+            let logger = Logger::new("Lantern Anomaly", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            vector_jobs::score_anomalies(&args, None, None, Some(logger))
+        }
+        cli::Commands::SampleRows(args) => {
+            let logger = Logger::new("Lantern Sample", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            vector_jobs::export_stratified_sample(&args, None, None, Some(logger))
+        }
+        cli::Commands::VectorOps(args) => {
+            let logger = Logger::new("Lantern Vector Ops", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            match args.command {
+                vector_jobs::cli::VectorOpsCommands::Average(args) => {
+                    vector_jobs::vector_ops::average_vectors(&args, Some(logger)).map(|_| ())
+                }
+                vector_jobs::cli::VectorOpsCommands::Combine(args) => {
+                    vector_jobs::vector_ops::combine_vectors(&args, Some(logger)).map(|_| ())
+                }
+                vector_jobs::cli::VectorOpsCommands::List(args) => {
+                    vector_jobs::vector_ops::list_query_vectors(&args, Some(logger)).map(|_| ())
+                }
+                vector_jobs::cli::VectorOpsCommands::Delete(args) => {
+                    vector_jobs::vector_ops::delete_query_vector(&args, Some(logger))
+                }
+            }
+        }
+        cli::Commands::ConvertColumn(args) => {
+            let logger = Logger::new("Lantern Convert Column", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            vector_jobs::convert_column(&args, None, None, Some(logger))
+        }
+        cli::Commands::Jobs(args) => {
+            let logger = Logger::new("Lantern Jobs", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            match args.command {
+                jobs::cli::JobsCommands::Init(args) => jobs::create_jobs_table(&args, Some(logger)),
+                jobs::cli::JobsCommands::Run(args) => jobs::run_jobs(&args, None, Some(logger)),
+            }
+        }
+        cli::Commands::SupportBundle(args) => {
+            let logger = Logger::new("Lantern Support Bundle", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            support_bundle::create_support_bundle(&args, Some(logger))
+        }
+        cli::Commands::Metrics(args) => {
+            let logger = Logger::new("Lantern Metrics", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            match args.command {
+                metrics::cli::MetricsCommands::List => metrics::list_metrics(Some(logger)),
+                metrics::cli::MetricsCommands::ExportDashboard(args) => {
+                    metrics::export_dashboard(&args, Some(logger))
+                }
+            }
+        }
+        cli::Commands::Config(args) => {
+            let logger = Logger::new("Lantern Config", LogLevel::Debug);
+            _main_logger = Some(logger.clone());
+            match args.command {
+                config::cli::ConfigCommands::Validate(args) => {
+                    config::validate_config(&args, Some(logger))
+                }
+            }
+        }
     };
 
+    drop(tracing_guard);
     let logger = _main_logger.unwrap();
     if let Err(e) = res {
         logger.error(&e.to_string());
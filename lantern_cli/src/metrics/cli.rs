@@ -0,0 +1,32 @@
+use clap::{Parser, Subcommand};
+
+#[derive(Subcommand, Debug)]
+pub enum MetricsCommands {
+    /// List metric names, types and labels
+    List,
+    /// Write Grafana dashboard JSON for the metrics
+    ExportDashboard(ExportDashboardArgs),
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct MetricsArgs {
+    #[command(subcommand)]
+    pub command: MetricsCommands,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct ExportDashboardArgs {
+    /// Output path of the dashboard JSON
+    #[arg(short, long, default_value = "lantern-dashboard.json")]
+    pub out: String,
+
+    /// Dashboard title
+    #[arg(long, default_value = "Lantern")]
+    pub title: String,
+
+    /// Dashboard uid, change it to import multiple copies of the dashboard
+    #[arg(long, default_value = "lantern-cli")]
+    pub uid: String,
+}
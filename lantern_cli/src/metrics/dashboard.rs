@@ -0,0 +1,156 @@
+use super::{Metric, MetricKind, Subsystem, LABEL_MODEL, LABEL_TABLE, METRICS};
+use serde_json::{json, Value};
+
+static SCHEMA_VERSION: u64 = 39;
+static PANEL_WIDTH: u64 = 12;
+static PANEL_HEIGHT: u64 = 8;
+// Labels which can be selected with dashboard variables
+static VARIABLE_LABELS: [&str; 2] = [LABEL_MODEL, LABEL_TABLE];
+
+fn get_selector(metric: &Metric) -> String {
+    let filters: Vec<String> = VARIABLE_LABELS
+        .iter()
+        .filter(|label| metric.labels.contains(label))
+        .map(|label| format!("{label}=~\"${label}\""))
+        .collect();
+    if filters.is_empty() {
+        return String::new();
+    }
+    format!("{{{}}}", filters.join(","))
+}
+
+// Counters are shown as per second rates and histograms as p50/p95 latencies
+fn get_targets(metric: &Metric) -> Vec<Value> {
+    let name = metric.full_name();
+    let selector = get_selector(metric);
+    let labels = metric.labels.join(", ");
+    let legend = metric
+        .labels
+        .iter()
+        .map(|label| format!("{{{{{label}}}}}"))
+        .collect::<Vec<String>>()
+        .join(" ");
+
+    match metric.kind {
+        MetricKind::Counter => vec![json!({
+            "expr": format!("sum by ({labels}) (rate({name}{selector}[$__rate_interval]))"),
+            "legendFormat": legend,
+            "refId": "A",
+        })],
+        MetricKind::Gauge => vec![json!({
+            "expr": format!("sum by ({labels}) ({name}{selector})"),
+            "legendFormat": legend,
+            "refId": "A",
+        })],
+        MetricKind::Histogram => ["0.5", "0.95"]
+            .iter()
+            .zip(["A", "B"])
+            .map(|(quantile, ref_id)| {
+                json!({
+                    "expr": format!("histogram_quantile({quantile}, sum by (le, {labels}) (rate({name}_bucket{selector}[$__rate_interval])))"),
+                    "legendFormat": format!("p{} {legend}", quantile.trim_start_matches("0.")),
+                    "refId": ref_id,
+                })
+            })
+            .collect(),
+    }
+}
+
+fn get_unit(metric: &Metric) -> &str {
+    if metric.name.ends_with("_seconds") {
+        "s"
+    } else if metric.name.ends_with("_ratio") {
+        "percentunit"
+    } else if metric.kind == MetricKind::Counter {
+        "ops"
+    } else {
+        "short"
+    }
+}
+
+fn get_panel(id: u64, metric: &Metric, x: u64, y: u64) -> Value {
+    json!({
+        "id": id,
+        "type": "timeseries",
+        "title": metric.help,
+        "description": format!("{} ({})", metric.full_name(), metric.kind),
+        "datasource": { "type": "prometheus", "uid": "${datasource}" },
+        "gridPos": { "x": x, "y": y, "w": PANEL_WIDTH, "h": PANEL_HEIGHT },
+        "fieldConfig": { "defaults": { "unit": get_unit(metric) }, "overrides": [] },
+        "options": { "legend": { "displayMode": "list", "placement": "bottom" } },
+        "targets": get_targets(metric),
+    })
+}
+
+fn get_variable(label: &str) -> Value {
+    json!({
+        "name": label,
+        "label": label,
+        "type": "query",
+        "datasource": { "type": "prometheus", "uid": "${datasource}" },
+        "query": { "query": format!("label_values({label})"), "refId": "PrometheusVariableQueryEditor-VariableQuery" },
+        "refresh": 2,
+        "includeAll": true,
+        "multi": true,
+        "allValue": ".*",
+        "current": { "text": "All", "value": "$__all" },
+    })
+}
+
+// Grafana dashboard with a row of panels for each subsystem
+pub fn get_dashboard(title: &str, uid: &str) -> Value {
+    let mut panels = Vec::new();
+    let mut id = 1;
+    let mut y = 0;
+
+    for subsystem in Subsystem::all() {
+        panels.push(json!({
+            "id": id,
+            "type": "row",
+            "title": subsystem.title(),
+            "collapsed": false,
+            "gridPos": { "x": 0, "y": y, "w": PANEL_WIDTH * 2, "h": 1 },
+            "panels": [],
+        }));
+        id += 1;
+        y += 1;
+
+        let metrics: Vec<&Metric> = METRICS
+            .iter()
+            .filter(|m| m.subsystem == subsystem)
+            .collect();
+        for (idx, metric) in metrics.iter().enumerate() {
+            let x = (idx as u64 % 2) * PANEL_WIDTH;
+            panels.push(get_panel(
+                id,
+                metric,
+                x,
+                y + (idx as u64 / 2) * PANEL_HEIGHT,
+            ));
+            id += 1;
+        }
+        y += metrics.len().div_ceil(2) as u64 * PANEL_HEIGHT;
+    }
+
+    let mut variables = vec![json!({
+        "name": "datasource",
+        "label": "Data source",
+        "type": "datasource",
+        "query": "prometheus",
+        "current": {},
+    })];
+    variables.extend(VARIABLE_LABELS.iter().map(|label| get_variable(label)));
+
+    json!({
+        "uid": uid,
+        "title": title,
+        "tags": ["lantern"],
+        "editable": true,
+        "schemaVersion": SCHEMA_VERSION,
+        "refresh": "30s",
+        "time": { "from": "now-6h", "to": "now" },
+        "templating": { "list": variables },
+        "annotations": { "list": [] },
+        "panels": panels,
+    })
+}
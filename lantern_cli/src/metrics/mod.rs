@@ -0,0 +1,233 @@
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use std::fmt;
+
+pub mod cli;
+mod dashboard;
+mod registry;
+mod server;
+
+pub use dashboard::get_dashboard;
+pub use registry::{add_gauge, inc_counter, observe, render, set_gauge};
+pub use server::start_metrics_server;
+
+// Metric names follow lantern_<subsystem>_<name>_<unit> format
+// Counters end with _total, durations are in seconds and progress is a 0-1 ratio
+// Labels with the same meaning have the same name in all subsystems
+pub static METRIC_PREFIX: &str = "lantern";
+pub const LABEL_MODEL: &str = "model";
+pub const LABEL_RUNTIME: &str = "runtime";
+pub const LABEL_TABLE: &str = "table";
+pub const LABEL_COLUMN: &str = "column";
+pub const LABEL_JOB_TYPE: &str = "job_type";
+pub const LABEL_STATUS: &str = "status";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Subsystem {
+    Embeddings,
+    Pq,
+    Daemon,
+}
+
+impl Subsystem {
+    pub fn all() -> [Subsystem; 3] {
+        [Subsystem::Embeddings, Subsystem::Pq, Subsystem::Daemon]
+    }
+
+    pub fn title(&self) -> &str {
+        match self {
+            Subsystem::Embeddings => "Embeddings",
+            Subsystem::Pq => "Product Quantization",
+            Subsystem::Daemon => "Daemon",
+        }
+    }
+}
+
+impl fmt::Display for Subsystem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Subsystem::Embeddings => write!(f, "embeddings"),
+            Subsystem::Pq => write!(f, "pq"),
+            Subsystem::Daemon => write!(f, "daemon"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetricKind {
+    Counter,
+    Gauge,
+    Histogram,
+}
+
+impl fmt::Display for MetricKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MetricKind::Counter => write!(f, "counter"),
+            MetricKind::Gauge => write!(f, "gauge"),
+            MetricKind::Histogram => write!(f, "histogram"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Metric {
+    pub subsystem: Subsystem,
+    pub name: &'static str,
+    pub kind: MetricKind,
+    pub help: &'static str,
+    pub labels: &'static [&'static str],
+}
+
+impl Metric {
+    // Name in lantern_<subsystem>_<name> format
+    pub fn full_name(&self) -> String {
+        format!("{METRIC_PREFIX}_{}_{}", self.subsystem, self.name)
+    }
+}
+
+lazy_static! {
+    pub static ref METRICS: Vec<Metric> = vec![
+        Metric {
+            subsystem: Subsystem::Embeddings,
+            name: "rows_processed_total",
+            kind: MetricKind::Counter,
+            help: "Rows with generated embeddings",
+            labels: &[LABEL_MODEL, LABEL_RUNTIME, LABEL_TABLE],
+        },
+        Metric {
+            subsystem: Subsystem::Embeddings,
+            name: "tokens_processed_total",
+            kind: MetricKind::Counter,
+            help: "Tokens sent to the embedding runtime",
+            labels: &[LABEL_MODEL, LABEL_RUNTIME, LABEL_TABLE],
+        },
+        Metric {
+            subsystem: Subsystem::Embeddings,
+            name: "runtime_errors_total",
+            kind: MetricKind::Counter,
+            help: "Failed embedding runtime requests",
+            labels: &[LABEL_MODEL, LABEL_RUNTIME],
+        },
+        Metric {
+            subsystem: Subsystem::Embeddings,
+            name: "request_retries_total",
+            kind: MetricKind::Counter,
+            help: "Retried embedding runtime requests",
+            labels: &[],
+        },
+        Metric {
+            subsystem: Subsystem::Embeddings,
+            name: "batch_duration_seconds",
+            kind: MetricKind::Histogram,
+            help: "Time to generate embeddings for one batch",
+            labels: &[LABEL_MODEL, LABEL_RUNTIME],
+        },
+        Metric {
+            subsystem: Subsystem::Embeddings,
+            name: "flush_duration_seconds",
+            kind: MetricKind::Histogram,
+            help: "Time to write one batch of embeddings to the destination table",
+            labels: &[LABEL_TABLE],
+        },
+        Metric {
+            subsystem: Subsystem::Embeddings,
+            name: "flush_retries_total",
+            kind: MetricKind::Counter,
+            help: "Retried writes to the destination table",
+            labels: &[LABEL_TABLE],
+        },
+        Metric {
+            subsystem: Subsystem::Embeddings,
+            name: "export_lag_rows",
+            kind: MetricKind::Gauge,
+            help: "Embedded rows not yet committed to the destination table",
+            labels: &[LABEL_TABLE],
+        },
+        Metric {
+            subsystem: Subsystem::Embeddings,
+            name: "progress_ratio",
+            kind: MetricKind::Gauge,
+            help: "Progress of the running embedding job",
+            labels: &[LABEL_TABLE, LABEL_COLUMN],
+        },
+        Metric {
+            subsystem: Subsystem::Pq,
+            name: "rows_quantized_total",
+            kind: MetricKind::Counter,
+            help: "Rows compressed with the codebook",
+            labels: &[LABEL_TABLE, LABEL_COLUMN],
+        },
+        Metric {
+            subsystem: Subsystem::Pq,
+            name: "codebook_duration_seconds",
+            kind: MetricKind::Histogram,
+            help: "Time to train the codebook",
+            labels: &[LABEL_TABLE, LABEL_COLUMN],
+        },
+        Metric {
+            subsystem: Subsystem::Pq,
+            name: "progress_ratio",
+            kind: MetricKind::Gauge,
+            help: "Progress of the running quantization job",
+            labels: &[LABEL_TABLE, LABEL_COLUMN],
+        },
+        Metric {
+            subsystem: Subsystem::Daemon,
+            name: "jobs_running",
+            kind: MetricKind::Gauge,
+            help: "Jobs currently running in the daemon",
+            labels: &[LABEL_JOB_TYPE],
+        },
+        Metric {
+            subsystem: Subsystem::Daemon,
+            name: "jobs_finished_total",
+            kind: MetricKind::Counter,
+            help: "Jobs finished by the daemon",
+            labels: &[LABEL_JOB_TYPE, LABEL_STATUS],
+        },
+        Metric {
+            subsystem: Subsystem::Daemon,
+            name: "job_duration_seconds",
+            kind: MetricKind::Histogram,
+            help: "Duration of the finished jobs",
+            labels: &[LABEL_JOB_TYPE],
+        },
+    ];
+}
+
+pub fn get_metric(subsystem: Subsystem, name: &str) -> Option<&'static Metric> {
+    METRICS
+        .iter()
+        .find(|m| m.subsystem == subsystem && m.name == name)
+}
+
+pub fn list_metrics(logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Metrics", LogLevel::Debug));
+    let mut lines = vec![format!(
+        "{:<44} {:<10} {:<28} {}",
+        "name", "type", "labels", "help"
+    )];
+    for metric in METRICS.iter() {
+        lines.push(format!(
+            "{:<44} {:<10} {:<28} {}",
+            metric.full_name(),
+            metric.kind.to_string(),
+            metric.labels.join(","),
+            metric.help
+        ));
+    }
+    logger.print_raw(&lines.join("\n"));
+    Ok(())
+}
+
+pub fn export_dashboard(
+    args: &cli::ExportDashboardArgs,
+    logger: Option<Logger>,
+) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Metrics", LogLevel::Debug));
+    let dashboard = get_dashboard(&args.title, &args.uid);
+    std::fs::write(&args.out, serde_json::to_string_pretty(&dashboard)?)?;
+    logger.info(&format!("Dashboard written to {}", args.out));
+    Ok(())
+}
@@ -0,0 +1,191 @@
+use super::{get_metric, Metric, MetricKind, Subsystem, METRICS};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+// Upper bounds of histogram buckets in seconds, from one local batch to a slow API request
+const BUCKETS: [f64; 12] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0,
+];
+
+#[derive(Debug, Clone, Default)]
+struct Histogram {
+    buckets: [u64; BUCKETS.len()],
+    sum: f64,
+    count: u64,
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Number(f64),
+    Histogram(Histogram),
+}
+
+// Label values are stored in the order of the metric labels in the catalog
+type Series = BTreeMap<Vec<String>, Value>;
+
+lazy_static! {
+    static ref REGISTRY: Mutex<HashMap<String, Series>> = Mutex::new(HashMap::new());
+}
+
+fn update(
+    subsystem: Subsystem,
+    name: &str,
+    kind: MetricKind,
+    label_values: &[&str],
+    f: impl FnOnce(&mut Value),
+) {
+    let metric = match get_metric(subsystem, name) {
+        Some(metric) => metric,
+        None => {
+            debug_assert!(false, "metric {subsystem}_{name} is not in the catalog");
+            return;
+        }
+    };
+    debug_assert_eq!(metric.kind, kind, "{}", metric.full_name());
+    debug_assert_eq!(
+        metric.labels.len(),
+        label_values.len(),
+        "{}",
+        metric.full_name()
+    );
+
+    let mut registry = REGISTRY.lock().unwrap();
+    let value = registry
+        .entry(metric.full_name())
+        .or_default()
+        .entry(label_values.iter().map(|v| v.to_string()).collect())
+        .or_insert_with(|| match kind {
+            MetricKind::Histogram => Value::Histogram(Histogram::default()),
+            _ => Value::Number(0.0),
+        });
+    f(value);
+}
+
+pub fn inc_counter(subsystem: Subsystem, name: &str, label_values: &[&str], value: f64) {
+    update(
+        subsystem,
+        name,
+        MetricKind::Counter,
+        label_values,
+        |current| {
+            if let Value::Number(current) = current {
+                *current += value;
+            }
+        },
+    );
+}
+
+pub fn set_gauge(subsystem: Subsystem, name: &str, label_values: &[&str], value: f64) {
+    update(
+        subsystem,
+        name,
+        MetricKind::Gauge,
+        label_values,
+        |current| {
+            if let Value::Number(current) = current {
+                *current = value;
+            }
+        },
+    );
+}
+
+pub fn add_gauge(subsystem: Subsystem, name: &str, label_values: &[&str], value: f64) {
+    update(
+        subsystem,
+        name,
+        MetricKind::Gauge,
+        label_values,
+        |current| {
+            if let Value::Number(current) = current {
+                *current += value;
+            }
+        },
+    );
+}
+
+pub fn observe(subsystem: Subsystem, name: &str, label_values: &[&str], value: f64) {
+    update(
+        subsystem,
+        name,
+        MetricKind::Histogram,
+        label_values,
+        |current| {
+            if let Value::Histogram(histogram) = current {
+                for (idx, bound) in BUCKETS.iter().enumerate() {
+                    if value <= *bound {
+                        histogram.buckets[idx] += 1;
+                    }
+                }
+                histogram.sum += value;
+                histogram.count += 1;
+            }
+        },
+    );
+}
+
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn format_labels(metric: &Metric, label_values: &[String], extra: Option<(&str, &str)>) -> String {
+    let labels: Vec<String> = metric
+        .labels
+        .iter()
+        .zip(label_values)
+        .map(|(label, value)| (*label, value.as_str()))
+        .chain(extra)
+        .map(|(label, value)| format!("{label}=\"{}\"", escape_label_value(value)))
+        .collect();
+
+    if labels.is_empty() {
+        return String::new();
+    }
+    format!("{{{}}}", labels.join(","))
+}
+
+// Recorded metrics in Prometheus text exposition format, in the catalog order
+pub fn render() -> String {
+    let registry = REGISTRY.lock().unwrap();
+    let mut output = String::new();
+
+    for metric in METRICS.iter() {
+        let name = metric.full_name();
+        let series = match registry.get(&name) {
+            Some(series) => series,
+            None => continue,
+        };
+
+        output.push_str(&format!("# HELP {name} {}\n", metric.help));
+        output.push_str(&format!("# TYPE {name} {}\n", metric.kind));
+        for (label_values, value) in series {
+            match value {
+                Value::Number(value) => output.push_str(&format!(
+                    "{name}{} {value}\n",
+                    format_labels(metric, label_values, None)
+                )),
+                Value::Histogram(histogram) => {
+                    for (bound, count) in BUCKETS.iter().zip(histogram.buckets) {
+                        let bound = bound.to_string();
+                        output.push_str(&format!(
+                            "{name}_bucket{} {count}\n",
+                            format_labels(metric, label_values, Some(("le", &bound)))
+                        ));
+                    }
+                    output.push_str(&format!(
+                        "{name}_bucket{} {}\n",
+                        format_labels(metric, label_values, Some(("le", "+Inf"))),
+                        histogram.count
+                    ));
+                    let labels = format_labels(metric, label_values, None);
+                    output.push_str(&format!("{name}_sum{labels} {}\n", histogram.sum));
+                    output.push_str(&format!("{name}_count{labels} {}\n", histogram.count));
+                }
+            }
+        }
+    }
+
+    output
+}
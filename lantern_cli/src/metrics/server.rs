@@ -0,0 +1,73 @@
+use super::registry::render;
+use crate::logger::Logger;
+use std::collections::HashSet;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+static CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+lazy_static! {
+    // Jobs run in a loop (sync, fan-out, tenants) reuse the server started by the first run
+    static ref SERVED_PORTS: Mutex<HashSet<u16>> = Mutex::new(HashSet::new());
+}
+
+fn handle_connection(mut stream: TcpStream) -> Result<(), anyhow::Error> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    // Headers are not used, but they should be read before the response is written
+    let mut header = String::new();
+    while reader.read_line(&mut header)? > 0 && header.trim() != "" {
+        header.clear();
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (status, body) = match (parts.next(), parts.next()) {
+        (Some("GET"), Some(path)) if path == "/metrics" || path.starts_with("/metrics?") => {
+            ("200 OK", render())
+        }
+        _ => ("404 Not Found", "Not Found\n".to_owned()),
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {CONTENT_TYPE}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()?;
+    Ok(())
+}
+
+// Serves /metrics on a background thread for the lifetime of the process
+// Returns the bound port, so port 0 can be used to pick a free one
+// Calls with an already served port do nothing
+pub fn start_metrics_server(port: u16, logger: Arc<Logger>) -> Result<u16, anyhow::Error> {
+    let mut served_ports = SERVED_PORTS.lock().unwrap();
+    if served_ports.contains(&port) {
+        return Ok(port);
+    }
+
+    let listener = TcpListener::bind(("0.0.0.0", port))
+        .map_err(|e| anyhow::anyhow!("Failed to bind metrics server to port {port}: {e}"))?;
+    let port = listener.local_addr()?.port();
+    served_ports.insert(port);
+    logger.info(&format!("Serving metrics on http://0.0.0.0:{port}/metrics"));
+
+    std::thread::spawn(move || {
+        // Scrapes are rare, so connections are handled one by one
+        for stream in listener.incoming() {
+            let result = stream
+                .map_err(anyhow::Error::from)
+                .and_then(handle_connection);
+            if let Err(e) = result {
+                logger.debug(&format!("Metrics request failed: {e}"));
+            }
+        }
+    });
+
+    Ok(port)
+}
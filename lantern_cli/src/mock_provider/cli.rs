@@ -0,0 +1,29 @@
+use clap::Parser;
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None)]
+pub struct MockProviderArgs {
+    /// Host to listen
+    #[arg(long, default_value = "127.0.0.1")]
+    pub host: String,
+
+    /// Port to bind
+    #[arg(long, default_value_t = 8089)]
+    pub port: u16,
+
+    /// Dimensions of returned embeddings if not specified in request
+    #[arg(long, default_value_t = 1536)]
+    pub dimensions: usize,
+
+    /// Delay in milliseconds added to each response
+    #[arg(long, default_value_t = 0)]
+    pub latency_ms: u64,
+
+    /// Require this API key in Authorization header
+    #[arg(long)]
+    pub api_key: Option<String>,
+
+    /// Respond with 429 status to every Nth request to test rate limit handling
+    #[arg(long)]
+    pub rate_limit_every: Option<usize>,
+}
@@ -0,0 +1,281 @@
+use crate::logger::{LogLevel, Logger};
+use crate::types::AnyhowVoidResult;
+use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+pub mod cli;
+
+static MOCK_MODEL_NAME: &str = "mock-embedding";
+
+struct MockState {
+    args: cli::MockProviderArgs,
+    request_count: AtomicUsize,
+}
+
+#[derive(Deserialize)]
+struct EmbeddingRequest {
+    input: Value,
+    model: Option<String>,
+    dimensions: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct RerankRequest {
+    query: String,
+    documents: Vec<String>,
+    top_n: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct TeiEmbedRequest {
+    inputs: Value,
+    normalize: Option<bool>,
+}
+
+// Deterministic pseudo-embedding seeded with md5 hash of the text
+// The same text always gets the same unit length vector, so the results are reproducible between runs
+pub fn get_mock_embedding(text: &str, dimensions: usize) -> Vec<f32> {
+    let digest = md5::compute(text);
+    let mut state = u64::from_le_bytes(digest[..8].try_into().unwrap());
+
+    let mut embedding: Vec<f32> = (0..dimensions)
+        .map(|_| {
+            // splitmix64
+            state = state.wrapping_add(0x9E3779B97F4A7C15);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+            z ^= z >> 31;
+            (z as f64 / u64::MAX as f64 * 2.0 - 1.0) as f32
+        })
+        .collect();
+
+    let norm = embedding.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        embedding.iter_mut().for_each(|v| *v /= norm);
+    }
+
+    embedding
+}
+
+// Input can be a string, list of strings, list of token ids or list of token id lists
+// Token ids are hashed as their JSON representation
+fn get_inputs(input: &Value) -> Result<Vec<(String, usize)>, String> {
+    let get_input = |value: &Value| match value {
+        Value::String(text) => Ok((text.clone(), text.split_whitespace().count())),
+        Value::Array(tokens) => Ok((value.to_string(), tokens.len())),
+        _ => Err("'input' should be a string or an array".to_owned()),
+    };
+
+    match input {
+        Value::Array(values) if values.iter().all(|v| v.is_number()) => Ok(vec![get_input(input)?]),
+        Value::Array(values) => values.iter().map(get_input).collect(),
+        value => Ok(vec![get_input(value)?]),
+    }
+}
+
+fn error_response(response: &mut actix_web::HttpResponseBuilder, message: &str) -> HttpResponse {
+    response.json(json!({ "error": { "message": message } }))
+}
+
+fn check_api_key(req: &HttpRequest, state: &MockState) -> Option<HttpResponse> {
+    let api_key = state.args.api_key.as_ref()?;
+    let authorization = req
+        .headers()
+        .get("authorization")
+        .and_then(|value| value.to_str().ok());
+    if authorization != Some(&format!("Bearer {api_key}")) {
+        return Some(error_response(
+            &mut HttpResponse::Unauthorized(),
+            "Invalid API key",
+        ));
+    }
+    None
+}
+
+#[post("/v1/embeddings")]
+async fn embeddings(
+    req: HttpRequest,
+    body: web::Json<EmbeddingRequest>,
+    state: web::Data<MockState>,
+) -> HttpResponse {
+    let request_number = state.request_count.fetch_add(1, Ordering::SeqCst) + 1;
+
+    if let Some(response) = check_api_key(&req, &state) {
+        return response;
+    }
+
+    if let Some(every) = state.args.rate_limit_every {
+        if every > 0 && request_number % every == 0 {
+            return error_response(
+                HttpResponse::TooManyRequests().insert_header(("Retry-After", "1")),
+                "Rate limit exceeded",
+            );
+        }
+    }
+
+    if state.args.latency_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(state.args.latency_ms)).await;
+    }
+
+    let inputs = match get_inputs(&body.input) {
+        Ok(inputs) => inputs,
+        Err(e) => return error_response(&mut HttpResponse::BadRequest(), &e),
+    };
+    let dimensions = body.dimensions.unwrap_or(state.args.dimensions);
+    if dimensions == 0 {
+        return error_response(
+            &mut HttpResponse::BadRequest(),
+            "'dimensions' should be greater than 0",
+        );
+    }
+
+    let total_tokens: usize = inputs.iter().map(|(_, tokens)| tokens).sum();
+    let data: Vec<Value> = inputs
+        .iter()
+        .enumerate()
+        .map(|(index, (text, _))| {
+            json!({
+                "object": "embedding",
+                "index": index,
+                "embedding": get_mock_embedding(text, dimensions),
+            })
+        })
+        .collect();
+
+    HttpResponse::Ok().json(json!({
+        "object": "list",
+        "data": data,
+        "model": body.model.as_deref().unwrap_or(MOCK_MODEL_NAME),
+        "usage": { "prompt_tokens": total_tokens, "total_tokens": total_tokens },
+    }))
+}
+
+// Text Embeddings Inference compatible endpoint, responds with list of embeddings
+#[post("/embed")]
+async fn tei_embed(
+    req: HttpRequest,
+    body: web::Json<TeiEmbedRequest>,
+    state: web::Data<MockState>,
+) -> HttpResponse {
+    state.request_count.fetch_add(1, Ordering::SeqCst);
+
+    if let Some(response) = check_api_key(&req, &state) {
+        return response;
+    }
+
+    if state.args.latency_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(state.args.latency_ms)).await;
+    }
+
+    let inputs = match get_inputs(&body.inputs) {
+        Ok(inputs) => inputs,
+        Err(e) => return error_response(&mut HttpResponse::BadRequest(), &e),
+    };
+
+    // Mock embeddings are always unit length, so scale them when normalization is disabled
+    let scale = if body.normalize.unwrap_or(true) {
+        1.0
+    } else {
+        2.0
+    };
+    let result: Vec<Vec<f32>> = inputs
+        .iter()
+        .map(|(text, _)| {
+            get_mock_embedding(text, state.args.dimensions)
+                .into_iter()
+                .map(|v| v * scale)
+                .collect()
+        })
+        .collect();
+
+    HttpResponse::Ok().json(result)
+}
+
+// Cohere compatible rerank endpoint
+// Score is the cosine similarity of mock embeddings mapped to [0, 1], so identical texts score 1
+#[post("/v1/rerank")]
+async fn rerank(
+    req: HttpRequest,
+    body: web::Json<RerankRequest>,
+    state: web::Data<MockState>,
+) -> HttpResponse {
+    state.request_count.fetch_add(1, Ordering::SeqCst);
+
+    if let Some(response) = check_api_key(&req, &state) {
+        return response;
+    }
+
+    if state.args.latency_ms > 0 {
+        tokio::time::sleep(Duration::from_millis(state.args.latency_ms)).await;
+    }
+
+    let query = get_mock_embedding(&body.query, state.args.dimensions);
+    let mut results: Vec<(usize, f32)> = body
+        .documents
+        .iter()
+        .enumerate()
+        .map(|(index, document)| {
+            let document = get_mock_embedding(document, state.args.dimensions);
+            let similarity: f32 = query.iter().zip(&document).map(|(a, b)| a * b).sum();
+            (index, (similarity + 1.0) / 2.0)
+        })
+        .collect();
+    results.sort_by(|(_, a), (_, b)| b.partial_cmp(a).unwrap_or(std::cmp::Ordering::Equal));
+    results.truncate(body.top_n.unwrap_or(results.len()));
+
+    let results: Vec<Value> = results
+        .into_iter()
+        .map(|(index, score)| json!({ "index": index, "relevance_score": score }))
+        .collect();
+    HttpResponse::Ok().json(json!({ "results": results }))
+}
+
+#[get("/v1/models")]
+async fn models() -> HttpResponse {
+    HttpResponse::Ok().json(json!({
+        "object": "list",
+        "data": [{ "id": MOCK_MODEL_NAME, "object": "model", "owned_by": "lantern" }],
+    }))
+}
+
+#[get("/health")]
+async fn health() -> HttpResponse {
+    HttpResponse::Ok().body("OK")
+}
+
+// OpenAI compatible server returning deterministic pseudo-embeddings
+// Use it with openai-compat runtime to test pipelines without API keys
+#[actix_web::main]
+pub async fn start(args: cli::MockProviderArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Mock Provider", LogLevel::Debug));
+    logger.info(&format!(
+        "Starting mock provider on http://{host}:{port}/v1/embeddings",
+        host = args.host,
+        port = args.port,
+    ));
+
+    let bind_address = (args.host.clone(), args.port);
+    let state = web::Data::new(MockState {
+        args,
+        request_count: AtomicUsize::new(0),
+    });
+
+    HttpServer::new(move || {
+        App::new()
+            .app_data(state.clone())
+            .app_data(web::JsonConfig::default().limit(1024 * 1024 * 1024))
+            .service(embeddings)
+            .service(tei_embed)
+            .service(rerank)
+            .service(models)
+            .service(health)
+    })
+    .bind(bind_address)?
+    .run()
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,43 @@
+use rayon::prelude::*;
+
+use super::cli::Accel;
+use super::distance::{l2sq_dist, l2sq_dist_scalar};
+
+pub fn get_distance_fn(accel: Accel) -> fn(&[f32], &[f32]) -> f32 {
+    match accel {
+        Accel::Cpu => l2sq_dist_scalar,
+        // Distances which are not batched are computed with SIMD in gpu mode
+        Accel::Simd | Accel::Gpu => l2sq_dist,
+    }
+}
+
+// Returns index of the closest centroid for each vector
+pub fn assign(
+    vectors: &[&[f32]],
+    centroids: &[Vec<f32>],
+    accel: Accel,
+) -> Result<Vec<usize>, anyhow::Error> {
+    if accel == Accel::Gpu {
+        #[cfg(feature = "pq-gpu")]
+        return super::gpu::assign(vectors, centroids);
+        #[cfg(not(feature = "pq-gpu"))]
+        anyhow::bail!("--accel gpu requires lantern-cli to be built with \"pq-gpu\" feature");
+    }
+
+    let distance_fn = get_distance_fn(accel);
+    Ok(vectors
+        .par_iter()
+        .map(|vec| {
+            let mut closest_distance = f32::MAX;
+            let mut closest_index = 0;
+            for (idx, centroid) in centroids.iter().enumerate() {
+                let distance = distance_fn(centroid, vec);
+                if distance < closest_distance {
+                    closest_distance = distance;
+                    closest_index = idx;
+                }
+            }
+            closest_index
+        })
+        .collect())
+}
@@ -1,4 +1,51 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+pub enum KmeansInit {
+    /// Centroids are picked with probability proportional to distance from already picked ones
+    Kmeanspp,
+    /// Centroids are picked randomly from the dataset
+    Random,
+}
+
+impl fmt::Display for KmeansInit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KmeansInit::Kmeanspp => write!(f, "kmeanspp"),
+            KmeansInit::Random => write!(f, "random"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum Accel {
+    /// Scalar distance computation
+    Cpu,
+    /// Distances are computed with AVX2 or NEON instructions detected at runtime
+    /// (AVX-512 with "pq-avx512" feature)
+    Simd,
+    /// Closest centroids are searched with a compute shader, needs "pq-gpu" feature
+    Gpu,
+}
+
+impl fmt::Display for Accel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Accel::Cpu => write!(f, "cpu"),
+            Accel::Simd => write!(f, "simd"),
+            Accel::Gpu => write!(f, "gpu"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CodebookFormat {
+    /// Versioned little-endian binary format
+    Binary,
+    /// JSON document with the same fields as binary format
+    Json,
+}
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -35,18 +82,64 @@ pub struct PQArgs {
     #[arg(long)]
     pub dataset_size: Option<usize>,
 
-    /// Cluster count for kmeans
-    #[arg(long, default_value_t = 256)]
+    /// Cluster count for kmeans, up to 65536. Codes of codebooks with more than 256 clusters are
+    /// stored in INT2[] column instead of PQVEC
+    #[arg(long, visible_alias = "cluster-count", default_value_t = 256)]
     pub clusters: usize,
 
     /// Subvector count to split vector
     #[arg(long, default_value_t = 1)]
     pub splits: usize,
 
+    /// Initialization method of kmeans centroids
+    #[arg(long, default_value_t = KmeansInit::Kmeanspp)]
+    pub kmeans_init: KmeansInit,
+
+    /// Maximum number of kmeans iterations for each subvector
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..))]
+    pub kmeans_iters: u64,
+
+    /// Kmeans stops when centroids move less than this distance in an iteration
+    #[arg(long, default_value_t = 0.1)]
+    pub kmeans_tolerance: f32,
+
+    /// Hardware used for distance computation in kmeans and vector quantization
+    #[arg(long, default_value_t = Accel::Simd)]
+    pub accel: Accel,
+
+    /// If set, codebook is trained with mini-batch kmeans over batches of this size streamed from
+    /// the table, so the dataset is not loaded in memory. --kmeans-iters limits passes over the table
+    #[arg(long)]
+    pub kmeans_batch_size: Option<usize>,
+
     /// Subvector part to process
     #[arg(long)]
     pub subvector_id: Option<usize>,
 
+    /// If true, a rotation matrix is learned before splitting vectors into subvectors (optimized
+    /// product quantization). Can not be used with --subvector-id or --run-on-gcp
+    #[arg(long, default_value_t = false)]
+    pub opq: bool,
+
+    /// Number of rotation training iterations for --opq
+    #[arg(long, default_value_t = 4)]
+    pub opq_iterations: usize,
+
+    /// If set, vectors are assigned to this many coarse centroids and the codebook is trained on
+    /// residuals from them (IVF-PQ). Coarse centroid ids are written to {column}_ivf column
+    #[arg(long, conflicts_with_all = ["opq", "kmeans_batch_size", "subvector_id"])]
+    pub coarse_clusters: Option<usize>,
+
+    /// Name of a centroid table in --schema created with `pq train-centroids`. Its centroids are
+    /// used as coarse centroids of IVF-PQ codebook instead of training them
+    #[arg(long, conflicts_with_all = ["coarse_clusters", "opq", "kmeans_batch_size", "subvector_id", "import_codebook"])]
+    pub coarse_centroids_table: Option<String>,
+
+    /// Path of a codebook file created with `pq export-codebook`. The codebook is imported
+    /// instead of being trained on the table
+    #[arg(long, conflicts_with_all = ["opq", "kmeans_batch_size", "subvector_id", "skip_codebook_creation", "coarse_clusters"])]
+    pub import_codebook: Option<String>,
+
     /// If true, codebook table will not be created and pq column will not be added to table. So
     /// they should be set up externally
     #[arg(long, default_value_t = false)]
@@ -80,6 +173,11 @@ pub struct PQArgs {
     #[arg(long)]
     pub quantization_task_id: Option<usize>,
 
+    /// If true, nothing is computed and overall progress of tasks run with --subvector-id or
+    /// --quantization-task-id is reported until all of them are finished
+    #[arg(long, default_value_t = false, conflicts_with_all = ["subvector_id", "quantization_task_id", "run_on_gcp"])]
+    pub watch: bool,
+
     // GCP ARGS
     /// If true job will be submitted to gcp
     #[arg(long, default_value_t = false)]
@@ -135,3 +233,181 @@ pub struct PQArgs {
     #[arg(long)]
     pub gcp_quantization_memory_gb: Option<usize>,
 }
+
+#[derive(Subcommand, Debug)]
+pub enum PQCommands {
+    /// Measure recall and distance distortion of product quantization
+    Evaluate(PQEvaluateArgs),
+    /// Export codebook table to a file, which can be imported with `pq-table --import-codebook`
+    ExportCodebook(PQExportCodebookArgs),
+    /// Cluster vectors into lists and store the list of each row for IVF partition pruning
+    TrainCentroids(PQTrainCentroidsArgs),
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct PQCommandArgs {
+    #[command(subcommand)]
+    pub command: PQCommands,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None)]
+pub struct PQEvaluateArgs {
+    /// Fully associated database connection string including db name
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Table name
+    #[arg(short, long)]
+    pub table: String,
+
+    /// Schema name
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Column name with uncompressed vectors
+    #[arg(short, long)]
+    pub column: String,
+
+    /// Primary key of the table
+    #[arg(long, default_value = "id")]
+    pub pk: String,
+
+    /// Name of existing codebook table. default: pq_{table}_{column}
+    #[arg(long, conflicts_with = "splits")]
+    pub codebook_table_name: Option<String>,
+
+    /// Subvector count. If specified, a temporary codebook is trained on the loaded vectors
+    /// instead of reading the codebook table
+    #[arg(long)]
+    pub splits: Option<usize>,
+
+    /// Cluster count for the temporary codebook, up to 65536
+    #[arg(long, visible_alias = "cluster-count", default_value_t = 256)]
+    pub clusters: usize,
+
+    /// Initialization method of kmeans centroids
+    #[arg(long, default_value_t = KmeansInit::Kmeanspp)]
+    pub kmeans_init: KmeansInit,
+
+    /// Maximum number of kmeans iterations for each subvector
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..))]
+    pub kmeans_iters: u64,
+
+    /// Kmeans stops when centroids move less than this distance in an iteration
+    #[arg(long, default_value_t = 0.1)]
+    pub kmeans_tolerance: f32,
+
+    /// Hardware used for distance computation in kmeans and vector quantization
+    #[arg(long, default_value_t = Accel::Simd)]
+    pub accel: Accel,
+
+    /// If true, the temporary codebook is trained with optimized product quantization rotation
+    #[arg(long, default_value_t = false, requires = "splits")]
+    pub opq: bool,
+
+    /// Number of rotation training iterations for --opq
+    #[arg(long, default_value_t = 4)]
+    pub opq_iterations: usize,
+
+    /// If set, the temporary codebook is trained on residuals from this many coarse centroids
+    #[arg(long, requires = "splits", conflicts_with = "opq")]
+    pub coarse_clusters: Option<usize>,
+
+    /// Number of rows sampled as query vectors
+    #[arg(long, default_value_t = 100)]
+    pub queries: usize,
+
+    /// Number of nearest neighbours to compare
+    #[arg(short, long, default_value_t = 10)]
+    pub k: usize,
+
+    /// Number of randomly sampled rows used as the dataset. default: all rows
+    #[arg(long)]
+    pub dataset_limit: Option<usize>,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None)]
+pub struct PQExportCodebookArgs {
+    /// Fully associated database connection string including db name
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Table name, used to get the default codebook table name
+    #[arg(short, long, required_unless_present = "codebook_table_name")]
+    pub table: Option<String>,
+
+    /// Column name, used to get the default codebook table name
+    #[arg(short, long, required_unless_present = "codebook_table_name")]
+    pub column: Option<String>,
+
+    /// Name of codebook table. default: pq_{table}_{column}
+    #[arg(long)]
+    pub codebook_table_name: Option<String>,
+
+    /// Output file path
+    #[arg(short, long)]
+    pub out: String,
+
+    /// File format. default: json if output file has .json extension, binary otherwise
+    #[arg(long)]
+    pub format: Option<CodebookFormat>,
+}
+
+#[derive(Parser, Debug, Clone)]
+#[command(version, about, long_about = None)]
+pub struct PQTrainCentroidsArgs {
+    /// Fully associated database connection string including db name
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Table name
+    #[arg(short, long)]
+    pub table: String,
+
+    /// Schema name
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Column name with vectors
+    #[arg(short, long)]
+    pub column: String,
+
+    /// Primary key of the table
+    #[arg(long, default_value = "id")]
+    pub pk: String,
+
+    /// Number of lists to split vectors into
+    #[arg(long, visible_alias = "lists")]
+    pub clusters: usize,
+
+    /// Name of centroid table created in --schema. default: {table}_{column}_centroids
+    #[arg(long)]
+    pub centroids_table: Option<String>,
+
+    /// Number of randomly sampled rows used to train centroids. default: all rows
+    #[arg(long)]
+    pub dataset_limit: Option<usize>,
+
+    /// Initialization method of kmeans centroids
+    #[arg(long, default_value_t = KmeansInit::Kmeanspp)]
+    pub kmeans_init: KmeansInit,
+
+    /// Maximum number of kmeans iterations
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u64).range(1..))]
+    pub kmeans_iters: u64,
+
+    /// Kmeans stops when centroids move less than this distance in an iteration
+    #[arg(long, default_value_t = 0.1)]
+    pub kmeans_tolerance: f32,
+
+    /// Hardware used for distance computation in kmeans and list assignment
+    #[arg(long, default_value_t = Accel::Simd)]
+    pub accel: Accel,
+
+    /// If true, only centroid table is created and {column}_ivf column is not filled
+    #[arg(long, default_value_t = false)]
+    pub skip_assignment: bool,
+}
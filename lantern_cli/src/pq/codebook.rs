@@ -7,64 +7,100 @@ use std::io::Write;
 use std::sync::atomic::AtomicU8;
 use std::sync::Arc;
 use std::time::Instant;
-use postgres::{Client, NoTls, Transaction};
+use postgres::{Client, GenericClient, NoTls, Transaction};
 
+use super::kmeans::{kmeans, KmeansParams, MiniBatchKmeans};
+use super::quantization::l2sq_dist;
+use super::ivf;
+use super::keyset::{get_key_ranges, get_pk_type};
+use super::opq;
+use super::validation::validate_coarse_centroids;
 use super::{set_and_report_progress, report_progress, DatasetItem};
-use linfa::traits::Fit;
-use linfa::DatasetBase;
-use linfa_clustering::KMeans;
 use ndarray::Array2;
 
 // Will run kmeans over dataset and return centroids
 pub fn create_codebook_for_subset(
     dataset: Vec<&[f32]>,
     cluster_count: usize,
+    kmeans_params: &KmeansParams,
     subvector_id: usize,
     logger: &Logger,
 ) -> Result<Vec<Vec<f32>>, anyhow::Error> {
-    let dim = dataset[0].len();
-    let dataset_creation_time = Instant::now();
-    let observations = DatasetBase::from(Array2::from_shape_vec(
-        (dataset.len(), dim),
-        dataset
-            .iter()
-            .cloned()
-            .map(|s| s.to_vec())
-            .flatten()
-            .collect(),
-    )?);
-    logger.debug(&format!(
-        "Subset {subvector_id} convert slice to ndarray duration: {}s",
-        dataset_creation_time.elapsed().as_secs()
-    ));
-
-    let kmeans_iteration_time = Instant::now();
-    let rng = rand::thread_rng();
-    let model = KMeans::params_with_rng(cluster_count, rng.clone())
-        .tolerance(1e-1)
-        .n_runs(1)
-        .max_n_iterations(20)
-        .fit(&observations)?;
+    let kmeans_time = Instant::now();
+    let centroids = kmeans(&dataset, cluster_count, kmeans_params, subvector_id, logger)?;
 
     logger.debug(&format!(
-        "Subset {subvector_id} kmeans iteration duration: {}s",
-        kmeans_iteration_time.elapsed().as_secs()
+        "Subset {subvector_id} kmeans duration: {}s",
+        kmeans_time.elapsed().as_secs()
     ));
+    Ok(centroids)
+}
 
-    let centroid_extracton_time = Instant::now();
-    let centroids = model
-        .centroids()
-        .into_iter()
-        .cloned()
-        .collect::<Vec<f32>>()
-        .chunks(dim)
-        .map(|s| s.to_vec())
-        .collect();
+// Write the generated centroids in codebook table
+pub fn write_codebook<'a>(
+    transaction: &mut Transaction<'a>,
+    codebook_table_name: &str,
+    all_centroids: Vec<(usize, Vec<Vec<f32>>)>,
+    logger: &Logger,
+) -> Result<HashMap<usize, Vec<Vec<f32>>>, anyhow::Error> {
+    let codebook_write_time_start = Instant::now();
+    let mut codebooks_hashmap: HashMap<usize, Vec<Vec<f32>>> = HashMap::new();
+    let mut writer = transaction.copy_in(&format!("COPY {codebook_table_name} FROM stdin", codebook_table_name = codebook_table_name))?;
+    for (subvector_id, centroids) in all_centroids {
+        for (centroid_id, centroid) in centroids.iter().enumerate() {
+            writer.write(subvector_id.to_string().as_bytes())?;
+            writer.write("\t".as_bytes())?;
+            writer.write(centroid_id.to_string().as_bytes())?;
+            writer.write("\t".as_bytes())?;
+            writer.write("{".as_bytes())?;
+            let row_str: String = centroid.iter().map(|&x| x.to_string() + ",").collect();
+            writer.write(row_str[0..row_str.len() - 1].as_bytes())?;
+            writer.write("}".as_bytes())?;
+            writer.write("\n".as_bytes())?;
+        }
+        codebooks_hashmap.insert(subvector_id, centroids);
+    }
+    
+    writer.flush()?;
+    writer.finish()?;
+ 
     logger.debug(&format!(
-        "Subset {subvector_id} centroid extraction duration: {}s",
-        centroid_extracton_time.elapsed().as_secs()
+        "Codebook write duration: {}s",
+        codebook_write_time_start.elapsed().as_secs()
     ));
-    Ok(centroids)
+    Ok(codebooks_hashmap)
+}
+
+// Read codebook table into { [subvector_id]: centroids ordered by centroid_id }
+pub fn read_codebook(
+    client: &mut impl GenericClient,
+    full_codebook_table_name: &str,
+) -> Result<HashMap<usize, Vec<Vec<f32>>>, anyhow::Error> {
+    let rows = client.query(
+        &format!("SELECT subvector_id, centroid_id, c FROM {full_codebook_table_name} ORDER BY subvector_id, centroid_id;"),
+        &[],
+    )?;
+
+    let mut codebooks_hashmap: HashMap<usize, Vec<Vec<f32>>> = HashMap::new();
+    for row in rows {
+        let subvector_id = row.get::<usize, i32>(0) as usize;
+        let centroid_id = row.get::<usize, i32>(1);
+        let centroids = codebooks_hashmap.entry(subvector_id).or_default();
+        // Centroid ids are used as PQ codes, so they should be dense from 0
+        if centroid_id != centroids.len() as i32 {
+            anyhow::bail!(
+                "Centroid ids of subvector {subvector_id} in {full_codebook_table_name} should be 0..k-1 without gaps or duplicates, found {centroid_id} at position {}",
+                centroids.len()
+            );
+        }
+        centroids.push(row.get::<usize, Vec<f32>>(2));
+    }
+
+    if codebooks_hashmap.is_empty() {
+        anyhow::bail!("Codebook table {full_codebook_table_name} is empty");
+    }
+
+    Ok(codebooks_hashmap)
 }
 
 pub struct CreateCodebookArgs<'a> {
@@ -76,23 +112,31 @@ pub struct CreateCodebookArgs<'a> {
    pub column: &'a str,
    pub full_table_name: &'a str,
    pub codebook_table_name: &'a str,
+   pub rotation_table_name: &'a str,
+   pub coarse_table_name: &'a str,
    pub total_row_count: usize,
    pub max_connections: usize,
    pub splits: usize,
    pub vector_dim: usize,
    pub subvector_dim: usize,
    pub cluster_count: usize,
+   pub kmeans_params: KmeansParams,
    pub start_offset_id: usize,
    pub subvector_id: &'a Option<usize>,
    pub parallel_task_count: &'a Option<usize>,
+   pub opq_iterations: Option<usize>,
+   pub coarse_clusters: Option<usize>,
+   // Centroids trained with `pq train-centroids`, which are used instead of training coarse centroids
+   pub coarse_centroids: Option<Vec<Vec<f32>>>,
 }
 
 pub fn create_codebook<'a> (
     args: CreateCodebookArgs, transaction: &mut Transaction<'a>)
- -> Result<(HashMap<usize, Vec<Vec<f32>>>, Arc<Vec<DatasetItem>>), anyhow::Error> {
+ -> Result<(HashMap<usize, Vec<Vec<f32>>>, Arc<Vec<DatasetItem>>, Option<Array2<f32>>, Option<Vec<Vec<f32>>>), anyhow::Error> {
 
     let logger = args.logger;
     let cluster_count = args.cluster_count;
+    let kmeans_params = args.kmeans_params;
     let progress_cb = args.progress_cb;
     let main_progress = args.main_progress;
     let codebook_table_name = args.codebook_table_name;
@@ -159,24 +203,26 @@ pub fn create_codebook<'a> (
     // (the indices will be 0;vector_dim)
     // Data will be fetched in parallel and then merged to speed up the fetch time
     
-    let rows = (0..num_connections)
+    // Dataset is taken by row positions in primary key order, so keys do not need to be dense
+    let pk_type = get_pk_type(transaction, full_table_name, pk)?;
+    let ranges = get_key_ranges(transaction, full_table_name, pk, start_offset_id, Some(start_offset_id + total_row_count), chunk_size)?;
+    let rows = ranges
         .into_par_iter()
-        .map(|i| {
+        .map(|range| {
             let mut client = Client::connect(db_uri, NoTls)?;
             let mut transaction = client.transaction()?;
-            let range_start = i * chunk_size + start_offset_id;
-            let range_end = if i == num_cores - 1 { start_offset_id + total_row_count + 1 } else { range_start + chunk_size };
 
             let fetch_start_time = Instant::now();
+            let (range_condition, range_params) = range.condition(pk, &pk_type);
             let rows = transaction.query(
                 &format!(
-                    "SELECT {pk}::text, {column}[{start_idx}:{end_idx}] FROM {full_table_name} WHERE {pk} >= {range_start} AND {pk} < {range_end} ORDER BY id;",
+                    "SELECT {pk}::text, {column}[{start_idx}:{end_idx}] FROM {full_table_name} WHERE {range_condition} ORDER BY {pk};",
                     pk = quote_ident(&pk),
                     column = quote_ident(column),
                     start_idx = subvector_start_idx + 1,
                     end_idx = subvector_end_idx + 1,
                 ),
-                &[],
+                &range_params,
             )?;
    
             logger.info(&format!(
@@ -221,16 +267,37 @@ pub fn create_codebook<'a> (
     // progress indicator is: 5% load, 70% codebook, 15% quantization, 10% export
     report_progress(&progress_cb, &logger, &args.main_progress, 5);
 
-    let mut codebooks_hashmap: HashMap<usize, Vec<Vec<f32>>> = HashMap::new();
     let codebook_creation_start = Instant::now();
     logger.info(&format!(
-        "Starting kmeans with params (cluster_count={cluster_count}, subset_count={splits})",
+        "Starting kmeans with params (cluster_count={cluster_count}, subset_count={splits}, init={init}, max_iterations={max_iterations}, tolerance={tolerance})",
         cluster_count = cluster_count,
-        splits = splits
+        splits = splits,
+        init = kmeans_params.init,
+        max_iterations = kmeans_params.max_iterations,
+        tolerance = kmeans_params.tolerance,
     ));
  
+    // In IVF-PQ mode codebook is trained on residuals from coarse centroids
+    // The original dataset is returned, as coarse ids are assigned again on quantization
     let dataset = Arc::new(dataset);
-    let dataset_clone = dataset.clone();
+    let mut coarse_centroids = None;
+    let training_dataset = match args.coarse_clusters {
+        Some(coarse_clusters) => {
+            let centroids = match &args.coarse_centroids {
+                Some(centroids) => {
+                    validate_coarse_centroids(centroids, vector_dim)?;
+                    centroids.clone()
+                }
+                None => ivf::train_coarse_centroids(&dataset, coarse_clusters, &kmeans_params, logger)?,
+            };
+            ivf::write_coarse_centroids(transaction, args.coarse_table_name, &centroids)?;
+            let (_, residuals) = ivf::compute_residuals(&dataset, &centroids, kmeans_params.accel)?;
+            coarse_centroids = Some(centroids);
+            Arc::new(residuals)
+        }
+        None => dataset.clone(),
+    };
+    let dataset_clone = training_dataset.clone();
 
     // If this is for all subvectors the range will be 0;$splits
     // If this is for one subvector the range will be $subvector_id;($subvector_id+1)
@@ -240,36 +307,54 @@ pub fn create_codebook<'a> (
 
 
     let progress_per_chunk = 70.0 / (subvector_count) as f32;
-    let all_centroids: Vec<(usize, Vec<Vec<f32>>)> = (subvector_range_start..subvector_range_end)
-        .into_par_iter()
-        .enumerate()
-        .map_with(dataset_clone, |dataset, (i, subvector_id)| {
-            let training_time_start = Instant::now();
-            let start_index = i * subvector_dim;
-            let end_index = start_index + subvector_dim;
+    let mut rotation = None;
+    let all_centroids: Vec<(usize, Vec<Vec<f32>>)> = if let Some(opq_iterations) = args.opq_iterations {
+        // Rotation and codebooks are trained together, so all subvectors are processed here
+        let (rotation_matrix, codebooks) = opq::train_rotation(
+            &dataset,
+            vector_dim,
+            splits,
+            subvector_dim,
+            cluster_count,
+            &kmeans_params,
+            opq_iterations,
+            logger,
+        )?;
+        opq::write_rotation(transaction, args.rotation_table_name, &rotation_matrix)?;
+        rotation = Some(rotation_matrix);
+        codebooks.into_iter().collect()
+    } else {
+        (subvector_range_start..subvector_range_end)
+            .into_par_iter()
+            .enumerate()
+            .map_with(dataset_clone, |dataset, (i, subvector_id)| {
+                let training_time_start = Instant::now();
+                let start_index = i * subvector_dim;
+                let end_index = start_index + subvector_dim;
 
-            let subset_dataset = dataset
-                .iter()
-                .map(|r| &r.vec[start_index..end_index])
-                .collect::<Vec<&[f32]>>();
-            // Prallel iterate over the subvectors and run kmeans returning centroids
-            let centroids =
-                create_codebook_for_subset(subset_dataset, cluster_count, subvector_id, &logger).unwrap();
-
-            logger.debug(&format!(
-                "Subset {subvector_id} training duration: {}s",
-                training_time_start.elapsed().as_secs()
-            ));
+                let subset_dataset = dataset
+                    .iter()
+                    .map(|r| &r.vec[start_index..end_index])
+                    .collect::<Vec<&[f32]>>();
+                // Prallel iterate over the subvectors and run kmeans returning centroids
+                let centroids =
+                    create_codebook_for_subset(subset_dataset, cluster_count, &kmeans_params, subvector_id, &logger).unwrap();
 
-            report_progress(
-                &progress_cb,
-                &logger,
-                &main_progress,
-                progress_per_chunk as u8,
-            );
-            (subvector_id, centroids)
-        })
-        .collect();
+                logger.debug(&format!(
+                    "Subset {subvector_id} training duration: {}s",
+                    training_time_start.elapsed().as_secs()
+                ));
+
+                report_progress(
+                    &progress_cb,
+                    &logger,
+                    &main_progress,
+                    progress_per_chunk as u8,
+                );
+                (subvector_id, centroids)
+            })
+            .collect()
+    };
 
     set_and_report_progress(
         &progress_cb,
@@ -277,37 +362,184 @@ pub fn create_codebook<'a> (
         &main_progress,
         75 as u8,
     );
-    let codebook_write_time_start = Instant::now();
- 
-    // Write the generated centroids in codebook table
-    let mut writer = transaction.copy_in(&format!("COPY {codebook_table_name} FROM stdin", codebook_table_name = codebook_table_name))?;
-    for (subvector_id, centroids) in all_centroids {
-        for (centroid_id, centroid) in centroids.iter().enumerate() {
-            writer.write(subvector_id.to_string().as_bytes())?;
-            writer.write("\t".as_bytes())?;
-            writer.write(centroid_id.to_string().as_bytes())?;
-            writer.write("\t".as_bytes())?;
-            writer.write("{".as_bytes())?;
-            let row_str: String = centroid.iter().map(|&x| x.to_string() + ",").collect();
-            writer.write(row_str[0..row_str.len() - 1].as_bytes())?;
-            writer.write("}".as_bytes())?;
-            writer.write("\n".as_bytes())?;
-            codebooks_hashmap.insert(subvector_id, centroids.clone());
-        }
-    }
-    
-    writer.flush()?;
-    writer.finish()?;
- 
+    let codebooks_hashmap = write_codebook(transaction, codebook_table_name, all_centroids, logger)?;
+
     logger.debug(&format!(
-        "Codebook write duration: {}s",
-        codebook_write_time_start.elapsed().as_secs()
+        "Codebook creation duration: {}s",
+        codebook_creation_start.elapsed().as_secs()
+    ));
+
+    Ok((codebooks_hashmap, dataset, rotation, coarse_centroids))
+}
+
+// Mini-batch kmeans over batches streamed from the table through a portal,
+// so memory usage depends on batch size instead of dataset size
+// Each pass reads a new random sample of total_row_count vectors
+pub fn create_codebook_mini_batch<'a>(
+    args: CreateCodebookArgs,
+    batch_size: usize,
+    transaction: &mut Transaction<'a>,
+) -> Result<HashMap<usize, Vec<Vec<f32>>>, anyhow::Error> {
+    let logger = args.logger;
+    let cluster_count = args.cluster_count;
+    let kmeans_params = args.kmeans_params;
+    let splits = args.splits;
+    let subvector_dim = args.subvector_dim;
+
+    if batch_size < cluster_count {
+        anyhow::bail!(
+            "--kmeans-batch-size ({batch_size}) should be greater than or equal to cluster count ({cluster_count})"
+        );
+    }
+
+    let subvector_ids: Vec<usize> = match args.subvector_id {
+        Some(subvector_id) => {
+            if *subvector_id >= splits {
+                anyhow::bail!(
+                    "--subvector-id {subvector_id} should be smaller than --splits {}",
+                    splits
+                );
+            }
+            vec![*subvector_id]
+        }
+        None => (0..splits).collect(),
+    };
+    let subvector_start_idx = subvector_ids[0] * subvector_dim;
+    let subvector_end_idx = (subvector_ids[subvector_ids.len() - 1] + 1) * subvector_dim;
+
+    logger.info(&format!(
+        "Starting mini-batch kmeans with params (cluster_count={cluster_count}, subset_count={}, batch_size={batch_size}, init={}, max_passes={}, tolerance={})",
+        subvector_ids.len(),
+        kmeans_params.init,
+        kmeans_params.max_iterations,
+        kmeans_params.tolerance,
     ));
 
+    let query = format!(
+        "SELECT {column}[{start_idx}:{end_idx}] FROM {full_table_name} WHERE {column} IS NOT NULL ORDER BY random() LIMIT {limit}",
+        column = quote_ident(args.column),
+        full_table_name = args.full_table_name,
+        start_idx = subvector_start_idx + 1,
+        end_idx = subvector_end_idx,
+        limit = args.total_row_count,
+    );
+
+    let codebook_creation_start = Instant::now();
+    let mut models: Vec<MiniBatchKmeans> = Vec::new();
+    let mut converged = false;
+    let mut pass = 0;
+    let mut shift = f32::MAX;
+    while pass < kmeans_params.max_iterations {
+        pass += 1;
+        let pass_start = Instant::now();
+        let previous_centroids: Vec<Vec<Vec<f32>>> =
+            models.iter().map(|model| model.centroids.clone()).collect();
+
+        let portal = transaction.bind(query.as_str(), &[])?;
+        let mut row_count = 0;
+        loop {
+            let rows = transaction.query_portal(&portal, batch_size as i32)?;
+            if rows.is_empty() {
+                break;
+            }
+            row_count += rows.len();
+            let batch: Vec<Vec<f32>> = rows
+                .iter()
+                .map(|row| row.get::<usize, Vec<f32>>(0))
+                .collect();
+
+            if models.is_empty() {
+                if batch.len() < cluster_count {
+                    anyhow::bail!(
+                        "Dataset size ({}) should be greater than or equal to cluster count ({cluster_count})",
+                        batch.len()
+                    );
+                }
+                models = (0..subvector_ids.len())
+                    .into_par_iter()
+                    .map(|i| {
+                        let subset = batch
+                            .iter()
+                            .map(|vec| &vec[i * subvector_dim..(i + 1) * subvector_dim])
+                            .collect::<Vec<&[f32]>>();
+                        MiniBatchKmeans::new(&subset, cluster_count, &kmeans_params)
+                    })
+                    .collect();
+            }
+
+            models
+                .par_iter_mut()
+                .enumerate()
+                .map(|(i, model)| {
+                    let subset = batch
+                        .iter()
+                        .map(|vec| &vec[i * subvector_dim..(i + 1) * subvector_dim])
+                        .collect::<Vec<&[f32]>>();
+                    model.fit_batch(&subset)
+                })
+                .collect::<Result<(), anyhow::Error>>()?;
+        }
+
+        if row_count == 0 {
+            anyhow::bail!("Table {} does not contain vectors", args.full_table_name);
+        }
+
+        // Largest sum of squared centroid shifts among subvectors
+        shift = if previous_centroids.is_empty() {
+            f32::MAX
+        } else {
+            models
+                .iter()
+                .zip(&previous_centroids)
+                .map(|(model, previous)| {
+                    model
+                        .centroids
+                        .iter()
+                        .zip(previous)
+                        .map(|(centroid, previous)| l2sq_dist(centroid, previous))
+                        .sum::<f32>()
+                })
+                .fold(0.0, f32::max)
+        };
+
+        logger.debug(&format!(
+            "Mini-batch kmeans pass {pass} over {row_count} rows: centroid shift {shift:.6}, duration {}s",
+            pass_start.elapsed().as_secs()
+        ));
+        set_and_report_progress(
+            args.progress_cb,
+            logger,
+            args.main_progress,
+            (5 + 70 * pass / kmeans_params.max_iterations) as u8,
+        );
+
+        if shift < kmeans_params.tolerance {
+            converged = true;
+            break;
+        }
+    }
+
+    if converged {
+        logger.debug(&format!("Mini-batch kmeans converged after {pass} passes"));
+    } else {
+        logger.warn(&format!(
+            "Mini-batch kmeans did not converge in {pass} passes (centroid shift {shift:.6}). Increase --kmeans-iters or --kmeans-tolerance"
+        ));
+    }
+
+    set_and_report_progress(args.progress_cb, logger, args.main_progress, 75);
+    let all_centroids: Vec<(usize, Vec<Vec<f32>>)> = subvector_ids
+        .into_iter()
+        .zip(models)
+        .map(|(subvector_id, model)| (subvector_id, model.centroids))
+        .collect();
+    let codebooks_hashmap =
+        write_codebook(transaction, args.codebook_table_name, all_centroids, logger)?;
+
     logger.debug(&format!(
         "Codebook creation duration: {}s",
         codebook_creation_start.elapsed().as_secs()
     ));
 
-    Ok((codebooks_hashmap, dataset))
+    Ok(codebooks_hashmap)
 }
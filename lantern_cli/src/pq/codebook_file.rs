@@ -0,0 +1,287 @@
+use crate::logger::{LogLevel, Logger};
+use crate::utils::{append_params_to_uri, get_full_table_name};
+use ndarray::Array2;
+use postgres::{Client, NoTls};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+
+use super::cli::{CodebookFormat, PQExportCodebookArgs};
+use super::codebook::read_codebook;
+use super::ivf;
+use super::opq;
+use super::{AnyhowVoidResult, CONNECTION_PARAMS, LANTERN_INTERNAL_SCHEMA_NAME};
+
+// Version is increased on incompatible changes, older versions should still be readable
+pub static CODEBOOK_FILE_VERSION: u32 = 1;
+static BINARY_MAGIC: &[u8; 4] = b"LPQC";
+
+// Binary layout (little-endian):
+// magic "LPQC" | version u32 | splits u32 | clusters u32 | subvector_dim u32 | rotation_dim u32
+// | centroids f32[splits * clusters * subvector_dim] | rotation f32[rotation_dim * rotation_dim]
+// rotation_dim is 0 if codebook was trained without --opq
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct CodebookFile {
+    pub version: u32,
+    pub splits: usize,
+    pub clusters: usize,
+    pub subvector_dim: usize,
+    /// Centroids of each subvector ordered by subvector id and centroid id
+    pub codebooks: Vec<Vec<Vec<f32>>>,
+    /// Rows of OPQ rotation matrix
+    pub rotation: Option<Vec<Vec<f32>>>,
+}
+
+impl CodebookFile {
+    pub fn new(
+        codebooks: HashMap<usize, Vec<Vec<f32>>>,
+        rotation: Option<&Array2<f32>>,
+    ) -> Result<Self, anyhow::Error> {
+        let splits = codebooks.len();
+        let codebooks: Vec<Vec<Vec<f32>>> = (0..splits)
+            .map(|subvector_id| {
+                codebooks.get(&subvector_id).cloned().ok_or(anyhow::anyhow!(
+                    "Incomplete codebook: subvector {subvector_id} is missing"
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let file = CodebookFile {
+            version: CODEBOOK_FILE_VERSION,
+            splits,
+            clusters: codebooks.first().map(|c| c.len()).unwrap_or(0),
+            subvector_dim: codebooks
+                .first()
+                .and_then(|c| c.first())
+                .map(|c| c.len())
+                .unwrap_or(0),
+            codebooks,
+            rotation: rotation.map(|rotation| {
+                rotation
+                    .outer_iter()
+                    .map(|row| row.to_vec())
+                    .collect::<Vec<Vec<f32>>>()
+            }),
+        };
+        file.validate()?;
+        Ok(file)
+    }
+
+    fn validate(&self) -> AnyhowVoidResult {
+        if self.version > CODEBOOK_FILE_VERSION {
+            anyhow::bail!(
+                "Codebook file version {} is not supported, latest supported version is {CODEBOOK_FILE_VERSION}. Please upgrade lantern-cli",
+                self.version
+            );
+        }
+        if self.splits == 0 || self.clusters == 0 || self.subvector_dim == 0 {
+            anyhow::bail!("Codebook file does not contain centroids");
+        }
+        if self.codebooks.len() != self.splits {
+            anyhow::bail!(
+                "Codebook file has {} subvectors, expected {}",
+                self.codebooks.len(),
+                self.splits
+            );
+        }
+        for (subvector_id, centroids) in self.codebooks.iter().enumerate() {
+            if centroids.len() != self.clusters {
+                anyhow::bail!(
+                    "Subvector {subvector_id} has {} centroids, expected {}",
+                    centroids.len(),
+                    self.clusters
+                );
+            }
+            if centroids.iter().any(|c| c.len() != self.subvector_dim) {
+                anyhow::bail!(
+                    "Subvector {subvector_id} has centroids with dimensions other than {}",
+                    self.subvector_dim
+                );
+            }
+        }
+        if let Some(rotation) = &self.rotation {
+            let dim = rotation.len();
+            if dim < self.splits * self.subvector_dim || rotation.iter().any(|r| r.len() != dim) {
+                anyhow::bail!(
+                    "Rotation matrix should be square and cover all subvector dimensions"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    pub fn codebooks_hashmap(&self) -> HashMap<usize, Vec<Vec<f32>>> {
+        self.codebooks.iter().cloned().enumerate().collect()
+    }
+
+    pub fn rotation_matrix(&self) -> Result<Option<Array2<f32>>, anyhow::Error> {
+        match &self.rotation {
+            Some(rotation) => Ok(Some(Array2::from_shape_vec(
+                (rotation.len(), rotation.len()),
+                rotation.concat(),
+            )?)),
+            None => Ok(None),
+        }
+    }
+
+    fn to_bytes(&self) -> Vec<u8> {
+        let rotation_dim = self.rotation.as_ref().map(|r| r.len()).unwrap_or(0);
+        let mut bytes = Vec::with_capacity(
+            24 + 4
+                * (self.splits * self.clusters * self.subvector_dim + rotation_dim * rotation_dim),
+        );
+        bytes.extend_from_slice(BINARY_MAGIC);
+        for value in [
+            self.version,
+            self.splits as u32,
+            self.clusters as u32,
+            self.subvector_dim as u32,
+            rotation_dim as u32,
+        ] {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        let values = self
+            .codebooks
+            .iter()
+            .flatten()
+            .chain(self.rotation.iter().flatten());
+        for row in values {
+            for value in row {
+                bytes.extend_from_slice(&value.to_le_bytes());
+            }
+        }
+        bytes
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<Self, anyhow::Error> {
+        let mut offset = BINARY_MAGIC.len();
+        let mut read_u32 = || -> Result<u32, anyhow::Error> {
+            let value = bytes
+                .get(offset..offset + 4)
+                .ok_or(anyhow::anyhow!("Codebook file is truncated"))?;
+            offset += 4;
+            Ok(u32::from_le_bytes(value.try_into()?))
+        };
+
+        let version = read_u32()?;
+        if version > CODEBOOK_FILE_VERSION {
+            anyhow::bail!(
+                "Codebook file version {version} is not supported, latest supported version is {CODEBOOK_FILE_VERSION}. Please upgrade lantern-cli"
+            );
+        }
+        let splits = read_u32()? as usize;
+        let clusters = read_u32()? as usize;
+        let subvector_dim = read_u32()? as usize;
+        let rotation_dim = read_u32()? as usize;
+        if splits == 0 || clusters == 0 || subvector_dim == 0 {
+            anyhow::bail!("Codebook file does not contain centroids");
+        }
+
+        let codebook_size = splits * clusters * subvector_dim;
+        if bytes.len() - offset != 4 * (codebook_size + rotation_dim * rotation_dim) {
+            anyhow::bail!("Codebook file size does not match its header");
+        }
+        let values: Vec<f32> = bytes[offset..]
+            .chunks_exact(4)
+            .map(|value| f32::from_le_bytes(value.try_into().unwrap()))
+            .collect();
+
+        let (codebook_values, rotation_values) = values.split_at(codebook_size);
+        let file = CodebookFile {
+            version,
+            splits,
+            clusters,
+            subvector_dim,
+            codebooks: codebook_values
+                .chunks(clusters * subvector_dim)
+                .map(|centroids| {
+                    centroids
+                        .chunks(subvector_dim)
+                        .map(|c| c.to_vec())
+                        .collect()
+                })
+                .collect(),
+            rotation: if rotation_dim > 0 {
+                Some(
+                    rotation_values
+                        .chunks(rotation_dim)
+                        .map(|r| r.to_vec())
+                        .collect(),
+                )
+            } else {
+                None
+            },
+        };
+        Ok(file)
+    }
+
+    pub fn write(&self, path: &str, format: CodebookFormat) -> AnyhowVoidResult {
+        let bytes = match format {
+            CodebookFormat::Binary => self.to_bytes(),
+            CodebookFormat::Json => serde_json::to_vec(self)?,
+        };
+        fs::write(path, bytes)?;
+        Ok(())
+    }
+
+    // Format is detected from the file contents
+    pub fn read(path: &str) -> Result<Self, anyhow::Error> {
+        let bytes = fs::read(path)
+            .map_err(|e| anyhow::anyhow!("Failed to read codebook file {path}: {e}"))?;
+        let file = if bytes.starts_with(BINARY_MAGIC) {
+            Self::from_bytes(&bytes)?
+        } else {
+            serde_json::from_slice::<CodebookFile>(&bytes)
+                .map_err(|e| anyhow::anyhow!("Invalid codebook file {path}: {e}"))?
+        };
+        file.validate()?;
+        Ok(file)
+    }
+}
+
+pub fn export_codebook(args: &PQExportCodebookArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern PQ", LogLevel::Debug));
+    logger.info("Lantern CLI - Export Codebook");
+
+    let codebook_table_name = match &args.codebook_table_name {
+        Some(name) => name.clone(),
+        None => format!(
+            "pq_{}_{}",
+            args.table.as_deref().unwrap_or_default(),
+            args.column.as_deref().unwrap_or_default()
+        ),
+    };
+    let full_codebook_table_name =
+        get_full_table_name(LANTERN_INTERNAL_SCHEMA_NAME, &codebook_table_name);
+    let full_rotation_table_name = get_full_table_name(
+        LANTERN_INTERNAL_SCHEMA_NAME,
+        &opq::get_rotation_table_name(&codebook_table_name),
+    );
+
+    let db_uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&db_uri, NoTls)?;
+    // Codebook of IVF-PQ is not usable without coarse centroids, which are not in the file format
+    let full_coarse_table_name = get_full_table_name(
+        LANTERN_INTERNAL_SCHEMA_NAME,
+        &ivf::get_coarse_table_name(&codebook_table_name),
+    );
+    if ivf::read_coarse_centroids(&mut client, &full_coarse_table_name)?.is_some() {
+        anyhow::bail!("Codebook trained with --coarse-clusters can not be exported");
+    }
+    let codebooks = read_codebook(&mut client, &full_codebook_table_name)?;
+    let rotation = opq::read_rotation(&mut client, &full_rotation_table_name)?;
+    let file = CodebookFile::new(codebooks, rotation.as_ref())?;
+
+    let format = args.format.unwrap_or(if args.out.ends_with(".json") {
+        CodebookFormat::Json
+    } else {
+        CodebookFormat::Binary
+    });
+    file.write(&args.out, format)?;
+
+    logger.info(&format!(
+        "Codebook with {} subvectors and {} centroids exported to {}",
+        file.splits, file.clusters, args.out
+    ));
+    Ok(())
+}
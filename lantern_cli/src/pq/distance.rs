@@ -0,0 +1,144 @@
+use std::sync::OnceLock;
+use wide::f32x8;
+
+type DistanceFn = fn(&[f32], &[f32]) -> f32;
+
+// Implementation is selected once based on CPU features available at runtime
+static L2SQ_DIST_IMPL: OnceLock<(&'static str, DistanceFn)> = OnceLock::new();
+
+pub fn l2sq_dist_scalar(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| ((*x) - (*y)) * ((*x) - (*y)))
+        .fold(0.0 as f32, ::std::ops::Add::add)
+}
+
+// Portable version for CPUs without AVX2 or NEON, compiles to baseline SIMD of the target
+pub fn l2sq_dist_portable(a: &[f32], b: &[f32]) -> f32 {
+    let len = a.len().min(b.len());
+    let chunks = len / 8;
+    let mut sum = f32x8::ZERO;
+
+    for i in 0..chunks {
+        let start = i * 8;
+        let x = f32x8::from(<[f32; 8]>::try_from(&a[start..start + 8]).unwrap());
+        let y = f32x8::from(<[f32; 8]>::try_from(&b[start..start + 8]).unwrap());
+        let diff = x - y;
+        sum = diff.mul_add(diff, sum);
+    }
+
+    sum.reduce_add() + l2sq_dist_scalar(&a[chunks * 8..len], &b[chunks * 8..len])
+}
+
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    #[target_feature(enable = "avx2,fma")]
+    unsafe fn l2sq_dist_avx2_impl(a: &[f32], b: &[f32]) -> f32 {
+        let len = a.len().min(b.len());
+        let chunks = len / 8;
+        let mut sum = _mm256_setzero_ps();
+
+        for i in 0..chunks {
+            let x = _mm256_loadu_ps(a.as_ptr().add(i * 8));
+            let y = _mm256_loadu_ps(b.as_ptr().add(i * 8));
+            let diff = _mm256_sub_ps(x, y);
+            sum = _mm256_fmadd_ps(diff, diff, sum);
+        }
+
+        // Horizontal sum of 8 lanes
+        let sum = _mm_add_ps(_mm256_castps256_ps128(sum), _mm256_extractf128_ps(sum, 1));
+        let sum = _mm_add_ps(sum, _mm_movehl_ps(sum, sum));
+        let sum = _mm_add_ss(sum, _mm_shuffle_ps(sum, sum, 1));
+
+        _mm_cvtss_f32(sum) + super::l2sq_dist_scalar(&a[chunks * 8..len], &b[chunks * 8..len])
+    }
+
+    #[cfg(feature = "pq-avx512")]
+    #[target_feature(enable = "avx512f")]
+    unsafe fn l2sq_dist_avx512_impl(a: &[f32], b: &[f32]) -> f32 {
+        let len = a.len().min(b.len());
+        let chunks = len / 16;
+        let mut sum = _mm512_setzero_ps();
+
+        for i in 0..chunks {
+            let x = _mm512_loadu_ps(a.as_ptr().add(i * 16));
+            let y = _mm512_loadu_ps(b.as_ptr().add(i * 16));
+            let diff = _mm512_sub_ps(x, y);
+            sum = _mm512_fmadd_ps(diff, diff, sum);
+        }
+
+        // Remainder is masked, so there is no scalar loop
+        let remainder = len - chunks * 16;
+        if remainder > 0 {
+            let mask: __mmask16 = (1 << remainder) - 1;
+            let x = _mm512_maskz_loadu_ps(mask, a.as_ptr().add(chunks * 16));
+            let y = _mm512_maskz_loadu_ps(mask, b.as_ptr().add(chunks * 16));
+            let diff = _mm512_sub_ps(x, y);
+            sum = _mm512_fmadd_ps(diff, diff, sum);
+        }
+
+        _mm512_reduce_add_ps(sum)
+    }
+
+    // Wrappers are only selected after the features are detected
+    pub fn l2sq_dist_avx2(a: &[f32], b: &[f32]) -> f32 {
+        unsafe { l2sq_dist_avx2_impl(a, b) }
+    }
+
+    #[cfg(feature = "pq-avx512")]
+    pub fn l2sq_dist_avx512(a: &[f32], b: &[f32]) -> f32 {
+        unsafe { l2sq_dist_avx512_impl(a, b) }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+mod arm {
+    use std::arch::aarch64::*;
+
+    // NEON is always available on aarch64
+    pub fn l2sq_dist_neon(a: &[f32], b: &[f32]) -> f32 {
+        let len = a.len().min(b.len());
+        let chunks = len / 4;
+
+        unsafe {
+            let mut sum = vdupq_n_f32(0.0);
+            for i in 0..chunks {
+                let x = vld1q_f32(a.as_ptr().add(i * 4));
+                let y = vld1q_f32(b.as_ptr().add(i * 4));
+                let diff = vsubq_f32(x, y);
+                sum = vfmaq_f32(sum, diff, diff);
+            }
+            vaddvq_f32(sum) + super::l2sq_dist_scalar(&a[chunks * 4..len], &b[chunks * 4..len])
+        }
+    }
+}
+
+fn detect_impl() -> (&'static str, DistanceFn) {
+    #[cfg(target_arch = "x86_64")]
+    {
+        #[cfg(feature = "pq-avx512")]
+        if is_x86_feature_detected!("avx512f") {
+            return ("avx512", x86::l2sq_dist_avx512);
+        }
+        if is_x86_feature_detected!("avx2") && is_x86_feature_detected!("fma") {
+            return ("avx2", x86::l2sq_dist_avx2);
+        }
+    }
+    #[cfg(target_arch = "aarch64")]
+    {
+        return ("neon", arm::l2sq_dist_neon);
+    }
+    #[allow(unreachable_code)]
+    ("portable", l2sq_dist_portable)
+}
+
+// Name of the instruction set used by l2sq_dist
+pub fn l2sq_dist_impl_name() -> &'static str {
+    L2SQ_DIST_IMPL.get_or_init(detect_impl).0
+}
+
+pub fn l2sq_dist(a: &[f32], b: &[f32]) -> f32 {
+    (L2SQ_DIST_IMPL.get_or_init(detect_impl).1)(a, b)
+}
@@ -0,0 +1,368 @@
+use crate::logger::{LogLevel, Logger};
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+use rand::seq::index::sample;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+use super::cli::PQEvaluateArgs;
+use super::codebook::{create_codebook_for_subset, read_codebook};
+use super::ivf;
+use super::kmeans::KmeansParams;
+use super::opq;
+use super::quantization::{l2sq_dist, quantize_vectors};
+use super::validation::{validate_coarse_centroids, MAX_CLUSTERS};
+use super::{DatasetItem, CONNECTION_PARAMS, LANTERN_INTERNAL_SCHEMA_NAME};
+
+#[derive(Debug, Clone)]
+pub struct PQEvaluation {
+    pub dataset_size: usize,
+    pub queries: usize,
+    pub k: usize,
+    pub splits: usize,
+    pub clusters: usize,
+    /// Coarse centroid count of IVF-PQ codebook
+    pub coarse_clusters: Option<usize>,
+    /// Share of exact k nearest neighbours found by search over decoded vectors
+    pub recall: f64,
+    /// Mean of |approximate - exact| / exact l2sq distance for exact neighbours
+    pub mean_distance_error: f64,
+    /// Mean l2sq distance between vectors and their decoded vectors
+    pub mean_reconstruction_error: f64,
+}
+
+// Returns indices of k nearest vectors, the query row itself is skipped
+fn get_nearest(dataset: &[&[f32]], query: &[f32], query_idx: usize, k: usize) -> Vec<(usize, f32)> {
+    let mut distances: Vec<(usize, f32)> = dataset
+        .iter()
+        .enumerate()
+        .filter(|(idx, _)| *idx != query_idx)
+        .map(|(idx, vec)| (idx, l2sq_dist(query, vec)))
+        .collect();
+    distances.sort_by(|(_, a), (_, b)| a.partial_cmp(b).unwrap_or(std::cmp::Ordering::Equal));
+    distances.truncate(k);
+    distances
+}
+
+// Exact KNN over uncompressed vectors is compared with KNN over vectors decoded from PQ codes
+// Queries are not compressed, which is how distances are computed when searching PQ indexes
+pub fn evaluate_pq(
+    args: &PQEvaluateArgs,
+    logger: Option<Logger>,
+) -> Result<PQEvaluation, anyhow::Error> {
+    let logger = logger.unwrap_or(Logger::new("Lantern PQ", LogLevel::Debug));
+    logger.info("Lantern CLI - Evaluate PQ");
+
+    if args.k == 0 || args.queries == 0 {
+        anyhow::bail!("-k and --queries should be greater than 0");
+    }
+
+    let db_uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&db_uri, NoTls)?;
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+
+    let fetch_start = Instant::now();
+    let limit = match args.dataset_limit {
+        Some(limit) => format!("ORDER BY random() LIMIT {limit}"),
+        None => String::new(),
+    };
+    let dataset: Vec<DatasetItem> = client
+        .query(
+            &format!(
+                "SELECT {pk}::text, {column} FROM {full_table_name} WHERE {column} IS NOT NULL {limit}",
+                pk = quote_ident(&args.pk),
+                column = quote_ident(&args.column),
+            ),
+            &[],
+        )?
+        .iter()
+        .map(|row| DatasetItem {
+            id: row.get::<usize, String>(0),
+            vec: row.get::<usize, Vec<f32>>(1),
+        })
+        .collect();
+    logger.info(&format!(
+        "Fetched {} items in {}s",
+        dataset.len(),
+        fetch_start.elapsed().as_secs()
+    ));
+
+    if dataset.len() <= args.k {
+        anyhow::bail!(
+            "Dataset size ({}) should be greater than -k ({})",
+            dataset.len(),
+            args.k
+        );
+    }
+
+    let vector_dim = dataset[0].vec.len();
+    if let Some(item) = dataset.iter().find(|item| item.vec.len() != vector_dim) {
+        anyhow::bail!(
+            "Vector of row {} has {} dimensions, expected {vector_dim}",
+            item.id,
+            item.vec.len()
+        );
+    }
+
+    let (codebooks_hashmap, rotation, coarse_centroids) = match args.splits {
+        Some(splits) => {
+            if splits == 0 || splits > vector_dim {
+                anyhow::bail!(
+                    "--splits ({splits}) should be between 1 and vector dimensions ({vector_dim})"
+                );
+            }
+            if args.clusters == 0 || args.clusters > MAX_CLUSTERS {
+                anyhow::bail!(
+                    "--clusters ({}) should be from 1 to {MAX_CLUSTERS}",
+                    args.clusters
+                );
+            }
+            if dataset.len() < args.clusters {
+                anyhow::bail!(
+                    "--clusters ({}) should be smaller than dataset size ({})",
+                    args.clusters,
+                    dataset.len()
+                );
+            }
+
+            // Codebook is trained the same way as in quantize_table, but it is not written to the database
+            let training_start = Instant::now();
+            let subvector_dim = vector_dim / splits;
+            let kmeans_params = KmeansParams {
+                init: args.kmeans_init,
+                max_iterations: args.kmeans_iters,
+                tolerance: args.kmeans_tolerance,
+                accel: args.accel,
+            };
+            // With --coarse-clusters subvector codebooks are trained on residuals
+            let mut coarse_centroids = None;
+            let residuals = match args.coarse_clusters {
+                Some(coarse_clusters) => {
+                    let centroids = ivf::train_coarse_centroids(
+                        &dataset,
+                        coarse_clusters,
+                        &kmeans_params,
+                        &logger,
+                    )?;
+                    let (_, residuals) = ivf::compute_residuals(&dataset, &centroids, args.accel)?;
+                    coarse_centroids = Some(centroids);
+                    Some(residuals)
+                }
+                None => None,
+            };
+            let training_dataset = residuals.as_ref().unwrap_or(&dataset);
+            let (codebooks, rotation) = if args.opq {
+                let (rotation, codebooks) = opq::train_rotation(
+                    &dataset,
+                    vector_dim,
+                    splits,
+                    subvector_dim,
+                    args.clusters,
+                    &kmeans_params,
+                    args.opq_iterations,
+                    &logger,
+                )?;
+                (codebooks, Some(rotation))
+            } else {
+                let codebooks = (0..splits)
+                    .into_par_iter()
+                    .map(|subvector_id| {
+                        let start_index = subvector_id * subvector_dim;
+                        let subset = training_dataset
+                            .iter()
+                            .map(|item| &item.vec[start_index..start_index + subvector_dim])
+                            .collect::<Vec<&[f32]>>();
+                        let centroids = create_codebook_for_subset(
+                            subset,
+                            args.clusters,
+                            &kmeans_params,
+                            subvector_id,
+                            &logger,
+                        )?;
+                        Ok::<_, anyhow::Error>((subvector_id, centroids))
+                    })
+                    .collect::<Result<HashMap<usize, Vec<Vec<f32>>>, anyhow::Error>>()?;
+                (codebooks, None)
+            };
+            logger.info(&format!(
+                "Temporary codebook trained in {}s",
+                training_start.elapsed().as_secs()
+            ));
+            (codebooks, rotation, coarse_centroids)
+        }
+        None => {
+            let codebook_table_name = args
+                .codebook_table_name
+                .clone()
+                .unwrap_or(format!("pq_{}_{}", args.table, args.column));
+            let full_codebook_table_name =
+                get_full_table_name(LANTERN_INTERNAL_SCHEMA_NAME, &codebook_table_name);
+            let full_rotation_table_name = get_full_table_name(
+                LANTERN_INTERNAL_SCHEMA_NAME,
+                &opq::get_rotation_table_name(&codebook_table_name),
+            );
+            let full_coarse_table_name = get_full_table_name(
+                LANTERN_INTERNAL_SCHEMA_NAME,
+                &ivf::get_coarse_table_name(&codebook_table_name),
+            );
+            (
+                read_codebook(&mut client, &full_codebook_table_name)?,
+                opq::read_rotation(&mut client, &full_rotation_table_name)?,
+                ivf::read_coarse_centroids(&mut client, &full_coarse_table_name)?,
+            )
+        }
+    };
+
+    // Rotation preserves distances, so the whole evaluation is done over rotated vectors
+    let dataset: Vec<DatasetItem> = match &rotation {
+        Some(rotation) => dataset
+            .into_par_iter()
+            .map(|item| DatasetItem {
+                vec: opq::rotate(&item.vec, rotation),
+                id: item.id,
+            })
+            .collect(),
+        None => dataset,
+    };
+
+    let splits = codebooks_hashmap.len();
+    if (0..splits).any(|i| !codebooks_hashmap.contains_key(&i)) {
+        anyhow::bail!(
+            "Incomplete codebook: subvector ids should be from 0 to {}",
+            splits - 1
+        );
+    }
+    let clusters = codebooks_hashmap[&0].len();
+    let subvector_dim = codebooks_hashmap[&0][0].len();
+    if subvector_dim * splits > vector_dim {
+        anyhow::bail!(
+            "Codebook subvectors ({splits} x {subvector_dim}) do not fit vector dimensions ({vector_dim})"
+        );
+    }
+
+    // Residuals are quantized and coarse centroids are added back when decoding
+    let (coarse_ids, residuals) = match &coarse_centroids {
+        Some(coarse_centroids) => {
+            validate_coarse_centroids(coarse_centroids, vector_dim)?;
+            let (coarse_ids, residuals) =
+                ivf::compute_residuals(&dataset, coarse_centroids, args.accel)?;
+            (Some(coarse_ids), Some(residuals))
+        }
+        None => (None, None),
+    };
+
+    let codebooks_hashmap = Arc::new(RwLock::new(codebooks_hashmap));
+    let codes = quantize_vectors(
+        residuals.as_ref().unwrap_or(&dataset),
+        vector_dim,
+        subvector_dim,
+        splits,
+        codebooks_hashmap.clone(),
+        None,
+        args.accel,
+        &logger,
+    )?;
+
+    // Decoded vectors contain only the dimensions covered by the codebook
+    // Distances are computed over these dimensions, as the rest is not encoded
+    let codebooks = codebooks_hashmap.read().unwrap();
+    let decoded: Vec<Vec<f32>> = codes
+        .iter()
+        .enumerate()
+        .map(|(idx, (_, code))| {
+            let decoded = code
+                .iter()
+                .enumerate()
+                .flat_map(|(subvector_id, centroid_id)| {
+                    codebooks[&subvector_id][*centroid_id as usize]
+                        .iter()
+                        .cloned()
+                });
+            match (&coarse_centroids, &coarse_ids) {
+                (Some(coarse_centroids), Some(coarse_ids)) => decoded
+                    .zip(&coarse_centroids[coarse_ids[idx]])
+                    .map(|(value, coarse_value)| value + coarse_value)
+                    .collect(),
+                _ => decoded.collect(),
+            }
+        })
+        .collect();
+
+    let mean_reconstruction_error = dataset
+        .iter()
+        .zip(&decoded)
+        .map(|(item, decoded)| l2sq_dist(&item.vec, decoded) as f64)
+        .sum::<f64>()
+        / dataset.len() as f64;
+
+    let search_start = Instant::now();
+    let query_count = args.queries.min(dataset.len());
+    let query_indices = sample(&mut rand::thread_rng(), dataset.len(), query_count).into_vec();
+    let original: Vec<&[f32]> = dataset
+        .iter()
+        .map(|item| &item.vec[..subvector_dim * splits])
+        .collect();
+    let decoded: Vec<&[f32]> = decoded.iter().map(|vec| vec.as_slice()).collect();
+
+    let (found, distance_error): (usize, f64) = query_indices
+        .par_iter()
+        .map(|&query_idx| {
+            let query = original[query_idx];
+            let exact = get_nearest(&original, query, query_idx, args.k);
+            let approximate = get_nearest(&decoded, query, query_idx, args.k);
+            let found = exact
+                .iter()
+                .filter(|(idx, _)| approximate.iter().any(|(a, _)| a == idx))
+                .count();
+            let distance_error = exact
+                .iter()
+                .map(|(idx, distance)| {
+                    let approximate_distance = l2sq_dist(query, decoded[*idx]);
+                    if *distance > 0.0 {
+                        ((approximate_distance - distance).abs() / distance) as f64
+                    } else {
+                        0.0
+                    }
+                })
+                .sum::<f64>();
+            (found, distance_error)
+        })
+        .reduce(|| (0, 0.0), |a, b| (a.0 + b.0, a.1 + b.1));
+    logger.debug(&format!(
+        "Search over {query_count} queries duration: {}s",
+        search_start.elapsed().as_secs()
+    ));
+
+    let evaluation = PQEvaluation {
+        dataset_size: dataset.len(),
+        queries: query_count,
+        k: args.k,
+        splits,
+        clusters,
+        coarse_clusters: coarse_centroids.as_ref().map(|c| c.len()),
+        recall: found as f64 / (query_count * args.k) as f64,
+        mean_distance_error: distance_error / (query_count * args.k) as f64,
+        mean_reconstruction_error,
+    };
+
+    logger.info(&format!(
+        "Splits: {splits}, clusters: {clusters}, dataset size: {}, queries: {query_count}",
+        evaluation.dataset_size
+    ));
+    if let Some(coarse_clusters) = evaluation.coarse_clusters {
+        logger.info(&format!("Coarse clusters: {coarse_clusters}"));
+    }
+    logger.info(&format!("Recall@{}: {:.4}", args.k, evaluation.recall));
+    logger.info(&format!(
+        "Mean distance error: {:.4}",
+        evaluation.mean_distance_error
+    ));
+    logger.info(&format!(
+        "Mean reconstruction error: {:.4}",
+        evaluation.mean_reconstruction_error
+    ));
+
+    Ok(evaluation)
+}
@@ -1,5 +1,7 @@
 use super::cli::PQArgs;
+use super::ledger::setup_ledger_table;
 use super::setup::{make_codebook_logged_and_readonly, setup_tables, setup_triggers};
+use super::task_progress::{aggregate_progress, setup_progress_table, TaskPhase};
 use super::{set_and_report_progress, AnyhowVoidResult, ProgressCbFn};
 use crate::logger::Logger;
 use crate::utils::quote_ident;
@@ -24,7 +26,7 @@ static CLUSTERING_TASK_TEMPLATE: &'static str = r#"{
            "entrypoint": "/bin/sh",
            "commands": [
              "-c",
-             "/lantern-cli pq-table --uri ${DB_URI} --table ${TABLE} --column ${COLUMN} --clusters ${CLUSTERS} --splits ${SPLITS} --parallel-task-count ${PARALLEL_TASK_COUNT} --dataset-size ${DATASET_SIZE} --dataset-limit ${DATASET_LIMIT} --start-offset-id ${START_OFFSET_ID} --subvector-id ${BATCH_TASK_INDEX} --skip-table-setup --skip-vector-quantization; exit $?"
+             "/lantern-cli pq-table --uri ${DB_URI} --table ${TABLE} --column ${COLUMN} --clusters ${CLUSTERS} --splits ${SPLITS} --kmeans-init ${KMEANS_INIT} --kmeans-iters ${KMEANS_ITERS} --kmeans-tolerance ${KMEANS_TOLERANCE} --accel ${ACCEL} --parallel-task-count ${PARALLEL_TASK_COUNT} --dataset-size ${DATASET_SIZE} --dataset-limit ${DATASET_LIMIT} --start-offset-id ${START_OFFSET_ID} --subvector-id ${BATCH_TASK_INDEX} --skip-table-setup --skip-vector-quantization; exit $?"
            ]
          },
          "environment": {
@@ -34,6 +36,10 @@ static CLUSTERING_TASK_TEMPLATE: &'static str = r#"{
              "COLUMN": "{column}",
              "CLUSTERS": "{cluster_count}",
              "SPLITS": "{splits}",
+             "KMEANS_INIT": "{kmeans_init}",
+             "KMEANS_ITERS": "{kmeans_iters}",
+             "KMEANS_TOLERANCE": "{kmeans_tolerance}",
+             "ACCEL": "{accel}",
              "PARALLEL_TASK_COUNT": "{gcp_quantization_task_parallelism}",
              "DATASET_SIZE": "{dataset_size}",
              "DATASET_LIMIT": "{dataset_limit}",
@@ -76,7 +82,7 @@ static QUANTIZATION_TASK_TEMPLATE: &'static str = r#"{
            "entrypoint": "/bin/sh",
            "commands": [
              "-c",
-             "/lantern-cli pq-table --uri ${DB_URI} --table ${TABLE} --column ${COLUMN} --clusters ${CLUSTERS} --splits ${SPLITS} --dataset-size ${DATASET_SIZE} --skip-table-setup --skip-codebook-creation --total-task-count ${QUANTIZATION_TASK_COUNT} --parallel-task-count ${PARALLEL_TASK_COUNT} --quantization-task-id ${BATCH_TASK_INDEX}; exit $?"
+             "/lantern-cli pq-table --uri ${DB_URI} --table ${TABLE} --column ${COLUMN} --clusters ${CLUSTERS} --splits ${SPLITS} --accel ${ACCEL} --dataset-size ${DATASET_SIZE} --skip-table-setup --skip-codebook-creation --total-task-count ${QUANTIZATION_TASK_COUNT} --parallel-task-count ${PARALLEL_TASK_COUNT} --quantization-task-id ${BATCH_TASK_INDEX}; exit $?"
            ]
          },
          "environment": {
@@ -86,6 +92,7 @@ static QUANTIZATION_TASK_TEMPLATE: &'static str = r#"{
              "COLUMN": "{column}",
              "CLUSTERS": "{cluster_count}",
              "SPLITS": "{splits}",
+             "ACCEL": "{accel}",
              "DATASET_SIZE": "{dataset_size}",
              "QUANTIZATION_TASK_COUNT": "{gcp_quantization_task_count}",
              "PARALLEL_TASK_COUNT": "{gcp_quantization_task_parallelism}"
@@ -133,7 +140,13 @@ struct BatchJobResponse {
     status: JobStatus,
 }
 
-fn run_batch_job(logger: &Logger, task_body: &str, parent: &str) -> AnyhowVoidResult {
+// on_poll is called each time job state is checked
+fn run_batch_job(
+    logger: &Logger,
+    task_body: &str,
+    parent: &str,
+    on_poll: &mut dyn FnMut(),
+) -> AnyhowVoidResult {
     let url = format!("https://batch.googleapis.com/v1/{parent}/jobs");
     let runtime = Runtime::new()?;
     let authentication_manager = runtime.block_on(gcp_auth::AuthenticationManager::new())?;
@@ -206,17 +219,43 @@ fn run_batch_job(logger: &Logger, task_body: &str, parent: &str) -> AnyhowVoidRe
             _ => (),
         }
         logger.debug(&format!("Job state is: {}", job.status.state));
+        on_poll();
         std::thread::sleep(Duration::from_secs(60));
     }
     Ok(())
 }
 
+// Maps aggregated progress of batch tasks written to the progress table to the given range of
+// job progress. Progress is not needed for the job to succeed, so errors are only logged
+fn report_tasks_progress(
+    client: &mut Client,
+    full_codebook_table_name: &str,
+    phase: TaskPhase,
+    task_count: usize,
+    progress_range: (u8, u8),
+    main_progress: &AtomicU8,
+    progress_cb: &Option<ProgressCbFn>,
+    logger: &Logger,
+) {
+    match aggregate_progress(client, full_codebook_table_name, phase, task_count) {
+        Ok(progress) => {
+            logger.info(&progress.to_string());
+            let (from, to) = progress_range;
+            let percent = from as f64 + (to - from) as f64 * progress.percent.min(100.0) / 100.0;
+            set_and_report_progress(progress_cb, logger, main_progress, percent as u8);
+        }
+        Err(e) => logger.warn(&format!("Failed to read task progress: {e}")),
+    }
+}
+
 pub fn quantize_table_on_gcp(
     args: PQArgs,
     main_progress: AtomicU8,
     db_uri: &str,
     full_table_name: &str,
     full_codebook_table_name: &str,
+    full_rotation_table_name: &str,
+    full_coarse_table_name: &str,
     pq_column_name: &str,
     progress_cb: Option<ProgressCbFn>,
     logger: &Logger,
@@ -236,6 +275,8 @@ pub fn quantize_table_on_gcp(
     );
 
     let mut db_client = Client::connect(&db_uri, NoTls)?;
+    // Progress of batch tasks is read outside of the setup transaction
+    let mut progress_client = Client::connect(&db_uri, NoTls)?;
     let mut transaction = db_client.transaction()?;
 
     let max_connections = transaction.query_one(
@@ -309,7 +350,11 @@ pub fn quantize_table_on_gcp(
             &mut transaction,
             &full_table_name,
             &full_codebook_table_name,
+            full_rotation_table_name,
+            full_coarse_table_name,
             &pq_column_name,
+            None,
+            args.clusters,
             args.overwrite,
             &logger,
         )?;
@@ -322,7 +367,12 @@ pub fn quantize_table_on_gcp(
             &args.column,
             "l2sq",
             args.splits,
+            args.clusters,
+            None,
+            None,
         )?;
+        setup_progress_table(&mut transaction, full_codebook_table_name)?;
+        setup_ledger_table(&mut transaction, full_codebook_table_name)?;
 
         // Creating new transaction, because  current transaction will lock table reads
         // and block the process
@@ -375,6 +425,14 @@ pub fn quantize_table_on_gcp(
             ["CLUSTERS"] = json!(args.clusters.to_string());
         body_json["taskGroups"][0]["taskSpec"]["runnables"][0]["environment"]["variables"]
             ["SPLITS"] = json!(args.splits.to_string());
+        body_json["taskGroups"][0]["taskSpec"]["runnables"][0]["environment"]["variables"]
+            ["KMEANS_INIT"] = json!(args.kmeans_init.to_string());
+        body_json["taskGroups"][0]["taskSpec"]["runnables"][0]["environment"]["variables"]
+            ["KMEANS_ITERS"] = json!(args.kmeans_iters.to_string());
+        body_json["taskGroups"][0]["taskSpec"]["runnables"][0]["environment"]["variables"]
+            ["KMEANS_TOLERANCE"] = json!(args.kmeans_tolerance.to_string());
+        body_json["taskGroups"][0]["taskSpec"]["runnables"][0]["environment"]["variables"]
+            ["ACCEL"] = json!(args.accel.to_string());
         body_json["taskGroups"][0]["taskSpec"]["runnables"][0]["environment"]["variables"]
             ["START_OFFSET_ID"] = json!(start_offset_id.to_string());
         body_json["taskGroups"][0]["taskSpec"]["runnables"][0]["environment"]["variables"]
@@ -400,6 +458,18 @@ pub fn quantize_table_on_gcp(
             &logger,
             &body_json.to_string(),
             &format!("projects/{gcp_project_id}/locations/{gcp_region}"),
+            &mut || {
+                report_tasks_progress(
+                    &mut progress_client,
+                    full_codebook_table_name,
+                    TaskPhase::Clustering,
+                    args.splits,
+                    (5, 90),
+                    &main_progress,
+                    &progress_cb,
+                    logger,
+                )
+            },
         )?;
         logger.debug(&format!(
             "Clustering duration: {}s",
@@ -427,6 +497,8 @@ pub fn quantize_table_on_gcp(
             ["CLUSTERS"] = json!(args.clusters.to_string());
         body_json["taskGroups"][0]["taskSpec"]["runnables"][0]["environment"]["variables"]
             ["SPLITS"] = json!(args.splits.to_string());
+        body_json["taskGroups"][0]["taskSpec"]["runnables"][0]["environment"]["variables"]
+            ["ACCEL"] = json!(args.accel.to_string());
         body_json["taskGroups"][0]["taskSpec"]["runnables"][0]["environment"]["variables"]
             ["DATASET_SIZE"] = json!(total_row_count.to_string());
         body_json["taskGroups"][0]["taskSpec"]["runnables"][0]["environment"]["variables"]
@@ -446,6 +518,18 @@ pub fn quantize_table_on_gcp(
             &logger,
             &body_json.to_string(),
             &format!("projects/{gcp_project_id}/locations/{gcp_region}"),
+            &mut || {
+                report_tasks_progress(
+                    &mut progress_client,
+                    full_codebook_table_name,
+                    TaskPhase::Quantization,
+                    gcp_quantization_task_count,
+                    (90, 100),
+                    &main_progress,
+                    &progress_cb,
+                    logger,
+                )
+            },
         )?;
         logger.debug(&format!(
             "quantization duration: {}s",
@@ -0,0 +1,216 @@
+use std::borrow::Cow;
+use std::sync::OnceLock;
+use wgpu::util::DeviceExt;
+
+static WORKGROUP_SIZE: usize = 64;
+
+// Each invocation scans all centroids for one vector
+static SHADER: &'static str = r#"
+struct Params {
+    rows: u32,
+    dim: u32,
+    clusters: u32,
+    _padding: u32,
+}
+
+@group(0) @binding(0) var<uniform> params: Params;
+@group(0) @binding(1) var<storage, read> vectors: array<f32>;
+@group(0) @binding(2) var<storage, read> centroids: array<f32>;
+@group(0) @binding(3) var<storage, read_write> assignments: array<u32>;
+
+@compute @workgroup_size(64)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let row = id.x;
+    if (row >= params.rows) {
+        return;
+    }
+
+    var closest: u32 = 0u;
+    var closest_distance: f32 = 3.40282347e+38;
+    for (var c: u32 = 0u; c < params.clusters; c = c + 1u) {
+        var distance: f32 = 0.0;
+        for (var d: u32 = 0u; d < params.dim; d = d + 1u) {
+            let diff = vectors[row * params.dim + d] - centroids[c * params.dim + d];
+            distance = distance + diff * diff;
+        }
+        if (distance < closest_distance) {
+            closest_distance = distance;
+            closest = c;
+        }
+    }
+    assignments[row] = closest;
+}
+"#;
+
+struct GpuContext {
+    device: wgpu::Device,
+    queue: wgpu::Queue,
+    pipeline: wgpu::ComputePipeline,
+}
+
+// Device is initialized once and shared between kmeans and quantization calls
+static GPU_CONTEXT: OnceLock<Result<GpuContext, String>> = OnceLock::new();
+
+fn init_context() -> Result<GpuContext, String> {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+        power_preference: wgpu::PowerPreference::HighPerformance,
+        force_fallback_adapter: false,
+        compatible_surface: None,
+    }))
+    .ok_or("No GPU adapter found".to_owned())?;
+
+    let (device, queue) = pollster::block_on(adapter.request_device(
+        &wgpu::DeviceDescriptor {
+            label: Some("lantern_pq"),
+            required_features: wgpu::Features::empty(),
+            required_limits: adapter.limits(),
+        },
+        None,
+    ))
+    .map_err(|e| e.to_string())?;
+
+    let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: Some("lantern_pq_assign"),
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(SHADER)),
+    });
+    let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some("lantern_pq_assign"),
+        layout: None,
+        module: &shader,
+        entry_point: "main",
+    });
+
+    Ok(GpuContext {
+        device,
+        queue,
+        pipeline,
+    })
+}
+
+fn get_context() -> Result<&'static GpuContext, anyhow::Error> {
+    match GPU_CONTEXT.get_or_init(init_context) {
+        Ok(context) => Ok(context),
+        Err(e) => anyhow::bail!("Failed to initialize GPU: {e}"),
+    }
+}
+
+fn assign_chunk(
+    context: &GpuContext,
+    vectors: &[&[f32]],
+    centroids: &wgpu::Buffer,
+    dim: usize,
+    cluster_count: usize,
+) -> Result<Vec<u32>, anyhow::Error> {
+    let device = &context.device;
+    let rows = vectors.len();
+    let flat_vectors: Vec<f32> = vectors.iter().flat_map(|vec| vec.iter().cloned()).collect();
+
+    let params = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&[rows as u32, dim as u32, cluster_count as u32, 0]),
+        usage: wgpu::BufferUsages::UNIFORM,
+    });
+    let vectors_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: None,
+        contents: bytemuck::cast_slice(&flat_vectors),
+        usage: wgpu::BufferUsages::STORAGE,
+    });
+    let output_size = (rows * std::mem::size_of::<u32>()) as u64;
+    let output = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: output_size,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    let staging = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: output_size,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &context.pipeline.get_bind_group_layout(0),
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: params.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: vectors_buffer.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: centroids.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: output.as_entire_binding(),
+            },
+        ],
+    });
+
+    let mut encoder =
+        device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: None,
+            timestamp_writes: None,
+        });
+        pass.set_pipeline(&context.pipeline);
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(rows.div_ceil(WORKGROUP_SIZE) as u32, 1, 1);
+    }
+    encoder.copy_buffer_to_buffer(&output, 0, &staging, 0, output_size);
+    context.queue.submit(Some(encoder.finish()));
+
+    let (tx, rx) = std::sync::mpsc::channel();
+    let slice = staging.slice(..);
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        let _ = tx.send(result);
+    });
+    device.poll(wgpu::Maintain::Wait);
+    rx.recv()??;
+
+    let assignments = bytemuck::cast_slice::<u8, u32>(&slice.get_mapped_range()).to_vec();
+    staging.unmap();
+    Ok(assignments)
+}
+
+// Returns index of the closest centroid for each vector
+// Vectors are uploaded in chunks which fit in storage buffer and dispatch limits
+pub fn assign(vectors: &[&[f32]], centroids: &[Vec<f32>]) -> Result<Vec<usize>, anyhow::Error> {
+    if vectors.is_empty() || centroids.is_empty() {
+        return Ok(vec![0; vectors.len()]);
+    }
+
+    let context = get_context()?;
+    let limits = context.device.limits();
+    let dim = centroids[0].len();
+    let flat_centroids: Vec<f32> = centroids.iter().flat_map(|c| c.iter().cloned()).collect();
+    let centroids_buffer = context
+        .device
+        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&flat_centroids),
+            usage: wgpu::BufferUsages::STORAGE,
+        });
+
+    let max_rows_by_buffer =
+        limits.max_storage_buffer_binding_size as usize / (dim * std::mem::size_of::<f32>());
+    let max_rows_by_dispatch =
+        limits.max_compute_workgroups_per_dimension as usize * WORKGROUP_SIZE;
+    let chunk_size = max_rows_by_buffer.min(max_rows_by_dispatch).max(1);
+
+    let mut assignments = Vec::with_capacity(vectors.len());
+    for chunk in vectors.chunks(chunk_size) {
+        let chunk_assignments =
+            assign_chunk(context, chunk, &centroids_buffer, dim, centroids.len())?;
+        assignments.extend(chunk_assignments.into_iter().map(|idx| idx as usize));
+    }
+
+    Ok(assignments)
+}
@@ -0,0 +1,309 @@
+use crate::logger::{LogLevel, Logger};
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, GenericClient, NoTls, Transaction};
+use rand::Rng;
+use rayon::prelude::*;
+use std::io::Write;
+use std::time::Instant;
+
+use super::accel::assign;
+use super::cli::{Accel, PQTrainCentroidsArgs};
+use super::kmeans::{kmeans, KmeansParams};
+use super::{AnyhowVoidResult, DatasetItem, CONNECTION_PARAMS};
+
+// Rows are assigned to lists in chunks, so the table is not loaded in memory at once
+static ASSIGNMENT_CHUNK_SIZE: i32 = 10000;
+
+// Coarse centroids are stored next to the codebook in {codebook_table_name}_ivf table
+pub fn get_coarse_table_name(codebook_table_name: &str) -> String {
+    format!("{codebook_table_name}_ivf")
+}
+
+// Id of the closest coarse centroid is stored in {column}_ivf column of the table
+pub fn get_ivf_column_name(column: &str) -> String {
+    format!("{column}_ivf")
+}
+
+// Centroids created with `pq train-centroids` are stored in {table}_{column}_centroids table
+pub fn get_centroids_table_name(table: &str, column: &str) -> String {
+    format!("{table}_{column}_centroids")
+}
+
+// Coarse centroids are trained on whole vectors, so each vector is assigned to one list
+pub(super) fn train_coarse_centroids(
+    dataset: &[DatasetItem],
+    coarse_clusters: usize,
+    kmeans_params: &KmeansParams,
+    logger: &Logger,
+) -> Result<Vec<Vec<f32>>, anyhow::Error> {
+    let training_start = Instant::now();
+    let vectors = dataset
+        .iter()
+        .map(|item| item.vec.as_slice())
+        .collect::<Vec<&[f32]>>();
+    let centroids = kmeans(&vectors, coarse_clusters, kmeans_params, 0, logger)?;
+
+    logger.debug(&format!(
+        "Coarse kmeans with {coarse_clusters} clusters duration: {}s",
+        training_start.elapsed().as_secs()
+    ));
+    Ok(centroids)
+}
+
+// Returns ids of the closest coarse centroids and residuals of vectors from them
+// Residuals keep the ids of dataset items, so they can be quantized as usual vectors
+pub(super) fn compute_residuals(
+    dataset: &[DatasetItem],
+    coarse_centroids: &[Vec<f32>],
+    accel: Accel,
+) -> Result<(Vec<usize>, Vec<DatasetItem>), anyhow::Error> {
+    let vectors = dataset
+        .iter()
+        .map(|item| item.vec.as_slice())
+        .collect::<Vec<&[f32]>>();
+    let coarse_ids = assign(&vectors, coarse_centroids, accel)?;
+
+    let residuals = dataset
+        .par_iter()
+        .zip(coarse_ids.par_iter())
+        .map(|(item, coarse_id)| DatasetItem {
+            id: item.id.clone(),
+            vec: item
+                .vec
+                .iter()
+                .zip(&coarse_centroids[*coarse_id])
+                .map(|(value, centroid_value)| value - centroid_value)
+                .collect(),
+        })
+        .collect();
+
+    Ok((coarse_ids, residuals))
+}
+
+pub fn write_coarse_centroids<'a>(
+    transaction: &mut Transaction<'a>,
+    full_coarse_table_name: &str,
+    coarse_centroids: &[Vec<f32>],
+) -> AnyhowVoidResult {
+    transaction.batch_execute(&format!(
+        "
+        DROP TABLE IF EXISTS {full_coarse_table_name};
+        CREATE TABLE {full_coarse_table_name} (id INT PRIMARY KEY, c REAL[]);
+        "
+    ))?;
+
+    let mut writer = transaction.copy_in(&format!("COPY {full_coarse_table_name} FROM stdin"))?;
+    for (id, centroid) in coarse_centroids.iter().enumerate() {
+        let centroid_str: Vec<String> = centroid.iter().map(|x| x.to_string()).collect();
+        writer.write_all(format!("{id}\t{{{}}}\n", centroid_str.join(",")).as_bytes())?;
+    }
+    writer.flush()?;
+    writer.finish()?;
+    Ok(())
+}
+
+// Returns None if the codebook was trained without --coarse-clusters
+pub fn read_coarse_centroids(
+    client: &mut impl GenericClient,
+    full_coarse_table_name: &str,
+) -> Result<Option<Vec<Vec<f32>>>, anyhow::Error> {
+    let exists = client
+        .query_one(
+            "SELECT to_regclass($1::text) IS NOT NULL",
+            &[&full_coarse_table_name],
+        )?
+        .get::<usize, bool>(0);
+    if !exists {
+        return Ok(None);
+    }
+
+    let rows = client.query(
+        &format!("SELECT id, c FROM {full_coarse_table_name} ORDER BY id"),
+        &[],
+    )?;
+    let mut coarse_centroids = Vec::with_capacity(rows.len());
+    for row in rows {
+        // Ids are written to the table, so they should be dense from 0
+        if row.get::<usize, i32>(0) as usize != coarse_centroids.len() {
+            anyhow::bail!(
+                "Coarse centroid ids in {full_coarse_table_name} should be 0..n-1 without gaps"
+            );
+        }
+        coarse_centroids.push(row.get::<usize, Vec<f32>>(1));
+    }
+    if coarse_centroids.is_empty() {
+        anyhow::bail!("Coarse centroid table {full_coarse_table_name} is empty");
+    }
+    Ok(Some(coarse_centroids))
+}
+
+// Id of the closest centroid is written to {column}_ivf column of all rows
+// Rows are copied to a temporary table in chunks and the table is updated with one query
+fn assign_lists<'a>(
+    transaction: &mut Transaction<'a>,
+    args: &PQTrainCentroidsArgs,
+    full_table_name: &str,
+    centroids: &[Vec<f32>],
+    logger: &Logger,
+) -> AnyhowVoidResult {
+    let assignment_start = Instant::now();
+    let ivf_column = quote_ident(&get_ivf_column_name(&args.column));
+    let column = quote_ident(&args.column);
+    let pk = quote_ident(&args.pk);
+    let temp_table_name = format!("_ivf_tmp_{}", rand::thread_rng().gen_range(0..1000000));
+
+    // Rows without vectors should not keep the list of a previous assignment
+    transaction.batch_execute(&format!(
+        "
+        ALTER TABLE {full_table_name} ADD COLUMN IF NOT EXISTS {ivf_column} INT;
+        UPDATE {full_table_name} SET {ivf_column} = NULL WHERE {column} IS NULL AND {ivf_column} IS NOT NULL;
+        CREATE TEMPORARY TABLE {temp_table_name} AS SELECT {pk} as id, {ivf_column} FROM {full_table_name} LIMIT 0;
+        "
+    ))?;
+
+    let portal = transaction.bind(
+        &format!("SELECT {pk}::text, {column} FROM {full_table_name} WHERE {column} IS NOT NULL"),
+        &[],
+    )?;
+    let vector_dim = centroids[0].len();
+    let mut assigned_row_cnt = 0;
+    loop {
+        let rows = transaction.query_portal(&portal, ASSIGNMENT_CHUNK_SIZE)?;
+        if rows.is_empty() {
+            break;
+        }
+        let ids: Vec<String> = rows.iter().map(|row| row.get(0)).collect();
+        let vectors: Vec<Vec<f32>> = rows.iter().map(|row| row.get(1)).collect();
+        if let Some(idx) = vectors.iter().position(|vec| vec.len() != vector_dim) {
+            anyhow::bail!(
+                "Vector of row {} has {} dimensions, expected {vector_dim}",
+                ids[idx],
+                vectors[idx].len()
+            );
+        }
+        let vectors = vectors
+            .iter()
+            .map(|vec| vec.as_slice())
+            .collect::<Vec<&[f32]>>();
+        let list_ids = assign(&vectors, centroids, args.accel)?;
+
+        let mut writer = transaction.copy_in(&format!("COPY {temp_table_name} FROM stdin"))?;
+        for (id, list_id) in ids.iter().zip(list_ids) {
+            writer.write_all(format!("{id}\t{list_id}\n").as_bytes())?;
+        }
+        writer.flush()?;
+        writer.finish()?;
+        assigned_row_cnt += ids.len();
+        logger.debug(&format!("Assigned lists of {assigned_row_cnt} rows"));
+    }
+    drop(portal);
+
+    transaction.execute(
+        &format!("UPDATE {full_table_name} dest SET {ivf_column} = src.{ivf_column} FROM {temp_table_name} src WHERE src.id = dest.{pk}"),
+        &[],
+    )?;
+    logger.info(&format!(
+        "Lists of {assigned_row_cnt} rows written to {ivf_column} column in {}s",
+        assignment_start.elapsed().as_secs()
+    ));
+    Ok(())
+}
+
+// Trains centroids on whole vectors and stores the closest centroid of each row in {column}_ivf
+// column, so only rows of the lists closest to a query can be searched. The centroid table has
+// the same layout as coarse centroid table, so it can be passed to `pq-table --coarse-centroids-table`
+pub fn train_centroids(args: &PQTrainCentroidsArgs, logger: Option<Logger>) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern PQ", LogLevel::Debug));
+    logger.info("Lantern CLI - Train Centroids");
+
+    if args.clusters == 0 {
+        anyhow::bail!("--clusters should be greater than 0");
+    }
+    if args
+        .dataset_limit
+        .is_some_and(|limit| limit < args.clusters)
+    {
+        anyhow::bail!("--dataset-limit should be greater than or equal to cluster count");
+    }
+    let centroids_table_name = args
+        .centroids_table
+        .clone()
+        .unwrap_or(get_centroids_table_name(&args.table, &args.column));
+    if centroids_table_name.len() > 63 {
+        anyhow::bail!("Centroid table name \"{centroids_table_name}\" exceeds 63 char limit")
+    }
+
+    let db_uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&db_uri, NoTls)?;
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let full_centroids_table_name = get_full_table_name(&args.schema, &centroids_table_name);
+
+    let fetch_start = Instant::now();
+    let limit = match args.dataset_limit {
+        Some(limit) => format!("ORDER BY random() LIMIT {limit}"),
+        None => String::new(),
+    };
+    let dataset: Vec<Vec<f32>> = client
+        .query(
+            &format!(
+                "SELECT {column} FROM {full_table_name} WHERE {column} IS NOT NULL {limit}",
+                column = quote_ident(&args.column),
+            ),
+            &[],
+        )?
+        .iter()
+        .map(|row| row.get::<usize, Vec<f32>>(0))
+        .collect();
+    logger.info(&format!(
+        "Fetched {} items in {}s",
+        dataset.len(),
+        fetch_start.elapsed().as_secs()
+    ));
+
+    if let Some(vec) = dataset.iter().find(|vec| vec.len() != dataset[0].len()) {
+        anyhow::bail!(
+            "All vectors should have the same dimensions, found {} and {}",
+            dataset[0].len(),
+            vec.len()
+        );
+    }
+
+    let training_start = Instant::now();
+    let kmeans_params = KmeansParams {
+        init: args.kmeans_init,
+        max_iterations: args.kmeans_iters,
+        tolerance: args.kmeans_tolerance,
+        accel: args.accel,
+    };
+    let vectors = dataset
+        .iter()
+        .map(|vec| vec.as_slice())
+        .collect::<Vec<&[f32]>>();
+    let centroids = kmeans(&vectors, args.clusters, &kmeans_params, 0, &logger)?;
+    drop(vectors);
+    drop(dataset);
+    logger.info(&format!(
+        "Trained {} centroids in {}s",
+        centroids.len(),
+        training_start.elapsed().as_secs()
+    ));
+
+    let mut transaction = client.transaction()?;
+    write_coarse_centroids(&mut transaction, &full_centroids_table_name, &centroids)?;
+    logger.info(&format!(
+        "Centroids written to {full_centroids_table_name} table"
+    ));
+
+    if !args.skip_assignment {
+        assign_lists(
+            &mut transaction,
+            args,
+            &full_table_name,
+            &centroids,
+            &logger,
+        )?;
+    }
+
+    transaction.commit()?;
+    Ok(())
+}
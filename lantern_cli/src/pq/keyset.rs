@@ -0,0 +1,144 @@
+use crate::utils::quote_ident;
+use postgres::types::ToSql;
+use postgres::GenericClient;
+
+// Range of rows ordered by primary key. Keys are kept as text and cast back to the primary key
+// type in queries, so ranges work for any orderable key, e.g. sparse integers, UUIDs or text
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyRange {
+    pub start: String,
+    // Exclusive, None if the range ends with the last row of the table
+    pub end: Option<String>,
+}
+
+impl KeyRange {
+    // Returns WHERE condition of the range and its parameters, which should be passed as $1, $2
+    pub fn condition(&self, pk: &str, pk_type: &str) -> (String, Vec<&(dyn ToSql + Sync)>) {
+        let pk = quote_ident(pk);
+        match &self.end {
+            Some(end) => (
+                format!("{pk} >= $1::text::{pk_type} AND {pk} < $2::text::{pk_type}"),
+                vec![&self.start, end],
+            ),
+            None => (format!("{pk} >= $1::text::{pk_type}"), vec![&self.start]),
+        }
+    }
+}
+
+pub fn get_pk_type(
+    client: &mut impl GenericClient,
+    full_table_name: &str,
+    pk: &str,
+) -> Result<String, anyhow::Error> {
+    let rows = client.query(
+        "SELECT format_type(atttypid, atttypmod) FROM pg_attribute WHERE attrelid = $1::text::regclass AND attname = $2 AND NOT attisdropped",
+        &[&full_table_name, &pk],
+    )?;
+    if rows.is_empty() {
+        anyhow::bail!("Column {pk} does not exist in {full_table_name}. Pass primary key of the table with --pk");
+    }
+    Ok(rows[0].get::<usize, String>(0))
+}
+
+// Splits rows at positions [row_start, row_end) in primary key order into ranges of chunk_size
+// rows. Boundary keys are found with one scan over the primary key, so rows are later fetched by
+// key without OFFSET and ids do not need to be dense
+pub fn get_key_ranges(
+    client: &mut impl GenericClient,
+    full_table_name: &str,
+    pk: &str,
+    row_start: usize,
+    row_end: Option<usize>,
+    chunk_size: usize,
+) -> Result<Vec<KeyRange>, anyhow::Error> {
+    let chunk_size = chunk_size.max(1) as i64;
+    let row_start = row_start as i64;
+    let row_end = row_end.map(|row_end| row_end as i64);
+    let rows = client.query(
+        &format!(
+            "SELECT rn, k FROM (SELECT {pk}::text AS k, row_number() OVER (ORDER BY {pk}) - 1 AS rn FROM {full_table_name}) s
+             WHERE rn >= $1 AND ($2::BIGINT IS NULL OR rn <= $2) AND ((rn - $1) % $3 = 0 OR rn = $2) ORDER BY rn",
+            pk = quote_ident(pk)
+        ),
+        &[&row_start, &row_end, &chunk_size],
+    )?;
+
+    let mut boundaries: Vec<(i64, String)> = rows
+        .iter()
+        .map(|row| (row.get::<usize, i64>(0), row.get::<usize, String>(1)))
+        .collect();
+    // Key at row_end is the end of the last range and does not start a new one
+    let last_end = match boundaries.last() {
+        Some((rn, _)) if Some(*rn) == row_end => boundaries.pop().map(|(_, key)| key),
+        _ => None,
+    };
+
+    let mut ranges = Vec::with_capacity(boundaries.len());
+    let mut keys = boundaries.into_iter().map(|(_, key)| key).peekable();
+    while let Some(start) = keys.next() {
+        let end = keys.peek().cloned().or(last_end.clone());
+        ranges.push(KeyRange { start, end });
+    }
+    Ok(ranges)
+}
+
+// Splits rows of the range, or of the whole table, into ranges of chunk_size rows in primary key
+// order. The first and the last ranges keep the bounds of the split range, so the ranges cover it
+// without gaps even if the rows at its bounds were deleted
+pub fn split_key_range(
+    client: &mut impl GenericClient,
+    full_table_name: &str,
+    pk: &str,
+    pk_type: &str,
+    range: Option<&KeyRange>,
+    chunk_size: usize,
+) -> Result<Vec<KeyRange>, anyhow::Error> {
+    let chunk_size = chunk_size.max(1) as i64;
+    let (condition, mut params) = match range {
+        Some(range) => range.condition(pk, pk_type),
+        None => ("TRUE".to_owned(), Vec::new()),
+    };
+    params.push(&chunk_size);
+    let rows = client.query(
+        &format!(
+            "SELECT k FROM (SELECT {pk}::text AS k, row_number() OVER (ORDER BY {pk}) - 1 AS rn FROM {full_table_name} WHERE {condition}) s
+             WHERE rn % ${chunk_size_param} = 0 ORDER BY rn",
+            pk = quote_ident(pk),
+            chunk_size_param = params.len()
+        ),
+        &params,
+    )?;
+
+    let mut keys: Vec<String> = rows.iter().map(|row| row.get::<usize, String>(0)).collect();
+    if let (Some(range), Some(first_key)) = (range, keys.first_mut()) {
+        *first_key = range.start.clone();
+    }
+    let last_end = range.and_then(|range| range.end.clone());
+
+    let mut ranges = Vec::with_capacity(keys.len());
+    let mut keys = keys.into_iter().peekable();
+    while let Some(start) = keys.next() {
+        let end = keys.peek().cloned().or(last_end.clone());
+        ranges.push(KeyRange { start, end });
+    }
+    Ok(ranges)
+}
+
+// Splits the table into key ranges of batch tasks. The last task quantizes the rest of the table
+pub fn get_task_key_ranges(
+    client: &mut impl GenericClient,
+    full_table_name: &str,
+    pk: &str,
+    total_row_count: usize,
+    task_count: usize,
+) -> Result<Vec<KeyRange>, anyhow::Error> {
+    let chunk_per_task = total_row_count / task_count.max(1);
+    let mut ranges = get_key_ranges(client, full_table_name, pk, 0, None, chunk_per_task)?;
+    if ranges.len() > task_count {
+        ranges.truncate(task_count);
+        if let Some(last_range) = ranges.last_mut() {
+            last_range.end = None;
+        }
+    }
+    Ok(ranges)
+}
@@ -0,0 +1,191 @@
+use crate::logger::Logger;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::index::sample;
+use rand::Rng;
+use rayon::prelude::*;
+
+use super::accel::{assign, get_distance_fn};
+use super::cli::{Accel, KmeansInit};
+
+#[derive(Debug, Clone, Copy)]
+pub struct KmeansParams {
+    pub init: KmeansInit,
+    pub max_iterations: u64,
+    pub tolerance: f32,
+    pub accel: Accel,
+}
+
+impl Default for KmeansParams {
+    fn default() -> Self {
+        KmeansParams {
+            init: KmeansInit::Kmeanspp,
+            max_iterations: 20,
+            tolerance: 1e-1,
+            accel: Accel::Cpu,
+        }
+    }
+}
+
+// Each next centroid is picked with probability proportional to the squared distance
+// from the closest already picked centroid
+fn init_kmeanspp(dataset: &[&[f32]], cluster_count: usize, accel: Accel) -> Vec<Vec<f32>> {
+    let l2sq_dist = get_distance_fn(accel);
+    let mut rng = rand::thread_rng();
+    let mut centroids = vec![dataset[rng.gen_range(0..dataset.len())].to_vec()];
+    let mut distances: Vec<f32> = dataset
+        .par_iter()
+        .map(|vec| l2sq_dist(&centroids[0], vec))
+        .collect();
+
+    while centroids.len() < cluster_count {
+        let idx = match WeightedIndex::new(&distances) {
+            Ok(weights) => weights.sample(&mut rng),
+            // All points are already picked, so duplicates are unavoidable
+            Err(_) => rng.gen_range(0..dataset.len()),
+        };
+        let centroid = dataset[idx].to_vec();
+        distances
+            .par_iter_mut()
+            .zip(dataset.par_iter())
+            .for_each(|(distance, vec)| *distance = distance.min(l2sq_dist(&centroid, vec)));
+        centroids.push(centroid);
+    }
+
+    centroids
+}
+
+fn init_random(dataset: &[&[f32]], cluster_count: usize) -> Vec<Vec<f32>> {
+    sample(&mut rand::thread_rng(), dataset.len(), cluster_count)
+        .iter()
+        .map(|idx| dataset[idx].to_vec())
+        .collect()
+}
+
+pub fn init_centroids(
+    dataset: &[&[f32]],
+    cluster_count: usize,
+    init: KmeansInit,
+    accel: Accel,
+) -> Vec<Vec<f32>> {
+    match init {
+        KmeansInit::Kmeanspp => init_kmeanspp(dataset, cluster_count, accel),
+        KmeansInit::Random => init_random(dataset, cluster_count),
+    }
+}
+
+// Lloyd's algorithm. Iterations stop when the sum of squared centroid shifts is below tolerance
+pub fn kmeans(
+    dataset: &[&[f32]],
+    cluster_count: usize,
+    params: &KmeansParams,
+    subvector_id: usize,
+    logger: &Logger,
+) -> Result<Vec<Vec<f32>>, anyhow::Error> {
+    if dataset.len() < cluster_count {
+        anyhow::bail!(
+            "Dataset size ({}) should be greater than or equal to cluster count ({cluster_count})",
+            dataset.len()
+        );
+    }
+    let dim = dataset[0].len();
+    let l2sq_dist = get_distance_fn(params.accel);
+
+    let mut centroids = init_centroids(dataset, cluster_count, params.init, params.accel);
+
+    let mut converged = false;
+    let mut iteration = 0;
+    let mut shift = f32::MAX;
+    while iteration < params.max_iterations {
+        iteration += 1;
+        let assignments: Vec<(usize, f32)> = assign(dataset, &centroids, params.accel)?
+            .into_par_iter()
+            .zip(dataset.par_iter())
+            .map(|(cluster, vec)| (cluster, l2sq_dist(&centroids[cluster], vec)))
+            .collect();
+        let inertia =
+            assignments.iter().map(|(_, d)| *d as f64).sum::<f64>() / dataset.len() as f64;
+
+        let mut sums = vec![vec![0.0_f64; dim]; cluster_count];
+        let mut counts = vec![0_usize; cluster_count];
+        for (vec, (cluster, _)) in dataset.iter().zip(&assignments) {
+            counts[*cluster] += 1;
+            for (sum, value) in sums[*cluster].iter_mut().zip(vec.iter()) {
+                *sum += *value as f64;
+            }
+        }
+
+        // Empty clusters are moved to the points farthest from their centroids
+        let mut farthest: Vec<usize> = Vec::new();
+        if counts.contains(&0) {
+            farthest = (0..dataset.len()).collect();
+            farthest.sort_by(|a, b| assignments[*b].1.total_cmp(&assignments[*a].1));
+        }
+        let mut farthest = farthest.into_iter();
+
+        shift = 0.0;
+        for (cluster, centroid) in centroids.iter_mut().enumerate() {
+            let new_centroid: Vec<f32> = if counts[cluster] > 0 {
+                sums[cluster]
+                    .iter()
+                    .map(|sum| (sum / counts[cluster] as f64) as f32)
+                    .collect()
+            } else {
+                dataset[farthest.next().unwrap_or(0)].to_vec()
+            };
+            shift += l2sq_dist(centroid, &new_centroid);
+            *centroid = new_centroid;
+        }
+
+        logger.debug(&format!(
+            "Subset {subvector_id} kmeans iteration {iteration}: inertia {inertia:.6}, centroid shift {shift:.6}"
+        ));
+
+        if shift < params.tolerance {
+            converged = true;
+            break;
+        }
+    }
+
+    if converged {
+        logger.debug(&format!(
+            "Subset {subvector_id} kmeans converged after {iteration} iterations"
+        ));
+    } else {
+        logger.warn(&format!(
+            "Subset {subvector_id} kmeans did not converge in {iteration} iterations (centroid shift {shift:.6}). Increase --kmeans-iters or --kmeans-tolerance"
+        ));
+    }
+
+    Ok(centroids)
+}
+
+// Mini-batch kmeans (Sculley, 2010). Centroids are moved towards the points of each batch
+// with per-centroid learning rate 1 / (points assigned so far), so only centroids are kept in memory
+pub struct MiniBatchKmeans {
+    pub centroids: Vec<Vec<f32>>,
+    counts: Vec<usize>,
+    accel: Accel,
+}
+
+impl MiniBatchKmeans {
+    pub fn new(initial_batch: &[&[f32]], cluster_count: usize, params: &KmeansParams) -> Self {
+        MiniBatchKmeans {
+            centroids: init_centroids(initial_batch, cluster_count, params.init, params.accel),
+            counts: vec![0; cluster_count],
+            accel: params.accel,
+        }
+    }
+
+    pub fn fit_batch(&mut self, batch: &[&[f32]]) -> Result<(), anyhow::Error> {
+        let assignments = assign(batch, &self.centroids, self.accel)?;
+
+        for (vec, cluster) in batch.iter().zip(assignments) {
+            self.counts[cluster] += 1;
+            let learning_rate = 1.0 / self.counts[cluster] as f32;
+            for (value, new_value) in self.centroids[cluster].iter_mut().zip(vec.iter()) {
+                *value += learning_rate * (new_value - *value);
+            }
+        }
+        Ok(())
+    }
+}
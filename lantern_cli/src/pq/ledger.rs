@@ -0,0 +1,189 @@
+use postgres::{Client, GenericClient, Transaction};
+use std::collections::HashMap;
+
+use super::keyset::{get_task_key_ranges, KeyRange};
+use super::{AnyhowVoidResult, LANTERN_INTERNAL_SCHEMA_NAME};
+
+// Key ranges quantized by batch tasks are recorded in the same transaction as the PQ codes,
+// so a task which is run again after a failure skips the ranges which are already committed
+static LEDGER_TABLE_NAME: &'static str = "pq_quantization_ledger";
+
+// Key ranges of batch tasks are resolved once by the first task which runs, so boundaries of the
+// tasks do not shift if rows are deleted between task runs
+static TASK_RANGES_TABLE_NAME: &'static str = "pq_quantization_task_ranges";
+
+// Resumable tasks are split into ranges of this many rows, so ranges are the same on each run
+pub static LEDGER_CHUNK_SIZE: usize = 10_000;
+
+fn get_full_ledger_table_name() -> String {
+    format!("{LANTERN_INTERNAL_SCHEMA_NAME}.{LEDGER_TABLE_NAME}")
+}
+
+fn get_full_task_ranges_table_name() -> String {
+    format!("{LANTERN_INTERNAL_SCHEMA_NAME}.{TASK_RANGES_TABLE_NAME}")
+}
+
+// Creates the ledger table if needed and removes ranges committed by previous jobs of the
+// codebook, as the codebook may change
+pub fn setup_ledger_table<'a>(
+    transaction: &mut Transaction<'a>,
+    full_codebook_table_name: &str,
+) -> AnyhowVoidResult {
+    let full_ledger_table_name = get_full_ledger_table_name();
+    let full_task_ranges_table_name = get_full_task_ranges_table_name();
+    transaction.batch_execute(&format!(
+        "
+        CREATE TABLE IF NOT EXISTS {full_ledger_table_name} (
+            codebook_table TEXT,
+            range_start TEXT,
+            range_end TEXT,
+            task_id INT NOT NULL,
+            committed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            PRIMARY KEY (codebook_table, range_start)
+        );
+        CREATE TABLE IF NOT EXISTS {full_task_ranges_table_name} (
+            codebook_table TEXT,
+            task_id INT,
+            task_count INT NOT NULL,
+            range_start TEXT NOT NULL,
+            range_end TEXT,
+            PRIMARY KEY (codebook_table, task_id)
+        );
+        "
+    ))?;
+    for table_name in [&full_ledger_table_name, &full_task_ranges_table_name] {
+        transaction.execute(
+            &format!("DELETE FROM {table_name} WHERE codebook_table = $1"),
+            &[&full_codebook_table_name],
+        )?;
+    }
+    Ok(())
+}
+
+pub fn ledger_exists(client: &mut impl GenericClient) -> Result<bool, anyhow::Error> {
+    Ok(client
+        .query_one(
+            "SELECT to_regclass($1::text) IS NOT NULL",
+            &[&get_full_ledger_table_name()],
+        )?
+        .get::<usize, bool>(0))
+}
+
+// Returns the key range of the task, or None if the table has fewer rows than tasks
+// Ranges of all tasks are written by the first task in one snapshot of the table
+pub fn get_task_range(
+    client: &mut Client,
+    full_codebook_table_name: &str,
+    full_table_name: &str,
+    pk: &str,
+    total_row_count: usize,
+    task_count: usize,
+    task_id: usize,
+) -> Result<Option<KeyRange>, anyhow::Error> {
+    let full_task_ranges_table_name = get_full_task_ranges_table_name();
+    let mut transaction = client.transaction()?;
+    // Tasks started at the same time wait until the first one writes the ranges
+    transaction.execute(
+        "SELECT pg_advisory_xact_lock(hashtext($1))",
+        &[&full_codebook_table_name],
+    )?;
+    let rows = transaction.query(
+        &format!("SELECT task_id, task_count, range_start, range_end FROM {full_task_ranges_table_name} WHERE codebook_table = $1"),
+        &[&full_codebook_table_name],
+    )?;
+
+    if rows.is_empty() {
+        let ranges = get_task_key_ranges(
+            &mut transaction,
+            full_table_name,
+            pk,
+            total_row_count,
+            task_count,
+        )?;
+        for (range_task_id, range) in ranges.iter().enumerate() {
+            transaction.execute(
+                &format!("INSERT INTO {full_task_ranges_table_name} (codebook_table, task_id, task_count, range_start, range_end) VALUES ($1, $2, $3, $4, $5)"),
+                &[
+                    &full_codebook_table_name,
+                    &(range_task_id as i32),
+                    &(task_count as i32),
+                    &range.start,
+                    &range.end,
+                ],
+            )?;
+        }
+        transaction.commit()?;
+        return Ok(ranges.into_iter().nth(task_id));
+    }
+
+    let resolved_task_count = rows[0].get::<usize, i32>(1) as usize;
+    if resolved_task_count != task_count {
+        anyhow::bail!("Key ranges were split for {resolved_task_count} quantization tasks, but --total-task-count is {task_count}. Run table setup again to split the table for the new task count");
+    }
+    transaction.commit()?;
+    Ok(rows
+        .iter()
+        .find(|row| row.get::<usize, i32>(0) as usize == task_id)
+        .map(|row| KeyRange {
+            start: row.get::<usize, String>(2),
+            end: row.get::<usize, Option<String>>(3),
+        }))
+}
+
+// Returns end keys of committed ranges by their start keys
+pub fn read_committed_ranges(
+    client: &mut impl GenericClient,
+    full_codebook_table_name: &str,
+) -> Result<HashMap<String, Option<String>>, anyhow::Error> {
+    let rows = client.query(
+        &format!(
+            "SELECT range_start, range_end FROM {} WHERE codebook_table = $1",
+            get_full_ledger_table_name()
+        ),
+        &[&full_codebook_table_name],
+    )?;
+    Ok(rows
+        .iter()
+        .map(|row| {
+            (
+                row.get::<usize, String>(0),
+                row.get::<usize, Option<String>>(1),
+            )
+        })
+        .collect())
+}
+
+// Returns ranges which are not committed. Ranges are split the same way on each run, so a range
+// is skipped only if the committed range has the same bounds
+pub fn get_pending_ranges(
+    ranges: Vec<KeyRange>,
+    committed_ranges: &HashMap<String, Option<String>>,
+) -> Vec<KeyRange> {
+    ranges
+        .into_iter()
+        .filter(|range| committed_ranges.get(&range.start) != Some(&range.end))
+        .collect()
+}
+
+// Should be called in the transaction which writes PQ codes of the range
+pub fn record_committed_range<'a>(
+    transaction: &mut Transaction<'a>,
+    full_codebook_table_name: &str,
+    task_id: usize,
+    range: &KeyRange,
+) -> AnyhowVoidResult {
+    transaction.execute(
+        &format!(
+            "INSERT INTO {} (codebook_table, range_start, range_end, task_id) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (codebook_table, range_start) DO UPDATE SET range_end = EXCLUDED.range_end, task_id = EXCLUDED.task_id, committed_at = now()",
+            get_full_ledger_table_name()
+        ),
+        &[
+            &full_codebook_table_name,
+            &range.start,
+            &range.end,
+            &(task_id as i32),
+        ],
+    )?;
+    Ok(())
+}
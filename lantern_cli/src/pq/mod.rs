@@ -2,6 +2,7 @@ use crate::logger::{LogLevel, Logger};
 use crate::types::JOB_CANCELLED_MESSAGE;
 use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
 use codebook::CreateCodebookArgs;
+use kmeans::KmeansParams;
 use quantization::QuantizeAndWriteVectorArgs;
 use rand::Rng;
 use std::sync::atomic::{AtomicU8, Ordering};
@@ -10,11 +11,24 @@ use std::time::Instant;
 
 use postgres::{Client, NoTls};
 
+mod accel;
 pub mod cli;
 mod codebook;
+pub mod codebook_file;
+pub mod distance;
+pub mod evaluate;
 mod gcp_batch;
+#[cfg(feature = "pq-gpu")]
+mod gpu;
+pub mod ivf;
+pub mod keyset;
+mod kmeans;
+pub mod ledger;
+pub mod opq;
 mod quantization;
 mod setup;
+pub mod task_progress;
+mod validation;
 
 type AnyhowVoidResult = Result<(), anyhow::Error>;
 pub type ProgressCbFn = Box<dyn Fn(u8) + Send + Sync>;
@@ -76,7 +90,10 @@ fn quantize_table_local(
     db_uri: &str,
     full_table_name: &str,
     full_codebook_table_name: &str,
+    full_rotation_table_name: &str,
+    full_coarse_table_name: &str,
     pq_column_name: &str,
+    ivf_column_name: &str,
     progress_cb: Option<ProgressCbFn>,
     is_canceled: Option<Arc<RwLock<bool>>>,
     logger: &Logger,
@@ -86,16 +103,60 @@ fn quantize_table_local(
     let schema = &args.schema;
     let table = &args.table;
 
+    // Codebook trained on another database is written instead of running kmeans
+    let imported_codebook = match &args.import_codebook {
+        Some(path) => {
+            let codebook = codebook_file::CodebookFile::read(path)?;
+            if codebook.splits != args.splits {
+                anyhow::bail!(
+                    "Imported codebook has {} subvectors, but --splits is {}",
+                    codebook.splits,
+                    args.splits
+                );
+            }
+            Some(codebook)
+        }
+        None => None,
+    };
+    let use_rotation = args.opq
+        || imported_codebook
+            .as_ref()
+            .is_some_and(|codebook| codebook.rotation.is_some());
+
     let mut client = Client::connect(db_uri, NoTls)?;
+
+    // Centroids trained with `pq train-centroids` are used instead of training coarse centroids
+    // They are read before table setup, which drops the coarse centroid table of the codebook
+    let trained_coarse_centroids = match &args.coarse_centroids_table {
+        Some(centroids_table_name) => {
+            let full_centroids_table_name = get_full_table_name(schema, centroids_table_name);
+            let Some(centroids) =
+                ivf::read_coarse_centroids(&mut client, &full_centroids_table_name)?
+            else {
+                anyhow::bail!("Centroid table {full_centroids_table_name} does not exist");
+            };
+            Some(centroids)
+        }
+        None => None,
+    };
+    let coarse_clusters = args.coarse_clusters.or(trained_coarse_centroids
+        .as_ref()
+        .map(|centroids| centroids.len()));
+
     let mut transaction = client.transaction()?;
 
     // Create codebook table and add pqvec column to table
     if !args.skip_table_setup {
+        let _span = tracing::info_span!("pq_setup").entered();
         setup::setup_tables(
             &mut transaction,
             &full_table_name,
             &full_codebook_table_name,
+            full_rotation_table_name,
+            full_coarse_table_name,
             &pq_column_name,
+            coarse_clusters.map(|_| ivf_column_name),
+            args.clusters,
             args.overwrite,
             &logger,
         )?;
@@ -108,7 +169,16 @@ fn quantize_table_local(
             column,
             "l2sq",
             args.splits,
+            args.clusters,
+            if use_rotation {
+                Some(full_rotation_table_name)
+            } else {
+                None
+            },
+            coarse_clusters.map(|_| (ivf_column_name, full_coarse_table_name)),
         )?;
+        task_progress::setup_progress_table(&mut transaction, full_codebook_table_name)?;
+        ledger::setup_ledger_table(&mut transaction, full_codebook_table_name)?;
 
         // Creating new transaction, because  current transaction will lock table reads
         // and block the process
@@ -147,6 +217,67 @@ fn quantize_table_local(
         }
     };
 
+    let max_connections = transaction.query_one(
+        "SELECT setting::int FROM pg_settings WHERE name = 'max_connections'",
+        &[],
+    )?;
+    let max_connections = max_connections.get::<usize, i32>(0) as usize;
+
+    if let Some(codebook) = imported_codebook {
+        let _span = tracing::info_span!("pq_import_codebook").entered();
+        let vector_dim = validation::get_vector_dim(&mut transaction, full_table_name, column)?;
+        validation::validate_codebook(&codebook.codebooks_hashmap(), args.splits, vector_dim)?;
+        codebook::write_codebook(
+            &mut transaction,
+            &full_codebook_table_name,
+            codebook.codebooks_hashmap().into_iter().collect(),
+            &logger,
+        )?;
+        if let Some(rotation) = codebook.rotation_matrix()? {
+            opq::write_rotation(&mut transaction, full_rotation_table_name, &rotation)?;
+        }
+        setup::make_codebook_logged_and_readonly(&mut transaction, &full_codebook_table_name)?;
+        transaction.commit()?;
+        logger.info(&format!(
+            "Imported codebook with {} subvectors and {} centroids",
+            codebook.splits, codebook.clusters
+        ));
+        set_and_report_progress(&progress_cb, &logger, &main_progress, 75);
+
+        if !args.skip_vector_quantization {
+            let _span = tracing::info_span!("pq_quantize_and_write").entered();
+            quantization::quantize_and_write_vectors(
+                QuantizeAndWriteVectorArgs {
+                    codebook_table_name: &full_codebook_table_name,
+                    rotation_table_name: full_rotation_table_name,
+                    coarse_table_name: full_coarse_table_name,
+                    full_table_name: &full_table_name,
+                    db_uri,
+                    schema,
+                    table,
+                    column,
+                    pq_column_name: &pq_column_name,
+                    ivf_column_name,
+                    pk: &args.pk,
+                    splits: args.splits,
+                    total_row_count,
+                    total_task_count: &None,
+                    parallel_task_count: &None,
+                    quantization_task_id: &None,
+                    max_connections,
+                    accel: args.accel,
+                    main_progress: &main_progress,
+                    progress_cb: &progress_cb,
+                    is_canceled: &is_canceled,
+                    logger: &logger,
+                },
+                client,
+            )?;
+        }
+        set_and_report_progress(&progress_cb, &logger, &main_progress, 100);
+        return Ok(());
+    }
+
     if total_row_count < args.clusters {
         anyhow::bail!(
             "--clusters ({clusters}) should be smaller than dataset size ({total_row_count})",
@@ -172,33 +303,33 @@ fn quantize_table_local(
         0
     };
 
+    // Vectors of all rows are quantized, even if codebook is trained on a limited dataset
+    let table_row_count = total_row_count;
     let total_row_count = if limit > 0 && limit <= total_row_count {
         limit
     } else {
         total_row_count
     };
 
-    let max_connections = transaction.query_one(
-        "SELECT setting::int FROM pg_settings WHERE name = 'max_connections'",
-        &[],
-    )?;
-    let max_connections = max_connections.get::<usize, i32>(0) as usize;
-
     // If --skip-codebook-creation is passed that means we only need to quantize and write vectors
     // As there will be three phases
     // 1. table setup, 2. codebook craetion 3. table quantization 4. trigger setup
     // 2 and 3 phases will be run in parallel
     if args.skip_codebook_creation && !args.skip_vector_quantization {
         drop(transaction);
+        let _span = tracing::info_span!("pq_quantize_and_write").entered();
         quantization::quantize_and_write_vectors(
             QuantizeAndWriteVectorArgs {
                 codebook_table_name: &full_codebook_table_name,
+                rotation_table_name: full_rotation_table_name,
+                coarse_table_name: full_coarse_table_name,
                 full_table_name: &full_table_name,
                 db_uri,
                 schema,
                 table,
                 column,
                 pq_column_name: &pq_column_name,
+                ivf_column_name,
                 pk: &args.pk,
                 splits: args.splits,
                 total_row_count,
@@ -206,8 +337,10 @@ fn quantize_table_local(
                 parallel_task_count: &args.parallel_task_count,
                 quantization_task_id: &args.quantization_task_id,
                 max_connections,
+                accel: args.accel,
                 main_progress: &main_progress,
                 progress_cb: &progress_cb,
+                is_canceled: &is_canceled,
                 logger: &logger,
             },
             client,
@@ -217,49 +350,102 @@ fn quantize_table_local(
     }
 
     // Get full vector dimension
-    let row = transaction.query_one(
-        &format!(
-            "SELECT ARRAY_LENGTH({column}, 1) FROM {full_table_name} WHERE {column} IS NOT NULL LIMIT 1;",
-            column = quote_ident(column)
-        ),
-        &[],
-    )?;
-    let vector_dim = row.try_get::<usize, i32>(0)? as usize;
-    if vector_dim < args.splits {
-        anyhow::bail!(
-            "--splits ({splits}) should be less than or equal to vector dimensions ({vector_dim})",
-            splits = args.splits
-        )
-    }
-    // Get subvector dimension
-    // It is not neccessary that vector_dim will be divisible to split count
-    // If there's reminder the last subvector's dimensions will be higher
-    // But it is better to provide a value which is divisible
+    // Vector dimensions should be divisible by split count, so all dimensions are quantized
+    let vector_dim = validation::get_vector_dim(&mut transaction, full_table_name, column)?;
+    validation::validate_splits(args.splits, vector_dim)?;
     let subvector_dim = vector_dim / args.splits;
 
     // Create codebook
-    let (codebooks_hashmap, dataset) = codebook::create_codebook(
-        CreateCodebookArgs {
-            logger: &logger,
-            main_progress: &main_progress,
-            progress_cb: &progress_cb,
-            db_uri,
-            pk: &args.pk,
-            column,
-            full_table_name: &full_table_name,
-            codebook_table_name: &full_codebook_table_name,
-            total_row_count,
-            start_offset_id,
-            max_connections,
-            splits: args.splits,
-            vector_dim,
-            subvector_dim,
-            cluster_count: args.clusters,
-            subvector_id: &args.subvector_id,
-            parallel_task_count: &args.parallel_task_count,
+    let codebook_span = tracing::info_span!("pq_create_codebook", total_row_count).entered();
+    let codebook_args = CreateCodebookArgs {
+        logger: &logger,
+        main_progress: &main_progress,
+        progress_cb: &progress_cb,
+        db_uri,
+        pk: &args.pk,
+        column,
+        full_table_name: &full_table_name,
+        codebook_table_name: &full_codebook_table_name,
+        rotation_table_name: full_rotation_table_name,
+        coarse_table_name: full_coarse_table_name,
+        total_row_count,
+        start_offset_id,
+        max_connections,
+        splits: args.splits,
+        vector_dim,
+        subvector_dim,
+        cluster_count: args.clusters,
+        kmeans_params: KmeansParams {
+            init: args.kmeans_init,
+            max_iterations: args.kmeans_iters,
+            tolerance: args.kmeans_tolerance,
+            accel: args.accel,
         },
-        &mut transaction,
-    )?;
+        subvector_id: &args.subvector_id,
+        parallel_task_count: &args.parallel_task_count,
+        opq_iterations: if args.opq {
+            Some(args.opq_iterations)
+        } else {
+            None
+        },
+        coarse_clusters,
+        coarse_centroids: trained_coarse_centroids,
+    };
+
+    // With mini-batch kmeans the dataset is not kept in memory,
+    // so vectors are quantized in chunks read from the table, as in --skip-codebook-creation mode
+    if let Some(batch_size) = args.kmeans_batch_size {
+        codebook::create_codebook_mini_batch(codebook_args, batch_size, &mut transaction)?;
+        drop(codebook_span);
+
+        if args.subvector_id.is_none() {
+            setup::make_codebook_logged_and_readonly(&mut transaction, &full_codebook_table_name)?;
+        }
+        // Codebook should be committed to be visible for quantization connections
+        transaction.commit()?;
+
+        if !args.skip_vector_quantization {
+            let _span = tracing::info_span!("pq_quantize_and_write").entered();
+            quantization::quantize_and_write_vectors(
+                QuantizeAndWriteVectorArgs {
+                    codebook_table_name: &full_codebook_table_name,
+                    rotation_table_name: full_rotation_table_name,
+                    coarse_table_name: full_coarse_table_name,
+                    full_table_name: &full_table_name,
+                    db_uri,
+                    schema,
+                    table,
+                    column,
+                    pq_column_name: &pq_column_name,
+                    ivf_column_name,
+                    pk: &args.pk,
+                    splits: args.splits,
+                    total_row_count: table_row_count,
+                    total_task_count: &None,
+                    parallel_task_count: &None,
+                    quantization_task_id: &None,
+                    max_connections,
+                    accel: args.accel,
+                    main_progress: &main_progress,
+                    progress_cb: &progress_cb,
+                    is_canceled: &is_canceled,
+                    logger: &logger,
+                },
+                client,
+            )?;
+        }
+        set_and_report_progress(&progress_cb, &logger, &main_progress, 100);
+        return Ok(());
+    }
+
+    let (codebooks_hashmap, dataset, rotation, coarse_centroids) =
+        codebook::create_codebook(codebook_args, &mut transaction)?;
+    drop(codebook_span);
+
+    // Codebook is not committed if the job is cancelled during training
+    if *is_canceled.read().unwrap() {
+        anyhow::bail!(JOB_CANCELLED_MESSAGE);
+    }
 
     if args.subvector_id.is_none() {
         // We will only run this if clustering is run for whole dataset
@@ -272,16 +458,36 @@ fn quantize_table_local(
     // quantize vectors using codebook
     // And write results to target table
     if !args.skip_vector_quantization {
+        validation::validate_pq_column(
+            &mut transaction,
+            full_table_name,
+            pq_column_name,
+            args.splits,
+            args.clusters,
+        )?;
         let codebooks_hashmap = Arc::new(RwLock::new(codebooks_hashmap));
 
+        let quantize_span = tracing::info_span!("pq_quantize_vectors").entered();
+        // Residuals are quantized, as the codebook was trained on them
+        let (coarse_ids, dataset) = match &coarse_centroids {
+            Some(coarse_centroids) => {
+                let (coarse_ids, residuals) =
+                    ivf::compute_residuals(&dataset, coarse_centroids, args.accel)?;
+                (Some(coarse_ids), Arc::new(residuals))
+            }
+            None => (None, dataset),
+        };
         let dataset = quantization::quantize_vectors(
             &dataset,
             vector_dim,
             subvector_dim,
             args.splits,
             codebooks_hashmap,
+            rotation.as_ref(),
+            args.accel,
             &logger,
         )?;
+        drop(quantize_span);
         set_and_report_progress(&progress_cb, &logger, &main_progress, 90);
 
         if *is_canceled.read().unwrap() {
@@ -290,9 +496,13 @@ fn quantize_table_local(
             anyhow::bail!(JOB_CANCELLED_MESSAGE);
         }
 
+        let _span = tracing::info_span!("pq_write_quantized_rows").entered();
         quantization::write_quantized_rows(
             &mut transaction,
             &dataset,
+            coarse_ids
+                .as_deref()
+                .map(|coarse_ids| (ivf_column_name, coarse_ids)),
             &args.schema,
             &args.table,
             &pq_column_name,
@@ -318,7 +528,21 @@ pub fn quantize_table(
     logger: Option<Logger>,
 ) -> AnyhowVoidResult {
     let logger = Arc::new(logger.unwrap_or(Logger::new("Lantern PQ", LogLevel::Debug)));
+    let _job_span = tracing::info_span!(
+        "pq_job",
+        table = %args.table,
+        column = %args.column,
+        splits = args.splits,
+        clusters = args.clusters
+    )
+    .entered();
     logger.info("Lantern CLI - Quantize Table");
+    if args.accel == cli::Accel::Simd {
+        logger.debug(&format!(
+            "Distances are computed with {} instructions",
+            distance::l2sq_dist_impl_name()
+        ));
+    }
 
     let main_progress = AtomicU8::new(0);
     let total_time_start = Instant::now();
@@ -334,9 +558,96 @@ pub fn quantize_table(
 
     let full_codebook_table_name =
         get_full_table_name(LANTERN_INTERNAL_SCHEMA_NAME, &codebook_table_name);
+    let rotation_table_name = opq::get_rotation_table_name(&codebook_table_name);
+    if (args.opq || args.import_codebook.is_some()) && rotation_table_name.len() > 63 {
+        anyhow::bail!("Rotation table name \"{rotation_table_name}\" exceeds 63 char limit")
+    }
+    let coarse_table_name = ivf::get_coarse_table_name(&codebook_table_name);
+    if (args.coarse_clusters.is_some() || args.coarse_centroids_table.is_some())
+        && coarse_table_name.len() > 63
+    {
+        anyhow::bail!("Coarse centroid table name \"{coarse_table_name}\" exceeds 63 char limit")
+    }
+    if args.coarse_clusters == Some(0) {
+        anyhow::bail!("--coarse-clusters should be greater than 0");
+    }
+    if args.coarse_clusters.is_some() && args.run_on_gcp {
+        anyhow::bail!("--coarse-clusters can not be used with --run-on-gcp, as coarse centroids are trained on whole vectors");
+    }
+    if args.coarse_centroids_table.is_some() && args.run_on_gcp {
+        anyhow::bail!("--coarse-centroids-table can not be used with --run-on-gcp");
+    }
+    if args.opq && args.kmeans_batch_size.is_some() {
+        anyhow::bail!("--opq needs the training dataset in memory, so it can not be used with --kmeans-batch-size");
+    }
+    if args.clusters == 0 || args.clusters > validation::MAX_CLUSTERS {
+        anyhow::bail!(
+            "--clusters ({}) should be from 1 to {}, as PQ codes are stored in two bytes",
+            args.clusters,
+            validation::MAX_CLUSTERS
+        );
+    }
+    if args.import_codebook.is_some() && args.run_on_gcp {
+        anyhow::bail!("--import-codebook can not be used with --run-on-gcp");
+    }
+    if args.accel == cli::Accel::Gpu && args.run_on_gcp {
+        anyhow::bail!(
+            "--accel gpu can not be used with --run-on-gcp, as batch jobs run on CPU machines"
+        );
+    }
+    if args.opq && (args.subvector_id.is_some() || args.run_on_gcp) {
+        anyhow::bail!("--opq trains all subvectors together, so it can not be used with --subvector-id or --run-on-gcp");
+    }
+    let full_rotation_table_name =
+        get_full_table_name(LANTERN_INTERNAL_SCHEMA_NAME, &rotation_table_name);
+    let full_coarse_table_name =
+        get_full_table_name(LANTERN_INTERNAL_SCHEMA_NAME, &coarse_table_name);
     let pq_column_name = format!("{}_pq", args.column);
+    let ivf_column_name = ivf::get_ivf_column_name(&args.column);
     let db_uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
 
+    if args.watch {
+        let mut phases = Vec::new();
+        if !args.skip_codebook_creation {
+            phases.push((task_progress::TaskPhase::Clustering, args.splits));
+        }
+        if !args.skip_vector_quantization {
+            let Some(total_task_count) = args.total_task_count else {
+                anyhow::bail!("--total-task-count is required to watch quantization tasks");
+            };
+            phases.push((task_progress::TaskPhase::Quantization, total_task_count));
+        }
+        return task_progress::watch_progress(
+            &db_uri,
+            &full_codebook_table_name,
+            &phases,
+            &progress_cb,
+            is_canceled,
+            &logger,
+        );
+    }
+
+    // Tasks of horizontally scaled jobs write their progress, so it can be aggregated with --watch
+    let progress_cb = match (args.subvector_id, args.quantization_task_id) {
+        (Some(subvector_id), _) if !args.run_on_gcp => Some(task_progress::task_progress_cb(
+            &db_uri,
+            &full_codebook_table_name,
+            task_progress::TaskPhase::Clustering,
+            subvector_id,
+            progress_cb,
+            &logger,
+        )),
+        (None, Some(task_id)) if !args.run_on_gcp => Some(task_progress::task_progress_cb(
+            &db_uri,
+            &full_codebook_table_name,
+            task_progress::TaskPhase::Quantization,
+            task_id,
+            progress_cb,
+            &logger,
+        )),
+        _ => progress_cb,
+    };
+
     if args.run_on_gcp {
         gcp_batch::quantize_table_on_gcp(
             args,
@@ -344,6 +655,8 @@ pub fn quantize_table(
             &db_uri,
             &full_table_name,
             &full_codebook_table_name,
+            &full_rotation_table_name,
+            &full_coarse_table_name,
             &pq_column_name,
             progress_cb,
             &logger,
@@ -355,7 +668,10 @@ pub fn quantize_table(
             &db_uri,
             &full_table_name,
             &full_codebook_table_name,
+            &full_rotation_table_name,
+            &full_coarse_table_name,
             &pq_column_name,
+            &ivf_column_name,
             progress_cb,
             is_canceled,
             &logger,
@@ -0,0 +1,219 @@
+use crate::logger::Logger;
+use ndarray::{Array2, ArrayView2, Axis};
+use postgres::{GenericClient, Transaction};
+use rand::seq::index::sample;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Instant;
+
+use super::codebook::create_codebook_for_subset;
+use super::kmeans::KmeansParams;
+use super::quantization::get_closest_centroid;
+use super::{AnyhowVoidResult, DatasetItem};
+
+// Rotation is learned on a sample, as each iteration multiplies the whole training set
+static ROTATION_TRAIN_ROWS: usize = 50000;
+static ORTHOGONALIZATION_MAX_ITERATIONS: usize = 100;
+
+// Rotation matrix is stored next to the codebook in {codebook_table_name}_opq table
+pub fn get_rotation_table_name(codebook_table_name: &str) -> String {
+    format!("{codebook_table_name}_opq")
+}
+
+// Returns the orthogonal matrix closest to the given one (polar factor U * V^T of its SVD)
+// It is computed with Newton-Schulz iterations, so only matrix products are needed
+fn orthogonalize(matrix: &Array2<f32>) -> Array2<f32> {
+    let dim = matrix.nrows();
+    let eye = Array2::<f64>::eye(dim);
+    let frobenius_norm = |m: &Array2<f64>| m.iter().map(|v| v * v).sum::<f64>().sqrt();
+    let matrix = matrix.mapv(|v| v as f64);
+    // Small identity term keeps the iterations convergent when the matrix is rank deficient
+    let matrix = &matrix / frobenius_norm(&matrix) + &eye * 1e-4;
+    // Frobenius norm is not smaller than the largest singular value, which should be below sqrt(3)
+    let mut rotation = &matrix / frobenius_norm(&matrix);
+
+    for _ in 0..ORTHOGONALIZATION_MAX_ITERATIONS {
+        let product = rotation.t().dot(&rotation);
+        let error = (&product - &eye)
+            .iter()
+            .fold(0.0_f64, |max, v| max.max(v.abs()));
+        if error < 1e-6 {
+            break;
+        }
+        rotation = rotation.dot(&(&eye * 3.0 - &product)) * 0.5;
+    }
+
+    rotation.mapv(|v| v as f32)
+}
+
+pub fn rotate(vec: &[f32], rotation: &Array2<f32>) -> Vec<f32> {
+    ArrayView2::from_shape((1, vec.len()), vec)
+        .unwrap()
+        .dot(rotation)
+        .into_raw_vec()
+}
+
+fn train_codebooks(
+    vectors: &Array2<f32>,
+    splits: usize,
+    subvector_dim: usize,
+    cluster_count: usize,
+    kmeans_params: &KmeansParams,
+    logger: &Logger,
+) -> Result<HashMap<usize, Vec<Vec<f32>>>, anyhow::Error> {
+    (0..splits)
+        .into_par_iter()
+        .map(|subvector_id| {
+            let start_index = subvector_id * subvector_dim;
+            let subset = vectors
+                .axis_iter(Axis(0))
+                .map(|row| &row.to_slice().unwrap()[start_index..start_index + subvector_dim])
+                .collect::<Vec<&[f32]>>();
+            let centroids = create_codebook_for_subset(
+                subset,
+                cluster_count,
+                kmeans_params,
+                subvector_id,
+                logger,
+            )?;
+            Ok::<_, anyhow::Error>((subvector_id, centroids))
+        })
+        .collect()
+}
+
+// Non-parametric OPQ: codebooks are trained on rotated vectors, then rotation is updated
+// to map vectors closest to their reconstructions. Returns rotation and codebooks trained
+// on the whole rotated dataset
+pub fn train_rotation(
+    dataset: &Vec<DatasetItem>,
+    vector_dim: usize,
+    splits: usize,
+    subvector_dim: usize,
+    cluster_count: usize,
+    kmeans_params: &KmeansParams,
+    iterations: usize,
+    logger: &Logger,
+) -> Result<(Array2<f32>, HashMap<usize, Vec<Vec<f32>>>), anyhow::Error> {
+    let training_start = Instant::now();
+    let sample_size = dataset.len().min(ROTATION_TRAIN_ROWS);
+    let sample_indices = sample(&mut rand::thread_rng(), dataset.len(), sample_size);
+    let vectors = Array2::from_shape_vec(
+        (sample_size, vector_dim),
+        sample_indices
+            .iter()
+            .flat_map(|idx| dataset[idx].vec.iter().cloned())
+            .collect(),
+    )?;
+
+    let mut rotation = Array2::<f32>::eye(vector_dim);
+    for iteration in 0..iterations {
+        let rotated = vectors.dot(&rotation);
+        let codebooks = train_codebooks(
+            &rotated,
+            splits,
+            subvector_dim,
+            cluster_count,
+            kmeans_params,
+            logger,
+        )?;
+
+        // Dimensions which are not covered by subvectors are kept as is
+        let mut reconstructed = rotated.clone();
+        let mut distortion = 0.0;
+        for mut row in reconstructed.axis_iter_mut(Axis(0)) {
+            let row = row.as_slice_mut().unwrap();
+            for (subvector_id, centroids) in &codebooks {
+                let start_index = subvector_id * subvector_dim;
+                let subvector = &mut row[start_index..start_index + subvector_dim];
+                let centroid = &centroids[get_closest_centroid(centroids, subvector) as usize];
+                for (value, centroid_value) in subvector.iter_mut().zip(centroid) {
+                    distortion += (*value - centroid_value) * (*value - centroid_value);
+                    *value = *centroid_value;
+                }
+            }
+        }
+
+        logger.debug(&format!(
+            "OPQ iteration {} distortion: {:.6}",
+            iteration + 1,
+            distortion / sample_size as f32
+        ));
+        rotation = orthogonalize(&vectors.t().dot(&reconstructed));
+    }
+
+    let rotated = Array2::from_shape_vec(
+        (dataset.len(), vector_dim),
+        dataset
+            .iter()
+            .flat_map(|item| item.vec.iter().cloned())
+            .collect(),
+    )?
+    .dot(&rotation);
+    let codebooks = train_codebooks(
+        &rotated,
+        splits,
+        subvector_dim,
+        cluster_count,
+        kmeans_params,
+        logger,
+    )?;
+
+    logger.debug(&format!(
+        "OPQ training duration: {}s",
+        training_start.elapsed().as_secs()
+    ));
+    Ok((rotation, codebooks))
+}
+
+pub fn write_rotation<'a>(
+    transaction: &mut Transaction<'a>,
+    full_rotation_table_name: &str,
+    rotation: &Array2<f32>,
+) -> AnyhowVoidResult {
+    transaction.batch_execute(&format!(
+        "
+        DROP TABLE IF EXISTS {full_rotation_table_name};
+        CREATE TABLE {full_rotation_table_name} (row_id INT PRIMARY KEY, r REAL[]);
+        "
+    ))?;
+
+    let mut writer = transaction.copy_in(&format!("COPY {full_rotation_table_name} FROM stdin"))?;
+    for (row_id, row) in rotation.axis_iter(Axis(0)).enumerate() {
+        let row_str: Vec<String> = row.iter().map(|x| x.to_string()).collect();
+        writer.write(format!("{row_id}\t{{{}}}\n", row_str.join(",")).as_bytes())?;
+    }
+    writer.flush()?;
+    writer.finish()?;
+    Ok(())
+}
+
+// Returns None if the codebook was trained without rotation
+pub fn read_rotation(
+    client: &mut impl GenericClient,
+    full_rotation_table_name: &str,
+) -> Result<Option<Array2<f32>>, anyhow::Error> {
+    let exists = client
+        .query_one(
+            "SELECT to_regclass($1::text) IS NOT NULL",
+            &[&full_rotation_table_name],
+        )?
+        .get::<usize, bool>(0);
+    if !exists {
+        return Ok(None);
+    }
+
+    let rows = client.query(
+        &format!("SELECT r FROM {full_rotation_table_name} ORDER BY row_id"),
+        &[],
+    )?;
+    let dim = rows.len();
+    let values: Vec<f32> = rows
+        .iter()
+        .flat_map(|row| row.get::<usize, Vec<f32>>(0))
+        .collect();
+    if values.len() != dim * dim {
+        anyhow::bail!("Rotation matrix in {full_rotation_table_name} should be {dim}x{dim}");
+    }
+    Ok(Some(Array2::from_shape_vec((dim, dim), values)?))
+}
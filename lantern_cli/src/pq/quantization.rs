@@ -1,4 +1,5 @@
 use crate::logger::Logger;
+use crate::types::JOB_CANCELLED_MESSAGE;
 use crate::utils::{get_full_table_name, quote_ident};
 use rand::Rng;
 use rayon::prelude::*;
@@ -8,20 +9,23 @@ use std::io::Write;
 use std::sync::atomic::{AtomicU8, Ordering};
 use std::sync::{Arc, RwLock};
 use std::time::Instant;
+use ndarray::Array2;
 use postgres::{Client, NoTls, Transaction};
 
+use super::accel::assign;
+use super::codebook::read_codebook;
+use super::cli::Accel;
+pub use super::distance::l2sq_dist;
+use super::ivf::{compute_residuals, read_coarse_centroids};
+use super::keyset::{get_pk_type, get_task_key_ranges, split_key_range};
+use super::ledger;
+use super::opq::{read_rotation, rotate};
+use super::validation::{get_vector_dim, validate_coarse_centroids, validate_codebook, validate_pq_column};
 use super::{set_and_report_progress, report_progress, AnyhowVoidResult, DatasetItem, ProgressCbFn};
 
 
-fn l2sq_dist(a: &[f32], b: &[f32]) -> f32 {
-    a.iter()
-        .zip(b.iter())
-        .map(|(x, y)| ((*x) - (*y)) * ((*x) - (*y)))
-        .fold(0.0 as f32, ::std::ops::Add::add)
-}
-
 // Will iterate over all clusters and search the closes centroid to provided vector
-fn get_closest_centroid(centroids: &Vec<Vec<f32>>, subvector: &[f32]) -> u8 {
+pub fn get_closest_centroid(centroids: &Vec<Vec<f32>>, subvector: &[f32]) -> u16 {
     let mut closest_distance = f32::MAX;
     let mut closest_index = 0;
 
@@ -29,16 +33,15 @@ fn get_closest_centroid(centroids: &Vec<Vec<f32>>, subvector: &[f32]) -> u8 {
         let distance = l2sq_dist(&centroid, subvector);
         if distance < closest_distance {
             closest_distance = distance;
-            closest_index = idx as u8;
+            closest_index = idx as u16;
         }
     }
 
     closest_index
 }
 
-// Will parallel iterate over the dataset
-// Then iterate over each subvector of the vector and return
-// closest centroid id for that subvector
+// Will rotate the dataset if OPQ rotation is provided
+// Then for each subvector find the closest centroid ids of all vectors
 // Result will be vector with row id and quantized vector 
 pub fn quantize_vectors(
     dataset: &Vec<DatasetItem>,
@@ -46,30 +49,42 @@ pub fn quantize_vectors(
     subvector_dim: usize,
     splits: usize,
     codebooks_hashmap: Arc<RwLock<HashMap<usize, Vec<Vec<f32>>>>>,
+    rotation: Option<&Array2<f32>>,
+    accel: Accel,
     logger: &Logger,
-) -> Result<Vec<(String, Vec<u8>)>, anyhow::Error> {
+) -> Result<Vec<(String, Vec<u16>)>, anyhow::Error> {
     let quantization_start = Instant::now();
-    let rows: Vec<_> = dataset
-        .iter()
-        .map(|r| r.clone())
-        .collect::<Vec<DatasetItem>>()
-        .into_par_iter()
-        .map_with(codebooks_hashmap, |s, x| {
-            (
-                x.id.clone(),
-                (0..splits)
-                    .map(|i| {
-                        let map = s.read().unwrap();
-                        let split_centroids = map.get(&i).unwrap();
-                        let start_index = i * subvector_dim;
-                        let end_index = cmp::min(start_index + subvector_dim, vector_dim);
-                        get_closest_centroid(split_centroids, &x.vec[start_index..end_index])
-                    })
-                    .collect::<Vec<u8>>(),
-            )
+    let vectors: Vec<Vec<f32>> = dataset
+        .par_iter()
+        .map(|x| match rotation {
+            Some(rotation) => rotate(&x.vec, rotation),
+            None => x.vec.clone(),
         })
         .collect();
 
+    let map = codebooks_hashmap.read().unwrap();
+    let mut codes: Vec<Vec<u16>> = vec![Vec::with_capacity(splits); dataset.len()];
+    for i in 0..splits {
+        let split_centroids = map.get(&i).unwrap();
+        let start_index = i * subvector_dim;
+        let end_index = cmp::min(start_index + subvector_dim, vector_dim);
+        let subvectors = vectors
+            .iter()
+            .map(|vec| &vec[start_index..end_index])
+            .collect::<Vec<&[f32]>>();
+        // Batched search over all vectors, so GPU can process whole subvector at once
+        let closest = assign(&subvectors, split_centroids, accel)?;
+        for (code, centroid_id) in codes.iter_mut().zip(closest) {
+            code.push(centroid_id as u16);
+        }
+    }
+
+    let rows = dataset
+        .iter()
+        .map(|x| x.id.clone())
+        .zip(codes)
+        .collect();
+
     logger.debug(&format!(
         "Vector quantization duration: {}s",
         quantization_start.elapsed().as_secs()
@@ -80,10 +95,12 @@ pub fn quantize_vectors(
 // This function will write quantized vector into temporary table
 // Using COPY protocol and then update the original table via pk mapping
 // So we will use only one UPDATE query to write quantized vectors
+// In IVF-PQ mode ivf is (ivf column, coarse centroid ids in the same order as rows)
 // This function can be run in parallel
 pub fn write_quantized_rows<'a>(
     transaction: &mut Transaction<'a>,
-    rows: &Vec<(String, Vec<u8>)>,
+    rows: &Vec<(String, Vec<u16>)>,
+    ivf: Option<(&str, &[usize])>,
     schema: &str,
     table: &str,
     pq_column: &str,
@@ -93,15 +110,30 @@ pub fn write_quantized_rows<'a>(
     progress_cb: &Option<ProgressCbFn>,
     logger: &Logger,
 ) -> AnyhowVoidResult {
+    // Nothing is written, so the transaction can still be used by the caller
+    if rows.is_empty() {
+        return Ok(());
+    }
+
     let mut rng = rand::thread_rng();
     let full_table_name = get_full_table_name(schema, table);
     let temp_table_name = format!("_pq_tmp_{tmp_table_suffix}_{}", rng.gen_range(0..1000000));
     let export_time_start = Instant::now();
 
+    // Coarse centroid id is written with the same UPDATE query as PQ codes
+    let (ivf_select, ivf_set) = match ivf {
+        Some((ivf_column, _)) => (
+            format!(", {ivf_column}", ivf_column = quote_ident(ivf_column)),
+            format!(", {ivf_column} = src.{ivf_column}", ivf_column = quote_ident(ivf_column)),
+        ),
+        None => (String::new(), String::new()),
+    };
+
+    // Temporary table has the same column types as the table, PQVEC or INT2[] for bigger codebooks
     transaction
             .execute(
                 &format!(
-                    "CREATE TEMPORARY TABLE {temp_table_name} AS SELECT {pk} as id, '{{1}}'::PQVEC AS {pq_column} FROM {full_table_name} LIMIT 0",
+                    "CREATE TEMPORARY TABLE {temp_table_name} AS SELECT {pk} as id, {pq_column}{ivf_select} FROM {full_table_name} LIMIT 0",
                     pq_column = quote_ident(pq_column),
                     pk = quote_ident(pk)
                 ),
@@ -109,19 +141,24 @@ pub fn write_quantized_rows<'a>(
             )?;
 
     let mut writer = transaction.copy_in(&format!("COPY {temp_table_name} FROM stdin"))?;
-    let update_sql = &format!("UPDATE {full_table_name} dest SET {pq_column} = src.{pq_column} FROM {temp_table_name} src WHERE src.id = dest.{pk}", pq_column = quote_ident(pq_column), temp_table_name = quote_ident(&temp_table_name), pk = quote_ident(pk));
+    let update_sql = &format!("UPDATE {full_table_name} dest SET {pq_column} = src.{pq_column}{ivf_set} FROM {temp_table_name} src WHERE src.id = dest.{pk}", pq_column = quote_ident(pq_column), temp_table_name = quote_ident(&temp_table_name), pk = quote_ident(pk));
 
     let mut processed_row_cnt = 0;
     let total_row_cnt = rows.len();
 
-    for row in rows {
+    for (idx, row) in rows.iter().enumerate() {
         writer.write(row.0.as_bytes())?;
         writer.write("\t".as_bytes())?;
         writer.write("{".as_bytes())?;
-        let row_str: String = row.1.iter().map(|&x| x.to_string() + ",").collect();
+        // Codes above 32767 are written as negative INT2 values with the same bits
+        let row_str: String = row.1.iter().map(|&x| (x as i16).to_string() + ",").collect();
         writer.write(row_str[0..row_str.len() - 1].as_bytes())?;
         drop(row_str);
         writer.write("}".as_bytes())?;
+        if let Some((_, coarse_ids)) = ivf {
+            writer.write("\t".as_bytes())?;
+            writer.write(coarse_ids[idx].to_string().as_bytes())?;
+        }
         writer.write("\n".as_bytes())?;
         processed_row_cnt += 1;
 
@@ -135,10 +172,6 @@ pub fn write_quantized_rows<'a>(
         }
     }
 
-    if processed_row_cnt == 0 {
-        return Ok(());
-    }
-
     writer.flush()?;
     writer.finish()?;
     transaction.execute(update_sql, &[])?;
@@ -158,12 +191,15 @@ pub fn write_quantized_rows<'a>(
 // so it can be split over multiple vm instances to speed up quantization times
 pub struct QuantizeAndWriteVectorArgs<'a> {
    pub codebook_table_name: &'a str,
+   pub rotation_table_name: &'a str,
+   pub coarse_table_name: &'a str,
    pub full_table_name: &'a str,
    pub db_uri: &'a str,
    pub schema: &'a str,
    pub table: &'a str,
    pub column: &'a str,
    pub pq_column_name: &'a str,
+   pub ivf_column_name: &'a str,
    pub pk: &'a str,
    pub splits: usize,
    pub total_row_count: usize,
@@ -171,8 +207,10 @@ pub struct QuantizeAndWriteVectorArgs<'a> {
    pub parallel_task_count: &'a Option<usize>,
    pub quantization_task_id: &'a Option<usize>,
    pub max_connections: usize,
+   pub accel: Accel,
    pub main_progress: &'a AtomicU8,
    pub progress_cb: &'a Option<super::ProgressCbFn>,
+   pub is_canceled: &'a RwLock<bool>,
    pub logger: &'a Logger,
 }
 
@@ -187,75 +225,69 @@ pub fn quantize_and_write_vectors(args: QuantizeAndWriteVectorArgs, mut client:
     let schema =  args.schema;
     let table =  args.table;
     let pq_column_name = args.pq_column_name;
+    let ivf_column_name = args.ivf_column_name;
     let pk = args.pk;
+    let accel = args.accel;
     let main_progress = args.main_progress;
     let progress_cb =  args.progress_cb;
     
-    let mut limit_start = 0;
-    let mut limit_end = args.total_row_count;
-
-    // In batch mode each task will operate on a range of vectors from dataset
-    // Here we will determine the range from the task id
-    if let Some(quantization_task_id) = args.quantization_task_id {
-        if args.total_task_count.is_none() {
-            anyhow::bail!("Please provide --total-task-count when providing --quantization-task-id");
-        }
-        let quantization_task_count = args.total_task_count.as_ref().unwrap();
-        
-        let chunk_per_task = limit_end / quantization_task_count;
-        limit_start = chunk_per_task * quantization_task_id;
-        limit_end = if *quantization_task_id == quantization_task_count - 1 { limit_end + 1 } else { limit_start + chunk_per_task };
+    // In batch mode each task will operate on a key range of the table
+    if args.quantization_task_id.is_some() && args.total_task_count.is_none() {
+        anyhow::bail!("Please provide --total-task-count when providing --quantization-task-id");
     }
 
     // Read all codebook and create a hashmap from it
-    let codebook_read_start = Instant::now();
-    let codebook_rows = transaction.query(
-        &format!(
-            "SELECT subvector_id, centroid_id, c FROM {full_codebook_table_name} ORDER BY centroid_id ASC;"
-        ),
-        &[],
-    )?;
-
-    if codebook_rows.len() == 0 {
-        anyhow::bail!("Codebook does not contain any entries");
-    }
-
-    logger.debug(&format!("Coedbook fetched in {}s", codebook_read_start.elapsed().as_secs()));
-
-    let mut codebooks_hashmap: HashMap<usize, Vec<Vec<f32>>> = HashMap::new();
-    let cluster_count = codebook_rows.len() / splits;
-
-    let codebook_hashmap_creation_start = Instant::now();
-    let subvector_dim = codebook_rows[0].get::<usize, Vec<f32>>(2).len();
-    // Create hashmap from codebook
     // The hashmap will contain { [subvector_id]: Vec<f32> }
-    for row in codebook_rows {
-        let subvector_id = row.get::<usize, i32>(0) as usize;
-        let centroid_id = row.get::<usize, i32>(1) as usize;
-        let centroid = row.get::<usize, Vec<f32>>(2);
-        let subvector_codebook = codebooks_hashmap
-            .entry(subvector_id)
-            .or_insert(Vec::with_capacity(cluster_count));
-        subvector_codebook.insert(centroid_id, centroid);
-    }
-
-    if codebooks_hashmap.len() != splits {
-        anyhow::bail!(
-            "Incomplete codebook: expected size equal to {}, got: {}",
-            splits,
-            codebooks_hashmap.len()
-        );
+    let codebook_read_start = Instant::now();
+    let codebooks_hashmap = read_codebook(&mut transaction, full_codebook_table_name)?;
+    logger.debug(&format!("Codebook fetched in {}s", codebook_read_start.elapsed().as_secs()));
+
+    // Codebook and pq column are checked before writing any codes
+    let vector_dim = get_vector_dim(&mut transaction, full_table_name, column)?;
+    validate_codebook(&codebooks_hashmap, splits, vector_dim)?;
+    validate_pq_column(&mut transaction, full_table_name, pq_column_name, splits, codebooks_hashmap[&0].len())?;
+    let subvector_dim = vector_dim / splits;
+
+    // Codebook trained with --opq is applied to rotated vectors
+    let rotation = read_rotation(&mut transaction, args.rotation_table_name)?;
+    // Codebook trained with --coarse-clusters is applied to residuals from coarse centroids
+    let coarse_centroids = read_coarse_centroids(&mut transaction, args.coarse_table_name)?;
+    if let Some(coarse_centroids) = &coarse_centroids {
+        validate_coarse_centroids(coarse_centroids, vector_dim)?;
     }
-
-    logger.debug(&format!("Coedbook hashmap created in {}s", codebook_hashmap_creation_start.elapsed().as_secs()));
     set_and_report_progress(progress_cb, logger, main_progress, 10);
 
     let codebooks_hashmap = Arc::new(RwLock::new(codebooks_hashmap));
  
+    let use_ledger = args.quantization_task_id.is_some() && ledger::ledger_exists(&mut transaction)?;
+    if args.quantization_task_id.is_some() && !use_ledger {
+        logger.warn("Quantization ledger table does not exist, so this task will not be resumable and task ranges may shift if rows are deleted. Run table setup to create it");
+    }
+
+    // Key ranges of the tasks are read from the ledger, so all tasks use the same boundaries
+    // The ranges are resolved with another connection, so the lock is not held until the task ends
+    let pk_type = get_pk_type(&mut transaction, full_table_name, pk)?;
+    let (task_range, range_row_count) = match args.quantization_task_id {
+        Some(task_id) => {
+            let task_count = args.total_task_count.unwrap();
+            let task_range = if use_ledger {
+                let mut ledger_client = Client::connect(db_uri, NoTls)?;
+                ledger::get_task_range(&mut ledger_client, full_codebook_table_name, full_table_name, pk, args.total_row_count, task_count, *task_id)?
+            } else {
+                get_task_key_ranges(&mut transaction, full_table_name, pk, args.total_row_count, task_count)?.into_iter().nth(*task_id)
+            };
+            let Some(task_range) = task_range else {
+                logger.info("Table has fewer rows than quantization tasks, so there is nothing to quantize in this task");
+                return Ok(());
+            };
+            (Some(task_range), args.total_row_count / task_count)
+        }
+        None => (None, args.total_row_count),
+    };
+
     // Here we will read the range of data for this chunk in parallel
     // Based on total task count and machine CPU count
     // Then we will quantize the range chunk and write to database
-    let range_row_count = limit_end - limit_start;
     let num_cores: usize = std::thread::available_parallelism().unwrap().into();
     let  num_connections: usize = if args.quantization_task_id.is_some() {
         // This will never fail as it is checked on start to be specified if task id is present
@@ -273,28 +305,49 @@ pub fn quantize_and_write_vectors(args: QuantizeAndWriteVectorArgs, mut client:
 
     // Avoid division by zero error
     let num_connections = cmp::max(num_connections, 1);
-    let chunk_size = range_row_count / num_connections;
- 
+    // Resumable tasks are split into smaller chunks of fixed size, so less work is lost on failure
+    let chunk_size = if use_ledger { ledger::LEDGER_CHUNK_SIZE } else { cmp::max(range_row_count.div_ceil(num_connections), 1) };
+    let chunks = split_key_range(&mut transaction, full_table_name, pk, &pk_type, task_range.as_ref(), chunk_size)?;
+
+    // Batch task which is run again resumes from the ranges which are not committed yet
+    let chunks = if use_ledger {
+        let chunk_count = chunks.len();
+        let committed_ranges = ledger::read_committed_ranges(&mut transaction, full_codebook_table_name)?;
+        let chunks = ledger::get_pending_ranges(chunks, &committed_ranges);
+        if chunks.len() < chunk_count {
+            logger.info(&format!("Resuming task, {} of {chunk_count} chunks are already quantized", chunk_count - chunks.len()));
+        }
+        chunks
+    } else {
+        chunks
+    };
     logger.debug(&format!("max_connections: {}, num_cores: {num_cores}, num_connections: {num_connections}, chunk_count: {chunk_size}", args.max_connections));
 
     let quantization_and_write_start_time = Instant::now();
     
-    let results = (0..num_connections)
+    // Chunks are processed by num_connections threads, so connection limit is not exceeded
+    let pool = rayon::ThreadPoolBuilder::new().num_threads(num_connections).build()?;
+    let results = pool.install(|| chunks
         .into_par_iter()
-        .map_with(codebooks_hashmap, |map, i| {
+        .enumerate()
+        .map_with(codebooks_hashmap, |map, (chunk_id, range)| {
+            // Chunks which are not started yet are skipped when the job is cancelled
+            if *args.is_canceled.read().unwrap() {
+                anyhow::bail!(JOB_CANCELLED_MESSAGE);
+            }
             let mut client = Client::connect(&db_uri, NoTls)?;
             let mut transaction = client.transaction()?;
-            let range_start = limit_start + (i * chunk_size);
-            let range_end = if i == num_cores - 1 { limit_end } else { range_start + chunk_size };
 
             let fetch_start_time = Instant::now();
+            let (range_condition, range_params) = range.condition(pk, &pk_type);
             let rows = transaction.query(
                 &format!(
-            "SELECT id::text, {column} FROM {full_table_name} WHERE id >= {range_start} AND id < {range_end} ORDER BY id;",
+            "SELECT {pk}::text, {column} FROM {full_table_name} WHERE {range_condition} ORDER BY {pk};",
             full_table_name = full_table_name,
             column = quote_ident(column),
+            pk = quote_ident(pk),
               ),
-                &[],
+                &range_params,
             )?;
                 logger.info(&format!(
                     "Fetched {} items in {}s",
@@ -320,31 +373,50 @@ pub fn quantize_and_write_vectors(args: QuantizeAndWriteVectorArgs, mut client:
 
                 })
                 .collect::<Vec<DatasetItem>>();
-            let vector_dim = rows[0].vec.len();
+            let (coarse_ids, rows) = match &coarse_centroids {
+                Some(coarse_centroids) => {
+                    let (coarse_ids, residuals) = compute_residuals(&rows, coarse_centroids, accel)?;
+                    (Some(coarse_ids), residuals)
+                }
+                None => (None, rows),
+            };
             let rows = quantize_vectors(
                 &rows,
                 vector_dim,
                 subvector_dim,
                 splits,
                 map.clone(),
+                rotation.as_ref(),
+                accel,
                 &logger,
             )?;
             
             write_quantized_rows(
                 &mut transaction,
                 &rows,
+                coarse_ids.as_deref().map(|coarse_ids| (ivf_column_name, coarse_ids)),
                 schema,
                 table,
                 pq_column_name,
                 pk,
-                &range_start.to_string(),
+                &format!("{}_{chunk_id}", args.quantization_task_id.unwrap_or(0)),
                 &main_progress,
                 progress_cb,
                 &logger,
             )?;
+            if use_ledger {
+                ledger::record_committed_range(&mut transaction, full_codebook_table_name, args.quantization_task_id.unwrap(), &range)?;
+            }
             transaction.commit()?;
             Ok::<(), anyhow::Error>(())
-        }).collect::<Vec<Result<(), anyhow::Error>>>();
+        }).collect::<Vec<Result<(), anyhow::Error>>>());
+
+    // Each chunk is committed separately, so chunks finished before cancelling are kept
+    let committed_chunk_count = results.iter().filter(|result| result.is_ok()).count();
+    if *args.is_canceled.read().unwrap() && committed_chunk_count < results.len() {
+        logger.warn(&format!("Quantization cancelled, {committed_chunk_count} of {} chunks were committed before stopping", results.len()));
+        anyhow::bail!(JOB_CANCELLED_MESSAGE);
+    }
 
     for result in results {
        result?;
@@ -4,12 +4,28 @@ use postgres::Transaction;
 
 use super::{AnyhowVoidResult, LANTERN_INTERNAL_SCHEMA_NAME};
 
-// Will create a codebook table add neccessary indexes and add PQVEC column into target table
+// PQVEC stores one byte codes, so codes of bigger codebooks are stored in INT2[] column
+// Codes above 32767 are stored as negative numbers with the same bits
+pub static MAX_PQVEC_CLUSTERS: usize = 256;
+
+pub fn get_pq_column_type(clusters: usize) -> &'static str {
+    if clusters <= MAX_PQVEC_CLUSTERS {
+        "PQVEC"
+    } else {
+        "INT2[]"
+    }
+}
+
+// Will create a codebook table add neccessary indexes and add PQ column into target table
 pub fn setup_tables<'a>(
     transaction: &mut Transaction<'a>,
     full_table_name: &str,
     full_codebook_table_name: &str,
+    full_rotation_table_name: &str,
+    full_coarse_table_name: &str,
     pq_column_name: &str,
+    ivf_column_name: Option<&str>,
+    clusters: usize,
     overwrite: bool,
     logger: &Logger,
 ) -> AnyhowVoidResult {
@@ -21,16 +37,36 @@ pub fn setup_tables<'a>(
         ",
             pq_column_name = quote_ident(&pq_column_name)
         ))?;
+        if let Some(ivf_column_name) = ivf_column_name {
+            transaction.batch_execute(&format!(
+                "ALTER TABLE {full_table_name} DROP COLUMN IF EXISTS {ivf_column_name};",
+                ivf_column_name = quote_ident(ivf_column_name)
+            ))?;
+        }
     }
+    // Rotation and coarse centroids of previous codebook should not be applied to the new codebook
+    transaction.batch_execute(&format!(
+        "
+             DROP TABLE IF EXISTS {full_rotation_table_name};
+             DROP TABLE IF EXISTS {full_coarse_table_name};
+        "
+    ))?;
     transaction.batch_execute(&format!(
         "
              CREATE UNLOGGED TABLE {full_codebook_table_name} (subvector_id INT, centroid_id INT, c REAL[]);
-             ALTER TABLE {full_table_name} ADD COLUMN {pq_column_name} PQVEC;
+             ALTER TABLE {full_table_name} ADD COLUMN {pq_column_name} {pq_column_type};
              CREATE INDEX ON {full_codebook_table_name} USING BTREE(subvector_id, centroid_id);
              CREATE INDEX ON {full_codebook_table_name} USING BTREE(centroid_id);
         ",
-        pq_column_name = quote_ident(&pq_column_name)
+        pq_column_name = quote_ident(&pq_column_name),
+        pq_column_type = get_pq_column_type(clusters)
     ))?;
+    if let Some(ivf_column_name) = ivf_column_name {
+        transaction.batch_execute(&format!(
+            "ALTER TABLE {full_table_name} ADD COLUMN IF NOT EXISTS {ivf_column_name} INT;",
+            ivf_column_name = quote_ident(ivf_column_name)
+        ))?;
+    }
     logger.info(&format!(
         "{full_codebook_table_name} table and {pq_column_name} column created successfully"
     ));
@@ -46,6 +82,9 @@ pub fn setup_triggers<'a>(
     column: &str,
     distance_metric: &str,
     splits: usize,
+    clusters: usize,
+    full_rotation_table_name: Option<&str>,
+    ivf: Option<(&str, &str)>,
 ) -> AnyhowVoidResult {
     // Setup triggers for new data
     let name_hash = md5::compute(format!("{}{}", full_table_name, pq_column));
@@ -53,6 +92,38 @@ pub fn setup_triggers<'a>(
     let update_trigger_name = format!("_pq_trigger_up_{:x}", name_hash);
     let trigger_fn_name = format!("{LANTERN_INTERNAL_SCHEMA_NAME}._set_pq_col_{:x}", name_hash);
 
+    // Vectors are rotated in the same way as during quantization, if codebook was trained with --opq
+    let vector_expr = match full_rotation_table_name {
+        Some(full_rotation_table_name) => {
+            setup_rotation_function(transaction)?;
+            format!("{LANTERN_INTERNAL_SCHEMA_NAME}.opq_rotate(NEW.{column}, '{full_rotation_table_name}'::regclass)", column = quote_ident(column))
+        }
+        None => format!("NEW.{column}", column = quote_ident(column)),
+    };
+
+    // In IVF-PQ mode the closest coarse centroid is stored and its residual is quantized
+    // ivf is (ivf column, coarse centroid table)
+    let (ivf_reset, ivf_assignment, vector_expr) = match ivf {
+        Some((ivf_column, full_coarse_table_name)) => {
+            setup_ivf_functions(transaction)?;
+            let ivf_column = quote_ident(ivf_column);
+            (
+                format!("NEW.{ivf_column} := NULL;"),
+                format!("NEW.{ivf_column} := {LANTERN_INTERNAL_SCHEMA_NAME}.ivf_assign({vector_expr}, '{full_coarse_table_name}'::regclass);"),
+                format!("{LANTERN_INTERNAL_SCHEMA_NAME}.ivf_residual({vector_expr}, NEW.{ivf_column}, '{full_coarse_table_name}'::regclass)"),
+            )
+        }
+        None => (String::new(), String::new(), vector_expr),
+    };
+
+    // quantize_vector of lantern returns PQVEC, codes of bigger codebooks are computed here
+    let quantize_fn_name = if clusters <= MAX_PQVEC_CLUSTERS {
+        format!("{LANTERN_INTERNAL_SCHEMA_NAME}.quantize_vector")
+    } else {
+        setup_quantize_u16_function(transaction)?;
+        format!("{LANTERN_INTERNAL_SCHEMA_NAME}.quantize_vector_u16")
+    };
+
     transaction.batch_execute(&format!("
       DROP TRIGGER IF EXISTS {insert_trigger_name} ON {full_table_name};
       DROP TRIGGER IF EXISTS {update_trigger_name} ON {full_table_name};
@@ -64,8 +135,10 @@ pub fn setup_triggers<'a>(
         BEGIN
           IF NEW.{column} IS NULL THEN
             NEW.{pq_column} := NULL;
+            {ivf_reset}
           ELSE
-            NEW.{pq_column} := {LANTERN_INTERNAL_SCHEMA_NAME}.quantize_vector(NEW.{column}, {splits}, '{full_codebook_table_name}'::regclass, '{distance_metric}');
+            {ivf_assignment}
+            NEW.{pq_column} := {quantize_fn_name}({vector_expr}, {splits}, '{full_codebook_table_name}'::regclass, '{distance_metric}');
           END IF;
           RETURN NEW;
         END
@@ -77,6 +150,80 @@ pub fn setup_triggers<'a>(
     Ok(())
 }
 
+// Multiplies vector with the rotation matrix stored as (row_id, r) rows
+// It can also be used to rotate query vectors to compare them with decoded vectors
+fn setup_rotation_function<'a>(transaction: &mut Transaction<'a>) -> AnyhowVoidResult {
+    transaction.batch_execute(&format!("
+      CREATE OR REPLACE FUNCTION {LANTERN_INTERNAL_SCHEMA_NAME}.opq_rotate(vec REAL[], rotation_table regclass)
+          RETURNS REAL[]
+          LANGUAGE plpgsql STABLE AS
+      $body$
+        DECLARE
+          result REAL[];
+        BEGIN
+          EXECUTE format('SELECT array_agg(val ORDER BY col) FROM (SELECT col, SUM($1[rot.row_id + 1] * rot.r[col])::real AS val FROM %s rot, generate_subscripts(rot.r, 1) AS col GROUP BY col) t', rotation_table)
+            INTO result USING vec;
+          RETURN result;
+        END
+      $body$;
+    "))?;
+    Ok(())
+}
+
+// Returns INT2[] codes with the closest centroid id of each subvector, as during quantization
+fn setup_quantize_u16_function<'a>(transaction: &mut Transaction<'a>) -> AnyhowVoidResult {
+    transaction.batch_execute(&format!("
+      CREATE OR REPLACE FUNCTION {LANTERN_INTERNAL_SCHEMA_NAME}.quantize_vector_u16(vec REAL[], splits INT, codebook_table regclass, distance_metric TEXT)
+          RETURNS INT2[]
+          LANGUAGE plpgsql STABLE AS
+      $body$
+        DECLARE
+          result INT2[];
+        BEGIN
+          IF distance_metric != 'l2sq' THEN
+            RAISE EXCEPTION 'Distance metric % is not supported', distance_metric;
+          END IF;
+          EXECUTE format('SELECT array_agg(code ORDER BY subvector_id) FROM (SELECT DISTINCT ON (subvector_id) subvector_id, (centroid_id - CASE WHEN centroid_id > 32767 THEN 65536 ELSE 0 END)::INT2 AS code FROM %s ORDER BY subvector_id, l2sq_dist(c, $1[subvector_id * $2 + 1:(subvector_id + 1) * $2]), centroid_id) t', codebook_table)
+            INTO result USING vec, array_length(vec, 1) / splits;
+          RETURN result;
+        END
+      $body$;
+    "))?;
+    Ok(())
+}
+
+// Closest coarse centroid and residual of a vector are computed in the same way as during quantization
+fn setup_ivf_functions<'a>(transaction: &mut Transaction<'a>) -> AnyhowVoidResult {
+    transaction.batch_execute(&format!("
+      CREATE OR REPLACE FUNCTION {LANTERN_INTERNAL_SCHEMA_NAME}.ivf_assign(vec REAL[], coarse_table regclass)
+          RETURNS INT
+          LANGUAGE plpgsql STABLE AS
+      $body$
+        DECLARE
+          result INT;
+        BEGIN
+          EXECUTE format('SELECT id FROM %s ORDER BY l2sq_dist(c, $1), id LIMIT 1', coarse_table)
+            INTO result USING vec;
+          RETURN result;
+        END
+      $body$;
+
+      CREATE OR REPLACE FUNCTION {LANTERN_INTERNAL_SCHEMA_NAME}.ivf_residual(vec REAL[], coarse_id INT, coarse_table regclass)
+          RETURNS REAL[]
+          LANGUAGE plpgsql STABLE AS
+      $body$
+        DECLARE
+          result REAL[];
+        BEGIN
+          EXECUTE format('SELECT array_agg((v - coarse.c[i])::real ORDER BY i) FROM %s coarse, unnest($1) WITH ORDINALITY AS t(v, i) WHERE coarse.id = $2', coarse_table)
+            INTO result USING vec, coarse_id;
+          RETURN result;
+        END
+      $body$;
+    "))?;
+    Ok(())
+}
+
 pub fn make_codebook_logged_and_readonly<'a>(
     transaction: &mut Transaction<'a>,
     full_codebook_table_name: &str,
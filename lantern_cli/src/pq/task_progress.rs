@@ -0,0 +1,248 @@
+use crate::logger::Logger;
+use crate::types::{format_duration, JOB_CANCELLED_MESSAGE};
+use postgres::{Client, GenericClient, NoTls, Transaction};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::Duration;
+
+use super::{AnyhowVoidResult, ProgressCbFn, LANTERN_INTERNAL_SCHEMA_NAME};
+
+// Progress of batch tasks of all codebooks is kept in one table, so any process with database
+// access can aggregate it without knowing where the tasks are run
+static PROGRESS_TABLE_NAME: &'static str = "pq_task_progress";
+static WATCH_INTERVAL: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TaskPhase {
+    // Tasks run with --subvector-id, one per subvector
+    Clustering,
+    // Tasks run with --quantization-task-id, one per range of rows
+    Quantization,
+}
+
+impl fmt::Display for TaskPhase {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaskPhase::Clustering => write!(f, "clustering"),
+            TaskPhase::Quantization => write!(f, "quantization"),
+        }
+    }
+}
+
+fn get_full_progress_table_name() -> String {
+    format!("{LANTERN_INTERNAL_SCHEMA_NAME}.{PROGRESS_TABLE_NAME}")
+}
+
+// Creates the progress table if needed and removes progress of previous jobs of the codebook
+pub fn setup_progress_table<'a>(
+    transaction: &mut Transaction<'a>,
+    full_codebook_table_name: &str,
+) -> AnyhowVoidResult {
+    let full_progress_table_name = get_full_progress_table_name();
+    transaction.batch_execute(&format!(
+        "
+        CREATE TABLE IF NOT EXISTS {full_progress_table_name} (
+            codebook_table TEXT,
+            phase TEXT,
+            task_id INT,
+            progress INT NOT NULL,
+            started_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+            PRIMARY KEY (codebook_table, phase, task_id)
+        );
+        "
+    ))?;
+    transaction.execute(
+        &format!("DELETE FROM {full_progress_table_name} WHERE codebook_table = $1"),
+        &[&full_codebook_table_name],
+    )?;
+    Ok(())
+}
+
+// Returns callback which writes progress of this task to the progress table and calls the
+// inner callback. Failed writes are logged and disable writing, as progress is not needed for
+// the task to succeed
+pub fn task_progress_cb(
+    db_uri: &str,
+    full_codebook_table_name: &str,
+    phase: TaskPhase,
+    task_id: usize,
+    progress_cb: Option<ProgressCbFn>,
+    logger: &Logger,
+) -> ProgressCbFn {
+    let logger = logger.clone();
+    let client = match Client::connect(db_uri, NoTls) {
+        Ok(client) => Some(Mutex::new(client)),
+        Err(e) => {
+            logger.warn(&format!("Task progress will not be reported: {e}"));
+            None
+        }
+    };
+    let disabled = AtomicBool::new(client.is_none());
+    let full_codebook_table_name = full_codebook_table_name.to_owned();
+    let query = format!(
+        "INSERT INTO {} (codebook_table, phase, task_id, progress) VALUES ($1, $2, $3, $4)
+         ON CONFLICT (codebook_table, phase, task_id) DO UPDATE SET progress = EXCLUDED.progress, updated_at = now()",
+        get_full_progress_table_name()
+    );
+
+    let write_progress = move |progress: u8| {
+        if disabled.load(Ordering::SeqCst) {
+            return;
+        }
+        let mut client = client.as_ref().unwrap().lock().unwrap();
+        let result = client.execute(
+            &query,
+            &[
+                &full_codebook_table_name,
+                &phase.to_string(),
+                &(task_id as i32),
+                &(progress as i32),
+            ],
+        );
+        if let Err(e) = result {
+            disabled.store(true, Ordering::SeqCst);
+            logger.warn(&format!(
+                "Task progress will not be reported: {e}. Run table setup to create the progress table"
+            ));
+        }
+    };
+    // Start time of the task is written before the first progress update
+    write_progress(0);
+
+    Box::new(move |progress: u8| {
+        write_progress(progress);
+        if let Some(progress_cb) = &progress_cb {
+            progress_cb(progress);
+        }
+    })
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AggregatedProgress {
+    pub phase: TaskPhase,
+    pub total_tasks: usize,
+    pub started_tasks: usize,
+    pub finished_tasks: usize,
+    // Mean progress of all tasks, tasks which are not started have 0%
+    pub percent: f64,
+    // Estimated from the time since the first task started
+    pub eta: Option<Duration>,
+}
+
+impl fmt::Display for AggregatedProgress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: {:.1}% ({}/{} tasks finished, {} running",
+            self.phase,
+            self.percent,
+            self.finished_tasks,
+            self.total_tasks,
+            self.started_tasks - self.finished_tasks
+        )?;
+        if let Some(eta) = self.eta {
+            write!(f, ", ETA {}", format_duration(eta))?;
+        }
+        write!(f, ")")
+    }
+}
+
+pub fn aggregate_progress(
+    client: &mut impl GenericClient,
+    full_codebook_table_name: &str,
+    phase: TaskPhase,
+    total_tasks: usize,
+) -> Result<AggregatedProgress, anyhow::Error> {
+    let full_progress_table_name = get_full_progress_table_name();
+    let exists = client
+        .query_one(
+            "SELECT to_regclass($1::text) IS NOT NULL",
+            &[&full_progress_table_name],
+        )?
+        .get::<usize, bool>(0);
+
+    let (started_tasks, finished_tasks, progress_sum, elapsed) = if exists {
+        let row = client.query_one(
+            &format!(
+                "SELECT COUNT(*), COUNT(*) FILTER (WHERE progress >= 100), COALESCE(SUM(LEAST(progress, 100)), 0)::BIGINT, EXTRACT(EPOCH FROM now() - MIN(started_at))::FLOAT8
+                 FROM {full_progress_table_name} WHERE codebook_table = $1 AND phase = $2"
+            ),
+            &[&full_codebook_table_name, &phase.to_string()],
+        )?;
+        (
+            row.get::<usize, i64>(0) as usize,
+            row.get::<usize, i64>(1) as usize,
+            row.get::<usize, i64>(2) as usize,
+            row.get::<usize, Option<f64>>(3),
+        )
+    } else {
+        (0, 0, 0, None)
+    };
+
+    let total_tasks = total_tasks.max(started_tasks).max(1);
+    let percent = progress_sum as f64 / total_tasks as f64;
+    let eta = match elapsed {
+        Some(elapsed) if percent > 0.0 && percent < 100.0 => Some(Duration::from_secs_f64(
+            elapsed * (100.0 - percent) / percent,
+        )),
+        _ => None,
+    };
+
+    Ok(AggregatedProgress {
+        phase,
+        total_tasks,
+        started_tasks,
+        finished_tasks,
+        percent,
+        eta,
+    })
+}
+
+// Reports overall progress of batch tasks until all of them are finished
+// Each phase is given with its expected task count
+pub fn watch_progress(
+    db_uri: &str,
+    full_codebook_table_name: &str,
+    phases: &[(TaskPhase, usize)],
+    progress_cb: &Option<ProgressCbFn>,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    logger: &Logger,
+) -> AnyhowVoidResult {
+    let mut client = Client::connect(db_uri, NoTls)?;
+    let total_tasks: usize = phases.iter().map(|(_, task_count)| task_count).sum();
+    let mut last_percent = 0;
+
+    loop {
+        let mut progress_sum = 0.0;
+        let mut finished = true;
+        for (phase, task_count) in phases {
+            let progress =
+                aggregate_progress(&mut client, full_codebook_table_name, *phase, *task_count)?;
+            logger.info(&progress.to_string());
+            progress_sum += progress.percent * progress.total_tasks as f64;
+            finished = finished && progress.finished_tasks >= progress.total_tasks;
+        }
+
+        let percent = (progress_sum / total_tasks.max(1) as f64).min(100.0) as u8;
+        if percent > last_percent {
+            last_percent = percent;
+            if let Some(progress_cb) = progress_cb {
+                progress_cb(percent);
+            }
+        }
+
+        if finished {
+            logger.info("All tasks are finished");
+            return Ok(());
+        }
+        if is_canceled
+            .as_ref()
+            .is_some_and(|is_canceled| *is_canceled.read().unwrap())
+        {
+            anyhow::bail!(JOB_CANCELLED_MESSAGE);
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
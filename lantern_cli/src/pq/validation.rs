@@ -0,0 +1,144 @@
+use crate::utils::quote_ident;
+use postgres::GenericClient;
+use std::collections::HashMap;
+
+use super::setup::get_pq_column_type;
+use super::AnyhowVoidResult;
+
+// PQ codes are u16, so there can not be more centroids per subvector
+pub static MAX_CLUSTERS: usize = 65536;
+
+// Returns dimensions of the first non null vector and checks that all vectors have the same
+pub fn get_vector_dim(
+    client: &mut impl GenericClient,
+    full_table_name: &str,
+    column: &str,
+) -> Result<usize, anyhow::Error> {
+    let column = quote_ident(column);
+    let rows = client.query(
+        &format!(
+            "SELECT MIN(ARRAY_LENGTH({column}, 1)), MAX(ARRAY_LENGTH({column}, 1)) FROM {full_table_name} WHERE {column} IS NOT NULL"
+        ),
+        &[],
+    )?;
+    let (min_dim, max_dim) = (
+        rows[0].get::<usize, Option<i32>>(0),
+        rows[0].get::<usize, Option<i32>>(1),
+    );
+
+    match (min_dim, max_dim) {
+        (Some(min_dim), Some(max_dim)) if min_dim == max_dim => Ok(min_dim as usize),
+        (Some(min_dim), Some(max_dim)) => anyhow::bail!(
+            "Vectors in column {column} should have the same dimensions, found vectors with {min_dim} and {max_dim} dimensions"
+        ),
+        _ => anyhow::bail!("Column {column} of {full_table_name} does not contain vectors"),
+    }
+}
+
+pub fn validate_splits(splits: usize, vector_dim: usize) -> AnyhowVoidResult {
+    if splits == 0 || vector_dim % splits != 0 {
+        anyhow::bail!(
+            "--splits ({splits}) should divide vector dimensions ({vector_dim}), otherwise the last {} dimensions are not quantized. Possible values are divisors of {vector_dim}",
+            if splits == 0 { vector_dim } else { vector_dim % splits }
+        );
+    }
+    Ok(())
+}
+
+// Codebook read from the table or a file should match the table it is applied to
+pub fn validate_codebook(
+    codebooks: &HashMap<usize, Vec<Vec<f32>>>,
+    splits: usize,
+    vector_dim: usize,
+) -> AnyhowVoidResult {
+    if let Some(subvector_id) = (0..splits).find(|id| !codebooks.contains_key(id)) {
+        anyhow::bail!(
+            "Incomplete codebook: subvector {subvector_id} is missing, codebook should have subvector ids from 0 to {}. Make sure all clustering tasks are finished",
+            splits - 1
+        );
+    }
+    if codebooks.len() != splits {
+        anyhow::bail!(
+            "Codebook has {} subvectors, but --splits is {splits}. Pass --splits {} or recreate the codebook",
+            codebooks.len(),
+            codebooks.len()
+        );
+    }
+
+    let clusters = codebooks[&0].len();
+    let subvector_dim = codebooks[&0].first().map(|c| c.len()).unwrap_or(0);
+    if clusters == 0 || clusters > MAX_CLUSTERS {
+        anyhow::bail!("Codebook should have from 1 to {MAX_CLUSTERS} centroids per subvector, found {clusters}");
+    }
+    for (subvector_id, centroids) in codebooks {
+        if centroids.len() != clusters {
+            anyhow::bail!(
+                "Subvector {subvector_id} has {} centroids, but subvector 0 has {clusters}. Recreate the codebook",
+                centroids.len()
+            );
+        }
+        if let Some(centroid_id) = centroids.iter().position(|c| c.len() != subvector_dim) {
+            anyhow::bail!(
+                "Centroid {centroid_id} of subvector {subvector_id} has {} dimensions, expected {subvector_dim}. Recreate the codebook",
+                centroids[centroid_id].len()
+            );
+        }
+    }
+
+    if subvector_dim * splits != vector_dim {
+        anyhow::bail!(
+            "Codebook covers {} dimensions ({splits} subvectors x {subvector_dim}), but vectors have {vector_dim} dimensions. The codebook was created for another column",
+            subvector_dim * splits
+        );
+    }
+    Ok(())
+}
+
+// Coarse centroids of IVF-PQ codebook are subtracted from whole vectors
+pub fn validate_coarse_centroids(
+    coarse_centroids: &[Vec<f32>],
+    vector_dim: usize,
+) -> AnyhowVoidResult {
+    if let Some(id) = coarse_centroids.iter().position(|c| c.len() != vector_dim) {
+        anyhow::bail!(
+            "Coarse centroid {id} has {} dimensions, but vectors have {vector_dim} dimensions. Recreate the codebook",
+            coarse_centroids[id].len()
+        );
+    }
+    Ok(())
+}
+
+// Type of PQ column should match the cluster count of the codebook
+// If PQ column was created with a type modifier, it should be equal to subvector count
+pub fn validate_pq_column(
+    client: &mut impl GenericClient,
+    full_table_name: &str,
+    pq_column: &str,
+    splits: usize,
+    clusters: usize,
+) -> AnyhowVoidResult {
+    let expected_type = get_pq_column_type(clusters);
+    let rows = client.query(
+        "SELECT atttypmod, format_type(atttypid, NULL), atttypid = $3::text::regtype FROM pg_attribute WHERE attrelid = $1::text::regclass AND attname = $2 AND NOT attisdropped",
+        &[&full_table_name, &pq_column, &expected_type],
+    )?;
+
+    if rows.is_empty() {
+        anyhow::bail!("Column {pq_column} does not exist in {full_table_name}. Run without --skip-table-setup to create it");
+    }
+
+    if !rows[0].get::<usize, bool>(2) {
+        anyhow::bail!(
+            "Column {pq_column} is {}, but codebook with {clusters} centroids per subvector needs {expected_type} column. Recreate the column with --overwrite",
+            rows[0].get::<usize, String>(1)
+        );
+    }
+
+    let typmod = rows[0].get::<usize, i32>(0);
+    if typmod > 0 && typmod as usize != splits {
+        anyhow::bail!(
+            "Column {pq_column} is PQVEC({typmod}), but --splits is {splits}. Pass --splits {typmod} or recreate the column with --overwrite"
+        );
+    }
+    Ok(())
+}
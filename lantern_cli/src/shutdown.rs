@@ -0,0 +1,52 @@
+use crate::logger::Logger;
+use crate::types::AnyhowVoidResult;
+use std::sync::{Arc, RwLock};
+use tokio::signal::unix::{signal, SignalKind};
+
+// Exit code of a process stopped with the second signal, the same as shells use for SIGINT
+static FORCED_EXIT_CODE: i32 = 130;
+
+fn listen_for_signals(is_canceled: Arc<RwLock<bool>>, logger: Logger) -> AnyhowVoidResult {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    // Handlers are registered before returning, so signals received after it are not missed
+    let (mut sigint, mut sigterm) = {
+        let _enter = runtime.enter();
+        (
+            signal(SignalKind::interrupt())?,
+            signal(SignalKind::terminate())?,
+        )
+    };
+
+    std::thread::Builder::new()
+        .name("lantern-signals".to_owned())
+        .spawn(move || {
+            runtime.block_on(async {
+                loop {
+                    tokio::select! {
+                        _ = sigint.recv() => {}
+                        _ = sigterm.recv() => {}
+                    }
+                    if *is_canceled.read().unwrap() {
+                        logger.error("Received second shutdown signal, exiting immediately");
+                        std::process::exit(FORCED_EXIT_CODE);
+                    }
+                    *is_canceled.write().unwrap() = true;
+                    logger.warn("Received shutdown signal, stopping after the current batch. Send it again to exit immediately");
+                }
+            })
+        })?;
+    Ok(())
+}
+
+// Returns the cancel flag of a job, which is set on the first SIGINT or SIGTERM
+// Jobs check the flag between batches, so the rows which were already written are committed
+// before the process exits. The second signal exits the process immediately
+pub fn cancel_on_signal(logger: &Logger) -> Arc<RwLock<bool>> {
+    let is_canceled = Arc::new(RwLock::new(false));
+    if let Err(e) = listen_for_signals(is_canceled.clone(), logger.clone()) {
+        logger.warn(&format!("Shutdown signals will not be handled: {e}"));
+    }
+    is_canceled
+}
@@ -0,0 +1,29 @@
+use clap::Parser;
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct SupportBundleArgs {
+    /// Fully associated database connection string including db name to collect server info
+    #[arg(short, long)]
+    pub uri: Option<String>,
+
+    /// Output path of the bundle
+    #[arg(short, long, default_value = "lantern-support-bundle.tar.gz")]
+    pub out: String,
+
+    /// Schema name of embedding jobs table
+    #[arg(long, default_value = "public")]
+    pub jobs_schema: String,
+
+    /// Embedding jobs table name to collect last job summaries from
+    #[arg(long, default_value = "embedding_jobs")]
+    pub jobs_table: String,
+
+    /// Log files to include in the bundle (can be passed multiple times)
+    #[arg(long)]
+    pub log_file: Vec<String>,
+
+    /// Number of last lines to include from each log file
+    #[arg(long, default_value_t = 1000)]
+    pub log_lines: usize,
+}
@@ -0,0 +1,235 @@
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, redact_secrets};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use postgres::{Client, NoTls};
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::path::Path;
+use std::time::SystemTime;
+use sysinfo::{System, SystemExt};
+
+pub mod cli;
+
+static CONNECTION_PARAMS: &'static str = "connect_timeout=10";
+static BUNDLE_DIR: &str = "lantern-support-bundle";
+static JOBS_LIMIT: i64 = 20;
+static ENV_PREFIXES: [&str; 4] = ["LANTERN", "ORT_", "RUST_LOG", "PG"];
+static DB_SETTINGS: [&str; 6] = [
+    "server_version",
+    "shared_buffers",
+    "work_mem",
+    "maintenance_work_mem",
+    "max_connections",
+    "shared_preload_libraries",
+];
+
+fn get_system_info() -> Value {
+    let sys = System::new_all();
+
+    #[cfg(feature = "embeddings")]
+    let runtimes = crate::embeddings::core::get_available_runtimes();
+    #[cfg(not(feature = "embeddings"))]
+    let runtimes: Vec<String> = Vec::new();
+
+    json!({
+        "lantern_cli_version": env!("CARGO_PKG_VERSION"),
+        "os": std::env::consts::OS,
+        "arch": std::env::consts::ARCH,
+        "os_version": sys.long_os_version(),
+        "kernel_version": sys.kernel_version(),
+        "cpus": sys.cpus().len(),
+        "total_memory": sys.total_memory(),
+        "available_memory": sys.available_memory(),
+        "runtimes": runtimes,
+    })
+}
+
+fn get_env_info() -> String {
+    let mut vars: Vec<String> = std::env::vars()
+        .filter(|(key, _)| ENV_PREFIXES.iter().any(|prefix| key.starts_with(prefix)))
+        .map(|(key, value)| {
+            // Hide values of secret-like variables entirely, e.g PGPASSWORD
+            if redact_secrets(&format!("{key}=x")) != format!("{key}=x") {
+                format!("{key}=***")
+            } else {
+                redact_secrets(&format!("{key}={value}"))
+            }
+        })
+        .collect();
+    vars.sort();
+    vars.join("\n")
+}
+
+fn get_database_info(client: &mut Client) -> Result<Value, anyhow::Error> {
+    let version = client
+        .query_one("SELECT version()", &[])?
+        .get::<usize, String>(0);
+
+    let extensions: Vec<Value> = client
+        .query("SELECT extname::text, extversion FROM pg_extension", &[])?
+        .iter()
+        .map(|row| {
+            json!({
+                "name": row.get::<usize, String>(0),
+                "version": row.get::<usize, String>(1),
+            })
+        })
+        .collect();
+
+    let settings: serde_json::Map<String, Value> = client
+        .query(
+            "SELECT name, setting FROM pg_settings WHERE name = ANY($1)",
+            &[&DB_SETTINGS.to_vec()],
+        )?
+        .iter()
+        .map(|row| {
+            (
+                row.get::<usize, String>(0),
+                Value::String(row.get::<usize, String>(1)),
+            )
+        })
+        .collect();
+
+    Ok(json!({
+        "version": version,
+        "extensions": extensions,
+        "settings": settings,
+    }))
+}
+
+// Connection strings and runtime params of jobs are not collected as they may contain credentials
+fn get_jobs_info(
+    client: &mut Client,
+    args: &cli::SupportBundleArgs,
+) -> Result<Value, anyhow::Error> {
+    let full_table_name = get_full_table_name(&args.jobs_schema, &args.jobs_table);
+    let table_exists = client
+        .query_one("SELECT to_regclass($1) IS NOT NULL", &[&full_table_name])?
+        .get::<usize, bool>(0);
+
+    if !table_exists {
+        return Ok(Value::Array(Vec::new()));
+    }
+
+    let jobs: Vec<Value> = client
+        .query(
+            &format!(
+                "
+                SELECT row_to_json(j)::text FROM (
+                    SELECT id, \"schema\", \"table\", src_column, dst_column, embedding_model, runtime, batch_size,
+                           status, progress, processed_rows, processed_tokens, error, attempts, runner,
+                           created_at, started_at, finished_at
+                    FROM {full_table_name} ORDER BY id DESC LIMIT $1
+                ) j
+            "
+            ),
+            &[&JOBS_LIMIT],
+        )?
+        .iter()
+        .map(|row| {
+            let job = redact_secrets(&row.get::<usize, String>(0));
+            serde_json::from_str(&job).unwrap_or(Value::String(job))
+        })
+        .collect();
+
+    Ok(Value::Array(jobs))
+}
+
+fn tail_log_file(path: &str, lines: usize) -> String {
+    match fs::read(path) {
+        Ok(content) => {
+            let content = String::from_utf8_lossy(&content);
+            let all_lines: Vec<&str> = content.lines().collect();
+            let start = all_lines.len().saturating_sub(lines);
+            redact_secrets(&all_lines[start..].join("\n"))
+        }
+        Err(e) => format!("Failed to read log file {path}: {e}"),
+    }
+}
+
+fn append_file(
+    builder: &mut tar::Builder<GzEncoder<File>>,
+    name: &str,
+    content: &str,
+) -> AnyhowVoidResult {
+    let mtime = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)?
+        .as_secs();
+    let mut header = tar::Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_mtime(mtime);
+    header.set_cksum();
+    builder.append_data(
+        &mut header,
+        format!("{BUNDLE_DIR}/{name}"),
+        content.as_bytes(),
+    )?;
+    Ok(())
+}
+
+// Collect sanitized diagnostics into a tarball to attach to bug reports
+// Database related failures are recorded in the bundle instead of failing the command
+pub fn create_support_bundle(
+    args: &cli::SupportBundleArgs,
+    logger: Option<Logger>,
+) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Support Bundle", LogLevel::Debug));
+    let mut builder = tar::Builder::new(GzEncoder::new(
+        File::create(&args.out)?,
+        Compression::default(),
+    ));
+
+    append_file(
+        &mut builder,
+        "system.json",
+        &serde_json::to_string_pretty(&get_system_info())?,
+    )?;
+    append_file(&mut builder, "env.txt", &get_env_info())?;
+
+    if let Some(uri) = &args.uri {
+        let uri = append_params_to_uri(uri, CONNECTION_PARAMS);
+        let (database_info, jobs_info) = match Client::connect(&uri, NoTls) {
+            Ok(mut client) => (
+                get_database_info(&mut client)
+                    .unwrap_or_else(|e| json!({ "error": e.to_string() })),
+                get_jobs_info(&mut client, args)
+                    .unwrap_or_else(|e| json!({ "error": e.to_string() })),
+            ),
+            Err(e) => {
+                logger.warn(&format!("Failed to connect to database: {e}"));
+                let error = json!({ "error": redact_secrets(&e.to_string()) });
+                (error.clone(), error)
+            }
+        };
+
+        append_file(
+            &mut builder,
+            "database.json",
+            &serde_json::to_string_pretty(&database_info)?,
+        )?;
+        append_file(
+            &mut builder,
+            "jobs.json",
+            &serde_json::to_string_pretty(&jobs_info)?,
+        )?;
+    }
+
+    for log_file in &args.log_file {
+        let file_name = Path::new(log_file)
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or(log_file.replace('/', "_"));
+        append_file(
+            &mut builder,
+            &format!("logs/{file_name}"),
+            &tail_log_file(log_file, args.log_lines),
+        )?;
+    }
+
+    builder.into_inner()?.finish()?;
+    logger.info(&format!("Support bundle written to {}", args.out));
+    Ok(())
+}
@@ -0,0 +1,48 @@
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::{runtime, trace, Resource};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+
+pub static DEFAULT_SERVICE_NAME: &str = "lantern-cli";
+
+// Spans are exported in batches from a dedicated runtime, as pipeline workers are plain threads
+// Remaining spans are flushed when the guard is dropped, so it should live until the process exits
+pub struct TracingGuard {
+    _runtime: tokio::runtime::Runtime,
+}
+
+impl Drop for TracingGuard {
+    fn drop(&mut self) {
+        opentelemetry::global::shutdown_tracer_provider();
+    }
+}
+
+// Export `tracing` spans to the OTLP gRPC endpoint (e.g. http://localhost:4317)
+pub fn init_tracing(endpoint: &str, service_name: &str) -> Result<TracingGuard, anyhow::Error> {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .thread_name("lantern-otlp")
+        .enable_all()
+        .build()?;
+    let _enter = runtime.enter();
+
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(
+            opentelemetry_otlp::new_exporter()
+                .tonic()
+                .with_endpoint(endpoint),
+        )
+        .with_trace_config(trace::config().with_resource(Resource::new(vec![KeyValue::new(
+            "service.name",
+            service_name.to_owned(),
+        )])))
+        .install_batch(runtime::Tokio)?;
+
+    tracing_subscriber::registry()
+        .with(tracing_opentelemetry::layer().with_tracer(tracer))
+        .try_init()?;
+
+    Ok(TracingGuard { _runtime: runtime })
+}
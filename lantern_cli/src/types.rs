@@ -1,5 +1,79 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::time::Duration;
+
 pub static JOB_CANCELLED_MESSAGE: &'static str = "Job cancelled";
 
 pub type AnyhowUsizeResult = Result<usize, anyhow::Error>;
 pub type AnyhowVoidResult = Result<(), anyhow::Error>;
 pub type ProgressCbFn = Box<dyn Fn(u8) + Send + Sync>;
+pub type ProgressEventCbFn = Box<dyn Fn(&ProgressEvent) + Send + Sync>;
+
+// Progress of a running job
+// Total rows, percent and ETA are only known when the row count is tracked
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProgressEvent {
+    pub processed_rows: usize,
+    pub total_rows: Option<usize>,
+    pub percent: u8,
+    pub tokens: usize,
+    pub emb_per_sec: f64,
+    pub eta: Option<Duration>,
+    pub stages: StageStats,
+}
+
+// Live state of the pipeline stages when the progress event was created
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StageStats {
+    // Rows fetched from the database and not yet received by embedding workers
+    pub producer_buffered_rows: usize,
+    // Embedded rows not yet received by the exporter
+    pub exporter_buffered_rows: usize,
+    // Runtime requests of all embedding workers which are waiting for the response
+    pub in_flight_requests: usize,
+    // Size of the last batch sent to the runtime
+    pub batch_size: usize,
+    // Database connections opened by the producer, embedding workers and exporter
+    pub active_connections: usize,
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        0..=59 => format!("{secs}s"),
+        60..=3599 => format!("{}m {}s", secs / 60, secs % 60),
+        _ => format!("{}h {}m", secs / 3600, secs % 3600 / 60),
+    }
+}
+
+impl fmt::Display for ProgressEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.total_rows {
+            Some(total_rows) => write!(
+                f,
+                "{}% ({}/{total_rows} rows",
+                self.percent, self.processed_rows
+            )?,
+            None => write!(f, "{} rows", self.processed_rows)?,
+        }
+        write!(f, ", {:.1} emb/s", self.emb_per_sec)?;
+        if let Some(eta) = self.eta {
+            write!(f, ", ETA {}", format_duration(eta))?;
+        }
+        if self.total_rows.is_some() {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+// Compat shim for callbacks which only need the percent
+// The callback is called only when the percent increases, as with the percent based progress
+pub fn percent_progress_cb(cb: ProgressCbFn) -> ProgressEventCbFn {
+    let last_percent = AtomicU8::new(0);
+    Box::new(move |event: &ProgressEvent| {
+        if event.percent > last_percent.fetch_max(event.percent, Ordering::SeqCst) {
+            cb(event.percent);
+        }
+    })
+}
@@ -1,3 +1,6 @@
+use regex::Regex;
+use std::sync::RwLock;
+
 pub fn quote_ident(str: &str) -> String {
     format!("\"{}\"", str.replace("\"", "\"\""))
 }
@@ -25,3 +28,46 @@ pub fn append_params_to_uri(db_uri: &str, params: &str) -> String {
 
     db_uri_string
 }
+
+// Escape value to be used in text format of COPY command
+pub fn escape_copy_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+lazy_static! {
+    static ref URI_PASSWORD_REGEX: Regex = Regex::new(r"(://[^:/@\s]+):[^@\s]+@").unwrap();
+    static ref SECRET_VALUE_REGEX: Regex =
+        Regex::new(r#"(?i)("?[a-z0-9_\-]*(token|key|secret|password)"?\s*[:=]\s*"?)[^"\s,}&]+"#)
+            .unwrap();
+    static ref REGISTERED_SECRETS: RwLock<Vec<String>> = RwLock::new(Vec::new());
+}
+
+// Secrets read from environment variables or files are masked wherever they appear,
+// e.g. in error messages of the providers which echo the key
+pub fn register_secret(secret: &str) {
+    // Short values would mask unrelated text
+    if secret.len() < 8 {
+        return;
+    }
+    let mut secrets = REGISTERED_SECRETS.write().unwrap();
+    if !secrets.iter().any(|s| s == secret) {
+        secrets.push(secret.to_owned());
+    }
+}
+
+// Mask passwords in connection strings, values of secret-like keys (api_token, password, ...)
+// and registered secrets
+pub fn redact_secrets(text: &str) -> String {
+    let text = URI_PASSWORD_REGEX.replace_all(text, "$1:***@");
+    let mut text = SECRET_VALUE_REGEX.replace_all(&text, "$1***").to_string();
+    for secret in REGISTERED_SECRETS.read().unwrap().iter() {
+        if text.contains(secret.as_str()) {
+            text = text.replace(secret.as_str(), "***");
+        }
+    }
+    text
+}
@@ -0,0 +1,112 @@
+use super::cli::{AnomalyArgs, AnomalyMethod};
+use super::{
+    compute_kmeans, fetch_vectors, get_nearest, get_top_k, report_progress, CONNECTION_PARAMS,
+};
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+use rayon::prelude::*;
+use std::cmp;
+use std::sync::{Arc, RwLock};
+
+pub fn score_anomalies(
+    args: &AnomalyArgs,
+    progress_cb: Option<ProgressCbFn>,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    logger: Option<Logger>,
+) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Anomaly", LogLevel::Debug));
+
+    if args.k == 0 || args.clusters == 0 || args.batch_size == 0 {
+        anyhow::bail!("k, clusters and batch size should be greater than 0");
+    }
+
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let out_column = quote_ident(&args.out_column);
+
+    // Rows are identified by ctid, so the scores are written in the same transaction
+    let mut transaction = client.transaction()?;
+    let vectors = fetch_vectors(&mut transaction, &full_table_name, "ctid", &args.column)?;
+
+    if vectors.is_empty() {
+        logger.warn("No vectors to score");
+        return Ok(());
+    }
+
+    let dimensions = vectors[0].1.len();
+    if vectors.iter().any(|(_, vec)| vec.len() != dimensions) {
+        anyhow::bail!(
+            "Column {} contains vectors with different dimensions",
+            &args.column
+        );
+    }
+
+    let centroids = match args.method {
+        AnomalyMethod::Centroid => {
+            let centroids =
+                compute_kmeans(&args.metric_kind, &vectors, args.clusters, args.iterations);
+            logger.info(&format!("Computed {} centroids", centroids.len()));
+            centroids
+        }
+        AnomalyMethod::Knn => Vec::new(),
+    };
+
+    transaction.batch_execute(&format!(
+        "ALTER TABLE {full_table_name} ADD COLUMN IF NOT EXISTS {out_column} REAL"
+    ))?;
+    let update_statement = transaction.prepare(&format!(
+        "UPDATE {full_table_name} dest SET {out_column} = src.score FROM UNNEST($1::text[], $2::real[]) AS src(id, score) WHERE dest.ctid = src.id::tid"
+    ))?;
+
+    let mut processed_rows = 0;
+    let mut progress = 0;
+
+    for chunk in vectors.chunks(args.batch_size) {
+        if let Some(is_canceled) = &is_canceled {
+            if *is_canceled.read().unwrap() {
+                anyhow::bail!(JOB_CANCELLED_MESSAGE);
+            }
+        }
+
+        let scores: Vec<f32> = chunk
+            .par_iter()
+            .map(|(_, vec)| match args.method {
+                AnomalyMethod::Knn => {
+                    // The row itself will be the first neighbour with 0 distance
+                    let neighbours = get_top_k(&args.metric_kind, &vectors, vec, args.k + 1);
+                    neighbours.last().unwrap().1
+                }
+                AnomalyMethod::Centroid => {
+                    get_nearest(
+                        &args.metric_kind,
+                        centroids.iter().map(|c| c.as_slice()),
+                        vec,
+                    )
+                    .1
+                }
+            })
+            .collect();
+        let ids: Vec<&str> = chunk.iter().map(|(id, _)| id.as_str()).collect();
+
+        transaction.execute(&update_statement, &[&ids, &scores])?;
+
+        processed_rows += chunk.len();
+        let new_progress = cmp::min(100, processed_rows * 100 / vectors.len()) as u8;
+        if new_progress > progress {
+            progress = new_progress;
+            report_progress(&progress_cb, &logger, progress);
+        }
+    }
+
+    transaction.commit()?;
+    logger.info(&format!(
+        "Anomaly scores for {processed_rows} rows written into column {}",
+        &args.out_column
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,188 @@
+use super::cli::ClassifyArgs;
+use super::{fetch_vectors, get_nearest, report_progress, VectorRow, CONNECTION_PARAMS};
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+use rayon::prelude::*;
+use std::cmp;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+// Average labeled example vectors into one centroid per label
+fn compute_centroids(examples: Vec<VectorRow>) -> Result<Vec<VectorRow>, anyhow::Error> {
+    let mut sums: HashMap<String, (Vec<f32>, usize)> = HashMap::new();
+
+    for (label, vec) in examples {
+        match sums.get_mut(&label) {
+            Some((sum, cnt)) => {
+                if sum.len() != vec.len() {
+                    anyhow::bail!("Examples of label {label} have different dimensions");
+                }
+                sum.iter_mut().zip(vec.iter()).for_each(|(s, v)| *s += v);
+                *cnt += 1;
+            }
+            None => {
+                sums.insert(label, (vec, 1));
+            }
+        }
+    }
+
+    Ok(sums
+        .into_iter()
+        .map(|(label, (sum, cnt))| {
+            let centroid = sum.iter().map(|s| s / cnt as f32).collect();
+            (label, centroid)
+        })
+        .collect())
+}
+
+fn get_prototypes(
+    client: &mut Client,
+    args: &ClassifyArgs,
+    logger: &Logger,
+) -> Result<Vec<VectorRow>, anyhow::Error> {
+    let prototype_schema = args.prototype_schema.as_deref().unwrap_or(&args.schema);
+    let prototype_full_table_name = get_full_table_name(prototype_schema, &args.prototype_table);
+    let prototype_column = args.prototype_column.as_deref().unwrap_or(&args.column);
+
+    let mut prototypes = fetch_vectors(
+        client,
+        &prototype_full_table_name,
+        &args.prototype_label_column,
+        prototype_column,
+    )?;
+
+    if args.compute_centroids {
+        let examples_cnt = prototypes.len();
+        prototypes = compute_centroids(prototypes)?;
+        logger.info(&format!(
+            "Computed {} centroids from {examples_cnt} labeled examples",
+            prototypes.len()
+        ));
+    }
+
+    if prototypes.is_empty() {
+        anyhow::bail!("Table {prototype_full_table_name} does not contain prototypes");
+    }
+
+    let dimensions = prototypes[0].1.len();
+    if prototypes.iter().any(|(_, vec)| vec.len() != dimensions) {
+        anyhow::bail!("Prototypes should have the same dimensions");
+    }
+
+    Ok(prototypes)
+}
+
+pub fn classify_by_centroid(
+    args: &ClassifyArgs,
+    progress_cb: Option<ProgressCbFn>,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    logger: Option<Logger>,
+) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Classify", LogLevel::Debug));
+
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let mut out_client = Client::connect(&uri, NoTls)?;
+
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let column = quote_ident(&args.column);
+    let label_column = quote_ident(&args.out_label_column);
+    let score_column = quote_ident(&args.out_score_column);
+
+    let prototypes = get_prototypes(&mut client, args, &logger)?;
+    let dimensions = prototypes[0].1.len();
+    logger.info(&format!(
+        "Classifying rows into {} labels",
+        prototypes.len()
+    ));
+
+    let total_rows = client.query_one(
+        &format!("SELECT COUNT(*) FROM {full_table_name} WHERE {column} IS NOT NULL"),
+        &[],
+    )?;
+    let total_rows = total_rows.get::<usize, i64>(0) as usize;
+
+    // Columns should be created before reading the table, otherwise the read will wait for the lock
+    out_client.batch_execute(&format!(
+        "ALTER TABLE {full_table_name} ADD COLUMN IF NOT EXISTS {label_column} TEXT, ADD COLUMN IF NOT EXISTS {score_column} REAL"
+    ))?;
+    let mut out_transaction = out_client.transaction()?;
+    let update_statement = out_transaction.prepare(&format!(
+        "UPDATE {full_table_name} dest SET {label_column} = src.label, {score_column} = src.score FROM UNNEST($1::text[], $2::text[], $3::real[]) AS src(id, label, score) WHERE dest.ctid = src.id::tid"
+    ))?;
+
+    let mut transaction = client.transaction()?;
+    let portal = transaction.bind(
+        &format!(
+            "SELECT ctid::text, {column}::real[] FROM {full_table_name} WHERE {column} IS NOT NULL"
+        ),
+        &[],
+    )?;
+
+    let mut processed_rows = 0;
+    let mut progress = 0;
+
+    loop {
+        let rows = transaction.query_portal(&portal, args.batch_size as i32)?;
+
+        if rows.is_empty() {
+            break;
+        }
+
+        if let Some(is_canceled) = &is_canceled {
+            if *is_canceled.read().unwrap() {
+                anyhow::bail!(JOB_CANCELLED_MESSAGE);
+            }
+        }
+
+        let batch: Vec<VectorRow> = rows
+            .iter()
+            .map(|row| (row.get::<usize, String>(0), row.get::<usize, Vec<f32>>(1)))
+            .collect();
+
+        if let Some((_, vec)) = batch.iter().find(|(_, vec)| vec.len() != dimensions) {
+            anyhow::bail!(
+                "Table vectors have {} dimensions while prototypes have {dimensions} dimensions",
+                vec.len()
+            );
+        }
+
+        let nearest: Vec<(usize, f32)> = batch
+            .par_iter()
+            .map(|(_, vec)| {
+                get_nearest(
+                    &args.metric_kind,
+                    prototypes.iter().map(|(_, p)| p.as_slice()),
+                    vec,
+                )
+            })
+            .collect();
+
+        let ids: Vec<&str> = batch.iter().map(|(id, _)| id.as_str()).collect();
+        let labels: Vec<&str> = nearest
+            .iter()
+            .map(|(idx, _)| prototypes[*idx].0.as_str())
+            .collect();
+        let scores: Vec<f32> = nearest.iter().map(|(_, score)| *score).collect();
+
+        out_transaction.execute(&update_statement, &[&ids, &labels, &scores])?;
+
+        processed_rows += batch.len();
+        if total_rows > 0 {
+            let new_progress = cmp::min(100, processed_rows * 100 / total_rows) as u8;
+            if new_progress > progress {
+                progress = new_progress;
+                report_progress(&progress_cb, &logger, progress);
+            }
+        }
+    }
+
+    out_transaction.commit()?;
+    logger.info(&format!(
+        "Classified {processed_rows} rows of {full_table_name}"
+    ));
+
+    Ok(())
+}
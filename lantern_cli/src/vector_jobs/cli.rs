@@ -0,0 +1,400 @@
+use crate::external_index::cli::UMetricKind;
+use clap::{Parser, Subcommand, ValueEnum};
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct SemanticJoinArgs {
+    /// Fully associated database connection string including db name
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Schema name
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Source table name (table A)
+    #[arg(short, long)]
+    pub table: String,
+
+    /// Embedding column of the source table
+    #[arg(short, long)]
+    pub column: String,
+
+    /// Primary key of the source table
+    #[arg(long, default_value = "id")]
+    pub pk: String,
+
+    /// Schema name of the table to join with. Defaults to schema
+    #[arg(long)]
+    pub join_schema: Option<String>,
+
+    /// Table name to join with (table B)
+    #[arg(long)]
+    pub join_table: String,
+
+    /// Embedding column of the table to join with. Defaults to column
+    #[arg(long)]
+    pub join_column: Option<String>,
+
+    /// Primary key of the table to join with
+    #[arg(long, default_value = "id")]
+    pub join_pk: String,
+
+    /// Output table name. It will be created with (a_pk, b_pk, distance) columns
+    #[arg(long)]
+    pub out_table: String,
+
+    /// Number of nearest rows to find for each source row
+    #[arg(long, default_value_t = 10)]
+    pub k: usize,
+
+    /// Distance algorithm
+    #[arg(long, value_enum, default_value_t = UMetricKind::L2sq)]
+    pub metric_kind: UMetricKind,
+
+    /// Use existing lantern index on the join table instead of brute force search
+    #[arg(long, default_value_t = false)]
+    pub use_index: bool,
+
+    /// Number of source rows processed in one batch
+    #[arg(long, default_value_t = 1000)]
+    pub batch_size: usize,
+
+    /// If true output table will be dropped if exists
+    #[arg(long, default_value_t = false)]
+    pub overwrite: bool,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct ClassifyArgs {
+    /// Fully associated database connection string including db name
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Schema name
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Table name
+    #[arg(short, long)]
+    pub table: String,
+
+    /// Embedding column name
+    #[arg(short, long)]
+    pub column: String,
+
+    /// Schema name of the prototypes table. Defaults to schema
+    #[arg(long)]
+    pub prototype_schema: Option<String>,
+
+    /// Table containing labeled prototype vectors
+    #[arg(long)]
+    pub prototype_table: String,
+
+    /// Label column of the prototypes table
+    #[arg(long, default_value = "label")]
+    pub prototype_label_column: String,
+
+    /// Embedding column of the prototypes table. Defaults to column
+    #[arg(long)]
+    pub prototype_column: Option<String>,
+
+    /// Treat prototypes table rows as labeled examples and use the centroid of each label as its prototype
+    #[arg(long, default_value_t = false)]
+    pub compute_centroids: bool,
+
+    /// Output column name for the assigned label
+    #[arg(long, default_value = "label")]
+    pub out_label_column: String,
+
+    /// Output column name for the distance to the assigned prototype
+    #[arg(long, default_value = "label_score")]
+    pub out_score_column: String,
+
+    /// Distance algorithm
+    #[arg(long, value_enum, default_value_t = UMetricKind::Cos)]
+    pub metric_kind: UMetricKind,
+
+    /// Number of rows processed in one batch
+    #[arg(long, default_value_t = 1000)]
+    pub batch_size: usize,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum AnomalyMethod {
+    /// Distance to the k-th nearest neighbour
+    Knn,
+    /// Distance to the nearest kmeans centroid
+    Centroid,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct AnomalyArgs {
+    /// Fully associated database connection string including db name
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Schema name
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Table name
+    #[arg(short, long)]
+    pub table: String,
+
+    /// Embedding column name
+    #[arg(short, long)]
+    pub column: String,
+
+    /// Output column name for the anomaly score
+    #[arg(long, default_value = "anomaly_score")]
+    pub out_column: String,
+
+    /// Scoring method
+    #[arg(long, value_enum, default_value_t = AnomalyMethod::Knn)]
+    pub method: AnomalyMethod,
+
+    /// Neighbour number used as score for knn method
+    #[arg(long, default_value_t = 10)]
+    pub k: usize,
+
+    /// Number of clusters for centroid method
+    #[arg(long, default_value_t = 8)]
+    pub clusters: usize,
+
+    /// Maximum kmeans iterations for centroid method
+    #[arg(long, default_value_t = 20)]
+    pub iterations: usize,
+
+    /// Distance algorithm
+    #[arg(long, value_enum, default_value_t = UMetricKind::L2sq)]
+    pub metric_kind: UMetricKind,
+
+    /// Number of rows scored and written in one batch
+    #[arg(long, default_value_t = 1000)]
+    pub batch_size: usize,
+}
+
+#[derive(Debug, Clone, ValueEnum)]
+pub enum SampleFormat {
+    Csv,
+    Jsonl,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct StratifiedSampleArgs {
+    /// Fully associated database connection string including db name
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Schema name
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Table name
+    #[arg(short, long)]
+    pub table: String,
+
+    /// Embedding column name
+    #[arg(short, long)]
+    pub column: String,
+
+    /// Primary key of the table
+    #[arg(long, default_value = "id")]
+    pub pk: String,
+
+    /// Text column which will be exported together with row ids
+    #[arg(long)]
+    pub text_column: Option<String>,
+
+    /// Number of clusters to sample from
+    #[arg(long, default_value_t = 10)]
+    pub clusters: usize,
+
+    /// Maximum kmeans iterations
+    #[arg(long, default_value_t = 20)]
+    pub iterations: usize,
+
+    /// Number of rows sampled from each cluster
+    #[arg(long, default_value_t = 10)]
+    pub samples_per_cluster: usize,
+
+    /// Distance algorithm
+    #[arg(long, value_enum, default_value_t = UMetricKind::L2sq)]
+    pub metric_kind: UMetricKind,
+
+    /// Output file path
+    #[arg(short, long)]
+    pub out: String,
+
+    /// Output file format
+    #[arg(long, value_enum, default_value_t = SampleFormat::Csv)]
+    pub format: SampleFormat,
+}
+
+#[derive(Parser, Debug, Clone)]
+pub struct QueryVectorStoreArgs {
+    /// Fully associated database connection string including db name
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Schema name of the query vectors table
+    #[arg(long, default_value = "public")]
+    pub vectors_schema: String,
+
+    /// Table storing named query vectors with (name, vector, description, updated_at) columns
+    #[arg(long, default_value = "lantern_query_vectors")]
+    pub vectors_table: String,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum VectorOpsCommands {
+    /// Average embeddings of selected table rows into a query vector
+    Average(VectorAverageArgs),
+    /// Add and subtract named or inline vectors
+    Combine(VectorCombineArgs),
+    /// List stored query vectors
+    List(QueryVectorStoreArgs),
+    /// Delete stored query vector
+    Delete(DeleteQueryVectorArgs),
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct VectorOpsArgs {
+    #[command(subcommand)]
+    pub command: VectorOpsCommands,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct VectorAverageArgs {
+    #[command(flatten)]
+    pub store: QueryVectorStoreArgs,
+
+    /// Schema name
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Table name
+    #[arg(short, long)]
+    pub table: String,
+
+    /// Embedding column name
+    #[arg(short, long)]
+    pub column: String,
+
+    /// Primary key of the table
+    #[arg(long, default_value = "id")]
+    pub pk: String,
+
+    /// Primary keys of the rows to average (e.g. "1,5,8")
+    #[arg(long, value_delimiter = ',')]
+    pub ids: Vec<String>,
+
+    /// Filter SQL to select the rows to average (e.g. "category = 'shoes'")
+    #[arg(long)]
+    pub filter: Option<String>,
+
+    /// Scale the result to unit length
+    #[arg(long, default_value_t = false)]
+    pub normalize: bool,
+
+    /// Store the result as named query vector. If not set the vector is printed
+    #[arg(long)]
+    pub name: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct VectorCombineArgs {
+    #[command(flatten)]
+    pub store: QueryVectorStoreArgs,
+
+    /// Named query vector or inline JSON array to add (e.g. "shoes" or "[0.1, 0.2]")
+    #[arg(long)]
+    pub add: Vec<String>,
+
+    /// Named query vector or inline JSON array to subtract
+    #[arg(long)]
+    pub subtract: Vec<String>,
+
+    /// Scale the result to unit length
+    #[arg(long, default_value_t = false)]
+    pub normalize: bool,
+
+    /// Store the result as named query vector. If not set the vector is printed
+    #[arg(long)]
+    pub name: Option<String>,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct DeleteQueryVectorArgs {
+    #[command(flatten)]
+    pub store: QueryVectorStoreArgs,
+
+    /// Name of the query vector
+    #[arg(long)]
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum VectorFormat {
+    /// REAL[] array
+    Real,
+    /// pgvector vector
+    Vector,
+    /// pgvector halfvec
+    Halfvec,
+    /// Packed little-endian float32 values
+    Bytea,
+}
+
+#[derive(Parser, Debug)]
+#[command(version, about, long_about = None)]
+pub struct ConvertColumnArgs {
+    /// Fully associated database connection string including db name
+    #[arg(short, long)]
+    pub uri: String,
+
+    /// Schema name
+    #[arg(short, long, default_value = "public")]
+    pub schema: String,
+
+    /// Table name
+    #[arg(short, long)]
+    pub table: String,
+
+    /// Embedding column to convert
+    #[arg(short, long)]
+    pub column: String,
+
+    /// Storage format to convert the column to
+    #[arg(long, value_enum)]
+    pub to: VectorFormat,
+
+    /// Write converted values to this column and keep the source column.
+    /// If not specified the column is converted in place
+    #[arg(long)]
+    pub out_column: Option<String>,
+
+    /// Number of rows updated in one transaction
+    #[arg(long, default_value_t = 1000)]
+    pub batch_size: usize,
+
+    /// Pause between batches in milliseconds to reduce load on the database
+    #[arg(long, default_value_t = 0)]
+    pub batch_delay: u64,
+
+    /// Maximum time in milliseconds to wait for table locks before failing
+    #[arg(long, default_value_t = 5000)]
+    pub lock_timeout: u64,
+
+    /// Recreate indexes of the column after in place conversion
+    #[arg(long, default_value_t = false)]
+    pub rebuild_index: bool,
+}
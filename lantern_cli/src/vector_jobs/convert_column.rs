@@ -0,0 +1,444 @@
+use super::cli::{ConvertColumnArgs, VectorFormat};
+use super::{report_progress, CONNECTION_PARAMS};
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, GenericClient, NoTls, Row};
+use std::cmp;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+// pgvector operator classes which have the same name for vector and halfvec except the prefix
+static PGVECTOR_OPCLASSES: [&str; 4] = ["l2_ops", "ip_ops", "cosine_ops", "l1_ops"];
+
+// Parse column type returned by format_type into storage format and dimensions
+pub fn parse_column_type(
+    column_type: &str,
+) -> Result<(VectorFormat, Option<usize>), anyhow::Error> {
+    let (type_name, dimensions) = match column_type.split_once('(') {
+        Some((type_name, typmod)) => (
+            type_name,
+            Some(typmod.trim_end_matches(')').parse::<usize>()?),
+        ),
+        None => (column_type, None),
+    };
+
+    // Type name is schema qualified when the extension schema is not in search_path
+    let format = match type_name.rsplit('.').next().unwrap_or(type_name) {
+        "real[]" => VectorFormat::Real,
+        "vector" => VectorFormat::Vector,
+        "halfvec" => VectorFormat::Halfvec,
+        "bytea" => VectorFormat::Bytea,
+        _ => anyhow::bail!(
+            "Unsupported column type {column_type}. Expected real[], vector, halfvec or bytea"
+        ),
+    };
+
+    Ok((format, dimensions))
+}
+
+fn get_type_sql(format: VectorFormat, dimensions: Option<usize>) -> String {
+    match (format, dimensions) {
+        (VectorFormat::Real, _) => "REAL[]".to_owned(),
+        (VectorFormat::Vector, Some(dimensions)) => format!("vector({dimensions})"),
+        (VectorFormat::Vector, None) => "vector".to_owned(),
+        (VectorFormat::Halfvec, Some(dimensions)) => format!("halfvec({dimensions})"),
+        (VectorFormat::Halfvec, None) => "halfvec".to_owned(),
+        (VectorFormat::Bytea, _) => "BYTEA".to_owned(),
+    }
+}
+
+// Values are read and written as text, so no casts between the types are required
+// Bytea is encoded as hex without \x prefix
+fn get_read_sql(format: VectorFormat, column: &str) -> String {
+    match format {
+        VectorFormat::Bytea => format!("encode({column}, 'hex')"),
+        _ => format!("{column}::text"),
+    }
+}
+
+fn get_write_sql(format: VectorFormat, value: &str, type_sql: &str) -> String {
+    match format {
+        VectorFormat::Bytea => format!("decode({value}, 'hex')"),
+        _ => format!("{value}::{type_sql}"),
+    }
+}
+
+pub fn parse_vector(format: VectorFormat, value: &str) -> Result<Vec<f32>, anyhow::Error> {
+    if format == VectorFormat::Bytea {
+        if value.len() % 8 != 0 {
+            anyhow::bail!("Bytea value length should be multiple of 4 bytes");
+        }
+
+        let bytes = (0..value.len())
+            .step_by(2)
+            .map(|idx| u8::from_str_radix(&value[idx..idx + 2], 16))
+            .collect::<Result<Vec<u8>, _>>()?;
+
+        return Ok(bytes
+            .chunks_exact(4)
+            .map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]))
+            .collect());
+    }
+
+    let elements = value
+        .trim_start_matches(['{', '['])
+        .trim_end_matches(['}', ']']);
+
+    if elements.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let vector = elements
+        .split(',')
+        .map(|element| element.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>();
+
+    match vector {
+        Ok(vector) => Ok(vector),
+        Err(e) => anyhow::bail!("Could not parse vector {value}: {e}"),
+    }
+}
+
+pub fn format_vector(format: VectorFormat, vector: &[f32]) -> String {
+    match format {
+        VectorFormat::Bytea => vector
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .map(|b| format!("{b:02x}"))
+            .collect(),
+        _ => {
+            let elements = vector
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+
+            if format == VectorFormat::Real {
+                format!("{{{elements}}}")
+            } else {
+                format!("[{elements}]")
+            }
+        }
+    }
+}
+
+// Rewrite pgvector operator classes of index definition for the new column type
+fn get_index_sql(index_def: &str, from: VectorFormat, to: VectorFormat) -> String {
+    let prefix = |format: VectorFormat| match format {
+        VectorFormat::Vector => Some("vector_"),
+        VectorFormat::Halfvec => Some("halfvec_"),
+        _ => None,
+    };
+
+    match (prefix(from), prefix(to)) {
+        (Some(from), Some(to)) => {
+            PGVECTOR_OPCLASSES
+                .iter()
+                .fold(index_def.to_owned(), |index_def, opclass| {
+                    index_def.replace(&format!("{from}{opclass}"), &format!("{to}{opclass}"))
+                })
+        }
+        _ => index_def.to_owned(),
+    }
+}
+
+struct Conversion {
+    source_format: VectorFormat,
+    target_format: VectorFormat,
+    select_sql: String,
+    update_sql: String,
+}
+
+impl Conversion {
+    fn convert_rows(&self, client: &mut impl GenericClient, rows: &[Row]) -> AnyhowVoidResult {
+        let mut ids = Vec::with_capacity(rows.len());
+        let mut values = Vec::with_capacity(rows.len());
+
+        for row in rows {
+            let vector = parse_vector(self.source_format, row.get::<usize, &str>(1))?;
+            ids.push(row.get::<usize, String>(0));
+            values.push(format_vector(self.target_format, &vector));
+        }
+
+        client.execute(&self.update_sql, &[&ids, &values])?;
+        Ok(())
+    }
+}
+
+fn get_column_type(
+    client: &mut impl GenericClient,
+    full_table_name: &str,
+    column: &str,
+) -> Result<Option<String>, anyhow::Error> {
+    let rows = client.query(
+        "SELECT format_type(atttypid, atttypmod) FROM pg_attribute WHERE attrelid=$1::text::regclass AND attname=$2 AND NOT attisdropped",
+        &[&full_table_name, &column],
+    )?;
+
+    Ok(rows.first().map(|row| row.get::<usize, String>(0)))
+}
+
+fn get_trigger_function_name(args: &ConvertColumnArgs) -> String {
+    get_full_table_name(
+        &args.schema,
+        &format!("_lantern_convert_{}_{}", args.table, args.column),
+    )
+}
+
+// Reset converted value when the source column is updated during conversion
+// So the row will be converted again before the columns are swapped
+fn create_reset_trigger(
+    client: &mut Client,
+    args: &ConvertColumnArgs,
+    tmp_column: &str,
+) -> AnyhowVoidResult {
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let function_name = get_trigger_function_name(args);
+
+    client.batch_execute(&format!(
+        "
+        CREATE OR REPLACE FUNCTION {function_name}() RETURNS TRIGGER AS $$
+        BEGIN
+            NEW.{tmp_column} := NULL;
+            RETURN NEW;
+        END;
+        $$ LANGUAGE plpgsql;
+        DROP TRIGGER IF EXISTS {tmp_column} ON {full_table_name};
+        CREATE TRIGGER {tmp_column} BEFORE UPDATE OF {column} ON {full_table_name} FOR EACH ROW EXECUTE FUNCTION {function_name}();
+        ",
+        column = quote_ident(&args.column),
+        tmp_column = quote_ident(tmp_column),
+    ))?;
+
+    Ok(())
+}
+
+// Swap converted column with the source column under exclusive lock
+// Rows which were updated or inserted after the scan are converted before the swap
+// Returns definitions of the indexes dropped with the source column
+fn swap_columns(
+    client: &mut Client,
+    args: &ConvertColumnArgs,
+    conversion: &Conversion,
+    tmp_column: &str,
+) -> Result<Vec<String>, anyhow::Error> {
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let mut transaction = client.transaction()?;
+
+    transaction.batch_execute(&format!(
+        "LOCK TABLE {full_table_name} IN ACCESS EXCLUSIVE MODE; DROP TRIGGER IF EXISTS {tmp_column} ON {full_table_name}; DROP FUNCTION IF EXISTS {}();",
+        get_trigger_function_name(args),
+        tmp_column = quote_ident(tmp_column),
+    ))?;
+
+    let rows = transaction.query(&conversion.select_sql, &[])?;
+    if !rows.is_empty() {
+        conversion.convert_rows(&mut transaction, &rows)?;
+    }
+
+    let index_defs = transaction
+        .query(
+            "SELECT pg_get_indexdef(i.indexrelid) FROM pg_index i JOIN pg_attribute a ON a.attrelid = i.indrelid AND a.attnum = ANY(i.indkey) WHERE i.indrelid = $1::text::regclass AND a.attname = $2",
+            &[&full_table_name, &args.column],
+        )?
+        .iter()
+        .map(|row| row.get::<usize, String>(0))
+        .collect();
+
+    transaction.batch_execute(&format!(
+        "ALTER TABLE {full_table_name} DROP COLUMN {column}; ALTER TABLE {full_table_name} RENAME COLUMN {tmp_column} TO {column};",
+        column = quote_ident(&args.column),
+        tmp_column = quote_ident(tmp_column),
+    ))?;
+    transaction.commit()?;
+
+    Ok(index_defs)
+}
+
+// Convert embedding column between REAL[], vector, halfvec and bytea formats in batches
+// Converted values are written to out column or to a temporary column which replaces the source column at the end
+// Only rows which are not converted yet are processed, so the failed job can be resumed
+pub fn convert_column(
+    args: &ConvertColumnArgs,
+    progress_cb: Option<ProgressCbFn>,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    logger: Option<Logger>,
+) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Convert Column", LogLevel::Debug));
+
+    if args.batch_size == 0 {
+        anyhow::bail!("--batch-size should be greater than 0");
+    }
+
+    if args.rebuild_index && args.out_column.is_some() {
+        anyhow::bail!("--rebuild-index can only be used with in place conversion");
+    }
+
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let mut out_client = Client::connect(&uri, NoTls)?;
+    out_client.batch_execute(&format!("SET lock_timeout = {}", args.lock_timeout))?;
+
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let column = quote_ident(&args.column);
+    let tmp_column = format!("_lantern_convert_{}", args.column);
+    let out_column = args.out_column.as_deref().unwrap_or(&tmp_column);
+
+    let Some(column_type) = get_column_type(&mut client, &full_table_name, &args.column)? else {
+        anyhow::bail!("Column {column} does not exist in table {full_table_name}");
+    };
+    let (source_format, mut dimensions) = parse_column_type(&column_type)?;
+
+    if source_format == args.to && args.out_column.is_none() {
+        anyhow::bail!("Column {column} is already of type {column_type}");
+    }
+
+    if dimensions.is_none() {
+        let row = client.query_opt(
+            &format!(
+                "SELECT {} FROM {full_table_name} WHERE {column} IS NOT NULL LIMIT 1",
+                get_read_sql(source_format, &column)
+            ),
+            &[],
+        )?;
+
+        if let Some(row) = row {
+            dimensions = Some(parse_vector(source_format, row.get::<usize, &str>(0))?.len());
+        }
+    }
+
+    let target_type = get_type_sql(args.to, dimensions);
+
+    match get_column_type(&mut client, &full_table_name, out_column)? {
+        Some(out_column_type) => {
+            if parse_column_type(&out_column_type)?.0 != args.to {
+                anyhow::bail!(
+                    "Column {} already exists with type {out_column_type}",
+                    quote_ident(out_column)
+                );
+            }
+            logger.info(&format!(
+                "Column {} already exists, resuming conversion",
+                quote_ident(out_column)
+            ));
+        }
+        None => {
+            // Column should be created before reading the table, otherwise the read will wait for the lock
+            out_client.batch_execute(&format!(
+                "ALTER TABLE {full_table_name} ADD COLUMN {} {target_type}",
+                quote_ident(out_column)
+            ))?;
+        }
+    }
+
+    if args.out_column.is_none() {
+        create_reset_trigger(&mut out_client, args, &tmp_column)?;
+    }
+
+    let conversion = Conversion {
+        source_format,
+        target_format: args.to,
+        select_sql: format!(
+            "SELECT ctid::text, {} FROM {full_table_name} WHERE {column} IS NOT NULL AND {} IS NULL",
+            get_read_sql(source_format, &column),
+            quote_ident(out_column)
+        ),
+        update_sql: format!(
+            "UPDATE {full_table_name} dest SET {} = {} FROM UNNEST($1::text[], $2::text[]) AS src(id, val) WHERE dest.ctid = src.id::tid",
+            quote_ident(out_column),
+            get_write_sql(args.to, "src.val", &target_type)
+        ),
+    };
+
+    logger.info(&format!(
+        "Converting {full_table_name}.{column} from {column_type} to {target_type}"
+    ));
+
+    let mut transaction = client.transaction()?;
+    let total_rows = transaction
+        .query_one(
+            &format!("SELECT COUNT(*) FROM ({}) t", conversion.select_sql),
+            &[],
+        )?
+        .get::<usize, i64>(0) as usize;
+    let portal = transaction.bind(&conversion.select_sql, &[])?;
+
+    let mut processed_rows = 0;
+    let mut progress = 0;
+
+    loop {
+        let rows = transaction.query_portal(&portal, args.batch_size as i32)?;
+
+        if rows.is_empty() {
+            break;
+        }
+
+        if let Some(is_canceled) = &is_canceled {
+            if *is_canceled.read().unwrap() {
+                anyhow::bail!(JOB_CANCELLED_MESSAGE);
+            }
+        }
+
+        // Each batch is committed separately, so row locks are held only for one batch
+        conversion.convert_rows(&mut out_client, &rows)?;
+
+        processed_rows += rows.len();
+        if let Some(new_progress) = (processed_rows * 100).checked_div(total_rows) {
+            let new_progress = cmp::min(100, new_progress) as u8;
+            if new_progress > progress {
+                progress = new_progress;
+                report_progress(&progress_cb, &logger, progress);
+            }
+        }
+
+        if args.batch_delay > 0 {
+            std::thread::sleep(Duration::from_millis(args.batch_delay));
+        }
+    }
+
+    // Read transaction should be closed before the swap, otherwise it will wait for the lock
+    transaction.commit()?;
+    logger.info(&format!(
+        "Converted {processed_rows} rows of {full_table_name}"
+    ));
+
+    if args.out_column.is_some() {
+        return Ok(());
+    }
+
+    let index_defs = swap_columns(&mut out_client, args, &conversion, &tmp_column)?;
+    logger.info(&format!("Column {column} replaced with converted column"));
+
+    if index_defs.is_empty() {
+        return Ok(());
+    }
+
+    if !args.rebuild_index {
+        logger.warn(&format!(
+            "Dropped indexes of the column, pass --rebuild-index to recreate them: {}",
+            index_defs.join("; ")
+        ));
+        return Ok(());
+    }
+
+    let mut failed_indexes = Vec::new();
+    for index_def in &index_defs {
+        let index_sql = get_index_sql(index_def, source_format, args.to);
+        logger.info(&format!("Recreating index: {index_sql}"));
+        if let Err(e) = out_client.batch_execute(&index_sql) {
+            logger.error(&format!("Could not recreate index: {e}"));
+            failed_indexes.push(index_sql);
+        }
+    }
+
+    if !failed_indexes.is_empty() {
+        anyhow::bail!(
+            "Column is converted, but {} indexes could not be recreated: {}",
+            failed_indexes.len(),
+            failed_indexes.join("; ")
+        );
+    }
+
+    Ok(())
+}
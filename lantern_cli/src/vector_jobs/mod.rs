@@ -0,0 +1,195 @@
+use crate::external_index::cli::UMetricKind;
+use crate::logger::Logger;
+use crate::types::*;
+use crate::utils::quote_ident;
+use postgres::GenericClient;
+use rayon::prelude::*;
+use std::cmp;
+
+mod anomaly;
+mod classify;
+pub mod cli;
+pub mod convert_column;
+mod sample;
+mod semantic_join;
+pub mod vector_ops;
+
+pub use anomaly::score_anomalies;
+pub use classify::classify_by_centroid;
+pub use convert_column::convert_column;
+pub use sample::export_stratified_sample;
+pub use semantic_join::semantic_join;
+
+static CONNECTION_PARAMS: &'static str = "connect_timeout=10";
+
+type VectorRow = (String, Vec<f32>);
+
+fn report_progress(progress_cb: &Option<ProgressCbFn>, logger: &Logger, progress: u8) {
+    logger.debug(&format!("Progress {progress}%"));
+    if progress_cb.is_some() {
+        let cb = progress_cb.as_ref().unwrap();
+        cb(progress);
+    }
+}
+
+// Distance functions have the same semantics as lantern's l2sq_dist, cos_dist and hamming_dist
+fn l2sq_dist(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b.iter()).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+fn cos_dist(a: &[f32], b: &[f32]) -> f32 {
+    let mut dot = 0.0;
+    let mut norm_a = 0.0;
+    let mut norm_b = 0.0;
+
+    for (x, y) in a.iter().zip(b.iter()) {
+        dot += x * y;
+        norm_a += x * x;
+        norm_b += y * y;
+    }
+
+    if norm_a == 0.0 || norm_b == 0.0 {
+        return 1.0;
+    }
+
+    1.0 - dot / (norm_a.sqrt() * norm_b.sqrt())
+}
+
+fn hamming_dist(a: &[f32], b: &[f32]) -> f32 {
+    a.iter()
+        .zip(b.iter())
+        .map(|(x, y)| ((*x as i32) ^ (*y as i32)).count_ones())
+        .sum::<u32>() as f32
+}
+
+fn compute_distance(metric_kind: &UMetricKind, a: &[f32], b: &[f32]) -> f32 {
+    match metric_kind {
+        UMetricKind::L2sq => l2sq_dist(a, b),
+        UMetricKind::Cos => cos_dist(a, b),
+        UMetricKind::Hamming => hamming_dist(a, b),
+    }
+}
+
+// Returns (index, distance) pairs of k nearest candidates sorted by distance
+fn get_top_k(
+    metric_kind: &UMetricKind,
+    candidates: &[VectorRow],
+    query: &[f32],
+    k: usize,
+) -> Vec<(usize, f32)> {
+    let mut distances: Vec<(usize, f32)> = candidates
+        .iter()
+        .enumerate()
+        .map(|(idx, (_, vec))| (idx, compute_distance(metric_kind, query, vec)))
+        .collect();
+
+    select_top_k(&mut distances, k);
+    distances
+}
+
+// Keeps k pairs with the smallest distances sorted by distance
+fn select_top_k(distances: &mut Vec<(usize, f32)>, k: usize) {
+    let k = cmp::min(k, distances.len());
+    if k == 0 {
+        distances.clear();
+        return;
+    }
+
+    if k < distances.len() {
+        distances.select_nth_unstable_by(k - 1, |a, b| a.1.total_cmp(&b.1));
+        distances.truncate(k);
+    }
+
+    distances.sort_by(|a, b| a.1.total_cmp(&b.1));
+}
+
+// Returns index and distance of the nearest candidate
+fn get_nearest<'a>(
+    metric_kind: &UMetricKind,
+    candidates: impl Iterator<Item = &'a [f32]>,
+    query: &[f32],
+) -> (usize, f32) {
+    let mut nearest = (0, f32::MAX);
+    for (idx, candidate) in candidates.enumerate() {
+        let distance = compute_distance(metric_kind, query, candidate);
+        if distance < nearest.1 {
+            nearest = (idx, distance);
+        }
+    }
+    nearest
+}
+
+// Lloyd's kmeans with centroids initialized from random vectors
+fn compute_kmeans(
+    metric_kind: &UMetricKind,
+    vectors: &[VectorRow],
+    clusters: usize,
+    iterations: usize,
+) -> Vec<Vec<f32>> {
+    let mut rng = rand::thread_rng();
+    let clusters = cmp::min(clusters, vectors.len());
+    let mut centroids: Vec<Vec<f32>> = rand::seq::index::sample(&mut rng, vectors.len(), clusters)
+        .iter()
+        .map(|idx| vectors[idx].1.clone())
+        .collect();
+
+    for _ in 0..iterations {
+        let assignments: Vec<usize> = vectors
+            .par_iter()
+            .map(|(_, vec)| get_nearest(metric_kind, centroids.iter().map(|c| c.as_slice()), vec).0)
+            .collect();
+
+        let dimensions = centroids[0].len();
+        let mut sums = vec![vec![0.0; dimensions]; clusters];
+        let mut counts = vec![0; clusters];
+
+        for ((_, vec), cluster) in vectors.iter().zip(assignments.iter()) {
+            sums[*cluster]
+                .iter_mut()
+                .zip(vec.iter())
+                .for_each(|(s, v)| *s += v);
+            counts[*cluster] += 1;
+        }
+
+        let mut changed = false;
+        for (cluster, sum) in sums.into_iter().enumerate() {
+            // Empty cluster will keep its previous centroid
+            if counts[cluster] == 0 {
+                continue;
+            }
+            let centroid: Vec<f32> = sum.iter().map(|s| s / counts[cluster] as f32).collect();
+            if centroid != centroids[cluster] {
+                changed = true;
+                centroids[cluster] = centroid;
+            }
+        }
+
+        if !changed {
+            break;
+        }
+    }
+
+    centroids
+}
+
+// Load all non null vectors of the column into memory as (pk, vector) pairs
+fn fetch_vectors(
+    client: &mut impl GenericClient,
+    full_table_name: &str,
+    pk: &str,
+    column: &str,
+) -> Result<Vec<VectorRow>, anyhow::Error> {
+    let rows = client.query(
+        &format!(
+            "SELECT {pk}::text, {column}::real[] FROM {full_table_name} WHERE {column} IS NOT NULL",
+            pk = quote_ident(pk),
+            column = quote_ident(column),
+        ),
+        &[],
+    )?;
+
+    Ok(rows
+        .iter()
+        .map(|row| (row.get::<usize, String>(0), row.get::<usize, Vec<f32>>(1)))
+        .collect())
+}
@@ -0,0 +1,159 @@
+use super::cli::{SampleFormat, StratifiedSampleArgs};
+use super::{compute_kmeans, fetch_vectors, get_nearest, report_progress, CONNECTION_PARAMS};
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use csv::Writer;
+use postgres::{Client, NoTls};
+use rand::seq::SliceRandom;
+use rayon::prelude::*;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::sync::{Arc, RwLock};
+
+// (id, cluster, text)
+type SampleRow = (String, usize, Option<String>);
+
+fn fetch_texts(
+    client: &mut Client,
+    args: &StratifiedSampleArgs,
+    full_table_name: &str,
+    ids: &[&str],
+) -> Result<HashMap<String, Option<String>>, anyhow::Error> {
+    if args.text_column.is_none() {
+        return Ok(HashMap::new());
+    }
+
+    let rows = client.query(
+        &format!(
+            "SELECT {pk}::text, {text_column}::text FROM {full_table_name} WHERE {pk}::text = ANY($1)",
+            pk = quote_ident(&args.pk),
+            text_column = quote_ident(args.text_column.as_ref().unwrap()),
+        ),
+        &[&ids],
+    )?;
+
+    Ok(rows
+        .iter()
+        .map(|row| {
+            (
+                row.get::<usize, String>(0),
+                row.get::<usize, Option<String>>(1),
+            )
+        })
+        .collect())
+}
+
+fn write_csv(path: &str, samples: &[SampleRow]) -> AnyhowVoidResult {
+    let mut wtr = Writer::from_path(path)?;
+    wtr.write_record(["id", "cluster", "text"])?;
+    for (id, cluster, text) in samples {
+        wtr.write_record([id, &cluster.to_string(), text.as_deref().unwrap_or("")])?;
+    }
+    wtr.flush()?;
+    Ok(())
+}
+
+fn write_jsonl(path: &str, samples: &[SampleRow]) -> AnyhowVoidResult {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for (id, cluster, text) in samples {
+        let line = serde_json::json!({ "id": id, "cluster": cluster, "text": text });
+        writeln!(writer, "{line}")?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn export_stratified_sample(
+    args: &StratifiedSampleArgs,
+    progress_cb: Option<ProgressCbFn>,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    logger: Option<Logger>,
+) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Sample", LogLevel::Debug));
+
+    if args.clusters == 0 || args.samples_per_cluster == 0 {
+        anyhow::bail!("clusters and samples per cluster should be greater than 0");
+    }
+
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+
+    let vectors = fetch_vectors(&mut client, &full_table_name, &args.pk, &args.column)?;
+    if vectors.is_empty() {
+        anyhow::bail!("Table {full_table_name} does not contain vectors");
+    }
+
+    let dimensions = vectors[0].1.len();
+    if vectors.iter().any(|(_, vec)| vec.len() != dimensions) {
+        anyhow::bail!(
+            "Column {} contains vectors with different dimensions",
+            &args.column
+        );
+    }
+
+    let centroids = compute_kmeans(&args.metric_kind, &vectors, args.clusters, args.iterations);
+    logger.info(&format!(
+        "Clustered {} rows into {} clusters",
+        vectors.len(),
+        centroids.len()
+    ));
+    report_progress(&progress_cb, &logger, 50);
+
+    if let Some(is_canceled) = &is_canceled {
+        if *is_canceled.read().unwrap() {
+            anyhow::bail!(JOB_CANCELLED_MESSAGE);
+        }
+    }
+
+    let assignments: Vec<usize> = vectors
+        .par_iter()
+        .map(|(_, vec)| {
+            get_nearest(
+                &args.metric_kind,
+                centroids.iter().map(|c| c.as_slice()),
+                vec,
+            )
+            .0
+        })
+        .collect();
+
+    let mut clusters: Vec<Vec<&str>> = vec![Vec::new(); centroids.len()];
+    for ((id, _), cluster) in vectors.iter().zip(assignments.iter()) {
+        clusters[*cluster].push(id.as_str());
+    }
+
+    let mut rng = rand::thread_rng();
+    let sampled: Vec<(&str, usize)> = clusters
+        .iter()
+        .enumerate()
+        .flat_map(|(cluster, ids)| {
+            ids.choose_multiple(&mut rng, args.samples_per_cluster)
+                .map(move |id| (*id, cluster))
+                .collect::<Vec<_>>()
+        })
+        .collect();
+
+    let ids: Vec<&str> = sampled.iter().map(|(id, _)| *id).collect();
+    let mut texts = fetch_texts(&mut client, args, &full_table_name, &ids)?;
+    let samples: Vec<SampleRow> = sampled
+        .into_iter()
+        .map(|(id, cluster)| (id.to_owned(), cluster, texts.remove(id).flatten()))
+        .collect();
+
+    match args.format {
+        SampleFormat::Csv => write_csv(&args.out, &samples)?,
+        SampleFormat::Jsonl => write_jsonl(&args.out, &samples)?,
+    }
+
+    report_progress(&progress_cb, &logger, 100);
+    logger.info(&format!(
+        "Exported {} sampled rows to {}",
+        samples.len(),
+        &args.out
+    ));
+
+    Ok(())
+}
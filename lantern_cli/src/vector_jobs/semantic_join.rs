@@ -0,0 +1,388 @@
+use super::cli::SemanticJoinArgs;
+use super::{
+    fetch_vectors, get_top_k, report_progress, select_top_k, VectorRow, CONNECTION_PARAMS,
+};
+use crate::external_index::cli::UMetricKind;
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, escape_copy_value, get_full_table_name, quote_ident};
+use ndarray::{s, Array2, ArrayView2, Axis};
+use postgres::{Client, NoTls, Transaction};
+use rayon::prelude::*;
+use std::cmp;
+use std::io::Write;
+use std::sync::{Arc, RwLock};
+
+// Source rows are compared with join table rows in tiles of this many rows of both tables,
+// so distances of a tile are computed with one matrix multiplication which fits in cache
+static JOIN_BLOCK_SIZE: usize = 256;
+
+struct JoinContext<'a> {
+    args: &'a SemanticJoinArgs,
+    full_table_name: &'a str,
+    join_full_table_name: &'a str,
+    out_full_table_name: &'a str,
+    total_rows: usize,
+}
+
+fn create_out_table(
+    transaction: &mut Transaction,
+    args: &SemanticJoinArgs,
+    full_table_name: &str,
+    join_full_table_name: &str,
+    out_full_table_name: &str,
+) -> AnyhowVoidResult {
+    if args.overwrite {
+        transaction.execute(&format!("DROP TABLE IF EXISTS {out_full_table_name}"), &[])?;
+    }
+
+    transaction.execute(
+        &format!(
+            "CREATE TABLE {out_full_table_name} AS SELECT a.{pk} AS a_pk, b.{join_pk} AS b_pk, 0::REAL AS distance FROM {full_table_name} a, {join_full_table_name} b LIMIT 0",
+            pk = quote_ident(&args.pk),
+            join_pk = quote_ident(&args.join_pk),
+        ),
+        &[],
+    )?;
+
+    Ok(())
+}
+
+// Vectors as matrix rows with their squared norms
+// For cosine distance the rows are normalized, so the distance is 1 - dot product
+struct VectorMatrix {
+    vectors: Array2<f32>,
+    norms: Vec<f32>,
+}
+
+impl VectorMatrix {
+    fn new(rows: &[VectorRow], dimensions: usize, metric_kind: &UMetricKind) -> Self {
+        let mut vectors = Array2::<f32>::zeros((rows.len(), dimensions));
+        let mut norms = Vec::with_capacity(rows.len());
+        for (mut row, (_, vec)) in vectors.axis_iter_mut(Axis(0)).zip(rows) {
+            let norm: f32 = vec.iter().map(|x| x * x).sum();
+            let scale = match metric_kind {
+                UMetricKind::Cos if norm > 0.0 => 1.0 / norm.sqrt(),
+                _ => 1.0,
+            };
+            for (value, x) in row.iter_mut().zip(vec) {
+                *value = x * scale;
+            }
+            norms.push(norm);
+        }
+        VectorMatrix { vectors, norms }
+    }
+
+    fn block(&self, start: usize) -> (ArrayView2<f32>, &[f32]) {
+        let end = cmp::min(start + JOIN_BLOCK_SIZE, self.norms.len());
+        (
+            self.vectors.slice(s![start..end, ..]),
+            &self.norms[start..end],
+        )
+    }
+}
+
+// Returns k nearest join rows of each source row. Distances of a tile of source and join rows
+// are computed from A * B^T, and candidates of each source row are pruned to k after each tile
+fn get_top_k_blocked(
+    metric_kind: &UMetricKind,
+    matrix: &VectorMatrix,
+    join_matrix: &VectorMatrix,
+    k: usize,
+) -> Vec<Vec<(usize, f32)>> {
+    let blocks: Vec<Vec<Vec<(usize, f32)>>> = (0..matrix.norms.len())
+        .step_by(JOIN_BLOCK_SIZE)
+        .collect::<Vec<usize>>()
+        .into_par_iter()
+        .map(|start| {
+            let (vectors, norms) = matrix.block(start);
+            let mut neighbours = vec![Vec::new(); norms.len()];
+            for join_start in (0..join_matrix.norms.len()).step_by(JOIN_BLOCK_SIZE) {
+                let (join_vectors, join_norms) = join_matrix.block(join_start);
+                let dot_products = vectors.dot(&join_vectors.t());
+                for ((row, norm), row_neighbours) in dot_products
+                    .axis_iter(Axis(0))
+                    .zip(norms)
+                    .zip(neighbours.iter_mut())
+                {
+                    for (idx, (dot_product, join_norm)) in row.iter().zip(join_norms).enumerate() {
+                        let distance = match metric_kind {
+                            UMetricKind::Cos if *norm == 0.0 || *join_norm == 0.0 => 1.0,
+                            UMetricKind::Cos => 1.0 - dot_product,
+                            _ => (norm + join_norm - 2.0 * dot_product).max(0.0),
+                        };
+                        row_neighbours.push((join_start + idx, distance));
+                    }
+                    select_top_k(row_neighbours, k);
+                }
+            }
+            neighbours
+        })
+        .collect();
+
+    blocks.into_iter().flatten().collect()
+}
+
+fn join_brute_force(
+    client: &mut Client,
+    out_transaction: &mut Transaction,
+    ctx: &JoinContext,
+    progress_cb: &Option<ProgressCbFn>,
+    is_canceled: &Option<Arc<RwLock<bool>>>,
+    logger: &Logger,
+) -> AnyhowUsizeResult {
+    let JoinContext {
+        args,
+        full_table_name,
+        join_full_table_name,
+        out_full_table_name,
+        total_rows,
+    } = *ctx;
+    let join_column = args.join_column.as_deref().unwrap_or(&args.column);
+    let join_rows = fetch_vectors(client, join_full_table_name, &args.join_pk, join_column)?;
+
+    if join_rows.is_empty() {
+        anyhow::bail!("Table {join_full_table_name} does not contain vectors");
+    }
+
+    logger.info(&format!(
+        "Loaded {} vectors from {join_full_table_name}",
+        join_rows.len()
+    ));
+
+    let mut transaction = client.transaction()?;
+    let portal = transaction.bind(
+        &format!(
+            "SELECT {pk}::text, {column}::real[] FROM {full_table_name} WHERE {column} IS NOT NULL",
+            pk = quote_ident(&args.pk),
+            column = quote_ident(&args.column),
+        ),
+        &[],
+    )?;
+
+    let mut writer = out_transaction.copy_in(&format!("COPY {out_full_table_name} FROM stdin"))?;
+    let mut processed_rows = 0;
+    let mut inserted_rows = 0;
+    let mut progress = 0;
+    let dimensions = join_rows[0].1.len();
+    if let Some((id, vec)) = join_rows.iter().find(|(_, vec)| vec.len() != dimensions) {
+        anyhow::bail!(
+            "Row with id {id} of {join_full_table_name} has {} dimensions while other rows have {dimensions} dimensions",
+            vec.len()
+        );
+    }
+    // Hamming distance can not be computed with matrix multiplication
+    let join_matrix = match args.metric_kind {
+        UMetricKind::Hamming => None,
+        _ => Some(VectorMatrix::new(&join_rows, dimensions, &args.metric_kind)),
+    };
+
+    loop {
+        let rows = transaction.query_portal(&portal, args.batch_size as i32)?;
+
+        if rows.is_empty() {
+            break;
+        }
+
+        if let Some(is_canceled) = is_canceled {
+            if *is_canceled.read().unwrap() {
+                anyhow::bail!(JOB_CANCELLED_MESSAGE);
+            }
+        }
+
+        let batch: Vec<VectorRow> = rows
+            .iter()
+            .map(|row| (row.get::<usize, String>(0), row.get::<usize, Vec<f32>>(1)))
+            .collect();
+
+        if let Some((id, vec)) = batch.iter().find(|(_, vec)| vec.len() != dimensions) {
+            anyhow::bail!(
+                "Row with id {id} has {} dimensions while join table vectors have {dimensions} dimensions",
+                vec.len()
+            );
+        }
+
+        let neighbours: Vec<Vec<(usize, f32)>> = match &join_matrix {
+            Some(join_matrix) => {
+                let matrix = VectorMatrix::new(&batch, dimensions, &args.metric_kind);
+                get_top_k_blocked(&args.metric_kind, &matrix, join_matrix, args.k)
+            }
+            None => batch
+                .par_iter()
+                .map(|(_, vec)| get_top_k(&args.metric_kind, &join_rows, vec, args.k))
+                .collect(),
+        };
+
+        let mut buf = String::new();
+        for ((id, _), row_neighbours) in batch.iter().zip(neighbours.iter()) {
+            for (idx, distance) in row_neighbours {
+                buf.push_str(&format!(
+                    "{}\t{}\t{distance}\n",
+                    escape_copy_value(id),
+                    escape_copy_value(&join_rows[*idx].0)
+                ));
+                inserted_rows += 1;
+            }
+        }
+        writer.write_all(buf.as_bytes())?;
+
+        processed_rows += batch.len();
+        if total_rows > 0 {
+            let new_progress = cmp::min(100, processed_rows * 100 / total_rows) as u8;
+            if new_progress > progress {
+                progress = new_progress;
+                report_progress(progress_cb, logger, progress);
+            }
+        }
+    }
+
+    writer.finish()?;
+    Ok(inserted_rows)
+}
+
+fn join_with_index(
+    client: &mut Client,
+    out_transaction: &mut Transaction,
+    ctx: &JoinContext,
+    progress_cb: &Option<ProgressCbFn>,
+    is_canceled: &Option<Arc<RwLock<bool>>>,
+    logger: &Logger,
+) -> AnyhowUsizeResult {
+    let JoinContext {
+        args,
+        full_table_name,
+        join_full_table_name,
+        out_full_table_name,
+        total_rows,
+    } = *ctx;
+    let join_column = args.join_column.as_deref().unwrap_or(&args.column);
+    let mut transaction = client.transaction()?;
+    let portal = transaction.bind(
+        &format!(
+            "SELECT ctid::text FROM {full_table_name} WHERE {column} IS NOT NULL",
+            column = quote_ident(&args.column),
+        ),
+        &[],
+    )?;
+
+    out_transaction.execute(&format!("SET LOCAL lantern_hnsw.init_k={}", args.k), &[])?;
+    let insert_statement = out_transaction.prepare(&format!(
+        "INSERT INTO {out_full_table_name} (a_pk, b_pk, distance)
+         SELECT a.{pk}, b.b_pk, b.distance FROM {full_table_name} a CROSS JOIN LATERAL (
+            SELECT j.{join_pk} AS b_pk, {function}(j.{join_column}, a.{column})::REAL AS distance
+            FROM {join_full_table_name} j
+            ORDER BY j.{join_column} {operator} a.{column}
+            LIMIT {k}
+         ) b WHERE a.ctid = ANY($1::text[]::tid[])",
+        pk = quote_ident(&args.pk),
+        join_pk = quote_ident(&args.join_pk),
+        column = quote_ident(&args.column),
+        join_column = quote_ident(join_column),
+        function = args.metric_kind.sql_function(),
+        operator = args.metric_kind.sql_operator(),
+        k = args.k,
+    ))?;
+
+    let mut processed_rows = 0;
+    let mut inserted_rows = 0;
+    let mut progress = 0;
+
+    loop {
+        let rows = transaction.query_portal(&portal, args.batch_size as i32)?;
+
+        if rows.is_empty() {
+            break;
+        }
+
+        if let Some(is_canceled) = is_canceled {
+            if *is_canceled.read().unwrap() {
+                anyhow::bail!(JOB_CANCELLED_MESSAGE);
+            }
+        }
+
+        let ids: Vec<String> = rows.iter().map(|row| row.get::<usize, String>(0)).collect();
+        inserted_rows += out_transaction.execute(&insert_statement, &[&ids])? as usize;
+
+        processed_rows += ids.len();
+        if total_rows > 0 {
+            let new_progress = cmp::min(100, processed_rows * 100 / total_rows) as u8;
+            if new_progress > progress {
+                progress = new_progress;
+                report_progress(progress_cb, logger, progress);
+            }
+        }
+    }
+
+    Ok(inserted_rows)
+}
+
+pub fn semantic_join(
+    args: &SemanticJoinArgs,
+    progress_cb: Option<ProgressCbFn>,
+    is_canceled: Option<Arc<RwLock<bool>>>,
+    logger: Option<Logger>,
+) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Semantic Join", LogLevel::Debug));
+
+    if args.k == 0 {
+        anyhow::bail!("k should be greater than 0");
+    }
+
+    let uri = append_params_to_uri(&args.uri, CONNECTION_PARAMS);
+    let mut client = Client::connect(&uri, NoTls)?;
+    let mut out_client = Client::connect(&uri, NoTls)?;
+
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let join_schema = args.join_schema.as_deref().unwrap_or(&args.schema);
+    let join_full_table_name = get_full_table_name(join_schema, &args.join_table);
+    let out_full_table_name = get_full_table_name(&args.schema, &args.out_table);
+
+    let total_rows = client.query_one(
+        &format!(
+            "SELECT COUNT(*) FROM {full_table_name} WHERE {column} IS NOT NULL",
+            column = quote_ident(&args.column)
+        ),
+        &[],
+    )?;
+    let total_rows = total_rows.get::<usize, i64>(0) as usize;
+
+    let mut out_transaction = out_client.transaction()?;
+    create_out_table(
+        &mut out_transaction,
+        args,
+        &full_table_name,
+        &join_full_table_name,
+        &out_full_table_name,
+    )?;
+
+    let join_fn = if args.use_index {
+        join_with_index
+    } else {
+        join_brute_force
+    };
+
+    let ctx = JoinContext {
+        args,
+        full_table_name: &full_table_name,
+        join_full_table_name: &join_full_table_name,
+        out_full_table_name: &out_full_table_name,
+        total_rows,
+    };
+
+    let inserted_rows = join_fn(
+        &mut client,
+        &mut out_transaction,
+        &ctx,
+        &progress_cb,
+        &is_canceled,
+        &logger,
+    )?;
+
+    out_transaction.commit()?;
+
+    logger.info(&format!(
+        "Joined {total_rows} rows into {out_full_table_name} ({inserted_rows} pairs)"
+    ));
+
+    Ok(())
+}
@@ -0,0 +1,321 @@
+use super::cli::{
+    DeleteQueryVectorArgs, QueryVectorStoreArgs, VectorAverageArgs, VectorCombineArgs,
+};
+use super::CONNECTION_PARAMS;
+use crate::logger::{LogLevel, Logger};
+use crate::types::*;
+use crate::utils::{append_params_to_uri, get_full_table_name, quote_ident};
+use postgres::{Client, NoTls};
+
+pub struct QueryVectorInfo {
+    pub name: String,
+    pub dimensions: usize,
+    pub description: Option<String>,
+    pub updated_at: String,
+}
+
+fn connect(store: &QueryVectorStoreArgs) -> Result<Client, anyhow::Error> {
+    let uri = append_params_to_uri(&store.uri, CONNECTION_PARAMS);
+    Ok(Client::connect(&uri, NoTls)?)
+}
+
+fn create_vectors_table(client: &mut Client, store: &QueryVectorStoreArgs) -> AnyhowVoidResult {
+    let full_table_name = get_full_table_name(&store.vectors_schema, &store.vectors_table);
+    client.batch_execute(&format!(
+        "
+        CREATE TABLE IF NOT EXISTS {full_table_name} (
+            name TEXT PRIMARY KEY,
+            vector REAL[] NOT NULL,
+            description TEXT,
+            updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+        );
+    "
+    ))?;
+    Ok(())
+}
+
+pub fn normalize_vector(vector: &mut [f32]) {
+    let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if norm > 0.0 {
+        vector.iter_mut().for_each(|v| *v /= norm);
+    }
+}
+
+pub fn mean_vector(vectors: &[Vec<f32>]) -> Result<Vec<f32>, anyhow::Error> {
+    if vectors.is_empty() {
+        anyhow::bail!("No vectors to average");
+    }
+
+    let dimensions = vectors[0].len();
+    let mut mean = vec![0.0; dimensions];
+    for vector in vectors {
+        if vector.len() != dimensions {
+            anyhow::bail!(
+                "Vector dimensions do not match: {} and {dimensions}",
+                vector.len()
+            );
+        }
+        mean.iter_mut().zip(vector).for_each(|(m, v)| *m += v);
+    }
+
+    mean.iter_mut().for_each(|m| *m /= vectors.len() as f32);
+    Ok(mean)
+}
+
+// Add vectors with sign 1.0 and subtract with sign -1.0
+pub fn combine(vectors: &[(f32, Vec<f32>)]) -> Result<Vec<f32>, anyhow::Error> {
+    if vectors.is_empty() {
+        anyhow::bail!("No vectors to combine");
+    }
+
+    let dimensions = vectors[0].1.len();
+    let mut result = vec![0.0; dimensions];
+    for (sign, vector) in vectors {
+        if vector.len() != dimensions {
+            anyhow::bail!(
+                "Vector dimensions do not match: {} and {dimensions}",
+                vector.len()
+            );
+        }
+        result
+            .iter_mut()
+            .zip(vector)
+            .for_each(|(r, v)| *r += sign * v);
+    }
+
+    Ok(result)
+}
+
+pub fn get_query_vector(
+    client: &mut Client,
+    store: &QueryVectorStoreArgs,
+    name: &str,
+) -> Result<Vec<f32>, anyhow::Error> {
+    let full_table_name = get_full_table_name(&store.vectors_schema, &store.vectors_table);
+    let rows = client.query(
+        &format!("SELECT vector FROM {full_table_name} WHERE name = $1"),
+        &[&name],
+    )?;
+
+    match rows.first() {
+        Some(row) => Ok(row.get::<usize, Vec<f32>>(0)),
+        None => anyhow::bail!("Query vector '{name}' not found"),
+    }
+}
+
+pub fn save_query_vector(
+    client: &mut Client,
+    store: &QueryVectorStoreArgs,
+    name: &str,
+    vector: &[f32],
+    description: &str,
+) -> AnyhowVoidResult {
+    create_vectors_table(client, store)?;
+    let full_table_name = get_full_table_name(&store.vectors_schema, &store.vectors_table);
+    client.execute(
+        &format!(
+            "INSERT INTO {full_table_name} (name, vector, description) VALUES ($1, $2, $3)
+             ON CONFLICT (name) DO UPDATE SET vector = EXCLUDED.vector, description = EXCLUDED.description, updated_at = now()"
+        ),
+        &[&name, &vector, &description],
+    )?;
+    Ok(())
+}
+
+// Inline vectors are passed as JSON arrays, other values are names of stored query vectors
+fn resolve_vector(
+    client: &mut Client,
+    store: &QueryVectorStoreArgs,
+    value: &str,
+) -> Result<Vec<f32>, anyhow::Error> {
+    if value.trim_start().starts_with('[') {
+        return serde_json::from_str::<Vec<f32>>(value)
+            .map_err(|e| anyhow::anyhow!("Invalid inline vector {value}: {e}"));
+    }
+
+    get_query_vector(client, store, value)
+}
+
+fn output_vector(
+    client: &mut Client,
+    store: &QueryVectorStoreArgs,
+    name: &Option<String>,
+    vector: &[f32],
+    description: &str,
+    logger: &Logger,
+) -> AnyhowVoidResult {
+    match name {
+        Some(name) => {
+            save_query_vector(client, store, name, vector, description)?;
+            logger.info(&format!(
+                "Query vector '{name}' ({} dimensions) saved to {}",
+                vector.len(),
+                get_full_table_name(&store.vectors_schema, &store.vectors_table)
+            ));
+        }
+        None => logger.print_raw(&serde_json::to_string(vector)?),
+    }
+    Ok(())
+}
+
+pub fn average_vectors(
+    args: &VectorAverageArgs,
+    logger: Option<Logger>,
+) -> Result<Vec<f32>, anyhow::Error> {
+    let logger = logger.unwrap_or(Logger::new("Lantern Vector Ops", LogLevel::Debug));
+
+    if args.ids.is_empty() && args.filter.is_none() {
+        anyhow::bail!("--ids or --filter should be specified to select the rows");
+    }
+
+    let mut client = connect(&args.store)?;
+    let full_table_name = get_full_table_name(&args.schema, &args.table);
+    let column = quote_ident(&args.column);
+    let mut conditions = vec![format!("{column} IS NOT NULL")];
+    if !args.ids.is_empty() {
+        conditions.push(format!("{}::text = ANY($1)", quote_ident(&args.pk)));
+    }
+    if let Some(filter) = &args.filter {
+        conditions.push(format!("({filter})"));
+    }
+
+    let sql = format!(
+        "SELECT {column}::real[] FROM {full_table_name} WHERE {}",
+        conditions.join(" AND ")
+    );
+    let rows = if args.ids.is_empty() {
+        client.query(&sql, &[])?
+    } else {
+        client.query(&sql, &[&args.ids])?
+    };
+
+    let vectors: Vec<Vec<f32>> = rows
+        .iter()
+        .map(|row| row.get::<usize, Vec<f32>>(0))
+        .collect();
+    if vectors.is_empty() {
+        anyhow::bail!("No rows with non null {} found", args.column);
+    }
+    logger.debug(&format!("Averaging {} vectors", vectors.len()));
+
+    let mut vector = mean_vector(&vectors)?;
+    if args.normalize {
+        normalize_vector(&mut vector);
+    }
+
+    let description = format!(
+        "average of {} rows of {full_table_name}.{column}",
+        vectors.len()
+    );
+    output_vector(
+        &mut client,
+        &args.store,
+        &args.name,
+        &vector,
+        &description,
+        &logger,
+    )?;
+    Ok(vector)
+}
+
+pub fn combine_vectors(
+    args: &VectorCombineArgs,
+    logger: Option<Logger>,
+) -> Result<Vec<f32>, anyhow::Error> {
+    let logger = logger.unwrap_or(Logger::new("Lantern Vector Ops", LogLevel::Debug));
+
+    if args.add.is_empty() && args.subtract.is_empty() {
+        anyhow::bail!("--add or --subtract should be specified");
+    }
+
+    let mut client = connect(&args.store)?;
+    let mut vectors = Vec::with_capacity(args.add.len() + args.subtract.len());
+    for value in &args.add {
+        vectors.push((1.0, resolve_vector(&mut client, &args.store, value)?));
+    }
+    for value in &args.subtract {
+        vectors.push((-1.0, resolve_vector(&mut client, &args.store, value)?));
+    }
+
+    let mut vector = combine(&vectors)?;
+    if args.normalize {
+        normalize_vector(&mut vector);
+    }
+
+    let mut description = args
+        .add
+        .iter()
+        .map(|value| format!("+ {value}"))
+        .chain(args.subtract.iter().map(|value| format!("- {value}")))
+        .collect::<Vec<String>>()
+        .join(" ");
+    description.truncate(1000);
+    output_vector(
+        &mut client,
+        &args.store,
+        &args.name,
+        &vector,
+        &description,
+        &logger,
+    )?;
+    Ok(vector)
+}
+
+pub fn list_query_vectors(
+    args: &QueryVectorStoreArgs,
+    logger: Option<Logger>,
+) -> Result<Vec<QueryVectorInfo>, anyhow::Error> {
+    let logger = logger.unwrap_or(Logger::new("Lantern Vector Ops", LogLevel::Debug));
+    let mut client = connect(args)?;
+    create_vectors_table(&mut client, args)?;
+    let full_table_name = get_full_table_name(&args.vectors_schema, &args.vectors_table);
+
+    let vectors: Vec<QueryVectorInfo> = client
+        .query(
+            &format!("SELECT name, COALESCE(array_length(vector, 1), 0), description, updated_at::text FROM {full_table_name} ORDER BY name"),
+            &[],
+        )?
+        .iter()
+        .map(|row| QueryVectorInfo {
+            name: row.get::<usize, String>(0),
+            dimensions: row.get::<usize, i32>(1) as usize,
+            description: row.get::<usize, Option<String>>(2),
+            updated_at: row.get::<usize, String>(3),
+        })
+        .collect();
+
+    let mut res = String::new();
+    for vector in &vectors {
+        res.push_str(&format!(
+            "{} - dimensions: {}, updated_at: {}, {}\n",
+            vector.name,
+            vector.dimensions,
+            vector.updated_at,
+            vector.description.as_deref().unwrap_or("")
+        ));
+    }
+    logger.info("Query vectors\n");
+    logger.print_raw(&res);
+    Ok(vectors)
+}
+
+pub fn delete_query_vector(
+    args: &DeleteQueryVectorArgs,
+    logger: Option<Logger>,
+) -> AnyhowVoidResult {
+    let logger = logger.unwrap_or(Logger::new("Lantern Vector Ops", LogLevel::Debug));
+    let mut client = connect(&args.store)?;
+    create_vectors_table(&mut client, &args.store)?;
+    let full_table_name =
+        get_full_table_name(&args.store.vectors_schema, &args.store.vectors_table);
+
+    let deleted = client.execute(
+        &format!("DELETE FROM {full_table_name} WHERE name = $1"),
+        &[&args.name],
+    )?;
+    if deleted == 0 {
+        anyhow::bail!("Query vector '{}' not found", args.name);
+    }
+    logger.info(&format!("Query vector '{}' deleted", args.name));
+    Ok(())
+}
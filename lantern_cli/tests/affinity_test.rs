@@ -0,0 +1,19 @@
+use lantern_cli::embeddings::affinity::parse_core_list;
+
+#[test]
+fn test_parse_core_list() {
+    assert_eq!(parse_core_list("0-3,6").unwrap(), vec![0, 1, 2, 3, 6]);
+    assert_eq!(parse_core_list(" 2 , 4-5 ").unwrap(), vec![2, 4, 5]);
+    assert!(parse_core_list("").is_err());
+    assert!(parse_core_list("3-1").is_err());
+
+    // Core ids which do not fit into cpu_set_t are rejected instead of panicking when pinning
+    assert_eq!(
+        parse_core_list("1024").unwrap_err().to_string(),
+        "Core id 1024 should be less than 1024"
+    );
+    assert_eq!(
+        parse_core_list("0-100000000000").unwrap_err().to_string(),
+        "Core id 100000000000 should be less than 1024"
+    );
+}
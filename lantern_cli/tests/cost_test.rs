@@ -0,0 +1,38 @@
+use lantern_cli::embeddings::cost::{get_token_price, CostReport};
+
+#[test]
+fn test_token_price() {
+    assert_eq!(
+        get_token_price("openai/text-embedding-3-small", None),
+        Some(0.02)
+    );
+    assert_eq!(
+        get_token_price("openai/text-embedding-3-small", Some(0.5)),
+        Some(0.5)
+    );
+    assert_eq!(get_token_price("BAAI/bge-small-en", None), None);
+    assert_eq!(get_token_price("BAAI/bge-small-en", Some(0.01)), Some(0.01));
+}
+
+#[test]
+fn test_cost_report() {
+    let mut report = CostReport {
+        model: "openai/text-embedding-3-large".to_owned(),
+        runtime: "openai".to_owned(),
+        rows: 1000,
+        tokens: 2_000_000,
+        requests: 10,
+        token_price: Some(0.13),
+    };
+    assert_eq!(report.estimated_cost(), Some(0.26));
+    assert_eq!(
+        report.to_string(),
+        "Cost report for openai/text-embedding-3-large (openai): 1000 rows, 2000000 tokens, 10 requests, estimated cost $0.2600 ($0.13 per 1M tokens)"
+    );
+
+    report.token_price = None;
+    assert_eq!(report.estimated_cost(), None);
+    assert!(report
+        .to_string()
+        .ends_with("estimated cost unknown (pass --token-price)"));
+}
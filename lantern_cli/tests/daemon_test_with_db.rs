@@ -193,6 +193,7 @@ fn start_daemon(
                 autotune_results_table: Some(AUTOTUNE_RESULTS_TABLE_NAME.to_owned()),
                 external_index_table,
                 log_level: LogLevel::Debug,
+                metrics_port: None,
             },
             None,
             Some((tx, rx)),
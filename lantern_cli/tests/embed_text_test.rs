@@ -0,0 +1,81 @@
+use std::time::Duration;
+
+use lantern_cli::embeddings::cli::{EmbedTextArgs, Runtime, VectorFormat};
+use lantern_cli::embeddings::embed_text::{embed_text, format_vector};
+use lantern_cli::mock_provider::{self, cli::MockProviderArgs};
+
+fn start_mock_provider(port: u16) {
+    std::thread::spawn(move || {
+        mock_provider::start(
+            MockProviderArgs {
+                host: "127.0.0.1".to_owned(),
+                port,
+                dimensions: 16,
+                latency_ms: 0,
+                api_key: None,
+                rate_limit_every: None,
+            },
+            None,
+        )
+        .expect("Failed to start mock provider");
+    });
+
+    for _ in 0..50 {
+        if isahc::get(format!("http://127.0.0.1:{port}/health")).is_ok() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    panic!("Mock provider did not start");
+}
+
+#[test]
+fn test_embed_text() {
+    let port = 8784;
+    start_mock_provider(port);
+
+    let args = EmbedTextArgs {
+        text: "Hello world".to_owned(),
+        model: "mock-embedding".to_owned(),
+        runtime: Runtime::OpenAiCompat,
+        runtime_params: format!("{{\"base_url\": \"http://127.0.0.1:{port}\"}}"),
+        truncate_dim: None,
+        normalize: false,
+        format: VectorFormat::Json,
+        stats: false,
+    };
+    let embedding = embed_text(&args).unwrap();
+    assert_eq!(
+        embedding,
+        mock_provider::get_mock_embedding("Hello world", 16)
+    );
+
+    let truncated = embed_text(&EmbedTextArgs {
+        truncate_dim: Some(4),
+        ..args
+    })
+    .unwrap();
+    let norm: f32 = truncated.iter().map(|v| v * v).sum::<f32>().sqrt();
+    assert_eq!(truncated.len(), 4);
+    assert!((norm - 1.0).abs() < 1e-4);
+}
+
+#[test]
+fn test_format_vector() {
+    let embedding = vec![0.5, -1.0, 0.25];
+    assert_eq!(
+        format_vector(&embedding, &VectorFormat::Json),
+        "[0.5,-1,0.25]"
+    );
+    assert_eq!(
+        format_vector(&embedding, &VectorFormat::Pgvector),
+        "[0.5,-1,0.25]"
+    );
+    assert_eq!(
+        format_vector(&embedding, &VectorFormat::Array),
+        "{0.5,-1,0.25}"
+    );
+    let parsed: Vec<f32> =
+        serde_json::from_str(&format_vector(&embedding, &VectorFormat::Json)).unwrap();
+    assert_eq!(parsed, embedding);
+}
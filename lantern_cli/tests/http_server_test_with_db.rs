@@ -14,6 +14,8 @@ use lantern_cli::{
 use tokio_postgres::{Client, NoTls};
 
 static TEST_COLLECTION_NAME: &'static str = "_lantern_http_test1";
+static TEST_JOBS_SOURCE_TABLE: &'static str = "_lantern_http_jobs_test";
+static TEST_JOBS_TABLE: &'static str = "_lantern_http_test_embedding_jobs";
 static SERVER_URL: &'static str = "http://127.0.0.1:7777";
 static AUTH_HEADER: (&'static str, &'static str) = ("Authorization", "Basic dGVzdDp0ZXN0");
 
@@ -24,6 +26,8 @@ async fn drop_db_tables(client: &mut Client) {
         DROP TABLE IF EXISTS {TEST_COLLECTION_NAME};
         DROP TABLE IF EXISTS _lantern_internal.http_collections;
         DROP TABLE IF EXISTS _lantern_internal.pq_{TEST_COLLECTION_NAME}_v;
+        DROP TABLE IF EXISTS {TEST_JOBS_SOURCE_TABLE};
+        DROP TABLE IF EXISTS {TEST_JOBS_TABLE};
     "
         ))
         .await
@@ -47,6 +51,11 @@ fn start_server(db_uri: String) -> Sender<()> {
                     port: 7777,
                     username: Some("test".to_owned()),
                     password: Some("test".to_owned()),
+                    enable_jobs: true,
+                    jobs_schema: "public".to_owned(),
+                    jobs_table: TEST_JOBS_TABLE.to_owned(),
+                    job_runners: 1,
+                    allow_job_db_uri: false,
                 },
                 None,
             )
@@ -431,6 +440,132 @@ async fn test_index_delete() -> AnyhowVoidResult {
     Ok(())
 }
 
+fn send_json(
+    request: isahc::http::request::Builder,
+    body: &str,
+) -> Result<(StatusCode, serde_json::Value), anyhow::Error> {
+    let request = request
+        .header("content-type", "application/json")
+        .header(AUTH_HEADER.0, AUTH_HEADER.1)
+        .body(body.as_bytes().to_vec())?;
+
+    let mut response = isahc::send(request)?;
+    let mut body: Vec<u8> = Vec::new();
+    response.copy_to(&mut body)?;
+    let body_json = String::from_utf8(body)?;
+    println!("Response: {:?}", body_json);
+    let body_json = serde_json::from_str(&body_json).unwrap_or(serde_json::Value::Null);
+    Ok((response.status(), body_json))
+}
+
+async fn test_jobs() -> AnyhowVoidResult {
+    let db_uri = env::var("DB_URL").expect("`DB_URL` not specified");
+    let (db_client, connection) = tokio_postgres::connect(&db_uri, NoTls).await?;
+    tokio::spawn(async move { connection.await.unwrap() });
+    db_client
+        .batch_execute(&format!(
+            "
+        CREATE TABLE {TEST_JOBS_SOURCE_TABLE} (id SERIAL PRIMARY KEY, content TEXT);
+        INSERT INTO {TEST_JOBS_SOURCE_TABLE} SELECT generate_series(1,10), 'Hello world!';
+    "
+        ))
+        .await?;
+
+    // Job does not match any rows, so it will succeed without loading the model
+    let body = format!(
+        r#"{{
+                 "table": "{TEST_JOBS_SOURCE_TABLE}",
+                 "column": "content",
+                 "out_column": "emb",
+                 "model": "BAAI/bge-small-en",
+                 "filter": "id < 0"
+             }}"#
+    );
+    let (status, job) = send_json(Request::post(&format!("{SERVER_URL}/jobs")), &body)?;
+    assert_eq!(status, StatusCode::from_u16(200)?);
+    assert_eq!(job["table"], TEST_JOBS_SOURCE_TABLE);
+    assert_eq!(job["runtime"], "ort");
+    assert!(job.get("db_uri").is_none());
+    let job_id = job["id"].as_i64().unwrap();
+
+    let mut job = job;
+    for _ in 0..60 {
+        if job["status"] != "queued" && job["status"] != "running" {
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(500));
+        (_, job) = send_json(Request::get(&format!("{SERVER_URL}/jobs/{job_id}")), "")?;
+    }
+    assert_eq!(job["status"], "succeeded");
+    assert_eq!(job["progress"], 100);
+    assert_eq!(job["processed_rows"], 0);
+
+    let (status, jobs) = send_json(
+        Request::get(&format!("{SERVER_URL}/jobs?status=succeeded")),
+        "",
+    )?;
+    assert_eq!(status, StatusCode::from_u16(200)?);
+    assert_eq!(jobs.as_array().unwrap().len(), 1);
+    let (_, jobs) = send_json(
+        Request::get(&format!("{SERVER_URL}/jobs?status=failed")),
+        "",
+    )?;
+    assert_eq!(jobs.as_array().unwrap().len(), 0);
+
+    // Finished jobs can not be canceled
+    let (status, _) = send_json(
+        Request::post(&format!("{SERVER_URL}/jobs/{job_id}/cancel")),
+        "",
+    )?;
+    assert_eq!(status, StatusCode::from_u16(409)?);
+    let (status, _) = send_json(
+        Request::post(&format!("{SERVER_URL}/jobs/999999/cancel")),
+        "",
+    )?;
+    assert_eq!(status, StatusCode::from_u16(404)?);
+    let (status, _) = send_json(Request::get(&format!("{SERVER_URL}/jobs/999999")), "")?;
+    assert_eq!(status, StatusCode::from_u16(404)?);
+
+    let body = format!(
+        r#"{{ "table": "{TEST_JOBS_SOURCE_TABLE}", "column": "content", "out_column": "emb", "model": "BAAI/bge-small-en", "runtime": "invalid" }}"#
+    );
+    let (status, _) = send_json(Request::post(&format!("{SERVER_URL}/jobs")), &body)?;
+    assert_eq!(status, StatusCode::from_u16(400)?);
+
+    // Other databases are rejected without --allow-job-db-uri
+    let body = format!(
+        r#"{{ "table": "{TEST_JOBS_SOURCE_TABLE}", "column": "content", "out_column": "emb", "model": "BAAI/bge-small-en", "db_uri": "postgres://postgres@other-host:5432/postgres" }}"#
+    );
+    let (status, _) = send_json(Request::post(&format!("{SERVER_URL}/jobs")), &body)?;
+    assert_eq!(status, StatusCode::from_u16(400)?);
+
+    let (status, runtimes) = send_json(Request::get(&format!("{SERVER_URL}/runtimes")), "")?;
+    assert_eq!(status, StatusCode::from_u16(200)?);
+    assert!(runtimes
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|runtime| runtime["name"] == "ort" && runtime["requires_api_key"] == false));
+
+    let (status, models) = send_json(
+        Request::get(&format!("{SERVER_URL}/models?runtime=ort")),
+        "",
+    )?;
+    assert_eq!(status, StatusCode::from_u16(200)?);
+    assert!(models
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|model| model["name"] == "BAAI/bge-small-en"));
+    let (status, _) = send_json(
+        Request::get(&format!("{SERVER_URL}/models?runtime=invalid")),
+        "",
+    )?;
+    assert_eq!(status, StatusCode::from_u16(400)?);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_http_server() {
     test_cleanup().await;
@@ -444,6 +579,7 @@ async fn test_http_server() {
     test_search_vector().await.unwrap();
     test_index_delete().await.unwrap();
     test_collection_delete().await.unwrap();
+    test_jobs().await.unwrap();
     tx.send(()).unwrap();
     test_cleanup().await;
 }
@@ -0,0 +1,101 @@
+use std::env;
+
+use lantern_cli::jobs;
+use postgres::{Client, NoTls};
+
+fn setup_db_tables(client: &mut Client, table_name: &str, jobs_table_name: &str) {
+    client
+        .batch_execute(&format!(
+            "
+    DROP TABLE IF EXISTS {table_name};
+    DROP TABLE IF EXISTS {jobs_table_name};
+    CREATE TABLE {table_name} (id SERIAL PRIMARY KEY, content TEXT);
+    INSERT INTO {table_name} SELECT generate_series(1,10), 'Hello world!';
+"
+        ))
+        .expect("Could not create necessarry tables");
+}
+
+fn drop_db_tables(client: &mut Client, table_name: &str, jobs_table_name: &str) {
+    client
+        .batch_execute(&format!(
+            "
+        DROP TABLE IF EXISTS {table_name};
+        DROP TABLE IF EXISTS {jobs_table_name};
+    "
+        ))
+        .expect("Could not drop tables");
+}
+
+#[test]
+fn test_jobs_runner() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = String::from("_jobs_test");
+    let jobs_table_name = String::from("_jobs_test_embedding_jobs");
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    setup_db_tables(&mut db_client, &table_name, &jobs_table_name);
+
+    jobs::create_jobs_table(
+        &jobs::cli::JobsTableArgs {
+            uri: db_url.clone(),
+            schema: "public".to_owned(),
+            table: jobs_table_name.clone(),
+        },
+        None,
+    )
+    .unwrap();
+
+    // First job does not match any rows, so it will succeed without loading the model
+    // Second job has invalid runtime and should fail
+    db_client
+        .batch_execute(&format!(
+            "
+        INSERT INTO {jobs_table_name} (\"table\", src_column, dst_column, embedding_model, filter) VALUES ('{table_name}', 'content', 'emb', 'BAAI/bge-small-en', 'id < 0');
+        INSERT INTO {jobs_table_name} (\"table\", src_column, dst_column, embedding_model, runtime) VALUES ('{table_name}', 'content', 'emb', 'BAAI/bge-small-en', 'invalid');
+    "
+        ))
+        .unwrap();
+
+    // Run 2 runners at the same time, each job should be claimed once
+    let handles: Vec<_> = (0..2)
+        .map(|i| {
+            let db_url = db_url.clone();
+            let jobs_table_name = jobs_table_name.clone();
+            std::thread::spawn(move || {
+                jobs::run_jobs(
+                    &jobs::cli::JobsRunnerArgs {
+                        uri: db_url,
+                        schema: "public".to_owned(),
+                        table: jobs_table_name,
+                        runner_name: Some(format!("runner-{i}")),
+                        poll_interval: 1,
+                        once: true,
+                    },
+                    None,
+                    None,
+                )
+            })
+        })
+        .collect();
+
+    for handle in handles {
+        handle.join().unwrap().unwrap();
+    }
+
+    let rows = db_client
+        .query(
+            &format!("SELECT status, progress, attempts, error FROM {jobs_table_name} ORDER BY id"),
+            &[],
+        )
+        .unwrap();
+
+    drop_db_tables(&mut db_client, &table_name, &jobs_table_name);
+
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].get::<usize, String>(0), "succeeded");
+    assert_eq!(rows[0].get::<usize, i16>(1), 100);
+    assert_eq!(rows[0].get::<usize, i32>(2), 1);
+    assert_eq!(rows[1].get::<usize, String>(0), "failed");
+    assert_eq!(rows[1].get::<usize, i32>(2), 1);
+    assert!(rows[1].get::<usize, Option<String>>(3).is_some());
+}
@@ -0,0 +1,180 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use isahc::ReadResponseExt;
+use lantern_cli::logger::{LogLevel, Logger};
+use lantern_cli::metrics::{self, cli::ExportDashboardArgs, MetricKind, Subsystem, METRICS};
+
+#[test]
+fn test_metric_names() {
+    let mut names = HashSet::new();
+    for metric in METRICS.iter() {
+        let name = metric.full_name();
+        assert!(names.insert(name.clone()), "duplicate metric {name}");
+        assert!(name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'));
+        assert!(name.starts_with(&format!("lantern_{}_", metric.subsystem)));
+        assert_eq!(
+            metric.kind == MetricKind::Counter,
+            name.ends_with("_total"),
+            "{name}"
+        );
+        if metric.kind == MetricKind::Histogram {
+            assert!(name.ends_with("_seconds"), "{name}");
+        }
+    }
+
+    for subsystem in Subsystem::all() {
+        assert!(METRICS.iter().any(|m| m.subsystem == subsystem));
+    }
+
+    assert_eq!(
+        metrics::get_metric(Subsystem::Embeddings, "rows_processed_total")
+            .unwrap()
+            .full_name(),
+        "lantern_embeddings_rows_processed_total"
+    );
+}
+
+#[test]
+fn test_export_dashboard() {
+    let out = std::env::temp_dir().join("lantern-dashboard-test.json");
+    metrics::export_dashboard(
+        &ExportDashboardArgs {
+            out: out.to_str().unwrap().to_owned(),
+            title: "Lantern Test".to_owned(),
+            uid: "lantern-test".to_owned(),
+        },
+        None,
+    )
+    .unwrap();
+
+    let dashboard: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&out).unwrap()).unwrap();
+    std::fs::remove_file(&out).unwrap();
+
+    assert_eq!(dashboard["uid"], "lantern-test");
+    assert_eq!(dashboard["title"], "Lantern Test");
+
+    let panels = dashboard["panels"].as_array().unwrap();
+    let rows: Vec<&str> = panels
+        .iter()
+        .filter(|p| p["type"] == "row")
+        .map(|p| p["title"].as_str().unwrap())
+        .collect();
+    assert_eq!(rows, vec!["Embeddings", "Product Quantization", "Daemon"]);
+
+    let ids: HashSet<u64> = panels.iter().map(|p| p["id"].as_u64().unwrap()).collect();
+    assert_eq!(ids.len(), panels.len());
+
+    let exprs: Vec<&str> = panels
+        .iter()
+        .filter_map(|p| p["targets"].as_array())
+        .flatten()
+        .map(|t| t["expr"].as_str().unwrap())
+        .collect();
+
+    // Each metric is shown on the dashboard
+    for metric in METRICS.iter() {
+        assert!(
+            exprs.iter().any(|e| e.contains(&metric.full_name())),
+            "{} is not on the dashboard",
+            metric.full_name()
+        );
+    }
+
+    assert!(exprs.contains(
+        &"sum by (model, runtime, table) (rate(lantern_embeddings_rows_processed_total{model=~\"$model\",table=~\"$table\"}[$__rate_interval]))"
+    ));
+    assert!(exprs.contains(
+        &"histogram_quantile(0.95, sum by (le, job_type) (rate(lantern_daemon_job_duration_seconds_bucket[$__rate_interval])))"
+    ));
+
+    let variables: Vec<&str> = dashboard["templating"]["list"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|v| v["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(variables, vec!["datasource", "model", "table"]);
+}
+
+#[test]
+fn test_render_metrics() {
+    metrics::inc_counter(
+        Subsystem::Embeddings,
+        "rows_processed_total",
+        &["render-model", "ort", "render_\"table\""],
+        10.0,
+    );
+    metrics::inc_counter(
+        Subsystem::Embeddings,
+        "rows_processed_total",
+        &["render-model", "ort", "render_\"table\""],
+        5.0,
+    );
+    metrics::set_gauge(
+        Subsystem::Embeddings,
+        "export_lag_rows",
+        &["render_table"],
+        42.0,
+    );
+    metrics::observe(
+        Subsystem::Embeddings,
+        "batch_duration_seconds",
+        &["render-model", "ort"],
+        0.2,
+    );
+    metrics::observe(
+        Subsystem::Embeddings,
+        "batch_duration_seconds",
+        &["render-model", "ort"],
+        3.0,
+    );
+
+    let output = metrics::render();
+    assert!(output.contains("# TYPE lantern_embeddings_rows_processed_total counter\n"));
+    assert!(output.contains(
+        "lantern_embeddings_rows_processed_total{model=\"render-model\",runtime=\"ort\",table=\"render_\\\"table\\\"\"} 15\n"
+    ));
+    assert!(output.contains("lantern_embeddings_export_lag_rows{table=\"render_table\"} 42\n"));
+    assert!(output.contains(
+        "lantern_embeddings_batch_duration_seconds_bucket{model=\"render-model\",runtime=\"ort\",le=\"0.25\"} 1\n"
+    ));
+    assert!(output.contains(
+        "lantern_embeddings_batch_duration_seconds_bucket{model=\"render-model\",runtime=\"ort\",le=\"5\"} 2\n"
+    ));
+    assert!(output.contains(
+        "lantern_embeddings_batch_duration_seconds_bucket{model=\"render-model\",runtime=\"ort\",le=\"+Inf\"} 2\n"
+    ));
+    assert!(output.contains(
+        "lantern_embeddings_batch_duration_seconds_count{model=\"render-model\",runtime=\"ort\"} 2\n"
+    ));
+}
+
+#[test]
+fn test_metrics_server() {
+    let logger = Arc::new(Logger::new("Lantern Metrics", LogLevel::Error));
+    let port = metrics::start_metrics_server(0, logger.clone()).unwrap();
+    assert!(port > 0);
+    // Server is started once per port
+    assert_eq!(metrics::start_metrics_server(port, logger).unwrap(), port);
+
+    metrics::inc_counter(
+        Subsystem::Daemon,
+        "jobs_finished_total",
+        &["server-test", "success"],
+        1.0,
+    );
+
+    let mut response = isahc::get(format!("http://127.0.0.1:{port}/metrics")).unwrap();
+    assert_eq!(response.status(), 200);
+    let body = response.text().unwrap();
+    assert!(body.contains(
+        "lantern_daemon_jobs_finished_total{job_type=\"server-test\",status=\"success\"} 1\n"
+    ));
+
+    let response = isahc::get(format!("http://127.0.0.1:{port}/other")).unwrap();
+    assert_eq!(response.status(), 404);
+}
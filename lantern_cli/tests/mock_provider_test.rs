@@ -0,0 +1,132 @@
+use std::time::Duration;
+
+use isahc::{ReadResponseExt, Request, RequestExt};
+use lantern_cli::embeddings::core::{get_runtime, Runtime};
+use lantern_cli::mock_provider::{self, cli::MockProviderArgs};
+
+fn start_mock_provider(port: u16, rate_limit_every: Option<usize>) {
+    std::thread::spawn(move || {
+        mock_provider::start(
+            MockProviderArgs {
+                host: "127.0.0.1".to_owned(),
+                port,
+                dimensions: 384,
+                latency_ms: 0,
+                api_key: Some("test-key".to_owned()),
+                rate_limit_every,
+            },
+            None,
+        )
+        .expect("Failed to start mock provider");
+    });
+
+    for _ in 0..50 {
+        if isahc::get(format!("http://127.0.0.1:{port}/health")).is_ok() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    panic!("Mock provider did not start");
+}
+
+#[test]
+fn test_mock_provider_embeddings() {
+    start_mock_provider(8765, None);
+
+    let runtime = get_runtime(
+        &Runtime::OpenAiCompat,
+        None,
+        r#"{"base_url": "http://127.0.0.1:8765", "api_key": "test-key"}"#,
+    )
+    .unwrap();
+    let res = runtime
+        .process("mock-embedding", &vec!["Hello world", "Hello world", "Bye"])
+        .unwrap();
+
+    assert_eq!(res.embeddings.len(), 3);
+    assert_eq!(res.embeddings[0].len(), 384);
+    assert_eq!(res.embeddings[0], res.embeddings[1]);
+    assert_ne!(res.embeddings[0], res.embeddings[2]);
+    assert_eq!(res.processed_tokens, 5);
+    assert_eq!(
+        res.embeddings[0],
+        mock_provider::get_mock_embedding("Hello world", 384)
+    );
+    let norm: f32 = res.embeddings[0].iter().map(|v| v * v).sum::<f32>().sqrt();
+    assert!((norm - 1.0).abs() < 1e-4);
+
+    let runtime = get_runtime(
+        &Runtime::OpenAiCompat,
+        None,
+        r#"{"base_url": "http://127.0.0.1:8765/v1", "api_key": "test-key", "dimensions": 8}"#,
+    )
+    .unwrap();
+    let res = runtime.process("mock-embedding", &vec!["Hello"]).unwrap();
+    assert_eq!(res.embeddings[0].len(), 8);
+
+    let response = Request::post("http://127.0.0.1:8765/v1/embeddings")
+        .header("Content-Type", "application/json")
+        .header("Authorization", "Bearer invalid")
+        .body(r#"{"input": "Hello"}"#)
+        .unwrap()
+        .send()
+        .unwrap();
+    assert_eq!(response.status(), 401);
+
+    let mut response = isahc::get("http://127.0.0.1:8765/v1/models").unwrap();
+    assert!(response.text().unwrap().contains("mock-embedding"));
+}
+
+#[test]
+fn test_mock_provider_rate_limit_retry() {
+    start_mock_provider(8766, Some(2));
+
+    let runtime = get_runtime(
+        &Runtime::OpenAiCompat,
+        None,
+        r#"{"base_url": "http://127.0.0.1:8766", "api_key": "test-key"}"#,
+    )
+    .unwrap();
+
+    // Every second request is rate limited and retried after Retry-After interval
+    for _ in 0..2 {
+        let res = runtime.process("mock-embedding", &vec!["Hello"]).unwrap();
+        assert_eq!(res.embeddings.len(), 1);
+    }
+}
+
+#[test]
+fn test_mock_provider_tei_embed() {
+    start_mock_provider(8767, None);
+
+    let runtime = get_runtime(
+        &Runtime::Tei,
+        None,
+        r#"{"base_url": "http://127.0.0.1:8767/", "api_key": "test-key", "max_batch_size": 2}"#,
+    )
+    .unwrap();
+    let res = runtime
+        .process(
+            "BAAI/bge-small-en",
+            &vec!["Hello world", "Hello world", "Bye"],
+        )
+        .unwrap();
+
+    assert_eq!(res.embeddings.len(), 3);
+    assert_eq!(res.processed_tokens, 0);
+    assert_eq!(res.embeddings[0], res.embeddings[1]);
+    assert_eq!(
+        res.embeddings[2],
+        mock_provider::get_mock_embedding("Bye", 384)
+    );
+
+    let runtime = get_runtime(
+        &Runtime::Tei,
+        None,
+        r#"{"base_url": "http://127.0.0.1:8767", "api_key": "test-key", "normalize": false}"#,
+    )
+    .unwrap();
+    let res = runtime.process("BAAI/bge-small-en", &vec!["Bye"]).unwrap();
+    let norm: f32 = res.embeddings[0].iter().map(|v| v * v).sum::<f32>().sqrt();
+    assert!((norm - 2.0).abs() < 1e-4);
+}
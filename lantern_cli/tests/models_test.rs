@@ -0,0 +1,64 @@
+use std::fs;
+
+use lantern_cli::embeddings::core::utils::{get_checksum_path, sha256_file};
+use lantern_cli::embeddings::models::{get_local_models, verify_local_model, ChecksumStatus};
+
+#[test]
+fn test_local_models() {
+    let data_path = std::env::temp_dir().join("_lantern_models_test");
+    let _ = fs::remove_dir_all(&data_path);
+
+    let builtin_folder = data_path.join("clip").join("ViT-B-32-textual");
+    let hf_folder = data_path
+        .join("hf")
+        .join("sentence-transformers")
+        .join("all-MiniLM-L6-v2")
+        .join("main");
+    fs::create_dir_all(&builtin_folder).unwrap();
+    fs::create_dir_all(&hf_folder).unwrap();
+    // Folders without model files are not listed
+    fs::create_dir_all(data_path.join("empty")).unwrap();
+
+    let model_path = builtin_folder.join("model.onnx");
+    fs::write(&model_path, b"model").unwrap();
+    fs::write(builtin_folder.join("tokenizer.json"), b"{}").unwrap();
+    fs::write(
+        get_checksum_path(&model_path),
+        sha256_file(&model_path).unwrap(),
+    )
+    .unwrap();
+    fs::write(hf_folder.join("model.onnx.part"), b"mod").unwrap();
+
+    let models = get_local_models(data_path.to_str().unwrap()).unwrap();
+    assert_eq!(models.len(), 2);
+    assert_eq!(models[0].name, "clip/ViT-B-32-textual");
+    assert!(models[0].partial_files.is_empty());
+    assert_eq!(models[1].name, "hf:sentence-transformers/all-MiniLM-L6-v2@main");
+    assert_eq!(models[1].partial_files, vec!["model.onnx.part".to_owned()]);
+    assert_eq!(models[1].size, 3);
+
+    assert_eq!(verify_local_model(&models[0]).unwrap(), ChecksumStatus::Ok);
+    assert_eq!(
+        verify_local_model(&models[1]).unwrap(),
+        ChecksumStatus::Unknown
+    );
+
+    fs::write(&model_path, b"corrupted").unwrap();
+    assert_eq!(
+        verify_local_model(&models[0]).unwrap(),
+        ChecksumStatus::Mismatch(vec!["model.onnx".to_owned()])
+    );
+
+    fs::remove_dir_all(&data_path).unwrap();
+}
+
+#[test]
+fn test_sha256_file() {
+    let path = std::env::temp_dir().join("_lantern_sha256_test");
+    fs::write(&path, b"abc").unwrap();
+    assert_eq!(
+        sha256_file(&path).unwrap(),
+        "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,40 @@
+use lantern_cli::embeddings::object_store::{self, ObjectUri};
+use std::env;
+
+#[test]
+fn test_parse_object_uri() {
+    assert_eq!(
+        ObjectUri::parse("s3://bucket/exports/embeddings.csv.gz").unwrap(),
+        Some(ObjectUri::S3 {
+            bucket: "bucket".to_owned(),
+            key: "exports/embeddings.csv.gz".to_owned()
+        })
+    );
+    assert_eq!(
+        ObjectUri::parse("gs://bucket/embeddings.parquet").unwrap(),
+        Some(ObjectUri::Gcs {
+            bucket: "bucket".to_owned(),
+            key: "embeddings.parquet".to_owned()
+        })
+    );
+    assert_eq!(ObjectUri::parse("/tmp/embeddings.csv").unwrap(), None);
+    assert!(ObjectUri::parse("s3://bucket").is_err());
+    assert!(ObjectUri::parse("azure://container/embeddings.csv").is_err());
+}
+
+#[test]
+fn test_local_file_roundtrip() {
+    let path = env::temp_dir().join("_object_store_test.json");
+    let path = path.to_str().unwrap();
+    let _ = std::fs::remove_file(path);
+
+    assert_eq!(object_store::read_to_string(path).unwrap(), None);
+    assert!(object_store::open_reader(path).is_err());
+
+    object_store::write(path, b"{\"mins\": []}").unwrap();
+    assert_eq!(
+        object_store::read_to_string(path).unwrap(),
+        Some("{\"mins\": []}".to_owned())
+    );
+    std::fs::remove_file(path).unwrap();
+}
@@ -6,6 +6,7 @@ use std::{
     },
 };
 
+use clap::Parser;
 use lantern_cli::pq;
 use lantern_cli::pq::*;
 use lantern_cli::utils::{get_full_table_name, quote_ident};
@@ -59,8 +60,18 @@ fn test_full_pq() {
             codebook_table_name: None,
             clusters: 10,
             splits: 32,
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 20,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Cpu,
+            kmeans_batch_size: None,
             dataset_limit: None,
             subvector_id: None,
+            opq: false,
+            opq_iterations: 4,
+            coarse_clusters: None,
+            coarse_centroids_table: None,
+            import_codebook: None,
             overwrite: false,
             skip_table_setup: false,
             skip_vector_quantization: false,
@@ -69,6 +80,7 @@ fn test_full_pq() {
             total_task_count: None,
             parallel_task_count: None,
             quantization_task_id: None,
+            watch: false,
             run_on_gcp: false,
             gcp_cli_image_tag: None,
             gcp_project: None,
@@ -115,6 +127,65 @@ fn test_full_pq() {
 
     assert_eq!(cnt, 0);
 
+    // Evaluate stored codebook
+    let evaluate_args = cli::PQEvaluateArgs {
+        uri: db_url.clone(),
+        table: table_name.clone(),
+        schema: "public".to_owned(),
+        column: "v".to_owned(),
+        pk: "id".to_owned(),
+        codebook_table_name: None,
+        splits: None,
+        clusters: 256,
+        kmeans_init: cli::KmeansInit::Kmeanspp,
+        kmeans_iters: 20,
+        kmeans_tolerance: 0.1,
+        accel: cli::Accel::Cpu,
+        opq: false,
+        opq_iterations: 4,
+        coarse_clusters: None,
+        queries: 20,
+        k: 5,
+        dataset_limit: None,
+    };
+    let evaluation = pq::evaluate::evaluate_pq(&evaluate_args, None).unwrap();
+    assert_eq!(evaluation.splits, 32);
+    assert_eq!(evaluation.clusters, 10);
+    assert_eq!(evaluation.queries, 20);
+    assert!(evaluation.recall > 0.0 && evaluation.recall <= 1.0);
+    assert!(evaluation.mean_reconstruction_error > 0.0);
+
+    // Temporary codebook with one centroid per vector reconstructs vectors exactly
+    let evaluation = pq::evaluate::evaluate_pq(
+        &cli::PQEvaluateArgs {
+            splits: Some(8),
+            clusters: 50,
+            dataset_limit: Some(50),
+            ..evaluate_args.clone()
+        },
+        None,
+    )
+    .unwrap();
+    assert_eq!(evaluation.splits, 8);
+    assert_eq!(evaluation.dataset_size, 50);
+    assert!(evaluation.recall > 0.99);
+    assert!(evaluation.mean_distance_error < 1e-3);
+
+    // Codebook is returned even if kmeans does not converge in the given iterations
+    let evaluation = pq::evaluate::evaluate_pq(
+        &cli::PQEvaluateArgs {
+            splits: Some(8),
+            clusters: 10,
+            kmeans_init: cli::KmeansInit::Random,
+            kmeans_iters: 1,
+            kmeans_tolerance: 0.0,
+            ..evaluate_args.clone()
+        },
+        None,
+    )
+    .unwrap();
+    assert_eq!(evaluation.clusters, 10);
+
     drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
 }
 
@@ -138,8 +209,18 @@ fn test_chunked_pq() {
             codebook_table_name: None,
             clusters: 10,
             splits: 32,
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 20,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Cpu,
+            kmeans_batch_size: None,
             dataset_limit: None,
             subvector_id: None,
+            opq: false,
+            opq_iterations: 4,
+            coarse_clusters: None,
+            coarse_centroids_table: None,
+            import_codebook: None,
             overwrite: false,
             skip_table_setup: false,
             skip_vector_quantization: true,
@@ -148,6 +229,7 @@ fn test_chunked_pq() {
             total_task_count: None,
             parallel_task_count: None,
             quantization_task_id: None,
+            watch: false,
             run_on_gcp: false,
             gcp_cli_image_tag: None,
             gcp_project: None,
@@ -205,8 +287,18 @@ fn test_chunked_pq() {
                 codebook_table_name: None,
                 clusters: 10,
                 splits: 32,
+                kmeans_init: cli::KmeansInit::Kmeanspp,
+                kmeans_iters: 20,
+                kmeans_tolerance: 0.1,
+                accel: cli::Accel::Cpu,
+                kmeans_batch_size: None,
                 dataset_limit: None,
                 subvector_id: Some(i),
+                opq: false,
+                opq_iterations: 4,
+                coarse_clusters: None,
+                coarse_centroids_table: None,
+                import_codebook: None,
                 overwrite: false,
                 skip_table_setup: true,
                 skip_vector_quantization: true,
@@ -215,6 +307,7 @@ fn test_chunked_pq() {
                 total_task_count: None,
                 parallel_task_count: Some(1),
                 quantization_task_id: None,
+                watch: false,
                 run_on_gcp: false,
                 gcp_cli_image_tag: None,
                 gcp_project: None,
@@ -275,9 +368,19 @@ fn test_chunked_pq() {
                 codebook_table_name: None,
                 clusters: 10,
                 splits: 32,
+                kmeans_init: cli::KmeansInit::Kmeanspp,
+                kmeans_iters: 20,
+                kmeans_tolerance: 0.1,
+                accel: cli::Accel::Cpu,
+                kmeans_batch_size: None,
                 overwrite: false,
                 dataset_limit: None,
                 subvector_id: None,
+                opq: false,
+                opq_iterations: 4,
+                coarse_clusters: None,
+                coarse_centroids_table: None,
+                import_codebook: None,
                 skip_table_setup: true,
                 skip_vector_quantization: false,
                 skip_codebook_creation: true,
@@ -285,6 +388,7 @@ fn test_chunked_pq() {
                 total_task_count: Some(3),
                 parallel_task_count: Some(1),
                 quantization_task_id: Some(i),
+                watch: false,
                 run_on_gcp: false,
                 gcp_cli_image_tag: None,
                 gcp_project: None,
@@ -333,6 +437,56 @@ fn test_chunked_pq() {
 
     assert_eq!(cnt, 0);
     // ==================================================================================
+
+    // ================= Check aggregated task progress ================
+    let clustering_progress = task_progress::aggregate_progress(
+        &mut db_client,
+        &codebook_table_name,
+        task_progress::TaskPhase::Clustering,
+        32,
+    )
+    .unwrap();
+    assert_eq!(clustering_progress.finished_tasks, 32);
+    assert_eq!(clustering_progress.percent, 100.0);
+
+    let quantization_progress = task_progress::aggregate_progress(
+        &mut db_client,
+        &codebook_table_name,
+        task_progress::TaskPhase::Quantization,
+        3,
+    )
+    .unwrap();
+    assert_eq!(quantization_progress.finished_tasks, 3);
+    assert_eq!(quantization_progress.percent, 100.0);
+
+    // Watch mode returns as all tasks are finished
+    let final_progress = Arc::new(AtomicU8::new(0));
+    let final_progress_r1 = final_progress.clone();
+    let callback = move |progress: u8| {
+        final_progress_r1.store(progress, Ordering::SeqCst);
+    };
+    pq::quantize_table(
+        cli::PQArgs::parse_from([
+            "pq-table",
+            "--uri",
+            &db_url,
+            "--table",
+            &table_name,
+            "--column",
+            "v",
+            "--splits",
+            "32",
+            "--total-task-count",
+            "3",
+            "--watch",
+        ]),
+        Some(Box::new(callback)),
+        None,
+        None,
+    )
+    .unwrap();
+    assert_eq!(final_progress.load(Ordering::SeqCst), 100);
+    // ==================================================================================
     drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
 }
 
@@ -356,9 +510,19 @@ fn test_chunked_pq_with_limit() {
             codebook_table_name: None,
             clusters: 10,
             splits: 32,
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 20,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Cpu,
+            kmeans_batch_size: None,
             overwrite: false,
             dataset_limit: Some(200),
             subvector_id: None,
+            opq: false,
+            opq_iterations: 4,
+            coarse_clusters: None,
+            coarse_centroids_table: None,
+            import_codebook: None,
             skip_table_setup: false,
             skip_vector_quantization: true,
             skip_codebook_creation: true,
@@ -366,6 +530,7 @@ fn test_chunked_pq_with_limit() {
             total_task_count: None,
             parallel_task_count: None,
             quantization_task_id: None,
+            watch: false,
             run_on_gcp: false,
             gcp_cli_image_tag: None,
             gcp_project: None,
@@ -424,7 +589,17 @@ fn test_chunked_pq_with_limit() {
                 clusters: 10,
                 dataset_limit: Some(200),
                 splits: 32,
+                kmeans_init: cli::KmeansInit::Kmeanspp,
+                kmeans_iters: 20,
+                kmeans_tolerance: 0.1,
+                accel: cli::Accel::Cpu,
+                kmeans_batch_size: None,
                 subvector_id: Some(i),
+                opq: false,
+                opq_iterations: 4,
+                coarse_clusters: None,
+                coarse_centroids_table: None,
+                import_codebook: None,
                 overwrite: false,
                 skip_table_setup: true,
                 skip_vector_quantization: true,
@@ -433,6 +608,7 @@ fn test_chunked_pq_with_limit() {
                 total_task_count: None,
                 parallel_task_count: Some(1),
                 quantization_task_id: None,
+                watch: false,
                 run_on_gcp: false,
                 gcp_cli_image_tag: None,
                 gcp_project: None,
@@ -494,7 +670,17 @@ fn test_chunked_pq_with_limit() {
                 clusters: 10,
                 dataset_limit: Some(200),
                 splits: 32,
+                kmeans_init: cli::KmeansInit::Kmeanspp,
+                kmeans_iters: 20,
+                kmeans_tolerance: 0.1,
+                accel: cli::Accel::Cpu,
+                kmeans_batch_size: None,
                 subvector_id: None,
+                opq: false,
+                opq_iterations: 4,
+                coarse_clusters: None,
+                coarse_centroids_table: None,
+                import_codebook: None,
                 overwrite: false,
                 skip_table_setup: true,
                 skip_vector_quantization: false,
@@ -503,6 +689,7 @@ fn test_chunked_pq_with_limit() {
                 total_task_count: Some(3),
                 parallel_task_count: Some(1),
                 quantization_task_id: Some(i),
+                watch: false,
                 run_on_gcp: false,
                 gcp_cli_image_tag: None,
                 gcp_project: None,
@@ -553,3 +740,1102 @@ fn test_chunked_pq_with_limit() {
     // ==================================================================================
     drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
 }
+
+#[test]
+fn test_opq() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = String::from("_pq_opq_test");
+    let codebook_table_name = get_full_table_name("_lantern_internal", "pq__pq_opq_test_v");
+    let rotation_table_name = get_full_table_name("_lantern_internal", "pq__pq_opq_test_v_opq");
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+    setup_db_tables(&mut db_client, &table_name, 1, 1000);
+
+    pq::quantize_table(
+        cli::PQArgs {
+            uri: db_url.clone(),
+            column: "v".to_owned(),
+            table: table_name.clone(),
+            schema: "public".to_owned(),
+            codebook_table_name: None,
+            clusters: 10,
+            splits: 16,
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 20,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Cpu,
+            kmeans_batch_size: None,
+            dataset_limit: None,
+            subvector_id: None,
+            opq: true,
+            opq_iterations: 2,
+            coarse_clusters: None,
+            coarse_centroids_table: None,
+            import_codebook: None,
+            overwrite: false,
+            skip_table_setup: false,
+            skip_vector_quantization: false,
+            skip_codebook_creation: false,
+            pk: "id".to_owned(),
+            total_task_count: None,
+            parallel_task_count: None,
+            quantization_task_id: None,
+            watch: false,
+            run_on_gcp: false,
+            gcp_cli_image_tag: None,
+            gcp_project: None,
+            gcp_region: None,
+            gcp_image: None,
+            gcp_quantization_task_count: None,
+            gcp_quantization_task_parallelism: None,
+            gcp_clustering_task_parallelism: None,
+            gcp_enable_image_streaming: false,
+            gcp_clustering_cpu: None,
+            gcp_clustering_memory_gb: None,
+            gcp_quantization_cpu: None,
+            gcp_quantization_memory_gb: None,
+            dataset_size: None,
+            start_offset_id: None,
+        },
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    // Rotation should be orthogonal
+    let rotation = pq::opq::read_rotation(&mut db_client, &rotation_table_name)
+        .unwrap()
+        .unwrap();
+    assert_eq!(rotation.shape(), &[128, 128]);
+    let product = rotation.t().dot(&rotation);
+    for ((i, j), value) in product.indexed_iter() {
+        let expected = if i == j { 1.0 } else { 0.0 };
+        assert!((value - expected).abs() < 1e-3);
+    }
+
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {table_name} WHERE ARRAY_LENGTH(v_pq::INT[], 1) != 16 or v_pq is null"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 0);
+
+    // New rows are rotated by the trigger before quantization
+    db_client
+        .batch_execute(&format!(
+            "INSERT INTO {table_name} (id, v) SELECT 1001, v FROM {table_name} WHERE id = 1"
+        ))
+        .unwrap();
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {table_name} a JOIN {table_name} b ON a.v_pq::INT[] = b.v_pq::INT[] WHERE a.id = 1 AND b.id = 1001"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 1);
+
+    let evaluation = pq::evaluate::evaluate_pq(
+        &cli::PQEvaluateArgs {
+            uri: db_url.clone(),
+            table: table_name.clone(),
+            schema: "public".to_owned(),
+            column: "v".to_owned(),
+            pk: "id".to_owned(),
+            codebook_table_name: None,
+            splits: None,
+            clusters: 256,
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 20,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Cpu,
+            opq: false,
+            opq_iterations: 4,
+            coarse_clusters: None,
+            queries: 20,
+            k: 5,
+            dataset_limit: None,
+        },
+        None,
+    )
+    .unwrap();
+    assert_eq!(evaluation.splits, 16);
+    assert!(evaluation.recall > 0.0);
+
+    db_client
+        .batch_execute(&format!("DROP TABLE IF EXISTS {rotation_table_name}"))
+        .unwrap();
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+}
+
+#[test]
+fn test_ivf_pq() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = String::from("_pq_ivf_test");
+    let codebook_table_name = get_full_table_name("_lantern_internal", "pq__pq_ivf_test_v");
+    let coarse_table_name = get_full_table_name("_lantern_internal", "pq__pq_ivf_test_v_ivf");
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+    setup_db_tables(&mut db_client, &table_name, 1, 1000);
+
+    pq::quantize_table(
+        cli::PQArgs {
+            uri: db_url.clone(),
+            column: "v".to_owned(),
+            table: table_name.clone(),
+            schema: "public".to_owned(),
+            codebook_table_name: None,
+            clusters: 10,
+            splits: 16,
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 20,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Cpu,
+            kmeans_batch_size: None,
+            dataset_limit: None,
+            subvector_id: None,
+            opq: false,
+            opq_iterations: 4,
+            coarse_clusters: Some(8),
+            coarse_centroids_table: None,
+            import_codebook: None,
+            overwrite: false,
+            skip_table_setup: false,
+            skip_vector_quantization: false,
+            skip_codebook_creation: false,
+            pk: "id".to_owned(),
+            total_task_count: None,
+            parallel_task_count: None,
+            quantization_task_id: None,
+            watch: false,
+            run_on_gcp: false,
+            gcp_cli_image_tag: None,
+            gcp_project: None,
+            gcp_region: None,
+            gcp_image: None,
+            gcp_quantization_task_count: None,
+            gcp_quantization_task_parallelism: None,
+            gcp_clustering_task_parallelism: None,
+            gcp_enable_image_streaming: false,
+            gcp_clustering_cpu: None,
+            gcp_clustering_memory_gb: None,
+            gcp_quantization_cpu: None,
+            gcp_quantization_memory_gb: None,
+            dataset_size: None,
+            start_offset_id: None,
+        },
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let coarse_centroids = pq::ivf::read_coarse_centroids(&mut db_client, &coarse_table_name)
+        .unwrap()
+        .unwrap();
+    assert_eq!(coarse_centroids.len(), 8);
+    assert!(coarse_centroids.iter().all(|c| c.len() == 128));
+
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {table_name} WHERE ARRAY_LENGTH(v_pq::INT[], 1) != 16 OR v_pq IS NULL OR v_ivf IS NULL OR v_ivf < 0 OR v_ivf >= 8"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 0);
+
+    // Trigger assigns the same coarse centroid and codes to a copy of existing row
+    db_client
+        .batch_execute(&format!(
+            "INSERT INTO {table_name} (id, v) SELECT 1001, v FROM {table_name} WHERE id = 1"
+        ))
+        .unwrap();
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {table_name} a JOIN {table_name} b ON a.v_pq::INT[] = b.v_pq::INT[] AND a.v_ivf = b.v_ivf WHERE a.id = 1 AND b.id = 1001"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 1);
+
+    let evaluation = pq::evaluate::evaluate_pq(
+        &cli::PQEvaluateArgs {
+            uri: db_url.clone(),
+            table: table_name.clone(),
+            schema: "public".to_owned(),
+            column: "v".to_owned(),
+            pk: "id".to_owned(),
+            codebook_table_name: None,
+            splits: None,
+            clusters: 256,
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 20,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Cpu,
+            opq: false,
+            opq_iterations: 4,
+            coarse_clusters: None,
+            queries: 20,
+            k: 5,
+            dataset_limit: None,
+        },
+        None,
+    )
+    .unwrap();
+    assert_eq!(evaluation.coarse_clusters, Some(8));
+    assert!(evaluation.recall > 0.0);
+
+    db_client
+        .batch_execute(&format!("DROP TABLE IF EXISTS {coarse_table_name}"))
+        .unwrap();
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+}
+
+#[test]
+fn test_train_centroids() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = String::from("_pq_centroids_test");
+    let centroids_table_name = String::from("_pq_centroids_test_v_centroids");
+    let codebook_table_name = get_full_table_name("_lantern_internal", "pq__pq_centroids_test_v");
+    let coarse_table_name = get_full_table_name("_lantern_internal", "pq__pq_centroids_test_v_ivf");
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+    setup_db_tables(&mut db_client, &table_name, 1, 1000);
+    // Rows of setup_db_tables have the same vector, so they would be assigned to one list
+    db_client
+        .batch_execute(&format!(
+            "UPDATE {table_name} SET v = ARRAY(SELECT random() + id * 0 FROM generate_series(0, 128 - 1))"
+        ))
+        .unwrap();
+
+    pq::ivf::train_centroids(
+        &cli::PQTrainCentroidsArgs {
+            uri: db_url.clone(),
+            table: table_name.clone(),
+            schema: "public".to_owned(),
+            column: "v".to_owned(),
+            pk: "id".to_owned(),
+            clusters: 8,
+            centroids_table: None,
+            dataset_limit: Some(500),
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 20,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Cpu,
+            skip_assignment: false,
+        },
+        None,
+    )
+    .unwrap();
+
+    let centroids = pq::ivf::read_coarse_centroids(
+        &mut db_client,
+        &get_full_table_name("public", &centroids_table_name),
+    )
+    .unwrap()
+    .unwrap();
+    assert_eq!(centroids.len(), 8);
+    assert!(centroids.iter().all(|c| c.len() == 128));
+
+    // Each row is assigned to the list of the closest centroid
+    let cnt = db_client
+        .query_one(
+            &format!(
+                "SELECT COUNT(*) FROM {table_name} WHERE v_ivf IS NULL OR v_ivf < 0 OR v_ivf >= 8"
+            ),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 0);
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(DISTINCT v_ivf) FROM {table_name}"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert!(cnt > 1);
+
+    // Trained centroids are used as coarse centroids of IVF-PQ codebook
+    pq::quantize_table(
+        cli::PQArgs {
+            uri: db_url.clone(),
+            column: "v".to_owned(),
+            table: table_name.clone(),
+            schema: "public".to_owned(),
+            codebook_table_name: None,
+            clusters: 10,
+            splits: 16,
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 20,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Cpu,
+            kmeans_batch_size: None,
+            dataset_limit: None,
+            subvector_id: None,
+            opq: false,
+            opq_iterations: 4,
+            coarse_clusters: None,
+            coarse_centroids_table: Some(centroids_table_name.clone()),
+            import_codebook: None,
+            overwrite: false,
+            skip_table_setup: false,
+            skip_vector_quantization: false,
+            skip_codebook_creation: false,
+            pk: "id".to_owned(),
+            total_task_count: None,
+            parallel_task_count: None,
+            quantization_task_id: None,
+            watch: false,
+            run_on_gcp: false,
+            gcp_cli_image_tag: None,
+            gcp_project: None,
+            gcp_region: None,
+            gcp_image: None,
+            gcp_quantization_task_count: None,
+            gcp_quantization_task_parallelism: None,
+            gcp_clustering_task_parallelism: None,
+            gcp_enable_image_streaming: false,
+            gcp_clustering_cpu: None,
+            gcp_clustering_memory_gb: None,
+            gcp_quantization_cpu: None,
+            gcp_quantization_memory_gb: None,
+            dataset_size: None,
+            start_offset_id: None,
+        },
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let coarse_centroids = pq::ivf::read_coarse_centroids(&mut db_client, &coarse_table_name)
+        .unwrap()
+        .unwrap();
+    assert_eq!(coarse_centroids, centroids);
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {table_name} WHERE v_pq IS NULL OR v_ivf IS NULL"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 0);
+
+    let res = pq::ivf::train_centroids(
+        &cli::PQTrainCentroidsArgs::parse_from(&[
+            "",
+            "--uri",
+            &db_url,
+            "--table",
+            &table_name,
+            "--column",
+            "v",
+            "--clusters",
+            "8",
+            "--dataset-limit",
+            "4",
+        ]),
+        None,
+    );
+    assert!(res.is_err());
+
+    db_client
+        .batch_execute(&format!(
+            "DROP TABLE IF EXISTS {coarse_table_name}; DROP TABLE IF EXISTS {centroids_table_name};"
+        ))
+        .unwrap();
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+}
+
+#[test]
+fn test_u16_codes() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = String::from("_pq_u16_test");
+    let codebook_table_name = get_full_table_name("_lantern_internal", "pq__pq_u16_test_v");
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+    setup_db_tables(&mut db_client, &table_name, 1, 1000);
+
+    pq::quantize_table(
+        cli::PQArgs {
+            uri: db_url.clone(),
+            column: "v".to_owned(),
+            table: table_name.clone(),
+            schema: "public".to_owned(),
+            codebook_table_name: None,
+            clusters: 300,
+            splits: 32,
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 10,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Simd,
+            kmeans_batch_size: None,
+            dataset_limit: None,
+            subvector_id: None,
+            opq: false,
+            opq_iterations: 4,
+            coarse_clusters: None,
+            coarse_centroids_table: None,
+            import_codebook: None,
+            overwrite: false,
+            skip_table_setup: false,
+            skip_vector_quantization: false,
+            skip_codebook_creation: false,
+            pk: "id".to_owned(),
+            total_task_count: None,
+            parallel_task_count: None,
+            quantization_task_id: None,
+            watch: false,
+            run_on_gcp: false,
+            gcp_cli_image_tag: None,
+            gcp_project: None,
+            gcp_region: None,
+            gcp_image: None,
+            gcp_quantization_task_count: None,
+            gcp_quantization_task_parallelism: None,
+            gcp_clustering_task_parallelism: None,
+            gcp_enable_image_streaming: false,
+            gcp_clustering_cpu: None,
+            gcp_clustering_memory_gb: None,
+            gcp_quantization_cpu: None,
+            gcp_quantization_memory_gb: None,
+            dataset_size: None,
+            start_offset_id: None,
+        },
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    // Codebooks with more than 256 clusters are stored in INT2[] column
+    let column_type = db_client
+        .query_one(
+            &format!("SELECT format_type(atttypid, NULL) FROM pg_attribute WHERE attrelid = '{table_name}'::regclass AND attname = 'v_pq'"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, String>(0);
+    assert_eq!(column_type, "smallint[]");
+
+    let cnt = db_client
+        .query_one(&format!("SELECT COUNT(*) FROM {codebook_table_name}"), &[])
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 300 * 32);
+
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {table_name} WHERE ARRAY_LENGTH(v_pq, 1) != 32 OR v_pq IS NULL OR EXISTS (SELECT 1 FROM unnest(v_pq) code WHERE code < 0 OR code >= 300)"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 0);
+
+    // Trigger computes the same codes as quantization job
+    db_client
+        .batch_execute(&format!(
+            "INSERT INTO {table_name} (id, v) SELECT 1001, v FROM {table_name} WHERE id = 1"
+        ))
+        .unwrap();
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {table_name} a JOIN {table_name} b ON a.v_pq = b.v_pq WHERE a.id = 1 AND b.id = 1001"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 1);
+
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+}
+
+#[test]
+fn test_mini_batch_pq() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = String::from("_pq_mini_batch_test");
+    let codebook_table_name = get_full_table_name("_lantern_internal", "pq__pq_mini_batch_test_v");
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+    setup_db_tables(&mut db_client, &table_name, 1, 1000);
+
+    pq::quantize_table(
+        cli::PQArgs {
+            uri: db_url.clone(),
+            column: "v".to_owned(),
+            table: table_name.clone(),
+            schema: "public".to_owned(),
+            codebook_table_name: None,
+            clusters: 10,
+            splits: 32,
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 5,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Simd,
+            kmeans_batch_size: Some(100),
+            dataset_limit: None,
+            subvector_id: None,
+            opq: false,
+            opq_iterations: 4,
+            coarse_clusters: None,
+            coarse_centroids_table: None,
+            import_codebook: None,
+            overwrite: false,
+            skip_table_setup: false,
+            skip_vector_quantization: false,
+            skip_codebook_creation: false,
+            pk: "id".to_owned(),
+            total_task_count: None,
+            parallel_task_count: None,
+            quantization_task_id: None,
+            watch: false,
+            run_on_gcp: false,
+            gcp_cli_image_tag: None,
+            gcp_project: None,
+            gcp_region: None,
+            gcp_image: None,
+            gcp_quantization_task_count: None,
+            gcp_quantization_task_parallelism: None,
+            gcp_clustering_task_parallelism: None,
+            gcp_enable_image_streaming: false,
+            gcp_clustering_cpu: None,
+            gcp_clustering_memory_gb: None,
+            gcp_quantization_cpu: None,
+            gcp_quantization_memory_gb: None,
+            dataset_size: None,
+            start_offset_id: None,
+        },
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let cnt = db_client
+        .query_one(&format!("SELECT COUNT(*) FROM {codebook_table_name}"), &[])
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 320);
+
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {table_name} WHERE ARRAY_LENGTH(v_pq::INT[], 1) != 32 or v_pq is null"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 0);
+
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+}
+
+#[test]
+fn test_codebook_export_import() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = String::from("_pq_export_test");
+    let import_table_name = String::from("_pq_import_test");
+    let codebook_table_name = get_full_table_name("_lantern_internal", "pq__pq_export_test_v");
+    let import_codebook_table_name =
+        get_full_table_name("_lantern_internal", "pq__pq_import_test_v");
+    let rotation_table_name = get_full_table_name("_lantern_internal", "pq__pq_export_test_v_opq");
+    let import_rotation_table_name =
+        get_full_table_name("_lantern_internal", "pq__pq_import_test_v_opq");
+    let binary_path = env::temp_dir().join("_pq_export_test.bin");
+    let binary_path = binary_path.to_str().unwrap();
+    let json_path = env::temp_dir().join("_pq_export_test.json");
+    let json_path = json_path.to_str().unwrap();
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+    drop_db_tables(
+        &mut db_client,
+        &import_table_name,
+        &import_codebook_table_name,
+    );
+    setup_db_tables(&mut db_client, &table_name, 1, 1000);
+    db_client
+        .batch_execute(&format!(
+            "CREATE TABLE {import_table_name} (id SERIAL PRIMARY KEY, v REAL[]);
+             INSERT INTO {import_table_name} SELECT id, v FROM {table_name};"
+        ))
+        .unwrap();
+
+    pq::quantize_table(
+        cli::PQArgs {
+            uri: db_url.clone(),
+            column: "v".to_owned(),
+            table: table_name.clone(),
+            schema: "public".to_owned(),
+            codebook_table_name: None,
+            clusters: 10,
+            splits: 16,
+            kmeans_init: cli::KmeansInit::Kmeanspp,
+            kmeans_iters: 20,
+            kmeans_tolerance: 0.1,
+            accel: cli::Accel::Cpu,
+            kmeans_batch_size: None,
+            dataset_limit: None,
+            subvector_id: None,
+            opq: true,
+            opq_iterations: 2,
+            coarse_clusters: None,
+            coarse_centroids_table: None,
+            import_codebook: None,
+            overwrite: false,
+            skip_table_setup: false,
+            skip_vector_quantization: false,
+            skip_codebook_creation: false,
+            pk: "id".to_owned(),
+            total_task_count: None,
+            parallel_task_count: None,
+            quantization_task_id: None,
+            watch: false,
+            run_on_gcp: false,
+            gcp_cli_image_tag: None,
+            gcp_project: None,
+            gcp_region: None,
+            gcp_image: None,
+            gcp_quantization_task_count: None,
+            gcp_quantization_task_parallelism: None,
+            gcp_clustering_task_parallelism: None,
+            gcp_enable_image_streaming: false,
+            gcp_clustering_cpu: None,
+            gcp_clustering_memory_gb: None,
+            gcp_quantization_cpu: None,
+            gcp_quantization_memory_gb: None,
+            dataset_size: None,
+            start_offset_id: None,
+        },
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    for (path, format) in [
+        (binary_path, cli::CodebookFormat::Binary),
+        (json_path, cli::CodebookFormat::Json),
+    ] {
+        pq::codebook_file::export_codebook(
+            &cli::PQExportCodebookArgs {
+                uri: db_url.clone(),
+                table: Some(table_name.clone()),
+                column: Some("v".to_owned()),
+                codebook_table_name: None,
+                out: path.to_owned(),
+                format: Some(format),
+            },
+            None,
+        )
+        .unwrap();
+    }
+
+    // Both formats contain the same codebook and rotation
+    let binary_codebook = pq::codebook_file::CodebookFile::read(binary_path).unwrap();
+    let json_codebook = pq::codebook_file::CodebookFile::read(json_path).unwrap();
+    assert_eq!(binary_codebook, json_codebook);
+    assert_eq!(
+        binary_codebook.version,
+        pq::codebook_file::CODEBOOK_FILE_VERSION
+    );
+    assert_eq!(binary_codebook.splits, 16);
+    assert_eq!(binary_codebook.clusters, 10);
+    assert!(binary_codebook.rotation.is_some());
+
+    // Vectors quantized with imported codebook get the same codes
+    for file_path in [binary_path, json_path] {
+        pq::quantize_table(
+            cli::PQArgs {
+                uri: db_url.clone(),
+                column: "v".to_owned(),
+                table: import_table_name.clone(),
+                schema: "public".to_owned(),
+                codebook_table_name: None,
+                clusters: 10,
+                splits: 16,
+                kmeans_init: cli::KmeansInit::Kmeanspp,
+                kmeans_iters: 20,
+                kmeans_tolerance: 0.1,
+                accel: cli::Accel::Cpu,
+                kmeans_batch_size: None,
+                dataset_limit: None,
+                subvector_id: None,
+                opq: false,
+                opq_iterations: 2,
+                coarse_clusters: None,
+                coarse_centroids_table: None,
+                import_codebook: Some(file_path.to_owned()),
+                overwrite: true,
+                skip_table_setup: false,
+                skip_vector_quantization: false,
+                skip_codebook_creation: false,
+                pk: "id".to_owned(),
+                total_task_count: None,
+                parallel_task_count: None,
+                quantization_task_id: None,
+                watch: false,
+                run_on_gcp: false,
+                gcp_cli_image_tag: None,
+                gcp_project: None,
+                gcp_region: None,
+                gcp_image: None,
+                gcp_quantization_task_count: None,
+                gcp_quantization_task_parallelism: None,
+                gcp_clustering_task_parallelism: None,
+                gcp_enable_image_streaming: false,
+                gcp_clustering_cpu: None,
+                gcp_clustering_memory_gb: None,
+                gcp_quantization_cpu: None,
+                gcp_quantization_memory_gb: None,
+                dataset_size: None,
+                start_offset_id: None,
+            },
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let cnt = db_client
+            .query_one(
+                &format!("SELECT COUNT(*) FROM {table_name} a JOIN {import_table_name} b USING (id) WHERE a.v_pq::INT[] = b.v_pq::INT[]"),
+                &[],
+            )
+            .unwrap()
+            .get::<usize, i64>(0);
+        assert_eq!(cnt, 1000);
+    }
+
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {import_rotation_table_name}"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, i64>(0);
+    assert_eq!(cnt, 128);
+
+    db_client
+        .batch_execute(&format!(
+            "DROP TABLE IF EXISTS {rotation_table_name}; DROP TABLE IF EXISTS {import_rotation_table_name};"
+        ))
+        .unwrap();
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+    drop_db_tables(
+        &mut db_client,
+        &import_table_name,
+        &import_codebook_table_name,
+    );
+    std::fs::remove_file(binary_path).unwrap();
+    std::fs::remove_file(json_path).unwrap();
+}
+
+#[test]
+fn test_codebook_validation() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = String::from("_pq_validation_test");
+    let codebook_table_name = get_full_table_name("_lantern_internal", "pq__pq_validation_test_v");
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+    setup_db_tables(&mut db_client, &table_name, 1, 1000);
+
+    let pq_args = |splits: usize, skip_codebook_creation: bool| cli::PQArgs {
+        uri: db_url.clone(),
+        column: "v".to_owned(),
+        table: table_name.clone(),
+        schema: "public".to_owned(),
+        codebook_table_name: None,
+        clusters: 10,
+        splits,
+        kmeans_init: cli::KmeansInit::Kmeanspp,
+        kmeans_iters: 20,
+        kmeans_tolerance: 0.1,
+        accel: cli::Accel::Cpu,
+        kmeans_batch_size: None,
+        dataset_limit: None,
+        subvector_id: None,
+        opq: false,
+        opq_iterations: 4,
+        coarse_clusters: None,
+        coarse_centroids_table: None,
+        import_codebook: None,
+        overwrite: !skip_codebook_creation,
+        skip_table_setup: skip_codebook_creation,
+        skip_vector_quantization: false,
+        skip_codebook_creation,
+        pk: "id".to_owned(),
+        total_task_count: None,
+        parallel_task_count: None,
+        quantization_task_id: None,
+        watch: false,
+        run_on_gcp: false,
+        gcp_cli_image_tag: None,
+        gcp_project: None,
+        gcp_region: None,
+        gcp_image: None,
+        gcp_quantization_task_count: None,
+        gcp_quantization_task_parallelism: None,
+        gcp_clustering_task_parallelism: None,
+        gcp_enable_image_streaming: false,
+        gcp_clustering_cpu: None,
+        gcp_clustering_memory_gb: None,
+        gcp_quantization_cpu: None,
+        gcp_quantization_memory_gb: None,
+        dataset_size: None,
+        start_offset_id: None,
+    };
+
+    // 128 dimensions can not be split into 3 subvectors
+    let err = pq::quantize_table(pq_args(3, false), None, None, None).unwrap_err();
+    assert!(err.to_string().contains("should divide vector dimensions"));
+
+    pq::quantize_table(pq_args(16, false), None, None, None).unwrap();
+
+    // Codebook has 16 subvectors
+    let err = pq::quantize_table(pq_args(8, true), None, None, None).unwrap_err();
+    assert!(err.to_string().contains("Codebook has 16 subvectors"));
+
+    // Centroid ids should be dense
+    db_client
+        .batch_execute(&format!(
+            "ALTER TABLE {codebook_table_name} DISABLE TRIGGER readonly_guard;
+             DELETE FROM {codebook_table_name} WHERE subvector_id = 0 AND centroid_id = 3;"
+        ))
+        .unwrap();
+    let err = pq::quantize_table(pq_args(16, true), None, None, None).unwrap_err();
+    assert!(err.to_string().contains("without gaps or duplicates"));
+
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+}
+
+#[test]
+fn test_pending_ranges() {
+    let range = |start: &str, end: Option<&str>| keyset::KeyRange {
+        start: start.to_owned(),
+        end: end.map(|end| end.to_owned()),
+    };
+    let ranges = vec![
+        range("0", Some("30")),
+        range("30", Some("60")),
+        range("60", None),
+    ];
+    let committed_ranges = [
+        ("0".to_owned(), Some("30".to_owned())),
+        // Range with other bounds was committed by a run with another chunk size
+        ("30".to_owned(), Some("90".to_owned())),
+    ]
+    .into_iter()
+    .collect();
+    assert_eq!(
+        ledger::get_pending_ranges(ranges, &committed_ranges),
+        vec![range("30", Some("60")), range("60", None)]
+    );
+}
+
+#[test]
+fn test_quantization_resume() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = String::from("_pq_resume_test");
+    let codebook_table_name = get_full_table_name("_lantern_internal", "pq__pq_resume_test_v");
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+    setup_db_tables(&mut db_client, &table_name, 0, 999);
+    // Ids are sparse and do not start from 0, tasks are split by row positions
+    db_client
+        .batch_execute(&format!("UPDATE {table_name} SET id = id * 3 + 3000"))
+        .unwrap();
+
+    let pq_args = |extra_args: &[&str]| {
+        let mut args = vec![
+            "pq-table",
+            "--uri",
+            &db_url,
+            "--table",
+            &table_name,
+            "--column",
+            "v",
+            "--clusters",
+            "10",
+            "--splits",
+            "16",
+            "--accel",
+            "cpu",
+        ];
+        args.extend_from_slice(extra_args);
+        cli::PQArgs::parse_from(args)
+    };
+    let task_args = |task_id: &'static str| {
+        pq_args(&[
+            "--skip-table-setup",
+            "--skip-codebook-creation",
+            "--total-task-count",
+            "2",
+            "--quantization-task-id",
+            task_id,
+        ])
+    };
+
+    // Set up tables and create codebook without quantizing vectors
+    pq::quantize_table(pq_args(&["--skip-vector-quantization"]), None, None, None).unwrap();
+
+    // First 500 rows of the first task were committed by a failed run
+    db_client
+        .execute(
+            "INSERT INTO _lantern_internal.pq_quantization_ledger (codebook_table, range_start, range_end, task_id) VALUES ($1, '3000', '4500', 0)",
+            &[&codebook_table_name],
+        )
+        .unwrap();
+
+    pq::quantize_table(task_args("0"), None, None, None).unwrap();
+    // Rows deleted between task runs should not shift the range of the second task
+    db_client
+        .batch_execute(&format!("DELETE FROM {table_name} WHERE id < 3600"))
+        .unwrap();
+    pq::quantize_table(task_args("1"), None, None, None).unwrap();
+
+    let quantized_ranges = db_client
+        .query_one(
+            &format!(
+                "SELECT COUNT(*) FILTER (WHERE id < 4500 AND v_pq IS NOT NULL), COUNT(*) FILTER (WHERE id >= 4500 AND v_pq IS NULL) FROM {table_name}"
+            ),
+            &[],
+        )
+        .unwrap();
+    // Committed range is skipped and the rest of the table is quantized
+    assert_eq!(quantized_ranges.get::<usize, i64>(0), 0);
+    assert_eq!(quantized_ranges.get::<usize, i64>(1), 0);
+
+    let committed_ranges = db_client
+        .query(
+            "SELECT range_start, range_end FROM _lantern_internal.pq_quantization_ledger WHERE codebook_table = $1 ORDER BY task_id",
+            &[&codebook_table_name],
+        )
+        .unwrap();
+    let committed_ranges: Vec<(String, Option<String>)> = committed_ranges
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    assert_eq!(
+        committed_ranges,
+        vec![
+            ("3000".to_owned(), Some("4500".to_owned())),
+            ("4500".to_owned(), None)
+        ]
+    );
+
+    let task_ranges = db_client
+        .query(
+            "SELECT range_start, range_end FROM _lantern_internal.pq_quantization_task_ranges WHERE codebook_table = $1 ORDER BY task_id",
+            &[&codebook_table_name],
+        )
+        .unwrap();
+    let task_ranges: Vec<(String, Option<String>)> = task_ranges
+        .iter()
+        .map(|row| (row.get(0), row.get(1)))
+        .collect();
+    assert_eq!(task_ranges, committed_ranges);
+
+    // Setup job of a new run clears the ledger
+    pq::quantize_table(
+        pq_args(&[
+            "--overwrite",
+            "--skip-codebook-creation",
+            "--skip-vector-quantization",
+        ]),
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    let ranges = db_client
+        .query_one(
+            "SELECT COUNT(*) FROM _lantern_internal.pq_quantization_ledger WHERE codebook_table = $1",
+            &[&codebook_table_name],
+        )
+        .unwrap();
+    assert_eq!(ranges.get::<usize, i64>(0), 0);
+    let task_ranges = db_client
+        .query_one(
+            "SELECT COUNT(*) FROM _lantern_internal.pq_quantization_task_ranges WHERE codebook_table = $1",
+            &[&codebook_table_name],
+        )
+        .unwrap();
+    assert_eq!(task_ranges.get::<usize, i64>(0), 0);
+
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+}
+
+#[test]
+fn test_text_pk() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = String::from("_pq_text_pk_test");
+    let codebook_table_name = get_full_table_name("_lantern_internal", "pq__pq_text_pk_test_v");
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+    // Keys are not ordered by insertion and vector subquery depends on i to be run for each row
+    db_client
+        .batch_execute(&format!(
+            "
+    CREATE TABLE {table_name} (key TEXT PRIMARY KEY, v REAL[]);
+    INSERT INTO {table_name} SELECT md5(i::text), (select array_agg(random() * 1.0 + i * 0) from generate_series (0, 128 - 1)) FROM generate_series(1, 1000) i;
+"
+        ))
+        .unwrap();
+
+    let pq_args = |extra_args: &[&str]| {
+        let mut args = vec![
+            "pq-table",
+            "--uri",
+            &db_url,
+            "--table",
+            &table_name,
+            "--column",
+            "v",
+            "--pk",
+            "key",
+            "--clusters",
+            "10",
+            "--splits",
+            "16",
+            "--accel",
+            "cpu",
+        ];
+        args.extend_from_slice(extra_args);
+        cli::PQArgs::parse_from(args)
+    };
+
+    pq::quantize_table(pq_args(&["--skip-vector-quantization"]), None, None, None).unwrap();
+    for task_id in ["0", "1", "2"] {
+        pq::quantize_table(
+            pq_args(&[
+                "--skip-table-setup",
+                "--skip-codebook-creation",
+                "--total-task-count",
+                "3",
+                "--quantization-task-id",
+                task_id,
+            ]),
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+    }
+
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM {table_name} WHERE v_pq IS NULL"),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(cnt.get::<usize, i64>(0), 0);
+
+    drop_db_tables(&mut db_client, &table_name, &codebook_table_name);
+}
@@ -0,0 +1,67 @@
+use lantern_cli::embeddings::report::{BatchLatencies, JobReport, LatencySummary};
+use std::time::Duration;
+
+fn millis(values: impl Iterator<Item = u64>) -> Vec<Duration> {
+    values.map(Duration::from_millis).collect()
+}
+
+#[test]
+fn test_latency_summary() {
+    // Latencies are sorted before the percentiles are taken
+    let summary = LatencySummary::new(&millis((1..=20).rev()));
+    assert_eq!(summary.batches, 20);
+    assert_eq!(summary.avg, Duration::from_micros(10_500));
+    assert_eq!(summary.median, Duration::from_millis(10));
+    assert_eq!(summary.p95, Duration::from_millis(19));
+    assert_eq!(summary.to_string(), "10.5/10.0/19.0 ms");
+
+    let summary = LatencySummary::new(&millis([7].into_iter()));
+    assert_eq!(summary.median, Duration::from_millis(7));
+    assert_eq!(summary.p95, Duration::from_millis(7));
+
+    assert_eq!(LatencySummary::new(&[]), LatencySummary::default());
+}
+
+#[test]
+fn test_job_report() {
+    let mut report = JobReport {
+        processed_rows: 1000,
+        processed_tokens: 5000,
+        wall_time: Duration::from_secs(4),
+        latencies: BatchLatencies {
+            fetch: millis([5, 5].into_iter()),
+            embed: millis([100, 300].into_iter()),
+            export: millis([20, 40].into_iter()),
+        },
+    };
+    assert_eq!(report.rows_per_sec(), 250.0);
+    assert_eq!(
+        report.to_string(),
+        "Job report: 1000 rows, 5000 tokens in 4.0s (250.0 rows/s), fetch avg/median/p95 5.0/5.0/5.0 ms (2 batches), embed avg/median/p95 200.0/100.0/300.0 ms (2 batches), export avg/median/p95 30.0/20.0/40.0 ms (2 batches)"
+    );
+
+    report.merge(JobReport {
+        processed_rows: 1000,
+        processed_tokens: 1000,
+        wall_time: Duration::from_secs(1),
+        latencies: BatchLatencies {
+            fetch: Vec::new(),
+            embed: millis([200].into_iter()),
+            export: Vec::new(),
+        },
+    });
+    assert_eq!(report.processed_rows, 2000);
+    assert_eq!(report.processed_tokens, 6000);
+    assert_eq!(report.rows_per_sec(), 400.0);
+    assert_eq!(report.embed().batches, 3);
+    assert_eq!(report.embed().median, Duration::from_millis(200));
+
+    // Reports of the jobs which did not run the pipeline have only the counts
+    let report = JobReport::new(0, 1200);
+    assert_eq!(report.rows_per_sec(), 0.0);
+    assert_eq!(report.embed(), LatencySummary::default());
+    assert_eq!(
+        report.to_string(),
+        "Job report: 0 rows, 1200 tokens in 0.0s (0.0 rows/s)"
+    );
+}
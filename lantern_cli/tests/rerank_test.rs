@@ -0,0 +1,108 @@
+use std::env;
+use std::time::Duration;
+
+use lantern_cli::embeddings::cli::{RerankArgs, Runtime};
+use lantern_cli::embeddings::rerank::rerank;
+use lantern_cli::mock_provider::{self, cli::MockProviderArgs};
+
+fn start_mock_provider(port: u16) {
+    std::thread::spawn(move || {
+        mock_provider::start(
+            MockProviderArgs {
+                host: "127.0.0.1".to_owned(),
+                port,
+                dimensions: 16,
+                latency_ms: 0,
+                api_key: Some("test".to_owned()),
+                rate_limit_every: None,
+            },
+            None,
+        )
+        .expect("Failed to start mock provider");
+    });
+
+    for _ in 0..50 {
+        if isahc::get(format!("http://127.0.0.1:{port}/health")).is_ok() {
+            return;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    panic!("Mock provider did not start");
+}
+
+#[test]
+fn test_rerank_jsonl() {
+    let port = 8786;
+    start_mock_provider(port);
+
+    let path = env::temp_dir().join("_rerank_test.jsonl");
+    let path = path.to_str().unwrap().to_owned();
+    std::fs::write(
+        &path,
+        "{\"id\": 1, \"text\": \"Lantern is a Postgres extension\"}\n\
+         {\"id\": 2, \"text\": \"Vector search in Postgres\"}\n\
+         \n\
+         {\"id\": 3, \"text\": \"Cats are cute\"}\n\
+         {\"id\": 4, \"text\": \"What is Lantern?\"}\n",
+    )
+    .unwrap();
+
+    let args = RerankArgs {
+        query: "What is Lantern?".to_owned(),
+        model: "cohere/rerank-english-v3.0".to_owned(),
+        runtime: Runtime::Cohere,
+        runtime_params: format!(
+            "{{\"api_token\": \"test\", \"base_url\": \"http://127.0.0.1:{port}\"}}"
+        ),
+        uri: None,
+        sql: None,
+        in_jsonl: Some(path.clone()),
+        text_column: "text".to_owned(),
+        top_k: None,
+        batch_size: 3,
+    };
+
+    let results = rerank(&args, None).unwrap();
+    assert_eq!(results.len(), 4);
+    // Identical text has the highest score
+    assert_eq!(results[0]["id"], 4);
+    assert!((results[0]["score"].as_f64().unwrap() - 1.0).abs() < 1e-4);
+    let scores: Vec<f64> = results
+        .iter()
+        .map(|r| r["score"].as_f64().unwrap())
+        .collect();
+    assert!(scores.windows(2).all(|w| w[0] >= w[1]));
+
+    let results = rerank(
+        &RerankArgs {
+            top_k: Some(2),
+            ..args.clone()
+        },
+        None,
+    )
+    .unwrap();
+    assert_eq!(results.len(), 2);
+    assert_eq!(results[0]["id"], 4);
+
+    let err = rerank(
+        &RerankArgs {
+            text_column: "content".to_owned(),
+            ..args.clone()
+        },
+        None,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("'content'"));
+
+    let err = rerank(
+        &RerankArgs {
+            runtime: Runtime::OpenAi,
+            ..args
+        },
+        None,
+    )
+    .unwrap_err();
+    assert!(err.to_string().contains("does not support reranking"));
+
+    std::fs::remove_file(&path).unwrap();
+}
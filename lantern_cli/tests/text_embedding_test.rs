@@ -1,4 +1,10 @@
-use lantern_cli::embeddings::core::{get_runtime, Runtime};
+use lantern_cli::embeddings::core::{
+    bedrock_runtime, get_available_runtimes, get_runtime, resolve_api_key,
+    runtime::{Modality, ModelInfo},
+    tei_runtime, Runtime,
+};
+use lantern_cli::embeddings::{get_model_listings, get_runtime_listings};
+use lantern_cli::utils::redact_secrets;
 
 static HELLO_WORLD_TEXT: &'static str = "Hello world!";
 #[rustfmt::skip]
@@ -133,3 +139,653 @@ text_embedding_test_multiple! {
   generate_jina_base_embeddings_large_text_batch_multiple: ("jinaai/jina-embeddings-v2-base-en", PATIENT_EMB_TEXT, WEATHER_TEXT, PATIENT_JINA_BASE_EMB.to_vec(), WEATHER_JINA_BASE_EMB.to_vec(), 10, 1380),
   generate_bge_m3_embeddings_large_text_batch_multiple: ("BAAI/bge-m3", PATIENT_EMB_TEXT, WEATHER_TEXT, PATIENT_BGE_M3_EMB.to_vec(), BGE_M3_WEATHER_EMB.to_vec(), 10, 1430),
 }
+
+#[test]
+fn test_cohere_runtime_params_validation() {
+    assert!(get_runtime(
+        &Runtime::Cohere,
+        None,
+        r#"{"api_token": "xxx", "input_type": "search_query", "truncate": "START"}"#
+    )
+    .is_ok());
+    assert!(get_runtime(
+        &Runtime::Cohere,
+        None,
+        r#"{"api_token": "xxx", "input_type": "invalid"}"#
+    )
+    .is_err());
+    assert!(get_runtime(
+        &Runtime::Cohere,
+        None,
+        r#"{"api_token": "xxx", "truncate": "MIDDLE"}"#
+    )
+    .is_err());
+}
+
+#[test]
+fn test_openai_dimensions_validation() {
+    let runtime = get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"api_token": "xxx", "dimensions": 4000}"#,
+    )
+    .unwrap();
+    assert!(runtime
+        .process("openai/text-embedding-3-small", &vec![HELLO_WORLD_TEXT])
+        .is_err_and(|e| e.to_string().contains("between 1 and 1536")));
+
+    let runtime = get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"api_token": "xxx", "dimensions": 512}"#,
+    )
+    .unwrap();
+    assert!(runtime
+        .process("openai/text-embedding-ada-002", &vec![HELLO_WORLD_TEXT])
+        .is_err_and(|e| e.to_string().contains("does not support custom dimensions")));
+}
+
+#[test]
+fn test_runtime_region() {
+    let runtime = get_runtime(&Runtime::OpenAi, None, r#"{"api_token": "xxx"}"#).unwrap();
+    assert_eq!(runtime.get_region(), Some("us".to_owned()));
+
+    let runtime = get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"api_token": "xxx", "region": "eu"}"#,
+    )
+    .unwrap();
+    assert_eq!(runtime.get_region(), Some("eu".to_owned()));
+
+    assert!(get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"api_token": "xxx", "region": "mars"}"#
+    )
+    .is_err());
+
+    let runtime = get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"azure_api_token": "xxx", "base_url": "https://test.openai.azure.com/openai/deployments/test/embeddings?api-version=2023-05-15"}"#,
+    )
+    .unwrap();
+    assert_eq!(runtime.get_region(), None);
+
+    assert!(get_runtime(
+        &Runtime::Cohere,
+        None,
+        r#"{"api_token": "xxx", "region": "eu"}"#
+    )
+    .is_err());
+
+    let runtime = get_runtime(
+        &Runtime::Cohere,
+        None,
+        r#"{"api_token": "xxx", "region": "eu", "base_url": "https://cohere.example.eu"}"#,
+    )
+    .unwrap();
+    assert_eq!(runtime.get_region(), Some("eu".to_owned()));
+}
+
+#[test]
+fn test_runtime_model_info() {
+    let runtime = get_runtime(&Runtime::OpenAi, None, r#"{"api_token": "xxx"}"#).unwrap();
+    assert_eq!(
+        runtime.get_model_info("openai/text-embedding-3-small"),
+        Some(ModelInfo {
+            name: "openai/text-embedding-3-small".to_owned(),
+            dims: Some(1536),
+            max_tokens: Some(8190),
+            modality: Modality::Text,
+            provider: "openai".to_owned(),
+        })
+    );
+    assert_eq!(runtime.get_model_info("openai/unknown"), None);
+    let models = runtime.get_models_info();
+    assert_eq!(models.len(), 3);
+    assert!(models.windows(2).all(|w| w[0].name < w[1].name));
+
+    // Dimensions from runtime params are reported for the models
+    let runtime = get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"api_token": "xxx", "dimensions": 256}"#,
+    )
+    .unwrap();
+    assert_eq!(
+        runtime
+            .get_model_info("openai/text-embedding-3-large")
+            .and_then(|info| info.dims),
+        Some(256)
+    );
+
+    let runtime = get_runtime(
+        &Runtime::Ort,
+        None,
+        r#"{"data_path": "/tmp/lantern-embeddings-core-test"}"#,
+    )
+    .unwrap();
+    let info = runtime.get_model_info("clip/ViT-B-32-visual").unwrap();
+    assert_eq!(info.dims, Some(512));
+    assert_eq!(info.modality, Modality::Image);
+    assert_eq!(info.provider, "ort");
+    let info = runtime.get_model_info("BAAI/bge-small-en").unwrap();
+    assert_eq!((info.dims, info.max_tokens), (Some(384), Some(512)));
+    // Rerankers do not produce embeddings
+    assert_eq!(runtime.get_model_info("BAAI/bge-reranker-base"), None);
+
+    let runtime = get_runtime(
+        &Runtime::Tei,
+        None,
+        r#"{"base_url": "http://localhost:8080"}"#,
+    )
+    .unwrap();
+    let info = runtime.get_model_info("any-model").unwrap();
+    assert_eq!(info.dims, None);
+    assert_eq!(info.provider, "tei");
+    assert!(runtime.get_models_info().is_empty());
+
+    let json = serde_json::to_value(&info).unwrap();
+    assert_eq!(json["modality"], "text");
+    assert!(json["dims"].is_null());
+}
+
+#[test]
+fn test_model_and_runtime_listings() {
+    let models = get_model_listings(
+        &Runtime::Ort,
+        r#"{"data_path": "/tmp/lantern-embeddings-core-test"}"#,
+    )
+    .unwrap();
+    let json = serde_json::to_value(&models).unwrap();
+    let model = json
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|m| m["name"] == "BAAI/bge-small-en")
+        .unwrap();
+    assert_eq!(model["dims"], 384);
+    assert_eq!(model["modality"], "text");
+    assert_eq!(model["provider"], "ort");
+    assert_eq!(model["default_batch_size"], 300);
+    assert_eq!(model["requires_api_key"], false);
+
+    let models = get_model_listings(&Runtime::Cohere, r#"{"api_token": "xxx"}"#).unwrap();
+    assert!(models.iter().all(|m| m.requires_api_key));
+
+    let runtimes = get_runtime_listings();
+    assert_eq!(runtimes.len(), get_available_runtimes().len());
+    let openai = runtimes.iter().find(|r| r.name == "openai").unwrap();
+    assert!(openai.requires_api_key);
+    let tei = runtimes.iter().find(|r| r.name == "tei").unwrap();
+    assert!(!tei.requires_api_key);
+}
+
+#[test]
+fn test_runtime_api_key_params() {
+    std::env::set_var("_LANTERN_TEST_OPENAI_KEY", "sk-test-env-key-1234");
+    assert!(get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"api_key_env": "_LANTERN_TEST_OPENAI_KEY"}"#
+    )
+    .is_ok());
+    assert!(get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"api_key_env": "_LANTERN_TEST_OPENAI_KEY_MISSING"}"#
+    )
+    .is_err_and(|e| e.to_string().contains("is not set")));
+    assert!(get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"api_token": "xxx", "api_key_env": "_LANTERN_TEST_OPENAI_KEY"}"#
+    )
+    .is_err());
+    assert!(get_runtime(
+        &Runtime::Bedrock,
+        None,
+        r#"{"api_key_env": "_LANTERN_TEST_OPENAI_KEY"}"#
+    )
+    .is_err_and(|e| e.to_string().contains("does not accept")));
+
+    let key_path = std::env::temp_dir().join("_lantern_api_key_test");
+    std::fs::write(&key_path, "sk-test-file-key-5678\n").unwrap();
+    let params = resolve_api_key(
+        &Runtime::Tei,
+        &format!(
+            r#"{{"base_url": "http://localhost:8080", "api_key_file": "{}"}}"#,
+            key_path.display()
+        ),
+    )
+    .unwrap();
+    let params: serde_json::Value = serde_json::from_str(&params).unwrap();
+    assert_eq!(params["api_key"], "sk-test-file-key-5678");
+    assert!(params.get("api_key_file").is_none());
+    assert!(resolve_api_key(
+        &Runtime::Tei,
+        r#"{"api_key_env": "_LANTERN_TEST_OPENAI_KEY", "api_key_file": "/run/secrets/key"}"#
+    )
+    .is_err());
+    std::fs::remove_file(&key_path).unwrap();
+
+    // Keys read from environment and files are masked in logs
+    assert_eq!(
+        redact_secrets("Request failed with key sk-test-file-key-5678"),
+        "Request failed with key ***"
+    );
+}
+
+#[test]
+fn test_azure_openai_runtime_params() {
+    assert!(get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"azure_api_token": "xxx", "azure_endpoint": "https://test.openai.azure.com/", "deployment": "emb", "api-version": "2024-02-01"}"#
+    )
+    .is_ok());
+    // deployment is required
+    assert!(get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"azure_api_token": "xxx", "azure_endpoint": "https://test.openai.azure.com"}"#
+    )
+    .is_err());
+    // endpoint should be azure openai endpoint
+    assert!(get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"azure_api_token": "xxx", "azure_endpoint": "https://example.com", "deployment": "emb"}"#
+    )
+    .is_err());
+    // azure token is required
+    assert!(get_runtime(
+        &Runtime::OpenAi,
+        None,
+        r#"{"api_token": "xxx", "azure_endpoint": "https://test.openai.azure.com", "deployment": "emb"}"#
+    )
+    .is_err());
+}
+
+#[test]
+fn test_vertex_runtime_params_validation() {
+    assert!(get_runtime(&Runtime::Vertex, None, r#"{"access_token": "xxx"}"#).is_err());
+    assert!(get_runtime(
+        &Runtime::Vertex,
+        None,
+        r#"{"project_id": "test", "access_token": "xxx", "task_type": "invalid"}"#
+    )
+    .is_err());
+
+    let runtime = get_runtime(
+        &Runtime::Vertex,
+        None,
+        r#"{"project_id": "test", "access_token": "xxx", "task_type": "RETRIEVAL_QUERY", "location": "europe-west4"}"#,
+    )
+    .unwrap();
+    assert_eq!(runtime.get_region(), Some("europe-west4".to_owned()));
+    assert!(runtime
+        .get_available_models()
+        .1
+        .iter()
+        .any(|(model, _)| model == "vertex/text-embedding-004"));
+    assert!(runtime
+        .process("vertex/unknown-model", &vec![HELLO_WORLD_TEXT])
+        .is_err_and(|e| e.to_string().contains("Unsupported model")));
+
+    let runtime = get_runtime(
+        &Runtime::Vertex,
+        None,
+        r#"{"project_id": "test", "access_token": "xxx", "dimensions": 256}"#,
+    )
+    .unwrap();
+    assert!(runtime
+        .process("vertex/textembedding-gecko@003", &vec![HELLO_WORLD_TEXT])
+        .is_err_and(|e| e.to_string().contains("does not support custom dimensions")));
+}
+
+#[test]
+fn test_bedrock_sigv4_signature() {
+    // "get-vanilla" example from AWS Signature Version 4 test suite
+    let credentials = bedrock_runtime::AwsCredentials {
+        access_key_id: "AKIDEXAMPLE".to_owned(),
+        secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_owned(),
+        session_token: None,
+    };
+    let authorization = bedrock_runtime::sign_request(
+        &credentials,
+        "us-east-1",
+        "service",
+        "GET",
+        "/",
+        &[
+            ("Host".to_owned(), "example.amazonaws.com".to_owned()),
+            ("X-Amz-Date".to_owned(), "20150830T123600Z".to_owned()),
+        ],
+        "",
+    )
+    .unwrap();
+
+    assert_eq!(authorization, "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, SignedHeaders=host;x-amz-date, Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31");
+}
+
+#[test]
+fn test_bedrock_runtime_params_validation() {
+    assert!(get_runtime(
+        &Runtime::Bedrock,
+        None,
+        r#"{"region": "us-east-1", "access_key_id": "xxx"}"#
+    )
+    .is_err());
+    assert!(get_runtime(
+        &Runtime::Bedrock,
+        None,
+        r#"{"region": "us-east-1", "access_key_id": "xxx", "secret_access_key": "xxx", "input_type": "invalid"}"#
+    )
+    .is_err());
+
+    let runtime = get_runtime(
+        &Runtime::Bedrock,
+        None,
+        r#"{"region": "eu-central-1", "access_key_id": "xxx", "secret_access_key": "xxx", "dimensions": 300}"#,
+    )
+    .unwrap();
+    assert_eq!(runtime.get_region(), Some("eu-central-1".to_owned()));
+    assert!(runtime
+        .get_available_models()
+        .1
+        .iter()
+        .any(|(model, _)| model == "bedrock/amazon.titan-embed-text-v2:0"));
+    assert!(runtime
+        .process(
+            "bedrock/amazon.titan-embed-text-v2:0",
+            &vec![HELLO_WORLD_TEXT]
+        )
+        .is_err_and(|e| e.to_string().contains("does not support 300 dimensions")));
+}
+
+#[test]
+fn test_voyage_mistral_runtime_params_validation() {
+    assert!(get_runtime(&Runtime::Voyage, None, r#"{}"#).is_err());
+    assert!(get_runtime(
+        &Runtime::Voyage,
+        None,
+        r#"{"api_token": "xxx", "input_type": "invalid"}"#
+    )
+    .is_err());
+    let runtime = get_runtime(
+        &Runtime::Voyage,
+        None,
+        r#"{"api_token": "xxx", "input_type": "query", "truncation": false}"#,
+    )
+    .unwrap();
+    assert_eq!(runtime.get_region(), Some("us".to_owned()));
+    assert!(runtime
+        .get_available_models()
+        .1
+        .contains(&("voyage/voyage-code-2".to_owned(), false)));
+
+    assert!(get_runtime(&Runtime::Mistral, None, r#"{}"#).is_err());
+    assert!(get_runtime(
+        &Runtime::Mistral,
+        None,
+        r#"{"api_token": "xxx", "region": "us"}"#
+    )
+    .is_err());
+    let runtime = get_runtime(&Runtime::Mistral, None, r#"{"api_token": "xxx"}"#).unwrap();
+    assert_eq!(runtime.get_region(), Some("eu".to_owned()));
+    assert!(runtime
+        .process("mistral/unknown", &vec![HELLO_WORLD_TEXT])
+        .is_err_and(|e| e.to_string().contains("Unsupported model")));
+}
+
+#[test]
+fn test_jina_nomic_runtime_params_validation() {
+    assert!(get_runtime(&Runtime::Jina, None, r#"{}"#).is_err());
+    assert!(get_runtime(
+        &Runtime::Jina,
+        None,
+        r#"{"api_token": "xxx", "task": "invalid"}"#
+    )
+    .is_err());
+    assert!(get_runtime(
+        &Runtime::Jina,
+        None,
+        r#"{"api_token": "xxx", "dimensions": 2048}"#
+    )
+    .is_err());
+    assert!(get_runtime(
+        &Runtime::Jina,
+        None,
+        r#"{"api_token": "xxx", "region": "eu"}"#
+    )
+    .is_err());
+    let runtime = get_runtime(
+        &Runtime::Jina,
+        None,
+        r#"{"api_token": "xxx", "task": "retrieval.query", "dimensions": 256}"#,
+    )
+    .unwrap();
+    assert_eq!(runtime.get_region(), None);
+
+    assert!(get_runtime(&Runtime::Nomic, None, r#"{}"#).is_err());
+    assert!(get_runtime(
+        &Runtime::Nomic,
+        None,
+        r#"{"api_token": "xxx", "task_type": "invalid"}"#
+    )
+    .is_err());
+    assert!(get_runtime(
+        &Runtime::Nomic,
+        None,
+        r#"{"api_token": "xxx", "dimensions": 32}"#
+    )
+    .is_err());
+    let runtime = get_runtime(
+        &Runtime::Nomic,
+        None,
+        r#"{"api_token": "xxx", "task_type": "search_query", "dimensionality": 256}"#,
+    )
+    .unwrap();
+    assert_eq!(runtime.get_region(), Some("us".to_owned()));
+    assert!(runtime
+        .get_available_models()
+        .1
+        .contains(&("nomic/nomic-embed-text-v1.5".to_owned(), false)));
+}
+
+#[test]
+fn test_openai_compat_runtime_params_validation() {
+    assert!(get_runtime(&Runtime::OpenAiCompat, None, r#"{}"#).is_err());
+    assert!(get_runtime(
+        &Runtime::OpenAiCompat,
+        None,
+        r#"{"base_url": "ftp://localhost:8000"}"#
+    )
+    .is_err());
+    assert!(get_runtime(
+        &Runtime::OpenAiCompat,
+        None,
+        r#"{"base_url": "http://localhost:8000", "max_batch_size": 0}"#
+    )
+    .is_err());
+
+    let runtime = get_runtime(
+        &Runtime::OpenAiCompat,
+        None,
+        r#"{"base_url": "http://localhost:8000/v1/", "api_key": "xxx", "model": "BAAI/bge-m3"}"#,
+    )
+    .unwrap();
+    let (models_str, models) = runtime.get_available_models();
+    assert!(models_str.contains("http://localhost:8000/v1/embeddings"));
+    assert_eq!(models, vec![("BAAI/bge-m3".to_owned(), false)]);
+    assert_eq!(runtime.get_region(), None);
+}
+
+#[test]
+fn test_tei_runtime_params_validation() {
+    assert!(get_runtime(&Runtime::Tei, None, r#"{}"#).is_err());
+    assert!(get_runtime(&Runtime::Tei, None, r#"{"base_url": "ftp://localhost"}"#).is_err());
+    assert!(get_runtime(
+        &Runtime::Tei,
+        None,
+        r#"{"base_url": "http://localhost:8080", "protocol": "websocket"}"#
+    )
+    .is_err());
+    assert!(get_runtime(
+        &Runtime::Tei,
+        None,
+        r#"{"base_url": "http://localhost:8080", "truncation_direction": "middle"}"#
+    )
+    .is_err());
+    assert!(get_runtime(
+        &Runtime::Tei,
+        None,
+        r#"{"base_url": "http://localhost:8080", "max_batch_size": 0}"#
+    )
+    .is_err());
+
+    let runtime = get_runtime(
+        &Runtime::Tei,
+        None,
+        r#"{"base_url": "http://localhost:8080", "protocol": "grpc", "truncation_direction": "left", "region": "eu"}"#,
+    )
+    .unwrap();
+    let (models_str, models) = runtime.get_available_models();
+    assert!(models_str.contains("http://localhost:8080"));
+    assert!(models.is_empty());
+    assert_eq!(runtime.get_region(), Some("eu".to_owned()));
+}
+
+#[test]
+fn test_tei_grpc_messages() {
+    let frame = tei_runtime::encode_embed_request("hi", true, true, "left", Some("query"));
+    #[rustfmt::skip]
+    assert_eq!(
+        frame,
+        vec![
+            0, 0, 0, 0, 17,
+            0x0A, 2, b'h', b'i',
+            0x10, 1,
+            0x18, 1,
+            0x20, 1,
+            0x2A, 5, b'q', b'u', b'e', b'r', b'y',
+        ]
+    );
+    // Default values are omitted from proto3 messages
+    assert_eq!(
+        tei_runtime::encode_embed_request("hi", false, false, "right", None),
+        vec![0, 0, 0, 0, 4, 0x0A, 2, b'h', b'i']
+    );
+
+    // EmbedResponse with packed embeddings [1.0, -0.5] and metadata { compute_chars: 5, compute_tokens: 3 }
+    let mut message = vec![0x0A, 8];
+    message.extend_from_slice(&1.0f32.to_le_bytes());
+    message.extend_from_slice(&(-0.5f32).to_le_bytes());
+    message.extend_from_slice(&[0x12, 4, 0x08, 5, 0x10, 3]);
+    let mut body = Vec::new();
+    for _ in 0..2 {
+        body.push(0);
+        body.extend_from_slice(&(message.len() as u32).to_be_bytes());
+        body.extend_from_slice(&message);
+    }
+
+    let res = tei_runtime::decode_embed_responses(&body).unwrap();
+    assert_eq!(res.embeddings, vec![vec![1.0, -0.5], vec![1.0, -0.5]]);
+    assert_eq!(res.processed_tokens, 6);
+
+    assert!(tei_runtime::decode_embed_responses(&body[..body.len() - 1]).is_err());
+}
+
+#[test]
+fn test_ort_runtime_device_params() {
+    use lantern_cli::embeddings::core::ort_runtime::Device;
+    use std::str::FromStr;
+
+    assert_eq!(Device::from_str("cpu").unwrap(), Device::Cpu);
+    assert_eq!(Device::from_str("cuda").unwrap(), Device::Cuda(0));
+    assert_eq!(Device::from_str("CUDA:1").unwrap(), Device::Cuda(1));
+    assert_eq!(Device::from_str("rocm:2").unwrap(), Device::Rocm(2));
+    assert_eq!(Device::from_str("directml").unwrap(), Device::DirectMl(0));
+    assert_eq!(Device::from_str("coreml").unwrap(), Device::CoreMl);
+    assert_eq!(Device::Cuda(3).to_string(), "cuda:3");
+    assert!(Device::from_str("cuda:x").is_err());
+    assert!(Device::from_str("cpu:1").is_err());
+    assert!(Device::from_str("tpu").is_err());
+
+    assert!(get_runtime(&Runtime::Ort, None, r#"{"device": "tpu"}"#).is_err());
+    assert!(get_runtime(
+        &Runtime::Ort,
+        None,
+        r#"{"device": "cuda:0", "fallback": false}"#
+    )
+    .is_ok());
+}
+
+#[test]
+fn test_ort_runtime_hf_models() {
+    use lantern_cli::embeddings::core::ort_runtime::HuggingFaceModel;
+
+    let model = HuggingFaceModel::parse("hf:sentence-transformers/all-MiniLM-L6-v2").unwrap();
+    assert_eq!(model.repo_id, "sentence-transformers/all-MiniLM-L6-v2");
+    assert_eq!(model.revision, "main");
+    assert_eq!(
+        model.get_folder("/tmp/data"),
+        std::path::PathBuf::from("/tmp/data/hf/sentence-transformers/all-MiniLM-L6-v2/main")
+    );
+
+    let model = HuggingFaceModel::parse("hf:BAAI/bge-small-en-v1.5@5c38ec7").unwrap();
+    assert_eq!(model.repo_id, "BAAI/bge-small-en-v1.5");
+    assert_eq!(model.revision, "5c38ec7");
+
+    assert!(HuggingFaceModel::parse("BAAI/bge-small-en").is_err());
+    assert!(HuggingFaceModel::parse("hf:bge-small-en").is_err());
+    assert!(HuggingFaceModel::parse("hf:BAAI/bge/small").is_err());
+    assert!(HuggingFaceModel::parse("hf:../bge-small-en").is_err());
+    assert!(HuggingFaceModel::parse("hf:BAAI/bge-small-en@").is_err());
+    assert!(HuggingFaceModel::parse("hf:BAAI/bge-small-en@..").is_err());
+
+    let runtime = get_runtime(
+        &Runtime::Ort,
+        None,
+        r#"{"hf_endpoint": "http://127.0.0.1:1", "data_path": "/tmp/lantern-embeddings-core-test"}"#,
+    )
+    .unwrap();
+    assert!(runtime
+        .process("hf:org", &vec![HELLO_WORLD_TEXT])
+        .is_err_and(|e| e.to_string().contains("Expected format")));
+    assert!(runtime
+        .process("hf:org/model", &vec![HELLO_WORLD_TEXT])
+        .is_err_and(|e| e.to_string().contains("Failed to fetch model info")));
+}
+
+#[test]
+fn test_ort_runtime_quantized_models() {
+    let data_path = "/tmp/lantern-embeddings-core-test-quantized";
+    let model_folder = format!("{data_path}/hf/org/quantized-model/main");
+    std::fs::create_dir_all(&model_folder).unwrap();
+    std::fs::write(
+        format!("{model_folder}/files.json"),
+        r#"["onnx/model.onnx", "onnx/model_qint8_avx512.onnx", "tokenizer.json"]"#,
+    )
+    .unwrap();
+
+    let params = format!(
+        r#"{{"quantized": true, "hf_endpoint": "http://127.0.0.1:1", "data_path": "{data_path}"}}"#
+    );
+    let runtime = get_runtime(&Runtime::Ort, None, &params).unwrap();
+
+    // Model info is registered from cached files list, download fails as endpoint is not reachable
+    assert!(runtime
+        .process("hf:org/quantized-model", &vec![HELLO_WORLD_TEXT])
+        .is_err());
+
+    let (models, _) = runtime.get_available_models();
+    assert!(models
+        .contains("hf:org/quantized-model - type: textual, downloaded: false, quantized: true"));
+    assert!(models.contains("BAAI/bge-small-en - type: textual"));
+
+    std::fs::remove_dir_all(data_path).unwrap();
+}
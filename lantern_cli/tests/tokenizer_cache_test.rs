@@ -0,0 +1,85 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use lantern_cli::embeddings::core::runtime::DEFAULT_TOKENIZER;
+use lantern_cli::embeddings::core::tokenizer_cache::{
+    get_cl100k_base, TokenizerCache, TIKTOKEN_CACHE,
+};
+
+#[test]
+fn test_tokenizer_cache_lazy_load() {
+    let cache: Arc<TokenizerCache<String>> = Arc::new(TokenizerCache::new(4));
+    let load_cnt = Arc::new(AtomicUsize::new(0));
+
+    // Workers requesting the same model share one instance
+    let handles: Vec<_> = (0..8)
+        .map(|_| {
+            let cache = cache.clone();
+            let load_cnt = load_cnt.clone();
+            std::thread::spawn(move || {
+                cache
+                    .get_or_load("model-a", || {
+                        load_cnt.fetch_add(1, Ordering::SeqCst);
+                        std::thread::sleep(std::time::Duration::from_millis(50));
+                        Ok("tokenizer-a".to_owned())
+                    })
+                    .unwrap()
+            })
+        })
+        .collect();
+    let tokenizers: Vec<Arc<String>> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    assert_eq!(load_cnt.load(Ordering::SeqCst), 1);
+    assert_eq!(cache.loads(), 1);
+    assert!(tokenizers.iter().all(|t| Arc::ptr_eq(t, &tokenizers[0])));
+
+    // Failed load is not cached
+    assert!(cache
+        .get_or_load("model-b", || anyhow::bail!("tokenizer not found"))
+        .is_err());
+    let tokenizer = cache
+        .get_or_load("model-b", || Ok("tokenizer-b".to_owned()))
+        .unwrap();
+    assert_eq!(tokenizer.as_str(), "tokenizer-b");
+    assert_eq!(cache.loads(), 2);
+}
+
+#[test]
+fn test_tokenizer_cache_eviction() {
+    let cache: TokenizerCache<String> = TokenizerCache::new(2);
+    let load = |name: &str| {
+        let name = name.to_owned();
+        move || Ok(name)
+    };
+
+    let tokenizer_a = cache.get_or_load("a", load("a")).unwrap();
+    cache.get_or_load("b", load("b")).unwrap();
+    // "a" becomes the most recently used, so "b" is evicted
+    cache.get_or_load("a", load("a")).unwrap();
+    cache.get_or_load("c", load("c")).unwrap();
+    assert_eq!(cache.len(), 2);
+    assert_eq!(cache.loads(), 3);
+
+    cache.get_or_load("a", load("a")).unwrap();
+    assert_eq!(cache.loads(), 3);
+    cache.get_or_load("b", load("b")).unwrap();
+    assert_eq!(cache.loads(), 4);
+
+    // Evicted tokenizer can still be used by its holders
+    cache.evict("a");
+    assert_eq!(tokenizer_a.as_str(), "a");
+    cache.get_or_load("a", load("a")).unwrap();
+    assert_eq!(cache.loads(), 5);
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn test_default_tokenizer_is_shared() {
+    let tokenizer = get_cl100k_base().unwrap();
+    assert!(Arc::ptr_eq(&tokenizer, &DEFAULT_TOKENIZER));
+    assert!(Arc::ptr_eq(&tokenizer, &get_cl100k_base().unwrap()));
+    assert_eq!(TIKTOKEN_CACHE.loads(), 1);
+    assert_eq!(tokenizer.encode_ordinary("Hello world!").len(), 3);
+}
@@ -0,0 +1,455 @@
+use std::env;
+
+use lantern_cli::external_index::cli::UMetricKind;
+use lantern_cli::vector_jobs::{self, cli};
+use postgres::{Client, NoTls};
+
+fn setup_db_tables(client: &mut Client, table_name: &str, join_table_name: &str) {
+    client
+        .batch_execute(&format!(
+            "
+    DROP TABLE IF EXISTS \"{table_name}\";
+    DROP TABLE IF EXISTS \"{join_table_name}\";
+    CREATE TABLE \"{table_name}\" (id SERIAL PRIMARY KEY, v REAL[]);
+    CREATE TABLE \"{join_table_name}\" (id SERIAL PRIMARY KEY, v REAL[]);
+    INSERT INTO \"{table_name}\" SELECT i, ARRAY[i, i]::REAL[] FROM generate_series(1, 100) i;
+    INSERT INTO \"{join_table_name}\" SELECT i, ARRAY[i + 0.1, i]::REAL[] FROM generate_series(1, 50) i;
+"
+        ))
+        .expect("Could not create necessarry tables");
+}
+
+fn drop_db_tables(client: &mut Client, tables: &[&str]) {
+    for table in tables {
+        client
+            .batch_execute(&format!("DROP TABLE IF EXISTS \"{table}\";"))
+            .expect("Could not drop tables");
+    }
+}
+
+#[test]
+fn test_semantic_join_brute_force() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = "_semantic_join_test_a";
+    let join_table_name = "_semantic_join_test_b";
+    let out_table_name = "_semantic_join_test_out";
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    setup_db_tables(&mut db_client, table_name, join_table_name);
+
+    vector_jobs::semantic_join(
+        &cli::SemanticJoinArgs {
+            uri: db_url.clone(),
+            schema: "public".to_owned(),
+            table: table_name.to_owned(),
+            column: "v".to_owned(),
+            pk: "id".to_owned(),
+            join_schema: None,
+            join_table: join_table_name.to_owned(),
+            join_column: None,
+            join_pk: "id".to_owned(),
+            out_table: out_table_name.to_owned(),
+            k: 2,
+            metric_kind: UMetricKind::L2sq,
+            use_index: false,
+            batch_size: 30,
+            overwrite: true,
+        },
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let cnt = db_client
+        .query_one(&format!("SELECT COUNT(*) FROM \"{out_table_name}\""), &[])
+        .unwrap();
+    assert_eq!(cnt.get::<usize, i64>(0), 200);
+
+    let rows = db_client
+        .query(
+            &format!(
+                "SELECT a_pk, b_pk FROM \"{out_table_name}\" WHERE a_pk <= 50 AND distance < 0.1"
+            ),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(rows.len(), 50);
+    for row in rows {
+        assert_eq!(row.get::<usize, i32>(0), row.get::<usize, i32>(1));
+    }
+
+    drop_db_tables(
+        &mut db_client,
+        &[table_name, join_table_name, out_table_name],
+    );
+}
+
+#[test]
+fn test_classify_by_centroid() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = "_classify_test";
+    let examples_table_name = "_classify_test_examples";
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    db_client
+        .batch_execute(&format!(
+            "
+    DROP TABLE IF EXISTS \"{table_name}\";
+    DROP TABLE IF EXISTS \"{examples_table_name}\";
+    CREATE TABLE \"{table_name}\" (id SERIAL PRIMARY KEY, v REAL[]);
+    CREATE TABLE \"{examples_table_name}\" (label TEXT, v REAL[]);
+    INSERT INTO \"{table_name}\" SELECT i, ARRAY[i, 0]::REAL[] FROM generate_series(1, 100) i;
+    INSERT INTO \"{examples_table_name}\" VALUES ('low', '{{0,0}}'), ('low', '{{20,0}}'), ('high', '{{80,0}}'), ('high', '{{90,0}}');
+"
+        ))
+        .expect("Could not create necessarry tables");
+
+    vector_jobs::classify_by_centroid(
+        &cli::ClassifyArgs {
+            uri: db_url.clone(),
+            schema: "public".to_owned(),
+            table: table_name.to_owned(),
+            column: "v".to_owned(),
+            prototype_schema: None,
+            prototype_table: examples_table_name.to_owned(),
+            prototype_label_column: "label".to_owned(),
+            prototype_column: None,
+            compute_centroids: true,
+            out_label_column: "label".to_owned(),
+            out_score_column: "label_score".to_owned(),
+            metric_kind: UMetricKind::L2sq,
+            batch_size: 30,
+        },
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let rows = db_client
+        .query(
+            &format!("SELECT label, COUNT(*) FROM \"{table_name}\" GROUP BY label ORDER BY label"),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(rows.len(), 2);
+    assert_eq!(rows[0].get::<usize, String>(0), "high");
+    assert_eq!(rows[0].get::<usize, i64>(1), 53);
+    assert_eq!(rows[1].get::<usize, String>(0), "low");
+    assert_eq!(rows[1].get::<usize, i64>(1), 47);
+
+    drop_db_tables(&mut db_client, &[table_name, examples_table_name]);
+}
+
+#[test]
+fn test_score_anomalies() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = "_anomaly_test";
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    db_client
+        .batch_execute(&format!(
+            "
+    DROP TABLE IF EXISTS \"{table_name}\";
+    CREATE TABLE \"{table_name}\" (id SERIAL PRIMARY KEY, v REAL[]);
+    INSERT INTO \"{table_name}\" SELECT i, ARRAY[i % 10, i / 10]::REAL[] FROM generate_series(1, 99) i;
+    INSERT INTO \"{table_name}\" VALUES (100, '{{1000,1000}}');
+"
+        ))
+        .expect("Could not create necessarry tables");
+
+    for method in [cli::AnomalyMethod::Knn, cli::AnomalyMethod::Centroid] {
+        vector_jobs::score_anomalies(
+            &cli::AnomalyArgs {
+                uri: db_url.clone(),
+                schema: "public".to_owned(),
+                table: table_name.to_owned(),
+                column: "v".to_owned(),
+                out_column: "anomaly_score".to_owned(),
+                method,
+                k: 3,
+                clusters: 1,
+                iterations: 20,
+                metric_kind: UMetricKind::L2sq,
+                batch_size: 30,
+            },
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+
+        let row = db_client
+            .query_one(
+                &format!("SELECT id FROM \"{table_name}\" ORDER BY anomaly_score DESC LIMIT 1"),
+                &[],
+            )
+            .unwrap();
+        assert_eq!(row.get::<usize, i32>(0), 100);
+    }
+
+    drop_db_tables(&mut db_client, &[table_name]);
+}
+
+#[test]
+fn test_stratified_sample_export() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = "_sample_test";
+    let out_path = "/tmp/_lantern_sample_test.jsonl";
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    db_client
+        .batch_execute(&format!(
+            "
+    DROP TABLE IF EXISTS \"{table_name}\";
+    CREATE TABLE \"{table_name}\" (id SERIAL PRIMARY KEY, content TEXT, v REAL[]);
+    INSERT INTO \"{table_name}\" SELECT i, 'text ' || i, ARRAY[(i % 3) * 100, 0]::REAL[] FROM generate_series(1, 90) i;
+"
+        ))
+        .expect("Could not create necessarry tables");
+
+    vector_jobs::export_stratified_sample(
+        &cli::StratifiedSampleArgs {
+            uri: db_url.clone(),
+            schema: "public".to_owned(),
+            table: table_name.to_owned(),
+            column: "v".to_owned(),
+            pk: "id".to_owned(),
+            text_column: Some("content".to_owned()),
+            clusters: 3,
+            iterations: 20,
+            samples_per_cluster: 5,
+            metric_kind: UMetricKind::L2sq,
+            out: out_path.to_owned(),
+            format: cli::SampleFormat::Jsonl,
+        },
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let content = std::fs::read_to_string(out_path).unwrap();
+    let lines: Vec<serde_json::Value> = content
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 15);
+    for line in lines {
+        let id = line["id"].as_str().unwrap();
+        assert_eq!(line["text"].as_str().unwrap(), format!("text {id}"));
+    }
+
+    std::fs::remove_file(out_path).unwrap();
+    drop_db_tables(&mut db_client, &[table_name]);
+}
+
+#[test]
+fn test_vector_ops() {
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = "_vector_ops_test";
+    let vectors_table_name = "_vector_ops_test_query_vectors";
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    drop_db_tables(&mut db_client, &[vectors_table_name]);
+    db_client
+        .batch_execute(&format!(
+            "
+    DROP TABLE IF EXISTS \"{table_name}\";
+    CREATE TABLE \"{table_name}\" (id SERIAL PRIMARY KEY, category TEXT, v REAL[]);
+    INSERT INTO \"{table_name}\" (category, v) VALUES ('a', '{{1,0}}'), ('a', '{{3,2}}'), ('b', '{{0,4}}'), ('b', NULL);
+"
+        ))
+        .expect("Could not create necessarry tables");
+
+    let store = cli::QueryVectorStoreArgs {
+        uri: db_url.clone(),
+        vectors_schema: "public".to_owned(),
+        vectors_table: vectors_table_name.to_owned(),
+    };
+    let average_args =
+        |ids: Vec<String>, filter: Option<String>, name: &str| cli::VectorAverageArgs {
+            store: store.clone(),
+            schema: "public".to_owned(),
+            table: table_name.to_owned(),
+            column: "v".to_owned(),
+            pk: "id".to_owned(),
+            ids,
+            filter,
+            normalize: false,
+            name: Some(name.to_owned()),
+        };
+
+    let a = vector_jobs::vector_ops::average_vectors(
+        &average_args(vec!["1".to_owned(), "2".to_owned()], None, "a"),
+        None,
+    )
+    .unwrap();
+    let b = vector_jobs::vector_ops::average_vectors(
+        &average_args(Vec::new(), Some("category = 'b'".to_owned()), "b"),
+        None,
+    )
+    .unwrap();
+    assert_eq!(a, vec![2.0, 1.0]);
+    assert_eq!(b, vec![0.0, 4.0]);
+
+    let combined = vector_jobs::vector_ops::combine_vectors(
+        &cli::VectorCombineArgs {
+            store: store.clone(),
+            add: vec!["a".to_owned(), "[1, -1]".to_owned()],
+            subtract: vec!["b".to_owned()],
+            normalize: true,
+            name: Some("a_minus_b".to_owned()),
+        },
+        None,
+    )
+    .unwrap();
+    assert_eq!(combined, vec![0.6, -0.8]);
+
+    let stored = db_client
+        .query_one(
+            &format!("SELECT vector FROM \"{vectors_table_name}\" WHERE name = 'a_minus_b'"),
+            &[],
+        )
+        .unwrap()
+        .get::<usize, Vec<f32>>(0);
+    assert_eq!(stored, combined);
+
+    let vectors = vector_jobs::vector_ops::list_query_vectors(&store, None).unwrap();
+    assert_eq!(vectors.len(), 3);
+
+    vector_jobs::vector_ops::delete_query_vector(
+        &cli::DeleteQueryVectorArgs {
+            store: store.clone(),
+            name: "a".to_owned(),
+        },
+        None,
+    )
+    .unwrap();
+    // Deleted vector can not be used anymore
+    assert!(vector_jobs::vector_ops::combine_vectors(
+        &cli::VectorCombineArgs {
+            store: store.clone(),
+            add: vec!["a".to_owned()],
+            subtract: Vec::new(),
+            normalize: false,
+            name: None,
+        },
+        None,
+    )
+    .is_err());
+
+    drop_db_tables(&mut db_client, &[table_name, vectors_table_name]);
+}
+
+#[test]
+fn test_convert_column() {
+    use lantern_cli::vector_jobs::convert_column::{format_vector, parse_vector};
+
+    let db_url = env::var("DB_URL").expect("`DB_URL` not specified");
+    let table_name = "_convert_column_test";
+    let mut db_client = Client::connect(&db_url, NoTls).expect("Database connection failed");
+    db_client
+        .batch_execute(&format!(
+            "
+    DROP TABLE IF EXISTS \"{table_name}\";
+    CREATE TABLE \"{table_name}\" (id SERIAL PRIMARY KEY, v REAL[], orig REAL[]);
+    INSERT INTO \"{table_name}\" (v, orig) SELECT ARRAY[i, -i / 3.0, 0.1]::REAL[], ARRAY[i, -i / 3.0, 0.1]::REAL[] FROM generate_series(1, 100) i;
+    INSERT INTO \"{table_name}\" (v, orig) VALUES (NULL, NULL);
+    CREATE INDEX \"{table_name}_v_idx\" ON \"{table_name}\" (v);
+"
+        ))
+        .unwrap();
+
+    assert_eq!(
+        format_vector(cli::VectorFormat::Bytea, &[1.0, -2.5]),
+        "0000803f000020c0"
+    );
+    assert_eq!(
+        parse_vector(cli::VectorFormat::Bytea, "0000803f000020c0").unwrap(),
+        vec![1.0, -2.5]
+    );
+    assert_eq!(
+        parse_vector(cli::VectorFormat::Vector, "[1,-2.5]").unwrap(),
+        vec![1.0, -2.5]
+    );
+    assert_eq!(
+        format_vector(cli::VectorFormat::Real, &[1.0, -2.5]),
+        "{1,-2.5}"
+    );
+    assert!(parse_vector(cli::VectorFormat::Bytea, "0000803f00").is_err());
+
+    let get_args = |to: cli::VectorFormat, out_column: Option<&str>| cli::ConvertColumnArgs {
+        uri: db_url.clone(),
+        schema: "public".to_owned(),
+        table: table_name.to_owned(),
+        column: "v".to_owned(),
+        to,
+        out_column: out_column.map(|c| c.to_owned()),
+        batch_size: 30,
+        batch_delay: 0,
+        lock_timeout: 5000,
+        rebuild_index: out_column.is_none(),
+    };
+
+    // Copy to another column keeping the source column
+    vector_jobs::convert_column(
+        &get_args(cli::VectorFormat::Bytea, Some("v_bytea")),
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    let row = db_client
+        .query_one(
+            &format!("SELECT encode(v_bytea, 'hex'), pg_typeof(v)::text FROM \"{table_name}\" WHERE id = 3"),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(row.get::<usize, &str>(0), "00004040000080bfcdcccc3d");
+    assert_eq!(row.get::<usize, &str>(1), "real[]");
+
+    // Convert in place to bytea and back
+    vector_jobs::convert_column(&get_args(cli::VectorFormat::Bytea, None), None, None, None)
+        .unwrap();
+    let column_type = db_client
+        .query_one(
+            &format!("SELECT format_type(atttypid, atttypmod) FROM pg_attribute WHERE attrelid = '\"{table_name}\"'::regclass AND attname = 'v'"),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(column_type.get::<usize, &str>(0), "bytea");
+
+    assert!(vector_jobs::convert_column(
+        &get_args(cli::VectorFormat::Bytea, None),
+        None,
+        None,
+        None
+    )
+    .is_err_and(|e| e.to_string().contains("already of type bytea")));
+
+    vector_jobs::convert_column(&get_args(cli::VectorFormat::Real, None), None, None, None)
+        .unwrap();
+
+    let cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM \"{table_name}\" WHERE v IS NOT DISTINCT FROM orig"),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(cnt.get::<usize, i64>(0), 101);
+
+    let index_cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM pg_indexes WHERE tablename = '{table_name}' AND indexdef LIKE '%(v)%'"),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(index_cnt.get::<usize, i64>(0), 1);
+
+    let trigger_cnt = db_client
+        .query_one(
+            &format!("SELECT COUNT(*) FROM pg_trigger WHERE tgrelid = '\"{table_name}\"'::regclass AND NOT tgisinternal"),
+            &[],
+        )
+        .unwrap();
+    assert_eq!(trigger_cnt.get::<usize, i64>(0), 0);
+
+    drop_db_tables(&mut db_client, &[table_name]);
+}
@@ -18,7 +18,21 @@ fn notice_fn(text: &str) {
 fn get_dummy_runtime_params(runtime: &Runtime) -> String {
     match runtime {
         Runtime::Ort => ORT_RUNTIME_PARAMS.to_owned(),
-        Runtime::OpenAi | Runtime::Cohere => r#"{ "api_token": "xxx" }"#.to_owned(),
+        Runtime::OpenAi
+        | Runtime::Cohere
+        | Runtime::Voyage
+        | Runtime::Mistral
+        | Runtime::Jina
+        | Runtime::Nomic => {
+            r#"{ "api_token": "xxx" }"#.to_owned()
+        }
+        Runtime::OpenAiCompat | Runtime::Tei => {
+            r#"{ "base_url": "http://localhost" }"#.to_owned()
+        }
+        Runtime::Vertex => r#"{ "project_id": "xxx", "access_token": "xxx" }"#.to_owned(),
+        Runtime::Bedrock => {
+            r#"{ "region": "xxx", "access_key_id": "xxx", "secret_access_key": "xxx" }"#.to_owned()
+        }
     }
 }
 
@@ -87,6 +101,10 @@ fn openai_embedding<'a>(
         api_token,
         azure_api_token,
         azure_entra_token,
+        region: None,
+        azure_endpoint: None,
+        deployment: None,
+        api_version: None,
     })?;
 
     let runtime = get_runtime(
@@ -110,6 +128,9 @@ fn cohere_embedding<'a>(
     let runtime_params = serde_json::to_string(&CohereRuntimeParams {
         api_token: Some(COHERE_TOKEN.get().unwrap().to_str().unwrap().to_owned()),
         input_type: Some(input_type.to_owned()),
+        truncate: None,
+        base_url: None,
+        region: None,
     })?;
 
     let runtime = get_runtime(